@@ -4,6 +4,7 @@ mod arg;
 mod phases;
 mod setup;
 mod util;
+mod watch;
 
 use std::{env, iter};
 
@@ -8,7 +8,7 @@ use std::{env, thread};
 
 use rustc_version::VersionMeta;
 
-use crate::{setup::*, util::*};
+use crate::{setup::*, util::*, watch::*};
 
 const CARGO_MIRI_HELP: &str = r"Runs binary crates and tests in Miri
 
@@ -18,11 +18,13 @@ Usage:
 Subcommands:
     run, r                   Run binaries
     test, t                  Run tests
+    bench                    Run each benchmark once
     nextest                  Run tests with nextest (requires cargo-nextest installed)
+    watch <subcommand>       Run `run`, `test` or `bench`, and run it again whenever a source file changes
     setup                    Only perform automatic setup, but without asking questions (for getting a proper libstd)
     clean                    Clean the Miri cache & target directory
 
-The cargo options are exactly the same as for `cargo run` and `cargo test`, respectively.
+The cargo options are exactly the same as for `cargo run`, `cargo test` and `cargo bench`, respectively.
 Furthermore, the following extra flags and environment variables are recognized for `run` and `test`:
 
     --many-seeds[=from..to]  Run the program/tests many times with different seeds in the given range.
@@ -33,6 +35,7 @@ Furthermore, the following extra flags and environment variables are recognized
 Examples:
     cargo miri run
     cargo miri test -- test-suite-filter
+    cargo miri watch test -- test-suite-filter
 
     cargo miri setup --print-sysroot
         This will print the path to the generated sysroot (and nothing else) on stdout.
@@ -87,11 +90,12 @@ pub fn phase_cargo_miri(mut args: impl Iterator<Item = String>) {
     };
     let subcommand = match &*subcommand {
         "setup" => MiriCommand::Setup,
-        "test" | "t" | "run" | "r" | "nextest" => MiriCommand::Forward(subcommand),
+        "test" | "t" | "run" | "r" | "bench" | "nextest" => MiriCommand::Forward(subcommand),
         "clean" => MiriCommand::Clean,
+        "watch" => phase_cargo_miri_watch(args),
         _ =>
             show_error!(
-                "`cargo miri` supports the following subcommands: `run`, `test`, `nextest`, `clean`, and `setup`."
+                "`cargo miri` supports the following subcommands: `run`, `test`, `bench`, `nextest`, `watch`, `clean`, and `setup`."
             ),
     };
     let verbose = num_arg_flag("-v");
@@ -105,8 +109,11 @@ pub fn phase_cargo_miri(mut args: impl Iterator<Item = String>) {
         )
     });
     let host = &rustc_version.host;
-    let target = get_arg_flag_value("--target");
-    let target = target.as_ref().unwrap_or(host);
+    // Like cargo, we support giving `--target` several times, to run everything once per target.
+    let mut targets: Vec<String> = get_arg_flag_values("--target").collect();
+    if targets.is_empty() {
+        targets.push(host.clone());
+    }
 
     // If cleaning the target directory & sysroot cache,
     // delete them then exit. There is no reason to setup a new
@@ -118,8 +125,13 @@ pub fn phase_cargo_miri(mut args: impl Iterator<Item = String>) {
         return;
     }
 
-    // We always setup.
-    let miri_sysroot = setup(&subcommand, target, &rustc_version, verbose, quiet);
+    // We always setup. All targets share the same sysroot directory; `rustc-build-sysroot` keeps
+    // the libraries for each target in their own subdirectory.
+    let mut miri_sysroot = None;
+    for target in &targets {
+        miri_sysroot = Some(setup(&subcommand, target, &rustc_version, verbose, quiet));
+    }
+    let miri_sysroot = miri_sysroot.unwrap();
 
     // Invoke actual cargo for the job, but with different flags.
     // We re-use `cargo test` and `cargo run`, which makes target and binary handling very easy but
@@ -161,6 +173,15 @@ pub fn phase_cargo_miri(mut args: impl Iterator<Item = String>) {
         cmd.arg(host);
     }
 
+    // Cargo only runs doctests for the host target unless `-Zdoctest-xcompile` is set; without it,
+    // doctests would be silently skipped when interpreting for a foreign target.
+    if matches!(&*cargo_cmd, "test" | "t")
+        && targets.iter().any(|target| target != host)
+        && !has_arg_flag("-Zdoctest-xcompile")
+    {
+        cmd.arg("-Zdoctest-xcompile");
+    }
+
     // Set ourselves as runner for al binaries invoked by cargo.
     // We use `all()` since `true` is not a thing in cfg-lang, but the empty conjunction is. :)
     let cargo_miri_path_for_toml = escape_for_toml(&cargo_miri_path);
@@ -241,6 +262,11 @@ pub fn phase_cargo_miri(mut args: impl Iterator<Item = String>) {
     if let Some(many_seeds) = many_seeds {
         cmd.env("MIRI_MANY_SEEDS", many_seeds);
     }
+    if cargo_cmd == "bench" && env::var_os("CARGO_PROFILE_BENCH_DEBUG_ASSERTIONS").is_none() {
+        // The bench profile turns off debug assertions, and with them the UB checks of the
+        // standard library. We do not care about speed, so turn them back on.
+        cmd.env("CARGO_PROFILE_BENCH_DEBUG_ASSERTIONS", "true");
+    }
     if verbose > 0 {
         cmd.env("MIRI_VERBOSE", verbose.to_string()); // This makes the other phases verbose.
     }
@@ -575,110 +601,123 @@ pub fn phase_runner(mut binary_args: impl Iterator<Item = String>, phase: Runner
         }
     };
 
-    let many_seeds = env::var("MIRI_MANY_SEEDS");
-    run_many_seeds(many_seeds.ok(), |seed| {
-        let mut cmd = miri();
-
-        // Set missing env vars. We prefer build-time env vars over run-time ones; see
-        // <https://github.com/rust-lang/miri/issues/1661> for the kind of issue that fixes.
-        for (name, val) in &info.env {
-            // `CARGO_MAKEFLAGS` contains information about how to reach the jobserver, but by the time
-            // the program is being run, that jobserver no longer exists (cargo only runs the jobserver
-            // for the build portion of `cargo run`/`cargo test`). Hence we shouldn't forward this.
-            // Also see <https://github.com/rust-lang/rust/pull/113730>.
-            if name == "CARGO_MAKEFLAGS" {
-                continue;
-            }
-            if let Some(old_val) = env::var_os(name) {
-                if *old_val == *val {
-                    // This one did not actually change, no need to re-set it.
-                    // (This keeps the `debug_cmd` below more manageable.)
-                    continue;
-                } else if verbose > 0 {
-                    eprintln!(
-                        "[cargo-miri runner] Overwriting run-time env var {name:?}={old_val:?} with build-time value {val:?}"
-                    );
-                }
-            }
-            cmd.env(name, val);
-        }
+    let mut cmd = miri();
 
-        if phase != RunnerPhase::Rustdoc {
-            // Set the sysroot. Not necessary in rustdoc, where we already set the sysroot in
-            // `phase_rustdoc`. rustdoc will forward that flag when invoking rustc (i.e., us), so the
-            // flag is present in `info.args`.
-            cmd.arg("--sysroot").arg(env::var_os("MIRI_SYSROOT").unwrap());
+    // Set missing env vars. We prefer build-time env vars over run-time ones; see
+    // <https://github.com/rust-lang/miri/issues/1661> for the kind of issue that fixes.
+    for (name, val) in &info.env {
+        // `CARGO_MAKEFLAGS` contains information about how to reach the jobserver, but by the time
+        // the program is being run, that jobserver no longer exists (cargo only runs the jobserver
+        // for the build portion of `cargo run`/`cargo test`). Hence we shouldn't forward this.
+        // Also see <https://github.com/rust-lang/rust/pull/113730>.
+        if name == "CARGO_MAKEFLAGS" {
+            continue;
         }
-        // Forward rustc arguments.
-        // We need to patch "--extern" filenames because we forced a check-only
-        // build without cargo knowing about that: replace `.rlib` suffix by
-        // `.rmeta`.
-        // We also need to remove `--error-format` as cargo specifies that to be JSON,
-        // but when we run here, cargo does not interpret the JSON any more. `--json`
-        // then also needs to be dropped.
-        let mut args = info.args.iter();
-        while let Some(arg) = args.next() {
-            if arg == "--extern" {
-                forward_patched_extern_arg(&mut (&mut args).cloned(), &mut cmd);
-            } else if let Some(suffix) = arg.strip_prefix("--error-format") {
-                assert!(suffix.starts_with('='));
-                // Drop this argument.
-            } else if let Some(suffix) = arg.strip_prefix("--json") {
-                assert!(suffix.starts_with('='));
-                // Drop this argument.
-            } else {
-                cmd.arg(arg);
+        if let Some(old_val) = env::var_os(name) {
+            if *old_val == *val {
+                // This one did not actually change, no need to re-set it.
+                // (This keeps the `debug_cmd` below more manageable.)
+                continue;
+            } else if verbose > 0 {
+                eprintln!(
+                    "[cargo-miri runner] Overwriting run-time env var {name:?}={old_val:?} with build-time value {val:?}"
+                );
             }
         }
-        // Respect `MIRIFLAGS`.
-        if let Ok(a) = env::var("MIRIFLAGS") {
-            let args = flagsplit(&a);
-            cmd.args(args);
+        cmd.env(name, val);
+    }
+
+    if phase != RunnerPhase::Rustdoc {
+        // Set the sysroot. Not necessary in rustdoc, where we already set the sysroot in
+        // `phase_rustdoc`. rustdoc will forward that flag when invoking rustc (i.e., us), so the
+        // flag is present in `info.args`.
+        cmd.arg("--sysroot").arg(env::var_os("MIRI_SYSROOT").unwrap());
+    }
+    // Forward rustc arguments.
+    // We need to patch "--extern" filenames because we forced a check-only
+    // build without cargo knowing about that: replace `.rlib` suffix by
+    // `.rmeta`.
+    // We also need to remove `--error-format` as cargo specifies that to be JSON,
+    // but when we run here, cargo does not interpret the JSON any more. `--json`
+    // then also needs to be dropped.
+    let mut args = info.args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--extern" {
+            forward_patched_extern_arg(&mut (&mut args).cloned(), &mut cmd);
+        } else if let Some(suffix) = arg.strip_prefix("--error-format") {
+            assert!(suffix.starts_with('='));
+            // Drop this argument.
+        } else if let Some(suffix) = arg.strip_prefix("--json") {
+            assert!(suffix.starts_with('='));
+            // Drop this argument.
+        } else {
+            cmd.arg(arg);
         }
-        // Set the current seed.
-        if let Some(seed) = seed {
-            eprintln!("Trying seed: {seed}");
-            cmd.arg(format!("-Zmiri-seed={seed}"));
+    }
+    // Respect `MIRIFLAGS`.
+    if let Ok(a) = env::var("MIRIFLAGS") {
+        let args = flagsplit(&a);
+        cmd.args(args);
+    }
+    // Explore many seeds, if requested. Miri runs them in parallel.
+    // Listing the tests (which is how cargo-nextest finds out what to run) does not depend on the
+    // seed, and repeating the list would only confuse whoever parses it.
+    if let Ok(many_seeds) = env::var("MIRI_MANY_SEEDS") {
+        if !binary_args.iter().any(|arg| arg == "--list") {
+            cmd.arg(format!("-Zmiri-many-seeds={many_seeds}"));
         }
+    }
 
-        // Then pass binary arguments.
-        cmd.arg("--");
-        cmd.args(&binary_args);
-
-        // Make sure we use the build-time working directory for interpreting Miri/rustc arguments.
-        // But then we need to switch to the run-time one, which we instruct Miri to do by setting `MIRI_CWD`.
-        cmd.current_dir(&info.current_dir);
-        cmd.env("MIRI_CWD", env::current_dir().unwrap());
-
-        // Run it.
-        debug_cmd("[cargo-miri runner]", verbose, &cmd);
-
-        match phase {
-            RunnerPhase::Rustdoc => {
-                cmd.stdin(std::process::Stdio::piped());
-                let mut child = cmd.spawn().expect("failed to spawn process");
-                let child_stdin = child.stdin.take().unwrap();
-                // Write stdin in a background thread, as it may block.
-                let exit_status = thread::scope(|s| {
-                    s.spawn(|| {
-                        let mut child_stdin = child_stdin;
-                        // Ignore failure, it is most likely due to the process having terminated.
-                        let _ = child_stdin.write_all(&info.stdin);
-                    });
-                    child.wait().expect("failed to run command")
+    // Then pass binary arguments.
+    cmd.arg("--");
+    cmd.args(&binary_args);
+    // libtest only accepts the machine-readable output formats with `-Zunstable-options`. The
+    // sysroot we built is always a nightly one, so we can just add that flag, which lets
+    // `cargo miri test -- --format json` work without further ado. libtest rejects `-Z` if it is
+    // given twice, so we must not add it when the user already did.
+    let binary_args_iter = || binary_args.iter().map(|s| s as &str);
+    if ArgFlagValueIter::from_str_iter(binary_args_iter(), "--format")
+        .any(|format| format == "json" || format == "junit")
+        && !binary_args_iter().any(|arg| arg == "-Zunstable-options")
+        && !ArgFlagValueIter::from_str_iter(binary_args_iter(), "-Z")
+            .any(|flag| flag == "unstable-options")
+    {
+        cmd.arg("-Zunstable-options");
+    }
+
+    // Make sure we use the build-time working directory for interpreting Miri/rustc arguments.
+    // But then we need to switch to the run-time one, which we instruct Miri to do by setting `MIRI_CWD`.
+    cmd.current_dir(&info.current_dir);
+    cmd.env("MIRI_CWD", env::current_dir().unwrap());
+
+    // Run it.
+    debug_cmd("[cargo-miri runner]", verbose, &cmd);
+
+    match phase {
+        RunnerPhase::Rustdoc => {
+            cmd.stdin(std::process::Stdio::piped());
+            let mut child = cmd.spawn().expect("failed to spawn process");
+            let child_stdin = child.stdin.take().unwrap();
+            // Write stdin in a background thread, as it may block.
+            let exit_status = thread::scope(|s| {
+                s.spawn(|| {
+                    let mut child_stdin = child_stdin;
+                    // Ignore failure, it is most likely due to the process having terminated.
+                    let _ = child_stdin.write_all(&info.stdin);
                 });
-                if !exit_status.success() {
-                    std::process::exit(exit_status.code().unwrap_or(-1));
-                }
+                child.wait().expect("failed to run command")
+            });
+            if !exit_status.success() {
+                std::process::exit(exit_status.code().unwrap_or(-1));
             }
-            RunnerPhase::Cargo => {
-                let exit_status = cmd.status().expect("failed to run command");
-                if !exit_status.success() {
-                    std::process::exit(exit_status.code().unwrap_or(-1));
-                }
+        }
+        RunnerPhase::Cargo => {
+            let exit_status = cmd.status().expect("failed to run command");
+            if !exit_status.success() {
+                std::process::exit(exit_status.code().unwrap_or(-1));
             }
         }
-    });
+    }
 }
 
 pub fn phase_rustdoc(mut args: impl Iterator<Item = String>) {
@@ -694,11 +733,10 @@ pub fn phase_rustdoc(mut args: impl Iterator<Item = String>) {
         if arg == "--extern" {
             // Patch --extern arguments to use *.rmeta files, since phase_cargo_rustc only creates stub *.rlib files.
             forward_patched_extern_arg(&mut args, &mut cmd);
-        } else if arg == "--runtool" {
-            // An existing --runtool flag indicates cargo is running in cross-target mode, which we don't support.
-            // Note that this is only passed when cargo is run with the unstable -Zdoctest-xcompile flag;
-            // otherwise, we won't be called as rustdoc at all.
-            show_error!("cross-interpreting doctests is not currently supported by Miri.");
+        } else if arg == "--runtool" || arg == "--runtool-arg" {
+            // Cargo passes these in cross-target mode (with `-Zdoctest-xcompile`) to forward the
+            // `target.runner` setting, which is just us. We set our own runtool below, so drop them.
+            args.next().expect("`--runtool` should be followed by a value");
         } else {
             cmd.arg(arg);
         }
@@ -78,15 +78,27 @@ pub fn setup(
             target.contains("-none")
                 || target.contains("nvptx")
                 || target.contains("switch")
-                || target.contains("-uefi"),
+                || target.contains("-uefi")
+                // Not part of the bootstrap heuristic since there the AVR targets are explicitly
+                // marked as no-std, but custom AVR target files are common.
+                || target.contains("avr"),
         Some(val) => val != "0",
     };
     let sysroot_config = if no_std {
         SysrootConfig::NoStd
     } else {
-        SysrootConfig::WithStd {
-            std_features: ["panic_unwind", "backtrace"].into_iter().map(Into::into).collect(),
-        }
+        // The user can pick other features, e.g. to get a smaller and faster std with
+        // `optimize_for_size` or `panic_immediate_abort`.
+        let std_features = match std::env::var("MIRI_SYSROOT_STD_FEATURES") {
+            Ok(features) =>
+                features
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|feature| !feature.is_empty())
+                    .map(Into::into)
+                    .collect(),
+            Err(_) => ["panic_unwind", "backtrace"].into_iter().map(Into::into).collect(),
+        };
+        SysrootConfig::WithStd { std_features }
     };
     let cargo_cmd = {
         let mut command = cargo();
@@ -132,8 +144,13 @@ pub fn setup(
     // Disable debug assertions in the standard library -- Miri is already slow enough.
     // But keep the overflow checks, they are cheap. This completely overwrites flags
     // the user might have set, which is consistent with normal `cargo build` that does
-    // not apply `RUSTFLAGS` to the sysroot either.
-    let rustflags = &["-Cdebug-assertions=off", "-Coverflow-checks=on"];
+    // not apply `RUSTFLAGS` to the sysroot either. Instead, `MIRI_SYSROOT_RUSTFLAGS` can
+    // be used to add flags (which come last, so they can also override the defaults).
+    let mut rustflags =
+        vec!["-Cdebug-assertions=off".to_owned(), "-Coverflow-checks=on".to_owned()];
+    if let Ok(flags) = std::env::var("MIRI_SYSROOT_RUSTFLAGS") {
+        rustflags.extend(flagsplit(&flags));
+    }
 
     let mut after_build_output = String::new(); // what should be printed when the build is done.
     let notify = || {
@@ -162,7 +179,7 @@ pub fn setup(
         .build_mode(BuildMode::Check)
         .rustc_version(rustc_version.clone())
         .sysroot_config(sysroot_config)
-        .rustflags(rustflags)
+        .rustflags(&rustflags)
         .cargo(cargo_cmd)
         .when_build_required(notify)
         .build_from_source(&rust_src);
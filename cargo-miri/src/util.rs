@@ -322,24 +322,3 @@ pub fn clean_target_dir(meta: &Metadata) {
 
     remove_dir_all_idem(&target_dir).unwrap_or_else(|err| show_error!("{}", err))
 }
-
-/// Run `f` according to the many-seeds argument. In single-seed mode, `f` will only
-/// be called once, with `None`.
-pub fn run_many_seeds(many_seeds: Option<String>, f: impl Fn(Option<u32>)) {
-    let Some(many_seeds) = many_seeds else {
-        return f(None);
-    };
-    let (from, to) = many_seeds
-        .split_once("..")
-        .unwrap_or_else(|| show_error!("invalid format for `--many-seeds`: expected `from..to`"));
-    let from: u32 = if from.is_empty() {
-        0
-    } else {
-        from.parse().unwrap_or_else(|_| show_error!("invalid `from` in `--many-seeds=from..to"))
-    };
-    let to: u32 =
-        to.parse().unwrap_or_else(|_| show_error!("invalid `to` in `--many-seeds=from..to"));
-    for seed in from..to {
-        f(Some(seed));
-    }
-}
@@ -0,0 +1,167 @@
+//! Implements `cargo miri watch`: run a `cargo miri` command, wait for the sources of the
+//! workspace to change, and run it again.
+//!
+//! Every run is a regular `cargo miri` invocation, so the sysroot is only built once and cargo
+//! only rebuilds the crates that changed. When all the changed files belong to integration tests,
+//! and the user did not select any targets themselves, we only re-run those tests.
+//!
+//! We only watch Rust sources and manifests, and take a fresh snapshot after every run: the
+//! program and cargo itself write files into the workspace (test output, `Cargo.lock`), and those
+//! must not make us run the command again.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use cargo_metadata::Metadata;
+
+use crate::util::*;
+
+/// How often we check the files for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The cargo flags that select which targets to build; if the user gave any of them, we do not
+/// narrow down the targets ourselves.
+const TARGET_SELECTION_FLAGS: &[&str] = &[
+    "--lib",
+    "--bin",
+    "--bins",
+    "--example",
+    "--examples",
+    "--test",
+    "--tests",
+    "--bench",
+    "--benches",
+    "--all-targets",
+    "--doc",
+];
+
+/// The modification times of all the source files we watch.
+type Snapshot = BTreeMap<PathBuf, SystemTime>;
+
+pub fn phase_cargo_miri_watch(mut args: impl Iterator<Item = String>) -> ! {
+    let Some(subcommand) = args.next() else {
+        show_error!("`cargo miri watch` expects a subcommand (e.g. `test`)");
+    };
+    if !matches!(&*subcommand, "test" | "t" | "run" | "r" | "bench") {
+        show_error!(
+            "`cargo miri watch` supports the following subcommands: `run`, `test`, and `bench`."
+        );
+    }
+    let args: Vec<String> = args.collect();
+    let user_selects_targets = args
+        .iter()
+        .take_while(|arg| *arg != "--")
+        .any(|arg| TARGET_SELECTION_FLAGS.contains(&arg.split('=').next().unwrap()));
+    let metadata = get_cargo_metadata();
+    let target_dir = get_target_dir(&metadata);
+    let target_dir = target_dir.parent().unwrap_or(&target_dir);
+    let cargo_miri_path = env::current_exe().expect("current executable path invalid");
+
+    let mut only_tests = Vec::new();
+    loop {
+        let mut cmd = Command::new(&cargo_miri_path);
+        cmd.arg("miri").arg(&subcommand);
+        for test in &only_tests {
+            cmd.arg("--test").arg(test);
+        }
+        cmd.args(&args);
+        let status = cmd.status().expect("failed to run cargo miri");
+        // Files written during the run are not changes to wait for.
+        let mut snapshot = self::snapshot(&metadata, target_dir);
+        eprintln!(
+            "[cargo-miri watch] {}; waiting for changes...",
+            if status.success() { "finished".to_owned() } else { format!("failed ({status})") }
+        );
+
+        // Wait until something changed.
+        let changed = loop {
+            thread::sleep(POLL_INTERVAL);
+            let new_snapshot = self::snapshot(&metadata, target_dir);
+            let changed = changed_files(&snapshot, &new_snapshot);
+            snapshot = new_snapshot;
+            if !changed.is_empty() {
+                break changed;
+            }
+        };
+        only_tests = if user_selects_targets || !matches!(&*subcommand, "test" | "t") {
+            Vec::new()
+        } else {
+            affected_tests(&metadata, &changed).unwrap_or_default()
+        };
+    }
+}
+
+/// Records the modification time of every Rust source file and manifest in the workspace members,
+/// except for those in the target directory and in hidden directories (like `.git`).
+fn snapshot(metadata: &Metadata, target_dir: &Path) -> Snapshot {
+    fn visit(dir: &Path, target_dir: &Path, snapshot: &mut Snapshot) {
+        let Ok(entries) = fs::read_dir(dir) else { return };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(file_type) = entry.file_type() else { continue };
+            if file_type.is_dir() {
+                if path != target_dir && !entry.file_name().to_string_lossy().starts_with('.') {
+                    visit(&path, target_dir, snapshot);
+                }
+            } else if is_source(&path) {
+                if let Ok(modified) = entry.metadata().and_then(|metadata| metadata.modified()) {
+                    snapshot.insert(path, modified);
+                }
+            }
+        }
+    }
+
+    let mut snapshot = Snapshot::new();
+    for package in metadata.workspace_packages() {
+        if let Some(dir) = package.manifest_path.parent() {
+            visit(dir.as_std_path(), target_dir, &mut snapshot);
+        }
+    }
+    snapshot
+}
+
+/// Whether a change to `path` can change what cargo builds.
+fn is_source(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "rs")
+        || path.file_name().is_some_and(|name| name == "Cargo.toml")
+}
+
+/// All files that were added, removed or modified between the two snapshots.
+fn changed_files(old: &Snapshot, new: &Snapshot) -> Vec<PathBuf> {
+    let mut changed: Vec<PathBuf> = new
+        .iter()
+        .filter(|(path, modified)| old.get(*path) != Some(*modified))
+        .map(|(path, _)| path.clone())
+        .collect();
+    changed.extend(old.keys().filter(|path| !new.contains_key(*path)).cloned());
+    changed
+}
+
+/// If all the `changed` files belong to integration tests, returns the names of those tests.
+fn affected_tests(metadata: &Metadata, changed: &[PathBuf]) -> Option<Vec<String>> {
+    let test_targets: Vec<_> = metadata
+        .workspace_packages()
+        .into_iter()
+        .flat_map(|package| &package.targets)
+        .filter(|target| target.kind.iter().any(|kind| kind == "test"))
+        .collect();
+    let mut tests = Vec::new();
+    for path in changed {
+        let target = test_targets.iter().find(|target| {
+            let src_path = target.src_path.as_std_path();
+            // A test in `tests/foo/main.rs` owns the entire `tests/foo` directory.
+            path == src_path
+                || (src_path.ends_with("main.rs")
+                    && src_path.parent().is_some_and(|dir| path.starts_with(dir)))
+        })?;
+        if !tests.contains(&target.name) {
+            tests.push(target.name.clone());
+        }
+    }
+    Some(tests)
+}
@@ -7,7 +7,8 @@ use std::cell::RefCell;
 use std::cmp::max;
 use std::collections::hash_map::Entry;
 
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 use rustc_data_structures::fx::{FxHashMap, FxHashSet};
 use rustc_span::Span;
@@ -50,8 +51,78 @@ pub struct GlobalStateInner {
     /// This is used as a memory address when a new pointer is casted to an integer. It
     /// is always larger than any address that was previously made part of a block.
     next_base_addr: u64,
+    /// The end of the range of addresses that allocations can use.
+    end_addr: u64,
+    /// New allocations are placed up to this many bytes after the previous one.
+    gap: u64,
     /// The provenance to use for int2ptr casts
     provenance_mode: ProvenanceMode,
+    /// If exposures are being tracked, where they happened.
+    exposure_sites: Option<FxHashMap<Span, ExposureSite>>,
+    /// Locations where we already warned about a wildcard pointer being resolved to an
+    /// exposed allocation (only used when exposures are being tracked).
+    wildcard_warnings: FxHashSet<Span>,
+    /// With `-Zmiri-strict-determinism`, addresses are picked with this RNG rather than the one of
+    /// the machine, so that they do not depend on unrelated random decisions like scheduling.
+    rng: Option<StdRng>,
+}
+
+thread_local! {
+    /// With `-Zmiri-strict-determinism`, allocations are numbered in the order they get their
+    /// address. Unlike `AllocId`s, these numbers do not depend on what the compiler allocated
+    /// before the program started, so they are stable across changes to the code and across runs.
+    /// This lives outside the machine so that the `Debug` impl of `Provenance` can use it.
+    static ALLOC_NUMBERS: RefCell<Option<FxHashMap<AllocId, u64>>> = const { RefCell::new(None) };
+}
+
+/// With `-Zmiri-strict-determinism`, the number of the given allocation, if it got an address.
+pub fn alloc_number(alloc_id: AllocId) -> Option<u64> {
+    ALLOC_NUMBERS.with_borrow(|numbers| numbers.as_ref()?.get(&alloc_id).copied())
+}
+
+/// With `-Zmiri-strict-determinism`, replace every `AllocId` (`alloc1234`) in a message meant for
+/// the user by the number of that allocation (`alloc#5`). `AllocId`s of allocations that never got
+/// an address are left alone.
+pub fn renumber_allocs(msg: String) -> String {
+    if ALLOC_NUMBERS.with_borrow(|numbers| numbers.is_none()) {
+        return msg;
+    }
+    let mut out = String::with_capacity(msg.len());
+    let mut rest = msg.as_str();
+    while let Some(pos) = rest.find("alloc") {
+        let (before, after) = rest.split_at(pos + "alloc".len());
+        out.push_str(before);
+        let digits = after.bytes().take_while(u8::is_ascii_digit).count();
+        // Only replace whole `AllocId`s, not e.g. the end of `dealloc`.
+        let is_word = !before[..pos].ends_with(|c: char| c.is_alphanumeric() || c == '_');
+        let number = after[..digits]
+            .parse()
+            .ok()
+            .filter(|_| is_word)
+            .and_then(std::num::NonZero::new)
+            .and_then(|id| alloc_number(AllocId(id)));
+        match number {
+            Some(number) => {
+                out.push('#');
+                out.push_str(&number.to_string());
+                rest = &after[digits..];
+            }
+            None => rest = after,
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Everything that was exposed at one location in the program.
+#[derive(Debug, Default)]
+struct ExposureSite {
+    /// How many times provenance was exposed here.
+    count: u64,
+    /// Which allocations were exposed.
+    allocs: FxHashSet<AllocId>,
+    /// Which threads exposed provenance here.
+    threads: FxHashSet<ThreadId>,
 }
 
 impl VisitProvenance for GlobalStateInner {
@@ -62,9 +133,15 @@ impl VisitProvenance for GlobalStateInner {
             reuse: _,
             exposed: _,
             next_base_addr: _,
+            end_addr: _,
+            gap: _,
             provenance_mode: _,
+            exposure_sites: _,
+            wildcard_warnings: _,
+            rng: _,
         } = self;
-        // Though base_addr, int_to_ptr_map, and exposed contain AllocIds, we do not want to visit them.
+        // Though base_addr, int_to_ptr_map, exposed, and exposure_sites contain AllocIds,
+        // we do not want to visit them. exposure_sites is only used for reporting.
         // int_to_ptr_map and exposed must contain only live allocations, and those
         // are never garbage collected.
         // base_addr is only relevant if we have a pointer to an AllocId and need to look up its
@@ -74,14 +151,21 @@ impl VisitProvenance for GlobalStateInner {
 }
 
 impl GlobalStateInner {
-    pub fn new(config: &MiriConfig, stack_addr: u64) -> Self {
+    pub fn new(config: &MiriConfig, start_addr: u64, end_addr: u64) -> Self {
+        // Start numbering from scratch, in case an earlier interpreter ran on this thread.
+        ALLOC_NUMBERS.set(config.strict_determinism.then(FxHashMap::default));
         GlobalStateInner {
             int_to_ptr_map: Vec::default(),
             base_addr: FxHashMap::default(),
             reuse: ReusePool::new(config),
             exposed: FxHashSet::default(),
-            next_base_addr: stack_addr,
+            next_base_addr: start_addr,
+            end_addr,
+            gap: config.address_gap,
             provenance_mode: config.provenance_mode,
+            exposure_sites: config.track_exposed_provenance.then(FxHashMap::default),
+            wildcard_warnings: FxHashSet::default(),
+            rng: config.strict_determinism.then(|| StdRng::seed_from_u64(config.seed.unwrap_or(0))),
         }
     }
 
@@ -90,6 +174,24 @@ impl GlobalStateInner {
         // is freed, so `base_addr` is the only one we have to clean up based on the GC.
         self.base_addr.retain(|id, _| allocs.is_live(*id));
     }
+
+    /// Find the live allocation with the largest base address that is at most `addr`, and the
+    /// offset of `addr` in it. Unlike int2ptr casts, this does not care about exposure; the caller
+    /// has to check that the offset is in bounds.
+    pub fn lookup_addr(&self, addr: u64) -> Option<(AllocId, u64)> {
+        let pos = match self.int_to_ptr_map.binary_search_by_key(&addr, |(addr, _)| *addr) {
+            Ok(pos) => pos,
+            Err(0) => return None,
+            Err(pos) => pos - 1,
+        };
+        let (base_addr, alloc_id) = self.int_to_ptr_map[pos];
+        Some((alloc_id, addr - base_addr))
+    }
+
+    /// The base addresses of all live allocations that have one, sorted by address.
+    pub fn live_base_addrs(&self) -> impl Iterator<Item = (u64, AllocId)> + '_ {
+        self.int_to_ptr_map.iter().copied()
+    }
 }
 
 /// Shifts `addr` to make it aligned with `align` by rounding `addr` to the smallest multiple
@@ -154,7 +256,14 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
         Ok(match global_state.base_addr.entry(alloc_id) {
             Entry::Occupied(entry) => *entry.get(),
             Entry::Vacant(entry) => {
-                let mut rng = ecx.machine.rng.borrow_mut();
+                let mut machine_rng = ecx.machine.rng.borrow_mut();
+                let rng = global_state.rng.as_mut().unwrap_or(&mut *machine_rng);
+                ALLOC_NUMBERS.with_borrow_mut(|numbers| {
+                    if let Some(numbers) = numbers {
+                        let number = u64::try_from(numbers.len()).unwrap();
+                        numbers.entry(alloc_id).or_insert(number);
+                    }
+                });
                 let (size, align, kind) = ecx.get_alloc_info(alloc_id);
                 // This is either called immediately after allocation (and then cached), or when
                 // adjusting `tcx` pointers (which never get freed). So assert that we are looking
@@ -174,12 +283,15 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     if let Some(clock) = clock {
                         ecx.acquire_clock(&clock);
                     }
+                    ecx.machine.log_nondeterminism("addresses", || {
+                        format!("reused address {reuse_addr:#x} for {alloc_id:?}")
+                    });
                     reuse_addr
                 } else {
                     // We have to pick a fresh address.
                     // Leave some space to the previous allocation, to give it some chance to be less aligned.
-                    // We ensure that `(global_state.next_base_addr + slack) % 16` is uniformly distributed.
-                    let slack = rng.gen_range(0..16);
+                    // We ensure that `(global_state.next_base_addr + slack) % gap` is uniformly distributed.
+                    let slack = rng.gen_range(0..global_state.gap);
                     // From next_base_addr + slack, round up to adjust for alignment.
                     let base_addr = global_state
                         .next_base_addr
@@ -195,9 +307,14 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
                         .checked_add(max(size.bytes(), 1))
                         .ok_or_else(|| err_exhaust!(AddressSpaceFull))?;
                     // Even if `Size` didn't overflow, we might still have filled up the address space.
-                    if global_state.next_base_addr > ecx.target_usize_max() {
+                    if global_state.next_base_addr > ecx.target_usize_max()
+                        || global_state.next_base_addr > global_state.end_addr
+                    {
                         throw_exhaust!(AddressSpaceFull);
                     }
+                    ecx.machine.log_nondeterminism("addresses", || {
+                        format!("assigned address {base_addr:#x} to {alloc_id:?}")
+                    });
 
                     base_addr
                 };
@@ -249,8 +366,16 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             return Ok(());
         }
         trace!("Exposing allocation id {alloc_id:?}");
+        let span = ecx.machine.current_span();
+        let thread = ecx.active_thread();
         let global_state = ecx.machine.alloc_addresses.get_mut();
         global_state.exposed.insert(alloc_id);
+        if let Some(exposure_sites) = &mut global_state.exposure_sites {
+            let site = exposure_sites.entry(span).or_default();
+            site.count += 1;
+            site.allocs.insert(alloc_id);
+            site.threads.insert(thread);
+        }
         if ecx.machine.borrow_tracker.is_some() {
             ecx.expose_tag(alloc_id, tag)?;
         }
@@ -327,7 +452,15 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             alloc_id
         } else {
             // A wildcard pointer.
-            ecx.alloc_id_from_addr(addr.bytes())?
+            let alloc_id = ecx.alloc_id_from_addr(addr.bytes())?;
+            let mut global_state = ecx.machine.alloc_addresses.borrow_mut();
+            if global_state.exposure_sites.is_some()
+                && global_state.wildcard_warnings.insert(ecx.machine.current_span())
+            {
+                drop(global_state); // emitting the diagnostic must not hold this borrow
+                ecx.emit_diagnostic(NonHaltingDiagnostic::WildcardResolved { alloc_id, addr });
+            }
+            alloc_id
         };
 
         // This cannot fail: since we already have a pointer with that provenance, adjust_alloc_root_pointer
@@ -345,9 +478,46 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 }
 
 impl<'tcx> MiriMachine<'tcx> {
+    /// The base address of the given allocation, if it was already assigned one.
+    pub fn known_base_addr(&self, alloc_id: AllocId) -> Option<u64> {
+        self.alloc_addresses.borrow().base_addr.get(&alloc_id).copied()
+    }
+
+    /// Print where provenance was exposed, if that was requested with
+    /// `-Zmiri-track-exposed-provenance`.
+    pub fn report_exposed_provenance(&self) {
+        let global_state = self.alloc_addresses.borrow();
+        let Some(exposure_sites) = &global_state.exposure_sites else { return };
+        let dcx = self.tcx.dcx();
+        if exposure_sites.is_empty() {
+            dcx.note("no pointer provenance was exposed during execution");
+            return;
+        }
+        let mut sites: Vec<_> = exposure_sites.iter().collect();
+        sites.sort_by_key(|(span, _)| **span);
+        dcx.note(format!("pointer provenance was exposed at {} location(s):", sites.len()));
+        for (span, site) in sites {
+            let mut threads: Vec<_> = site
+                .threads
+                .iter()
+                .map(|&thread| self.threads.get_thread_diagnostic_name(thread))
+                .collect();
+            threads.sort();
+            dcx.span_note(
+                *span,
+                format!(
+                    "exposed {} time(s), covering {} allocation(s), on thread(s) {}",
+                    site.count,
+                    site.allocs.len(),
+                    threads.join(", ")
+                ),
+            );
+        }
+    }
+
     pub fn free_alloc_id(&mut self, dead_id: AllocId, size: Size, align: Align, kind: MemoryKind) {
         let global_state = self.alloc_addresses.get_mut();
-        let rng = self.rng.get_mut();
+        let rng = global_state.rng.as_mut().unwrap_or(self.rng.get_mut());
 
         // We can *not* remove this from `base_addr`, since the interpreter design requires that we
         // be able to retrieve an AllocId + offset for any memory access *before* we check if the
@@ -1,6 +1,7 @@
 use std::alloc;
 use std::alloc::Layout;
 use std::borrow::Cow;
+use std::rc::Rc;
 use std::slice;
 
 use rustc_middle::mir::interpret::AllocBytes;
@@ -8,27 +9,51 @@ use rustc_target::abi::{Align, Size};
 
 /// Allocation bytes that explicitly handle the layout of the data they're storing.
 /// This is necessary to interface with native code that accesses the program store in Miri.
+///
+/// Cloning shares the bytes, and they are only copied once one of the clones gets written to. Only
+/// two places make use of that: the `memcpy` shim when it copies an entire large allocation (see
+/// `mem_copy_sharing`), and constants that get several `AllocId`s (see `adjusted_consts`). All
+/// other copies, like typed copies, `ptr::copy`, and the initial contents of statics, are made by
+/// the interpreter core, which copies the bytes one by one.
 #[derive(Debug)]
 pub struct MiriAllocBytes {
+    storage: Rc<Storage>,
+}
+
+#[derive(Debug)]
+struct Storage {
     /// Stored layout information about the allocation.
     layout: alloc::Layout,
     /// Pointer to the allocation contents.
     /// Invariant:
     /// * If `self.layout.size() == 0`, then `self.ptr` was allocated with the equivalent layout with size 1.
-    /// * Otherwise, `self.ptr` points to memory allocated with `self.layout`.
+    /// * Otherwise, `self.ptr` points to memory allocated with `self.layout`, or to an anonymous
+    ///   host mapping of `self.layout.size()` bytes if `self.mapped` is set (only on unix hosts).
     ptr: *mut u8,
+    /// Whether `self.ptr` was allocated with `mmap` rather than the global allocator.
+    #[cfg(unix)]
+    mapped: bool,
 }
 
+/// Zeroed allocations of at least this many bytes are backed by an anonymous host mapping, so that
+/// the host only provides memory for the pages that are actually used. This keeps programs that
+/// reserve huge, mostly unused arenas from making Miri consume the entire reservation.
+const MAP_THRESHOLD: usize = 1 << 20;
+
 impl Clone for MiriAllocBytes {
     fn clone(&self) -> Self {
-        let bytes: Cow<'_, [u8]> = Cow::Borrowed(self);
-        let align = Align::from_bytes(self.layout.align().try_into().unwrap()).unwrap();
-        MiriAllocBytes::from_bytes(bytes, align)
+        MiriAllocBytes { storage: Rc::clone(&self.storage) }
     }
 }
 
-impl Drop for MiriAllocBytes {
+impl Drop for Storage {
     fn drop(&mut self) {
+        #[cfg(unix)]
+        if self.mapped {
+            // SAFETY: Invariant, `self.ptr` points to a mapping of `self.layout.size()` bytes.
+            unsafe { libc::munmap(self.ptr.cast(), self.layout.size()) };
+            return;
+        }
         // We have to reconstruct the actual layout used for allocation.
         // (`Deref` relies on `size` so we can't just always set it to at least 1.)
         let alloc_layout = if self.layout.size() == 0 {
@@ -45,17 +70,20 @@ impl std::ops::Deref for MiriAllocBytes {
     type Target = [u8];
 
     fn deref(&self) -> &Self::Target {
+        let storage = &*self.storage;
         // SAFETY: `ptr` is non-null, properly aligned, and valid for reading out `self.layout.size()`-many bytes.
         // Note that due to the invariant this is true even if `self.layout.size() == 0`.
-        unsafe { slice::from_raw_parts(self.ptr, self.layout.size()) }
+        unsafe { slice::from_raw_parts(storage.ptr, storage.layout.size()) }
     }
 }
 
 impl std::ops::DerefMut for MiriAllocBytes {
     fn deref_mut(&mut self) -> &mut Self::Target {
+        let storage = self.unshare();
         // SAFETY: `ptr` is non-null, properly aligned, and valid for reading out `self.layout.size()`-many bytes.
         // Note that due to the invariant this is true even if `self.layout.size() == 0`.
-        unsafe { slice::from_raw_parts_mut(self.ptr, self.layout.size()) }
+        // We hold the only reference to the storage.
+        unsafe { slice::from_raw_parts_mut(storage.ptr, storage.layout.size()) }
     }
 }
 
@@ -78,9 +106,61 @@ impl MiriAllocBytes {
         if ptr.is_null() {
             Err(())
         } else {
-            // SAFETY: All `MiriAllocBytes` invariants are fulfilled.
-            Ok(Self { ptr, layout })
+            // SAFETY: All `Storage` invariants are fulfilled.
+            Ok(Self {
+                storage: Rc::new(Storage {
+                    ptr,
+                    layout,
+                    #[cfg(unix)]
+                    mapped: false,
+                }),
+            })
+        }
+    }
+
+    /// Make sure no clone shares the bytes anymore, by copying them if necessary.
+    fn unshare(&mut self) -> &mut Storage {
+        if Rc::get_mut(&mut self.storage).is_none() {
+            let align = Align::from_bytes(self.storage.layout.align().try_into().unwrap()).unwrap();
+            let bytes: Cow<'_, [u8]> = Cow::Borrowed(self);
+            let copy = MiriAllocBytes::from_bytes(bytes, align);
+            *self = copy;
         }
+        Rc::get_mut(&mut self.storage).unwrap()
+    }
+
+    /// Allocate `size` zeroed bytes with an anonymous host mapping. Returns `None` if that is not
+    /// possible, e.g. because the alignment exceeds the host page size.
+    #[cfg(unix)]
+    fn map_zeroed(size: usize, align: usize) -> Option<MiriAllocBytes> {
+        // SAFETY: `sysconf` has no preconditions.
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+        if usize::try_from(page_size).ok()? < align {
+            return None;
+        }
+        let layout = Layout::from_size_align(size, align).ok()?;
+        // SAFETY: We are asking for a fresh mapping, so this does not affect any existing memory.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return None;
+        }
+        // SAFETY: All `Storage` invariants are fulfilled: the mapping is page-aligned, and fresh
+        // anonymous mappings are zeroed.
+        Some(Self { storage: Rc::new(Storage { ptr: ptr.cast(), layout, mapped: true }) })
+    }
+
+    #[cfg(not(unix))]
+    fn map_zeroed(_size: usize, _align: usize) -> Option<MiriAllocBytes> {
+        None
     }
 }
 
@@ -95,25 +175,31 @@ impl AllocBytes for MiriAllocBytes {
             .unwrap_or_else(|()| {
                 panic!("Miri ran out of memory: cannot create allocation of {size} bytes")
             });
-        // SAFETY: `alloc_bytes.ptr` and `slice.as_ptr()` are non-null, properly aligned
+        // SAFETY: `alloc_bytes.storage.ptr` and `slice.as_ptr()` are non-null, properly aligned
         // and valid for the `size`-many bytes to be copied.
-        unsafe { alloc_bytes.ptr.copy_from(slice.as_ptr(), size) };
+        unsafe { alloc_bytes.storage.ptr.copy_from(slice.as_ptr(), size) };
         alloc_bytes
     }
 
     fn zeroed(size: Size, align: Align) -> Option<Self> {
         let size = size.bytes();
         let align = align.bytes();
+        if let (Ok(size), Ok(align)) = (usize::try_from(size), usize::try_from(align))
+            && size >= MAP_THRESHOLD
+            && let Some(bytes) = MiriAllocBytes::map_zeroed(size, align)
+        {
+            return Some(bytes);
+        }
         // SAFETY: `alloc_fn` will only be used with `size != 0`.
         let alloc_fn = |layout| unsafe { alloc::alloc_zeroed(layout) };
         MiriAllocBytes::alloc_with(size, align, alloc_fn).ok()
     }
 
     fn as_mut_ptr(&mut self) -> *mut u8 {
-        self.ptr
+        self.unshare().ptr
     }
 
     fn as_ptr(&self) -> *const u8 {
-        self.ptr
+        self.storage.ptr
     }
 }
@@ -16,18 +16,22 @@ extern crate rustc_driver;
 extern crate rustc_hir;
 extern crate rustc_interface;
 extern crate rustc_log;
-extern crate rustc_metadata;
 extern crate rustc_middle;
 extern crate rustc_session;
 
 use std::env::{self, VarError};
 use std::num::NonZero;
+use std::ops::Range;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicI64, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use tracing::debug;
 
-use rustc_data_structures::sync::Lrc;
+use rustc_data_structures::fx::FxHashMap;
+use rustc_data_structures::sync;
 use rustc_driver::Compilation;
 use rustc_hir::{self as hir, Node};
 use rustc_interface::interface::Config;
@@ -38,32 +42,28 @@ use rustc_middle::{
     },
     query::LocalCrate,
     ty::TyCtxt,
-    util::Providers,
 };
 use rustc_session::config::{CrateType, ErrorOutputType, OptLevel};
-use rustc_session::search_paths::PathKind;
 use rustc_session::{CtfeBacktrace, EarlyDiagCtxt};
 
-use miri::{BacktraceStyle, BorrowTrackerMethod, ProvenanceMode, RetagFields};
+use miri::{
+    BacktraceStyle, BorrowTrackerMethod, HeapProfileFormat, MiriErrorKind, MiriMemoryKind,
+    ProvenanceMode, RetagFields, TraceMode,
+};
 
 struct MiriCompilerCalls {
     miri_config: miri::MiriConfig,
+    many_seeds: Option<ManySeedsConfig>,
+}
+
+struct ManySeedsConfig {
+    seeds: Range<u32>,
+    keep_going: bool,
 }
 
 impl rustc_driver::Callbacks for MiriCompilerCalls {
     fn config(&mut self, config: &mut Config) {
-        config.override_queries = Some(|_, providers| {
-            providers.extern_queries.used_crate_source = |tcx, cnum| {
-                let mut providers = Providers::default();
-                rustc_metadata::provide(&mut providers);
-                let mut crate_source = (providers.extern_queries.used_crate_source)(tcx, cnum);
-                // HACK: rustc will emit "crate ... required to be available in rlib format, but
-                // was not found in this form" errors once we use `tcx.dependency_formats()` if
-                // there's no rlib provided, so setting a dummy path here to workaround those errors.
-                Lrc::make_mut(&mut crate_source).rlib = Some((PathBuf::new(), PathKind::All));
-                crate_source
-            };
-        });
+        miri::override_queries(config);
     }
 
     fn after_analysis<'tcx>(
@@ -82,11 +82,7 @@ impl rustc_driver::Callbacks for MiriCompilerCalls {
                 tcx.dcx().fatal("miri only makes sense on bin crates");
             }
 
-            let (entry_def_id, entry_type) = if let Some(entry_def) = tcx.entry_fn(()) {
-                entry_def
-            } else {
-                tcx.dcx().fatal("miri can only run programs that have a main function");
-            };
+            let (entry_def_id, entry_type) = miri::entry_fn(tcx);
             let mut config = self.miri_config.clone();
 
             // Add filename to `miri` arguments.
@@ -111,11 +107,56 @@ impl rustc_driver::Callbacks for MiriCompilerCalls {
                     optimizations is usually marginal at best.");
             }
 
-            if let Some(return_code) = miri::eval_entry(tcx, entry_def_id, entry_type, config) {
+            // Errors are reported via the `DiagCtxt`; this is just the exit code for them.
+            let error_exit_codes = config.error_exit_codes.clone();
+            let error_exit_code = |error: miri::MiriError| -> i64 {
+                match &error_exit_codes {
+                    Some(codes) => codes[&error.kind].into(),
+                    None => rustc_driver::EXIT_FAILURE.into(),
+                }
+            };
+
+            if let Some(many_seeds) = self.many_seeds.take() {
+                assert!(config.seed.is_none());
+                let exit_code = sync::IntoDynSyncSend(AtomicI64::new(0));
+                let num_failed = sync::IntoDynSyncSend(AtomicU32::new(0));
+                sync::par_for_each_in(many_seeds.seeds.clone(), |seed| {
+                    let mut config = config.clone();
+                    config.seed = Some(seed.into());
+                    eprintln!("Trying seed: {seed}");
+                    let return_code = miri::eval_entry(tcx, entry_def_id, entry_type, config)
+                        .unwrap_or_else(error_exit_code);
+                    if return_code != 0 {
+                        eprintln!("FAILING SEED: {seed}");
+                        if !many_seeds.keep_going {
+                            // `abort_if_errors` would not stop here, since `par_for_each_in` waits
+                            // for all other seeds to finish. So we exit immediately.
+                            std::process::exit(
+                                i32::try_from(return_code).expect("Return value was too large!"),
+                            );
+                        }
+                        exit_code.0.store(return_code, Ordering::Relaxed);
+                        num_failed.0.fetch_add(1, Ordering::Relaxed);
+                    }
+                });
+                let num_failed = num_failed.0.into_inner();
+                if num_failed > 0 {
+                    eprintln!("{num_failed}/{total} SEEDS FAILED", total = many_seeds.seeds.count());
+                }
                 std::process::exit(
-                    i32::try_from(return_code).expect("Return value was too large!"),
+                    i32::try_from(exit_code.0.into_inner()).expect("Return value was too large!"),
                 );
             }
+
+            match miri::eval_entry(tcx, entry_def_id, entry_type, config) {
+                Ok(return_code) =>
+                    std::process::exit(
+                        i32::try_from(return_code).expect("Return value was too large!"),
+                    ),
+                Err(error) if error_exit_codes.is_some() =>
+                    std::process::exit(error_exit_code(error).try_into().unwrap()),
+                Err(_) => {}
+            }
             tcx.dcx().abort_if_errors();
         });
 
@@ -306,6 +347,22 @@ fn parse_comma_list<T: FromStr>(input: &str) -> Result<Vec<T>, T::Err> {
     input.split(',').map(str::parse::<T>).collect()
 }
 
+/// Parses a range of the form `<from>..<to>` or `..<to>` (where `from` defaults to 0).
+fn parse_range(input: &str) -> Result<Range<u32>, &'static str> {
+    let (from, to) = input.split_once("..").ok_or("expected `..`")?;
+    let from = if from.is_empty() { 0 } else { from.parse().map_err(|_| "invalid start")? };
+    let to = to.parse().map_err(|_| "invalid end")?;
+    Ok(from..to)
+}
+
+/// Parses an address, given either in decimal or in hexadecimal with a `0x` prefix.
+fn parse_addr(input: &str) -> Result<u64, std::num::ParseIntError> {
+    match input.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => input.parse(),
+    }
+}
+
 /// Parses the input as a float in the range from 0.0 to 1.0 (inclusive).
 fn parse_rate(input: &str) -> Result<f64, &'static str> {
     match input.parse::<f64>() {
@@ -315,6 +372,26 @@ fn parse_rate(input: &str) -> Result<f64, &'static str> {
     }
 }
 
+/// The error code passed to `--explain`, if any.
+fn explain_arg(args: &[String]) -> Option<&str> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            return None;
+        } else if arg == "--explain" {
+            return args.next().map(String::as_str);
+        } else if let Some(code) = arg.strip_prefix("--explain=") {
+            return Some(code);
+        }
+    }
+    None
+}
+
+/// The exit codes that `-Zmiri-error-exit-codes` uses unless told otherwise.
+fn default_error_exit_codes() -> FxHashMap<MiriErrorKind, i32> {
+    MiriErrorKind::ALL.into_iter().map(|kind| (kind, kind.default_exit_code())).collect()
+}
+
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 fn jemalloc_magic() {
     // These magic runes are copied from
@@ -392,6 +469,17 @@ fn main() {
         )
     }
 
+    // Explain our own error codes; rustc's error codes are left to rustc's `--explain`.
+    if let Some(code) = explain_arg(&args) {
+        if code.to_ascii_uppercase().starts_with("MIRI-") {
+            match miri::explain_error_code(code) {
+                Some(explanation) => print!("{explanation}"),
+                None => show_error!("`{code}` is not a valid Miri error code"),
+            }
+            std::process::exit(0);
+        }
+    }
+
     // Add an ICE bug report hook.
     let using_internal_features =
         rustc_driver::install_ice_hook("https://github.com/rust-lang/miri/issues/new", |_| ());
@@ -407,6 +495,9 @@ fn main() {
     let mut after_dashdash = false;
     // If user has explicitly enabled/disabled isolation
     let mut isolation_enabled: Option<bool> = None;
+    // Whether we should run the program under many seeds, and which ones.
+    let mut many_seeds: Option<Range<u32>> = None;
+    let mut many_seeds_keep_going = false;
 
     // Note that we require values to be given with `=`, not with a space.
     // This matches how rustc parses `-Z`.
@@ -426,6 +517,8 @@ fn main() {
             miri_config.borrow_tracker = None;
         } else if arg == "-Zmiri-tree-borrows" {
             miri_config.borrow_tracker = Some(BorrowTrackerMethod::TreeBorrows);
+        } else if arg == "-Zmiri-both-borrow-trackers" {
+            miri_config.borrow_tracker = Some(BorrowTrackerMethod::Both);
         } else if arg == "-Zmiri-unique-is-unique" {
             miri_config.unique_is_unique = true;
         } else if arg == "-Zmiri-disable-data-race-detector" {
@@ -455,6 +548,56 @@ fn main() {
             miri_config.weak_memory_emulation = false;
         } else if arg == "-Zmiri-track-weak-memory-loads" {
             miri_config.track_outdated_loads = true;
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-weak-memory-buffer-size=") {
+            let size = param.parse::<usize>().unwrap_or_else(|err| {
+                show_error!("-Zmiri-weak-memory-buffer-size requires a `usize`: {}", err)
+            });
+            if size == 0 {
+                show_error!("-Zmiri-weak-memory-buffer-size must be at least 1");
+            }
+            miri_config.weak_memory_buffer_size = size;
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-weak-memory-eviction=") {
+            miri_config.weak_memory_eviction = match param {
+                "oldest" => miri::StoreBufferEviction::Oldest,
+                "unreadable" => miri::StoreBufferEviction::Unreadable,
+                _ => show_error!("-Zmiri-weak-memory-eviction must be `oldest` or `unreadable`"),
+            };
+        } else if arg == "-Zmiri-explain-data-races" {
+            miri_config.explain_data_races = true;
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-livelock-threshold=") {
+            miri_config.livelock_threshold = param.parse::<u64>().unwrap_or_else(|err| {
+                show_error!("-Zmiri-livelock-threshold requires a `u64`: {}", err)
+            });
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-race-report=") {
+            miri_config.race_report = match param {
+                "miri" => miri::RaceReportFormat::Miri,
+                "tsan" => miri::RaceReportFormat::Tsan,
+                _ => show_error!("-Zmiri-race-report must be `miri` or `tsan`"),
+            };
+        } else if arg == "-Zmiri-track-exposed-provenance" {
+            miri_config.track_exposed_provenance = true;
+        } else if arg == "-Zmiri-dpor" {
+            miri_config.dpor_preemption_bound.get_or_insert(2);
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-dpor-preemption-bound=") {
+            let bound = param.parse::<u32>().unwrap_or_else(|err| {
+                show_error!("-Zmiri-dpor-preemption-bound requires a `u32`: {}", err)
+            });
+            miri_config.dpor_preemption_bound = Some(bound);
+        } else if arg == "-Zmiri-model-check" {
+            miri_config.model_check = true;
+        } else if arg == "-Zmiri-litmus" {
+            miri_config.litmus_runs = Some(1000);
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-litmus=") {
+            let runs = param
+                .parse::<u32>()
+                .unwrap_or_else(|err| show_error!("-Zmiri-litmus requires a `u32`: {}", err));
+            miri_config.litmus_runs = Some(runs);
+        } else if arg == "-Zmiri-track-lock-order" {
+            miri_config.track_lock_order = true;
+        } else if arg == "-Zmiri-report-contention" {
+            miri_config.report_contention = true;
+        } else if arg == "-Zmiri-profile-summary" {
+            miri_config.profile_summary = true;
         } else if let Some(param) = arg.strip_prefix("-Zmiri-isolation-error=") {
             if matches!(isolation_enabled, Some(false)) {
                 show_error!(
@@ -475,26 +618,91 @@ fn main() {
                         "-Zmiri-isolation-error must be `abort`, `hide`, `warn`, or `warn-nobacktrace`"
                     ),
             };
+        } else if arg == "-Zmiri-error-exit-codes" {
+            miri_config.error_exit_codes = Some(default_error_exit_codes());
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-error-exit-codes=") {
+            let codes = miri_config.error_exit_codes.get_or_insert_with(default_error_exit_codes);
+            for entry in param.split(',') {
+                let Some((name, code)) = entry.split_once('=') else {
+                    show_error!(
+                        "-Zmiri-error-exit-codes entries must have the form `<kind>=<code>`"
+                    )
+                };
+                let Some(kind) = MiriErrorKind::ALL.into_iter().find(|kind| kind.name() == name)
+                else {
+                    show_error!("-Zmiri-error-exit-codes: unknown error kind `{name}`")
+                };
+                let code = code.parse::<i32>().unwrap_or_else(|err| {
+                    show_error!("-Zmiri-error-exit-codes requires `i32` codes: {}", err)
+                });
+                codes.insert(kind, code);
+            }
         } else if arg == "-Zmiri-ignore-leaks" {
             miri_config.ignore_leaks = true;
             miri_config.collect_leak_backtraces = false;
+        } else if arg == "-Zmiri-ignore-reachable-leaks" {
+            miri_config.ignore_reachable_leaks = true;
+        } else if arg == "-Zmiri-check-unjoined-threads" {
+            miri_config.check_unjoined_threads = true;
         } else if arg == "-Zmiri-panic-on-unsupported" {
             miri_config.panic_on_unsupported = true;
+        } else if arg == "-Zmiri-cheri" {
+            miri_config.cheri = true;
+            miri_config.borrow_tracker = None;
         } else if arg == "-Zmiri-strict-provenance" {
             miri_config.provenance_mode = ProvenanceMode::Strict;
         } else if arg == "-Zmiri-permissive-provenance" {
             miri_config.provenance_mode = ProvenanceMode::Permissive;
         } else if arg == "-Zmiri-mute-stdout-stderr" {
             miri_config.mute_stdout_stderr = true;
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-panic-handler=") {
+            miri_config.abort_in_panic_handler = match param {
+                "program" => false,
+                "abort" => true,
+                _ =>
+                    show_error!(
+                        "-Zmiri-panic-handler must be `program` or `abort`, but got `{param}`"
+                    ),
+            };
         } else if arg == "-Zmiri-retag-fields" {
             miri_config.retag_fields = RetagFields::Yes;
         } else if let Some(retag_fields) = arg.strip_prefix("-Zmiri-retag-fields=") {
-            miri_config.retag_fields = match retag_fields {
+            // `<mode>:<path>,<path>,...` sets the mode only for the given types.
+            let (mode, types) = match retag_fields.split_once(':') {
+                Some((mode, types)) => (mode, Some(types)),
+                None => (retag_fields, None),
+            };
+            let mode = match mode {
                 "all" => RetagFields::Yes,
                 "none" => RetagFields::No,
                 "scalar" => RetagFields::OnlyScalar,
                 _ => show_error!("`-Zmiri-retag-fields` can only be `all`, `none`, or `scalar`"),
             };
+            match types {
+                Some(types) =>
+                    for path in types.split(',') {
+                        if path.is_empty() {
+                            show_error!("`-Zmiri-retag-fields` type paths must not be empty");
+                        }
+                        miri_config.retag_fields_overrides.insert(path.to_owned(), mode);
+                    },
+                None => miri_config.retag_fields = mode,
+            }
+        } else if arg == "-Zmiri-many-seeds" {
+            many_seeds = Some(0..64);
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-many-seeds=") {
+            let range = parse_range(param).unwrap_or_else(|err| {
+                show_error!(
+                    "-Zmiri-many-seeds requires a range in the form `from..to` or `..to`: {err}"
+                )
+            });
+            many_seeds = Some(range);
+        } else if arg == "-Zmiri-many-seeds-keep-going" {
+            many_seeds_keep_going = true;
+        } else if arg == "-Zmiri-parallel-tests" {
+            miri_config.parallel_tests = true;
+        } else if arg == "-Zmiri-server" {
+            miri_config.server = true;
         } else if let Some(param) = arg.strip_prefix("-Zmiri-seed=") {
             if miri_config.seed.is_some() {
                 show_error!("Cannot specify -Zmiri-seed multiple times!");
@@ -503,17 +711,106 @@ fn main() {
                 show_error!("-Zmiri-seed must be an integer that fits into u64")
             });
             miri_config.seed = Some(seed);
+        } else if arg == "-Zmiri-log-nondeterminism" {
+            miri_config.log_nondeterminism = true;
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-float-max-ulp-error=") {
+            miri_config.float_max_ulp_error = param.parse::<u32>().unwrap_or_else(|err| {
+                show_error!("-Zmiri-float-max-ulp-error requires a `u32`: {}", err)
+            });
+        } else if arg == "-Zmiri-strict-determinism" {
+            miri_config.strict_determinism = true;
         } else if let Some(_param) = arg.strip_prefix("-Zmiri-env-exclude=") {
             show_error!(
                 "`-Zmiri-env-exclude` has been removed; unset env vars before starting Miri instead"
             );
         } else if let Some(param) = arg.strip_prefix("-Zmiri-env-forward=") {
             miri_config.forwarded_env_vars.push(param.to_owned());
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-env-file=") {
+            let contents = std::fs::read_to_string(param).unwrap_or_else(|err| {
+                show_error!("-Zmiri-env-file: failed to read `{param}`: {err}")
+            });
+            // Each line sets a variable (`NAME=value`), forwards the variables matching a pattern
+            // (`NAME` or `RUST_*`), or excludes them (`!PATTERN`); empty lines and `#` comments
+            // are ignored.
+            for line in contents.lines().map(str::trim) {
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                if let Some((name, value)) = line.split_once('=') {
+                    miri_config.set_env_vars.insert(name.to_owned(), value.to_owned());
+                } else if let Some(pattern) = line.strip_prefix('!') {
+                    miri_config.excluded_env_vars.push(pattern.to_owned());
+                } else {
+                    miri_config.forwarded_env_vars.push(line.to_owned());
+                }
+            }
         } else if let Some(param) = arg.strip_prefix("-Zmiri-env-set=") {
             let Some((name, value)) = param.split_once('=') else {
                 show_error!("-Zmiri-env-set requires an argument of the form <name>=<value>");
             };
             miri_config.set_env_vars.insert(name.to_owned(), value.to_owned());
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-entry-fn=") {
+            if miri_config.entry_fn.is_some() {
+                show_error!("-Zmiri-entry-fn and -Zmiri-test can only be given once");
+            }
+            miri_config.entry_fn = Some(param.to_owned());
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-test=") {
+            if miri_config.entry_fn.is_some() {
+                show_error!("-Zmiri-entry-fn and -Zmiri-test can only be given once");
+            }
+            miri_config.entry_fn = Some(param.to_owned());
+            miri_config.entry_fn_is_test = true;
+        } else if arg == "-Zmiri-virtual-fs" {
+            miri_config.virtual_fs = true;
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-map-dir=") {
+            let mut parts = param.split(':');
+            let (Some(host), Some(guest), mode, None) =
+                (parts.next(), parts.next(), parts.next(), parts.next())
+            else {
+                show_error!("-Zmiri-map-dir requires an argument of the form <host path>:<guest path>[:ro|:rw]");
+            };
+            let read_only = match mode {
+                None | Some("ro") => true,
+                Some("rw") => false,
+                Some(mode) =>
+                    show_error!("-Zmiri-map-dir: unknown mode `{mode}`, expected `ro` or `rw`"),
+            };
+            let host = std::fs::canonicalize(host)
+                .unwrap_or_else(|err| show_error!("-Zmiri-map-dir: cannot access `{host}`: {err}"));
+            miri_config.mapped_dirs.push((host, PathBuf::from(guest), read_only));
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-extern-static=") {
+            let mut parts = param.split(':');
+            let (Some(name), Some(size), bytes, None) =
+                (parts.next(), parts.next(), parts.next(), parts.next())
+            else {
+                show_error!(
+                    "-Zmiri-extern-static requires an argument of the form <name>:<size>[:<hex bytes>]"
+                );
+            };
+            if miri_config.extern_statics.iter().any(|(other, _)| other == name) {
+                show_error!("-Zmiri-extern-static: extern static `{name}` is declared twice");
+            }
+            let size = size.parse::<usize>().unwrap_or_else(|err| {
+                show_error!("-Zmiri-extern-static requires a `usize` size: {err}")
+            });
+            let bytes = match bytes {
+                None => vec![0; size],
+                Some(hex) => {
+                    if hex.len() != 2 * size || !hex.is_ascii() {
+                        show_error!(
+                            "-Zmiri-extern-static: `{hex}` is not {size} bytes written in hex"
+                        );
+                    }
+                    (0..size)
+                        .map(|i| {
+                            u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap_or_else(|err| {
+                                show_error!("-Zmiri-extern-static: invalid hex bytes {hex}: {err}")
+                            })
+                        })
+                        .collect()
+                }
+            };
+            miri_config.extern_statics.push((name.to_owned(), bytes));
         } else if let Some(param) = arg.strip_prefix("-Zmiri-track-pointer-tag=") {
             let ids: Vec<u64> = parse_comma_list(param).unwrap_or_else(|err| {
                 show_error!("-Zmiri-track-pointer-tag requires a comma separated list of valid `u64` arguments: {err}")
@@ -549,13 +846,76 @@ fn main() {
         } else if let Some(param) = arg.strip_prefix("-Zmiri-address-reuse-cross-thread-rate=") {
             miri_config.address_reuse_cross_thread_rate = parse_rate(param)
                 .unwrap_or_else(|err| show_error!("-Zmiri-address-reuse-cross-thread-rate {err}"));
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-address-range=") {
+            let range = param
+                .split_once("..")
+                .and_then(|(start, end)| Some((parse_addr(start).ok()?, parse_addr(end).ok()?)));
+            let Some((start, end)) = range else {
+                show_error!(
+                    "-Zmiri-address-range requires a range of addresses in the form `start..end`"
+                );
+            };
+            if start == 0 || start >= end {
+                show_error!(
+                    "-Zmiri-address-range must be a non-empty range that does not include 0"
+                );
+            }
+            miri_config.address_range = Some((start, end));
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-address-gap=") {
+            let gap = param
+                .parse::<u64>()
+                .unwrap_or_else(|err| show_error!("-Zmiri-address-gap requires a `u64`: {}", err));
+            if gap == 0 {
+                show_error!("-Zmiri-address-gap must be at least 1");
+            }
+            miri_config.address_gap = gap;
+        } else if arg == "-Zmiri-aslr" {
+            miri_config.aslr = true;
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-addresses-per-const=") {
+            let addrs = param.parse::<usize>().unwrap_or_else(|err| {
+                show_error!("-Zmiri-addresses-per-const requires a `usize`: {}", err)
+            });
+            if addrs == 0 {
+                show_error!("-Zmiri-addresses-per-const must be at least 1");
+            }
+            miri_config.addrs_per_const = addrs;
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-alloc-failure-rate=") {
+            miri_config.alloc_failure_rate = parse_rate(param)
+                .unwrap_or_else(|err| show_error!("-Zmiri-alloc-failure-rate {err}"));
         } else if let Some(param) = arg.strip_prefix("-Zmiri-compare-exchange-weak-failure-rate=") {
             miri_config.cmpxchg_weak_failure_rate = parse_rate(param).unwrap_or_else(|err| {
                 show_error!("-Zmiri-compare-exchange-weak-failure-rate {err}")
             });
+        } else if arg == "-Zmiri-random-wakeup-order" {
+            miri_config.random_wakeup_order = true;
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-spurious-wakeup-rate=") {
+            miri_config.spurious_wakeup_rate = parse_rate(param)
+                .unwrap_or_else(|err| show_error!("-Zmiri-spurious-wakeup-rate {err}"));
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-futex-spurious-wakeup-rate=") {
+            miri_config.futex_spurious_wakeup_rate = parse_rate(param)
+                .unwrap_or_else(|err| show_error!("-Zmiri-futex-spurious-wakeup-rate {err}"));
         } else if let Some(param) = arg.strip_prefix("-Zmiri-preemption-rate=") {
             miri_config.preemption_rate =
                 parse_rate(param).unwrap_or_else(|err| show_error!("-Zmiri-preemption-rate {err}"));
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-thread-preemption-rate=") {
+            let Some((name, rate)) = param.rsplit_once('=') else {
+                show_error!(
+                    "-Zmiri-thread-preemption-rate requires an argument of the form <name>=<rate>"
+                );
+            };
+            let rate = parse_rate(rate)
+                .unwrap_or_else(|err| show_error!("-Zmiri-thread-preemption-rate {err}"));
+            miri_config.thread_preemption_rates.push((name.to_owned(), rate));
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-thread-priority-bias=") {
+            miri_config.thread_priority_bias = parse_rate(param)
+                .unwrap_or_else(|err| show_error!("-Zmiri-thread-priority-bias {err}"));
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-scheduler=") {
+            miri_config.scheduler = match param {
+                "random" => miri::SchedulingPolicy::Random,
+                "round-robin" => miri::SchedulingPolicy::RoundRobin,
+                "fifo" => miri::SchedulingPolicy::Fifo,
+                _ => show_error!("-Zmiri-scheduler must be `random`, `round-robin`, or `fifo`"),
+            };
         } else if arg == "-Zmiri-report-progress" {
             // This makes it take a few seconds between progress reports on my laptop.
             miri_config.report_progress = Some(1_000_000);
@@ -564,6 +924,60 @@ fn main() {
                 show_error!("-Zmiri-report-progress requires a `u32`: {}", err)
             });
             miri_config.report_progress = Some(interval);
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-report-progress-json=") {
+            miri_config.report_progress_json = Some(param.to_owned());
+            miri_config.report_progress.get_or_insert(1_000_000);
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-gdb=") {
+            miri_config.gdb_addr = Some(param.to_owned());
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-dap=") {
+            miri_config.dap_addr = Some(param.to_owned());
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-max-blocks=") {
+            let blocks = param
+                .parse::<u64>()
+                .unwrap_or_else(|err| show_error!("-Zmiri-max-blocks requires a `u64`: {}", err));
+            miri_config.max_blocks = Some(blocks);
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-stack-size=") {
+            let bytes = param
+                .parse::<u64>()
+                .unwrap_or_else(|err| show_error!("-Zmiri-stack-size requires a `u64`: {}", err));
+            miri_config.stack_size = Some(bytes);
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-timeout=") {
+            let seconds = param
+                .parse::<u64>()
+                .unwrap_or_else(|err| show_error!("-Zmiri-timeout requires a `u64`: {}", err));
+            let timeout = Duration::from_secs(seconds);
+            // The limit counts from when Miri started.
+            miri_config.timeout = Some((timeout, Instant::now() + timeout));
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-test-max-blocks=") {
+            let blocks = param.parse::<u64>().unwrap_or_else(|err| {
+                show_error!("-Zmiri-test-max-blocks requires a `u64`: {}", err)
+            });
+            miri_config.test_max_blocks = Some(blocks);
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-test-timeout=") {
+            let seconds = param
+                .parse::<u64>()
+                .unwrap_or_else(|err| show_error!("-Zmiri-test-timeout requires a `u64`: {}", err));
+            miri_config.test_timeout = Some(Duration::from_secs(seconds));
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-clock-rate=") {
+            let nanoseconds = param
+                .parse::<u64>()
+                .unwrap_or_else(|err| show_error!("-Zmiri-clock-rate requires a `u64`: {}", err));
+            miri_config.clock_nanoseconds_per_block = nanoseconds.into();
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-clock-epoch=") {
+            let seconds = param
+                .parse::<u64>()
+                .unwrap_or_else(|err| show_error!("-Zmiri-clock-epoch requires a `u64`: {}", err));
+            miri_config.clock_epoch = Some(Duration::from_secs(seconds));
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-memory-limit=") {
+            let bytes = param
+                .parse::<u64>()
+                .unwrap_or_else(|err| show_error!("-Zmiri-memory-limit requires a `u64`: {}", err));
+            miri_config.memory_limit = Some(bytes);
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-stop-before-error=") {
+            let blocks = param.parse::<u64>().unwrap_or_else(|err| {
+                show_error!("-Zmiri-stop-before-error requires a `u64`: {}", err)
+            });
+            miri_config.stop_before_error = Some(blocks);
         } else if let Some(param) = arg.strip_prefix("-Zmiri-provenance-gc=") {
             let interval = param.parse::<u32>().unwrap_or_else(|err| {
                 show_error!("-Zmiri-provenance-gc requires a `u32`: {}", err)
@@ -571,6 +985,27 @@ fn main() {
             miri_config.gc_interval = interval;
         } else if let Some(param) = arg.strip_prefix("-Zmiri-measureme=") {
             miri_config.measureme_out = Some(param.to_string());
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-coverage=") {
+            miri_config.coverage_out = Some(param.to_string());
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-junit=") {
+            miri_config.junit_out = Some(param.to_string());
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-heap-profile=") {
+            miri_config.heap_profile_out = Some(param.to_string());
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-heap-profile-format=") {
+            miri_config.heap_profile_format = match param {
+                "massif" => HeapProfileFormat::Massif,
+                "json" => HeapProfileFormat::Json,
+                _ => show_error!("-Zmiri-heap-profile-format must be `massif` or `json`"),
+            };
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-trace=") {
+            miri_config.trace = Some(match param {
+                "calls" => TraceMode::Calls,
+                "shims" => TraceMode::Shims,
+                "all" => TraceMode::All,
+                _ => show_error!("-Zmiri-trace must be `calls`, `shims`, or `all`"),
+            });
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-trace-out=") {
+            miri_config.trace_out = Some(param.to_string());
         } else if let Some(param) = arg.strip_prefix("-Zmiri-backtrace=") {
             miri_config.backtrace_style = match param {
                 "0" => BacktraceStyle::Off,
@@ -578,6 +1013,65 @@ fn main() {
                 "full" => BacktraceStyle::Full,
                 _ => show_error!("-Zmiri-backtrace may only be 0, 1, or full"),
             };
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-backtrace-depth=") {
+            let depth = param.parse::<usize>().unwrap_or_else(|err| {
+                show_error!("-Zmiri-backtrace-depth requires a `usize`: {}", err)
+            });
+            miri_config.backtrace_max_depth = Some(depth);
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-backtrace-include=") {
+            miri_config.backtrace_include.extend(param.split(',').map(str::to_owned));
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-backtrace-exclude=") {
+            miri_config.backtrace_exclude.extend(param.split(',').map(str::to_owned));
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-aliasing-suppressions=") {
+            let contents = std::fs::read_to_string(param).unwrap_or_else(|err| {
+                show_error!("-Zmiri-aliasing-suppressions: failed to read `{param}`: {err}")
+            });
+            // One pattern per line; empty lines and `#` comments are ignored.
+            miri_config.aliasing_suppressions.extend(
+                contents
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(str::to_owned),
+            );
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-leak-suppressions=") {
+            let contents = std::fs::read_to_string(param).unwrap_or_else(|err| {
+                show_error!("-Zmiri-leak-suppressions: failed to read `{param}`: {err}")
+            });
+            // One pattern per line, optionally followed by a memory kind; empty lines and `#`
+            // comments are ignored.
+            for line in contents.lines().map(str::trim) {
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let mut parts = line.split_whitespace();
+                let pattern = parts.next().unwrap().to_owned();
+                let kind = parts.next().map(|kind| {
+                    match kind {
+                        "rust" => MiriMemoryKind::Rust,
+                        "c" => MiriMemoryKind::C,
+                        "miri" => MiriMemoryKind::Miri,
+                        "win-heap" => MiriMemoryKind::WinHeap,
+                        "win-local" => MiriMemoryKind::WinLocal,
+                        "runtime" => MiriMemoryKind::Runtime,
+                        "mmap" => MiriMemoryKind::Mmap,
+                        _ => show_error!("-Zmiri-leak-suppressions: unknown memory kind `{kind}`"),
+                    }
+                });
+                if parts.next().is_some() {
+                    show_error!("-Zmiri-leak-suppressions: invalid line `{line}` in `{param}`");
+                }
+                miri_config.leak_suppressions.push((pattern, kind));
+            }
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-shim-stubs=") {
+            let contents = std::fs::read_to_string(param).unwrap_or_else(|err| {
+                show_error!("-Zmiri-shim-stubs: failed to read `{param}`: {err}")
+            });
+            let stubs = miri::parse_shim_stubs(&contents)
+                .unwrap_or_else(|err| show_error!("-Zmiri-shim-stubs: invalid `{param}`: {err}"));
+            for (link_name, stub) in stubs {
+                miri_config.shim_hooks.insert(link_name, Arc::new(stub));
+            }
         } else if let Some(param) = arg.strip_prefix("-Zmiri-native-lib=") {
             let filename = param.to_string();
             if std::path::Path::new(&filename).exists() {
@@ -588,11 +1082,24 @@ fn main() {
             } else {
                 show_error!("-Zmiri-native-lib `{}` does not exist", filename);
             }
+        } else if arg == "-Zmiri-native-lib-diff-writes" {
+            miri_config.native_lib_diff_writes = true;
         } else if let Some(param) = arg.strip_prefix("-Zmiri-num-cpus=") {
             let num_cpus = param
                 .parse::<u32>()
                 .unwrap_or_else(|err| show_error!("-Zmiri-num-cpus requires a `u32`: {}", err));
             miri_config.num_cpus = num_cpus;
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-hostname=") {
+            // 64 is `HOST_NAME_MAX` on Linux, and the smallest `uname` field has room for 64 bytes.
+            if param.is_empty() || param.len() > 64 || param.contains('\0') {
+                show_error!("-Zmiri-hostname requires 1 to 64 bytes without NUL bytes");
+            }
+            miri_config.hostname = param.to_owned();
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-os-release=") {
+            if param.is_empty() || param.len() > 64 || param.contains('\0') {
+                show_error!("-Zmiri-os-release requires 1 to 64 bytes without NUL bytes");
+            }
+            miri_config.os_release = Some(param.to_owned());
         } else if let Some(param) = arg.strip_prefix("-Zmiri-force-page-size=") {
             let page_size = param.parse::<u64>().unwrap_or_else(|err| {
                 show_error!("-Zmiri-force-page-size requires a `u64`: {}", err)
@@ -604,6 +1111,14 @@ fn main() {
                 show_error!("-Zmiri-force-page-size requires a power of 2: {page_size}");
             };
             miri_config.page_size = Some(page_size);
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-max-atomic-width=") {
+            let width = param.parse::<u64>().unwrap_or_else(|err| {
+                show_error!("-Zmiri-max-atomic-width requires a `u64`: {}", err)
+            });
+            if !matches!(width, 0 | 8 | 16 | 32 | 64 | 128) {
+                show_error!("-Zmiri-max-atomic-width must be 0, 8, 16, 32, 64, or 128: {width}");
+            }
+            miri_config.max_atomic_width = Some(width);
         } else {
             // Forward to rustc.
             rustc_args.push(arg);
@@ -618,12 +1133,84 @@ fn main() {
         );
     }
 
+    if many_seeds.is_some()
+        && (miri_config.dpor_preemption_bound.is_some() || miri_config.model_check)
+    {
+        show_error!("-Zmiri-many-seeds cannot be combined with -Zmiri-dpor or -Zmiri-model-check");
+    }
+    if miri_config.cheri && miri_config.borrow_tracker.is_some() {
+        show_error!("-Zmiri-cheri cannot be combined with a borrow tracker");
+    }
+    if miri_config.cheri {
+        // Integers cast to pointers cannot be used to access memory on CHERI.
+        if miri_config.provenance_mode == ProvenanceMode::Permissive {
+            show_error!("-Zmiri-cheri cannot be combined with -Zmiri-permissive-provenance");
+        }
+        miri_config.provenance_mode = ProvenanceMode::Strict;
+    }
+    if miri_config.gdb_addr.is_some() && miri_config.dap_addr.is_some() {
+        show_error!("-Zmiri-gdb and -Zmiri-dap cannot be used at the same time");
+    }
+    if (miri_config.gdb_addr.is_some() || miri_config.dap_addr.is_some())
+        && (many_seeds.is_some()
+            || miri_config.dpor_preemption_bound.is_some()
+            || miri_config.model_check
+            || miri_config.litmus_runs.is_some()
+            || miri_config.parallel_tests)
+    {
+        show_error!(
+            "-Zmiri-gdb and -Zmiri-dap cannot be combined with options that run the program several times"
+        );
+    }
+    if many_seeds.is_some() && miri_config.litmus_runs.is_some() {
+        show_error!("-Zmiri-many-seeds cannot be combined with -Zmiri-litmus");
+    }
+    if miri_config.parallel_tests
+        && (many_seeds.is_some()
+            || miri_config.dpor_preemption_bound.is_some()
+            || miri_config.model_check
+            || miri_config.litmus_runs.is_some())
+    {
+        show_error!(
+            "-Zmiri-parallel-tests cannot be combined with options that run the program several times"
+        );
+    }
+    if miri_config.server
+        && (many_seeds.is_some()
+            || miri_config.parallel_tests
+            || miri_config.gdb_addr.is_some()
+            || miri_config.dap_addr.is_some())
+    {
+        show_error!(
+            "-Zmiri-server cannot be combined with -Zmiri-many-seeds, -Zmiri-parallel-tests, -Zmiri-gdb, or -Zmiri-dap"
+        );
+    }
+    // `-Zmiri-many-seeds` picks the seeds itself.
+    if many_seeds.is_some() && miri_config.seed.is_some() {
+        show_error!("Only one of -Zmiri-seed and -Zmiri-many-seeds can be set");
+    }
+    if many_seeds_keep_going && many_seeds.is_none() {
+        show_error!("-Zmiri-many-seeds-keep-going only has an effect with -Zmiri-many-seeds");
+    }
+    if (many_seeds.is_some() || miri_config.parallel_tests)
+        && !rustc_args.iter().any(|arg| arg.starts_with("-Zthreads="))
+    {
+        // The seeds (or tests) are explored by rustc's thread pool, so make sure that it has some
+        // threads.
+        rustc_args.push(format!(
+            "-Zthreads={}",
+            std::thread::available_parallelism().map_or(1, |n| n.get())
+        ));
+    }
+    let many_seeds =
+        many_seeds.map(|seeds| ManySeedsConfig { seeds, keep_going: many_seeds_keep_going });
+
     debug!("rustc arguments: {:?}", rustc_args);
     debug!("crate arguments: {:?}", miri_config.args);
     run_compiler(
         rustc_args,
         /* target_crate: */ true,
-        &mut MiriCompilerCalls { miri_config },
+        &mut MiriCompilerCalls { miri_config, many_seeds },
         using_internal_features,
     )
 }
@@ -1,3 +1,4 @@
+use std::any::Any;
 use std::cell::RefCell;
 use std::fmt;
 use std::num::NonZero;
@@ -6,6 +7,8 @@ use smallvec::SmallVec;
 
 use rustc_data_structures::fx::{FxHashMap, FxHashSet};
 use rustc_middle::mir::RetagKind;
+use rustc_middle::ty::{self, Ty};
+use rustc_span::{Span, SpanData};
 use rustc_target::abi::Size;
 
 use crate::*;
@@ -107,8 +110,15 @@ pub struct GlobalStateInner {
     tracked_call_ids: FxHashSet<CallId>,
     /// Whether to recurse into datatypes when searching for pointers to retag.
     retag_fields: RetagFields,
+    /// Per-type exceptions to `retag_fields`, keyed by the path of the type.
+    retag_fields_overrides: FxHashMap<String, RetagFields>,
     /// Whether `core::ptr::Unique` gets special (`Box`-like) handling.
     unique_is_unique: bool,
+    /// Function path patterns for which aliasing violations are only warned about.
+    suppressions: Vec<String>,
+    /// The suppressions (by index) that already fired, and where.
+    /// Each of them is only reported once per location.
+    reported_suppressions: FxHashSet<(usize, Span)>,
 }
 
 impl VisitProvenance for GlobalStateInner {
@@ -170,7 +180,9 @@ impl GlobalStateInner {
         tracked_pointer_tags: FxHashSet<BorTag>,
         tracked_call_ids: FxHashSet<CallId>,
         retag_fields: RetagFields,
+        retag_fields_overrides: FxHashMap<String, RetagFields>,
         unique_is_unique: bool,
+        suppressions: Vec<String>,
     ) -> Self {
         GlobalStateInner {
             borrow_tracker_method,
@@ -181,7 +193,10 @@ impl GlobalStateInner {
             tracked_pointer_tags,
             tracked_call_ids,
             retag_fields,
+            retag_fields_overrides,
             unique_is_unique,
+            suppressions,
+            reported_suppressions: FxHashSet::default(),
         }
     }
 
@@ -241,6 +256,9 @@ pub enum BorrowTrackerMethod {
     StackedBorrows,
     /// Tree borrows, as implemented in borrow_tracker/tree_borrows
     TreeBorrows,
+    /// Both of the above. Stacked Borrows decides which tags get created, and Tree Borrows
+    /// mirrors these retags. Every access is checked against both models.
+    Both,
 }
 
 impl BorrowTrackerMethod {
@@ -250,7 +268,9 @@ impl BorrowTrackerMethod {
             config.tracked_pointer_tags.clone(),
             config.tracked_call_ids.clone(),
             config.retag_fields,
+            config.retag_fields_overrides.clone(),
             config.unique_is_unique,
+            config.aliasing_suppressions.clone(),
         ))
     }
 }
@@ -272,10 +292,90 @@ impl GlobalStateInner {
                 AllocState::TreeBorrows(Box::new(RefCell::new(Tree::new_allocation(
                     id, alloc_size, self, kind, machine,
                 )))),
+            BorrowTrackerMethod::Both =>
+                AllocState::Both {
+                    sb: Box::new(RefCell::new(Stacks::new_allocation(
+                        id, alloc_size, self, kind, machine,
+                    ))),
+                    tb: Box::new(RefCell::new(Tree::new_allocation(
+                        id, alloc_size, self, kind, machine,
+                    ))),
+                },
         }
     }
 }
 
+/// When running both borrow trackers, combine their verdicts on the same operation.
+/// If any of them rejects the operation, the error says which model(s) did so.
+/// When both reject it, the Stacked Borrows error is reported.
+pub fn combine_verdicts<'tcx, T>(
+    sb: InterpResult<'tcx, T>,
+    tb: InterpResult<'tcx>,
+) -> InterpResult<'tcx, T> {
+    let (err, note) = match (sb, tb) {
+        (Ok(val), Ok(())) => return Ok(val),
+        (Err(err), Ok(())) =>
+            (err, "this operation is rejected by Stacked Borrows, but accepted by Tree Borrows"),
+        (Ok(_), Err(err)) =>
+            (err, "this operation is rejected by Tree Borrows, but accepted by Stacked Borrows"),
+        (Err(err), Err(_)) =>
+            (err, "this operation is rejected by both Stacked Borrows and Tree Borrows"),
+    };
+    let (mut kind, _backtrace) = err.into_parts();
+    if let InterpError::MachineStop(info) = &mut kind {
+        let info: &mut dyn Any = &mut **info;
+        match info.downcast_mut::<TerminationInfo>() {
+            Some(TerminationInfo::StackedBorrowsUb { help, .. }) => help.push(note.to_owned()),
+            Some(TerminationInfo::TreeBorrowsUb { details, .. }) => details.push(note.to_owned()),
+            _ => {}
+        }
+    }
+    Err(kind.into())
+}
+
+impl<'tcx> MiriMachine<'tcx> {
+    /// If `verdict` is an aliasing violation that happened while a function matching one of the
+    /// user-provided suppressions is on the stack, report it as a warning (once per location)
+    /// and carry on with `fallback` instead.
+    fn suppress_aliasing_violation<T>(
+        &self,
+        verdict: InterpResult<'tcx, T>,
+        fallback: impl FnOnce() -> T,
+    ) -> InterpResult<'tcx, T> {
+        let Err(err) = verdict else { return verdict };
+        let mut global = self.borrow_tracker.as_ref().unwrap().borrow_mut();
+        if global.suppressions.is_empty() {
+            return Err(err);
+        }
+        let InterpError::MachineStop(info) = err.kind() else { return Err(err) };
+        let Some(info) = info.downcast_ref::<TerminationInfo>() else { return Err(err) };
+        if !matches!(
+            info,
+            TerminationInfo::StackedBorrowsUb { .. } | TerminationInfo::TreeBorrowsUb { .. }
+        ) {
+            return Err(err);
+        }
+        let msg = info.to_string();
+        let stack = self.threads.active_thread_stack();
+        let Some(idx) = global.suppressions.iter().position(|pattern| {
+            stack.iter().any(|frame| {
+                helpers::glob_matches(pattern, &self.tcx.def_path_str(frame.instance.def_id()))
+            })
+        }) else {
+            return Err(err);
+        };
+        if global.reported_suppressions.insert((idx, self.current_span())) {
+            let pattern = global.suppressions[idx].clone();
+            drop(global); // emitting the diagnostic may need the global state
+            self.emit_diagnostic(NonHaltingDiagnostic::SuppressedAliasingViolation {
+                msg,
+                pattern,
+            });
+        }
+        Ok(fallback())
+    }
+}
+
 impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
 pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     fn retag_ptr_value(
@@ -285,10 +385,13 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     ) -> InterpResult<'tcx, ImmTy<'tcx>> {
         let this = self.eval_context_mut();
         let method = this.machine.borrow_tracker.as_ref().unwrap().borrow().borrow_tracker_method;
-        match method {
-            BorrowTrackerMethod::StackedBorrows => this.sb_retag_ptr_value(kind, val),
+        let verdict = match method {
+            BorrowTrackerMethod::StackedBorrows | BorrowTrackerMethod::Both =>
+                this.sb_retag_ptr_value(kind, val),
             BorrowTrackerMethod::TreeBorrows => this.tb_retag_ptr_value(kind, val),
-        }
+        };
+        // A suppressed violation leaves the pointer with its old tag.
+        this.machine.suppress_aliasing_violation(verdict, || val.clone())
     }
 
     fn retag_place_contents(
@@ -298,26 +401,32 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     ) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
         let method = this.machine.borrow_tracker.as_ref().unwrap().borrow().borrow_tracker_method;
-        match method {
-            BorrowTrackerMethod::StackedBorrows => this.sb_retag_place_contents(kind, place),
+        let verdict = match method {
+            BorrowTrackerMethod::StackedBorrows | BorrowTrackerMethod::Both =>
+                this.sb_retag_place_contents(kind, place),
             BorrowTrackerMethod::TreeBorrows => this.tb_retag_place_contents(kind, place),
-        }
+        };
+        this.machine.suppress_aliasing_violation(verdict, || ())
     }
 
     fn protect_place(&mut self, place: &MPlaceTy<'tcx>) -> InterpResult<'tcx, MPlaceTy<'tcx>> {
         let this = self.eval_context_mut();
         let method = this.machine.borrow_tracker.as_ref().unwrap().borrow().borrow_tracker_method;
-        match method {
-            BorrowTrackerMethod::StackedBorrows => this.sb_protect_place(place),
+        let verdict = match method {
+            BorrowTrackerMethod::StackedBorrows | BorrowTrackerMethod::Both =>
+                this.sb_protect_place(place),
             BorrowTrackerMethod::TreeBorrows => this.tb_protect_place(place),
-        }
+        };
+        this.machine.suppress_aliasing_violation(verdict, || place.clone())
     }
 
     fn expose_tag(&mut self, alloc_id: AllocId, tag: BorTag) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
         let method = this.machine.borrow_tracker.as_ref().unwrap().borrow().borrow_tracker_method;
         match method {
-            BorrowTrackerMethod::StackedBorrows => this.sb_expose_tag(alloc_id, tag),
+            // Tree Borrows does not do anything with exposed tags yet.
+            BorrowTrackerMethod::StackedBorrows | BorrowTrackerMethod::Both =>
+                this.sb_expose_tag(alloc_id, tag),
             BorrowTrackerMethod::TreeBorrows => this.tb_expose_tag(alloc_id, tag),
         }
     }
@@ -335,20 +444,89 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 this.tcx.tcx.dcx().warn("Stacked Borrows does not support named pointers; `miri_pointer_name` is a no-op");
                 Ok(())
             }
-            BorrowTrackerMethod::TreeBorrows =>
+            BorrowTrackerMethod::TreeBorrows | BorrowTrackerMethod::Both =>
                 this.tb_give_pointer_debug_name(ptr, nth_parent, name),
         }
     }
 
+    /// The policy for recursing into the fields of a value of type `ty` during retagging,
+    /// taking per-type overrides into account.
+    fn retag_fields_for(&self, ty: Ty<'tcx>) -> RetagFields {
+        let this = self.eval_context_ref();
+        let global = this.machine.borrow_tracker.as_ref().unwrap().borrow();
+        if let ty::Adt(adt, _) = ty.kind()
+            && !global.retag_fields_overrides.is_empty()
+        {
+            // Always use the full path including the crate name, also for local types.
+            let did = adt.did();
+            let path = format!(
+                "{}{}",
+                this.tcx.crate_name(did.krate),
+                this.tcx.def_path(did).to_string_no_crate_verbose()
+            );
+            if let Some(&retag_fields) = global.retag_fields_overrides.get(&path) {
+                return retag_fields;
+            }
+        }
+        global.retag_fields
+    }
+
     fn print_borrow_state(&mut self, alloc_id: AllocId, show_unnamed: bool) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
         let method = this.machine.borrow_tracker.as_ref().unwrap().borrow().borrow_tracker_method;
         match method {
             BorrowTrackerMethod::StackedBorrows => this.print_stacks(alloc_id),
             BorrowTrackerMethod::TreeBorrows => this.print_tree(alloc_id, show_unnamed),
+            BorrowTrackerMethod::Both => {
+                this.print_stacks(alloc_id)?;
+                this.print_tree(alloc_id, show_unnamed)
+            }
         }
     }
 
+    fn print_tag_history(&mut self, ptr: Pointer) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        if this.machine.borrow_tracker.is_none() {
+            this.tcx
+                .tcx
+                .dcx()
+                .warn("borrow tracking is disabled; `miri_get_tag_history` is a no-op");
+            return Ok(());
+        }
+        let Some(Provenance::Concrete { alloc_id, tag }) = ptr.provenance else {
+            throw_machine_stop!(TerminationInfo::Abort(format!(
+                "pointer passed to `miri_get_tag_history` must have a concrete tag, got {ptr:?}"
+            )));
+        };
+        let events = this.tag_history(alloc_id, tag)?;
+        this.emit_diagnostic(NonHaltingDiagnostic::TagHistory { tag, alloc_id, events });
+        Ok(())
+    }
+
+    /// Gather everything the borrow tracker knows about `tag` in the given allocation. Borrow
+    /// tracking must be enabled.
+    fn tag_history(
+        &mut self,
+        alloc_id: AllocId,
+        tag: BorTag,
+    ) -> InterpResult<'tcx, Vec<(Option<SpanData>, String)>> {
+        let this = self.eval_context_mut();
+        let method = this.machine.borrow_tracker.as_ref().unwrap().borrow().borrow_tracker_method;
+        Ok(match method {
+            BorrowTrackerMethod::StackedBorrows => this.sb_tag_history(alloc_id, tag)?,
+            BorrowTrackerMethod::TreeBorrows => this.tb_tag_history(alloc_id, tag)?,
+            BorrowTrackerMethod::Both => {
+                let mut events = this.sb_tag_history(alloc_id, tag)?;
+                events.extend(
+                    this.tb_tag_history(alloc_id, tag)?
+                        .into_iter()
+                        .map(|(span, msg)| (span, format!("Tree Borrows: {msg}"))),
+                );
+                events
+            }
+        })
+    }
+
     fn on_stack_pop(
         &self,
         frame: &Frame<'tcx, Provenance, FrameExtra<'tcx>>,
@@ -394,13 +572,18 @@ pub enum AllocState {
     StackedBorrows(Box<RefCell<stacked_borrows::AllocState>>),
     /// Data corresponding to Tree Borrows
     TreeBorrows(Box<RefCell<tree_borrows::AllocState>>),
+    /// Data corresponding to both models, when running them side by side
+    Both {
+        sb: Box<RefCell<stacked_borrows::AllocState>>,
+        tb: Box<RefCell<tree_borrows::AllocState>>,
+    },
 }
 
 impl machine::AllocExtra<'_> {
     #[track_caller]
     pub fn borrow_tracker_sb(&self) -> &RefCell<stacked_borrows::AllocState> {
         match self.borrow_tracker {
-            Some(AllocState::StackedBorrows(ref sb)) => sb,
+            Some(AllocState::StackedBorrows(ref sb)) | Some(AllocState::Both { ref sb, .. }) => sb,
             _ => panic!("expected Stacked Borrows borrow tracking, got something else"),
         }
     }
@@ -408,7 +591,8 @@ impl machine::AllocExtra<'_> {
     #[track_caller]
     pub fn borrow_tracker_sb_mut(&mut self) -> &mut RefCell<stacked_borrows::AllocState> {
         match self.borrow_tracker {
-            Some(AllocState::StackedBorrows(ref mut sb)) => sb,
+            Some(AllocState::StackedBorrows(ref mut sb))
+            | Some(AllocState::Both { ref mut sb, .. }) => sb,
             _ => panic!("expected Stacked Borrows borrow tracking, got something else"),
         }
     }
@@ -416,7 +600,7 @@ impl machine::AllocExtra<'_> {
     #[track_caller]
     pub fn borrow_tracker_tb(&self) -> &RefCell<tree_borrows::AllocState> {
         match self.borrow_tracker {
-            Some(AllocState::TreeBorrows(ref tb)) => tb,
+            Some(AllocState::TreeBorrows(ref tb)) | Some(AllocState::Both { ref tb, .. }) => tb,
             _ => panic!("expected Tree Borrows borrow tracking, got something else"),
         }
     }
@@ -430,7 +614,7 @@ impl AllocState {
         range: AllocRange,
         machine: &MiriMachine<'tcx>,
     ) -> InterpResult<'tcx> {
-        match self {
+        let verdict = match self {
             AllocState::StackedBorrows(sb) =>
                 sb.borrow_mut().before_memory_read(alloc_id, prov_extra, range, machine),
             AllocState::TreeBorrows(tb) =>
@@ -441,7 +625,17 @@ impl AllocState {
                     range,
                     machine,
                 ),
-        }
+            AllocState::Both { sb, tb } => {
+                let sb_verdict =
+                    sb.borrow_mut().before_memory_read(alloc_id, prov_extra, range, machine);
+                let mut tb = tb.borrow_mut();
+                let tb_prov = tb_provenance(&tb, prov_extra);
+                let tb_verdict =
+                    tb.before_memory_access(AccessKind::Read, alloc_id, tb_prov, range, machine);
+                combine_verdicts(sb_verdict, tb_verdict)
+            }
+        };
+        machine.suppress_aliasing_violation(verdict, || ())
     }
 
     pub fn before_memory_write<'tcx>(
@@ -451,7 +645,7 @@ impl AllocState {
         range: AllocRange,
         machine: &MiriMachine<'tcx>,
     ) -> InterpResult<'tcx> {
-        match self {
+        let verdict = match self {
             AllocState::StackedBorrows(sb) =>
                 sb.get_mut().before_memory_write(alloc_id, prov_extra, range, machine),
             AllocState::TreeBorrows(tb) =>
@@ -462,7 +656,17 @@ impl AllocState {
                     range,
                     machine,
                 ),
-        }
+            AllocState::Both { sb, tb } => {
+                let sb_verdict =
+                    sb.get_mut().before_memory_write(alloc_id, prov_extra, range, machine);
+                let tb = tb.get_mut();
+                let tb_prov = tb_provenance(tb, prov_extra);
+                let tb_verdict =
+                    tb.before_memory_access(AccessKind::Write, alloc_id, tb_prov, range, machine);
+                combine_verdicts(sb_verdict, tb_verdict)
+            }
+        };
+        machine.suppress_aliasing_violation(verdict, || ())
     }
 
     pub fn before_memory_deallocation<'tcx>(
@@ -472,18 +676,29 @@ impl AllocState {
         size: Size,
         machine: &MiriMachine<'tcx>,
     ) -> InterpResult<'tcx> {
-        match self {
+        let verdict = match self {
             AllocState::StackedBorrows(sb) =>
                 sb.get_mut().before_memory_deallocation(alloc_id, prov_extra, size, machine),
             AllocState::TreeBorrows(tb) =>
                 tb.get_mut().before_memory_deallocation(alloc_id, prov_extra, size, machine),
-        }
+            AllocState::Both { sb, tb } => {
+                let sb_verdict =
+                    sb.get_mut().before_memory_deallocation(alloc_id, prov_extra, size, machine);
+                let tb = tb.get_mut();
+                let tb_prov = tb_provenance(tb, prov_extra);
+                let tb_verdict = tb.before_memory_deallocation(alloc_id, tb_prov, size, machine);
+                combine_verdicts(sb_verdict, tb_verdict)
+            }
+        };
+        machine.suppress_aliasing_violation(verdict, || ())
     }
 
     pub fn remove_unreachable_tags(&self, tags: &FxHashSet<BorTag>) {
         match self {
             AllocState::StackedBorrows(sb) => sb.borrow_mut().remove_unreachable_tags(tags),
             AllocState::TreeBorrows(tb) => tb.borrow_mut().remove_unreachable_tags(tags),
+            // The Tree Borrows half may contain aliased tags, which its GC cannot handle.
+            AllocState::Both { sb, tb: _ } => sb.borrow_mut().remove_unreachable_tags(tags),
         }
     }
 
@@ -495,11 +710,20 @@ impl AllocState {
         tag: BorTag,
         alloc_id: AllocId, // diagnostics
     ) -> InterpResult<'tcx> {
-        match self {
+        let verdict = match self {
             AllocState::StackedBorrows(_sb) => Ok(()),
             AllocState::TreeBorrows(tb) =>
                 tb.borrow_mut().release_protector(machine, global, tag, alloc_id),
-        }
+            AllocState::Both { sb: _, tb } => {
+                let mut tb = tb.borrow_mut();
+                if tb.knows_tag(tag) {
+                    tb.release_protector(machine, global, tag, alloc_id)
+                } else {
+                    Ok(())
+                }
+            }
+        };
+        machine.suppress_aliasing_violation(verdict, || ())
     }
 }
 
@@ -508,6 +732,20 @@ impl VisitProvenance for AllocState {
         match self {
             AllocState::StackedBorrows(sb) => sb.visit_provenance(visit),
             AllocState::TreeBorrows(tb) => tb.visit_provenance(visit),
+            AllocState::Both { sb, tb } => {
+                sb.visit_provenance(visit);
+                tb.visit_provenance(visit);
+            }
         }
     }
 }
+
+/// The provenance to use for the Tree Borrows half of `AllocState::Both`. Tags that
+/// Tree Borrows never heard of (because they were derived from a wildcard pointer)
+/// are treated like wildcards.
+fn tb_provenance(tb: &tree_borrows::AllocState, prov_extra: ProvenanceExtra) -> ProvenanceExtra {
+    match prov_extra {
+        ProvenanceExtra::Concrete(tag) if !tb.knows_tag(tag) => ProvenanceExtra::Wildcard,
+        prov_extra => prov_extra,
+    }
+}
@@ -233,6 +233,34 @@ impl AllocHistory {
         self.creations.retain(|event| live_tags.contains(&event.retag.new_tag));
         self.protectors.retain(|event| live_tags.contains(&event.tag));
     }
+
+    /// Collect every recorded event that concerns `tag`, in the order creation, protection,
+    /// invalidation. Used by `miri_get_tag_history`.
+    pub fn tag_history(&self, tag: BorTag) -> Vec<(Option<SpanData>, String)> {
+        let mut events = Vec::new();
+        if self.root.0.tag() == tag {
+            events.push((
+                Some(self.root.1.data()),
+                format!("{tag:?} was created here, as the root tag for {:?}", self.id),
+            ));
+        }
+        for creation in self.creations.iter().filter(|event| event.retag.new_tag == tag) {
+            let (msg, span) = creation.generate_diagnostic();
+            events.push((Some(span), msg));
+            events.push((None, format!("{tag:?} was derived from {:?}", creation.retag.orig_tag)));
+        }
+        for protection in self.protectors.iter().filter(|event| event.tag == tag) {
+            events.push((
+                Some(protection.span.data()),
+                format!("{tag:?} was protected here, as an argument of this call"),
+            ));
+        }
+        for invalidation in self.invalidations.iter().filter(|event| event.tag == tag) {
+            let (msg, span) = invalidation.generate_diagnostic();
+            events.push((Some(span), msg));
+        }
+        events
+    }
 }
 
 impl<'history, 'ecx, 'tcx> DiagnosticCx<'history, 'ecx, 'tcx> {
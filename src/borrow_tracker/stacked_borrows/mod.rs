@@ -12,11 +12,14 @@ use std::mem;
 use rustc_data_structures::fx::FxHashSet;
 use rustc_middle::mir::{Mutability, RetagKind};
 use rustc_middle::ty::{self, layout::HasParamEnv, Ty};
+use rustc_span::SpanData;
 use rustc_target::abi::{Abi, Size};
 
 use crate::borrow_tracker::{
+    combine_verdicts,
     stacked_borrows::diagnostics::{AllocHistory, DiagnosticCx, DiagnosticCxBuilder},
-    GlobalStateInner, ProtectorKind,
+    tree_borrows::ShadowRetag,
+    BorrowTrackerMethod, GlobalStateInner, ProtectorKind,
 };
 use crate::concurrency::data_race::{NaReadType, NaWriteType};
 use crate::*;
@@ -811,7 +814,8 @@ trait EvalContextPrivExt<'tcx, 'ecx>: crate::MiriInterpCxExt<'tcx> {
         &mut self,
         place: &MPlaceTy<'tcx>,
         new_perm: NewPermission,
-        info: RetagInfo, // diagnostics info about this retag
+        shadow: ShadowRetag, // only used when Tree Borrows runs alongside
+        info: RetagInfo,     // diagnostics info about this retag
     ) -> InterpResult<'tcx, MPlaceTy<'tcx>> {
         let this = self.eval_context_mut();
         let size = this.size_and_align_of_mplace(place)?.map(|(size, _)| size);
@@ -827,7 +831,16 @@ trait EvalContextPrivExt<'tcx, 'ecx>: crate::MiriInterpCxExt<'tcx> {
         let new_tag = this.machine.borrow_tracker.as_mut().unwrap().get_mut().new_ptr();
 
         // Reborrow.
-        let new_prov = this.sb_reborrow(place, size, new_perm, new_tag, info)?;
+        let new_prov = this.sb_reborrow(place, size, new_perm, new_tag, info);
+        let method = this.machine.borrow_tracker.as_ref().unwrap().borrow().borrow_tracker_method;
+        let new_prov = if method == BorrowTrackerMethod::Both {
+            // Let Tree Borrows know about this retag as well, even if Stacked Borrows rejected it,
+            // so that we can tell which of the two models flags it.
+            let tb_verdict = this.tb_shadow_reborrow(place, size, new_tag, shadow);
+            combine_verdicts(new_prov, tb_verdict)?
+        } else {
+            new_prov?
+        };
 
         // Adjust place.
         // (If the closure gets called, that means the old provenance was `Some`, and hence the new
@@ -841,11 +854,12 @@ trait EvalContextPrivExt<'tcx, 'ecx>: crate::MiriInterpCxExt<'tcx> {
         &mut self,
         val: &ImmTy<'tcx>,
         new_perm: NewPermission,
-        info: RetagInfo, // diagnostics info about this retag
+        shadow: ShadowRetag, // only used when Tree Borrows runs alongside
+        info: RetagInfo,     // diagnostics info about this retag
     ) -> InterpResult<'tcx, ImmTy<'tcx>> {
         let this = self.eval_context_mut();
         let place = this.ref_to_mplace(val)?;
-        let new_place = this.sb_retag_place(&place, new_perm, info)?;
+        let new_place = this.sb_retag_place(&place, new_perm, shadow, info)?;
         Ok(ImmTy::from_immediate(new_place.to_ref(this), val.layout))
     }
 }
@@ -859,12 +873,13 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     ) -> InterpResult<'tcx, ImmTy<'tcx>> {
         let this = self.eval_context_mut();
         let new_perm = NewPermission::from_ref_ty(val.layout.ty, kind, this);
+        let shadow = ShadowRetag::from_ref_ty(val.layout.ty, this);
         let cause = match kind {
             RetagKind::TwoPhase { .. } => RetagCause::TwoPhase,
             RetagKind::FnEntry => unreachable!(),
             RetagKind::Raw | RetagKind::Default => RetagCause::Normal,
         };
-        this.sb_retag_reference(val, new_perm, RetagInfo { cause, in_field: false })
+        this.sb_retag_reference(val, new_perm, shadow, RetagInfo { cause, in_field: false })
     }
 
     fn sb_retag_place_contents(
@@ -873,14 +888,12 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         place: &PlaceTy<'tcx>,
     ) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
-        let retag_fields = this.machine.borrow_tracker.as_mut().unwrap().get_mut().retag_fields;
         let retag_cause = match kind {
             RetagKind::TwoPhase { .. } => unreachable!(), // can only happen in `retag_ptr_value`
             RetagKind::FnEntry => RetagCause::FnEntry,
             RetagKind::Default | RetagKind::Raw => RetagCause::Normal,
         };
-        let mut visitor =
-            RetagVisitor { ecx: this, kind, retag_cause, retag_fields, in_field: false };
+        let mut visitor = RetagVisitor { ecx: this, kind, retag_cause, in_field: false };
         return visitor.visit_value(place);
 
         // The actual visitor.
@@ -888,7 +901,6 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             ecx: &'ecx mut MiriInterpCx<'tcx>,
             kind: RetagKind,
             retag_cause: RetagCause,
-            retag_fields: RetagFields,
             in_field: bool,
         }
         impl<'ecx, 'tcx> RetagVisitor<'ecx, 'tcx> {
@@ -897,11 +909,13 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 &mut self,
                 place: &PlaceTy<'tcx>,
                 new_perm: NewPermission,
+                shadow: ShadowRetag,
             ) -> InterpResult<'tcx> {
                 let val = self.ecx.read_immediate(&self.ecx.place_to_op(place)?)?;
                 let val = self.ecx.sb_retag_reference(
                     &val,
                     new_perm,
+                    shadow,
                     RetagInfo { cause: self.retag_cause, in_field: self.in_field },
                 )?;
                 self.ecx.write_immediate(*val, place)?;
@@ -921,7 +935,8 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 if box_ty.is_box_global(*self.ecx.tcx) {
                     // Boxes get a weak protectors, since they may be deallocated.
                     let new_perm = NewPermission::from_box_ty(place.layout.ty, self.kind, self.ecx);
-                    self.retag_ptr_inplace(place, new_perm)?;
+                    let shadow = ShadowRetag::from_box_ty(place.layout.ty, self.ecx);
+                    self.retag_ptr_inplace(place, new_perm, shadow)?;
                 }
                 Ok(())
             }
@@ -943,7 +958,8 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                         {
                             let new_perm =
                                 NewPermission::from_ref_ty(place.layout.ty, self.kind, self.ecx);
-                            self.retag_ptr_inplace(place, new_perm)?;
+                            let shadow = ShadowRetag::from_ref_ty(place.layout.ty, self.ecx);
+                            self.retag_ptr_inplace(place, new_perm, shadow)?;
                         }
                     }
                     ty::Adt(adt, _) if adt.is_box() => {
@@ -954,7 +970,8 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     }
                     _ => {
                         // Not a reference/pointer/box. Only recurse if configured appropriately.
-                        let recurse = match self.retag_fields {
+                        let retag_fields = self.ecx.retag_fields_for(place.layout.ty);
+                        let recurse = match retag_fields {
                             RetagFields::No => false,
                             RetagFields::Yes => true,
                             RetagFields::OnlyScalar => {
@@ -989,9 +1006,13 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             access: Some(AccessKind::Write),
             protector: Some(ProtectorKind::StrongProtector),
         };
+        let shadow = ShadowRetag::Reserved {
+            ty_is_freeze: place.layout.ty.is_freeze(*this.tcx, this.param_env()),
+        };
         this.sb_retag_place(
             place,
             new_perm,
+            shadow,
             RetagInfo { cause: RetagCause::InPlaceFnPassing, in_field: false },
         )
     }
@@ -1037,4 +1058,41 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         }
         Ok(())
     }
+
+    /// Gather everything Stacked Borrows knows about `tag` in the given allocation.
+    fn sb_tag_history(
+        &mut self,
+        alloc_id: AllocId,
+        tag: BorTag,
+    ) -> InterpResult<'tcx, Vec<(Option<SpanData>, String)>> {
+        let this = self.eval_context_mut();
+        let alloc_extra = this.get_alloc_extra(alloc_id)?;
+        let stacks = alloc_extra.borrow_tracker_sb().borrow();
+        let mut events = stacks.history.tag_history(tag);
+        if stacks.exposed_tags.contains(&tag) {
+            events.push((None, format!("{tag:?} has been exposed")));
+        }
+        let global = this.machine.borrow_tracker.as_ref().unwrap().borrow();
+        let protection = match global.protected_tags.get(&tag) {
+            Some(ProtectorKind::StrongProtector) => "strongly protected",
+            Some(ProtectorKind::WeakProtector) => "weakly protected",
+            None => "not protected",
+        };
+        events.push((None, format!("{tag:?} is currently {protection}")));
+        // Show where the tag still lives, which tells the user what kind of access would
+        // invalidate it next.
+        for (range, stack) in stacks.stacks.iter_all() {
+            let perm = (0..stack.len())
+                .map(|i| stack.get(i).unwrap())
+                .find(|item| item.tag() == tag)
+                .map(|item| item.perm());
+            if let Some(perm) = perm {
+                events.push((
+                    None,
+                    format!("{tag:?} currently has {perm:?} permission at offsets {range:?}"),
+                ));
+            }
+        }
+        Ok(events)
+    }
 }
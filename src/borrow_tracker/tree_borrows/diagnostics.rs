@@ -227,6 +227,37 @@ impl<'tcx> Tree {
         Ok(())
     }
 
+    /// Debug helper: the full history of `tag`, its parent, and its current
+    /// permissions. Returns `None` if the tag is not part of this tree.
+    pub fn tag_history(&self, tag: BorTag) -> Option<Vec<(Option<SpanData>, String)>> {
+        let idx = self.tag_mapping.get(&tag)?;
+        let node = self.nodes.get(idx)?;
+        let mut history = HistoryData::default();
+        history.extend(node.debug_info.history.clone(), "requested", /* show_initial_state */ true);
+        let mut events = history.events;
+        let this = &node.debug_info;
+        let relation = match node.parent {
+            Some(parent) => {
+                let parent = &self.nodes.get(parent).unwrap().debug_info;
+                format!("the requested tag {this} is a child of {parent}")
+            }
+            None => format!("the requested tag {this} is the root of its allocation"),
+        };
+        events.push((None, relation));
+        for (range, perms) in self.rperms.iter_all() {
+            if let Some(perm) = perms.get(idx) {
+                events.push((
+                    None,
+                    format!(
+                        "the requested tag {this} currently has state {} at offsets {range:?}",
+                        perm.permission(),
+                    ),
+                ));
+            }
+        }
+        Some(events)
+    }
+
     /// Debug helper: determines if the tree contains a tag.
     pub fn is_allocation_of(&self, tag: BorTag) -> bool {
         self.tag_mapping.contains_key(&tag)
@@ -6,7 +6,7 @@ use rustc_middle::{
         Ty,
     },
 };
-use rustc_span::def_id::DefId;
+use rustc_span::{def_id::DefId, SpanData};
 use rustc_target::abi::{Abi, Size};
 
 use crate::*;
@@ -187,6 +187,43 @@ impl<'tcx> NewPermission {
     }
 }
 
+/// How a retag performed by Stacked Borrows is mirrored in the Tree Borrows state
+/// when both borrow trackers run side by side (`-Zmiri-both-borrow-trackers`).
+#[derive(Debug, Clone, Copy)]
+pub enum ShadowRetag {
+    /// Tree Borrows would create a `Reserved` child, like for `&mut` and `Box`.
+    Reserved { ty_is_freeze: bool },
+    /// Tree Borrows would create a `Frozen` child, like for `&` to a `Freeze` type.
+    Frozen,
+    /// Tree Borrows would not retag this pointer, the new tag behaves like its parent.
+    Alias,
+}
+
+impl<'tcx> ShadowRetag {
+    /// The Tree Borrows treatment of a retag of the reference or raw pointer type `ty`.
+    /// This mirrors `NewPermission::from_ref_ty`.
+    pub fn from_ref_ty(ty: Ty<'tcx>, cx: &crate::MiriInterpCx<'tcx>) -> Self {
+        match ty.kind() {
+            &ty::Ref(_, pointee, Mutability::Mut) if pointee.is_unpin(*cx.tcx, cx.param_env()) =>
+                Self::Reserved { ty_is_freeze: pointee.is_freeze(*cx.tcx, cx.param_env()) },
+            &ty::Ref(_, pointee, Mutability::Not) if pointee.is_freeze(*cx.tcx, cx.param_env()) =>
+                Self::Frozen,
+            _ => Self::Alias,
+        }
+    }
+
+    /// The Tree Borrows treatment of a retag of a `Box`, given the type of its inner pointer.
+    /// This mirrors `NewPermission::from_unique_ty`.
+    pub fn from_box_ty(ty: Ty<'tcx>, cx: &crate::MiriInterpCx<'tcx>) -> Self {
+        let pointee = ty.builtin_deref(true).unwrap();
+        if pointee.is_unpin(*cx.tcx, cx.param_env()) {
+            Self::Reserved { ty_is_freeze: ty.is_freeze(*cx.tcx, cx.param_env()) }
+        } else {
+            Self::Alias
+        }
+    }
+}
+
 /// Retagging/reborrowing.
 /// Policy on which permission to grant to each pointer should be left to
 /// the implementation of NewPermission.
@@ -406,17 +443,15 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     ) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
         let options = this.machine.borrow_tracker.as_mut().unwrap().get_mut();
-        let retag_fields = options.retag_fields;
         let unique_did =
             options.unique_is_unique.then(|| this.tcx.lang_items().ptr_unique()).flatten();
-        let mut visitor = RetagVisitor { ecx: this, kind, retag_fields, unique_did };
+        let mut visitor = RetagVisitor { ecx: this, kind, unique_did };
         return visitor.visit_value(place);
 
         // The actual visitor.
         struct RetagVisitor<'ecx, 'tcx> {
             ecx: &'ecx mut MiriInterpCx<'tcx>,
             kind: RetagKind,
-            retag_fields: RetagFields,
             unique_did: Option<DefId>,
         }
         impl<'ecx, 'tcx> RetagVisitor<'ecx, 'tcx> {
@@ -499,7 +534,8 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     }
                     _ => {
                         // Not a reference/pointer/box. Only recurse if configured appropriately.
-                        let recurse = match self.retag_fields {
+                        let retag_fields = self.ecx.retag_fields_for(place.layout.ty);
+                        let recurse = match retag_fields {
                             RetagFields::No => false,
                             RetagFields::Yes => true,
                             RetagFields::OnlyScalar => {
@@ -590,6 +626,77 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let mut tree_borrows = alloc_extra.borrow_tracker_tb().borrow_mut();
         tree_borrows.give_pointer_debug_name(tag, nth_parent, name)
     }
+
+    /// Record in the Tree Borrows state a retag that Stacked Borrows just performed, so that
+    /// later accesses can be checked against both models. The protector (if any) has already
+    /// been registered by Stacked Borrows, and both models share the set of protected tags.
+    fn tb_shadow_reborrow(
+        &mut self,
+        place: &MPlaceTy<'tcx>,
+        size: Size,
+        new_tag: BorTag,
+        shadow: ShadowRetag,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        // Pointers without an allocation and wildcard pointers are not tracked by Tree Borrows.
+        let Ok((alloc_id, base_offset, ProvenanceExtra::Concrete(orig_tag))) =
+            this.ptr_try_get_alloc_id(place.ptr())
+        else {
+            return Ok(());
+        };
+        if !matches!(this.get_alloc_info(alloc_id).2, AllocKind::LiveData) {
+            return Ok(());
+        }
+        let span = this.machine.current_span();
+        let alloc_extra = this.get_alloc_extra(alloc_id)?;
+        let mut tree_borrows = alloc_extra.borrow_tracker_tb().borrow_mut();
+        // The parent may itself be a tag that was derived from a wildcard pointer.
+        if !tree_borrows.knows_tag(orig_tag) {
+            return Ok(());
+        }
+        let initial_state = match shadow {
+            ShadowRetag::Reserved { ty_is_freeze } => Permission::new_reserved(ty_is_freeze),
+            ShadowRetag::Frozen => Permission::new_frozen(),
+            ShadowRetag::Alias => {
+                tree_borrows.new_alias(orig_tag, new_tag);
+                return Ok(());
+            }
+        };
+        let range = alloc_range(base_offset, size);
+        // All reborrows incur a (possibly zero-sized) read access to the parent
+        tree_borrows.perform_access(
+            AccessKind::Read,
+            orig_tag,
+            Some(range),
+            this.machine.borrow_tracker.as_ref().unwrap(),
+            alloc_id,
+            span,
+            diagnostics::AccessCause::Reborrow,
+        )?;
+        tree_borrows.new_child(orig_tag, new_tag, initial_state, range, span)
+    }
+
+    /// Gather everything Tree Borrows knows about `tag` in the given allocation.
+    fn tb_tag_history(
+        &mut self,
+        alloc_id: AllocId,
+        tag: BorTag,
+    ) -> InterpResult<'tcx, Vec<(Option<SpanData>, String)>> {
+        let this = self.eval_context_mut();
+        let alloc_extra = this.get_alloc_extra(alloc_id)?;
+        let tree_borrows = alloc_extra.borrow_tracker_tb().borrow();
+        let Some(mut events) = tree_borrows.tag_history(tag) else {
+            return Ok(vec![(None, format!("{tag:?} is not part of the tree of {alloc_id:?}"))]);
+        };
+        let global = this.machine.borrow_tracker.as_ref().unwrap().borrow();
+        let protection = match global.protected_tags.get(&tag) {
+            Some(ProtectorKind::StrongProtector) => "strongly protected",
+            Some(ProtectorKind::WeakProtector) => "weakly protected",
+            None => "not protected",
+        };
+        events.push((None, format!("the requested tag {tag:?} is currently {protection}")));
+        Ok(events)
+    }
 }
 
 /// Takes a place for a `Unique` and turns it into a place with the inner raw pointer.
@@ -518,6 +518,21 @@ impl<'tcx> Tree {
         Ok(())
     }
 
+    /// Whether `tag` is known to this tree, either as a node or as an alias of one.
+    pub fn knows_tag(&self, tag: BorTag) -> bool {
+        self.tag_mapping.contains_key(&tag)
+    }
+
+    /// Make `new_tag` behave exactly like `parent_tag`. This is how Tree Borrows sees
+    /// pointers that another model retagged but that Tree Borrows itself would not retag
+    /// (raw pointers, `!Unpin` mutable references, interior mutable shared references).
+    ///
+    /// Aliases are not compatible with `remove_unreachable_tags`, which thus must not be
+    /// called on a tree that has any.
+    pub fn new_alias(&mut self, parent_tag: BorTag, new_tag: BorTag) {
+        self.tag_mapping.insert_alias(&parent_tag, new_tag);
+    }
+
     /// Deallocation requires
     /// - a pointer that permits write accesses
     /// - the absence of Strong Protectors anywhere in the allocation
@@ -112,6 +112,21 @@ where
         UniIndex { idx }
     }
 
+    /// Assign this key to the index already used by `existing`, so that both keys
+    /// refer to the same value in every `UniValMap`. Panics if `existing` has no
+    /// index or if `key` is already assigned.
+    ///
+    /// Note: since the index is now shared, `remove` must not be called on either key;
+    /// this would return the index to the deassigned pool while it is still in use.
+    #[track_caller]
+    pub fn insert_alias(&mut self, existing: &K, key: K) -> UniIndex {
+        let idx = *self.mapping.get(existing).expect("cannot alias a key that is not assigned");
+        if self.mapping.insert(key, idx).is_some() {
+            panic!("This key is already assigned to a different index");
+        }
+        UniIndex { idx }
+    }
+
     /// If it exists, the index this key maps to.
     pub fn get(&self, key: &K) -> Option<UniIndex> {
         self.mapping.get(key).map(|&idx| UniIndex { idx })
@@ -0,0 +1,104 @@
+//! A log of what the program does, for `-Zmiri-trace`: every function call, every call of a
+//! foreign item that Miri emulates (with its arguments and return value), and every thread switch,
+//! one line each. Since Miri is deterministic, the traces of two runs can be diffed to find where
+//! they start to behave differently.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use rustc_span::Symbol;
+use rustc_target::abi::Abi;
+
+use crate::*;
+
+#[derive(Debug)]
+pub struct CallTrace {
+    calls: bool,
+    shims: bool,
+    out: BufWriter<File>,
+}
+
+impl CallTrace {
+    pub fn new(mode: TraceMode, out: &Path) -> io::Result<Self> {
+        let (calls, shims) = match mode {
+            TraceMode::Calls => (true, false),
+            TraceMode::Shims => (false, true),
+            TraceMode::All => (true, true),
+        };
+        Ok(CallTrace { calls, shims, out: BufWriter::new(File::create(out)?) })
+    }
+
+    fn log(&mut self, thread: &str, depth: usize, message: std::fmt::Arguments<'_>) {
+        // If the trace cannot be written, there is nothing useful we can do about it, and it is
+        // not worth aborting the program for.
+        let _ = writeln!(self.out, "[{thread}] {:indent$}{message}", "", indent = 2 * depth);
+    }
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// With `-Zmiri-trace=calls`, log that the topmost frame was just pushed.
+    fn trace_call(&mut self) {
+        let this = self.eval_context_mut();
+        if !this.machine.call_trace.as_ref().is_some_and(|trace| trace.calls) {
+            return;
+        }
+        let instance = this.frame().instance;
+        let depth = this.active_thread_stack().len() - 1;
+        let thread = this.machine.threads.get_thread_display_name(this.active_thread());
+        let trace = this.machine.call_trace.as_mut().unwrap();
+        trace.log(&thread, depth, format_args!("call {instance}"));
+    }
+
+    /// With `-Zmiri-trace=shims`, render the arguments of a foreign item call, to be passed to
+    /// `trace_shim_return` once the call is done.
+    fn trace_shim_args(&self, args: &[OpTy<'tcx>]) -> Option<String> {
+        let this = self.eval_context_ref();
+        if !this.machine.call_trace.as_ref().is_some_and(|trace| trace.shims) {
+            return None;
+        }
+        let args: Vec<String> = args.iter().map(|arg| this.trace_value(arg)).collect();
+        Some(args.join(", "))
+    }
+
+    /// With `-Zmiri-trace=shims`, log a call of the foreign item `link_name` with the arguments
+    /// rendered by `trace_shim_args`. `ret` is the place the return value was written to, if the
+    /// call returned normally.
+    fn trace_shim_return(&mut self, link_name: Symbol, args: String, ret: Option<&MPlaceTy<'tcx>>) {
+        let this = self.eval_context_mut();
+        let ret = match ret {
+            Some(ret) if ret.layout.is_zst() => String::new(),
+            Some(ret) => format!(" -> {}", this.trace_value(&ret.clone().into())),
+            None => String::new(),
+        };
+        let depth = this.active_thread_stack().len();
+        let thread = this.machine.threads.get_thread_display_name(this.active_thread());
+        let trace = this.machine.call_trace.as_mut().unwrap();
+        trace.log(&thread, depth, format_args!("shim {link_name}({args}){ret}"));
+    }
+
+    /// With `-Zmiri-trace`, log that we switched to thread `next`.
+    fn trace_thread_switch(&mut self, next: ThreadId) {
+        let this = self.eval_context_mut();
+        if this.machine.call_trace.is_none() {
+            return;
+        }
+        let thread = this.machine.threads.get_thread_display_name(next);
+        let trace = this.machine.call_trace.as_mut().unwrap();
+        trace.log(&thread, 0, format_args!("switched to thread `{thread}`"));
+    }
+
+    /// Render a value for the trace. Values that are not passed as scalars are only described by
+    /// their type, and values that cannot be read are marked as such rather than raising an error.
+    fn trace_value(&self, op: &OpTy<'tcx>) -> String {
+        let this = self.eval_context_ref();
+        if !matches!(op.layout.abi, Abi::Scalar(_) | Abi::ScalarPair(..)) {
+            return format!("<{}>", op.layout.ty);
+        }
+        match this.read_immediate(op) {
+            Ok(imm) => imm.to_string(),
+            Err(_) => format!("<invalid {}>", op.layout.ty),
+        }
+    }
+}
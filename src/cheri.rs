@@ -0,0 +1,117 @@
+//! An experimental emulation of CHERI capabilities, enabled with `-Zmiri-cheri`.
+//!
+//! On CHERI hardware, every pointer is a capability that carries the bounds of the memory it may
+//! access, and the hardware checks every access against those bounds. A pointer starts out with
+//! the bounds of its allocation (which Miri always enforces via provenance), and the program can
+//! narrow them further with `miri_cheri_set_bounds`; accesses outside the narrowed bounds then
+//! fault, even if they are in-bounds of the allocation.
+//!
+//! We need a way to tell apart pointers with different bounds. The borrow tracker is disabled in
+//! this mode, so we reuse the borrow tags for that: each call to `miri_cheri_set_bounds` hands
+//! out a fresh tag, and since nothing else changes the tags, every pointer derived from the result
+//! keeps those bounds.
+
+use rustc_data_structures::fx::FxHashMap;
+use rustc_span::SpanData;
+use rustc_target::abi::Size;
+
+use crate::*;
+
+#[derive(Debug)]
+pub struct Capabilities {
+    /// The tag to hand out for the next capability with narrowed bounds.
+    next_tag: BorTag,
+    /// For the capabilities with narrowed bounds: their allocation, the range of offsets they
+    /// may access, and where the bounds were set.
+    bounds: FxHashMap<BorTag, (AllocId, Size, Size, SpanData)>,
+}
+
+impl Capabilities {
+    pub fn new() -> Self {
+        // Tag 1 is the default tag that all pointers start out with.
+        Capabilities { next_tag: BorTag::one().succ().unwrap(), bounds: FxHashMap::default() }
+    }
+
+    /// Check that `range` of `alloc_id` is within the bounds of the capability with the given tag.
+    /// `action` describes the operation for the error message.
+    pub fn check<'tcx>(
+        &self,
+        alloc_id: AllocId,
+        tag: ProvenanceExtra,
+        range: AllocRange,
+        action: &str,
+    ) -> InterpResult<'tcx> {
+        let ProvenanceExtra::Concrete(tag) = tag else { return Ok(()) };
+        let Some(&(bounds_alloc, start, end, span)) = self.bounds.get(&tag) else { return Ok(()) };
+        // Tags are not shared between allocations, but be defensive: a capability for one
+        // allocation cannot access any other.
+        if bounds_alloc != alloc_id {
+            throw_machine_stop!(TerminationInfo::CapabilityFault {
+                msg: format!(
+                    "{action} {alloc_id:?} with a capability for {bounds_alloc:?}[{:#x}..{:#x}]",
+                    start.bytes(),
+                    end.bytes(),
+                ),
+                bounds_set: Some(span),
+            });
+        }
+        if range.start < start || range.end() > end {
+            throw_machine_stop!(TerminationInfo::CapabilityFault {
+                msg: format!(
+                    "{action} {alloc_id:?}[{:#x}..{:#x}] with a capability whose bounds are only {alloc_id:?}[{:#x}..{:#x}]",
+                    range.start.bytes(),
+                    range.end().bytes(),
+                    start.bytes(),
+                    end.bytes(),
+                ),
+                bounds_set: Some(span),
+            });
+        }
+        Ok(())
+    }
+
+    /// Create a capability for `range` of `alloc_id`, and return its tag.
+    pub fn set_bounds(&mut self, alloc_id: AllocId, range: AllocRange, span: SpanData) -> BorTag {
+        let tag = self.next_tag;
+        self.next_tag = tag.succ().unwrap();
+        self.bounds.insert(tag, (alloc_id, range.start, range.end(), span));
+        tag
+    }
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Implements `miri_cheri_set_bounds`: derive a capability from `ptr` that can only access
+    /// the `len` bytes starting at `ptr`.
+    fn cheri_set_bounds(&mut self, ptr: Pointer, len: u64) -> InterpResult<'tcx, Pointer> {
+        let this = self.eval_context_mut();
+        if this.machine.capabilities.is_none() {
+            throw_unsup_format!("`miri_cheri_set_bounds` requires `-Zmiri-cheri`");
+        }
+        let (alloc_id, offset, tag) = this.ptr_get_alloc_id(ptr)?;
+        let range = alloc_range(offset, Size::from_bytes(len));
+        // Bounds can only ever shrink.
+        let (size, _align, _kind) = this.get_alloc_info(alloc_id);
+        if range.end() > size {
+            throw_machine_stop!(TerminationInfo::CapabilityFault {
+                msg: format!(
+                    "setting the bounds of a capability to {alloc_id:?}[{:#x}..{:#x}], which is outside of its allocation of size {}",
+                    range.start.bytes(),
+                    range.end().bytes(),
+                    size.bytes(),
+                ),
+                bounds_set: None,
+            });
+        }
+        let capabilities = this.machine.capabilities.as_ref().unwrap();
+        capabilities.borrow().check(
+            alloc_id,
+            tag,
+            range,
+            "setting the bounds of a capability to",
+        )?;
+        let span = this.machine.current_span().data();
+        let tag = capabilities.borrow_mut().set_bounds(alloc_id, range, span);
+        Ok(Pointer::new(Some(Provenance::Concrete { alloc_id, tag }), ptr.addr()))
+    }
+}
@@ -2,11 +2,11 @@ use std::cell::Cell;
 use std::time::{Duration, Instant as StdInstant};
 
 /// When using a virtual clock, this defines how many nanoseconds we pretend are passing for each
-/// basic block.
+/// basic block, unless configured otherwise with `-Zmiri-clock-rate`.
 /// This number is pretty random, but it has been shown to approximately cause
 /// some sample programs to run within an order of magnitude of real time on desktop CPUs.
 /// (See `tests/pass/shims/time-with-isolation*.rs`.)
-const NANOSECONDS_PER_BASIC_BLOCK: u128 = 5000;
+pub const NANOSECONDS_PER_BASIC_BLOCK: u128 = 5000;
 
 #[derive(Debug)]
 pub struct Instant {
@@ -76,16 +76,27 @@ enum ClockKind {
     Virtual {
         /// The "current virtual time".
         nanoseconds: Cell<u128>,
+        /// How far the virtual time advances for each basic block. If this is 0, time only
+        /// passes when the program sleeps.
+        nanoseconds_per_block: u128,
+        /// The wall-clock time at virtual time 0, as the duration since the Unix epoch. If this is
+        /// `None`, the program cannot read the wall-clock time.
+        wall_clock_epoch: Option<Duration>,
     },
 }
 
 impl Clock {
-    /// Create a new clock based on the availability of communication with the host.
-    pub fn new(communicate: bool) -> Self {
+    /// Create a new clock based on the availability of communication with the host. The other
+    /// parameters only affect the virtual clock, which is used if we cannot communicate.
+    pub fn new(
+        communicate: bool,
+        nanoseconds_per_block: u128,
+        wall_clock_epoch: Option<Duration>,
+    ) -> Self {
         let kind = if communicate {
             ClockKind::Host { epoch: StdInstant::now() }
         } else {
-            ClockKind::Virtual { nanoseconds: 0.into() }
+            ClockKind::Virtual { nanoseconds: 0.into(), nanoseconds_per_block, wall_clock_epoch }
         };
 
         Self { kind }
@@ -97,8 +108,8 @@ impl Clock {
             ClockKind::Host { .. } => {
                 // Time will pass without us doing anything.
             }
-            ClockKind::Virtual { nanoseconds } => {
-                nanoseconds.update(|x| x + NANOSECONDS_PER_BASIC_BLOCK);
+            ClockKind::Virtual { nanoseconds, nanoseconds_per_block, .. } => {
+                nanoseconds.update(|x| x + *nanoseconds_per_block);
             }
         }
     }
@@ -107,7 +118,7 @@ impl Clock {
     pub fn sleep(&self, duration: Duration) {
         match &self.kind {
             ClockKind::Host { .. } => std::thread::sleep(duration),
-            ClockKind::Virtual { nanoseconds } => {
+            ClockKind::Virtual { nanoseconds, .. } => {
                 // Just pretend that we have slept for some time.
                 let nanos: u128 = duration.as_nanos();
                 nanoseconds.update(|x| {
@@ -129,8 +140,17 @@ impl Clock {
     pub fn now(&self) -> Instant {
         match &self.kind {
             ClockKind::Host { .. } => Instant { kind: InstantKind::Host(StdInstant::now()) },
-            ClockKind::Virtual { nanoseconds } =>
+            ClockKind::Virtual { nanoseconds, .. } =>
                 Instant { kind: InstantKind::Virtual { nanoseconds: nanoseconds.get() } },
         }
     }
+
+    /// For the virtual clock, return the wall-clock time at time = 0 (as the duration since the
+    /// Unix epoch), if one was configured with `-Zmiri-clock-epoch`.
+    pub fn wall_clock_epoch(&self) -> Option<Duration> {
+        match &self.kind {
+            ClockKind::Host { .. } => None,
+            ClockKind::Virtual { wall_clock_epoch, .. } => *wall_clock_epoch,
+        }
+    }
 }
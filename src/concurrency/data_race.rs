@@ -50,13 +50,14 @@ use rustc_ast::Mutability;
 use rustc_data_structures::fx::FxHashSet;
 use rustc_index::{Idx, IndexVec};
 use rustc_middle::{mir, ty::Ty};
-use rustc_span::Span;
+use rustc_span::{Span, SpanData};
 use rustc_target::abi::{Align, HasDataLayout, Size};
 
 use crate::diagnostics::RacingOp;
 use crate::*;
 
 use super::{
+    dpor::VisibleOp,
     vector_clock::{VClock, VTimestamp, VectorIdx},
     weak_memory::EvalContextExt as _,
 };
@@ -187,10 +188,13 @@ struct AtomicMemoryCellClocks {
     /// performed on the data.
     sync_vector: VClock,
 
-    /// The size of accesses to this atomic location.
+    /// The size of accesses to this atomic location, or `None` if unsynchronized reads of
+    /// different sizes happened here.
     /// We use this to detect non-synchronized mixed-size accesses. Since all accesses must be
     /// aligned to their size, this is sufficient to detect imperfectly overlapping accesses.
-    size: Size,
+    /// Unsynchronized mixed-size reads are fine, but a write must be synchronized with all
+    /// accesses of a different size.
+    size: Option<Size>,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -335,7 +339,7 @@ impl AtomicMemoryCellClocks {
             read_vector: Default::default(),
             write_vector: Default::default(),
             sync_vector: Default::default(),
-            size,
+            size: Some(size),
         }
     }
 }
@@ -377,22 +381,29 @@ impl MemoryCellClocks {
     }
 
     /// Load or create the internal atomic memory metadata if it does not exist. Also ensures we do
-    /// not do mixed-size atomic accesses, and updates the recorded atomic access size.
+    /// not do racing mixed-size atomic accesses (other than reads), and updates the recorded atomic
+    /// access size.
     fn atomic_access(
         &mut self,
         thread_clocks: &ThreadClockSet,
         size: Size,
+        write: bool,
     ) -> Result<&mut AtomicMemoryCellClocks, DataRace> {
         match self.atomic_ops {
             Some(ref mut atomic) => {
                 // We are good if the size is the same or all atomic accesses are before our current time.
-                if atomic.size == size {
+                if atomic.size == Some(size) {
                     Ok(atomic)
                 } else if atomic.read_vector <= thread_clocks.clock
                     && atomic.write_vector <= thread_clocks.clock
                 {
                     // This is now the new size that must be used for accesses here.
-                    atomic.size = size;
+                    atomic.size = Some(size);
+                    Ok(atomic)
+                } else if !write && atomic.write_vector <= thread_clocks.clock {
+                    // A read that only races with reads of a different size. That is fine, but
+                    // from now on, a write has to be synchronized with all these reads.
+                    atomic.size = None;
                     Ok(atomic)
                 } else {
                     Err(DataRace)
@@ -507,7 +518,7 @@ impl MemoryCellClocks {
         access_size: Size,
     ) -> Result<(), DataRace> {
         trace!("Atomic read with vectors: {:#?} :: {:#?}", self, thread_clocks);
-        let atomic = self.atomic_access(thread_clocks, access_size)?;
+        let atomic = self.atomic_access(thread_clocks, access_size, /* write */ false)?;
         atomic.read_vector.set_at_index(&thread_clocks.clock, index);
         // Make sure the last non-atomic write and all non-atomic reads were before this access.
         if self.write_was_before(&thread_clocks.clock) && self.read <= thread_clocks.clock {
@@ -526,7 +537,7 @@ impl MemoryCellClocks {
         access_size: Size,
     ) -> Result<(), DataRace> {
         trace!("Atomic write with vectors: {:#?} :: {:#?}", self, thread_clocks);
-        let atomic = self.atomic_access(thread_clocks, access_size)?;
+        let atomic = self.atomic_access(thread_clocks, access_size, /* write */ true)?;
         atomic.write_vector.set_at_index(&thread_clocks.clock, index);
         // Make sure the last non-atomic write and all non-atomic reads were before this access.
         if self.write_was_before(&thread_clocks.clock) && self.read <= thread_clocks.clock {
@@ -617,9 +628,15 @@ pub trait EvalContextExt<'tcx>: MiriInterpCxExt<'tcx> {
         // the *value* (including the associated provenance if this is an AtomicPtr) at this location.
         // Only metadata on the location itself is used.
         let scalar = this.allow_data_races_ref(move |this| this.read_scalar(place))?;
-        this.buffered_atomic_read(place, atomic, scalar, || {
-            this.validate_atomic_load(place, atomic)
-        })
+        let scalar = this.buffered_atomic_read(
+            place,
+            atomic,
+            scalar,
+            |_| true,
+            || this.validate_atomic_load(place, atomic),
+        )?;
+        this.check_spin_loop(place, scalar)?;
+        Ok(scalar)
     }
 
     /// Perform an atomic write operation at the memory location.
@@ -743,36 +760,48 @@ pub trait EvalContextExt<'tcx>: MiriInterpCxExt<'tcx> {
         // Read as immediate for the sake of `binary_op()`
         let old = this.allow_data_races_mut(|this| this.read_immediate(place))?;
         // `binary_op` will bail if either of them is not a scalar.
-        let eq = this.binary_op(mir::BinOp::Eq, &old, expect_old)?;
+        let eq = this.binary_op(mir::BinOp::Eq, &old, expect_old)?.to_scalar().to_bool()?;
         // If the operation would succeed, but is "weak", fail some portion
         // of the time, based on `success_rate`.
         let success_rate = 1.0 - this.machine.cmpxchg_weak_failure_rate;
-        let cmpxchg_success = eq.to_scalar().to_bool()?
+        let cmpxchg_success = eq
             && if can_fail_spuriously {
-                this.machine.rng.get_mut().gen_bool(success_rate)
+                let success = this.machine.rng.get_mut().gen_bool(success_rate);
+                this.machine.log_nondeterminism("cmpxchg-weak", || {
+                    if success { "succeeded" } else { "failed spuriously" }.to_owned()
+                });
+                success
             } else {
                 true
             };
-        let res = Immediate::ScalarPair(old.to_scalar(), Scalar::from_bool(cmpxchg_success));
 
         // Update ptr depending on comparison.
         // if successful, perform a full rw-atomic validation
         // otherwise treat this as an atomic load with the fail ordering.
-        if cmpxchg_success {
+        let loaded = if cmpxchg_success {
             this.allow_data_races_mut(|this| this.write_scalar(new, place))?;
             this.validate_atomic_rmw(place, success)?;
             this.buffered_atomic_rmw(new, place, success, old.to_scalar())?;
+            old.to_scalar()
         } else {
-            this.validate_atomic_load(place, fail)?;
-            // A failed compare exchange is equivalent to a load, reading from the latest store
-            // in the modification order.
-            // Since `old` is only a value and not the store element, we need to separately
-            // find it in our store buffer and perform load_impl on it.
-            this.perform_read_on_buffered_latest(place, fail, old.to_scalar())?;
-        }
+            // A failed compare exchange is just a load, so it may read from an older store just
+            // like `read_scalar_atomic`. However, unless this is a spurious failure, it must not
+            // read a value that would have made the comparison succeed.
+            // If the comparison cannot be performed, we conservatively consider the values equal.
+            let this = &*this;
+            let can_read = |val: Scalar| {
+                eq || !this
+                    .binary_op(mir::BinOp::Eq, &ImmTy::from_scalar(val, old.layout), expect_old)
+                    .and_then(|eq| eq.to_scalar().to_bool())
+                    .unwrap_or(true)
+            };
+            this.buffered_atomic_read(place, fail, old.to_scalar(), can_read, || {
+                this.validate_atomic_load(place, fail)
+            })?
+        };
 
         // Return the old value.
-        Ok(res)
+        Ok(Immediate::ScalarPair(loaded, Scalar::from_bool(cmpxchg_success)))
     }
 
     /// Update the data-race detector for an atomic fence on the current thread.
@@ -952,7 +981,9 @@ impl VClockAlloc {
         ty: Option<Ty<'_>>,
     ) -> InterpResult<'tcx> {
         let (active_index, active_clocks) = global.active_thread_state(thread_mgr);
-        let mut other_size = None; // if `Some`, this was a size-mismatch race
+        // If `Some`, this was a size-mismatch race. The inner `None` means that the other accesses
+        // were reads of several sizes.
+        let mut other_size: Option<Option<Size>> = None;
         let write_clock;
         let (other_access, other_thread, other_clock) =
             // First check the atomic-nonatomic cases. If it looks like multiple
@@ -975,7 +1006,7 @@ impl VClockAlloc {
             } else if let Some(idx) = Self::find_gt_index(&mem_clocks.read, &active_clocks.clock) {
                 (AccessType::NaRead(mem_clocks.read[idx].read_type()), idx, &mem_clocks.read)
             // Finally, mixed-size races.
-            } else if access.is_atomic() && let Some(atomic) = mem_clocks.atomic() && atomic.size != access_size {
+            } else if access.is_atomic() && let Some(atomic) = mem_clocks.atomic() && atomic.size != Some(access_size) {
                 // This is only a race if we are not synchronized with all atomic accesses, so find
                 // the one we are not synchronized with.
                 other_size = Some(atomic.size);
@@ -999,6 +1030,18 @@ impl VClockAlloc {
         let active_thread_info = global.print_thread_metadata(thread_mgr, active_index);
         let other_thread_info = global.print_thread_metadata(thread_mgr, other_thread);
         let involves_non_atomic = !access.is_atomic() || !other_access.is_atomic();
+        let other_timestamp = other_clock.as_slice()[other_thread.index()];
+        let explanation = if global.explain_data_races {
+            global.explain_race(
+                thread_mgr,
+                &active_clocks.clock,
+                active_index,
+                other_clock,
+                other_thread,
+            )
+        } else {
+            Vec::new()
+        };
 
         // Throw the data-race detection.
         let extra = if other_size.is_some() {
@@ -1016,16 +1059,25 @@ impl VClockAlloc {
             involves_non_atomic,
             extra,
             retag_explain: access.is_retag() || other_access.is_retag(),
+            explanation,
             ptr: ptr_dbg,
             op1: RacingOp {
-                action: other_access.description(None, other_size),
+                action: other_access.description(None, other_size.flatten()),
                 thread_info: other_thread_info,
-                span: other_clock.as_slice()[other_thread.index()].span_data(),
+                span: other_timestamp.span_data(),
+                thread: global.vector_info.borrow()[other_thread],
+                size: other_size.flatten().unwrap_or(access_size),
+                is_write: !other_access.is_read(),
+                is_atomic: other_access.is_atomic(),
             },
             op2: RacingOp {
                 action: access.description(ty, other_size.map(|_| access_size)),
                 thread_info: active_thread_info,
                 span: active_clocks.clock.as_slice()[active_index.index()].span_data(),
+                thread: thread_mgr.active_thread(),
+                size: access_size,
+                is_write: !access.is_read(),
+                is_atomic: access.is_atomic(),
             },
         }))?
     }
@@ -1179,9 +1231,14 @@ trait EvalContextPrivExt<'tcx>: MiriInterpCxExt<'tcx> {
         // <https://github.com/rust-lang/miri/pull/2464#discussion_r939636130> for details.
         // We avoid `get_ptr_alloc` since we do *not* want to run the access hooks -- the actual
         // access will happen later.
-        let (alloc_id, _offset, _prov) = this
+        let (alloc_id, offset, _prov) = this
             .ptr_try_get_alloc_id(place.ptr())
             .expect("there are no zero-sized atomic accesses");
+        this.machine.threads.record_visible_op(VisibleOp::Atomic {
+            alloc_id,
+            offset,
+            write: !matches!(access_type, AtomicAccessType::Load(_)),
+        });
         if this.get_alloc_mutability(alloc_id)? == Mutability::Not {
             // See if this is fine.
             match access_type {
@@ -1437,6 +1494,15 @@ pub struct GlobalState {
 
     /// Track when an outdated (weak memory) load happens.
     pub track_outdated_loads: bool,
+
+    /// Explain data races in terms of vector clocks.
+    explain_data_races: bool,
+
+    /// The maximum number of stores kept per location for weak memory emulation.
+    pub(super) store_buffer_limit: usize,
+
+    /// Which stores to drop from the weak memory store buffers.
+    pub(super) store_buffer_eviction: StoreBufferEviction,
 }
 
 impl VisitProvenance for GlobalState {
@@ -1459,6 +1525,9 @@ impl GlobalState {
             last_sc_fence: RefCell::new(VClock::default()),
             last_sc_write: RefCell::new(VClock::default()),
             track_outdated_loads: config.track_outdated_loads,
+            explain_data_races: config.explain_data_races,
+            store_buffer_limit: config.weak_memory_buffer_size,
+            store_buffer_eviction: config.weak_memory_eviction,
         };
 
         // Setup the main-thread since it is not explicitly created:
@@ -1671,8 +1740,78 @@ impl GlobalState {
     /// returns the id and the name for better diagnostics.
     fn print_thread_metadata(&self, thread_mgr: &ThreadManager<'_>, vector: VectorIdx) -> String {
         let thread = self.vector_info.borrow()[vector];
-        let thread_name = thread_mgr.get_thread_display_name(thread);
-        format!("thread `{thread_name}`")
+        format!("thread {}", thread_mgr.get_thread_diagnostic_name(thread))
+    }
+
+    /// Explain why the access of thread `other` races with the current access of thread `active`,
+    /// whose vector clock is `clock`. `other_clock` is the clock of the accesses to the location
+    /// that (1) is part of. List the synchronization that did happen between the threads, and
+    /// show that it was not enough.
+    fn explain_race(
+        &self,
+        thread_mgr: &ThreadManager<'_>,
+        clock: &VClock,
+        active: VectorIdx,
+        other_clock: &VClock,
+        other: VectorIdx,
+    ) -> Vec<(Option<SpanData>, String)> {
+        let other_thread = self.print_thread_metadata(thread_mgr, other);
+        let other_time = other_clock.as_slice()[other.index()];
+        let print_clock = |clock: &VClock| {
+            let entries: Vec<String> = clock
+                .as_slice()
+                .iter()
+                .enumerate()
+                .filter(|(_, time)| time.time() > 0)
+                .map(|(idx, time)| {
+                    let thread = self.print_thread_metadata(thread_mgr, VectorIdx::new(idx));
+                    format!("{thread}: {}", time.time())
+                })
+                .collect();
+            entries.join(", ")
+        };
+        let mut notes = Vec::new();
+        notes.push((
+            None,
+            format!(
+                "(1) and the other accesses of its kind to this location happened at [{}]",
+                print_clock(other_clock)
+            ),
+        ));
+        notes.push((None, format!("the vector clock of (2) is [{}]", print_clock(clock))));
+        notes.push((
+            None,
+            format!(
+                "(1) happened at time {} of {other_thread}, but (2) is only synchronized with {other_thread} up to time {}",
+                other_time.time(),
+                clock[other].time(),
+            ),
+        ));
+        // The last operation of each thread that (2) is synchronized with. These are the
+        // release operations that partially ordered the two accesses.
+        for (idx, time) in clock.as_slice().iter().enumerate() {
+            let idx = VectorIdx::new(idx);
+            if idx == active || time.time() == 0 {
+                continue;
+            }
+            let thread = self.print_thread_metadata(thread_mgr, idx);
+            let msg = if idx == other {
+                format!(
+                    "(2) is synchronized with {thread} up to this operation, which is before (1)"
+                )
+            } else {
+                format!("(2) is synchronized with {thread} up to this operation")
+            };
+            if time.span.is_dummy() {
+                notes.push((None, format!("{msg} (time {})", time.time())));
+            } else {
+                notes.push((Some(time.span_data()), msg));
+            }
+        }
+        let vector_info = self.vector_info.borrow();
+        notes.extend(thread_mgr.spawn_site_note(vector_info[other]));
+        notes.extend(thread_mgr.spawn_site_note(vector_info[active]));
+        notes
     }
 
     /// Acquire the given clock into the current thread, establishing synchronization with
@@ -1726,6 +1865,12 @@ impl GlobalState {
         (index, clocks)
     }
 
+    /// The sets of vector clocks of all vector indices, including those of threads that have
+    /// terminated.
+    pub(super) fn all_thread_states(&self) -> Ref<'_, IndexVec<VectorIdx, ThreadClockSet>> {
+        self.vector_clocks.borrow()
+    }
+
     /// Load the current vector clock in use and the current set of thread clocks
     /// in use for the vector.
     #[inline]
@@ -0,0 +1,343 @@
+//! Systematic exploration of thread interleavings using dynamic partial-order reduction (DPOR).
+//!
+//! Instead of preempting threads at random, the program is executed several times. Threads only
+//! switch at *scheduling points*, which are right after *visible operations*: atomic accesses and
+//! operations on synchronization objects. (Non-atomic accesses do not need to be considered: if
+//! two of them conflict and are not ordered by the visible operations, the data race detector
+//! reports them in every interleaving.) The first execution follows a "run the active thread as
+//! long as possible" policy. Afterwards, we look for pairs of conflicting visible operations of
+//! different threads that are not ordered by happens-before; for each such *race*, we schedule
+//! an execution that reverses it, following the classic algorithm by Flanagan and Godefroid.
+//! Executions replay the prefix of scheduling decisions leading up to the point where the race
+//! gets reversed, and then continue with the default policy again.
+//!
+//! To keep this tractable, the exploration is bounded by the number of *preemptions*, i.e.,
+//! switches away from a thread that could have kept running.
+//!
+//! In model-checking mode (`-Zmiri-model-check`), the preemption bound is lifted and weak memory
+//! effects are explored as well: whenever an atomic load may read from several stores, each of
+//! them is tried in some execution (a *load choice*). Together, this covers all executions that
+//! Miri's weak memory emulation can produce with the random decisions it makes elsewhere (like
+//! spurious failures of `compare_exchange_weak`), so such a bug in a small test is found with
+//! certainty rather than with some probability.
+
+use std::collections::BTreeSet;
+
+use rustc_data_structures::fx::FxHashMap;
+use rustc_target::abi::Size;
+
+use crate::*;
+
+/// A synchronization object whose operations are visible to other threads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SyncObject {
+    Mutex(MutexId),
+    RwLock(RwLockId),
+    Condvar(CondvarId),
+    Futex(u64),
+    Thread(ThreadId),
+}
+
+/// An operation that other threads can observe.
+#[derive(Clone, Copy, Debug)]
+pub enum VisibleOp {
+    /// An atomic access to the given location.
+    Atomic { alloc_id: AllocId, offset: Size, write: bool },
+    /// An operation on a synchronization object. All such operations are considered to conflict
+    /// with each other.
+    Sync(SyncObject),
+    /// Creation of the given thread.
+    Spawn(ThreadId),
+}
+
+/// What visible operations conflict on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Location {
+    Atomic(AllocId, Size),
+    Sync(SyncObject),
+}
+
+/// A decision of the scheduler.
+#[derive(Debug)]
+struct SchedulingPoint {
+    /// The thread that was running before this point.
+    previous: ThreadId,
+    /// Whether `previous` explicitly yielded.
+    yielded: bool,
+    /// The threads that could have been picked.
+    enabled: Vec<ThreadId>,
+    /// The thread that was picked.
+    chosen: ThreadId,
+    /// The number of preemptions up to and including this point.
+    preemptions: u32,
+    /// The threads that still have to be picked here in some execution.
+    backtrack: BTreeSet<ThreadId>,
+    /// The threads that have already been picked here.
+    done: BTreeSet<ThreadId>,
+}
+
+impl SchedulingPoint {
+    fn is_preemption(&self, chosen: ThreadId) -> bool {
+        chosen != self.previous && !self.yielded && self.enabled.contains(&self.previous)
+    }
+}
+
+/// The decision which of several stores a load reads from.
+#[derive(Debug)]
+struct LoadChoice {
+    /// The number of scheduling points that happened before this load.
+    after_point: usize,
+    /// The number of stores that could have been picked.
+    options: usize,
+    /// The store that was picked.
+    chosen: usize,
+}
+
+/// A visible operation performed during the current execution.
+#[derive(Debug)]
+struct Step {
+    thread: ThreadId,
+    op: VisibleOp,
+    /// The scheduling point that started the segment of execution this operation is part of.
+    /// `None` if it happened before the first scheduling point.
+    point: Option<usize>,
+}
+
+#[derive(Debug)]
+pub struct Dpor {
+    preemption_bound: u32,
+    /// The scheduling points of the current execution. At the start of an execution, this is the
+    /// prefix that gets replayed.
+    points: Vec<SchedulingPoint>,
+    /// The next scheduling point of the current execution.
+    next_point: usize,
+    /// Whether loads explore all the stores they may read from.
+    explore_loads: bool,
+    /// The load choices of the current execution, like `points`.
+    choices: Vec<LoadChoice>,
+    /// The next load choice of the current execution.
+    next_choice: usize,
+    /// The visible operations of the current execution.
+    steps: Vec<Step>,
+    /// Whether the active thread performed a visible operation since the last scheduling point.
+    after_visible_op: bool,
+    /// How many executions were started so far.
+    executions: u64,
+}
+
+impl Dpor {
+    pub fn new(preemption_bound: u32, explore_loads: bool) -> Self {
+        Dpor {
+            preemption_bound,
+            points: Vec::new(),
+            next_point: 0,
+            explore_loads,
+            choices: Vec::new(),
+            next_choice: 0,
+            steps: Vec::new(),
+            after_visible_op: false,
+            executions: 1,
+        }
+    }
+
+    /// How many executions were started so far.
+    pub fn executions(&self) -> u64 {
+        self.executions
+    }
+
+    /// Whether the scheduler has to make a decision before the next step.
+    pub fn at_scheduling_point(&self) -> bool {
+        self.after_visible_op
+    }
+
+    /// Whether loads should let [`Dpor::choose_load`] pick the store they read from.
+    pub fn explores_loads(&self) -> bool {
+        self.explore_loads
+    }
+
+    /// Pick which of `options` stores a load reads from.
+    pub fn choose_load(&mut self, options: usize) -> usize {
+        assert!(options > 0);
+        let idx = self.next_choice;
+        self.next_choice += 1;
+        if let Some(choice) = self.choices.get(idx) {
+            if choice.after_point == self.next_point && choice.options == options {
+                return choice.chosen;
+            }
+            self.choices.truncate(idx);
+        }
+        self.choices.push(LoadChoice { after_point: self.next_point, options, chosen: 0 });
+        0
+    }
+
+    /// Record that `thread` performed a visible operation.
+    pub fn record(&mut self, thread: ThreadId, op: VisibleOp) {
+        let point = self.next_point.checked_sub(1);
+        self.steps.push(Step { thread, op, point });
+        self.after_visible_op = true;
+    }
+
+    /// Pick the thread to run next. `enabled` must be non-empty.
+    pub fn schedule(
+        &mut self,
+        active: ThreadId,
+        yielded: bool,
+        enabled: Vec<ThreadId>,
+    ) -> ThreadId {
+        assert!(!enabled.is_empty());
+        self.after_visible_op = false;
+        let idx = self.next_point;
+        self.next_point += 1;
+        if let Some(point) = self.points.get(idx) {
+            if point.previous == active && point.enabled == enabled {
+                return point.chosen;
+            }
+            // The execution diverged from the one we are replaying (e.g. because it depends on
+            // the host's clock). Continue with the default policy from here on.
+            self.points.truncate(idx);
+            self.choices.retain(|choice| choice.after_point <= idx);
+        }
+        let chosen = if !yielded && enabled.contains(&active) {
+            active
+        } else {
+            // Round-robin, starting after the active thread (and only picking the active thread
+            // if it is the only option).
+            *enabled.iter().find(|&&t| t > active).unwrap_or(&enabled[0])
+        };
+        let mut point = SchedulingPoint {
+            previous: active,
+            yielded,
+            enabled,
+            chosen,
+            preemptions: self.points.last().map_or(0, |p| p.preemptions),
+            backtrack: BTreeSet::new(),
+            done: BTreeSet::from([chosen]),
+        };
+        if point.is_preemption(chosen) {
+            point.preemptions += 1;
+        }
+        self.points.push(point);
+        chosen
+    }
+
+    /// Analyze the races of the execution that just finished, and prepare the next execution.
+    /// Returns `false` if the exploration is complete.
+    pub fn next_execution(&mut self) -> bool {
+        self.find_races();
+        // Backtrack to the deepest decision that still has an alternative to explore: either a
+        // scheduling point with a thread to explore, or a load with a store not yet read from.
+        loop {
+            let last_point = self.points.len().checked_sub(1);
+            if let Some(choice) = self.choices.last_mut()
+                && last_point.is_none_or(|idx| choice.after_point > idx)
+            {
+                if choice.chosen + 1 < choice.options {
+                    choice.chosen += 1;
+                    self.start_execution();
+                    return true;
+                }
+                self.choices.pop();
+                continue;
+            }
+            let Some(idx) = last_point else { return false };
+            let preemptions_before = idx.checked_sub(1).map_or(0, |p| self.points[p].preemptions);
+            let point = &mut self.points[idx];
+            if let Some(&thread) = point.backtrack.difference(&point.done).next() {
+                point.chosen = thread;
+                point.done.insert(thread);
+                point.preemptions = preemptions_before + u32::from(point.is_preemption(thread));
+                self.start_execution();
+                return true;
+            }
+            self.points.pop();
+        }
+    }
+
+    fn start_execution(&mut self) {
+        self.next_point = 0;
+        self.next_choice = 0;
+        self.steps.clear();
+        self.after_visible_op = false;
+        self.executions += 1;
+    }
+
+    /// Find all pairs of conflicting visible operations that are not ordered by happens-before,
+    /// and add the thread of the later operation to the backtrack set of the scheduling point
+    /// before the earlier one.
+    fn find_races(&mut self) {
+        // For each thread, how many of its steps happen-before its current position, for each
+        // thread. (A simple vector clock.)
+        let mut clocks: FxHashMap<ThreadId, FxHashMap<ThreadId, usize>> = FxHashMap::default();
+        // The vector clock of each step, and its index among its thread's steps.
+        let mut step_clocks: Vec<FxHashMap<ThreadId, usize>> = Vec::new();
+        let mut step_indices: Vec<usize> = Vec::new();
+        // For each location, the last write and the reads since then.
+        let mut accesses: FxHashMap<Location, (Option<usize>, Vec<usize>)> = FxHashMap::default();
+        let mut races = Vec::new();
+
+        for (j, step) in self.steps.iter().enumerate() {
+            let mut clock = clocks.get(&step.thread).cloned().unwrap_or_default();
+            let idx = clock.get(&step.thread).copied().unwrap_or(0) + 1;
+            let (location, write) = match step.op {
+                VisibleOp::Atomic { alloc_id, offset, write } =>
+                    (Some(Location::Atomic(alloc_id, offset)), write),
+                VisibleOp::Sync(obj) => (Some(Location::Sync(obj)), true),
+                VisibleOp::Spawn(_) => (None, false),
+            };
+            if let Some(location) = location {
+                let (last_write, reads) = accesses.entry(location).or_default();
+                let dependent: Vec<usize> = last_write
+                    .iter()
+                    .copied()
+                    .chain(if write { reads.clone() } else { vec![] })
+                    .collect();
+                let before = clock.clone();
+                for i in dependent {
+                    let other = self.steps[i].thread;
+                    if other != step.thread
+                        && before.get(&other).copied().unwrap_or(0) < step_indices[i]
+                    {
+                        races.push((i, j));
+                    }
+                    for (&t, &c) in &step_clocks[i] {
+                        let entry = clock.entry(t).or_insert(0);
+                        *entry = (*entry).max(c);
+                    }
+                }
+                if write {
+                    *last_write = Some(j);
+                    reads.clear();
+                } else {
+                    reads.push(j);
+                }
+            }
+            clock.insert(step.thread, idx);
+            if let VisibleOp::Spawn(child) = step.op {
+                // Everything the parent did so far happens-before the child.
+                clocks.insert(child, clock.clone());
+            }
+            clocks.insert(step.thread, clock.clone());
+            step_clocks.push(clock);
+            step_indices.push(idx);
+        }
+
+        for (i, j) in races {
+            let Some(point_idx) = self.steps[i].point else { continue };
+            let thread = self.steps[j].thread;
+            let preemptions_before =
+                point_idx.checked_sub(1).map_or(0, |p| self.points[p].preemptions);
+            let point = &mut self.points[point_idx];
+            let candidates =
+                if point.enabled.contains(&thread) { vec![thread] } else { point.enabled.clone() };
+            for candidate in candidates {
+                if point.done.contains(&candidate) {
+                    continue;
+                }
+                let preemptions = preemptions_before + u32::from(point.is_preemption(candidate));
+                if preemptions <= self.preemption_bound {
+                    point.backtrack.insert(candidate);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! Tracking of the order in which locks are acquired, to detect potential deadlocks.
+//!
+//! Whenever a thread acquires a lock while already holding some other locks, we record an edge
+//! from each held lock to the newly acquired one. A cycle in the resulting graph means that
+//! there exists an interleaving of the threads involved in which each of them waits for a lock
+//! held by the next one, i.e., a deadlock -- even if the current execution did not run into it.
+
+use std::fmt;
+
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
+use rustc_span::Span;
+
+use super::sync::SyncId;
+use crate::*;
+
+/// A lock that participates in the lock-order graph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LockId {
+    Mutex(MutexId),
+    RwLock(RwLockId),
+}
+
+impl fmt::Display for LockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockId::Mutex(id) => write!(f, "mutex #{}", id.to_u32()),
+            LockId::RwLock(id) => write!(f, "rwlock #{}", id.to_u32()),
+        }
+    }
+}
+
+/// Where a thread acquired a lock.
+#[derive(Clone, Debug)]
+pub struct Acquisition<'tcx> {
+    /// The span in the closest user-relevant frame.
+    pub span: Span,
+    pub backtrace: Vec<FrameInfo<'tcx>>,
+}
+
+/// The first time we saw `thread` acquire `to` while holding `from`.
+#[derive(Clone, Debug)]
+pub struct LockOrderEdge<'tcx> {
+    pub from: LockId,
+    pub to: LockId,
+    pub thread: ThreadId,
+    /// Where `from` was acquired.
+    pub from_acquisition: Acquisition<'tcx>,
+    /// Where `to` was acquired.
+    pub to_acquisition: Acquisition<'tcx>,
+}
+
+#[derive(Debug)]
+pub struct LockOrderGraph<'tcx> {
+    /// The locks currently held by each thread, in acquisition order, together with where they
+    /// were acquired.
+    held: FxHashMap<ThreadId, Vec<(LockId, Acquisition<'tcx>)>>,
+    /// For each lock, the locks that have been acquired while holding it. There is one edge for
+    /// each thread that did so, since whether a cycle can deadlock depends on which threads it
+    /// involves.
+    edges: FxHashMap<LockId, Vec<LockOrderEdge<'tcx>>>,
+    /// The cycles we already reported, identified by their (sorted) set of locks.
+    reported: FxHashSet<Vec<LockId>>,
+}
+
+impl Default for LockOrderGraph<'_> {
+    fn default() -> Self {
+        LockOrderGraph {
+            held: FxHashMap::default(),
+            edges: FxHashMap::default(),
+            reported: FxHashSet::default(),
+        }
+    }
+}
+
+impl<'tcx> LockOrderGraph<'tcx> {
+    /// Record that `thread` acquired `lock`. Returns a cycle of edges if this acquisition closes
+    /// a lock-order cycle that was not reported before.
+    pub fn acquire(
+        &mut self,
+        thread: ThreadId,
+        lock: LockId,
+        acquisition: Acquisition<'tcx>,
+    ) -> Option<Vec<LockOrderEdge<'tcx>>> {
+        let held = self.held.entry(thread).or_default();
+        let mut new_edges = Vec::new();
+        for (from, from_acquisition) in held.iter() {
+            if *from == lock {
+                continue;
+            }
+            let out = self.edges.entry(*from).or_default();
+            if out.iter().all(|edge| edge.to != lock || edge.thread != thread) {
+                let edge = LockOrderEdge {
+                    from: *from,
+                    to: lock,
+                    thread,
+                    from_acquisition: from_acquisition.clone(),
+                    to_acquisition: acquisition.clone(),
+                };
+                out.push(edge.clone());
+                new_edges.push(edge);
+            }
+        }
+        held.push((lock, acquisition));
+
+        for edge in new_edges {
+            let Some(mut cycle) = self.find_path(edge.to, edge.from, edge.thread) else { continue };
+            cycle.insert(0, edge);
+            let mut key: Vec<LockId> = cycle.iter().map(|e| e.from).collect();
+            key.sort();
+            if self.reported.insert(key) {
+                return Some(cycle);
+            }
+        }
+        None
+    }
+
+    /// Record that `thread` released `lock`.
+    pub fn release(&mut self, thread: ThreadId, lock: LockId) {
+        let held = self.held.entry(thread).or_default();
+        if let Some(pos) = held.iter().rposition(|(l, _)| *l == lock) {
+            held.remove(pos);
+        }
+    }
+
+    /// The locks currently held by `thread`, together with where they were acquired.
+    pub fn held_by(&self, thread: ThreadId) -> &[(LockId, Acquisition<'tcx>)] {
+        self.held.get(&thread).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Find a path of edges from `start` to `target` that contains an edge of some thread other
+    /// than `thread`: a cycle that only a single thread contributed to cannot deadlock. This is a
+    /// breadth-first search over pairs of a lock and whether such an edge was taken yet, so that
+    /// we report the shortest cycle.
+    fn find_path(
+        &self,
+        start: LockId,
+        target: LockId,
+        thread: ThreadId,
+    ) -> Option<Vec<LockOrderEdge<'tcx>>> {
+        let mut pred: FxHashMap<(LockId, bool), ((LockId, bool), &LockOrderEdge<'tcx>)> =
+            FxHashMap::default();
+        let mut visited = FxHashSet::default();
+        visited.insert((start, false));
+        let mut queue = std::collections::VecDeque::from([(start, false)]);
+        while let Some(state) = queue.pop_front() {
+            let (lock, other_thread) = state;
+            if lock == target {
+                if !other_thread {
+                    // Going on from here would visit `target` twice.
+                    continue;
+                }
+                let mut path = Vec::new();
+                let mut cur = state;
+                while cur != (start, false) {
+                    let (prev, edge) = pred[&cur];
+                    path.push(edge.clone());
+                    cur = prev;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for edge in self.edges.get(&lock).into_iter().flatten() {
+                if edge.to == start {
+                    continue;
+                }
+                let next = (edge.to, other_thread || edge.thread != thread);
+                if visited.insert(next) {
+                    pred.insert(next, (state, edge));
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+}
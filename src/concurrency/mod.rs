@@ -1,5 +1,7 @@
 pub mod data_race;
+pub mod dpor;
 pub mod init_once;
+mod lock_order;
 mod range_object_map;
 pub mod sync;
 pub mod thread;
@@ -5,9 +5,13 @@ use std::time::Duration;
 use rustc_data_structures::fx::FxHashMap;
 use rustc_index::{Idx, IndexVec};
 use rustc_middle::ty::layout::TyAndLayout;
+use rustc_span::SpanData;
 
+use super::dpor::{SyncObject, VisibleOp};
 use super::init_once::InitOnce;
+use super::lock_order::{Acquisition, LockId, LockOrderGraph};
 use super::vector_clock::VClock;
+use crate::diagnostics::report_lock_acquisitions;
 use crate::*;
 
 pub trait SyncId {
@@ -66,6 +70,47 @@ pub(super) use declare_id;
 
 declare_id!(MutexId);
 
+/// How contended a lock or futex was, for `-Zmiri-report-contention`.
+#[derive(Default, Debug)]
+struct ContentionStats {
+    /// How often the lock was acquired.
+    acquisitions: u64,
+    /// How often a thread had to block.
+    contended: u64,
+    /// The total time threads spent blocked.
+    blocked_time: Duration,
+    /// The largest number of threads that were blocked at the same time.
+    max_waiters: usize,
+    /// When each currently blocked thread started to wait.
+    blocked_since: FxHashMap<ThreadId, Instant>,
+}
+
+impl ContentionStats {
+    fn block(&mut self, thread: ThreadId, now: Instant) {
+        self.contended += 1;
+        self.blocked_since.insert(thread, now);
+        self.max_waiters = self.max_waiters.max(self.blocked_since.len());
+    }
+
+    fn unblock(&mut self, thread: ThreadId, now: Instant) {
+        if let Some(since) = self.blocked_since.remove(&thread) {
+            self.blocked_time += now.duration_since(since);
+        }
+    }
+
+    fn describe(&self, object: String) -> String {
+        let acquisitions = if self.acquisitions > 0 {
+            format!("{} acquisition(s), ", self.acquisitions)
+        } else {
+            String::new()
+        };
+        format!(
+            "{object}: {acquisitions}{} contended, at most {} waiter(s), blocked for {:?} in total",
+            self.contended, self.max_waiters, self.blocked_time
+        )
+    }
+}
+
 /// The mutex state.
 #[derive(Default, Debug)]
 struct Mutex {
@@ -77,6 +122,8 @@ struct Mutex {
     queue: VecDeque<ThreadId>,
     /// Mutex clock. This tracks the moment of the last unlock.
     clock: VClock,
+    /// How contended this mutex is.
+    stats: ContentionStats,
 }
 
 declare_id!(RwLockId);
@@ -113,6 +160,8 @@ struct RwLock {
     /// locks.
     /// This is only relevant when there is an active reader.
     clock_current_readers: VClock,
+    /// How contended this lock is.
+    stats: ContentionStats,
 }
 
 declare_id!(CondvarId);
@@ -127,6 +176,8 @@ struct Condvar {
     /// Contains the clock of the last thread to
     /// perform a condvar-signal.
     clock: VClock,
+    /// The last thread that signaled this condvar.
+    last_signaled_by: Option<ThreadId>,
 }
 
 /// The futex state.
@@ -139,6 +190,10 @@ struct Futex {
     /// Contains the clock of the last thread to
     /// perform a futex-wake.
     clock: VClock,
+    /// The last thread that woke this futex.
+    last_woken_by: Option<ThreadId>,
+    /// How often threads waited on this futex, and for how long.
+    stats: ContentionStats,
 }
 
 /// A thread waiting on a futex.
@@ -152,12 +207,159 @@ struct FutexWaiter {
 
 /// The state of all synchronization objects.
 #[derive(Default, Debug)]
-pub struct SynchronizationObjects {
+pub struct SynchronizationObjects<'tcx> {
     mutexes: IndexVec<MutexId, Mutex>,
     rwlocks: IndexVec<RwLockId, RwLock>,
     condvars: IndexVec<CondvarId, Condvar>,
     futexes: FxHashMap<u64, Futex>,
     pub(super) init_onces: IndexVec<InitOnceId, InitOnce>,
+    /// The lock-acquisition-order graph, if `-Zmiri-track-lock-order` is set.
+    lock_order: Option<LockOrderGraph<'tcx>>,
+}
+
+impl<'tcx> SynchronizationObjects<'tcx> {
+    pub fn new(track_lock_order: bool) -> Self {
+        SynchronizationObjects {
+            lock_order: track_lock_order.then(LockOrderGraph::default),
+            ..Default::default()
+        }
+    }
+
+    /// Summarize how contended each lock and futex was, most contended first. Returns `None` if
+    /// the program did not use any.
+    pub fn contention_report(&self) -> Option<String> {
+        let mut entries: Vec<(&ContentionStats, String)> = Vec::new();
+        for (id, mutex) in self.mutexes.iter_enumerated() {
+            entries.push((&mutex.stats, LockId::Mutex(id).to_string()));
+        }
+        for (id, rwlock) in self.rwlocks.iter_enumerated() {
+            entries.push((&rwlock.stats, LockId::RwLock(id).to_string()));
+        }
+        let mut futexes: Vec<_> = self.futexes.iter().collect();
+        futexes.sort_by_key(|&(addr, _)| *addr);
+        for (addr, futex) in futexes {
+            entries.push((&futex.stats, format!("futex at {addr:#x}")));
+        }
+        entries.retain(|(stats, _)| stats.acquisitions > 0 || stats.contended > 0);
+        if entries.is_empty() {
+            return None;
+        }
+        // The sort is stable, so objects with the same contention stay in order of creation.
+        entries.sort_by(|(a, _), (b, _)| {
+            b.contended.cmp(&a.contended).then(b.blocked_time.cmp(&a.blocked_time))
+        });
+        let mut msg = String::from("lock contention statistics:");
+        for (stats, object) in entries {
+            msg.push_str("\n  ");
+            msg.push_str(&stats.describe(object));
+        }
+        Some(msg)
+    }
+
+    /// Describe what the given thread is blocked on, and which thread could have unblocked it:
+    /// the holder of the lock it waits for, or the last thread that signaled the condvar or futex.
+    /// Returns `None` if the thread is not blocked.
+    pub fn describe_wait(&self, threads: &ThreadManager<'_>, thread: ThreadId) -> Option<String> {
+        let (_, reason) = threads.blocked_threads().find(|&(id, _)| id == thread)?;
+        let name = |thread| threads.get_thread_diagnostic_name(thread);
+        let waiter = name(thread);
+        Some(match reason {
+            BlockReason::Join(joined) =>
+                format!("thread {waiter} is waiting for thread {} to terminate", name(joined)),
+            BlockReason::Sleep => format!("thread {waiter} is sleeping"),
+            BlockReason::Mutex(id) =>
+                match self.mutexes[id].owner {
+                    Some(owner) =>
+                        format!(
+                            "thread {waiter} is waiting to lock a mutex that is held by thread {}",
+                            name(owner)
+                        ),
+                    None => format!("thread {waiter} is waiting to lock a mutex"),
+                },
+            BlockReason::RwLock(id) => {
+                let rwlock = &self.rwlocks[id];
+                if let Some(writer) = rwlock.writer {
+                    format!(
+                        "thread {waiter} is waiting to lock a rwlock that is write-locked by thread {}",
+                        name(writer)
+                    )
+                } else {
+                    let mut readers = rwlock.readers.keys().copied().collect::<Vec<_>>();
+                    readers.sort();
+                    let threads = if readers.len() == 1 { "thread" } else { "threads" };
+                    let readers = readers.into_iter().map(name).collect::<Vec<_>>().join(", ");
+                    format!(
+                        "thread {waiter} is waiting to lock a rwlock that is read-locked by {threads} {readers}"
+                    )
+                }
+            }
+            BlockReason::Condvar(id) =>
+                match self.condvars[id].last_signaled_by {
+                    Some(signaler) =>
+                        format!(
+                            "thread {waiter} is waiting on a condition variable that was last signaled by thread {}",
+                            name(signaler)
+                        ),
+                    None =>
+                        format!(
+                            "thread {waiter} is waiting on a condition variable that was never signaled"
+                        ),
+                },
+            BlockReason::Futex { addr } =>
+                match self.futexes.get(&addr).and_then(|futex| futex.last_woken_by) {
+                    Some(waker) =>
+                        format!(
+                            "thread {waiter} is waiting on a futex that was last woken by thread {}",
+                            name(waker)
+                        ),
+                    None =>
+                        format!(
+                            "thread {waiter} is waiting on a futex that was never woken since the first thread waited on it"
+                        ),
+                },
+            BlockReason::InitOnce(_) =>
+                format!(
+                    "thread {waiter} is waiting for a one-time initialization by another thread to complete"
+                ),
+        })
+    }
+
+    /// Describe which locks the blocked threads are waiting for, who holds those locks, and
+    /// where the waiting threads acquired the locks they hold themselves. Only available if
+    /// `-Zmiri-track-lock-order` is set.
+    pub fn describe_lock_waits(
+        &self,
+        threads: &ThreadManager<'_>,
+    ) -> Vec<(Option<SpanData>, String)> {
+        let Some(lock_order) = &self.lock_order else { return vec![] };
+        let name = |thread| threads.get_thread_diagnostic_name(thread);
+        let mut notes = vec![];
+        for (thread, reason) in threads.blocked_threads() {
+            let (lock, holders) = match reason {
+                BlockReason::Mutex(id) =>
+                    (LockId::Mutex(id), self.mutexes[id].owner.into_iter().collect::<Vec<_>>()),
+                BlockReason::RwLock(id) => {
+                    let rwlock = &self.rwlocks[id];
+                    let holders = rwlock.writer.into_iter().chain(rwlock.readers.keys().copied());
+                    (LockId::RwLock(id), holders.collect())
+                }
+                _ => continue,
+            };
+            let holders = holders.into_iter().map(name).collect::<Vec<_>>().join(", ");
+            notes.push((
+                None,
+                format!("thread {} is waiting for {lock}, held by {holders}", name(thread)),
+            ));
+            for (held, acquisition) in lock_order.held_by(thread) {
+                notes.push((
+                    Some(acquisition.span.data()),
+                    format!("thread {} acquired {held} here", name(thread)),
+                ));
+            }
+            notes.extend(threads.spawn_site_note(thread));
+        }
+        notes
+    }
 }
 
 // Private extension trait for local helper methods
@@ -197,6 +399,62 @@ pub(super) trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
         })
     }
 
+    /// Record in the lock-order graph that the active thread acquired `lock`, and warn if this
+    /// closes a cycle.
+    fn lock_order_acquire(&mut self, lock: LockId) {
+        let this = self.eval_context_mut();
+        if this.machine.sync.lock_order.is_none() {
+            return;
+        }
+        let thread = this.machine.threads.active_thread();
+        let acquisition =
+            Acquisition { span: this.machine.current_span(), backtrace: this.generate_stacktrace() };
+        let lock_order = this.machine.sync.lock_order.as_mut().unwrap();
+        let Some(cycle) = lock_order.acquire(thread, lock, acquisition) else { return };
+        let name = |thread| this.machine.threads.get_thread_diagnostic_name(thread);
+        let mut locks = cycle.iter().map(|edge| edge.from.to_string()).collect::<Vec<_>>();
+        locks.push(cycle[0].from.to_string());
+        let mut events = vec![];
+        let mut acquisitions = vec![];
+        for edge in &cycle {
+            let thread = name(edge.thread);
+            events.push((
+                None,
+                format!("thread {thread} acquired {} while holding {}", edge.to, edge.from),
+            ));
+            acquisitions.push((
+                format!("thread {thread} acquired {} here, while holding {}:", edge.to, edge.from),
+                edge.thread,
+                edge.to_acquisition.backtrace.clone(),
+            ));
+            acquisitions.push((
+                format!("thread {thread} acquired {} here:", edge.from),
+                edge.thread,
+                edge.from_acquisition.backtrace.clone(),
+            ));
+        }
+        let mut involved: Vec<ThreadId> = cycle.iter().map(|edge| edge.thread).collect();
+        involved.sort();
+        involved.dedup();
+        events.extend(
+            involved.into_iter().filter_map(|thread| this.machine.threads.spawn_site_note(thread)),
+        );
+        this.emit_diagnostic(NonHaltingDiagnostic::PotentialDeadlock {
+            cycle: locks.join(" -> "),
+            events,
+        });
+        report_lock_acquisitions(&this.machine, acquisitions);
+    }
+
+    /// Record in the lock-order graph that the active thread released `lock`.
+    fn lock_order_release(&mut self, lock: LockId) {
+        let this = self.eval_context_mut();
+        let thread = this.machine.threads.active_thread();
+        if let Some(lock_order) = &mut this.machine.sync.lock_order {
+            lock_order.release(thread, lock);
+        }
+    }
+
     /// Provides the closure with the next MutexId. Creates that mutex if the closure returns None,
     /// otherwise returns the value from the closure.
     #[inline]
@@ -339,6 +597,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     /// Lock by setting the mutex owner and increasing the lock count.
     fn mutex_lock(&mut self, id: MutexId) {
         let this = self.eval_context_mut();
+        this.machine.threads.record_visible_op(VisibleOp::Sync(SyncObject::Mutex(id)));
         let thread = this.active_thread();
         let mutex = &mut this.machine.sync.mutexes[id];
         if let Some(current_owner) = mutex.owner {
@@ -347,10 +606,14 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 mutex.lock_count > 0,
                 "invariant violation: lock_count == 0 iff the thread is unlocked"
             );
+            mutex.lock_count = mutex.lock_count.strict_add(1);
         } else {
             mutex.owner = Some(thread);
+            mutex.lock_count = 1;
+            mutex.stats.acquisitions += 1;
+            this.lock_order_acquire(LockId::Mutex(id));
         }
-        mutex.lock_count = mutex.lock_count.strict_add(1);
+        let mutex = &this.machine.sync.mutexes[id];
         if let Some(data_race) = &this.machine.data_race {
             data_race.acquire_clock(&mutex.clock, &this.machine.threads);
         }
@@ -362,6 +625,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     /// return `None`.
     fn mutex_unlock(&mut self, id: MutexId) -> InterpResult<'tcx, Option<usize>> {
         let this = self.eval_context_mut();
+        this.machine.threads.record_visible_op(VisibleOp::Sync(SyncObject::Mutex(id)));
         let mutex = &mut this.machine.sync.mutexes[id];
         Ok(if let Some(current_owner) = mutex.owner {
             // Mutex is locked.
@@ -378,6 +642,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 if let Some(data_race) = &this.machine.data_race {
                     mutex.clock.clone_from(&data_race.release_clock(&this.machine.threads));
                 }
+                this.lock_order_release(LockId::Mutex(id));
                 if let Some(thread) = this.machine.sync.mutexes[id].queue.pop_front() {
                     this.unblock_thread(thread, BlockReason::Mutex(id))?;
                 }
@@ -394,9 +659,13 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     #[inline]
     fn mutex_enqueue_and_block(&mut self, id: MutexId, retval: Scalar, dest: MPlaceTy<'tcx>) {
         let this = self.eval_context_mut();
+        this.machine.threads.record_visible_op(VisibleOp::Sync(SyncObject::Mutex(id)));
         assert!(this.mutex_is_locked(id), "queing on unlocked mutex");
         let thread = this.active_thread();
-        this.machine.sync.mutexes[id].queue.push_back(thread);
+        let now = this.machine.clock.now();
+        let mutex = &mut this.machine.sync.mutexes[id];
+        mutex.queue.push_back(thread);
+        mutex.stats.block(thread, now);
         this.block_thread(
             BlockReason::Mutex(id),
             None,
@@ -408,6 +677,8 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 }
                 @unblock = |this| {
                     assert!(!this.mutex_is_locked(id));
+                    let (thread, now) = (this.active_thread(), this.machine.clock.now());
+                    this.machine.sync.mutexes[id].stats.unblock(thread, now);
                     this.mutex_lock(id);
                     this.write_scalar(retval, &dest)?;
                     Ok(())
@@ -443,12 +714,18 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     /// this lock.
     fn rwlock_reader_lock(&mut self, id: RwLockId) {
         let this = self.eval_context_mut();
+        this.machine.threads.record_visible_op(VisibleOp::Sync(SyncObject::RwLock(id)));
         let thread = this.active_thread();
         assert!(!this.rwlock_is_write_locked(id), "the lock is write locked");
         trace!("rwlock_reader_lock: {:?} now also held (one more time) by {:?}", id, thread);
         let rwlock = &mut this.machine.sync.rwlocks[id];
+        rwlock.stats.acquisitions += 1;
         let count = rwlock.readers.entry(thread).or_insert(0);
         *count = count.strict_add(1);
+        if *count == 1 {
+            this.lock_order_acquire(LockId::RwLock(id));
+        }
+        let rwlock = &this.machine.sync.rwlocks[id];
         if let Some(data_race) = &this.machine.data_race {
             data_race.acquire_clock(&rwlock.clock_unlocked, &this.machine.threads);
         }
@@ -458,6 +735,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     /// Returns `true` if succeeded, `false` if this `reader` did not hold the lock.
     fn rwlock_reader_unlock(&mut self, id: RwLockId) -> InterpResult<'tcx, bool> {
         let this = self.eval_context_mut();
+        this.machine.threads.record_visible_op(VisibleOp::Sync(SyncObject::RwLock(id)));
         let thread = this.active_thread();
         let rwlock = &mut this.machine.sync.rwlocks[id];
         match rwlock.readers.entry(thread) {
@@ -468,12 +746,14 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 if *count == 0 {
                     trace!("rwlock_reader_unlock: {:?} no longer held by {:?}", id, thread);
                     entry.remove();
+                    this.lock_order_release(LockId::RwLock(id));
                 } else {
                     trace!("rwlock_reader_unlock: {:?} held one less time by {:?}", id, thread);
                 }
             }
             Entry::Vacant(_) => return Ok(false), // we did not even own this lock
         }
+        let rwlock = &mut this.machine.sync.rwlocks[id];
         if let Some(data_race) = &this.machine.data_race {
             // Add this to the shared-release clock of all concurrent readers.
             rwlock.clock_current_readers.join(&data_race.release_clock(&this.machine.threads));
@@ -504,9 +784,13 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         dest: MPlaceTy<'tcx>,
     ) {
         let this = self.eval_context_mut();
+        this.machine.threads.record_visible_op(VisibleOp::Sync(SyncObject::RwLock(id)));
         let thread = this.active_thread();
         assert!(this.rwlock_is_write_locked(id), "read-queueing on not write locked rwlock");
-        this.machine.sync.rwlocks[id].reader_queue.push_back(thread);
+        let now = this.machine.clock.now();
+        let rwlock = &mut this.machine.sync.rwlocks[id];
+        rwlock.reader_queue.push_back(thread);
+        rwlock.stats.block(thread, now);
         this.block_thread(
             BlockReason::RwLock(id),
             None,
@@ -517,6 +801,8 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     dest: MPlaceTy<'tcx>,
                 }
                 @unblock = |this| {
+                    let (thread, now) = (this.active_thread(), this.machine.clock.now());
+                    this.machine.sync.rwlocks[id].stats.unblock(thread, now);
                     this.rwlock_reader_lock(id);
                     this.write_scalar(retval, &dest)?;
                     Ok(())
@@ -529,11 +815,15 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     #[inline]
     fn rwlock_writer_lock(&mut self, id: RwLockId) {
         let this = self.eval_context_mut();
+        this.machine.threads.record_visible_op(VisibleOp::Sync(SyncObject::RwLock(id)));
         let thread = this.active_thread();
         assert!(!this.rwlock_is_locked(id), "the rwlock is already locked");
         trace!("rwlock_writer_lock: {:?} now held by {:?}", id, thread);
         let rwlock = &mut this.machine.sync.rwlocks[id];
         rwlock.writer = Some(thread);
+        rwlock.stats.acquisitions += 1;
+        this.lock_order_acquire(LockId::RwLock(id));
+        let rwlock = &this.machine.sync.rwlocks[id];
         if let Some(data_race) = &this.machine.data_race {
             data_race.acquire_clock(&rwlock.clock_unlocked, &this.machine.threads);
         }
@@ -544,6 +834,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     #[inline]
     fn rwlock_writer_unlock(&mut self, id: RwLockId) -> InterpResult<'tcx, bool> {
         let this = self.eval_context_mut();
+        this.machine.threads.record_visible_op(VisibleOp::Sync(SyncObject::RwLock(id)));
         let thread = this.active_thread();
         let rwlock = &mut this.machine.sync.rwlocks[id];
         Ok(if let Some(current_writer) = rwlock.writer {
@@ -553,6 +844,8 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             }
             rwlock.writer = None;
             trace!("rwlock_writer_unlock: {:?} unlocked by {:?}", id, thread);
+            this.lock_order_release(LockId::RwLock(id));
+            let rwlock = &mut this.machine.sync.rwlocks[id];
             // Record release clock for next lock holder.
             if let Some(data_race) = &this.machine.data_race {
                 rwlock.clock_unlocked.clone_from(&*data_race.release_clock(&this.machine.threads));
@@ -587,9 +880,13 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         dest: MPlaceTy<'tcx>,
     ) {
         let this = self.eval_context_mut();
+        this.machine.threads.record_visible_op(VisibleOp::Sync(SyncObject::RwLock(id)));
         assert!(this.rwlock_is_locked(id), "write-queueing on unlocked rwlock");
         let thread = this.active_thread();
-        this.machine.sync.rwlocks[id].writer_queue.push_back(thread);
+        let now = this.machine.clock.now();
+        let rwlock = &mut this.machine.sync.rwlocks[id];
+        rwlock.writer_queue.push_back(thread);
+        rwlock.stats.block(thread, now);
         this.block_thread(
             BlockReason::RwLock(id),
             None,
@@ -600,6 +897,8 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     dest: MPlaceTy<'tcx>,
                 }
                 @unblock = |this| {
+                    let (thread, now) = (this.active_thread(), this.machine.clock.now());
+                    this.machine.sync.rwlocks[id].stats.unblock(thread, now);
                     this.rwlock_writer_lock(id);
                     this.write_scalar(retval, &dest)?;
                     Ok(())
@@ -627,7 +926,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         retval_timeout: Scalar,
         dest: MPlaceTy<'tcx>,
     ) -> InterpResult<'tcx> {
+        use rand::Rng as _;
+
         let this = self.eval_context_mut();
+        this.machine.threads.record_visible_op(VisibleOp::Sync(SyncObject::Condvar(condvar)));
         if let Some(old_locked_count) = this.mutex_unlock(mutex)? {
             if old_locked_count != 1 {
                 throw_unsup_format!(
@@ -639,6 +941,17 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 "awaiting a condvar on a mutex that is unlocked or owned by a different thread"
             );
         }
+        if this.machine.spurious_wakeup_rate > 0.0
+            && this.machine.rng.get_mut().gen_bool(this.machine.spurious_wakeup_rate)
+        {
+            // Wake up spuriously, i.e., return success without having been signaled and without
+            // ever waiting for the timeout. We just released the mutex, so this takes it back right
+            // away. The yield only happens after the current step, so other threads get to run
+            // before this one continues, but they cannot acquire the mutex in the meantime.
+            this.machine.log_nondeterminism("sync", || "spurious condvar wakeup".to_owned());
+            this.yield_active_thread();
+            return this.condvar_reacquire_mutex(mutex, retval_succ, dest);
+        }
         let thread = this.active_thread();
         let waiters = &mut this.machine.sync.condvars[condvar].waiters;
         waiters.push_back(thread);
@@ -681,17 +994,33 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     /// Wake up some thread (if there is any) sleeping on the conditional
     /// variable. Returns `true` iff any thread was woken up.
     fn condvar_signal(&mut self, id: CondvarId) -> InterpResult<'tcx, bool> {
+        use rand::Rng as _;
+
         let this = self.eval_context_mut();
+        this.machine.threads.record_visible_op(VisibleOp::Sync(SyncObject::Condvar(id)));
         let condvar = &mut this.machine.sync.condvars[id];
         let data_race = &this.machine.data_race;
+        condvar.last_signaled_by = Some(this.machine.threads.active_thread());
 
         // Each condvar signal happens-before the end of the condvar wake
         if let Some(data_race) = data_race {
             condvar.clock.clone_from(&*data_race.release_clock(&this.machine.threads));
         }
-        let Some(waiter) = condvar.waiters.pop_front() else {
+        // Wake up the thread that waited longest, or a random one.
+        let idx = if this.machine.random_wakeup_order && !condvar.waiters.is_empty() {
+            this.machine.rng.get_mut().gen_range(0..condvar.waiters.len())
+        } else {
+            0
+        };
+        let Some(waiter) = condvar.waiters.remove(idx) else {
             return Ok(false);
         };
+        if this.machine.random_wakeup_order {
+            this.machine.log_nondeterminism("sync", || {
+                let name = this.machine.threads.get_thread_display_name(waiter);
+                format!("woke up thread `{name}` waiting on a condvar")
+            });
+        }
         this.unblock_thread(waiter, BlockReason::Condvar(id))?;
         Ok(true)
     }
@@ -703,18 +1032,46 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         &mut self,
         addr: u64,
         bitset: u32,
-        timeout: Option<(TimeoutClock, TimeoutAnchor, Duration)>,
+        mut timeout: Option<(TimeoutClock, TimeoutAnchor, Duration)>,
         retval_succ: Scalar,
         retval_timeout: Scalar,
         dest: MPlaceTy<'tcx>,
         errno_timeout: Scalar,
-    ) {
+    ) -> InterpResult<'tcx> {
+        use rand::Rng as _;
+
         let this = self.eval_context_mut();
+        this.machine.threads.record_visible_op(VisibleOp::Sync(SyncObject::Futex(addr)));
+        let rate = this.machine.futex_spurious_wakeup_rate;
+        if rate > 0.0 && this.machine.rng.get_mut().gen_bool(rate) {
+            // Report a successful wakeup without ever queueing this thread as a waiter, so no
+            // `futex_wake` is involved and any timeout is moot. The caller (e.g. `thread::park`
+            // or a futex-based lock) has to re-check the futex word. Since it will usually just
+            // wait again, yield so that this does not turn into a busy loop that starves the
+            // thread that would actually change the futex word.
+            this.machine.log_nondeterminism("sync", || "spurious futex wakeup".to_owned());
+            this.yield_active_thread();
+            return this.write_scalar(retval_succ, &dest);
+        }
+        if let Some((_, TimeoutAnchor::Relative, duration)) = &mut timeout
+            && this.tcx.sess.target.os == "windows"
+            && rate > 0.0
+            && this.machine.rng.get_mut().gen_bool(rate)
+        {
+            // Windows timeouts are only as precise as the system timer, so they may expire up to
+            // one timer tick (15.625ms) early.
+            let early_us = this.machine.rng.get_mut().gen_range(0..=15_625);
+            this.machine
+                .log_nondeterminism("sync", || format!("futex timeout expires {early_us}us early"));
+            *duration = duration.saturating_sub(Duration::from_micros(early_us));
+        }
         let thread = this.active_thread();
+        let now = this.machine.clock.now();
         let futex = &mut this.machine.sync.futexes.entry(addr).or_default();
         let waiters = &mut futex.waiters;
         assert!(waiters.iter().all(|waiter| waiter.thread != thread), "thread is already waiting");
         waiters.push_back(FutexWaiter { thread, bitset });
+        futex.stats.block(thread, now);
         this.block_thread(
             BlockReason::Futex { addr },
             timeout,
@@ -727,7 +1084,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     errno_timeout: Scalar,
                 }
                 @unblock = |this| {
-                    let futex = this.machine.sync.futexes.get(&addr).unwrap();
+                    let (thread, now) = (this.active_thread(), this.machine.clock.now());
+                    let futex = this.machine.sync.futexes.get_mut(&addr).unwrap();
+                    futex.stats.unblock(thread, now);
+                    futex.stats.acquisitions += 1;
                     // Acquire the clock of the futex.
                     if let Some(data_race) = &this.machine.data_race {
                         data_race.acquire_clock(&futex.clock, &this.machine.threads);
@@ -739,8 +1099,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 @timeout = |this| {
                     // Remove the waiter from the futex.
                     let thread = this.active_thread();
+                    let now = this.machine.clock.now();
                     let futex = this.machine.sync.futexes.get_mut(&addr).unwrap();
                     futex.waiters.retain(|waiter| waiter.thread != thread);
+                    futex.stats.unblock(thread, now);
                     // Set errno and write return value.
                     this.set_last_error(errno_timeout)?;
                     this.write_scalar(retval_timeout, &dest)?;
@@ -748,26 +1110,56 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 }
             ),
         );
+        Ok(())
+    }
+
+    /// Record that a thread wanted to wait on the futex, but did not have to block since the
+    /// futex value had already changed.
+    fn futex_acquired_without_waiting(&mut self, addr: u64) {
+        let this = self.eval_context_mut();
+        this.machine.sync.futexes.entry(addr).or_default().stats.acquisitions += 1;
     }
 
     /// Returns whether anything was woken.
     fn futex_wake(&mut self, addr: u64, bitset: u32) -> InterpResult<'tcx, bool> {
+        use rand::seq::IteratorRandom;
+
         let this = self.eval_context_mut();
+        this.machine.threads.record_visible_op(VisibleOp::Sync(SyncObject::Futex(addr)));
         let Some(futex) = this.machine.sync.futexes.get_mut(&addr) else {
             return Ok(false);
         };
         let data_race = &this.machine.data_race;
+        futex.last_woken_by = Some(this.machine.threads.active_thread());
 
         // Each futex-wake happens-before the end of the futex wait
         if let Some(data_race) = data_race {
             futex.clock.clone_from(&*data_race.release_clock(&this.machine.threads));
         }
 
-        // Wake up the first thread in the queue that matches any of the bits in the bitset.
-        let Some(i) = futex.waiters.iter().position(|w| w.bitset & bitset != 0) else {
+        // Wake up the first thread in the queue that matches any of the bits in the bitset, or a
+        // random one among those.
+        let mut matching = futex
+            .waiters
+            .iter()
+            .enumerate()
+            .filter(|(_, w)| w.bitset & bitset != 0)
+            .map(|(i, _)| i);
+        let i = if this.machine.random_wakeup_order {
+            matching.choose(this.machine.rng.get_mut())
+        } else {
+            matching.next()
+        };
+        let Some(i) = i else {
             return Ok(false);
         };
         let waiter = futex.waiters.remove(i).unwrap();
+        if this.machine.random_wakeup_order {
+            this.machine.log_nondeterminism("sync", || {
+                let name = this.machine.threads.get_thread_display_name(waiter.thread);
+                format!("woke up thread `{name}` waiting on a futex")
+            });
+        }
         this.unblock_thread(waiter.thread, BlockReason::Futex { addr })?;
         Ok(true)
     }
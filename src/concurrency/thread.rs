@@ -1,5 +1,6 @@
 //! Implements threads.
 
+use std::cell::{Cell, RefCell};
 use std::mem;
 use std::num::TryFromIntError;
 use std::sync::atomic::Ordering::Relaxed;
@@ -14,10 +15,12 @@ use rustc_hir::def_id::DefId;
 use rustc_index::{Idx, IndexVec};
 use rustc_middle::mir::Mutability;
 use rustc_middle::ty::layout::TyAndLayout;
-use rustc_span::Span;
+use rustc_span::{Span, SpanData};
+use rustc_target::abi::Size;
 use rustc_target::spec::abi::Abi;
 
 use crate::concurrency::data_race;
+use crate::concurrency::dpor::{Dpor, SyncObject, VisibleOp};
 use crate::shims::tls;
 use crate::*;
 
@@ -256,10 +259,27 @@ pub struct Thread<'tcx> {
     /// which then forwards it to 'Resume'. However this argument is implicit in MIR,
     /// so we have to store it out-of-band. When there are multiple active unwinds,
     /// the innermost one is always caught first, so we can store them as a stack.
-    pub(crate) panic_payloads: Vec<Scalar>,
+    /// Foreign exceptions have no payload, but are recorded here as well.
+    pub(crate) panic_payloads: Vec<UnwindPayload>,
 
     /// Last OS error location in memory. It is a 32-bit integer.
     pub(crate) last_error: Option<MPlaceTy<'tcx>>,
+
+    /// When this thread last became ready to run, used by the FIFO scheduler.
+    ready_since: u64,
+
+    /// The scheduling priority set by the program. Higher values mean higher priority.
+    priority: i32,
+
+    /// Which thread spawned this one, and where. `None` for the main thread.
+    spawned_by: Option<(ThreadId, Span)>,
+
+    /// The preemption rate for this thread, if it differs from `-Zmiri-preemption-rate`.
+    preemption_rate: Option<f64>,
+
+    /// A backtrace to where this thread was spawned, in case it never gets joined.
+    /// Only collected with `-Zmiri-check-unjoined-threads`.
+    spawn_backtrace: Option<Vec<FrameInfo<'tcx>>>,
 }
 
 pub type StackEmptyCallback<'tcx> =
@@ -344,6 +364,11 @@ impl<'tcx> Thread<'tcx> {
             panic_payloads: Vec::new(),
             last_error: None,
             on_stack_empty,
+            ready_since: 0,
+            priority: 0,
+            spawned_by: None,
+            preemption_rate: None,
+            spawn_backtrace: None,
         }
     }
 }
@@ -359,6 +384,11 @@ impl VisitProvenance for Thread<'_> {
             thread_name: _,
             join_status: _,
             on_stack_empty: _, // we assume the closure captures no GC-relevant state
+            ready_since: _,
+            priority: _,
+            spawned_by: _,
+            preemption_rate: _,
+            spawn_backtrace: _,
         } = self;
 
         for payload in panic_payload {
@@ -451,6 +481,15 @@ pub enum TimeoutAnchor {
     Absolute,
 }
 
+/// A run of atomic loads by the same thread that all observed the same value at the same location.
+#[derive(Clone, Copy, Debug)]
+struct SpinState {
+    thread: ThreadId,
+    addr: Size,
+    value: Scalar,
+    loads: u64,
+}
+
 /// A set of threads.
 #[derive(Debug)]
 pub struct ThreadManager<'tcx> {
@@ -464,6 +503,25 @@ pub struct ThreadManager<'tcx> {
     thread_local_allocs: FxHashMap<(DefId, ThreadId), StrictPointer>,
     /// A flag that indicates that we should change the active thread.
     yield_active_thread: bool,
+    /// The systematic exploration of interleavings, if `-Zmiri-dpor` is set.
+    dpor: Option<RefCell<Dpor>>,
+    /// How to pick the threads to preempt and to switch to.
+    scheduler: SchedulingPolicy,
+    /// Incremented whenever a thread becomes ready to run, to order the threads for the FIFO
+    /// scheduler.
+    ready_counter: u64,
+    /// The probability of switching to a higher-priority thread instead of following the
+    /// scheduling policy.
+    priority_bias: f64,
+    /// Whether the program ever set a thread priority. If not, we can skip all priority logic.
+    priorities_used: bool,
+    /// The atomic loads the active thread performed since it last made progress, used to detect
+    /// livelocks.
+    spin: Cell<Option<SpinState>>,
+    /// After how many unchanged atomic loads we check for a livelock. 0 disables the check.
+    livelock_threshold: u64,
+    /// The preemption rates to use for threads with the given names.
+    named_preemption_rates: FxHashMap<Vec<u8>, f64>,
 }
 
 impl VisitProvenance for ThreadManager<'_> {
@@ -473,6 +531,14 @@ impl VisitProvenance for ThreadManager<'_> {
             thread_local_allocs,
             active_thread: _,
             yield_active_thread: _,
+            dpor: _,
+            scheduler: _,
+            ready_counter: _,
+            priority_bias: _,
+            priorities_used: _,
+            spin,
+            livelock_threshold: _,
+            named_preemption_rates: _,
         } = self;
 
         for thread in threads {
@@ -481,11 +547,14 @@ impl VisitProvenance for ThreadManager<'_> {
         for ptr in thread_local_allocs.values() {
             ptr.visit_provenance(visit);
         }
+        if let Some(spin) = spin.get() {
+            spin.value.visit_provenance(visit);
+        }
     }
 }
 
-impl<'tcx> Default for ThreadManager<'tcx> {
-    fn default() -> Self {
+impl<'tcx> ThreadManager<'tcx> {
+    pub(crate) fn new(config: &MiriConfig) -> Self {
         let mut threads = IndexVec::new();
         // Create the main thread and add it to the list of threads.
         threads.push(Thread::new(Some("main"), None));
@@ -494,11 +563,21 @@ impl<'tcx> Default for ThreadManager<'tcx> {
             threads,
             thread_local_allocs: Default::default(),
             yield_active_thread: false,
+            dpor: None,
+            scheduler: config.scheduler,
+            ready_counter: 0,
+            priority_bias: config.thread_priority_bias,
+            priorities_used: false,
+            spin: Cell::new(None),
+            livelock_threshold: config.livelock_threshold,
+            named_preemption_rates: config
+                .thread_preemption_rates
+                .iter()
+                .map(|(name, rate)| (name.as_bytes().to_vec(), *rate))
+                .collect(),
         }
     }
-}
 
-impl<'tcx> ThreadManager<'tcx> {
     pub(crate) fn init(
         ecx: &mut MiriInterpCx<'tcx>,
         on_main_stack_empty: StackEmptyCallback<'tcx>,
@@ -512,6 +591,54 @@ impl<'tcx> ThreadManager<'tcx> {
         }
     }
 
+    /// Explore interleavings systematically using the given DPOR state.
+    pub(crate) fn set_dpor(&mut self, dpor: Dpor) {
+        self.dpor = Some(RefCell::new(dpor));
+    }
+
+    /// Take back the DPOR state at the end of an execution.
+    pub(crate) fn take_dpor(&mut self) -> Option<Dpor> {
+        self.dpor.take().map(RefCell::into_inner)
+    }
+
+    /// Tell the DPOR scheduler (if any) that the active thread performed an operation that other
+    /// threads can observe.
+    pub(crate) fn record_visible_op(&self, op: VisibleOp) {
+        if let Some(dpor) = &self.dpor {
+            dpor.borrow_mut().record(self.active_thread, op);
+        }
+    }
+
+    /// Whether the model checker explores which store each atomic load reads from.
+    pub(crate) fn explores_loads(&self) -> bool {
+        self.dpor.as_ref().is_some_and(|dpor| dpor.borrow().explores_loads())
+    }
+
+    /// Pick which of the `options` stores the current load reads from. Must only be called if
+    /// [`ThreadManager::explores_loads`] is true.
+    pub(crate) fn choose_load(&self, options: usize) -> usize {
+        self.dpor.as_ref().unwrap().borrow_mut().choose_load(options)
+    }
+
+    /// Record that the active thread did something other than spinning on an atomic load, such as
+    /// writing to memory or calling a foreign function.
+    pub(crate) fn note_progress(&self) {
+        self.spin.set(None);
+    }
+
+    /// Record that the active thread atomically loaded `value` from `addr`. Returns how many loads
+    /// in a row observed that same value without the thread making progress in between.
+    fn note_atomic_load(&self, addr: Size, value: Scalar) -> u64 {
+        let loads = match self.spin.get() {
+            Some(spin)
+                if spin.thread == self.active_thread && spin.addr == addr && spin.value == value =>
+                spin.loads + 1,
+            _ => 1,
+        };
+        self.spin.set(Some(SpinState { thread: self.active_thread, addr, value, loads }));
+        loads
+    }
+
     /// Check if we have an allocation for the given thread local static for the
     /// active thread.
     fn get_thread_local_alloc_id(&self, def_id: DefId) -> Option<StrictPointer> {
@@ -545,6 +672,8 @@ impl<'tcx> ThreadManager<'tcx> {
     fn create_thread(&mut self, on_stack_empty: StackEmptyCallback<'tcx>) -> ThreadId {
         let new_thread_id = ThreadId::new(self.threads.len());
         self.threads.push(Thread::new(None, Some(on_stack_empty)));
+        self.mark_ready(new_thread_id);
+        self.record_visible_op(VisibleOp::Spawn(new_thread_id));
         new_thread_id
     }
 
@@ -559,6 +688,17 @@ impl<'tcx> ThreadManager<'tcx> {
         std::mem::replace(&mut self.active_thread, id)
     }
 
+    /// Remember that `id` became ready to run just now.
+    fn mark_ready(&mut self, id: ThreadId) {
+        self.ready_counter += 1;
+        self.threads[id].ready_since = self.ready_counter;
+    }
+
+    /// The scheduling policy in use.
+    pub fn scheduler(&self) -> SchedulingPolicy {
+        self.scheduler
+    }
+
     /// Get the id of the currently active thread.
     pub fn active_thread(&self) -> ThreadId {
         self.active_thread
@@ -569,6 +709,12 @@ impl<'tcx> ThreadManager<'tcx> {
         self.threads.len()
     }
 
+    /// The thread with the given ID, if the program ever spawned one with that ID.
+    pub fn thread_id_try_from(&self, id: impl TryInto<u32>) -> Option<ThreadId> {
+        let id = ThreadId(id.try_into().ok()?);
+        (id.index() < self.threads.len()).then_some(id)
+    }
+
     /// Get the total of threads that are currently live, i.e., not yet terminated.
     /// (They might be blocked.)
     pub fn get_live_thread_count(&self) -> usize {
@@ -576,7 +722,7 @@ impl<'tcx> ThreadManager<'tcx> {
     }
 
     /// Has the given thread terminated?
-    fn has_terminated(&self, thread_id: ThreadId) -> bool {
+    pub fn has_terminated(&self, thread_id: ThreadId) -> bool {
         self.threads[thread_id].state.is_terminated()
     }
 
@@ -640,6 +786,7 @@ impl<'tcx> ThreadManager<'tcx> {
         // Mark the joined thread as being joined so that we detect if other
         // threads try to join it.
         self.threads[joined_thread_id].join_status = ThreadJoinStatus::Joined;
+        self.record_visible_op(VisibleOp::Sync(SyncObject::Thread(joined_thread_id)));
         if !self.threads[joined_thread_id].state.is_terminated() {
             trace!(
                 "{:?} blocked on {:?} when trying to join",
@@ -699,18 +846,105 @@ impl<'tcx> ThreadManager<'tcx> {
 
     /// Set the name of the given thread.
     pub fn set_thread_name(&mut self, thread: ThreadId, new_thread_name: Vec<u8>) {
+        if let Some(&rate) = self.named_preemption_rates.get(&new_thread_name) {
+            self.threads[thread].preemption_rate = Some(rate);
+        }
         self.threads[thread].thread_name = Some(new_thread_name);
     }
 
+    /// Set the preemption rate of the given thread, overriding `-Zmiri-preemption-rate`.
+    pub fn set_thread_preemption_rate(&mut self, thread: ThreadId, rate: f64) {
+        self.threads[thread].preemption_rate = Some(rate);
+    }
+
+    /// The preemption rate of the given thread, where `default` is the global preemption rate.
+    pub fn thread_preemption_rate(&self, thread: ThreadId, default: f64) -> f64 {
+        self.threads[thread].preemption_rate.unwrap_or(default)
+    }
+
+    /// Whether any thread may get preempted at random, given the global preemption rate.
+    pub fn any_thread_preemptible(&self, default: f64) -> bool {
+        self.threads.iter().any(|t| t.preemption_rate.unwrap_or(default) > 0.0)
+    }
+
     /// Get the name of the given thread.
     pub fn get_thread_name(&self, thread: ThreadId) -> Option<&[u8]> {
         self.threads[thread].thread_name()
     }
 
+    /// Describe the given thread for diagnostics: its name in backticks, followed by its ID if the
+    /// program picked the name (otherwise, the name already identifies the thread).
+    pub fn get_thread_diagnostic_name(&self, thread: ThreadId) -> String {
+        let name = self.get_thread_display_name(thread);
+        if self.threads[thread].thread_name.is_some() && thread != ThreadId::MAIN_THREAD {
+            format!("`{name}` (id {})", thread.to_u32())
+        } else {
+            format!("`{name}`")
+        }
+    }
+
+    /// The threads that terminated without ever being joined or detached, together with the
+    /// backtrace of where they were spawned (if it was collected).
+    pub fn take_unjoined_threads(&mut self) -> Vec<(ThreadId, Option<Vec<FrameInfo<'tcx>>>)> {
+        self.threads
+            .iter_enumerated_mut()
+            .filter(|(id, t)| {
+                *id != ThreadId::MAIN_THREAD
+                    && t.state.is_terminated()
+                    && t.join_status == ThreadJoinStatus::Joinable
+            })
+            .map(|(id, t)| (id, t.spawn_backtrace.take()))
+            .collect()
+    }
+
+    /// Which thread spawned the given thread, and where. `None` for the main thread.
+    pub fn get_thread_spawn_site(&self, thread: ThreadId) -> Option<(ThreadId, Span)> {
+        self.threads[thread].spawned_by
+    }
+
+    /// A note pointing to where the given thread was spawned, if known.
+    pub fn spawn_site_note(&self, thread: ThreadId) -> Option<(Option<SpanData>, String)> {
+        let (_, span) = self.threads[thread].spawned_by?;
+        Some((
+            Some(span.data()),
+            format!("thread {} was spawned here", self.get_thread_diagnostic_name(thread)),
+        ))
+    }
+
+    /// Set the scheduling priority of the given thread.
+    pub fn set_thread_priority(&mut self, thread: ThreadId, priority: i32) {
+        self.threads[thread].priority = priority;
+        self.priorities_used |= priority != 0;
+    }
+
+    /// Get the scheduling priority of the given thread.
+    pub fn get_thread_priority(&self, thread: ThreadId) -> i32 {
+        self.threads[thread].priority
+    }
+
+    /// The highest priority among the enabled threads other than the active one.
+    fn highest_waiting_priority(&self) -> Option<i32> {
+        self.threads
+            .iter_enumerated()
+            .filter(|&(id, thread)| id != self.active_thread && thread.state.is_enabled())
+            .map(|(_, thread)| thread.priority)
+            .max()
+    }
+
     pub fn get_thread_display_name(&self, thread: ThreadId) -> String {
         self.threads[thread].thread_display_name(thread)
     }
 
+    /// Iterate over all blocked threads, together with what they are blocked on.
+    pub fn blocked_threads(&self) -> impl Iterator<Item = (ThreadId, BlockReason)> + '_ {
+        self.threads.iter_enumerated().filter_map(|(id, thread)| {
+            match thread.state {
+                ThreadState::Blocked { reason, .. } => Some((id, reason)),
+                _ => None,
+            }
+        })
+    }
+
     /// Put the thread into the blocked state.
     fn block_thread(
         &mut self,
@@ -720,7 +954,8 @@ impl<'tcx> ThreadManager<'tcx> {
     ) {
         let state = &mut self.threads[self.active_thread].state;
         assert!(state.is_enabled());
-        *state = ThreadState::Blocked { reason, timeout, callback: Box::new(callback) }
+        *state = ThreadState::Blocked { reason, timeout, callback: Box::new(callback) };
+        self.note_progress();
     }
 
     /// Change the active thread to some enabled thread.
@@ -729,6 +964,7 @@ impl<'tcx> ThreadManager<'tcx> {
         // could lead to all sorts of confusion.
         // We should only switch stacks between steps.
         self.yield_active_thread = true;
+        self.note_progress();
     }
 
     /// Get the wait time for the next timeout, or `None` if no timeout is pending.
@@ -751,9 +987,20 @@ impl<'tcx> ThreadManager<'tcx> {
     /// used in stateless model checkers such as Loom: run the active thread as
     /// long as we can and switch only when we have to (the active thread was
     /// blocked, terminated, or has explicitly asked to be preempted).
-    fn schedule(&mut self, clock: &Clock) -> InterpResult<'tcx, SchedulingAction> {
+    /// With `-Zmiri-dpor`, we additionally let the DPOR scheduler decide after every
+    /// visible operation. Which thread runs next after a switch is determined by the
+    /// `SchedulingPolicy`.
+    fn schedule(
+        &mut self,
+        clock: &Clock,
+        rng: &mut impl rand::Rng,
+    ) -> InterpResult<'tcx, SchedulingAction> {
+        let dpor_point = self.dpor.as_ref().is_some_and(|dpor| dpor.borrow().at_scheduling_point());
         // This thread and the program can keep going.
-        if self.threads[self.active_thread].state.is_enabled() && !self.yield_active_thread {
+        if self.threads[self.active_thread].state.is_enabled()
+            && !self.yield_active_thread
+            && !dpor_point
+        {
             // The currently active thread is still enabled, just continue with it.
             return Ok(SchedulingAction::ExecuteStep);
         }
@@ -768,29 +1015,74 @@ impl<'tcx> ThreadManager<'tcx> {
             return Ok(SchedulingAction::ExecuteTimeoutCallback);
         }
         // No callbacks immediately scheduled, pick a regular thread to execute.
-        // The active thread blocked or yielded. So we go search for another enabled thread.
-        // Crucially, we start searching at the current active thread ID, rather than at 0, since we
-        // want to avoid always scheduling threads 0 and 1 without ever making progress in thread 2.
-        //
-        // `skip(N)` means we start iterating at thread N, so we skip 1 more to start just *after*
-        // the active thread. Then after that we look at `take(N)`, i.e., the threads *before* the
-        // active thread.
-        let threads = self
-            .threads
-            .iter_enumerated()
-            .skip(self.active_thread.index() + 1)
-            .chain(self.threads.iter_enumerated().take(self.active_thread.index()));
-        for (id, thread) in threads {
-            debug_assert_ne!(self.active_thread, id);
-            if thread.state.is_enabled() {
-                info!(
-                    "---------- Now executing on thread `{}` (previous: `{}`) ----------------------------------------",
-                    self.get_thread_display_name(id),
-                    self.get_thread_display_name(self.active_thread)
+        if let Some(dpor) = &self.dpor {
+            let enabled: Vec<ThreadId> = self
+                .threads
+                .iter_enumerated()
+                .filter(|(_, thread)| thread.state.is_enabled())
+                .map(|(id, _)| id)
+                .collect();
+            if !enabled.is_empty() {
+                let next = dpor.borrow_mut().schedule(
+                    self.active_thread,
+                    self.yield_active_thread,
+                    enabled,
                 );
-                self.active_thread = id;
-                break;
+                if next != self.active_thread {
+                    self.set_active_thread_id(next);
+                }
+                self.yield_active_thread = false;
+                return Ok(SchedulingAction::ExecuteStep);
+            }
+        }
+        // The active thread blocked or yielded. So we go search for another enabled thread.
+        let mut next = match self.scheduler {
+            SchedulingPolicy::Random | SchedulingPolicy::RoundRobin => {
+                // Crucially, we start searching at the current active thread ID, rather than at 0,
+                // since we want to avoid always scheduling threads 0 and 1 without ever making
+                // progress in thread 2.
+                //
+                // `skip(N)` means we start iterating at thread N, so we skip 1 more to start just
+                // *after* the active thread. Then after that we look at `take(N)`, i.e., the
+                // threads *before* the active thread.
+                self.threads
+                    .iter_enumerated()
+                    .skip(self.active_thread.index() + 1)
+                    .chain(self.threads.iter_enumerated().take(self.active_thread.index()))
+                    .find(|(_, thread)| thread.state.is_enabled())
+                    .map(|(id, _)| id)
+            }
+            SchedulingPolicy::Fifo =>
+                self.threads
+                    .iter_enumerated()
+                    .filter(|&(id, thread)| id != self.active_thread && thread.state.is_enabled())
+                    .min_by_key(|(_, thread)| thread.ready_since)
+                    .map(|(id, _)| id),
+        };
+        if let Some(policy_next) = next
+            && self.priorities_used
+            && self.priority_bias > 0.0
+            && rng.gen_bool(self.priority_bias)
+        {
+            // Prefer a thread with the highest priority, and among those, the one the policy
+            // would have picked (or else the next one by thread ID).
+            let highest = self.highest_waiting_priority().unwrap();
+            if self.threads[policy_next].priority < highest {
+                next = self
+                    .threads
+                    .iter_enumerated()
+                    .skip(self.active_thread.index() + 1)
+                    .chain(self.threads.iter_enumerated().take(self.active_thread.index()))
+                    .find(|(_, thread)| thread.state.is_enabled() && thread.priority == highest)
+                    .map(|(id, _)| id);
+            }
+        }
+        if let Some(next) = next {
+            if self.threads[self.active_thread].state.is_enabled() {
+                // The active thread goes to the back of the queue.
+                self.mark_ready(self.active_thread);
             }
+            self.set_active_thread_id(next);
         }
         self.yield_active_thread = false;
         if self.threads[self.active_thread].state.is_enabled() {
@@ -833,6 +1125,7 @@ trait EvalContextPrivExt<'tcx>: MiriInterpCxExt<'tcx> {
             }
         }
         if let Some((thread, callback)) = found_callback {
+            this.machine.threads.mark_ready(thread);
             // This back-and-forth with `set_active_thread` is here because of two
             // design decisions:
             // 1. Make the caller and not the callback responsible for changing
@@ -919,6 +1212,12 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             Box::new(move |m| state.on_stack_empty(m))
         });
         let current_span = this.machine.current_span();
+        let parent = this.machine.threads.active_thread();
+        this.machine.threads.threads[new_thread_id].spawned_by = Some((parent, current_span));
+        if this.machine.check_unjoined_threads {
+            this.machine.threads.threads[new_thread_id].spawn_backtrace =
+                Some(this.generate_stacktrace());
+        }
         if let Some(data_race) = &mut this.machine.data_race {
             data_race.thread_created(&this.machine.threads, new_thread_id, current_span);
         }
@@ -973,6 +1272,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         }
         // Deallocate TLS.
         let gone_thread = this.active_thread();
+        this.machine.threads.record_visible_op(VisibleOp::Sync(SyncObject::Thread(gone_thread)));
         {
             let mut free_tls_statics = Vec::new();
             this.machine.threads.thread_local_allocs.retain(|&(_def_id, thread), &mut alloc_id| {
@@ -1055,6 +1355,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let this = self.eval_context_mut();
         let old_state =
             mem::replace(&mut this.machine.threads.threads[thread].state, ThreadState::Enabled);
+        this.machine.threads.mark_ready(thread);
         let callback = match old_state {
             ThreadState::Blocked { reason: actual_reason, callback, .. } => {
                 assert_eq!(
@@ -1163,17 +1464,88 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         self.eval_context_ref().machine.threads.get_thread_name(thread)
     }
 
+    #[inline]
+    fn set_thread_priority(&mut self, thread: ThreadId, priority: i32) {
+        self.eval_context_mut().machine.threads.set_thread_priority(thread, priority);
+    }
+
+    #[inline]
+    fn get_thread_priority(&self, thread: ThreadId) -> i32 {
+        self.eval_context_ref().machine.threads.get_thread_priority(thread)
+    }
+
     #[inline]
     fn yield_active_thread(&mut self) {
         self.eval_context_mut().machine.threads.yield_active_thread();
     }
 
+    /// Record an atomic load of `value` from `place` by the active thread, and report a livelock if
+    /// the thread keeps loading the same value without making any progress while no other thread
+    /// gets to run and change it.
+    fn check_spin_loop(&self, place: &MPlaceTy<'tcx>, value: Scalar) -> InterpResult<'tcx> {
+        let this = self.eval_context_ref();
+        let threads = &this.machine.threads;
+        if threads.livelock_threshold == 0 {
+            return Ok(());
+        }
+        let loads = threads.note_atomic_load(place.ptr().addr(), value);
+        if loads % threads.livelock_threshold != 0 {
+            return Ok(());
+        }
+        // The loop can only end if some other thread changes the value, which requires that
+        // another thread is (or will become) enabled, and that the spinning thread gets preempted.
+        let others_can_run = threads.threads.iter_enumerated().any(|(id, thread)| {
+            id != threads.active_thread
+                && matches!(
+                    thread.state,
+                    ThreadState::Enabled | ThreadState::Blocked { timeout: Some(_), .. }
+                )
+        });
+        let can_preempt = threads.dpor.is_some()
+            || threads.scheduler == SchedulingPolicy::RoundRobin
+            || threads.thread_preemption_rate(threads.active_thread, this.machine.preemption_rate)
+                > 0.0
+            || (threads.priorities_used && threads.priority_bias > 0.0);
+        if others_can_run && can_preempt {
+            return Ok(());
+        }
+        throw_machine_stop!(TerminationInfo::Livelock {
+            thread: threads.get_thread_diagnostic_name(threads.active_thread),
+            loads,
+        });
+    }
+
     #[inline]
     fn maybe_preempt_active_thread(&mut self) {
         use rand::Rng as _;
 
         let this = self.eval_context_mut();
-        if this.machine.rng.get_mut().gen_bool(this.machine.preemption_rate) {
+        if this.machine.threads.dpor.is_some() {
+            // The DPOR scheduler decides on its own when to switch threads.
+            return;
+        }
+        let threads = &this.machine.threads;
+        let rng = this.machine.rng.get_mut();
+        let mut preempt = match threads.scheduler {
+            SchedulingPolicy::RoundRobin => true,
+            SchedulingPolicy::Random | SchedulingPolicy::Fifo => {
+                let rate = threads
+                    .thread_preemption_rate(threads.active_thread, this.machine.preemption_rate);
+                rng.gen_bool(rate)
+            }
+        };
+        if !preempt && threads.priorities_used && threads.priority_bias > 0.0 {
+            // If a higher-priority thread is ready to run, give it a chance to take over.
+            let active_priority = threads.get_thread_priority(threads.active_thread);
+            if threads.highest_waiting_priority().is_some_and(|prio| prio > active_priority) {
+                preempt = rng.gen_bool(threads.priority_bias);
+            }
+        }
+        if preempt {
+            this.machine.log_nondeterminism("scheduler", || {
+                let name = threads.get_thread_display_name(threads.active_thread);
+                format!("preempted thread `{name}`")
+            });
             this.yield_active_thread();
         }
     }
@@ -1187,8 +1559,26 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 this.machine.handle_abnormal_termination();
                 std::process::exit(1);
             }
-            match this.machine.threads.schedule(&this.machine.clock)? {
+            let prev = this.machine.threads.active_thread();
+            let action =
+                this.machine.threads.schedule(&this.machine.clock, this.machine.rng.get_mut())?;
+            let next = this.machine.threads.active_thread();
+            if next != prev {
+                this.machine.log_nondeterminism("scheduler", || {
+                    let threads = &this.machine.threads;
+                    format!(
+                        "switched from thread `{}` to thread `{}`",
+                        threads.get_thread_display_name(prev),
+                        threads.get_thread_display_name(next)
+                    )
+                });
+                this.trace_thread_switch(next);
+            }
+            match action {
                 SchedulingAction::ExecuteStep => {
+                    if !this.machine.running_tests.is_empty() {
+                        this.fail_tests_over_budget()?;
+                    }
                     if !this.step()? {
                         // See if this thread can do something else.
                         match this.run_on_stack_empty()? {
@@ -1201,8 +1591,22 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 SchedulingAction::ExecuteTimeoutCallback => {
                     this.run_timeout_callback()?;
                 }
-                SchedulingAction::Sleep(duration) => {
+                SchedulingAction::Sleep(mut duration) => {
+                    // With the host clock, we really sleep; wake up in time to stop the program
+                    // when it reaches its time limit.
+                    if let Some((_, deadline)) = this.machine.timeout
+                        && this.machine.communicate()
+                    {
+                        duration = duration
+                            .min(deadline.saturating_duration_since(std::time::Instant::now()));
+                    }
                     this.machine.clock.sleep(duration);
+                    this.machine.check_timeout()?;
+                    // No basic blocks are executed while all threads sleep, so this is the only
+                    // place where a test that sleeps for too long can exceed its timeout.
+                    if !this.machine.running_tests.is_empty() {
+                        this.check_test_budgets(true)?;
+                    }
                 }
             }
         }
@@ -75,7 +75,7 @@ impl VTimestamp {
     }
 
     #[inline]
-    fn time(&self) -> u32 {
+    pub(super) fn time(&self) -> u32 {
         self.time_and_read_type.shr(1)
     }
 
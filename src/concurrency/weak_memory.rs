@@ -52,7 +52,10 @@
 //! In Rust, these operations can only be done through a `&mut AtomicFoo` reference or one derived from it, therefore these operations
 //! can only happen after all previous accesses on the same locations. This implementation is adapted to allow these operations.
 //! A mixed atomicity read that races with writes, or a write that races with reads or writes will still cause UBs to be thrown.
-//! Mixed size atomic accesses must not race with any other atomic access, whether read or write, or a UB will be thrown.
+//! Mixed size atomic writes must not race with any other atomic access, whether read or write, or a UB will be thrown.
+//! Mixed size atomic reads may race with each other (this is allowed by Rust's memory model, but not by C++'s). Such a
+//! read replaces the store buffers it imperfectly overlaps with a fresh one holding the latest value, so it (and later
+//! reads) will always observe the latest store. This may miss some weak behaviours, but never produces impossible ones.
 //! You can refer to test cases in weak_memory/extra_cpp.rs and weak_memory/extra_cpp_unsafe.rs for examples of these operations.
 
 // Our and the author's own implementation (tsan11) of the paper have some deviations from the provided operational semantics in §5.3:
@@ -76,6 +79,13 @@
 // This is implemented correctly in tsan11
 // (https://github.com/ChrisLidbury/tsan11/blob/ecbd6b81e9b9454e01cba78eb9d88684168132c7/lib/tsan/rtl/tsan_relaxed.cc#L295)
 // and here.
+//
+// 5. The paper treats a failed compare-exchange like an RMW that does not write, i.e. it always reads
+// the latest store in modification order. As in RC11 and the promising semantics, we instead treat it
+// as a plain load with the failure ordering: it may read from any store a load could read from, as
+// long as that store's value does not compare equal to the expected value (otherwise the operation
+// would have succeeded). Only successful RMWs have to read from the latest store, which guarantees
+// their atomicity.
 
 use std::{
     cell::{Ref, RefCell},
@@ -94,12 +104,6 @@ use super::{
 
 pub type AllocState = StoreBufferAlloc;
 
-// Each store buffer must be bounded otherwise it will grow indefinitely.
-// However, bounding the store buffer means restricting the amount of weak
-// behaviours observable. The author picked 128 as a good tradeoff
-// so we follow them here.
-const STORE_BUFFER_LIMIT: usize = 128;
-
 #[derive(Debug, Clone)]
 pub struct StoreBufferAlloc {
     /// Store buffer of each atomic object in this allocation
@@ -124,6 +128,8 @@ impl VisitProvenance for StoreBufferAlloc {
 pub(super) struct StoreBuffer {
     // Stores to this location in modification order
     buffer: VecDeque<StoreElement>,
+    /// The number of stores since we last dropped unreadable stores, see `buffered_write`.
+    stores_since_scan: usize,
 }
 
 /// Whether a load returned the latest value or not.
@@ -208,7 +214,8 @@ impl StoreBufferAlloc {
                 pos
             }
             AccessType::ImperfectlyOverlapping(pos_range) => {
-                // Once we reach here we would've already checked that this access is not racy.
+                // Once we reach here we would've already checked that this access is not racy,
+                // or that it only races with reads. Either way, reading the latest value is fine.
                 let mut buffers = self.store_buffers.borrow_mut();
                 buffers.remove_pos_range(pos_range.clone());
                 buffers.insert_at_pos(pos_range.start, range, StoreBuffer::new(init));
@@ -245,9 +252,7 @@ impl StoreBufferAlloc {
 
 impl<'tcx> StoreBuffer {
     fn new(init: Scalar) -> Self {
-        let mut buffer = VecDeque::new();
-        buffer.reserve(STORE_BUFFER_LIMIT);
-        let mut ret = Self { buffer };
+        let mut ret = Self { buffer: VecDeque::new(), stores_since_scan: 0 };
         let store_elem = StoreElement {
             // The thread index and timestamp of the initialisation write
             // are never meaningfully used, so it's fine to leave them as 0
@@ -280,6 +285,7 @@ impl<'tcx> StoreBuffer {
         global: &DataRaceState,
         thread_mgr: &ThreadManager<'_>,
         is_seqcst: bool,
+        can_read: impl Fn(Scalar) -> bool,
         rng: &mut (impl rand::Rng + ?Sized),
         validate: impl FnOnce() -> InterpResult<'tcx>,
     ) -> InterpResult<'tcx, (Scalar, LoadRecency)> {
@@ -291,7 +297,7 @@ impl<'tcx> StoreBuffer {
             // as the race detector will update it
             let (.., clocks) = global.active_thread_state(thread_mgr);
             // Load from a valid entry in the store buffer
-            self.fetch_store(is_seqcst, &clocks, &mut *rng)
+            self.fetch_store(is_seqcst, &clocks, can_read, thread_mgr, &mut *rng)
         };
 
         // Unlike in buffered_atomic_write, thread clock updates have to be done
@@ -314,16 +320,50 @@ impl<'tcx> StoreBuffer {
     ) -> InterpResult<'tcx> {
         let (index, clocks) = global.active_thread_state(thread_mgr);
 
-        self.store_impl(val, index, &clocks.clock, is_seqcst);
+        self.store_impl(val, index, &clocks.clock, is_seqcst, global.store_buffer_limit);
+        drop(clocks);
+        // Looking for unreadable stores takes time proportional to the number of threads times
+        // the size of the buffer. Doing that only once the buffer has received half as many new
+        // stores as it holds keeps the time per store proportional to just the number of threads.
+        self.stores_since_scan += 1;
+        if global.store_buffer_eviction == StoreBufferEviction::Unreadable
+            && self.stores_since_scan * 2 >= self.buffer.len()
+        {
+            self.drop_unreadable(global);
+            self.stores_since_scan = 0;
+        }
         Ok(())
     }
 
+    /// Drop all stores that no thread can read from anymore. For every thread, a load cannot read
+    /// from anything before the latest store that happens-before it (CoWR), and since clocks only
+    /// grow, this remains true for all future loads. Threads spawned later start out with the
+    /// clock of their parent, so they are covered as well.
+    fn drop_unreadable(&mut self, global: &DataRaceState) {
+        let keep_from = global
+            .all_thread_states()
+            .iter()
+            .map(|clocks| {
+                self.buffer
+                    .iter()
+                    .rposition(|store_elem| {
+                        store_elem.timestamp <= clocks.clock[store_elem.store_index]
+                    })
+                    .unwrap_or(0)
+            })
+            .min()
+            .unwrap_or(0);
+        self.buffer.drain(..keep_from);
+    }
+
     #[allow(clippy::if_same_then_else, clippy::needless_bool)]
-    /// Selects a valid store element in the buffer.
+    /// Selects a valid store element in the buffer, among those whose value satisfies `can_read`.
     fn fetch_store<R: rand::Rng + ?Sized>(
         &self,
         is_seqcst: bool,
         clocks: &ThreadClockSet,
+        can_read: impl Fn(Scalar) -> bool,
+        thread_mgr: &ThreadManager<'_>,
         rng: &mut R,
     ) -> (&StoreElement, LoadRecency) {
         use rand::seq::IteratorRandom;
@@ -395,9 +435,16 @@ impl<'tcx> StoreBuffer {
                 } else {
                     true
                 }
-            });
+            })
+            .filter(|&store_elem| can_read(store_elem.val));
 
-        let chosen = candidates.choose(rng).expect("store buffer cannot be empty");
+        let chosen = if thread_mgr.explores_loads() {
+            // The model checker tries every candidate in some execution.
+            let candidates: Vec<_> = candidates.collect();
+            candidates[thread_mgr.choose_load(candidates.len())]
+        } else {
+            candidates.choose(rng).expect("store buffer cannot be empty")
+        };
         if std::ptr::eq(chosen, self.buffer.back().expect("store buffer cannot be empty")) {
             (chosen, LoadRecency::Latest)
         } else {
@@ -412,6 +459,7 @@ impl<'tcx> StoreBuffer {
         index: VectorIdx,
         thread_clock: &VClock,
         is_seqcst: bool,
+        limit: usize,
     ) {
         let store_elem = StoreElement {
             store_index: index,
@@ -425,7 +473,10 @@ impl<'tcx> StoreBuffer {
             load_info: RefCell::new(LoadInfo::default()),
         };
         self.buffer.push_back(store_elem);
-        if self.buffer.len() > STORE_BUFFER_LIMIT {
+        // Each store buffer must be bounded otherwise it will grow indefinitely.
+        // However, bounding the store buffer means restricting the amount of weak
+        // behaviours observable.
+        if self.buffer.len() > limit {
             self.buffer.pop_front();
         }
         if is_seqcst {
@@ -486,11 +537,14 @@ pub(super) trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         Ok(())
     }
 
+    /// Perform the store buffer part of an atomic load. Only stores whose value satisfies
+    /// `can_read` are considered; the latest store in modification order must satisfy it.
     fn buffered_atomic_read(
         &self,
         place: &MPlaceTy<'tcx>,
         atomic: AtomicReadOrd,
         latest_in_mo: Scalar,
+        can_read: impl Fn(Scalar) -> bool,
         validate: impl FnOnce() -> InterpResult<'tcx>,
     ) -> InterpResult<'tcx, Scalar> {
         let this = self.eval_context_ref();
@@ -509,9 +563,16 @@ pub(super) trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     global,
                     &this.machine.threads,
                     atomic == AtomicReadOrd::SeqCst,
+                    can_read,
                     &mut *rng,
                     validate,
                 )?;
+                this.machine.log_nondeterminism("weak-memory", || {
+                    match recency {
+                        LoadRecency::Outdated => format!("loaded outdated value {loaded}"),
+                        LoadRecency::Latest => format!("loaded latest value {loaded}"),
+                    }
+                });
                 if global.track_outdated_loads && recency == LoadRecency::Outdated {
                     this.emit_diagnostic(NonHaltingDiagnostic::WeakMemoryOutdatedLoad {
                         ptr: place.ptr(),
@@ -568,34 +629,4 @@ pub(super) trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         // Caller should've written to dest with the vanilla scalar write, we do nothing here
         Ok(())
     }
-
-    /// Caller should never need to consult the store buffer for the latest value.
-    /// This function is used exclusively for failed atomic_compare_exchange_scalar
-    /// to perform load_impl on the latest store element
-    fn perform_read_on_buffered_latest(
-        &self,
-        place: &MPlaceTy<'tcx>,
-        atomic: AtomicReadOrd,
-        init: Scalar,
-    ) -> InterpResult<'tcx> {
-        let this = self.eval_context_ref();
-
-        if let Some(global) = &this.machine.data_race {
-            if atomic == AtomicReadOrd::SeqCst {
-                global.sc_read(&this.machine.threads);
-            }
-            let size = place.layout.size;
-            let (alloc_id, base_offset, ..) = this.ptr_get_alloc_id(place.ptr())?;
-            if let Some(alloc_buffers) = this.get_alloc_extra(alloc_id)?.weak_memory.as_ref() {
-                let buffer = alloc_buffers
-                    .get_or_create_store_buffer(alloc_range(base_offset, size), init)?;
-                buffer.read_from_last_store(
-                    global,
-                    &this.machine.threads,
-                    atomic == AtomicReadOrd::SeqCst,
-                );
-            }
-        }
-        Ok(())
-    }
 }
@@ -0,0 +1,146 @@
+//! Line coverage of the local crates, for `-Zmiri-coverage`.
+//!
+//! We count how often each basic block of each function of the local crates was executed. At the
+//! end of the execution, every line that a basic block has code on gets the count of the most
+//! frequently executed such block, and we write that out in the lcov format, which most coverage
+//! tools (e.g. `genhtml`, or the coverage gutters of editors) understand.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use either::Either;
+
+use rustc_data_structures::fx::FxHashMap;
+use rustc_hir::def::DefKind;
+use rustc_hir::def_id::DefId;
+use rustc_middle::mir;
+use rustc_middle::ty::{self, TyCtxt};
+use rustc_span::{FileName, Span};
+
+use crate::*;
+
+#[derive(Debug)]
+pub struct Coverage {
+    /// Where to write the report.
+    out: PathBuf,
+    /// For each function that was called, how often each of its basic blocks was executed.
+    blocks: FxHashMap<DefId, Vec<u64>>,
+}
+
+/// The coverage of one source file.
+#[derive(Default)]
+struct FileCoverage {
+    /// For each function: its name, the line it starts on, and how often it was called.
+    functions: Vec<(String, usize, u64)>,
+    /// For each line with code on it: how often it was executed.
+    lines: BTreeMap<usize, u64>,
+}
+
+impl Coverage {
+    pub fn new(out: PathBuf) -> Self {
+        Coverage { out, blocks: FxHashMap::default() }
+    }
+
+    /// Write the report. Besides the functions that were called, this includes all functions of
+    /// the crate being run, so that code that was never reached shows up as uncovered.
+    pub fn write_report(&self, tcx: TyCtxt<'_>) -> io::Result<()> {
+        let mut def_ids: Vec<DefId> = self.blocks.keys().copied().collect();
+        for &def_id in tcx.mir_keys(()) {
+            let def_id = def_id.to_def_id();
+            if matches!(tcx.def_kind(def_id), DefKind::Fn | DefKind::AssocFn | DefKind::Closure)
+                && !self.blocks.contains_key(&def_id)
+            {
+                def_ids.push(def_id);
+            }
+        }
+
+        let mut files: BTreeMap<PathBuf, FileCoverage> = BTreeMap::new();
+        for def_id in def_ids {
+            let body = tcx.optimized_mir(def_id);
+            let counts = self.blocks.get(&def_id);
+            let count = |block: mir::BasicBlock| counts.map_or(0, |counts| counts[block.index()]);
+            if let Some(path) = source_path(tcx, body.span) {
+                let line = tcx.sess.source_map().lookup_char_pos(body.span.lo()).line;
+                let name = tcx.def_path_str(def_id);
+                let calls = count(mir::START_BLOCK);
+                files.entry(path).or_default().functions.push((name, line, calls));
+            }
+            for (block, data) in body.basic_blocks.iter_enumerated() {
+                let spans = data
+                    .statements
+                    .iter()
+                    .map(|statement| statement.source_info.span)
+                    .chain([data.terminator().source_info.span]);
+                for span in spans {
+                    // Attribute the code from macro expansions to the line of the macro call.
+                    let span = span.source_callsite();
+                    let Some(path) = source_path(tcx, span) else { continue };
+                    let line = tcx.sess.source_map().lookup_char_pos(span.lo()).line;
+                    let lines = &mut files.entry(path).or_default().lines;
+                    let line_count = lines.entry(line).or_default();
+                    *line_count = (*line_count).max(count(block));
+                }
+            }
+        }
+
+        let mut report = String::new();
+        for (path, file) in files {
+            writeln!(report, "SF:{}", path.display()).unwrap();
+            for (name, line, _) in &file.functions {
+                writeln!(report, "FN:{line},{name}").unwrap();
+            }
+            for (name, _, calls) in &file.functions {
+                writeln!(report, "FNDA:{calls},{name}").unwrap();
+            }
+            writeln!(report, "FNF:{}", file.functions.len()).unwrap();
+            let hit = file.functions.iter().filter(|(_, _, calls)| *calls > 0).count();
+            writeln!(report, "FNH:{hit}").unwrap();
+            for (line, count) in &file.lines {
+                writeln!(report, "DA:{line},{count}").unwrap();
+            }
+            writeln!(report, "LF:{}", file.lines.len()).unwrap();
+            let hit = file.lines.values().filter(|&&count| count > 0).count();
+            writeln!(report, "LH:{hit}").unwrap();
+            writeln!(report, "end_of_record").unwrap();
+        }
+        if let Some(dir) = self.out.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.out, report)
+    }
+}
+
+/// The absolute path of the local source file that `span` is in.
+fn source_path(tcx: TyCtxt<'_>, span: Span) -> Option<PathBuf> {
+    if span.is_dummy() {
+        return None;
+    }
+    let file = tcx.sess.source_map().lookup_source_file(span.lo());
+    let FileName::Real(name) = &file.name else { return None };
+    let path = name.local_path()?;
+    Some(Path::new(tcx.sess.opts.working_dir.local_path_if_available()).join(path))
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// With `-Zmiri-coverage`, count the execution of the current basic block.
+    fn record_coverage(&mut self) {
+        let this = self.eval_context_mut();
+        if this.machine.coverage.is_none() {
+            return;
+        }
+        let frame = this.frame();
+        let ty::InstanceKind::Item(def_id) = frame.instance.def else { return };
+        let Either::Left(location) = frame.current_loc() else { return };
+        if !def_id.is_local() && !this.machine.local_crates.contains(&def_id.krate) {
+            return;
+        }
+        let num_blocks = frame.body.basic_blocks.len();
+        let coverage = this.machine.coverage.as_mut().unwrap();
+        let counts = coverage.blocks.entry(def_id).or_insert_with(|| vec![0; num_blocks]);
+        counts[location.block.index()] += 1;
+    }
+}
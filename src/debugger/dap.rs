@@ -0,0 +1,428 @@
+//! A server for the Debug Adapter Protocol, so that IDEs like VS Code can attach to the
+//! interpreted program.
+//!
+//! The program can be stopped at source lines and at functions, and stepping executes one MIR
+//! terminator at a time. Line breakpoints are only checked before terminators and on function
+//! entry, since the interpreter has no hook for individual statements. Variables can only be
+//! inspected in the innermost frame of the thread that stopped. Besides the locals, that frame
+//! has a "Borrow tracking" scope that shows the borrow tag of each local pointer, together with
+//! everything the borrow tracker knows about that tag.
+
+use std::io::{self, BufRead, Read, Write};
+use std::mem;
+use std::path::PathBuf;
+
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
+use serde_json::{json, Value};
+
+use super::{connect, fn_path_matches, EvalContextExt as _, Frontend, Resume, StopReason};
+use crate::*;
+
+/// What a `variablesReference` handed out to the client refers to. References are only valid
+/// until the program continues.
+#[derive(Clone, Copy, Debug)]
+enum VarsRef {
+    /// The locals of the innermost frame of the stopped thread.
+    Locals,
+    /// The borrow tags of the local pointers of the innermost frame of the stopped thread.
+    Borrows,
+    /// The history of a borrow tag.
+    TagHistory(AllocId, BorTag),
+}
+
+pub struct DapServer {
+    reader: Box<dyn BufRead>,
+    writer: Box<dyn Write>,
+    /// The sequence number of the next message we send.
+    seq: u64,
+    /// The lines to stop at, by absolute path.
+    line_breakpoints: FxHashMap<PathBuf, FxHashSet<usize>>,
+    /// The functions to stop at, by path.
+    fn_breakpoints: Vec<String>,
+    /// Whether to stop before the next terminator.
+    stepping: bool,
+    /// Whether we entered a function with a breakpoint. We stop before its first terminator, since
+    /// its arguments are not initialized yet on entry.
+    entered_breakpoint: bool,
+    /// Whether the client asked to stop before the program starts.
+    stop_on_entry: bool,
+    /// Whether the client is waiting for the program to stop.
+    running: bool,
+    /// The thread, stack depth, file, and line at which we last checked for line breakpoints, so
+    /// that we stop only once per line even if it contains several terminators.
+    last_line: Option<(ThreadId, usize, PathBuf, usize)>,
+    vars_refs: Vec<VarsRef>,
+}
+
+impl DapServer {
+    /// Wait for a client to connect at `addr` (or use stdio, see `connect`).
+    pub fn listen(addr: &str) -> io::Result<Self> {
+        let (reader, writer) = connect(addr)?;
+        Ok(DapServer {
+            reader,
+            writer,
+            seq: 1,
+            line_breakpoints: FxHashMap::default(),
+            fn_breakpoints: Vec::new(),
+            // Stop before the first terminator, to let the client configure breakpoints.
+            stepping: true,
+            entered_breakpoint: false,
+            stop_on_entry: false,
+            running: false,
+            last_line: None,
+            vars_refs: Vec::new(),
+        })
+    }
+
+    /// Answer a single request. Returns how to resume the program, if the request resumes it.
+    fn handle_request<'tcx>(
+        &mut self,
+        ecx: &mut MiriInterpCx<'tcx>,
+        command: &str,
+        args: &Value,
+    ) -> Result<(Value, Option<Resume>), String> {
+        let threads = &ecx.machine.threads;
+        let body = match command {
+            "initialize" =>
+                json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsFunctionBreakpoints": true,
+                }),
+            "attach" | "launch" => {
+                self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+                json!({})
+            }
+            "setBreakpoints" => {
+                let path = PathBuf::from(args["source"]["path"].as_str().unwrap_or_default());
+                let lines = args["breakpoints"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|bp| bp["line"].as_u64())
+                    .map(|line| line as usize)
+                    .collect::<FxHashSet<_>>();
+                let mut lines_sorted = lines.iter().copied().collect::<Vec<_>>();
+                lines_sorted.sort();
+                self.line_breakpoints.insert(path, lines);
+                let breakpoints = lines_sorted
+                    .into_iter()
+                    .map(|line| json!({ "verified": true, "line": line }))
+                    .collect::<Vec<_>>();
+                json!({ "breakpoints": breakpoints })
+            }
+            "setFunctionBreakpoints" => {
+                self.fn_breakpoints = args["breakpoints"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|bp| bp["name"].as_str())
+                    .map(str::to_owned)
+                    .collect();
+                let breakpoints =
+                    self.fn_breakpoints.iter().map(|_| json!({ "verified": true })).collect();
+                json!({ "breakpoints": Value::Array(breakpoints) })
+            }
+            // With `stopOnEntry`, `serve` reports that we stopped after sending the response.
+            "configurationDone" if self.stop_on_entry => json!({}),
+            "configurationDone" => return Ok((json!({}), Some(Resume::Continue))),
+            "threads" => {
+                let threads = threads
+                    .all_stacks()
+                    .filter(|&(thread, _)| !threads.has_terminated(thread))
+                    .map(|(thread, _)| {
+                        json!({
+                            "id": thread.to_u32(),
+                            "name": threads.get_thread_display_name(thread),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                json!({ "threads": threads })
+            }
+            "stackTrace" => {
+                let thread = args["threadId"].as_u64().unwrap_or_default();
+                let Some((thread, stack)) =
+                    threads.all_stacks().find(|(t, _)| u64::from(t.to_u32()) == thread)
+                else {
+                    return Err(format!("there is no thread {thread}"));
+                };
+                let frames = Frame::generate_stacktrace_from_stack(stack)
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, frame)| {
+                        let mut json = json!({
+                            "id": frame_id(thread, idx),
+                            "name": frame.instance.to_string(),
+                            "line": 0,
+                            "column": 0,
+                        });
+                        if let Some((path, line, col)) = ecx.debugger_source_location(frame.span) {
+                            json["source"] = json!({ "path": path });
+                            json["line"] = json!(line);
+                            json["column"] = json!(col);
+                        }
+                        json
+                    })
+                    .collect::<Vec<_>>();
+                json!({ "stackFrames": frames, "totalFrames": frames.len() })
+            }
+            "scopes" => {
+                // We can only show the innermost frame of the thread that stopped.
+                let frame = args["frameId"].as_u64().unwrap_or_default();
+                let scopes = if frame == frame_id(threads.active_thread(), 0) {
+                    vec![
+                        json!({
+                            "name": "Locals",
+                            "variablesReference": self.vars_ref(VarsRef::Locals),
+                            "expensive": false,
+                        }),
+                        json!({
+                            "name": "Borrow tracking",
+                            "variablesReference": self.vars_ref(VarsRef::Borrows),
+                            "expensive": false,
+                        }),
+                    ]
+                } else {
+                    vec![]
+                };
+                json!({ "scopes": scopes })
+            }
+            "variables" => {
+                let vars_ref = args["variablesReference"].as_u64().unwrap_or_default();
+                let Some(&vars_ref) = usize::try_from(vars_ref)
+                    .ok()
+                    .and_then(|r| self.vars_refs.get(r.wrapping_sub(1)))
+                else {
+                    return Err(format!("invalid variables reference {vars_ref}"));
+                };
+                json!({ "variables": self.variables(ecx, vars_ref) })
+            }
+            "continue" =>
+                return Ok((json!({ "allThreadsContinued": true }), Some(Resume::Continue))),
+            // We step one terminator at a time, no matter how the client wants to step.
+            "next" | "stepIn" | "stepOut" => {
+                self.stepping = true;
+                return Ok((json!({}), Some(Resume::Continue)));
+            }
+            // The program is already stopped.
+            "pause" => json!({}),
+            "disconnect" =>
+                return Ok((
+                    json!({}),
+                    Some(if args["terminateDebuggee"].as_bool() == Some(true) {
+                        Resume::Kill
+                    } else {
+                        Resume::Detach
+                    }),
+                )),
+            _ => return Err(format!("unsupported request `{command}`")),
+        };
+        Ok((body, None))
+    }
+
+    /// The variables a `variablesReference` refers to.
+    fn variables<'tcx>(&mut self, ecx: &mut MiriInterpCx<'tcx>, vars_ref: VarsRef) -> Vec<Value> {
+        match vars_ref {
+            VarsRef::Locals =>
+                ecx.debugger_locals()
+                    .into_iter()
+                    .map(|(name, op)| {
+                        let value = match &op {
+                            Some(op) => ecx.debugger_describe_value(op),
+                            None => "<unavailable>".to_owned(),
+                        };
+                        let ty = op.map(|op| op.layout.ty.to_string()).unwrap_or_default();
+                        json!({ "name": name, "value": value, "type": ty, "variablesReference": 0 })
+                    })
+                    .collect(),
+            VarsRef::Borrows => {
+                if ecx.machine.borrow_tracker.is_none() {
+                    return vec![json!({
+                        "name": "borrow tracking is disabled",
+                        "value": "",
+                        "variablesReference": 0,
+                    })];
+                }
+                let mut vars = Vec::new();
+                for (name, op) in ecx.debugger_locals() {
+                    let Some(op) = op else { continue };
+                    if !op.layout.ty.is_any_ptr() {
+                        continue;
+                    }
+                    let Some(Provenance::Concrete { alloc_id, tag }) =
+                        ecx.debugger_pointer_provenance(&op)
+                    else {
+                        continue;
+                    };
+                    vars.push(json!({
+                        "name": name,
+                        "value": renumber_allocs(format!("{tag:?} in {alloc_id:?}")),
+                        "variablesReference": self.vars_ref(VarsRef::TagHistory(alloc_id, tag)),
+                    }));
+                }
+                vars
+            }
+            VarsRef::TagHistory(alloc_id, tag) => {
+                let tcx = ecx.tcx.tcx;
+                let sm = tcx.sess.source_map();
+                let Ok(events) = ecx.tag_history(alloc_id, tag) else { return vec![] };
+                events
+                    .into_iter()
+                    .enumerate()
+                    .map(|(idx, (span, msg))| {
+                        let name = match span {
+                            Some(span) => sm.span_to_embeddable_string(span.span()),
+                            None => format!("{idx}"),
+                        };
+                        json!({ "name": name, "value": msg, "variablesReference": 0 })
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Hand out a `variablesReference` for `vars_ref`.
+    fn vars_ref(&mut self, vars_ref: VarsRef) -> usize {
+        self.vars_refs.push(vars_ref);
+        self.vars_refs.len()
+    }
+
+    /// Receive the next message, or `None` if the client disconnected.
+    fn recv(&mut self) -> io::Result<Option<Value>> {
+        let mut len = None;
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            let line = line.trim();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(n) = line.strip_prefix("Content-Length:") {
+                len = n.trim().parse::<usize>().ok();
+            }
+        }
+        let Some(len) = len else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "missing `Content-Length`"));
+        };
+        let mut buf = vec![0; len];
+        self.reader.read_exact(&mut buf)?;
+        Ok(Some(serde_json::from_slice(&buf)?))
+    }
+
+    fn send(&mut self, mut msg: Value) -> io::Result<()> {
+        msg["seq"] = json!(self.seq);
+        self.seq += 1;
+        let msg = msg.to_string();
+        write!(self.writer, "Content-Length: {}\r\n\r\n{msg}", msg.len())?;
+        self.writer.flush()
+    }
+
+    fn send_event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    fn send_stopped<'tcx>(
+        &mut self,
+        ecx: &MiriInterpCx<'tcx>,
+        reason: StopReason,
+    ) -> io::Result<()> {
+        let reason = match reason {
+            StopReason::Start => "entry",
+            StopReason::Step => "step",
+            StopReason::Breakpoint => "breakpoint",
+            StopReason::Error => "exception",
+        };
+        self.send_event(
+            "stopped",
+            json!({
+                "reason": reason,
+                "threadId": ecx.machine.threads.active_thread().to_u32(),
+                "allThreadsStopped": true,
+            }),
+        )
+    }
+}
+
+impl<'tcx> Frontend<'tcx> for DapServer {
+    fn should_stop(&mut self, ecx: &MiriInterpCx<'tcx>, entry: bool) -> Option<StopReason> {
+        if entry {
+            if !self.fn_breakpoints.is_empty() {
+                let path = ecx.tcx.def_path_str(ecx.frame().instance.def_id());
+                self.entered_breakpoint |=
+                    self.fn_breakpoints.iter().any(|bp| fn_path_matches(bp, &path));
+            }
+            return None;
+        }
+        if mem::take(&mut self.entered_breakpoint) {
+            return Some(StopReason::Breakpoint);
+        }
+        if self.stepping {
+            let first = ecx.machine.basic_block_count == 1;
+            return Some(if first { StopReason::Start } else { StopReason::Step });
+        }
+        if self.line_breakpoints.is_empty() {
+            return None;
+        }
+        let (path, line, _) = ecx.debugger_source_location(ecx.frame().current_span())?;
+        let here =
+            (ecx.machine.threads.active_thread(), ecx.active_thread_stack().len(), path, line);
+        if self.last_line.as_ref() == Some(&here) {
+            return None;
+        }
+        let (_, _, path, line) = self.last_line.insert(here);
+        let hit = self.line_breakpoints.get(path).is_some_and(|lines| lines.contains(line));
+        hit.then_some(StopReason::Breakpoint)
+    }
+
+    fn serve(&mut self, ecx: &mut MiriInterpCx<'tcx>, reason: StopReason) -> io::Result<Resume> {
+        self.stepping = false;
+        self.vars_refs.clear();
+        if self.running {
+            self.running = false;
+            self.send_stopped(ecx, reason)?;
+        }
+        loop {
+            let Some(msg) = self.recv()? else { return Ok(Resume::Detach) };
+            if msg["type"] != "request" {
+                continue;
+            }
+            let command = msg["command"].as_str().unwrap_or_default().to_owned();
+            let (success, body, resume) =
+                match self.handle_request(ecx, &command, &msg["arguments"]) {
+                    Ok((body, resume)) => (true, body, resume),
+                    Err(message) =>
+                        (false, json!({ "error": { "id": 1, "format": message } }), None),
+                };
+            self.send(json!({
+                "type": "response",
+                "request_seq": msg["seq"],
+                "success": success,
+                "command": command,
+                "body": body,
+            }))?;
+            if let Some(resume) = resume {
+                self.running = true;
+                return Ok(resume);
+            }
+            // Some events have to follow the response to a request.
+            match command.as_str() {
+                "initialize" => self.send_event("initialized", json!({}))?,
+                "configurationDone" => self.send_stopped(ecx, StopReason::Start)?,
+                _ => {}
+            }
+        }
+    }
+
+    fn exited(&mut self, return_code: Option<i64>) -> io::Result<()> {
+        // Errors make Miri exit with code 1.
+        self.send_event("exited", json!({ "exitCode": return_code.unwrap_or(1) }))?;
+        self.send_event("terminated", json!({}))
+    }
+}
+
+/// The `frameId` we use for the frame at index `idx` (counting from the innermost frame) of the
+/// stack of `thread`.
+fn frame_id(thread: ThreadId, idx: usize) -> u64 {
+    (u64::from(thread.to_u32()) << 32) | idx as u64
+}
@@ -0,0 +1,268 @@
+//! A server for the GDB remote serial protocol, so that GDB or LLDB can attach to the interpreted
+//! program.
+//!
+//! Miri has neither machine registers nor code addresses, so we only support what maps naturally
+//! onto the interpreter: listing threads, reading memory by address, and stopping and stepping
+//! (one basic block at a time). Since there are no code addresses to put breakpoints on,
+//! breakpoints are set on functions via `monitor break <path>`, `monitor bt` prints the
+//! source-level stack of the selected thread, and `monitor locals` prints the locals of the
+//! innermost frame of the thread that stopped.
+
+use std::fmt::Write as _;
+use std::io::{self, BufRead, Read, Write};
+use std::mem;
+
+use super::{connect, fn_path_matches, EvalContextExt as _, Frontend, Resume, StopReason};
+use crate::*;
+
+pub struct GdbStub {
+    reader: Box<dyn BufRead>,
+    writer: Box<dyn Write>,
+    /// The functions to stop at, by path.
+    breakpoints: Vec<String>,
+    /// Whether to stop before the next basic block.
+    stepping: bool,
+    /// Whether we entered a function with a breakpoint. We stop before its first terminator, since
+    /// its arguments are not initialized yet on entry.
+    entered_breakpoint: bool,
+    /// Whether the debugger is waiting for the program to stop.
+    running: bool,
+    /// The thread the debugger selected with `Hg`.
+    selected: ThreadId,
+}
+
+impl GdbStub {
+    /// Wait for a debugger to connect at `addr` (or use stdio, see `connect`).
+    pub fn listen(addr: &str) -> io::Result<Self> {
+        let (reader, writer) = connect(addr)?;
+        Ok(GdbStub {
+            reader,
+            writer,
+            breakpoints: Vec::new(),
+            // Stop before the first basic block, so that the debugger can set up breakpoints.
+            stepping: true,
+            entered_breakpoint: false,
+            running: false,
+            selected: ThreadId::MAIN_THREAD,
+        })
+    }
+
+    /// Answer the packets that have arguments.
+    fn handle_packet_with_args<'tcx>(
+        &mut self,
+        ecx: &MiriInterpCx<'tcx>,
+        packet: &str,
+    ) -> io::Result<String> {
+        Ok(if packet.starts_with("qSupported") {
+            "PacketSize=4000".to_owned()
+        } else if let Some(thread) = packet.strip_prefix("qThreadExtraInfo,") {
+            match parse_thread(ecx, thread) {
+                Some(thread) =>
+                    hex_encode(ecx.machine.threads.get_thread_display_name(thread).as_bytes()),
+                None => "E01".to_owned(),
+            }
+        } else if let Some(thread) = packet.strip_prefix('T') {
+            if parse_thread(ecx, thread).is_some() { "OK" } else { "E01" }.to_owned()
+        } else if let Some(thread) = packet.strip_prefix("Hg") {
+            // `0` and `-1` mean "any thread", so we keep the current selection.
+            if let Some(thread) = parse_thread(ecx, thread) {
+                self.selected = thread;
+            }
+            "OK".to_owned()
+        } else if packet.starts_with('H') {
+            "OK".to_owned()
+        } else if let Some(args) = packet.strip_prefix('m') {
+            let bytes = args.split_once(',').and_then(|(addr, len)| {
+                let addr = u64::from_str_radix(addr, 16).ok()?;
+                let len = u64::from_str_radix(len, 16).ok()?;
+                ecx.debugger_read_memory(addr, len)
+            });
+            match bytes {
+                Some(bytes) => hex_encode(&bytes),
+                None => "E01".to_owned(),
+            }
+        } else if let Some(cmd) = packet.strip_prefix("qRcmd,") {
+            let cmd = hex_decode(cmd).unwrap_or_default();
+            for line in self.monitor(ecx, &cmd) {
+                self.send_packet(&format!("O{}", hex_encode(format!("{line}\n").as_bytes())))?;
+            }
+            "OK".to_owned()
+        } else {
+            // An empty reply means that we do not support this packet.
+            String::new()
+        })
+    }
+
+    /// Run a `monitor` command, and return the lines to print.
+    fn monitor<'tcx>(&mut self, ecx: &MiriInterpCx<'tcx>, cmd: &str) -> Vec<String> {
+        let cmd = cmd.trim();
+        match cmd.split_once(' ').map(|(cmd, arg)| (cmd, arg.trim())) {
+            _ if cmd == "bt" => ecx.debugger_backtrace(self.selected),
+            _ if cmd == "locals" =>
+                ecx.debugger_locals()
+                    .into_iter()
+                    .map(|(name, op)| {
+                        match op {
+                            Some(op) =>
+                                format!(
+                                    "{name}: {} = {}",
+                                    op.layout.ty,
+                                    ecx.debugger_describe_value(&op)
+                                ),
+                            None => format!("{name}: <unavailable>"),
+                        }
+                    })
+                    .collect(),
+            _ if cmd == "info breakpoints" =>
+                self.breakpoints.iter().map(|bp| format!("breakpoint on `{bp}`")).collect(),
+            Some(("break", path)) => {
+                self.breakpoints.push(path.to_owned());
+                vec![format!("breakpoint set on `{path}`")]
+            }
+            Some(("delete", path)) => {
+                self.breakpoints.retain(|bp| bp != path);
+                vec![format!("breakpoint on `{path}` deleted")]
+            }
+            _ =>
+                vec![format!(
+                    "unknown command `{cmd}`; supported are `bt`, `locals`, `break <fn>`, `delete <fn>`, and `info breakpoints`"
+                )],
+        }
+    }
+
+    fn stop_reply(&self, reason: StopReason) -> String {
+        format!("T{:02x}thread:{:x};", reason.signal(), gdb_thread_id(self.selected))
+    }
+
+    /// Receive the next packet, or `None` if the debugger disconnected.
+    fn recv_packet(&mut self) -> io::Result<Option<String>> {
+        // Skip acknowledgments and interrupt requests (the program is stopped already).
+        let mut skipped = Vec::new();
+        if self.reader.read_until(b'$', &mut skipped)? == 0 || skipped.last() != Some(&b'$') {
+            return Ok(None);
+        }
+        let mut packet = Vec::new();
+        self.reader.read_until(b'#', &mut packet)?;
+        if packet.pop() != Some(b'#') {
+            return Ok(None);
+        }
+        let mut checksum = [0; 2];
+        self.reader.read_exact(&mut checksum)?;
+        self.writer.write_all(b"+")?;
+        Ok(Some(String::from_utf8_lossy(&packet).into_owned()))
+    }
+
+    fn send_packet(&mut self, data: &str) -> io::Result<()> {
+        let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(self.writer, "${data}#{checksum:02x}")?;
+        self.writer.flush()?;
+        // Wait for the acknowledgment.
+        let mut ack = [0; 1];
+        self.reader.read_exact(&mut ack)?;
+        Ok(())
+    }
+}
+
+impl<'tcx> Frontend<'tcx> for GdbStub {
+    fn should_stop(&mut self, ecx: &MiriInterpCx<'tcx>, entry: bool) -> Option<StopReason> {
+        if entry {
+            if !self.breakpoints.is_empty() {
+                let path = ecx.tcx.def_path_str(ecx.frame().instance.def_id());
+                self.entered_breakpoint |=
+                    self.breakpoints.iter().any(|bp| fn_path_matches(bp, &path));
+            }
+            None
+        } else if mem::take(&mut self.entered_breakpoint) {
+            Some(StopReason::Breakpoint)
+        } else if self.stepping {
+            let first = ecx.machine.basic_block_count == 1;
+            Some(if first { StopReason::Start } else { StopReason::Step })
+        } else {
+            None
+        }
+    }
+
+    fn serve(&mut self, ecx: &mut MiriInterpCx<'tcx>, reason: StopReason) -> io::Result<Resume> {
+        self.stepping = false;
+        self.selected = ecx.machine.threads.active_thread();
+        if self.running {
+            self.running = false;
+            let reply = self.stop_reply(reason);
+            self.send_packet(&reply)?;
+        }
+        loop {
+            let Some(packet) = self.recv_packet()? else { return Ok(Resume::Detach) };
+            let reply = match packet.as_str() {
+                "?" => self.stop_reply(reason),
+                "qAttached" => "1".to_owned(),
+                "qfThreadInfo" => {
+                    let threads = ecx
+                        .machine
+                        .threads
+                        .all_stacks()
+                        .filter(|&(thread, _)| !ecx.machine.threads.has_terminated(thread))
+                        .map(|(thread, _)| format!("{:x}", gdb_thread_id(thread)))
+                        .collect::<Vec<_>>();
+                    format!("m{}", threads.join(","))
+                }
+                "qsThreadInfo" => "l".to_owned(),
+                "qC" => format!("QC{:x}", gdb_thread_id(self.selected)),
+                // We have no registers to show.
+                "g" => "xxxxxxxxxxxxxxxx".to_owned(),
+                "c" => {
+                    self.running = true;
+                    return Ok(Resume::Continue);
+                }
+                "s" => {
+                    self.running = true;
+                    self.stepping = true;
+                    return Ok(Resume::Continue);
+                }
+                "D" => {
+                    self.send_packet("OK")?;
+                    return Ok(Resume::Detach);
+                }
+                "k" => return Ok(Resume::Kill),
+                packet => self.handle_packet_with_args(ecx, packet)?,
+            };
+            self.send_packet(&reply)?;
+        }
+    }
+
+    fn exited(&mut self, return_code: Option<i64>) -> io::Result<()> {
+        if !self.running {
+            return Ok(());
+        }
+        match return_code {
+            // GDB only supports 8-bit exit codes.
+            Some(code) => self.send_packet(&format!("W{:02x}", code as u8)),
+            None => self.send_packet(&format!("X{:02x}", StopReason::Error.signal())),
+        }
+    }
+}
+
+/// GDB thread IDs must be positive, so we shift ours by one.
+fn gdb_thread_id(thread: ThreadId) -> u32 {
+    thread.to_u32() + 1
+}
+
+/// Parse a GDB thread ID, and return the corresponding thread if it exists.
+fn parse_thread<'tcx>(ecx: &MiriInterpCx<'tcx>, id: &str) -> Option<ThreadId> {
+    let id = u32::from_str_radix(id, 16).ok()?.checked_sub(1)?;
+    ecx.machine.threads.all_stacks().map(|(thread, _)| thread).find(|t| t.to_u32() == id)
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut s, b| {
+        write!(s, "{b:02x}").unwrap();
+        s
+    })
+}
+
+fn hex_decode(hex: &str) -> Option<String> {
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    String::from_utf8(bytes).ok()
+}
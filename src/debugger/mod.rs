@@ -0,0 +1,223 @@
+//! Support for inspecting the interpreted program with an interactive debugger.
+
+mod dap;
+mod gdb;
+mod repl;
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+
+use rustc_middle::mir;
+use rustc_span::{FileName, Span};
+use rustc_target::abi::{Abi, Size};
+
+pub use self::dap::DapServer;
+pub use self::gdb::GdbStub;
+use crate::*;
+
+/// A debugger that is attached to the program.
+pub trait Frontend<'tcx> {
+    /// Whether to stop the program here. `entry` is set when the program just entered a function;
+    /// otherwise, it is about to execute a terminator.
+    fn should_stop(&mut self, ecx: &MiriInterpCx<'tcx>, entry: bool) -> Option<StopReason>;
+
+    /// Tell the debugger that the program stopped, and answer its requests until it lets the
+    /// program continue.
+    fn serve(&mut self, ecx: &mut MiriInterpCx<'tcx>, reason: StopReason) -> io::Result<Resume>;
+
+    /// Tell the debugger how the program ended.
+    fn exited(&mut self, return_code: Option<i64>) -> io::Result<()>;
+}
+
+/// Why the program stopped.
+#[derive(Clone, Copy, Debug)]
+pub enum StopReason {
+    /// We are about to execute the first basic block.
+    Start,
+    /// The debugger asked to execute a single basic block.
+    Step,
+    /// We entered a function with a breakpoint, or the program called `miri_breakpoint`.
+    Breakpoint,
+    /// The program ran into an error, which has not been reported yet.
+    Error,
+}
+
+impl StopReason {
+    /// The signal that a native program would stop with.
+    fn signal(self) -> u8 {
+        match self {
+            StopReason::Start | StopReason::Step | StopReason::Breakpoint => 5, // SIGTRAP
+            StopReason::Error => 6,                                             // SIGABRT
+        }
+    }
+}
+
+/// What the debugger wants the program to do after a stop.
+#[derive(Clone, Copy, Debug)]
+pub enum Resume {
+    Continue,
+    /// Continue without the debugger.
+    Detach,
+    Kill,
+}
+
+/// Wait for a debugger to connect at `addr`, and return the streams to talk to it on. If `addr` is
+/// `stdio`, the debugger talks to us on stdin and stdout instead (so the program cannot use them).
+fn connect(addr: &str) -> io::Result<(Box<dyn BufRead>, Box<dyn Write>)> {
+    if addr == "stdio" {
+        return Ok((Box::new(io::stdin().lock()), Box::new(io::stdout())));
+    }
+    let listener = TcpListener::bind(addr)?;
+    eprintln!("Waiting for a debugger to connect to {}...", listener.local_addr()?);
+    let (stream, _) = listener.accept()?;
+    stream.set_nodelay(true)?;
+    Ok((Box::new(BufReader::new(stream.try_clone()?)), Box::new(stream)))
+}
+
+/// Whether a function breakpoint on `pattern` applies to the function with the given path. The
+/// pattern may omit leading path segments.
+fn fn_path_matches(pattern: &str, path: &str) -> bool {
+    path == pattern || path.strip_suffix(pattern).is_some_and(|rest| rest.ends_with("::"))
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Hand control to the debugger, if one is attached.
+    fn debugger_stop(&mut self, reason: StopReason) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let Some(mut debugger) = this.machine.debugger.take() else { return Ok(()) };
+        let resume = debugger.serve(this, reason).unwrap_or_else(|err| {
+            eprintln!("lost the connection to the debugger: {err}");
+            Resume::Detach
+        });
+        match resume {
+            Resume::Continue => this.machine.debugger = Some(debugger),
+            Resume::Detach => {}
+            Resume::Kill =>
+                throw_machine_stop!(TerminationInfo::Abort(
+                    "the program was killed by the debugger".to_owned()
+                )),
+        }
+        Ok(())
+    }
+
+    /// Handle a call to `miri_breakpoint`: stop in the attached debugger, or run a simple REPL on
+    /// the terminal if there is none.
+    fn debugger_breakpoint(&mut self) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        if this.machine.debugger.is_some() {
+            this.debugger_stop(StopReason::Breakpoint)
+        } else {
+            repl::run(this)
+        }
+    }
+
+    /// Stop if the debugger wants to. `entry` is set when the program just entered a function;
+    /// otherwise, it is about to execute a terminator.
+    fn debugger_check_stop(&mut self, entry: bool) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let Some(mut debugger) = this.machine.debugger.take() else { return Ok(()) };
+        let reason = debugger.should_stop(this, entry);
+        this.machine.debugger = Some(debugger);
+        if let Some(reason) = reason {
+            this.debugger_stop(reason)?;
+        }
+        Ok(())
+    }
+
+    /// Tell the debugger how the program ended. If it ran into an error (i.e., there is no return
+    /// code), the debugger gets to inspect the program first.
+    fn debugger_finish(&mut self, return_code: Option<i64>) {
+        let this = self.eval_context_mut();
+        if return_code.is_none() {
+            // The program is over either way, so it does not matter how the debugger resumes.
+            let _ = this.debugger_stop(StopReason::Error);
+        }
+        if let Some(mut debugger) = this.machine.debugger.take()
+            && let Err(err) = debugger.exited(return_code)
+        {
+            eprintln!("lost the connection to the debugger: {err}");
+        }
+    }
+
+    /// Describe the stack of `thread`, innermost frame first.
+    fn debugger_backtrace(&self, thread: ThreadId) -> Vec<String> {
+        let this = self.eval_context_ref();
+        let sm = this.tcx.sess.source_map();
+        let Some((_, stack)) = this.machine.threads.all_stacks().find(|&(t, _)| t == thread) else {
+            return vec![];
+        };
+        Frame::generate_stacktrace_from_stack(stack)
+            .iter()
+            .enumerate()
+            .map(|(idx, frame)| {
+                format!("#{idx} {frame} at {}", sm.span_to_embeddable_string(frame.span))
+            })
+            .collect()
+    }
+
+    /// The source location of `span`: the absolute path of the file, and the 1-based line and
+    /// column.
+    fn debugger_source_location(&self, span: Span) -> Option<(PathBuf, usize, usize)> {
+        let this = self.eval_context_ref();
+        let loc = this.tcx.sess.source_map().lookup_char_pos(span.lo());
+        let FileName::Real(name) = &loc.file.name else { return None };
+        let path = name.local_path()?;
+        let path = std::env::current_dir().ok()?.join(path);
+        Some((path, loc.line, loc.col.0 + 1))
+    }
+
+    /// Read the `len` bytes at address `addr`, if they are all inside a single live allocation.
+    /// Uninitialized bytes and provenance are ignored.
+    fn debugger_read_memory(&self, addr: u64, len: u64) -> Option<Vec<u8>> {
+        let this = self.eval_context_ref();
+        let (alloc_id, offset) = this.machine.alloc_addresses.borrow().lookup_addr(addr)?;
+        let (size, _align, kind) = this.get_alloc_info(alloc_id);
+        if !matches!(kind, AllocKind::LiveData) || offset.checked_add(len)? > size.bytes() {
+            return None;
+        }
+        let alloc = this.get_alloc_raw(alloc_id).ok()?;
+        let range = alloc_range(Size::from_bytes(offset), Size::from_bytes(len));
+        Some(alloc.get_bytes_unchecked(range).to_vec())
+    }
+
+    /// The named locals of the innermost frame of the active thread, with their values if they
+    /// are live.
+    fn debugger_locals(&self) -> Vec<(String, Option<OpTy<'tcx>>)> {
+        let this = self.eval_context_ref();
+        let Some(frame) = this.active_thread_stack().last() else { return vec![] };
+        frame
+            .body
+            .var_debug_info
+            .iter()
+            .filter_map(|info| {
+                let mir::VarDebugInfoContents::Place(place) = info.value else { return None };
+                if !place.projection.is_empty() {
+                    return None;
+                }
+                Some((info.name.to_string(), this.local_to_op(place.local, None).ok()))
+            })
+            .collect()
+    }
+
+    /// Show a value like the debugger of a native program would.
+    fn debugger_describe_value(&self, op: &OpTy<'tcx>) -> String {
+        let this = self.eval_context_ref();
+        if !matches!(op.layout.abi, Abi::Scalar(_) | Abi::ScalarPair(..)) {
+            return format!("<{} bytes>", op.layout.size.bytes());
+        }
+        match this.read_immediate(op) {
+            Ok(imm) => format!("{imm}"),
+            Err(_) => "<uninitialized>".to_owned(),
+        }
+    }
+
+    /// The provenance of the pointer `op` (the data pointer, for wide pointers).
+    fn debugger_pointer_provenance(&self, op: &OpTy<'tcx>) -> Option<Provenance> {
+        let this = self.eval_context_ref();
+        let imm = this.read_immediate(op).ok()?;
+        let (Immediate::Scalar(ptr) | Immediate::ScalarPair(ptr, _)) = *imm else { return None };
+        ptr.to_pointer(this).ok()?.provenance
+    }
+}
@@ -0,0 +1,113 @@
+//! The simple REPL that `miri_breakpoint` drops into when no debugger is attached.
+
+use std::io::{self, BufRead, Write};
+
+use super::EvalContextExt as _;
+use crate::*;
+
+const HELP: &str = "\
+commands:
+  continue, c         continue running the program
+  bt                  print the stack of the current thread
+  locals              print the locals of the current function
+  mem <addr> [<len>]  print <len> (default 16) bytes of memory at address <addr>
+  borrows <local>     print the borrow tracking state of the allocation that the pointer in
+                      <local> points to, and the history of its tag";
+
+/// Read commands from stdin and run them, until the user lets the program continue.
+pub fn run<'tcx>(ecx: &mut MiriInterpCx<'tcx>) -> InterpResult<'tcx> {
+    let span = ecx.cur_span();
+    eprintln!(
+        "stopped at `miri_breakpoint` at {}; type `help` for a list of commands",
+        ecx.tcx.sess.source_map().span_to_embeddable_string(span)
+    );
+    let mut stdin = io::stdin().lock();
+    loop {
+        eprint!("(miri) ");
+        io::stderr().flush().unwrap();
+        let mut line = String::new();
+        if stdin.read_line(&mut line).unwrap_or(0) == 0 {
+            // Continue when there is no more input.
+            eprintln!();
+            return Ok(());
+        }
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        match words[..] {
+            [] => {}
+            ["continue" | "c"] => return Ok(()),
+            ["help"] => eprintln!("{HELP}"),
+            ["bt"] =>
+                for frame in ecx.debugger_backtrace(ecx.machine.threads.active_thread()) {
+                    eprintln!("{frame}");
+                },
+            ["locals"] =>
+                for (name, op) in ecx.debugger_locals() {
+                    match op {
+                        Some(op) =>
+                            eprintln!(
+                                "{name}: {} = {}",
+                                op.layout.ty,
+                                ecx.debugger_describe_value(&op)
+                            ),
+                        None => eprintln!("{name}: <unavailable>"),
+                    }
+                },
+            ["mem", addr] => print_memory(ecx, addr, "16"),
+            ["mem", addr, len] => print_memory(ecx, addr, len),
+            ["borrows", name] => print_borrows(ecx, name)?,
+            _ => eprintln!("unknown command; type `help` for a list of commands"),
+        }
+    }
+}
+
+fn print_memory<'tcx>(ecx: &MiriInterpCx<'tcx>, addr: &str, len: &str) {
+    let parse = |s: &str| {
+        match s.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => s.parse::<u64>().ok(),
+        }
+    };
+    let (Some(addr), Some(len)) = (parse(addr), parse(len)) else {
+        eprintln!("invalid address or length");
+        return;
+    };
+    let Some(bytes) = ecx.debugger_read_memory(addr, len) else {
+        eprintln!("{len} bytes at {addr:#x} are not all inside a single live allocation");
+        return;
+    };
+    for (idx, chunk) in bytes.chunks(16).enumerate() {
+        let hex = chunk.iter().map(|b| format!("{b:02x}")).collect::<Vec<_>>().join(" ");
+        eprintln!("{:#x}: {hex}", addr + 16 * idx as u64);
+    }
+}
+
+fn print_borrows<'tcx>(ecx: &mut MiriInterpCx<'tcx>, name: &str) -> InterpResult<'tcx> {
+    if ecx.machine.borrow_tracker.is_none() {
+        eprintln!("borrow tracking is disabled");
+        return Ok(());
+    }
+    let Some((_, op)) = ecx.debugger_locals().into_iter().find(|(local, _)| local == name) else {
+        eprintln!("there is no local `{name}`");
+        return Ok(());
+    };
+    let Some(Provenance::Concrete { alloc_id, tag }) =
+        op.and_then(|op| ecx.debugger_pointer_provenance(&op))
+    else {
+        eprintln!("`{name}` is not a pointer with a known borrow tag");
+        return Ok(());
+    };
+    eprintln!("{}", renumber_allocs(format!("`{name}` has tag {tag:?} in {alloc_id:?}")));
+    ecx.print_borrow_state(alloc_id, /* show_unnamed */ true)?;
+    let tcx = ecx.tcx.tcx;
+    for (span, msg) in ecx.tag_history(alloc_id, tag)? {
+        match span {
+            Some(span) =>
+                eprintln!(
+                    "{msg} at {}",
+                    tcx.sess.source_map().span_to_embeddable_string(span.span())
+                ),
+            None => eprintln!("{msg}"),
+        }
+    }
+    Ok(())
+}
@@ -1,7 +1,18 @@
 use std::fmt::{self, Write};
+use std::io::Write as _;
+use std::mem;
 use std::num::NonZero;
+use std::sync::{Arc, Mutex};
 
-use rustc_errors::{Diag, DiagMessage, Level};
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
+use rustc_data_structures::sync::Lrc;
+use rustc_errors::emitter::Emitter;
+use rustc_errors::translation::Translate;
+use rustc_errors::{
+    Diag, DiagCtxt, DiagInner, DiagMessage, FluentBundle, LazyFallbackBundle, Level,
+};
+use rustc_middle::ty::TyCtxt;
+use rustc_span::source_map::SourceMap;
 use rustc_span::{SpanData, Symbol, DUMMY_SP};
 use rustc_target::abi::{Align, Size};
 
@@ -28,7 +39,27 @@ pub enum TerminationInfo {
         history: tree_diagnostics::HistoryData,
     },
     Int2PtrWithStrictProvenance,
+    /// An access outside the bounds of a capability, with `-Zmiri-cheri`.
+    CapabilityFault {
+        msg: String,
+        bounds_set: Option<SpanData>,
+    },
     Deadlock,
+    Livelock {
+        thread: String,
+        loads: u64,
+    },
+    /// A thread used more stack than it is allowed to.
+    StackOverflow {
+        limit: u64,
+    },
+    /// The limit set with `-Zmiri-max-blocks` or `-Zmiri-timeout` was reached, or the one set with
+    /// `-Zmiri-test-max-blocks` or `-Zmiri-test-timeout` for the test with the given name.
+    BudgetExceeded {
+        budget: String,
+        flag: &'static str,
+        test: Option<String>,
+    },
     MultipleSymbolDefinitions {
         link_name: Symbol,
         first: SpanData,
@@ -47,14 +78,106 @@ pub enum TerminationInfo {
         op2: RacingOp,
         extra: Option<&'static str>,
         retag_explain: bool,
+        /// Notes explaining the race in terms of vector clocks, with `-Zmiri-explain-data-races`.
+        explanation: Vec<(Option<SpanData>, String)>,
     },
     UnsupportedForeignItem(String),
+    /// A test run with `-Zmiri-test` did not panic like its `#[should_panic]` attribute says.
+    ShouldPanicFailed {
+        msg: String,
+        attr: SpanData,
+    },
+}
+
+/// What kind of error made Miri stop the program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MiriErrorKind {
+    /// The program has Undefined Behavior. This includes data races and violations of the
+    /// aliasing model.
+    UndefinedBehavior,
+    /// The program did something that Miri does not support.
+    Unsupported,
+    /// The program ran out of a resource, like its stack or the address space.
+    ResourceExhaustion,
+    /// The program accessed memory outside the bounds of a capability, with `-Zmiri-cheri`.
+    CapabilityFault,
+    /// The program deadlocked or livelocked.
+    Deadlock,
+    /// The program aborted, e.g. because of a panic that could not unwind.
+    Abort,
+    /// The program did not finish within the limit set with `-Zmiri-max-blocks` or
+    /// `-Zmiri-timeout`.
+    BudgetExceeded,
+    /// The program leaked memory, or did not wait for all its threads.
+    Leak,
+    /// Any other error, e.g. a post-monomorphization error or conflicting symbol definitions.
+    Other,
+}
+
+impl MiriErrorKind {
+    pub const ALL: [MiriErrorKind; 9] = [
+        MiriErrorKind::UndefinedBehavior,
+        MiriErrorKind::Unsupported,
+        MiriErrorKind::ResourceExhaustion,
+        MiriErrorKind::CapabilityFault,
+        MiriErrorKind::Deadlock,
+        MiriErrorKind::Abort,
+        MiriErrorKind::BudgetExceeded,
+        MiriErrorKind::Leak,
+        MiriErrorKind::Other,
+    ];
+
+    /// The name of this kind of error in `-Zmiri-error-exit-codes`.
+    pub fn name(self) -> &'static str {
+        match self {
+            MiriErrorKind::UndefinedBehavior => "ub",
+            MiriErrorKind::Unsupported => "unsupported",
+            MiriErrorKind::ResourceExhaustion => "resource-exhaustion",
+            MiriErrorKind::CapabilityFault => "capability-fault",
+            MiriErrorKind::Deadlock => "deadlock",
+            MiriErrorKind::Abort => "abort",
+            MiriErrorKind::BudgetExceeded => "budget-exceeded",
+            MiriErrorKind::Leak => "leak",
+            MiriErrorKind::Other => "other",
+        }
+    }
+
+    /// The exit code for this kind of error with `-Zmiri-error-exit-codes`, unless the user picked
+    /// a different one. These stay clear of the codes that the driver uses otherwise (1 for
+    /// compilation errors, 101 for ICEs) and of the ones that programs commonly exit with (e.g.
+    /// the `sysexits.h` codes 64 to 78, and 128 plus a signal number).
+    pub fn default_exit_code(self) -> i32 {
+        match self {
+            MiriErrorKind::UndefinedBehavior => 80,
+            MiriErrorKind::Unsupported => 81,
+            MiriErrorKind::ResourceExhaustion => 82,
+            MiriErrorKind::CapabilityFault => 83,
+            MiriErrorKind::Deadlock => 84,
+            MiriErrorKind::Abort => 85,
+            MiriErrorKind::BudgetExceeded => 86,
+            MiriErrorKind::Leak => 87,
+            MiriErrorKind::Other => 88,
+        }
+    }
+}
+
+/// An error that made Miri stop the program. The full error has already been emitted as a
+/// diagnostic; this is a summary for tools that embed Miri.
+#[derive(Clone, Debug)]
+pub struct MiriError {
+    pub kind: MiriErrorKind,
+    /// The main message of the diagnostic.
+    pub message: String,
 }
 
 pub struct RacingOp {
     pub action: String,
     pub thread_info: String,
     pub span: SpanData,
+    pub thread: ThreadId,
+    pub size: Size,
+    pub is_write: bool,
+    pub is_atomic: bool,
 }
 
 impl fmt::Display for TerminationInfo {
@@ -69,9 +192,20 @@ impl fmt::Display for TerminationInfo {
                     f,
                     "integer-to-pointer casts and `ptr::with_exposed_provenance` are not supported with `-Zmiri-strict-provenance`"
                 ),
+            CapabilityFault { msg, .. } => write!(f, "{msg}"),
             StackedBorrowsUb { msg, .. } => write!(f, "{msg}"),
             TreeBorrowsUb { title, .. } => write!(f, "{title}"),
             Deadlock => write!(f, "the evaluated program deadlocked"),
+            Livelock { .. } => write!(f, "the evaluated program livelocked"),
+            StackOverflow { limit } =>
+                write!(
+                    f,
+                    "stack overflow in interpreted program: the stack of this thread exceeded {limit} bytes"
+                ),
+            BudgetExceeded { budget, test: None, .. } =>
+                write!(f, "the evaluated program did not finish within {budget}"),
+            BudgetExceeded { budget, test: Some(test), .. } =>
+                write!(f, "test `{test}` did not finish within {budget}"),
             MultipleSymbolDefinitions { link_name, .. } =>
                 write!(f, "multiple definitions of symbol `{link_name}`"),
             SymbolShimClashing { link_name, .. } =>
@@ -87,6 +221,7 @@ impl fmt::Display for TerminationInfo {
                     op2.thread_info
                 ),
             UnsupportedForeignItem(msg) => write!(f, "{msg}"),
+            ShouldPanicFailed { msg, .. } => write!(f, "{msg}"),
         }
     }
 }
@@ -124,12 +259,38 @@ pub enum NonHaltingDiagnostic {
     ProgressReport {
         block_count: u64, // how many basic blocks have been run so far
     },
+    /// We stopped the program this many basic blocks before the error, with
+    /// `-Zmiri-stop-before-error`.
+    StoppedBeforeError {
+        blocks: u64,
+    },
     Int2Ptr {
         details: bool,
     },
     WeakMemoryOutdatedLoad {
         ptr: Pointer,
     },
+    /// The history of a tag, as requested by `miri_get_tag_history`.
+    TagHistory {
+        tag: BorTag,
+        alloc_id: AllocId,
+        events: Vec<(Option<SpanData>, String)>,
+    },
+    /// A wildcard pointer was resolved to an exposed allocation.
+    WildcardResolved {
+        alloc_id: AllocId,
+        addr: Size,
+    },
+    /// An aliasing violation matched a user-provided suppression.
+    SuppressedAliasingViolation {
+        msg: String,
+        pattern: String,
+    },
+    /// The lock acquisition orders of several threads form a cycle.
+    PotentialDeadlock {
+        cycle: String,
+        events: Vec<(Option<SpanData>, String)>,
+    },
 }
 
 /// Level of Miri specific diagnostics
@@ -143,6 +304,31 @@ pub enum DiagLevel {
 /// frames were pruned. If the stacktrace does not have any local frames, we conclude that it must
 /// be pointing to a problem in the Rust runtime itself, and do not prune it at all.
 pub fn prune_stacktrace<'tcx>(
+    stacktrace: Vec<FrameInfo<'tcx>>,
+    machine: &MiriMachine<'tcx>,
+) -> (Vec<FrameInfo<'tcx>>, bool) {
+    let (mut stacktrace, was_pruned) = prune_stacktrace_by_style(stacktrace, machine);
+    // Apply the filters the user asked for. These do not count as pruning, since
+    // `-Zmiri-backtrace=full` would not bring those frames back. We always keep the frame where
+    // the error happened.
+    if !machine.backtrace_exclude.is_empty() {
+        let mut is_first = true;
+        stacktrace.retain(|frame| {
+            let path = machine.tcx.def_path_str(frame.instance.def_id());
+            mem::take(&mut is_first)
+                || !machine
+                    .backtrace_exclude
+                    .iter()
+                    .any(|pattern| helpers::glob_matches(pattern, &path))
+        });
+    }
+    if let Some(max_depth) = machine.backtrace_max_depth {
+        stacktrace.truncate(max_depth.max(1));
+    }
+    (stacktrace, was_pruned)
+}
+
+fn prune_stacktrace_by_style<'tcx>(
     mut stacktrace: Vec<FrameInfo<'tcx>>,
     machine: &MiriMachine<'tcx>,
 ) -> (Vec<FrameInfo<'tcx>>, bool) {
@@ -198,30 +384,68 @@ pub fn prune_stacktrace<'tcx>(
     }
 }
 
+/// Shorten the stacktrace of a stack overflow: if the innermost frames repeat, only show the
+/// first repetition. Returns how many frames were removed.
+fn fold_recursion(stacktrace: &mut Vec<FrameInfo<'_>>) -> usize {
+    // Find the shortest cycle of functions that repeats at least twice at the top of the stack.
+    let Some(period) = (1..=16).find(|&period| {
+        stacktrace.len() >= 2 * period
+            && (0..period).all(|i| stacktrace[i].instance == stacktrace[i + period].instance)
+    }) else {
+        return 0;
+    };
+    // Then find where that cycle ends.
+    let end = (period..stacktrace.len())
+        .find(|&i| stacktrace[i].instance != stacktrace[i - period].instance)
+        .unwrap_or(stacktrace.len());
+    stacktrace.drain(period..end);
+    end - period
+}
+
 /// Emit a custom diagnostic without going through the miri-engine machinery.
 ///
-/// Returns `Some` if this was regular program termination with a given exit code and a `bool` indicating whether a leak check should happen; `None` otherwise.
+/// Returns `Ok` if this was regular program termination with a given exit code and a `bool` indicating whether a leak check should happen; otherwise, returns the error that was reported.
 pub fn report_error<'tcx>(
     ecx: &InterpCx<'tcx, MiriMachine<'tcx>>,
     e: InterpErrorInfo<'tcx>,
-) -> Option<(i64, bool)> {
+) -> Result<(i64, bool), MiriError> {
     use InterpError::*;
     use UndefinedBehaviorInfo::*;
 
     let mut msg = vec![];
 
-    let (title, helps) = if let MachineStop(info) = e.kind() {
+    let (title, kind, code, mut helps) = if let MachineStop(info) = e.kind() {
         let info = info.downcast_ref::<TerminationInfo>().expect("invalid MachineStop payload");
         use TerminationInfo::*;
-        let title = match info {
-            Exit { code, leak_check } => return Some((*code, *leak_check)),
-            Abort(_) => Some("abnormal termination"),
-            UnsupportedInIsolation(_) | Int2PtrWithStrictProvenance | UnsupportedForeignItem(_) =>
-                Some("unsupported operation"),
-            StackedBorrowsUb { .. } | TreeBorrowsUb { .. } | DataRace { .. } =>
-                Some("Undefined Behavior"),
-            Deadlock => Some("deadlock"),
-            MultipleSymbolDefinitions { .. } | SymbolShimClashing { .. } => None,
+        let (title, kind, code) = match info {
+            Exit { code, leak_check } => return Ok((*code, *leak_check)),
+            Abort(_) => (Some("abnormal termination"), MiriErrorKind::Abort, "MIRI-ABORT-001"),
+            UnsupportedInIsolation(_) =>
+                (Some("unsupported operation"), MiriErrorKind::Unsupported, "MIRI-UNSUP-002"),
+            Int2PtrWithStrictProvenance =>
+                (Some("unsupported operation"), MiriErrorKind::Unsupported, "MIRI-UNSUP-003"),
+            UnsupportedForeignItem(_) =>
+                (Some("unsupported operation"), MiriErrorKind::Unsupported, "MIRI-UNSUP-004"),
+            StackedBorrowsUb { .. } =>
+                (Some("Undefined Behavior"), MiriErrorKind::UndefinedBehavior, "MIRI-SB-001"),
+            TreeBorrowsUb { .. } =>
+                (Some("Undefined Behavior"), MiriErrorKind::UndefinedBehavior, "MIRI-TB-001"),
+            DataRace { involves_non_atomic: true, .. } =>
+                (Some("Undefined Behavior"), MiriErrorKind::UndefinedBehavior, "MIRI-RACE-001"),
+            DataRace { involves_non_atomic: false, .. } =>
+                (Some("Undefined Behavior"), MiriErrorKind::UndefinedBehavior, "MIRI-RACE-002"),
+            CapabilityFault { .. } =>
+                (Some("capability fault"), MiriErrorKind::CapabilityFault, "MIRI-CHERI-001"),
+            Deadlock => (Some("deadlock"), MiriErrorKind::Deadlock, "MIRI-DEADLOCK-001"),
+            Livelock { .. } => (Some("livelock"), MiriErrorKind::Deadlock, "MIRI-DEADLOCK-002"),
+            StackOverflow { .. } =>
+                (Some("stack overflow"), MiriErrorKind::ResourceExhaustion, "MIRI-RES-001"),
+            BudgetExceeded { .. } =>
+                (Some("execution budget exceeded"), MiriErrorKind::BudgetExceeded, "MIRI-RES-003"),
+            MultipleSymbolDefinitions { .. } => (None, MiriErrorKind::Other, "MIRI-LINK-001"),
+            SymbolShimClashing { .. } => (None, MiriErrorKind::Other, "MIRI-LINK-002"),
+            ShouldPanicFailed { .. } =>
+                (Some("test failed"), MiriErrorKind::Other, "MIRI-TEST-001"),
         };
         #[rustfmt::skip]
         let helps = match info {
@@ -272,10 +496,21 @@ pub fn report_error<'tcx>(
                 ],
             SymbolShimClashing { link_name, span } =>
                 vec![(Some(*span), format!("the `{link_name}` symbol is defined here"))],
+            ShouldPanicFailed { attr, .. } =>
+                vec![(Some(*attr), format!("the test is marked `#[should_panic]` here"))],
+            CapabilityFault { bounds_set, .. } => {
+                let mut helps = vec![];
+                if let Some(span) = bounds_set {
+                    helps.push((Some(*span), format!("the bounds of the capability were set here")));
+                }
+                helps.push((None, format!("on CHERI hardware, this operation would trap")));
+                helps
+            }
             Int2PtrWithStrictProvenance =>
                 vec![(None, format!("use Strict Provenance APIs (https://doc.rust-lang.org/nightly/std/ptr/index.html#strict-provenance, https://crates.io/crates/sptr) instead"))],
-            DataRace { op1, extra, retag_explain, .. } => {
+            DataRace { op1, extra, retag_explain, explanation, .. } => {
                 let mut helps = vec![(Some(op1.span), format!("and (1) occurred earlier here"))];
+                helps.extend(explanation.iter().cloned());
                 if let Some(extra) = extra {
                     helps.push((None, format!("{extra}")));
                     helps.push((None, format!("see https://doc.rust-lang.org/nightly/std/sync/atomic/index.html#memory-model-for-atomic-accesses for more information about the Rust memory model")));
@@ -290,11 +525,33 @@ pub fn report_error<'tcx>(
                 helps
             }
                 ,
+            Deadlock => {
+                let threads = &ecx.machine.threads;
+                let wait = ecx.machine.sync.describe_wait(threads, threads.active_thread());
+                let mut helps: Vec<_> = wait.into_iter().map(|wait| (None, wait)).collect();
+                helps.extend(ecx.machine.sync.describe_lock_waits(threads));
+                helps
+            }
+            Livelock { thread, loads } =>
+                vec![
+                    (None, format!("thread {thread} loaded the same value from this location {loads} times in a row, without writing to memory, yielding, or calling any foreign function in between")),
+                    (None, format!("no other thread can run and change that value, so this loop would spin forever")),
+                ],
+            StackOverflow { .. } =>
+                vec![
+                    (None, format!("this is most likely caused by unbounded recursion")),
+                    (None, format!("the stack size can be changed with `-Zmiri-stack-size=<bytes>`; note that Miri counts every local variable, so frames are larger than in a compiled program")),
+                ],
+            BudgetExceeded { flag, .. } =>
+                vec![
+                    (None, format!("this limit was set with `{flag}`")),
+                    (None, format!("the program may be stuck in an infinite loop, or it may just need more time")),
+                ],
             _ => vec![],
         };
-        (title, helps)
+        (title, kind, code, helps)
     } else {
-        let title = match e.kind() {
+        let (title, kind) = match e.kind() {
             UndefinedBehavior(ValidationError(validation_err))
                 if matches!(
                     validation_err.kind,
@@ -307,18 +564,18 @@ pub fn report_error<'tcx>(
                     format_interp_error(ecx.tcx.dcx(), e)
                 );
             }
-            UndefinedBehavior(_) => "Undefined Behavior",
-            ResourceExhaustion(_) => "resource exhaustion",
+            UndefinedBehavior(_) => ("Undefined Behavior", MiriErrorKind::UndefinedBehavior),
+            ResourceExhaustion(_) => ("resource exhaustion", MiriErrorKind::ResourceExhaustion),
             Unsupported(
                 // We list only the ones that can actually happen.
                 UnsupportedOpInfo::Unsupported(_)
                 | UnsupportedOpInfo::UnsizedLocal
                 | UnsupportedOpInfo::ExternTypeField,
-            ) => "unsupported operation",
+            ) => ("unsupported operation", MiriErrorKind::Unsupported),
             InvalidProgram(
                 // We list only the ones that can actually happen.
                 InvalidProgramInfo::AlreadyReported(_) | InvalidProgramInfo::Layout(..),
-            ) => "post-monomorphization error",
+            ) => ("post-monomorphization error", MiriErrorKind::Other),
             _ => {
                 ecx.handle_ice(); // print interpreter backtrace (this is outside the eval `catch_unwind`)
                 bug!(
@@ -327,6 +584,38 @@ pub fn report_error<'tcx>(
                 );
             }
         };
+        let code = match e.kind() {
+            UndefinedBehavior(
+                PointerUseAfterFree(..) | PointerOutOfBounds { .. } | DanglingIntPointer { .. },
+            ) => "MIRI-UB-001",
+            UndefinedBehavior(AlignmentCheckFailed { .. }) => "MIRI-UB-002",
+            UndefinedBehavior(
+                ValidationError(_)
+                | InvalidBool(_)
+                | InvalidChar(_)
+                | InvalidTag(_)
+                | InvalidFunctionPointer(_)
+                | InvalidVTablePointer(_)
+                | InvalidStr(_),
+            ) => "MIRI-UB-003",
+            UndefinedBehavior(InvalidUninitBytes(_)) => "MIRI-UB-004",
+            UndefinedBehavior(AbiMismatchArgument { .. } | AbiMismatchReturn { .. }) =>
+                "MIRI-UB-005",
+            UndefinedBehavior(WriteToReadOnly(_)) => "MIRI-UB-006",
+            UndefinedBehavior(
+                DivisionByZero
+                | RemainderByZero
+                | DivisionOverflow
+                | RemainderOverflow
+                | ArithOverflow { .. }
+                | ShiftOverflow { .. },
+            ) => "MIRI-UB-007",
+            UndefinedBehavior(_) => "MIRI-UB-000",
+            ResourceExhaustion(_) => "MIRI-RES-002",
+            Unsupported(_) => "MIRI-UNSUP-001",
+            InvalidProgram(_) => "MIRI-MONO-001",
+            MachineStop(_) => unreachable!(),
+        };
         #[rustfmt::skip]
         let helps = match e.kind() {
             Unsupported(_) =>
@@ -366,18 +655,22 @@ pub fn report_error<'tcx>(
                 InvalidProgramInfo::AlreadyReported(_)
             ) => {
                 // This got already reported. No point in reporting it again.
-                return None;
+                return Err(MiriError {
+                    kind,
+                    message: format!("{title}: the error was already reported"),
+                });
             }
             _ =>
                 vec![],
         };
-        (Some(title), helps)
+        (Some(title), kind, code, helps)
     };
 
     let stacktrace = ecx.generate_stacktrace();
-    let (stacktrace, mut any_pruned) = prune_stacktrace(stacktrace, &ecx.machine);
+    let (mut stacktrace, mut any_pruned) = prune_stacktrace(stacktrace, &ecx.machine);
 
     let mut show_all_threads = false;
+    let mut show_other_threads = false;
 
     // We want to dump the allocation if this is `InvalidUninitBytes`.
     // Since `format_interp_error` consumes `e`, we compute the outut early.
@@ -398,17 +691,44 @@ pub fn report_error<'tcx>(
                 TerminationInfo::Deadlock => {
                     show_all_threads = true;
                 }
+                TerminationInfo::BudgetExceeded { .. } => {
+                    show_other_threads = true;
+                }
+                TerminationInfo::StackOverflow { .. } => {
+                    let hidden = fold_recursion(&mut stacktrace);
+                    if hidden > 0 {
+                        helps.push((
+                            None,
+                            format!("{hidden} frames of the recursion are not shown"),
+                        ));
+                    }
+                }
+                TerminationInfo::DataRace { ptr, op1, op2, .. }
+                    if ecx.machine.race_report == RaceReportFormat::Tsan =>
+                {
+                    write_tsan_race_report(&mut extra, ecx, *ptr, op1, op2, &stacktrace);
+                }
                 _ => {}
             }
         }
         _ => {}
     }
 
+    helps.push((None, explain_hint(code)));
+
     msg.insert(0, format_interp_error(ecx.tcx.dcx(), e));
+    let error = MiriError {
+        kind,
+        message: if let Some(title) = title {
+            format!("{title}: {}", msg[0])
+        } else {
+            msg[0].clone()
+        },
+    };
 
     report_msg(
         DiagLevel::Error,
-        if let Some(title) = title { format!("{title}: {}", msg[0]) } else { msg[0].clone() },
+        error.message.clone(),
         msg,
         vec![],
         helps,
@@ -417,7 +737,8 @@ pub fn report_error<'tcx>(
         &ecx.machine,
     );
 
-    eprint!("{extra}"); // newlines are already in the string
+    // Newlines are already in the string.
+    let _ignore = ecx.machine.write_to_stderr(extra.as_bytes());
 
     if show_all_threads {
         for (thread, stack) in ecx.machine.threads.all_stacks() {
@@ -425,12 +746,13 @@ pub fn report_error<'tcx>(
                 let stacktrace = Frame::generate_stacktrace_from_stack(stack);
                 let (stacktrace, was_pruned) = prune_stacktrace(stacktrace, &ecx.machine);
                 any_pruned |= was_pruned;
+                let wait = ecx.machine.sync.describe_wait(&ecx.machine.threads, thread);
                 report_msg(
                     DiagLevel::Error,
                     format!("deadlock: the evaluated program deadlocked"),
                     vec![format!("the evaluated program deadlocked")],
                     vec![],
-                    vec![],
+                    wait.into_iter().map(|wait| (None, wait)).collect(),
                     &stacktrace,
                     Some(thread),
                     &ecx.machine,
@@ -438,10 +760,30 @@ pub fn report_error<'tcx>(
             }
         }
     }
+    if show_other_threads {
+        let threads = &ecx.machine.threads;
+        for (thread, stack) in threads.all_stacks() {
+            if thread != ecx.active_thread() && !threads.has_terminated(thread) {
+                let stacktrace = Frame::generate_stacktrace_from_stack(stack);
+                let (stacktrace, was_pruned) = prune_stacktrace(stacktrace, &ecx.machine);
+                any_pruned |= was_pruned;
+                report_msg(
+                    DiagLevel::Note,
+                    format!("thread {} was here", threads.get_thread_diagnostic_name(thread)),
+                    vec![format!("here")],
+                    vec![],
+                    vec![],
+                    &stacktrace,
+                    Some(thread),
+                    &ecx.machine,
+                );
+            }
+        }
+    }
 
     // Include a note like `std` does when we omit frames from a backtrace
     if any_pruned {
-        ecx.tcx.dcx().note(
+        ecx.machine.dcx().note(
             "some details are omitted, run with `MIRIFLAGS=-Zmiri-backtrace=full` for a verbose backtrace",
         );
     }
@@ -456,23 +798,166 @@ pub fn report_error<'tcx>(
         }
     }
 
-    None
+    Err(error)
+}
+
+/// An allocation that leaked, as returned by `find_leaked_allocations`.
+pub type LeakedAllocation<'tcx> =
+    (AllocId, MemoryKind, Allocation<Provenance, AllocExtra<'tcx>, MiriAllocBytes>);
+
+/// How a leaked allocation can still be found, similar to the categories of Valgrind's memcheck.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LeakReachability {
+    /// Not pointed to by any other leaked allocation, or picked as the root of a cycle of leaked
+    /// allocations (like an `Rc` cycle) that nothing else points to.
+    Definite,
+    /// Only pointed to by other leaked allocations that are not reachable.
+    Indirect,
+    /// Reachable from memory that did not leak, e.g. a thread-local of the main thread.
+    StillReachable,
+}
+
+/// Remove the leaks that match one of the user-provided `suppressions`: their kind matches, and a
+/// function matching the pattern was on the stack when they were allocated.
+pub fn suppress_leaks<'tcx>(
+    ecx: &InterpCx<'tcx, MiriMachine<'tcx>>,
+    leaks: &mut Vec<LeakedAllocation<'tcx>>,
+    suppressions: &[(String, Option<MiriMemoryKind>)],
+) {
+    if suppressions.is_empty() {
+        return;
+    }
+    let before = leaks.len();
+    leaks.retain(|(_, kind, alloc)| {
+        let Some(backtrace) = &alloc.extra.backtrace else { return true };
+        !suppressions.iter().any(|(pattern, suppressed_kind)| {
+            suppressed_kind.is_none_or(|suppressed_kind| *kind == suppressed_kind.into())
+                && backtrace.iter().any(|frame| {
+                    let path = ecx.tcx.def_path_str(frame.instance.def_id());
+                    helpers::glob_matches(pattern, &path)
+                })
+        })
+    });
+    let suppressed = before - leaks.len();
+    if suppressed > 0 {
+        ecx.machine.dcx().note(format!(
+            "{suppressed} leaked allocation(s) were not reported since they match a leak suppression"
+        ));
+    }
+}
+
+/// Determine how each of the leaked allocations can still be found. Pointers that an allocation
+/// holds to itself do not count.
+pub fn classify_leaks<'tcx>(
+    ecx: &InterpCx<'tcx, MiriMachine<'tcx>>,
+    mut leaks: Vec<LeakedAllocation<'tcx>>,
+) -> Vec<(LeakedAllocation<'tcx>, LeakReachability)> {
+    fn targets<'a>(
+        alloc: &'a Allocation<Provenance, AllocExtra<'_>, MiriAllocBytes>,
+    ) -> impl Iterator<Item = AllocId> + 'a {
+        alloc.provenance().provenances().filter_map(|prov| prov.get_alloc_id())
+    }
+
+    // Report the leaks in the order they were allocated, and prefer the earliest allocation as
+    // the root of a cycle.
+    leaks.sort_by_key(|(id, ..)| *id);
+    let leaked: FxHashMap<AllocId, usize> =
+        leaks.iter().enumerate().map(|(idx, (id, ..))| (*id, idx)).collect();
+    // Find the leaks that can be reached from the allocations that did not leak.
+    let mut todo: Vec<AllocId> = ecx.memory.alloc_map().iter(|it| {
+        it.filter(|(id, _)| !leaked.contains_key(*id))
+            .flat_map(|(_, (_, alloc))| targets(alloc))
+            .collect()
+    });
+    let mut reachable = FxHashSet::default();
+    while let Some(id) = todo.pop() {
+        if let Some(&idx) = leaked.get(&id)
+            && reachable.insert(idx)
+        {
+            todo.extend(targets(&leaks[idx].2));
+        }
+    }
+    // Among the others, each strongly connected component that no other leak points to gets one
+    // allocation as its root, which is leaked definitely; everything else is leaked indirectly.
+    // Visiting the leaks by decreasing DFS finish time, each one that is not reachable from the
+    // roots we found so far is in such a component.
+    let edges: Vec<Vec<usize>> = leaks
+        .iter()
+        .map(|(id, _, alloc)| {
+            targets(alloc)
+                .filter(|target| target != id)
+                .filter_map(|target| leaked.get(&target).copied())
+                .filter(|target| !reachable.contains(target))
+                .collect()
+        })
+        .collect();
+    let mut finished = Vec::new();
+    let mut visited = FxHashSet::default();
+    for start in (0..leaks.len()).filter(|idx| !reachable.contains(idx)) {
+        if !visited.insert(start) {
+            continue;
+        }
+        let mut stack = vec![(start, edges[start].iter())];
+        while let Some((idx, children)) = stack.last_mut() {
+            if let Some(&child) = children.next() {
+                if visited.insert(child) {
+                    stack.push((child, edges[child].iter()));
+                }
+            } else {
+                finished.push(*idx);
+                stack.pop();
+            }
+        }
+    }
+    let mut definite = FxHashSet::default();
+    let mut covered = FxHashSet::default();
+    for &root in finished.iter().rev() {
+        if !covered.insert(root) {
+            continue;
+        }
+        definite.insert(root);
+        let mut todo = edges[root].clone();
+        while let Some(idx) = todo.pop() {
+            if covered.insert(idx) {
+                todo.extend_from_slice(&edges[idx]);
+            }
+        }
+    }
+    leaks
+        .into_iter()
+        .enumerate()
+        .map(|(idx, leak)| {
+            let reachability = if reachable.contains(&idx) {
+                LeakReachability::StillReachable
+            } else if definite.contains(&idx) {
+                LeakReachability::Definite
+            } else {
+                LeakReachability::Indirect
+            };
+            (leak, reachability)
+        })
+        .collect()
 }
 
 pub fn report_leaks<'tcx>(
     ecx: &InterpCx<'tcx, MiriMachine<'tcx>>,
-    leaks: Vec<(AllocId, MemoryKind, Allocation<Provenance, AllocExtra<'tcx>, MiriAllocBytes>)>,
+    leaks: Vec<(LeakedAllocation<'tcx>, LeakReachability)>,
 ) {
     let mut any_pruned = false;
-    for (id, kind, mut alloc) in leaks {
+    for ((id, kind, mut alloc), reachability) in leaks {
+        let how = match reachability {
+            LeakReachability::Definite => "",
+            LeakReachability::Indirect => " (only referenced by other leaked memory)",
+            LeakReachability::StillReachable => " (still reachable from memory that did not leak)",
+        };
         let mut title = format!(
-            "memory leaked: {id:?} ({}, size: {:?}, align: {:?})",
+            "memory leaked{how}: {id:?} ({}, size: {:?}, align: {:?})",
             kind,
             alloc.size().bytes(),
             alloc.align.bytes()
         );
         let Some(backtrace) = alloc.extra.backtrace.take() else {
-            ecx.tcx.dcx().err(title);
+            ecx.machine.dcx().err(title);
             continue;
         };
         title.push_str(", allocated here:");
@@ -490,7 +975,7 @@ pub fn report_leaks<'tcx>(
         );
     }
     if any_pruned {
-        ecx.tcx.dcx().note(
+        ecx.machine.dcx().note(
             "some details are omitted, run with `MIRIFLAGS=-Zmiri-backtrace=full` for a verbose backtrace",
         );
     }
@@ -518,9 +1003,14 @@ pub fn report_msg<'tcx>(
         DiagLevel::Warning => Level::Warning,
         DiagLevel::Note => Level::Note,
     };
-    let mut err = Diag::<()>::new(sess.dcx(), level, title);
+    let mut err = Diag::<()>::new(machine.dcx(), level, renumber_allocs(title));
     err.span(span);
 
+    // With `-Zmiri-strict-determinism`, show allocation numbers instead of `AllocId`s everywhere.
+    let span_msg = span_msg.into_iter().map(renumber_allocs);
+    let notes = notes.into_iter().map(|(span, note)| (span, renumber_allocs(note)));
+    let helps = helps.into_iter().map(|(span, help)| (span, renumber_allocs(help)));
+
     // Show main message.
     if span != DUMMY_SP {
         for line in span_msg {
@@ -562,7 +1052,8 @@ pub fn report_msg<'tcx>(
         let thread_name = machine.threads.get_thread_display_name(thread);
         if thread_name != "main" {
             // Only print thread name if it is not `main`.
-            write!(backtrace_title, " on thread `{thread_name}`").unwrap();
+            let thread_name = machine.threads.get_thread_diagnostic_name(thread);
+            write!(backtrace_title, " on thread {thread_name}").unwrap();
         };
     }
     write!(backtrace_title, ":").unwrap();
@@ -582,6 +1073,225 @@ pub fn report_msg<'tcx>(
     err.emit();
 }
 
+/// The output of a program that runs with `MiriConfig::captured_output`: what it writes to stdout
+/// and stderr, and the diagnostics that Miri emits while running it.
+#[derive(Clone, Debug, Default)]
+pub struct CapturedOutput {
+    pub stdout: Arc<Mutex<Vec<u8>>>,
+    pub stderr: Arc<Mutex<Vec<u8>>>,
+    pub diagnostics: Arc<Mutex<Vec<DiagInner>>>,
+}
+
+impl CapturedOutput {
+    /// A `DiagCtxt` whose diagnostics end up in `self.diagnostics` instead of being printed.
+    pub(crate) fn dcx(&self, tcx: TyCtxt<'_>) -> DiagCtxt {
+        let fallback_bundle = rustc_errors::fallback_fluent_bundle(
+            rustc_driver::DEFAULT_LOCALE_RESOURCES.to_vec(),
+            false,
+        );
+        DiagCtxt::new(Box::new(CapturingEmitter {
+            diagnostics: Arc::clone(&self.diagnostics),
+            source_map: tcx.sess.psess.clone_source_map(),
+            fallback_bundle,
+        }))
+    }
+
+    /// Print what was captured, and emit the captured diagnostics to `dcx`.
+    pub fn print(&self, dcx: &DiagCtxt) {
+        // Errors writing to the host's stdout and stderr are ignored, like for the program's own
+        // output.
+        let _ignore = std::io::stdout().lock().write_all(&self.stdout.lock().unwrap());
+        let _ignore = std::io::stderr().lock().write_all(&self.stderr.lock().unwrap());
+        for diag in self.diagnostics.lock().unwrap().drain(..) {
+            dcx.emit_diagnostic(diag);
+        }
+    }
+}
+
+/// Collects the diagnostics of a `DiagCtxt` created by `CapturedOutput::dcx`.
+struct CapturingEmitter {
+    diagnostics: Arc<Mutex<Vec<DiagInner>>>,
+    source_map: Lrc<SourceMap>,
+    fallback_bundle: LazyFallbackBundle,
+}
+
+impl Translate for CapturingEmitter {
+    fn fluent_bundle(&self) -> Option<&Lrc<FluentBundle>> {
+        None
+    }
+
+    fn fallback_fluent_bundle(&self) -> &FluentBundle {
+        &self.fallback_bundle
+    }
+}
+
+impl Emitter for CapturingEmitter {
+    fn emit_diagnostic(&mut self, diag: DiagInner) {
+        self.diagnostics.lock().unwrap().push(diag);
+    }
+
+    fn source_map(&self) -> Option<&Lrc<SourceMap>> {
+        Some(&self.source_map)
+    }
+}
+
+/// Report the threads that are still blocked after the main thread terminated: what each of them
+/// is waiting for, and where.
+pub fn report_blocked_threads<'tcx>(ecx: &InterpCx<'tcx, MiriMachine<'tcx>>) {
+    let threads = &ecx.machine.threads;
+    let mut any_pruned = false;
+    for (thread, stack) in threads.all_stacks() {
+        let Some(wait) = ecx.machine.sync.describe_wait(threads, thread) else { continue };
+        let stacktrace = Frame::generate_stacktrace_from_stack(stack);
+        let (stacktrace, was_pruned) = prune_stacktrace(stacktrace, &ecx.machine);
+        any_pruned |= was_pruned;
+        report_msg(
+            DiagLevel::Note,
+            format!("thread {} is still blocked", threads.get_thread_diagnostic_name(thread)),
+            vec![format!("blocked here")],
+            vec![],
+            vec![(None, wait)],
+            &stacktrace,
+            Some(thread),
+            &ecx.machine,
+        );
+    }
+    if any_pruned {
+        ecx.machine.dcx().note(
+            "some details are omitted, run with `MIRIFLAGS=-Zmiri-backtrace=full` for a verbose backtrace",
+        );
+    }
+}
+
+/// Report where each of the lock acquisitions of a potential deadlock happened: one note with a
+/// backtrace per acquisition.
+pub fn report_lock_acquisitions<'tcx>(
+    machine: &MiriMachine<'tcx>,
+    acquisitions: Vec<(String, ThreadId, Vec<FrameInfo<'tcx>>)>,
+) {
+    let mut any_pruned = false;
+    for (title, thread, backtrace) in acquisitions {
+        let (backtrace, pruned) = prune_stacktrace(backtrace, machine);
+        any_pruned |= pruned;
+        report_msg(DiagLevel::Note, title, vec![], vec![], vec![], &backtrace, Some(thread), machine);
+    }
+    if any_pruned {
+        machine.dcx().note(
+            "some details are omitted, run with `MIRIFLAGS=-Zmiri-backtrace=full` for a verbose backtrace",
+        );
+    }
+}
+
+/// Report the threads that terminated without being joined or detached. Returns whether there
+/// were any.
+pub fn report_unjoined_threads<'tcx>(ecx: &mut InterpCx<'tcx, MiriMachine<'tcx>>) -> bool {
+    let unjoined = ecx.machine.threads.take_unjoined_threads();
+    let any_unjoined = !unjoined.is_empty();
+    let mut any_pruned = false;
+    for (thread, backtrace) in unjoined {
+        let mut title = format!(
+            "thread {} was never joined or detached",
+            ecx.machine.threads.get_thread_diagnostic_name(thread)
+        );
+        let Some(backtrace) = backtrace else {
+            ecx.machine.dcx().err(title);
+            continue;
+        };
+        title.push_str(", spawned here:");
+        let (backtrace, pruned) = prune_stacktrace(backtrace, &ecx.machine);
+        any_pruned |= pruned;
+        report_msg(DiagLevel::Error, title, vec![], vec![], vec![], &backtrace, None, &ecx.machine);
+    }
+    if any_pruned {
+        ecx.machine.dcx().note(
+            "some details are omitted, run with `MIRIFLAGS=-Zmiri-backtrace=full` for a verbose backtrace",
+        );
+    }
+    any_unjoined
+}
+
+/// Describe a data race in the format of ThreadSanitizer reports, so that tools that consume those
+/// can also consume Miri's findings.
+fn write_tsan_race_report<'tcx>(
+    out: &mut String,
+    ecx: &InterpCx<'tcx, MiriMachine<'tcx>>,
+    ptr: interpret::Pointer<AllocId>,
+    op1: &RacingOp,
+    op2: &RacingOp,
+    stacktrace: &[FrameInfo<'tcx>],
+) {
+    let sm = ecx.tcx.sess.source_map();
+    let threads = &ecx.machine.threads;
+    let thread_desc = |thread: ThreadId| {
+        if thread == ThreadId::MAIN_THREAD {
+            "main thread".to_owned()
+        } else {
+            format!("thread T{}", thread.to_u32())
+        }
+    };
+    let (alloc_id, offset) = ptr.into_parts();
+    let addr = match ecx.machine.known_base_addr(alloc_id) {
+        Some(base) => format!("{:#x}", base.wrapping_add(offset.bytes())),
+        // The allocation was never assigned an address; describe the location symbolically.
+        None => format!("{alloc_id:?}+{:#x}", offset.bytes()),
+    };
+    let access = |op: &RacingOp| {
+        let kind = match (op.is_atomic, op.is_write) {
+            (true, true) => "atomic write",
+            (true, false) => "atomic read",
+            (false, true) => "write",
+            (false, false) => "read",
+        };
+        format!("{kind} of size {} at {addr} by {}", op.size.bytes(), thread_desc(op.thread))
+    };
+    let current = access(op2);
+
+    writeln!(out, "==================").unwrap();
+    writeln!(out, "WARNING: ThreadSanitizer: data race (pid={})", ecx.machine.process_id).unwrap();
+    writeln!(out, "  {}{}:", current[..1].to_uppercase(), &current[1..]).unwrap();
+    for (idx, frame) in stacktrace.iter().enumerate() {
+        writeln!(out, "    #{idx} {} {}", frame.instance, sm.span_to_embeddable_string(frame.span))
+            .unwrap();
+    }
+    writeln!(out).unwrap();
+    writeln!(out, "  Previous {}:", access(op1)).unwrap();
+    // We only know where the earlier access happened, not its full stack.
+    writeln!(out, "    #0 <null> {}", sm.span_to_embeddable_string(op1.span.span())).unwrap();
+
+    let mut involved = vec![op2.thread, op1.thread];
+    involved.dedup();
+    for thread in involved {
+        let Some((parent, span)) = threads.get_thread_spawn_site(thread) else { continue };
+        let name = match threads.get_thread_name(thread) {
+            Some(name) => format!(" '{}'", String::from_utf8_lossy(name)),
+            None => String::new(),
+        };
+        let state = if threads.has_terminated(thread) { "finished" } else { "running" };
+        writeln!(out).unwrap();
+        writeln!(
+            out,
+            "  Thread T{}{name} (tid={}, {state}) created by {} at:",
+            thread.to_u32(),
+            thread.to_u32(),
+            thread_desc(parent),
+        )
+        .unwrap();
+        writeln!(out, "    #0 <null> {}", sm.span_to_embeddable_string(span)).unwrap();
+    }
+
+    writeln!(out).unwrap();
+    if let Some(frame) = stacktrace.first() {
+        writeln!(
+            out,
+            "SUMMARY: ThreadSanitizer: data race {} in {}",
+            sm.span_to_embeddable_string(frame.span),
+            frame.instance,
+        )
+        .unwrap();
+    }
+    writeln!(out, "==================").unwrap();
+}
+
 impl<'tcx> MiriMachine<'tcx> {
     pub fn emit_diagnostic(&self, e: NonHaltingDiagnostic) {
         use NonHaltingDiagnostic::*;
@@ -602,6 +1312,13 @@ impl<'tcx> MiriMachine<'tcx> {
             | ProgressReport { .. }
             | WeakMemoryOutdatedLoad { .. } =>
                 ("tracking was triggered".to_string(), DiagLevel::Note),
+            StoppedBeforeError { .. } => ("stopped before error".to_string(), DiagLevel::Note),
+            TagHistory { .. } => ("tag history".to_string(), DiagLevel::Note),
+            SuppressedAliasingViolation { .. } =>
+                ("suppressed aliasing violation".to_string(), DiagLevel::Warning),
+            WildcardResolved { .. } =>
+                ("wildcard pointer resolved".to_string(), DiagLevel::Warning),
+            PotentialDeadlock { .. } => ("potential deadlock".to_string(), DiagLevel::Warning),
         };
 
         let msg = match &e {
@@ -627,9 +1344,20 @@ impl<'tcx> MiriMachine<'tcx> {
                 format!("{op} was made to return an error due to isolation"),
             ProgressReport { .. } =>
                 format!("progress report: current operation being executed is here"),
+            StoppedBeforeError { blocks } =>
+                format!("this is where the program was {blocks} basic blocks before the error"),
             Int2Ptr { .. } => format!("integer-to-pointer cast"),
             WeakMemoryOutdatedLoad { ptr } =>
                 format!("weak memory emulation: outdated value returned from load at {ptr}"),
+            TagHistory { tag, alloc_id, .. } => format!("history of {tag:?} in {alloc_id:?}"),
+            SuppressedAliasingViolation { msg, .. } => msg.clone(),
+            WildcardResolved { alloc_id, addr } =>
+                format!(
+                    "pointer with wildcard provenance to address {addr:#x} resolved to {alloc_id:?}",
+                    addr = addr.bytes(),
+                ),
+            PotentialDeadlock { cycle, .. } =>
+                format!("lock acquisition order forms a cycle: {cycle}"),
         };
 
         let notes = match &e {
@@ -638,6 +1366,26 @@ impl<'tcx> MiriMachine<'tcx> {
                 // identical diagnostics are being deduplicated.
                 vec![(None, format!("so far, {block_count} basic blocks have been executed"))]
             }
+            StoppedBeforeError { .. } =>
+                vec![(None, format!("the program was run again from the start to get here"))],
+            TagHistory { events, .. } | PotentialDeadlock { events, .. } => events.clone(),
+            SuppressedAliasingViolation { pattern, .. } =>
+                vec![
+                    (None, format!("this violation is suppressed by the pattern `{pattern}`")),
+                    (
+                        None,
+                        format!(
+                            "execution continues, but aliasing checks may be inaccurate from here on"
+                        ),
+                    ),
+                ],
+            WildcardResolved { .. } =>
+                vec![(
+                    None,
+                    format!(
+                        "this pointer was created by an integer-to-pointer cast, so Miri had to pick among all exposed allocations"
+                    ),
+                )],
             _ => vec![],
         };
 
@@ -698,6 +1446,29 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         this.machine.emit_diagnostic(e);
     }
 
+    /// Report how far the program got, as a diagnostic or (with `-Zmiri-report-progress-json`)
+    /// as a line of JSON.
+    fn report_progress(&self) {
+        let this = self.eval_context_ref();
+        let block_count = this.machine.basic_block_count;
+        let Some(mut out) = this.machine.progress_json.as_ref() else {
+            this.emit_diagnostic(NonHaltingDiagnostic::ProgressReport { block_count });
+            return;
+        };
+        let source_map = this.tcx.sess.source_map();
+        let frame = this.active_thread_stack().last();
+        let report = serde_json::json!({
+            "block_count": block_count,
+            "thread": this.machine.threads.get_thread_display_name(this.active_thread()),
+            "function": frame.map(|frame| frame.instance.to_string()),
+            "span": frame.map(|frame| source_map.span_to_embeddable_string(frame.current_span())),
+            "user_span": source_map.span_to_embeddable_string(this.machine.current_span()),
+        });
+        // Write the whole line at once, so that tools reading the file never see half a report.
+        // If the file cannot be written, there is nothing useful we can do about it.
+        let _ = out.write_all(format!("{report}\n").as_bytes());
+    }
+
     /// We had a panic in Miri itself, try to print something useful.
     fn handle_ice(&self) {
         eprintln!();
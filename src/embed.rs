@@ -0,0 +1,148 @@
+//! An API for tools that want to run programs in Miri (fuzzers, test orchestrators, research
+//! prototypes, ...) without going through the `miri` binary and scraping its output.
+//!
+//! Like the rest of Miri, this is tied to the nightly compiler it is built with, so it is only
+//! "stable" in the sense that we try not to change it without reason.
+
+use std::path::PathBuf;
+
+use rustc_data_structures::sync::Lrc;
+use rustc_driver::Compilation;
+use rustc_hir::def_id::{DefId, LOCAL_CRATE};
+use rustc_interface::interface::{Compiler, Config};
+use rustc_interface::Queries;
+use rustc_middle::middle::exported_symbols::ExportedSymbol;
+use rustc_middle::ty::{Ty, TyCtxt};
+use rustc_middle::util::Providers;
+use rustc_session::config::{CrateType, EntryFnType};
+use rustc_session::search_paths::PathKind;
+
+use crate::*;
+
+/// Hooks into [`run_program`].
+pub trait MiriCallbacks: Send {
+    /// Called once the program was compiled, right before Miri starts running it. This can inspect
+    /// the program, and adjust the configuration.
+    fn before_run<'tcx>(&mut self, _tcx: TyCtxt<'tcx>, _config: &mut MiriConfig) {}
+
+    /// Called once Miri is done running the program.
+    fn after_run<'tcx>(&mut self, _tcx: TyCtxt<'tcx>, _outcome: &MiriOutcome) {}
+}
+
+impl MiriCallbacks for () {}
+
+/// How running a program in Miri ended.
+#[derive(Clone, Debug)]
+pub enum MiriOutcome {
+    /// The program ran to completion with the given exit code.
+    Exit(i64),
+    /// Miri stopped the program because of an error.
+    Error(MiriError),
+    /// The program failed to compile, or it is not something Miri can run (e.g., it has no entry
+    /// point).
+    CompilationFailed,
+}
+
+/// Run a program in Miri.
+///
+/// `args` are the arguments for the compiler, starting with the name of the binary, just like the
+/// `miri` binary takes them, except that there are no `-Zmiri` flags: those settings are taken from
+/// `config` instead. `--sysroot` has to point to a standard library that was built for Miri (see
+/// `cargo miri setup`).
+///
+/// All diagnostics are still emitted to stderr; the outcome summarizes them. Unlike the `miri`
+/// binary, this never exits the process.
+pub fn run_program(
+    mut args: Vec<String>,
+    config: MiriConfig,
+    callbacks: impl MiriCallbacks,
+) -> MiriOutcome {
+    args.splice(1..1, MIRI_DEFAULT_ARGS.iter().map(ToString::to_string));
+    let mut compiler_calls = EmbeddedCompilerCalls { config, callbacks, outcome: None };
+    // If compilation fails, the diagnostics have already been emitted, and there is no outcome.
+    let _ = rustc_driver::catch_fatal_errors(|| {
+        rustc_driver::RunCompiler::new(&args, &mut compiler_calls).run()
+    });
+    compiler_calls.outcome.unwrap_or(MiriOutcome::CompilationFailed)
+}
+
+struct EmbeddedCompilerCalls<C> {
+    config: MiriConfig,
+    callbacks: C,
+    outcome: Option<MiriOutcome>,
+}
+
+impl<C: MiriCallbacks> rustc_driver::Callbacks for EmbeddedCompilerCalls<C> {
+    fn config(&mut self, config: &mut Config) {
+        override_queries(config);
+    }
+
+    fn after_analysis<'tcx>(&mut self, _: &Compiler, queries: &'tcx Queries<'tcx>) -> Compilation {
+        queries.global_ctxt().unwrap().enter(|tcx| {
+            if tcx.dcx().has_errors_or_delayed_bugs().is_some() {
+                return;
+            }
+            if !tcx.crate_types().contains(&CrateType::Executable) {
+                tcx.dcx().err("miri only makes sense on bin crates");
+                return;
+            }
+            let (entry_def_id, entry_type) = entry_fn(tcx);
+            let mut config = self.config.clone();
+            config.args.insert(0, tcx.sess.io.input.filestem().to_string());
+            self.callbacks.before_run(tcx, &mut config);
+            let outcome = match eval_entry(tcx, entry_def_id, entry_type, config) {
+                Ok(return_code) => MiriOutcome::Exit(return_code),
+                Err(error) => MiriOutcome::Error(error),
+            };
+            self.callbacks.after_run(tcx, &outcome);
+            self.outcome = Some(outcome);
+        });
+        Compilation::Stop
+    }
+}
+
+/// Adjust the queries of the compiler for running programs in Miri.
+pub fn override_queries(config: &mut Config) {
+    config.override_queries = Some(|_, providers| {
+        providers.extern_queries.used_crate_source = |tcx, cnum| {
+            let mut providers = Providers::default();
+            rustc_metadata::provide(&mut providers);
+            let mut crate_source = (providers.extern_queries.used_crate_source)(tcx, cnum);
+            // HACK: rustc will emit "crate ... required to be available in rlib format, but
+            // was not found in this form" errors once we use `tcx.dependency_formats()` if
+            // there's no rlib provided, so setting a dummy path here to workaround those errors.
+            Lrc::make_mut(&mut crate_source).rlib = Some((PathBuf::new(), PathKind::All));
+            crate_source
+        };
+    });
+}
+
+/// Find the function to start the program with. Programs without a `main` function (e.g. embedded
+/// programs with `#![no_main]`, whose entry point depends on the runtime of their target) can
+/// export a `miri_start` function instead, which is called like a `#[start]` function.
+pub fn entry_fn(tcx: TyCtxt<'_>) -> (DefId, EntryFnType) {
+    if let Some(entry_def) = tcx.entry_fn(()) {
+        return entry_def;
+    }
+    let miri_start = tcx.exported_symbols(LOCAL_CRATE).iter().find_map(|(sym, _)| {
+        match sym {
+            ExportedSymbol::NonGeneric(def_id)
+                if sym.symbol_name_for_local_instance(tcx).name == "miri_start" =>
+                Some(*def_id),
+            _ => None,
+        }
+    });
+    let Some(def_id) = miri_start else {
+        tcx.dcx().fatal(
+            "miri can only run programs that have a main function, or that export a `miri_start` function",
+        );
+    };
+    let sig = tcx.fn_sig(def_id).instantiate_identity().skip_binder();
+    let argv = Ty::new_imm_ptr(tcx, Ty::new_imm_ptr(tcx, tcx.types.u8));
+    if sig.inputs() != [tcx.types.isize, argv] || sig.output() != tcx.types.isize {
+        tcx.dcx().fatal(
+            "`miri_start` must have the signature `fn(argc: isize, argv: *const *const u8) -> isize`",
+        );
+    }
+    (def_id, EntryFnType::Start)
+}
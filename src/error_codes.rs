@@ -0,0 +1,60 @@
+//! Stable codes for the kinds of errors Miri reports, like rustc's `E0080`, and their extended
+//! explanations, shown by `miri --explain <code>`. As in rustc, each explanation lives in its own
+//! Markdown file in `error_codes/`.
+
+macro_rules! error_codes {
+    ($($code:literal,)*) => {
+        /// All error codes, with their explanations.
+        pub const ERROR_CODES: &[(&str, &str)] = &[
+            $(($code, include_str!(concat!("error_codes/", $code, ".md"))),)*
+        ];
+    };
+}
+
+error_codes! {
+    "MIRI-UB-000",
+    "MIRI-UB-001",
+    "MIRI-UB-002",
+    "MIRI-UB-003",
+    "MIRI-UB-004",
+    "MIRI-UB-005",
+    "MIRI-UB-006",
+    "MIRI-UB-007",
+    "MIRI-SB-001",
+    "MIRI-TB-001",
+    "MIRI-RACE-001",
+    "MIRI-RACE-002",
+    "MIRI-UNSUP-001",
+    "MIRI-UNSUP-002",
+    "MIRI-UNSUP-003",
+    "MIRI-UNSUP-004",
+    "MIRI-DEADLOCK-001",
+    "MIRI-DEADLOCK-002",
+    "MIRI-RES-001",
+    "MIRI-RES-002",
+    "MIRI-RES-003",
+    "MIRI-TEST-001",
+    "MIRI-ABORT-001",
+    "MIRI-CHERI-001",
+    "MIRI-MONO-001",
+    "MIRI-LINK-001",
+    "MIRI-LINK-002",
+    "MIRI-LEAK-001",
+    "MIRI-LEAK-002",
+    "MIRI-LEAK-003",
+}
+
+/// The extended explanation of `code`, if it is one of our error codes. Codes are
+/// case-insensitive.
+pub fn explain_error_code(code: &str) -> Option<&'static str> {
+    ERROR_CODES
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(code))
+        .map(|&(_, explanation)| explanation)
+}
+
+/// The hint we attach to errors with the given code.
+pub fn explain_hint(code: &str) -> String {
+    debug_assert!(explain_error_code(code).is_some(), "unknown error code {code}");
+    format!("for more information about this error, try `miri --explain {code}`")
+}
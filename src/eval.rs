@@ -1,22 +1,33 @@
 //! Main evaluator loop and setting up the initial stack frame.
 
+use std::collections::BTreeMap;
 use std::ffi::{OsStr, OsString};
 use std::iter;
+use std::mem;
 use std::panic::{self, AssertUnwindSafe};
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::task::Poll;
 use std::thread;
+use std::time::{Duration, Instant};
 
+use crate::concurrency::dpor::Dpor;
 use crate::concurrency::thread::TlsAllocAction;
-use crate::diagnostics::report_leaks;
+use crate::diagnostics::{
+    classify_leaks, report_blocked_threads, report_leaks, report_unjoined_threads, suppress_leaks,
+    LeakReachability,
+};
 use rustc_data_structures::fx::{FxHashMap, FxHashSet};
-use rustc_hir::def::Namespace;
-use rustc_hir::def_id::DefId;
+use rustc_hir::def::{DefKind, Namespace};
+use rustc_hir::def_id::{DefId, LOCAL_CRATE};
+use rustc_middle::mir;
 use rustc_middle::ty::{
     self,
     layout::{LayoutCx, LayoutOf},
+    print::with_no_trimmed_paths,
     Ty, TyCtxt,
 };
+use rustc_span::{sym, SpanData};
 use rustc_target::spec::abi::Abi;
 
 use rustc_session::config::EntryFnType;
@@ -68,6 +79,35 @@ pub enum IsolatedOp {
     Allow,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SchedulingPolicy {
+    /// Preempt the active thread at random (see `preemption_rate`), and then continue with the
+    /// next enabled thread in order of thread IDs.
+    Random,
+    /// Preempt the active thread at the end of every basic block, and continue with the next
+    /// enabled thread in order of thread IDs.
+    RoundRobin,
+    /// Preempt the active thread at random, and continue with the thread that has been ready to
+    /// run for the longest time.
+    Fifo,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StoreBufferEviction {
+    /// Once a store buffer is full, drop its oldest store.
+    Oldest,
+    /// Additionally drop stores that no thread can read from anymore, after every store.
+    Unreadable,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RaceReportFormat {
+    /// Only report data races as regular Miri errors.
+    Miri,
+    /// Additionally print a report in the format used by ThreadSanitizer.
+    Tsan,
+}
+
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum BacktraceStyle {
     /// Prints a terser backtrace which ideally only contains relevant information.
@@ -78,6 +118,24 @@ pub enum BacktraceStyle {
     Off,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HeapProfileFormat {
+    /// The format of Valgrind's massif, which `ms_print` and `massif-visualizer` can display.
+    Massif,
+    /// Our own JSON format, which also contains statistics for each allocation site.
+    Json,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TraceMode {
+    /// Log every function call.
+    Calls,
+    /// Log every call of an emulated foreign item, with its arguments and return value.
+    Shims,
+    /// Log both.
+    All,
+}
+
 /// Configuration needed to spawn a Miri instance.
 #[derive(Clone)]
 pub struct MiriConfig {
@@ -96,16 +154,47 @@ pub struct MiriConfig {
     pub check_alignment: AlignmentCheck,
     /// Action for an op requiring communication with the host.
     pub isolated_op: IsolatedOp,
+    /// If `Some`, the driver exits with the given code when Miri stops the program because of an
+    /// error of the given kind, instead of always using 1.
+    pub error_exit_codes: Option<FxHashMap<MiriErrorKind, i32>>,
     /// Determines if memory leaks should be ignored.
     pub ignore_leaks: bool,
-    /// Environment variables that should always be forwarded from the host.
+    /// Determines if leaked memory that is still reachable from memory that did not leak should be
+    /// ignored.
+    pub ignore_reachable_leaks: bool,
+    /// Whether to report threads that terminated but were never joined or detached.
+    pub check_unjoined_threads: bool,
+    /// Patterns for the names of environment variables that should always be forwarded from the
+    /// host, where `*` matches any sequence of characters.
     pub forwarded_env_vars: Vec<String>,
+    /// Patterns for the names of environment variables that should never be forwarded from the
+    /// host, even if they match `forwarded_env_vars` or isolation is disabled.
+    pub excluded_env_vars: Vec<String>,
+    /// Host directories that are visible to the interpreted program even with isolation enabled,
+    /// set with `-Zmiri-map-dir`: the host path, the path the program uses to access it, and
+    /// whether the program may only read from it.
+    pub mapped_dirs: Vec<(PathBuf, PathBuf, bool)>,
+    /// Whether to emulate an in-memory file system for paths that are not mapped when isolation
+    /// is enabled.
+    pub virtual_fs: bool,
     /// Additional environment variables that should be set in the interpreted program.
     pub set_env_vars: FxHashMap<String, String>,
     /// Command-line arguments passed to the interpreted program.
     pub args: Vec<String>,
+    /// The path of a function of the local crate to run instead of `main`.
+    pub entry_fn: Option<String>,
+    /// Whether `entry_fn` is a `#[test]` function, which is then run like the test harness would.
+    pub entry_fn_is_test: bool,
     /// The seed to use when non-determinism or randomness are required (e.g. ptr-to-int cast, `getrandom()`).
     pub seed: Option<u64>,
+    /// Whether to print every nondeterministic decision.
+    pub log_nondeterminism: bool,
+    /// The maximal error, in ULPs, that is applied to the results of float operations whose
+    /// precision is not guaranteed.
+    pub float_max_ulp_error: u32,
+    /// Whether to make addresses and allocation numbers independent of anything but the program
+    /// itself and the seed, so that traces of different versions of a program can be diffed.
+    pub strict_determinism: bool,
     /// The stacked borrows pointer ids to report about
     pub tracked_pointer_tags: FxHashSet<BorTag>,
     /// The stacked borrows call IDs to report about
@@ -120,42 +209,188 @@ pub struct MiriConfig {
     pub weak_memory_emulation: bool,
     /// Track when an outdated (weak memory) load happens.
     pub track_outdated_loads: bool,
+    /// The maximum number of stores kept per location for weak memory emulation.
+    pub weak_memory_buffer_size: usize,
+    /// Which stores to drop from the weak memory store buffers.
+    pub weak_memory_eviction: StoreBufferEviction,
+    /// Explain data races in terms of vector clocks.
+    pub explain_data_races: bool,
+    /// The format in which data races are reported.
+    pub race_report: RaceReportFormat,
+    /// After how many atomic loads of an unchanged value to check for a livelock. 0 disables the
+    /// check.
+    pub livelock_threshold: u64,
+    /// Record where provenance gets exposed and report it at the end of execution.
+    pub track_exposed_provenance: bool,
+    /// Track the order in which locks are acquired and warn about potential deadlocks.
+    pub track_lock_order: bool,
+    /// Print how contended each lock and futex was at the end of the execution.
+    pub report_contention: bool,
+    /// Print which functions executed the most basic blocks, allocated the most heap memory, and
+    /// performed the most retags, at the end of the execution.
+    pub profile_summary: bool,
+    /// Explore thread interleavings systematically with DPOR, with the given bound on the number
+    /// of preemptions per execution.
+    pub dpor_preemption_bound: Option<u32>,
+    /// Model-check the program: explore all interleavings (unless `dpor_preemption_bound` is set)
+    /// and all stores each atomic load may read from.
+    pub model_check: bool,
+    /// Run the program as a litmus test, this many times (unless DPOR is used), and print how
+    /// often each combination of values passed to `miri_observe` occurred.
+    pub litmus_runs: Option<u32>,
     /// Rate of spurious failures for compare_exchange_weak atomic operations,
     /// between 0.0 and 1.0, defaulting to 0.8 (80% chance of failure).
     pub cmpxchg_weak_failure_rate: f64,
+    /// The probability of a condvar wait returning spuriously, i.e., without being signaled.
+    pub spurious_wakeup_rate: f64,
+    /// The probability of a futex wait returning spuriously, i.e., without being woken. On Windows,
+    /// this is also the probability of a timed futex wait expiring slightly early.
+    pub futex_spurious_wakeup_rate: f64,
+    /// Wake up a random waiter of a futex or condvar, instead of the one that waited longest.
+    pub random_wakeup_order: bool,
     /// If `Some`, enable the `measureme` profiler, writing results to a file
     /// with the specified prefix.
     pub measureme_out: Option<String>,
+    /// If `Some`, write an lcov coverage report of the local crates to a file in this directory.
+    pub coverage_out: Option<String>,
+    /// If `Some`, run the tests of a libtest test harness one by one, and write a JUnit report of
+    /// their outcomes to a file in this directory.
+    pub junit_out: Option<String>,
+    /// If `Some`, write a profile of the heap usage over time to a file in this directory.
+    pub heap_profile_out: Option<String>,
+    /// The format of that profile.
+    pub heap_profile_format: HeapProfileFormat,
+    /// If `Some`, log function calls and/or foreign item calls, as well as thread switches, to
+    /// `trace_out` (or to `<crate>.trace` in the current directory).
+    pub trace: Option<TraceMode>,
+    pub trace_out: Option<String>,
     /// Panic when unsupported functionality is encountered.
     pub panic_on_unsupported: bool,
     /// Which style to use for printing backtraces.
     pub backtrace_style: BacktraceStyle,
+    /// The maximal number of frames to show in backtraces.
+    pub backtrace_max_depth: Option<usize>,
+    /// Patterns for function paths whose frames are shown in backtraces like those of the local
+    /// crates, and are considered when picking the span to point an error at.
+    pub backtrace_include: Vec<String>,
+    /// Patterns for function paths whose frames are left out of backtraces.
+    pub backtrace_exclude: Vec<String>,
     /// Which provenance to use for int2ptr casts
     pub provenance_mode: ProvenanceMode,
     /// Whether to ignore any output by the program. This is helpful when debugging miri
     /// as its messages don't get intermingled with the program messages.
     pub mute_stdout_stderr: bool,
+    /// Whether to stop the program with an error instead of calling the `#[panic_handler]` of a
+    /// `no_std` program.
+    pub abort_in_panic_handler: bool,
+    /// If set, the program's stdout and stderr, and the diagnostics that Miri emits while running
+    /// it, are collected here instead of being printed.
+    pub captured_output: Option<CapturedOutput>,
+    /// Run each test of a libtest test harness in its own instance of the interpreter, in
+    /// parallel.
+    pub parallel_tests: bool,
+    /// Instead of running the program, serve requests to run its functions and tests on stdin.
+    pub server: bool,
     /// The probability of the active thread being preempted at the end of each basic block.
     pub preemption_rate: f64,
+    /// Preemption rates for threads with the given names, overriding `preemption_rate`.
+    pub thread_preemption_rates: Vec<(String, f64)>,
+    /// How to pick the threads to preempt and to switch to.
+    pub scheduler: SchedulingPolicy,
+    /// The probability of preferring a higher-priority thread when preempting and switching
+    /// threads.
+    pub thread_priority_bias: f64,
     /// Report the current instruction being executed every N basic blocks.
     pub report_progress: Option<u32>,
+    /// If `Some`, write the progress reports to this file as JSON lines instead of printing them.
+    pub report_progress_json: Option<String>,
+    /// Abort the program after executing this many basic blocks.
+    pub max_blocks: Option<u64>,
+    /// Abort the program after it ran for this long (in wall-clock time), and the instant when
+    /// that is the case. The time counts from when Miri started rather than from when the program
+    /// started, so that it bounds all executions together, e.g. with `-Zmiri-many-seeds`.
+    pub timeout: Option<(Duration, Instant)>,
+    /// Abort the program once a single libtest test executed this many basic blocks.
+    pub test_max_blocks: Option<u64>,
+    /// Abort the program once a single libtest test ran for this long (in wall-clock time).
+    pub test_timeout: Option<Duration>,
+    /// How many nanoseconds the virtual clock that is used with isolation enabled advances for
+    /// each basic block.
+    pub clock_nanoseconds_per_block: u128,
+    /// With isolation enabled, the wall-clock time at which the program starts, as the duration
+    /// since the Unix epoch. If this is `None`, reading the wall-clock time is rejected.
+    pub clock_epoch: Option<Duration>,
+    /// The number of bytes of stack each thread may use, counting all locals of all its frames.
+    pub stack_size: Option<u64>,
+    /// Make heap allocations fail once the live heap allocations would take up more than this
+    /// many bytes.
+    pub memory_limit: Option<u64>,
+    /// The probability of a heap allocation failing.
+    pub alloc_failure_rate: f64,
+    /// When the program runs into an error, run it again from the start and stop N basic blocks
+    /// before the error to show where the program was at that point.
+    pub stop_before_error: Option<u64>,
+    /// Stop the program once it has executed this many basic blocks. Used when running the
+    /// program again for `stop_before_error`.
+    pub stop_at_block: Option<u64>,
+    /// The address to wait for a GDB or LLDB connection at before running the program.
+    pub gdb_addr: Option<String>,
+    /// The address to wait for a Debug Adapter Protocol client (e.g., an IDE) at before running
+    /// the program.
+    pub dap_addr: Option<String>,
     /// Whether Stacked Borrows and Tree Borrows retagging should recurse into fields of datatypes.
     pub retag_fields: RetagFields,
+    /// Per-type overrides of `retag_fields`, keyed by type path (without generic arguments).
+    pub retag_fields_overrides: FxHashMap<String, RetagFields>,
+    /// Patterns for function paths; aliasing violations that occur while a matching function
+    /// is on the stack are reported as warnings instead of stopping execution.
+    pub aliasing_suppressions: Vec<String>,
+    /// Patterns for function paths, each optionally restricted to one kind of memory; leaked
+    /// allocations that were made while a matching function was on the stack are not reported.
+    pub leak_suppressions: Vec<(String, Option<MiriMemoryKind>)>,
+    /// Handlers for foreign functions, keyed by their link name. These take precedence over
+    /// Miri's own shims. This can only be set when using Miri as a library.
+    pub shim_hooks: FxHashMap<String, Arc<dyn ShimHook>>,
+    /// Extern statics declared with `-Zmiri-extern-static`, with their initial contents.
+    pub extern_statics: Vec<(String, Vec<u8>)>,
     /// The location of a shared object file to load when calling external functions
     /// FIXME! consider allowing users to specify paths to multiple files, or to a directory
     pub native_lib: Option<PathBuf>,
+    /// Whether to check the bytes that native code changed like writes of the calling thread.
+    /// This is best-effort: it compares the memory before and after the call.
+    pub native_lib_diff_writes: bool,
     /// Run a garbage collector for BorTags every N basic blocks.
     pub gc_interval: u32,
     /// The number of CPUs to be reported by miri.
     pub num_cpus: u32,
+    /// The host name reported to the program.
+    pub hostname: String,
+    /// The OS release reported by `uname`. `None` uses a default that depends on the target.
+    pub os_release: Option<String>,
     /// Requires Miri to emulate pages of a certain size
     pub page_size: Option<u64>,
+    /// Overrides the largest size (in bits) of an atomic access that the target supports.
+    pub max_atomic_width: Option<u64>,
     /// Whether to collect a backtrace when each allocation is created, just in case it leaks.
     pub collect_leak_backtraces: bool,
     /// Probability for address reuse.
     pub address_reuse_rate: f64,
     /// Probability for address reuse across threads.
     pub address_reuse_cross_thread_rate: f64,
+    /// How many different addresses each const can have. Since const allocations are never
+    /// deallocated, choosing a new base address for each evaluation would produce unbounded memory
+    /// usage, so we evaluate each const at most this many times. `1` makes the address of each
+    /// const stable.
+    pub addrs_per_const: usize,
+    /// The range of addresses to put allocations in. By default, allocations start a few pages
+    /// into the address space and can use all of the rest.
+    pub address_range: Option<(u64, u64)>,
+    /// New allocations are placed a random number of bytes below this after the previous one.
+    pub address_gap: u64,
+    /// Start allocating at a random page in the address range rather than at its start.
+    pub aslr: bool,
+    /// Emulate CHERI capabilities.
+    pub cheri: bool,
 }
 
 impl Default for MiriConfig {
@@ -167,11 +402,22 @@ impl Default for MiriConfig {
             unique_is_unique: false,
             check_alignment: AlignmentCheck::Int,
             isolated_op: IsolatedOp::Reject(RejectOpWith::Abort),
+            error_exit_codes: None,
             ignore_leaks: false,
+            ignore_reachable_leaks: false,
+            check_unjoined_threads: false,
             forwarded_env_vars: vec![],
+            excluded_env_vars: vec![],
+            mapped_dirs: vec![],
+            virtual_fs: false,
             set_env_vars: FxHashMap::default(),
             args: vec![],
+            entry_fn: None,
+            entry_fn_is_test: false,
             seed: None,
+            log_nondeterminism: false,
+            float_max_ulp_error: 0,
+            strict_determinism: false,
             tracked_pointer_tags: FxHashSet::default(),
             tracked_call_ids: FxHashSet::default(),
             tracked_alloc_ids: FxHashSet::default(),
@@ -179,22 +425,83 @@ impl Default for MiriConfig {
             data_race_detector: true,
             weak_memory_emulation: true,
             track_outdated_loads: false,
+            // The authors of the paper our weak memory emulation is based on picked 128 as a good
+            // tradeoff between the weak behaviours that can be observed and memory usage.
+            weak_memory_buffer_size: 128,
+            weak_memory_eviction: StoreBufferEviction::Oldest,
+            explain_data_races: false,
+            race_report: RaceReportFormat::Miri,
+            livelock_threshold: 0,
+            track_exposed_provenance: false,
+            track_lock_order: false,
+            report_contention: false,
+            profile_summary: false,
+            dpor_preemption_bound: None,
+            model_check: false,
+            litmus_runs: None,
             cmpxchg_weak_failure_rate: 0.8, // 80%
+            spurious_wakeup_rate: 0.0,
+            futex_spurious_wakeup_rate: 0.0,
+            random_wakeup_order: false,
             measureme_out: None,
+            coverage_out: None,
+            junit_out: None,
+            heap_profile_out: None,
+            heap_profile_format: HeapProfileFormat::Massif,
+            trace: None,
+            trace_out: None,
             panic_on_unsupported: false,
             backtrace_style: BacktraceStyle::Short,
+            backtrace_max_depth: None,
+            backtrace_include: vec![],
+            backtrace_exclude: vec![],
             provenance_mode: ProvenanceMode::Default,
             mute_stdout_stderr: false,
+            abort_in_panic_handler: false,
+            captured_output: None,
+            parallel_tests: false,
+            server: false,
             preemption_rate: 0.01, // 1%
+            thread_preemption_rates: vec![],
+            scheduler: SchedulingPolicy::Random,
+            thread_priority_bias: 0.5,
             report_progress: None,
+            report_progress_json: None,
+            max_blocks: None,
+            timeout: None,
+            test_max_blocks: None,
+            test_timeout: None,
+            clock_nanoseconds_per_block: crate::clock::NANOSECONDS_PER_BASIC_BLOCK,
+            clock_epoch: None,
+            stack_size: None,
+            memory_limit: None,
+            alloc_failure_rate: 0.0,
+            stop_before_error: None,
+            stop_at_block: None,
+            gdb_addr: None,
+            dap_addr: None,
             retag_fields: RetagFields::Yes,
+            retag_fields_overrides: FxHashMap::default(),
+            aliasing_suppressions: vec![],
+            leak_suppressions: vec![],
+            shim_hooks: FxHashMap::default(),
+            extern_statics: vec![],
             native_lib: None,
+            native_lib_diff_writes: false,
             gc_interval: 10_000,
             num_cpus: 1,
+            hostname: "miri".to_owned(),
+            os_release: None,
             page_size: None,
+            max_atomic_width: None,
             collect_leak_backtraces: true,
             address_reuse_rate: 0.5,
             address_reuse_cross_thread_rate: 0.1,
+            addrs_per_const: 16,
+            address_range: None,
+            address_gap: 16,
+            aslr: false,
+            cheri: false,
         }
     }
 }
@@ -205,6 +512,7 @@ enum MainThreadState<'tcx> {
     #[default]
     Running,
     TlsDtors(tls::TlsDtorsState<'tcx>),
+    AtExit,
     Yield {
         remaining: u32,
     },
@@ -224,20 +532,27 @@ impl<'tcx> MainThreadState<'tcx> {
             TlsDtors(state) =>
                 match state.on_stack_empty(this)? {
                     Poll::Pending => {} // just keep going
-                    Poll::Ready(()) => {
-                        // Give background threads a chance to finish by yielding the main thread a
-                        // couple of times -- but only if we would also preempt threads randomly.
-                        if this.machine.preemption_rate > 0.0 {
-                            // There is a non-zero chance they will yield back to us often enough to
-                            // make Miri terminate eventually.
-                            *self = Yield { remaining: MAIN_THREAD_YIELDS_AT_SHUTDOWN };
-                        } else {
-                            // The other threads did not get preempted, so no need to yield back to
-                            // them.
-                            *self = Done;
-                        }
-                    }
+                    Poll::Ready(()) => *self = AtExit,
                 },
+            AtExit => {
+                // Returning from `main` is like calling `exit`, so we run the `atexit` handlers,
+                // one at a time. They run before the leak check, so they can free memory.
+                if !this.call_next_atexit_handler(StackPopCleanup::Root { cleanup: true })? {
+                    // Give background threads a chance to finish by yielding the main thread a
+                    // couple of times -- but only if we would also preempt threads randomly.
+                    if this.machine.threads.any_thread_preemptible(this.machine.preemption_rate)
+                        || this.machine.threads.scheduler() == SchedulingPolicy::RoundRobin
+                    {
+                        // There is a non-zero chance they will yield back to us often enough to
+                        // make Miri terminate eventually.
+                        *self = Yield { remaining: MAIN_THREAD_YIELDS_AT_SHUTDOWN };
+                    } else {
+                        // The other threads did not get preempted, so no need to yield back to
+                        // them.
+                        *self = Done;
+                    }
+                }
+            }
             Yield { remaining } =>
                 match remaining.checked_sub(1) {
                     None => *self = Done,
@@ -249,7 +564,12 @@ impl<'tcx> MainThreadState<'tcx> {
             Done => {
                 // Figure out exit code.
                 let ret_place = this.machine.main_fn_ret_place.clone().unwrap();
-                let exit_code = this.read_target_isize(&ret_place)?;
+                let mut exit_code = this.read_target_isize(&ret_place)?;
+                // `lang_start` turns a panic into exit code 101. If the test was supposed to panic,
+                // `check_should_panic` already made sure that it panicked in the right way.
+                if this.machine.entry_fn_should_panic.is_some() && exit_code == 101 {
+                    exit_code = 0;
+                }
                 // Deal with our thread-local memory. We do *not* want to actually free it, instead we consider TLS
                 // to be like a global `static`, so that all memory reached by it is considered to "not leak".
                 this.terminate_active_thread(TlsAllocAction::Leak)?;
@@ -325,8 +645,11 @@ pub fn create_ecx<'tcx>(
         ecx.mark_immutable(&argvs_place);
         // A pointer to that place is the 3rd argument for main.
         let argv = argvs_place.to_ref(&ecx);
-        // Store `argc` and `argv` for macOS `_NSGetArg{c,v}`.
-        {
+        // Store `argc` and `argv` for macOS `_NSGetArg{c,v}`, and the command line for Windows
+        // `GetCommandLineW`. Only the standard library uses those; `no_std` programs just get
+        // `argc` and `argv` as arguments of their entry point.
+        let links_std = tcx.crates(()).iter().any(|&krate| tcx.crate_name(krate) == sym::std);
+        if links_std {
             let argc_place =
                 ecx.allocate(ecx.machine.layouts.isize, MiriMemoryKind::Machine.into())?;
             ecx.write_scalar(argc, &argc_place)?;
@@ -342,7 +665,7 @@ pub fn create_ecx<'tcx>(
             ecx.machine.argv = Some(argv_place.ptr());
         }
         // Store command line as UTF-16 for Windows `GetCommandLineW`.
-        {
+        if links_std {
             // Construct a command string with all the arguments.
             let cmd_utf16: Vec<u16> = args_to_utf16_command_string(config.args.iter());
 
@@ -366,44 +689,19 @@ pub fn create_ecx<'tcx>(
     ecx.machine.main_fn_ret_place = Some(ret_place.clone());
     // Call start function.
 
-    match entry_type {
-        EntryFnType::Main { .. } => {
-            let start_id = tcx.lang_items().start_fn().unwrap_or_else(|| {
-                tcx.dcx().fatal(
-                    "could not find start function. Make sure the entry point is marked with `#[start]`."
-                );
-            });
+    match (&config.entry_fn, entry_type) {
+        (Some(path), _) => {
+            let (main_ptr, main_ret_ty) =
+                prepare_entry_fn(&mut ecx, path, config.entry_fn_is_test)?;
+            call_lang_start(&mut ecx, main_ptr, main_ret_ty, argc, argv, &ret_place)?;
+        }
+        (None, EntryFnType::Main { .. }) => {
             let main_ret_ty = tcx.fn_sig(entry_id).no_bound_vars().unwrap().output();
             let main_ret_ty = main_ret_ty.no_bound_vars().unwrap();
-            let start_instance = ty::Instance::resolve(
-                tcx,
-                ty::ParamEnv::reveal_all(),
-                start_id,
-                tcx.mk_args(&[ty::GenericArg::from(main_ret_ty)]),
-            )
-            .unwrap()
-            .unwrap();
-
             let main_ptr = ecx.fn_ptr(FnVal::Instance(entry_instance));
-
-            // Always using DEFAULT is okay since we don't support signals in Miri anyway.
-            // (This means we are effectively ignoring `-Zon-broken-pipe`.)
-            let sigpipe = rustc_session::config::sigpipe::DEFAULT;
-
-            ecx.call_function(
-                start_instance,
-                Abi::Rust,
-                &[
-                    Scalar::from_pointer(main_ptr, &ecx).into(),
-                    argc.into(),
-                    argv,
-                    Scalar::from_u8(sigpipe).into(),
-                ],
-                Some(&ret_place),
-                StackPopCleanup::Root { cleanup: true },
-            )?;
+            call_lang_start(&mut ecx, main_ptr, main_ret_ty, argc, argv, &ret_place)?;
         }
-        EntryFnType::Start => {
+        (None, EntryFnType::Start) => {
             ecx.call_function(
                 entry_instance,
                 Abi::Rust,
@@ -417,20 +715,448 @@ pub fn create_ecx<'tcx>(
     Ok(ecx)
 }
 
+/// Push the frame of the `lang_start` lang item, which sets up the runtime and then calls `main`.
+fn call_lang_start<'tcx>(
+    ecx: &mut MiriInterpCx<'tcx>,
+    main_ptr: StrictPointer,
+    main_ret_ty: Ty<'tcx>,
+    argc: Scalar,
+    argv: Immediate<Provenance>,
+    ret_place: &MPlaceTy<'tcx>,
+) -> InterpResult<'tcx> {
+    let tcx = ecx.tcx.tcx;
+    let start_id = tcx.lang_items().start_fn().unwrap_or_else(|| {
+        tcx.dcx().fatal(
+            "could not find start function. Make sure the entry point is marked with `#[start]`.",
+        );
+    });
+    let start_instance = ty::Instance::resolve(
+        tcx,
+        ty::ParamEnv::reveal_all(),
+        start_id,
+        tcx.mk_args(&[ty::GenericArg::from(main_ret_ty)]),
+    )
+    .unwrap()
+    .unwrap();
+
+    // Always using DEFAULT is okay since we don't support signals in Miri anyway.
+    // (This means we are effectively ignoring `-Zon-broken-pipe`.)
+    let sigpipe = rustc_session::config::sigpipe::DEFAULT;
+
+    ecx.call_function(
+        start_instance,
+        Abi::Rust,
+        &[
+            Scalar::from_pointer(main_ptr, ecx).into(),
+            argc.into(),
+            argv,
+            Scalar::from_u8(sigpipe).into(),
+        ],
+        Some(ret_place),
+        StackPopCleanup::Root { cleanup: true },
+    )
+}
+
+/// The name of the function pointer that `lang_start` calls as `main` with `-Zmiri-entry-fn`. It
+/// constructs the arguments of the entry function and then calls it.
+pub(crate) const ENTRY_FN_TRAMPOLINE: &str = "miri::entry_fn";
+
+/// For `-Zmiri-entry-fn`: find the function to run, and the `Default::default` instances that
+/// construct its arguments. Returns the pointer `lang_start` has to call as `main`, and its return
+/// type.
+fn prepare_entry_fn<'tcx>(
+    ecx: &mut MiriInterpCx<'tcx>,
+    path: &str,
+    is_test: bool,
+) -> InterpResult<'tcx, (StrictPointer, Ty<'tcx>)> {
+    let tcx = ecx.tcx.tcx;
+    // The path is relative to the local crate, but may start with its name.
+    let crate_name = tcx.crate_name(LOCAL_CRATE);
+    let relative_path = path
+        .strip_prefix("crate::")
+        .or_else(|| path.strip_prefix(&format!("{crate_name}::")))
+        .unwrap_or(path);
+    let mut candidates = tcx.hir().body_owners().filter(|&def_id| {
+        matches!(tcx.def_kind(def_id), DefKind::Fn | DefKind::AssocFn)
+            && with_no_trimmed_paths!(tcx.def_path_str(def_id)) == relative_path
+    });
+    let Some(def_id) = candidates.next() else {
+        ecx.machine
+            .dcx()
+            .fatal(format!("-Zmiri-entry-fn: there is no function `{path}` in this crate"));
+    };
+    let def_id = def_id.to_def_id();
+    if is_test {
+        // For every `#[test]` function, the test harness generates a `const` with the same path
+        // that describes the test.
+        let is_test = tcx.hir().body_owners().any(|const_id| {
+            tcx.def_kind(const_id) == DefKind::Const
+                && tcx.has_attr(const_id, sym::rustc_test_marker)
+                && with_no_trimmed_paths!(tcx.def_path_str(const_id)) == relative_path
+        });
+        if !is_test {
+            ecx.machine.dcx().fatal(format!(
+                "-Zmiri-test: `{path}` is not a test (make sure to build the crate with `--test`)"
+            ));
+        }
+        if let Some(attr) = tcx.get_attr(def_id, sym::should_panic) {
+            // Like the test harness, accept both `#[should_panic = "..."]` and
+            // `#[should_panic(expected = "...")]`.
+            let expected = match attr.meta_item_list() {
+                Some(list) =>
+                    list.iter()
+                        .find(|item| item.has_name(sym::expected))
+                        .and_then(|item| item.value_str()),
+                None => attr.value_str(),
+            };
+            ecx.machine.entry_fn_should_panic = Some(ShouldPanic {
+                expected: expected.map(|expected| expected.to_string()),
+                span: attr.span.data(),
+                stack_len: 0,
+            });
+        }
+    }
+    if tcx.generics_of(def_id).requires_monomorphization(tcx) {
+        ecx.machine.dcx().fatal(format!("-Zmiri-entry-fn: `{path}` must not be generic"));
+    }
+    let sig = tcx.instantiate_bound_regions_with_erased(tcx.fn_sig(def_id).instantiate_identity());
+    if sig.abi != Abi::Rust || sig.c_variadic {
+        ecx.machine.dcx().fatal(format!("-Zmiri-entry-fn: `{path}` must use the Rust ABI"));
+    }
+
+    // `lang_start` requires the return type to implement `Termination`, like for `main`.
+    // Both `Termination` and `Default` have exactly one method.
+    let implements = |trait_id: Option<DefId>, ty: Ty<'tcx>| {
+        let method = tcx.associated_item_def_ids(trait_id.unwrap())[0];
+        ty::Instance::resolve(tcx, ty::ParamEnv::reveal_all(), method, tcx.mk_args(&[ty.into()]))
+            .ok()
+            .flatten()
+    };
+    let ret_ty = sig.output();
+    if implements(tcx.lang_items().termination(), ret_ty).is_none() {
+        ecx.machine.dcx().fatal(format!(
+            "-Zmiri-entry-fn: the return type `{ret_ty}` of `{path}` does not implement `Termination`"
+        ));
+    }
+    let mut defaults = Vec::new();
+    for &arg_ty in sig.inputs() {
+        let Some(default) = implements(tcx.get_diagnostic_item(sym::Default), arg_ty) else {
+            ecx.machine.dcx().fatal(format!(
+                "-Zmiri-entry-fn: the argument type `{arg_ty}` of `{path}` does not implement `Default`"
+            ));
+        };
+        defaults.push(default);
+    }
+
+    ecx.machine.entry_fn = Some((ty::Instance::mono(tcx, def_id), defaults));
+    let main_ptr = ecx.fn_ptr(FnVal::Other(DynSym::from_str(ENTRY_FN_TRAMPOLINE)));
+    Ok((main_ptr, ret_ty))
+}
+
+/// With `-Zmiri-test`, the `#[should_panic]` attribute of the test.
+#[derive(Debug)]
+pub(crate) struct ShouldPanic {
+    /// The string that the panic message must contain, from `expected = "..."`.
+    expected: Option<String>,
+    /// The span of the attribute.
+    span: SpanData,
+    /// The length of the main thread's stack with the frame of the test on top.
+    stack_len: usize,
+}
+
+/// With `-Zmiri-test`, called before a frame is popped: if it is the frame of a `#[should_panic]`
+/// test, check that the test is left by a panic with the expected message.
+pub(crate) fn check_should_panic<'tcx>(
+    ecx: &MiriInterpCx<'tcx>,
+    frame: &Frame<'tcx, Provenance, FrameExtra<'tcx>>,
+) -> InterpResult<'tcx> {
+    let Some(should_panic) = &ecx.machine.entry_fn_should_panic else {
+        return Ok(());
+    };
+    if ecx.active_thread() != ThreadId::MAIN_THREAD
+        || ecx.active_thread_stack().len() != should_panic.stack_len
+    {
+        return Ok(());
+    }
+    // A frame that is popped by unwinding is not at a statement or terminator anymore.
+    let msg = if frame.current_loc().is_left() {
+        "the test returned without panicking".to_owned()
+    } else if let Some(expected) = &should_panic.expected {
+        match ecx.panic_payload_message()? {
+            Some(msg) if msg.contains(expected.as_str()) => return Ok(()),
+            Some(msg) =>
+                format!(
+                    "the test panicked with `{msg}`, which does not contain the expected string `{expected}`"
+                ),
+            None =>
+                format!(
+                    "the test panicked with a payload that is not a string, so it does not contain the expected string `{expected}`"
+                ),
+        }
+    } else {
+        return Ok(());
+    };
+    throw_machine_stop!(TerminationInfo::ShouldPanicFailed { msg, attr: should_panic.span })
+}
+
+/// Called by `lang_start` in place of `main` with `-Zmiri-entry-fn`: construct the arguments of
+/// the entry function and call it, and return to `lang_start` when it is done. Since this runs
+/// after the runtime was set up, `Default::default` can do anything `main` could, e.g. panic.
+pub(crate) fn call_entry_fn<'tcx>(
+    ecx: &mut MiriInterpCx<'tcx>,
+    caller_abi: Abi,
+    dest: &MPlaceTy<'tcx>,
+    ret: Option<mir::BasicBlock>,
+    unwind: mir::UnwindAction,
+) -> InterpResult<'tcx> {
+    let (instance, defaults) = ecx.machine.entry_fn.take().unwrap();
+    if caller_abi != Abi::Rust {
+        throw_ub_format!("calling the `-Zmiri-entry-fn` function with ABI {}", caller_abi.name());
+    }
+    let mir = ecx.load_mir(instance.def, None)?;
+    ecx.push_stack_frame(instance, mir, dest, StackPopCleanup::Goto { ret, unwind })?;
+    let stack_len = ecx.active_thread_stack().len();
+    if let Some(should_panic) = &mut ecx.machine.entry_fn_should_panic {
+        should_panic.stack_len = stack_len;
+    }
+    let locals: Vec<_> = ecx.frame().body.args_iter().collect();
+    assert_eq!(locals.len(), defaults.len());
+    let mut places = Vec::new();
+    for &local in &locals {
+        ecx.storage_live(local)?;
+        let place = ecx.local_to_place(local)?;
+        places.push(ecx.force_allocation(&place)?);
+    }
+    ecx.storage_live_for_always_live_locals()?;
+    // Construct every argument directly in its local: when `Default::default` returns, the entry
+    // function continues at its first block, i.e., where it currently is. A panic unwinds out of
+    // the entry function, like a panic in its body would. The arguments are constructed in order,
+    // so the first one is on top of the stack.
+    for (default, place) in iter::zip(defaults, places).rev() {
+        ecx.call_function(
+            default,
+            Abi::Rust,
+            &[],
+            Some(&place),
+            StackPopCleanup::Goto {
+                ret: Some(mir::START_BLOCK),
+                unwind: mir::UnwindAction::Continue,
+            },
+        )?;
+    }
+    Ok(())
+}
+
 /// Evaluates the entry function specified by `entry_id`.
-/// Returns `Some(return_code)` if program executed completed.
-/// Returns `None` if an evaluation error occurred.
+/// Returns `Ok(return_code)` if program executed completed.
+/// Returns `Err` if an evaluation error occurred; the error has already been reported.
 #[allow(clippy::needless_lifetimes)]
 pub fn eval_entry<'tcx>(
     tcx: TyCtxt<'tcx>,
     entry_id: DefId,
     entry_type: EntryFnType,
     config: MiriConfig,
-) -> Option<i64> {
-    // Copy setting before we move `config`.
+) -> Result<i64, MiriError> {
+    if config.server {
+        return server::serve(tcx, entry_id, entry_type, config);
+    }
+    if config.parallel_tests || (config.junit_out.is_some() && tcx.sess.opts.test) {
+        return parallel_tests::eval_tests_in_parallel(tcx, entry_id, entry_type, config);
+    }
+    let mut litmus = config.litmus_runs.map(|_| LitmusOutcomes::default());
+    let preemption_bound = match (config.dpor_preemption_bound, config.model_check) {
+        (Some(bound), _) => bound,
+        (None, true) => u32::MAX,
+        (None, false) => {
+            let (Some(runs), Some(litmus)) = (config.litmus_runs, &mut litmus) else {
+                // Run the program again whenever it restarts.
+                let mut restart_points = RestartPoints::default();
+                let return_code = loop {
+                    let (return_code, _, blocks) = eval_entry_once(
+                        tcx,
+                        entry_id,
+                        entry_type,
+                        &config,
+                        None,
+                        None,
+                        Some(&mut restart_points),
+                    );
+                    if return_code.is_err() || !restart_points.restart_requested {
+                        break (return_code, blocks);
+                    }
+                };
+                // Run the program again from the start, which reaches the error in exactly the
+                // same way, and stop shortly before the error.
+                if let (Err(_), blocks) = return_code
+                    && let Some(before) = config.stop_before_error
+                {
+                    let before = before.min(blocks);
+                    let config = MiriConfig {
+                        stop_before_error: Some(before),
+                        stop_at_block: Some(blocks - before),
+                        mute_stdout_stderr: true,
+                        ..config.clone()
+                    };
+                    eval_entry_once(
+                        tcx,
+                        entry_id,
+                        entry_type,
+                        &config,
+                        None,
+                        None,
+                        Some(&mut restart_points),
+                    );
+                }
+                return return_code.0;
+            };
+            // Run the program with a different seed each time.
+            let first_seed = config.seed.unwrap_or(0);
+            for run in 0..u64::from(runs) {
+                let seed = first_seed.wrapping_add(run);
+                let config = MiriConfig { seed: Some(seed), ..config.clone() };
+                let return_code =
+                    eval_entry_once(tcx, entry_id, entry_type, &config, None, Some(litmus), None).0;
+                if !matches!(return_code, Ok(0)) {
+                    tcx.dcx().note(format!(
+                        "this failure was found in run {} of the litmus test, with seed {seed}",
+                        run + 1
+                    ));
+                    return return_code;
+                }
+            }
+            litmus.report(tcx, u64::from(runs));
+            return Ok(0);
+        }
+    };
+
+    // Run the program again and again, until DPOR has explored all interleavings (within the
+    // preemption bound) or an execution fails.
+    let mut dpor = Dpor::new(preemption_bound, config.model_check);
+    loop {
+        let (return_code, returned_dpor, _) =
+            eval_entry_once(tcx, entry_id, entry_type, &config, Some(dpor), litmus.as_mut(), None);
+        dpor = returned_dpor.unwrap();
+        if !matches!(return_code, Ok(0)) {
+            tcx.dcx().note(format!(
+                "this failure was found in execution {} of the systematic exploration",
+                dpor.executions()
+            ));
+            return return_code;
+        }
+        if !dpor.next_execution() {
+            if let Some(litmus) = &litmus {
+                litmus.report(tcx, dpor.executions());
+            }
+            let bound = if preemption_bound == u32::MAX {
+                String::new()
+            } else {
+                format!(" with at most {preemption_bound} preemption(s) each")
+            };
+            tcx.dcx().note(format!("explored {} execution(s){bound}", dpor.executions()));
+            return return_code;
+        }
+    }
+}
+
+/// The restart points reached with `miri_restart_point`. This is not a snapshot of the program
+/// state: restarting with `miri_restart` ends the execution, and the program is run again from
+/// the start. Since execution is deterministic, it reaches the same state at the restart point
+/// again (and repeats everything it did before, including its output), and `miri_restart_point`
+/// then returns how often the program was restarted there, so that it can pick a different
+/// continuation.
+#[derive(Default, Debug)]
+pub struct RestartPoints {
+    /// What each `miri_restart_point` call returned, in the order they were reached. When running
+    /// the program again, the calls up to the one restarted at have to return the same values
+    /// (except for that one itself).
+    returned: Vec<u64>,
+    /// How many restart points were reached in the current execution.
+    reached: usize,
+    /// Whether the current execution ended by restarting.
+    restart_requested: bool,
+}
+
+impl RestartPoints {
+    fn start_execution(&mut self) {
+        self.reached = 0;
+        self.restart_requested = false;
+    }
+
+    /// Reach a restart point, and return how often the program was restarted there so far.
+    pub(crate) fn reach(&mut self) -> u64 {
+        let id = self.reached;
+        self.reached += 1;
+        if let Some(&restarts) = self.returned.get(id) {
+            restarts
+        } else {
+            self.returned.push(0);
+            0
+        }
+    }
+
+    /// Restart at the restart point with the given ID, i.e., the one that was reached `id`-th in
+    /// this execution. Returns `false` if there is no such restart point.
+    pub(crate) fn restart(&mut self, id: u64) -> bool {
+        let Some(id) = usize::try_from(id).ok().filter(|&id| id < self.reached) else {
+            return false;
+        };
+        self.returned.truncate(id + 1);
+        self.returned[id] += 1;
+        self.restart_requested = true;
+        true
+    }
+}
+
+/// The values passed to `miri_observe` in one execution, sorted by name.
+type LitmusOutcome = Vec<(String, u64)>;
+
+/// How often each outcome occurred over all executions of a litmus test.
+#[derive(Default)]
+struct LitmusOutcomes(BTreeMap<LitmusOutcome, u64>);
+
+impl LitmusOutcomes {
+    fn record(&mut self, mut outcome: LitmusOutcome) {
+        // The order in which threads observe values may depend on the interleaving, so we ignore
+        // it. (The sort is stable, so values observed under the same name keep their order.)
+        outcome.sort_by(|(a, _), (b, _)| a.cmp(b));
+        *self.0.entry(outcome).or_insert(0) += 1;
+    }
+
+    fn report(&self, tcx: TyCtxt<'_>, executions: u64) {
+        let mut msg = format!("observed the following outcomes in {executions} execution(s):");
+        for (outcome, count) in &self.0 {
+            let outcome = if outcome.is_empty() {
+                "(nothing observed)".to_owned()
+            } else {
+                outcome
+                    .iter()
+                    .map(|(name, value)| format!("{name}={value}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            msg.push_str(&format!("\n{count:>8}  {outcome}"));
+        }
+        tcx.dcx().note(msg);
+    }
+}
+
+/// Run the program once. If `dpor` is set, the thread interleaving is picked by the DPOR
+/// scheduler, and its state is returned after the execution. If `litmus` is set, the values the
+/// program observed are recorded there (unless the execution failed). If `restart_points` is set,
+/// the program may restart, which ends the execution; the caller then has to run it again.
+/// Also returns how many basic blocks were executed.
+fn eval_entry_once<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    entry_id: DefId,
+    entry_type: EntryFnType,
+    config: &MiriConfig,
+    dpor: Option<Dpor>,
+    litmus: Option<&mut LitmusOutcomes>,
+    mut restart_points: Option<&mut RestartPoints>,
+) -> (Result<i64, MiriError>, Option<Dpor>, u64) {
     let ignore_leaks = config.ignore_leaks;
 
-    let mut ecx = match create_ecx(tcx, entry_id, entry_type, &config) {
+    let mut ecx = match create_ecx(tcx, entry_id, entry_type, config) {
         Ok(v) => v,
         Err(err) => {
             let (kind, backtrace) = err.into_parts();
@@ -438,6 +1164,13 @@ pub fn eval_entry<'tcx>(
             panic!("Miri initialization error: {kind:?}")
         }
     };
+    if let Some(dpor) = dpor {
+        ecx.machine.threads.set_dpor(dpor);
+    }
+    if let Some(restart_points) = &mut restart_points {
+        restart_points.start_execution();
+        ecx.machine.restart_points = Some(mem::take(&mut **restart_points));
+    }
 
     // Perform the main execution.
     let res: thread::Result<InterpResult<'_, !>> =
@@ -451,6 +1184,11 @@ pub fn eval_entry<'tcx>(
         // `Ok` can never happen
         Ok(never) => match never {},
     };
+    let dpor = ecx.machine.threads.take_dpor();
+    let blocks = ecx.machine.basic_block_count;
+    if let Some(restart_points) = restart_points {
+        *restart_points = ecx.machine.restart_points.take().unwrap();
+    }
 
     // Machine cleanup. Only do this if all threads have terminated; threads that are still running
     // might cause Stacked Borrows errors (https://github.com/rust-lang/miri/issues/2396).
@@ -462,27 +1200,84 @@ pub fn eval_entry<'tcx>(
         EnvVars::cleanup(&mut ecx).expect("error during env var cleanup");
     }
 
+    ecx.machine.report_exposed_provenance();
+
     // Process the result.
-    let (return_code, leak_check) = report_error(&ecx, res)?;
+    let result = report_error(&ecx, res);
+    ecx.debugger_finish(result.as_ref().ok().map(|&(return_code, _)| return_code));
+    if config.report_contention
+        && let Some(report) = ecx.machine.sync.contention_report()
+    {
+        ecx.machine.dcx().note(report);
+    }
+    if let Some(summary) = ecx.machine.profile_summary() {
+        ecx.machine.dcx().note(summary);
+    }
+    if let Some(coverage) = &ecx.machine.coverage
+        && let Err(err) = coverage.write_report(tcx)
+    {
+        ecx.machine.dcx().warn(format!("failed to write the coverage report: {err}"));
+    }
+    if let Some(heap_profile) = &ecx.machine.heap_profile
+        && let Err(err) = heap_profile.borrow().write_report(tcx, ecx.machine.basic_block_count)
+    {
+        ecx.machine.dcx().warn(format!("failed to write the heap profile: {err}"));
+    }
+    let (return_code, leak_check) = match result {
+        Ok(result) => result,
+        Err(error) => return (Err(error), dpor, blocks),
+    };
     if leak_check && !ignore_leaks {
         // Check for thread leaks.
         if !ecx.have_all_terminated() {
-            tcx.dcx().err("the main thread terminated without waiting for all remaining threads");
-            tcx.dcx().note("set `MIRIFLAGS=-Zmiri-ignore-leaks` to disable this check");
-            return None;
+            let message = "the main thread terminated without waiting for all remaining threads";
+            ecx.machine.dcx().err(message);
+            report_blocked_threads(&ecx);
+            ecx.machine.dcx().note("set `MIRIFLAGS=-Zmiri-ignore-leaks` to disable this check");
+            ecx.machine.dcx().note(explain_hint("MIRI-LEAK-002"));
+            let error = MiriError { kind: MiriErrorKind::Leak, message: message.to_owned() };
+            return (Err(error), dpor, blocks);
+        }
+        if ecx.machine.check_unjoined_threads && report_unjoined_threads(&mut ecx) {
+            ecx.machine.dcx().note("remove `-Zmiri-check-unjoined-threads` to disable this check");
+            ecx.machine.dcx().note(explain_hint("MIRI-LEAK-003"));
+            let error = MiriError {
+                kind: MiriErrorKind::Leak,
+                message: "some threads were never joined".to_owned(),
+            };
+            return (Err(error), dpor, blocks);
         }
         // Check for memory leaks.
         info!("Additional static roots: {:?}", ecx.machine.static_roots);
-        let leaks = ecx.find_leaked_allocations(&ecx.machine.static_roots);
+        let mut leaks = ecx.find_leaked_allocations(&ecx.machine.static_roots);
+        suppress_leaks(&ecx, &mut leaks, &config.leak_suppressions);
+        let mut leaks = classify_leaks(&ecx, leaks);
+        if config.ignore_reachable_leaks {
+            let before = leaks.len();
+            leaks.retain(|(_, reachability)| *reachability != LeakReachability::StillReachable);
+            let reachable = before - leaks.len();
+            if reachable > 0 {
+                ecx.machine.dcx().note(format!(
+                    "{reachable} leaked allocation(s) were not reported since they are still reachable"
+                ));
+            }
+        }
         if !leaks.is_empty() {
+            let message = format!("the program leaked {} allocation(s)", leaks.len());
             report_leaks(&ecx, leaks);
-            tcx.dcx().note("set `MIRIFLAGS=-Zmiri-ignore-leaks` to disable this check");
+            ecx.machine.dcx().note("set `MIRIFLAGS=-Zmiri-ignore-leaks` to disable this check");
+            ecx.machine.dcx().note(explain_hint("MIRI-LEAK-001"));
             // Ignore the provided return code - let the reported error
             // determine the return code.
-            return None;
+            return (Err(MiriError { kind: MiriErrorKind::Leak, message }), dpor, blocks);
         }
     }
-    Some(return_code)
+    if let Some(litmus) = litmus
+        && return_code == 0
+    {
+        litmus.record(ecx.machine.observations.take().unwrap_or_default());
+    }
+    (Ok(return_code), dpor, blocks)
 }
 
 /// Turns an array of arguments into a Windows command line string.
@@ -0,0 +1,249 @@
+//! A heap profiler in the style of Valgrind's massif, for `-Zmiri-heap-profile`.
+//!
+//! We track the live heap allocations by the place they were made at (the span of the topmost
+//! frame in a local crate), and take snapshots of the total heap size over "time", which we measure
+//! in executed basic blocks. Like massif, we keep a bounded number of snapshots: whenever we have
+//! too many, we drop every other one and take snapshots half as often from then on. Some snapshots
+//! are detailed, i.e., they also record how much memory each allocation site holds; this includes
+//! the peak snapshot, which is (again like massif) taken when the heap has grown by more than 1%
+//! beyond the last peak, so it can be off by up to that much.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use rustc_data_structures::fx::FxHashMap;
+use rustc_middle::ty::{self, TyCtxt};
+use rustc_span::Span;
+use serde_json::json;
+
+use crate::*;
+
+/// The maximal number of snapshots we keep.
+const MAX_SNAPSHOTS: usize = 100;
+/// Every this many snapshots is a detailed one.
+const DETAILED_FREQ: usize = 10;
+
+#[derive(Debug)]
+pub struct HeapProfile<'tcx> {
+    /// Where to write the profile.
+    out: PathBuf,
+    format: HeapProfileFormat,
+    /// The statistics of each allocation site.
+    sites: FxHashMap<Span, SiteStats<'tcx>>,
+    /// The site and size of each live heap allocation.
+    live: FxHashMap<AllocId, (Span, u64)>,
+    /// The total size of the live heap allocations.
+    total: u64,
+    snapshots: Vec<Snapshot>,
+    /// How many basic blocks to wait between two snapshots.
+    interval: u64,
+    /// The detailed snapshot of the largest heap size seen so far.
+    peak: Option<Snapshot>,
+}
+
+#[derive(Debug)]
+struct SiteStats<'tcx> {
+    /// The function that contains the site.
+    instance: Option<ty::Instance<'tcx>>,
+    /// How many allocations were made here.
+    count: u64,
+    /// How many bytes were allocated here in total.
+    allocated: u64,
+    /// How many bytes of memory allocated here are still live.
+    live: u64,
+    /// The largest value of `live` over the course of the execution.
+    peak: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Snapshot {
+    time: u64,
+    total: u64,
+    /// For detailed snapshots, the live bytes of each site that holds any memory, largest first.
+    sites: Option<Vec<(Span, u64)>>,
+    is_peak: bool,
+}
+
+impl<'tcx> HeapProfile<'tcx> {
+    pub fn new(out: PathBuf, format: HeapProfileFormat) -> Self {
+        HeapProfile {
+            out,
+            format,
+            sites: FxHashMap::default(),
+            live: FxHashMap::default(),
+            total: 0,
+            snapshots: vec![Snapshot { time: 0, total: 0, sites: None, is_peak: false }],
+            interval: 1024,
+            peak: None,
+        }
+    }
+
+    /// Record a new heap allocation, made at `site` in `instance`.
+    pub fn record_alloc(
+        &mut self,
+        id: AllocId,
+        size: u64,
+        site: Span,
+        instance: Option<ty::Instance<'tcx>>,
+        time: u64,
+    ) {
+        let stats = self.sites.entry(site).or_insert(SiteStats {
+            instance,
+            count: 0,
+            allocated: 0,
+            live: 0,
+            peak: 0,
+        });
+        stats.count += 1;
+        stats.allocated += size;
+        stats.live += size;
+        stats.peak = stats.peak.max(stats.live);
+        self.live.insert(id, (site, size));
+        self.total += size;
+        if self.peak.as_ref().is_none_or(|peak| self.total > peak.total + peak.total / 100) {
+            self.peak =
+                Some(Snapshot { is_peak: true, ..self.snapshot(time, /* detailed */ true) });
+        }
+        self.maybe_snapshot(time);
+    }
+
+    /// Record that a heap allocation was freed.
+    pub fn record_dealloc(&mut self, id: AllocId, time: u64) {
+        let Some((site, size)) = self.live.remove(&id) else { return };
+        self.sites.get_mut(&site).unwrap().live -= size;
+        self.total -= size;
+        self.maybe_snapshot(time);
+    }
+
+    fn snapshot(&self, time: u64, detailed: bool) -> Snapshot {
+        let sites = detailed.then(|| {
+            let mut sites: Vec<(Span, u64)> = self
+                .sites
+                .iter()
+                .filter(|(_, stats)| stats.live > 0)
+                .map(|(&site, stats)| (site, stats.live))
+                .collect();
+            // Sort by site as well, so that the output is deterministic.
+            sites.sort_by(|(site_a, a), (site_b, b)| b.cmp(a).then(site_a.cmp(site_b)));
+            sites
+        });
+        Snapshot { time, total: self.total, sites, is_peak: false }
+    }
+
+    fn maybe_snapshot(&mut self, time: u64) {
+        if time < self.snapshots.last().unwrap().time + self.interval {
+            return;
+        }
+        let detailed = self.snapshots.len() % DETAILED_FREQ == 0;
+        self.snapshots.push(self.snapshot(time, detailed));
+        if self.snapshots.len() == MAX_SNAPSHOTS {
+            // Keep every other snapshot.
+            let mut idx = 0;
+            self.snapshots.retain(|_| {
+                idx += 1;
+                idx % 2 == 1
+            });
+            self.interval *= 2;
+        }
+    }
+
+    /// Write the profile, with a final detailed snapshot at `time`.
+    pub fn write_report(&self, tcx: TyCtxt<'tcx>, time: u64) -> io::Result<()> {
+        let mut snapshots = self.snapshots.clone();
+        snapshots.extend(self.peak.clone());
+        snapshots.push(self.snapshot(time, /* detailed */ true));
+        snapshots.sort_by_key(|snapshot| snapshot.time);
+
+        let location = |site: Span| {
+            let stats = &self.sites[&site];
+            let function = stats.instance.map_or_else(String::new, |instance| instance.to_string());
+            (function, tcx.sess.source_map().span_to_embeddable_string(site))
+        };
+        let report = match self.format {
+            HeapProfileFormat::Massif => {
+                let cmd = tcx.crate_name(rustc_hir::def_id::LOCAL_CRATE);
+                let mut report = format!("desc: -Zmiri-heap-profile\ncmd: {cmd}\ntime_unit: i\n");
+                for (idx, snapshot) in snapshots.iter().enumerate() {
+                    writeln!(report, "#-----------\nsnapshot={idx}\n#-----------").unwrap();
+                    writeln!(report, "time={}\nmem_heap_B={}", snapshot.time, snapshot.total)
+                        .unwrap();
+                    writeln!(report, "mem_heap_extra_B=0\nmem_stacks_B=0").unwrap();
+                    let Some(sites) = &snapshot.sites else {
+                        writeln!(report, "heap_tree=empty").unwrap();
+                        continue;
+                    };
+                    let tree = if snapshot.is_peak { "peak" } else { "detailed" };
+                    writeln!(report, "heap_tree={tree}").unwrap();
+                    writeln!(
+                        report,
+                        "n{}: {} (heap allocation functions) malloc/new/new[], --alloc-fns, etc.",
+                        sites.len(),
+                        snapshot.total
+                    )
+                    .unwrap();
+                    for &(site, bytes) in sites {
+                        let (function, location) = location(site);
+                        writeln!(report, " n0: {bytes} 0x0: {function} ({location})").unwrap();
+                    }
+                }
+                report
+            }
+            HeapProfileFormat::Json => {
+                let mut sites: Vec<_> = self.sites.iter().collect();
+                sites.sort_by(|(site_a, a), (site_b, b)| {
+                    b.peak.cmp(&a.peak).then(site_a.cmp(site_b))
+                });
+                let sites: Vec<_> = sites
+                    .into_iter()
+                    .map(|(&site, stats)| {
+                        let (function, location) = location(site);
+                        json!({
+                            "function": function,
+                            "location": location,
+                            "count": stats.count,
+                            "allocated_bytes": stats.allocated,
+                            "peak_bytes": stats.peak,
+                            "live_bytes_at_exit": stats.live,
+                        })
+                    })
+                    .collect();
+                let snapshots: Vec<_> = snapshots
+                    .iter()
+                    .map(|snapshot| {
+                        let sites = snapshot.sites.as_ref().map(|sites| {
+                            sites
+                                .iter()
+                                .map(|&(site, bytes)| {
+                                    let (function, location) = location(site);
+                                    json!({
+                                        "function": function,
+                                        "location": location,
+                                        "bytes": bytes,
+                                    })
+                                })
+                                .collect::<Vec<_>>()
+                        });
+                        json!({ "time": snapshot.time, "bytes": snapshot.total, "sites": sites })
+                    })
+                    .collect();
+                let peak = self
+                    .peak
+                    .as_ref()
+                    .map(|peak| json!({ "time": peak.time, "bytes": peak.total }));
+                let profile = json!({
+                    "time_unit": "basic blocks",
+                    "peak": peak,
+                    "snapshots": snapshots,
+                    "sites": sites,
+                });
+                format!("{profile:#}\n")
+            }
+        };
+        if let Some(dir) = self.out.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.out, report)
+    }
+}
@@ -24,7 +24,9 @@ use rustc_middle::ty::{
 };
 use rustc_session::config::CrateType;
 use rustc_span::{sym, Span, Symbol};
-use rustc_target::abi::{Align, FieldIdx, FieldsShape, Size, Variants};
+use rustc_target::abi::{
+    Abi as LayoutAbi, Align, FieldIdx, FieldsShape, Primitive, Size, Variants,
+};
 use rustc_target::spec::abi::Abi;
 
 use crate::*;
@@ -243,9 +245,16 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
     /// Gets an instance for a path; fails gracefully if the path does not exist.
     fn try_resolve_path(&self, path: &[&str], namespace: Namespace) -> Option<ty::Instance<'tcx>> {
-        let tcx = self.eval_context_ref().tcx.tcx;
-        let did = try_resolve_did(tcx, path, Some(namespace))?;
-        Some(ty::Instance::mono(tcx, did))
+        let this = self.eval_context_ref();
+        let tcx = this.tcx.tcx;
+        // Looking through all the items of a module is slow, and shims look up the same
+        // items (e.g. `libc` constants) over and over.
+        *this.machine.path_cache.borrow_mut().entry((path.join("::"), namespace)).or_insert_with(
+            || {
+                let did = try_resolve_did(tcx, path, Some(namespace))?;
+                Some(ty::Instance::mono(tcx, did))
+            },
+        )
     }
 
     /// Gets an instance for a path.
@@ -449,6 +458,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         } else {
             let rng = this.machine.rng.get_mut();
             rng.fill_bytes(&mut data);
+            this.machine.log_nondeterminism("random", || format!("generated {len} random bytes"));
         }
 
         this.write_bytes_ptr(ptr, data.iter().copied())
@@ -935,6 +945,15 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
     /// Read a sequence of bytes until the first null terminator.
     fn read_c_str<'a>(&'a self, ptr: Pointer) -> InterpResult<'tcx, &'a [u8]>
+    where
+        'tcx: 'a,
+    {
+        self.read_c_str_bounded(ptr, u64::MAX)
+    }
+
+    /// Read a sequence of bytes until the first null terminator, but at most `max_len` bytes.
+    /// Memory after the first `max_len` bytes is never accessed, so it does not have to be valid.
+    fn read_c_str_bounded<'a>(&'a self, ptr: Pointer, max_len: u64) -> InterpResult<'tcx, &'a [u8]>
     where
         'tcx: 'a,
     {
@@ -943,7 +962,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
         // Step 1: determine the length.
         let mut len = Size::ZERO;
-        loop {
+        while len.bytes() < max_len {
             // FIXME: We are re-getting the allocation each time around the loop.
             // Would be nice if we could somehow "extend" an existing AllocRange.
             let alloc = this.get_ptr_alloc(ptr.offset(len, this)?, size1)?.unwrap(); // not a ZST, so we will get a result
@@ -1102,6 +1121,27 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         crate_name == "std" || crate_name == "std_miri_test"
     }
 
+    /// Whether the active thread is capturing or printing a backtrace with the standard library:
+    /// either one the program captured with `std::backtrace`, or the one printed on a panic.
+    /// Note that `std::sys::backtrace` also contains `__rust_begin_short_backtrace`, which is on
+    /// the stack of every thread, so we only look for the code that captures and prints backtraces.
+    fn in_std_backtrace(&self) -> bool {
+        let this = self.eval_context_ref();
+        let tcx = this.tcx.tcx;
+        this.active_thread_stack().iter().any(|frame| {
+            let path = tcx.def_path(frame.instance.def_id());
+            let crate_name = tcx.crate_name(path.krate);
+            if !matches!(crate_name.as_str(), "std" | "std_miri_test") {
+                return false;
+            }
+            let names: Vec<_> =
+                path.data.iter().filter_map(|elem| elem.data.get_opt_name()).collect();
+            let names: Vec<_> = names.iter().map(|name| name.as_str()).collect();
+            matches!(names.first(), Some(&("backtrace" | "backtrace_rs")))
+                || names.starts_with(&["sys", "backtrace", "_print"])
+        })
+    }
+
     /// Handler that should be called when an unsupported foreign item is encountered.
     /// This function will either panic within the context of the emulated application
     /// or return an error in the Miri process context
@@ -1156,6 +1196,30 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         check_arg_count(args)
     }
 
+    /// Like `check_shim`, but for variadic functions: the first `N` arguments are the fixed ones,
+    /// the others can be read one after the other from the returned [`VarArgs`].
+    fn check_shim_variadic<'a, const N: usize>(
+        &mut self,
+        abi: Abi,
+        exp_abi: Abi,
+        link_name: Symbol,
+        args: &'a [OpTy<'tcx>],
+    ) -> InterpResult<'tcx, (&'a [OpTy<'tcx>; N], VarArgs<'a, 'tcx>)>
+    where
+        &'a [OpTy<'tcx>; N]: TryFrom<&'a [OpTy<'tcx>]>,
+    {
+        self.check_abi_and_shim_symbol_clash(abi, exp_abi, link_name)?;
+        if args.len() < N {
+            throw_ub_format!(
+                "incorrect number of arguments for `{link_name}`: got {}, expected at least {N}",
+                args.len()
+            );
+        }
+        let (fixed, varargs) = args.split_at(N);
+        let Ok(fixed) = fixed.try_into() else { unreachable!() };
+        Ok((fixed, VarArgs { link_name, consumed: N, args: varargs }))
+    }
+
     /// Mark a machine allocation that was just created as immutable.
     fn mark_immutable(&mut self, mplace: &MPlaceTy<'tcx>) {
         let this = self.eval_context_mut();
@@ -1339,7 +1403,9 @@ impl<'tcx> MiriMachine<'tcx> {
     /// This is the source of truth for the `is_user_relevant` flag in our `FrameExtra`.
     pub fn is_user_relevant(&self, frame: &Frame<'tcx, Provenance>) -> bool {
         let def_id = frame.instance.def_id();
-        (def_id.is_local() || self.local_crates.contains(&def_id.krate))
+        (def_id.is_local()
+            || self.local_crates.contains(&def_id.krate)
+            || self.is_backtrace_included(def_id))
             && !frame.instance.def.requires_caller_location(self.tcx)
     }
 }
@@ -1357,6 +1423,90 @@ where
     throw_ub_format!("incorrect number of arguments: got {}, expected {}", args.len(), N)
 }
 
+/// The variadic arguments of a call to a shim, see `check_shim_variadic`. Like with `va_arg` in C,
+/// the shim reads them one after the other, as the types that it expects.
+pub struct VarArgs<'a, 'tcx> {
+    link_name: Symbol,
+    /// How many arguments (including the fixed ones) were read so far.
+    consumed: usize,
+    /// The arguments that were not read yet.
+    args: &'a [OpTy<'tcx>],
+}
+
+impl<'a, 'tcx> VarArgs<'a, 'tcx> {
+    /// How many arguments were not read yet.
+    pub fn remaining(&self) -> usize {
+        self.args.len()
+    }
+
+    /// Reads the next argument as a value of type `layout`. It is UB if there are no arguments
+    /// left, or if the argument that was passed is not compatible with `layout` in the C calling
+    /// conventions: integers smaller than `int` are promoted to `int`, so they are read from an
+    /// `int`; apart from that, integers and pointers must have the same size as `layout`, and
+    /// floats must be floats of the same size. Integers and pointers of the same size are
+    /// interchangeable (`syscall`, for instance, reads all its arguments as `long`).
+    pub fn next(
+        &mut self,
+        ecx: &MiriInterpCx<'tcx>,
+        layout: TyAndLayout<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let arg = self.next_op(layout)?;
+        let scalar = ecx.read_scalar(arg)?;
+        if arg.layout.size == layout.size {
+            Ok(scalar)
+        } else {
+            // This is a promoted integer.
+            let bits = scalar.to_bits(arg.layout.size)?;
+            Ok(Scalar::from_uint(layout.size.truncate(bits), layout.size))
+        }
+    }
+
+    /// Like `next`, but returns the argument as it was passed instead of reading it. Its size can
+    /// differ from `layout` if it is a promoted integer.
+    pub fn next_op(&mut self, layout: TyAndLayout<'tcx>) -> InterpResult<'tcx, &'a OpTy<'tcx>> {
+        let Some((arg, rest)) = self.args.split_first() else {
+            throw_ub_format!(
+                "incorrect number of arguments for `{}`: got {}, expected at least {}",
+                self.link_name,
+                self.consumed,
+                self.consumed + 1,
+            );
+        };
+        self.args = rest;
+        self.consumed += 1;
+
+        let (LayoutAbi::Scalar(expected), LayoutAbi::Scalar(actual)) = (layout.abi, arg.layout.abi)
+        else {
+            throw_unsup_format!(
+                "only scalar variadic arguments are supported, but `{}` was passed an argument of type `{}`",
+                self.link_name,
+                arg.layout.ty
+            );
+        };
+        // All targets with variadic shims have a 32-bit `int`.
+        let int_size = Size::from_bits(32);
+        let compatible = match (expected.primitive(), actual.primitive()) {
+            (Primitive::Int(..), Primitive::Int(..)) =>
+                arg.layout.size == layout.size.max(int_size),
+            (
+                Primitive::Int(..) | Primitive::Pointer(_),
+                Primitive::Int(..) | Primitive::Pointer(_),
+            ) => arg.layout.size == layout.size,
+            (Primitive::Float(_), Primitive::Float(_)) => arg.layout.size == layout.size,
+            _ => false,
+        };
+        if !compatible {
+            throw_ub_format!(
+                "`{}` expected a variadic argument of type `{}`, but got an argument of type `{}`",
+                self.link_name,
+                layout.ty,
+                arg.layout.ty
+            );
+        }
+        Ok(arg)
+    }
+}
+
 pub fn isolation_abort_error<'tcx>(name: &str) -> InterpResult<'tcx> {
     throw_machine_stop!(TerminationInfo::UnsupportedInIsolation(format!(
         "{name} not available when isolation is enabled",
@@ -1413,3 +1563,26 @@ pub(crate) fn windows_check_buffer_size((success, len): (bool, u64)) -> u32 {
         u32::try_from(len).unwrap()
     }
 }
+
+/// Matches `text` against `pattern`, where `*` in the pattern matches any sequence of characters.
+pub(crate) fn glob_matches(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    // `split` always yields at least one part.
+    let first = parts.next().unwrap();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts = parts.peekable();
+    while let Some(part) = parts.next() {
+        if parts.peek().is_none() {
+            // The last part must match the end of the text.
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+    // There was no `*` at all.
+    rest.is_empty()
+}
@@ -122,6 +122,21 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
 impl<'tcx> EvalContextPrivExt<'tcx> for MiriInterpCx<'tcx> {}
 trait EvalContextPrivExt<'tcx>: MiriInterpCxExt<'tcx> {
+    /// Checks that the target supports atomic accesses of the size of `place`.
+    fn check_atomic_width(&self, place: &MPlaceTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_ref();
+        let width = place.layout.size.bits();
+        if let Some(max_atomic_width) = this.machine.max_atomic_width
+            && width > max_atomic_width
+        {
+            throw_unsup_format!(
+                "{width}-bit atomic accesses are not supported on this target \
+                (the maximal atomic width is {max_atomic_width} bits)",
+            );
+        }
+        Ok(())
+    }
+
     fn atomic_load(
         &mut self,
         args: &[OpTy<'tcx>],
@@ -132,6 +147,7 @@ trait EvalContextPrivExt<'tcx>: MiriInterpCxExt<'tcx> {
 
         let [place] = check_arg_count(args)?;
         let place = this.deref_pointer(place)?;
+        this.check_atomic_width(&place)?;
 
         // Perform atomic load.
         let val = this.read_scalar_atomic(&place, atomic)?;
@@ -145,6 +161,7 @@ trait EvalContextPrivExt<'tcx>: MiriInterpCxExt<'tcx> {
 
         let [place, val] = check_arg_count(args)?;
         let place = this.deref_pointer(place)?;
+        this.check_atomic_width(&place)?;
 
         // Perform regular load.
         let val = this.read_scalar(val)?;
@@ -186,6 +203,7 @@ trait EvalContextPrivExt<'tcx>: MiriInterpCxExt<'tcx> {
 
         let [place, rhs] = check_arg_count(args)?;
         let place = this.deref_pointer(place)?;
+        this.check_atomic_width(&place)?;
         let rhs = this.read_immediate(rhs)?;
 
         if !place.layout.ty.is_integral() && !place.layout.ty.is_unsafe_ptr() {
@@ -227,6 +245,7 @@ trait EvalContextPrivExt<'tcx>: MiriInterpCxExt<'tcx> {
 
         let [place, new] = check_arg_count(args)?;
         let place = this.deref_pointer(place)?;
+        this.check_atomic_width(&place)?;
         let new = this.read_scalar(new)?;
 
         let old = this.atomic_exchange_scalar(&place, new, atomic)?;
@@ -246,6 +265,7 @@ trait EvalContextPrivExt<'tcx>: MiriInterpCxExt<'tcx> {
 
         let [place, expect_old, new] = check_arg_count(args)?;
         let place = this.deref_pointer(place)?;
+        this.check_atomic_width(&place)?;
         let expect_old = this.read_immediate(expect_old)?; // read as immediate for the sake of `binary_op()`
         let new = this.read_scalar(new)?;
 
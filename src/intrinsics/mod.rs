@@ -6,7 +6,8 @@ mod simd;
 use std::iter;
 
 use rand::Rng;
-use rustc_apfloat::{Float, Round};
+use rustc_apfloat::ieee::{Half, Single};
+use rustc_apfloat::{Float, FloatConvert, Round};
 use rustc_middle::ty::layout::LayoutOf;
 use rustc_middle::{
     mir,
@@ -155,10 +156,19 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let [arg] = check_arg_count(args)?;
                 this.validate_operand(arg)?;
                 let branch: bool = this.machine.rng.get_mut().gen();
+                this.machine.log_nondeterminism("intrinsics", || {
+                    format!("`is_val_statically_known` returned {branch}")
+                });
                 this.write_scalar(Scalar::from_bool(branch), dest)?;
             }
 
             // Floating-point operations
+            "fabsf16" => {
+                let [f] = check_arg_count(args)?;
+                let f = this.read_scalar(f)?.to_f16()?;
+                // This is a "bitwise" operation, so there's no NaN non-determinism.
+                this.write_scalar(Scalar::from(f.abs()), dest)?;
+            }
             "fabsf32" => {
                 let [f] = check_arg_count(args)?;
                 let f = this.read_scalar(f)?.to_f32()?;
@@ -171,7 +181,28 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 // This is a "bitwise" operation, so there's no NaN non-determinism.
                 this.write_scalar(Scalar::from_f64(f.abs()), dest)?;
             }
+            "fabsf128" => {
+                let [f] = check_arg_count(args)?;
+                let f = this.read_scalar(f)?.to_f128()?;
+                // This is a "bitwise" operation, so there's no NaN non-determinism.
+                this.write_scalar(Scalar::from(f.abs()), dest)?;
+            }
 
+            "floorf16" | "ceilf16" | "truncf16" | "roundf16" | "rintf16" => {
+                let [f] = check_arg_count(args)?;
+                let f = this.read_scalar(f)?.to_f16()?;
+                let mode = match intrinsic_name {
+                    "floorf16" => Round::TowardNegative,
+                    "ceilf16" => Round::TowardPositive,
+                    "truncf16" => Round::TowardZero,
+                    "roundf16" => Round::NearestTiesToAway,
+                    "rintf16" => Round::NearestTiesToEven,
+                    _ => bug!(),
+                };
+                let res = f.round_to_integral(mode).value;
+                let res = this.adjust_nan(res, &[f]);
+                this.write_scalar(res, dest)?;
+            }
             "floorf32" | "ceilf32" | "truncf32" | "roundf32" | "rintf32" => {
                 let [f] = check_arg_count(args)?;
                 let f = this.read_scalar(f)?.to_f32()?;
@@ -202,7 +233,60 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let res = this.adjust_nan(res, &[f]);
                 this.write_scalar(res, dest)?;
             }
+            "floorf128" | "ceilf128" | "truncf128" | "roundf128" | "rintf128" => {
+                let [f] = check_arg_count(args)?;
+                let f = this.read_scalar(f)?.to_f128()?;
+                let mode = match intrinsic_name {
+                    "floorf128" => Round::TowardNegative,
+                    "ceilf128" => Round::TowardPositive,
+                    "truncf128" => Round::TowardZero,
+                    "roundf128" => Round::NearestTiesToAway,
+                    "rintf128" => Round::NearestTiesToEven,
+                    _ => bug!(),
+                };
+                let res = f.round_to_integral(mode).value;
+                let res = this.adjust_nan(res, &[f]);
+                this.write_scalar(res, dest)?;
+            }
 
+            #[rustfmt::skip]
+            | "sinf16"
+            | "cosf16"
+            | "sqrtf16"
+            | "expf16"
+            | "exp2f16"
+            | "logf16"
+            | "log10f16"
+            | "log2f16"
+            => {
+                let [f] = check_arg_count(args)?;
+                let f = this.read_scalar(f)?.to_f16()?;
+                // Using host floats (but it's fine, these operations do not have guaranteed precision).
+                // There are no `f16` host floats that we could rely on, so we compute with `f32`,
+                // which represents every `f16` exactly. For `sqrt`, rounding the `f32` result to
+                // `f16` is still correctly rounded, since `f32` has more than twice the precision.
+                let f_host = f.convert::<Single>(&mut false).value.to_host();
+                let res = match intrinsic_name {
+                    "sinf16" => f_host.sin(),
+                    "cosf16" => f_host.cos(),
+                    "sqrtf16" => f_host.sqrt(),
+                    "expf16" => f_host.exp(),
+                    "exp2f16" => f_host.exp2(),
+                    "logf16" => f_host.ln(),
+                    "log10f16" => f_host.log10(),
+                    "log2f16" => f_host.log2(),
+                    _ => bug!(),
+                };
+                let res: Half = res.to_soft().convert(&mut false).value;
+                // `sqrt` is correctly rounded, the other operations are not.
+                let res = if intrinsic_name.starts_with("sqrt") {
+                    res
+                } else {
+                    this.apply_random_float_error_ulp(res, intrinsic_name)
+                };
+                let res = this.adjust_nan(res, &[f]);
+                this.write_scalar(res, dest)?;
+            }
             #[rustfmt::skip]
             | "sinf32"
             | "cosf32"
@@ -229,6 +313,12 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     _ => bug!(),
                 };
                 let res = res.to_soft();
+                // `sqrt` is correctly rounded, the other operations are not.
+                let res = if intrinsic_name.starts_with("sqrt") {
+                    res
+                } else {
+                    this.apply_random_float_error_ulp(res, intrinsic_name)
+                };
                 let res = this.adjust_nan(res, &[f]);
                 this.write_scalar(res, dest)?;
             }
@@ -258,10 +348,28 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     _ => bug!(),
                 };
                 let res = res.to_soft();
+                // `sqrt` is correctly rounded, the other operations are not.
+                let res = if intrinsic_name.starts_with("sqrt") {
+                    res
+                } else {
+                    this.apply_random_float_error_ulp(res, intrinsic_name)
+                };
                 let res = this.adjust_nan(res, &[f]);
                 this.write_scalar(res, dest)?;
             }
 
+            "minnumf16" | "maxnumf16" | "copysignf16" => {
+                let [a, b] = check_arg_count(args)?;
+                let a = this.read_scalar(a)?.to_f16()?;
+                let b = this.read_scalar(b)?.to_f16()?;
+                let res = match intrinsic_name {
+                    "minnumf16" => this.adjust_nan(a.min(b), &[a, b]),
+                    "maxnumf16" => this.adjust_nan(a.max(b), &[a, b]),
+                    "copysignf16" => a.copy_sign(b), // bitwise, no NaN adjustments
+                    _ => bug!(),
+                };
+                this.write_scalar(Scalar::from(res), dest)?;
+            }
             "minnumf32" | "maxnumf32" | "copysignf32" => {
                 let [a, b] = check_arg_count(args)?;
                 let a = this.read_scalar(a)?.to_f32()?;
@@ -286,7 +394,29 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 };
                 this.write_scalar(Scalar::from_f64(res), dest)?;
             }
+            "minnumf128" | "maxnumf128" | "copysignf128" => {
+                let [a, b] = check_arg_count(args)?;
+                let a = this.read_scalar(a)?.to_f128()?;
+                let b = this.read_scalar(b)?.to_f128()?;
+                let res = match intrinsic_name {
+                    "minnumf128" => this.adjust_nan(a.min(b), &[a, b]),
+                    "maxnumf128" => this.adjust_nan(a.max(b), &[a, b]),
+                    "copysignf128" => a.copy_sign(b), // bitwise, no NaN adjustments
+                    _ => bug!(),
+                };
+                this.write_scalar(Scalar::from(res), dest)?;
+            }
 
+            "fmaf16" => {
+                let [a, b, c] = check_arg_count(args)?;
+                let a = this.read_scalar(a)?.to_f16()?;
+                let b = this.read_scalar(b)?.to_f16()?;
+                let c = this.read_scalar(c)?.to_f16()?;
+                // There are no host floats for this type, so we have to use soft-floats.
+                let res = a.mul_add(b, c).value;
+                let res = this.adjust_nan(res, &[a, b, c]);
+                this.write_scalar(res, dest)?;
+            }
             "fmaf32" => {
                 let [a, b, c] = check_arg_count(args)?;
                 let a = this.read_scalar(a)?.to_f32()?;
@@ -307,13 +437,36 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let res = this.adjust_nan(res, &[a, b, c]);
                 this.write_scalar(res, dest)?;
             }
+            "fmaf128" => {
+                let [a, b, c] = check_arg_count(args)?;
+                let a = this.read_scalar(a)?.to_f128()?;
+                let b = this.read_scalar(b)?.to_f128()?;
+                let c = this.read_scalar(c)?.to_f128()?;
+                // There are no host floats for this type, so we have to use soft-floats.
+                let res = a.mul_add(b, c).value;
+                let res = this.adjust_nan(res, &[a, b, c]);
+                this.write_scalar(res, dest)?;
+            }
 
+            "powf16" => {
+                let [f1, f2] = check_arg_count(args)?;
+                let f1 = this.read_scalar(f1)?.to_f16()?;
+                let f2 = this.read_scalar(f2)?.to_f16()?;
+                // Using host floats (but it's fine, this operation does not have guaranteed precision).
+                let f1_host = f1.convert::<Single>(&mut false).value.to_host();
+                let f2_host = f2.convert::<Single>(&mut false).value.to_host();
+                let res: Half = f1_host.powf(f2_host).to_soft().convert(&mut false).value;
+                let res = this.apply_random_float_error_ulp(res, intrinsic_name);
+                let res = this.adjust_nan(res, &[f1, f2]);
+                this.write_scalar(res, dest)?;
+            }
             "powf32" => {
                 let [f1, f2] = check_arg_count(args)?;
                 let f1 = this.read_scalar(f1)?.to_f32()?;
                 let f2 = this.read_scalar(f2)?.to_f32()?;
                 // Using host floats (but it's fine, this operation does not have guaranteed precision).
                 let res = f1.to_host().powf(f2.to_host()).to_soft();
+                let res = this.apply_random_float_error_ulp(res, intrinsic_name);
                 let res = this.adjust_nan(res, &[f1, f2]);
                 this.write_scalar(res, dest)?;
             }
@@ -323,16 +476,29 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let f2 = this.read_scalar(f2)?.to_f64()?;
                 // Using host floats (but it's fine, this operation does not have guaranteed precision).
                 let res = f1.to_host().powf(f2.to_host()).to_soft();
+                let res = this.apply_random_float_error_ulp(res, intrinsic_name);
                 let res = this.adjust_nan(res, &[f1, f2]);
                 this.write_scalar(res, dest)?;
             }
 
+            "powif16" => {
+                let [f, i] = check_arg_count(args)?;
+                let f = this.read_scalar(f)?.to_f16()?;
+                let i = this.read_scalar(i)?.to_i32()?;
+                // Using host floats (but it's fine, this operation does not have guaranteed precision).
+                let f_host = f.convert::<Single>(&mut false).value.to_host();
+                let res: Half = f_host.powi(i).to_soft().convert(&mut false).value;
+                let res = this.apply_random_float_error_ulp(res, intrinsic_name);
+                let res = this.adjust_nan(res, &[f]);
+                this.write_scalar(res, dest)?;
+            }
             "powif32" => {
                 let [f, i] = check_arg_count(args)?;
                 let f = this.read_scalar(f)?.to_f32()?;
                 let i = this.read_scalar(i)?.to_i32()?;
                 // Using host floats (but it's fine, this operation does not have guaranteed precision).
                 let res = f.to_host().powi(i).to_soft();
+                let res = this.apply_random_float_error_ulp(res, intrinsic_name);
                 let res = this.adjust_nan(res, &[f]);
                 this.write_scalar(res, dest)?;
             }
@@ -342,6 +508,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let i = this.read_scalar(i)?.to_i32()?;
                 // Using host floats (but it's fine, this operation does not have guaranteed precision).
                 let res = f.to_host().powi(i).to_soft();
+                let res = this.apply_random_float_error_ulp(res, intrinsic_name);
                 let res = this.adjust_nan(res, &[f]);
                 this.write_scalar(res, dest)?;
             }
@@ -392,10 +559,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                         bug!("float_finite: non-float input type {}", x.layout.ty)
                     };
                     Ok(match fty {
-                        FloatTy::F16 => unimplemented!("f16_f128"),
+                        FloatTy::F16 => x.to_scalar().to_f16()?.is_finite(),
                         FloatTy::F32 => x.to_scalar().to_f32()?.is_finite(),
                         FloatTy::F64 => x.to_scalar().to_f64()?.is_finite(),
-                        FloatTy::F128 => unimplemented!("f16_f128"),
+                        FloatTy::F128 => x.to_scalar().to_f128()?.is_finite(),
                     })
                 };
                 match (float_finite(&a)?, float_finite(&b)?) {
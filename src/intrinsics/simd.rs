@@ -1,6 +1,7 @@
 use either::Either;
 
-use rustc_apfloat::{Float, Round};
+use rustc_apfloat::ieee::{Half, Single};
+use rustc_apfloat::{Float, FloatConvert, Round};
 use rustc_middle::ty::layout::{HasParamEnv, LayoutOf};
 use rustc_middle::{mir, ty, ty::FloatTy};
 use rustc_span::{sym, Symbol};
@@ -87,17 +88,16 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                             this.unary_op(mir_op, &op)?.to_scalar()
                         }
                         Op::Abs => {
-                            // Works for f32 and f64.
                             let ty::Float(float_ty) = op.layout.ty.kind() else {
                                 span_bug!(this.cur_span(), "{} operand is not a float", intrinsic_name)
                             };
                             let op = op.to_scalar();
                             // "Bitwise" operation, no NaN adjustments
                             match float_ty {
-                                FloatTy::F16 => unimplemented!("f16_f128"),
+                                FloatTy::F16 => Scalar::from(op.to_f16()?.abs()),
                                 FloatTy::F32 => Scalar::from_f32(op.to_f32()?.abs()),
                                 FloatTy::F64 => Scalar::from_f64(op.to_f64()?.abs()),
-                                FloatTy::F128 => unimplemented!("f16_f128"),
+                                FloatTy::F128 => Scalar::from(op.to_f128()?.abs()),
                             }
                         }
                         Op::HostOp(host_op) => {
@@ -106,7 +106,31 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                             };
                             // Using host floats (but it's fine, these operations do not have guaranteed precision).
                             match float_ty {
-                                FloatTy::F16 => unimplemented!("f16_f128"),
+                                FloatTy::F16 => {
+                                    let f = op.to_scalar().to_f16()?;
+                                    // There are no `f16` host floats, so we compute with `f32`.
+                                    let f_host = f.convert::<Single>(&mut false).value.to_host();
+                                    let res = match host_op {
+                                        "fsqrt" => f_host.sqrt(),
+                                        "fsin" => f_host.sin(),
+                                        "fcos" => f_host.cos(),
+                                        "fexp" => f_host.exp(),
+                                        "fexp2" => f_host.exp2(),
+                                        "flog" => f_host.ln(),
+                                        "flog2" => f_host.log2(),
+                                        "flog10" => f_host.log10(),
+                                        _ => bug!(),
+                                    };
+                                    let res: Half = res.to_soft().convert(&mut false).value;
+                                    // `sqrt` is correctly rounded, the other operations are not.
+                                    let res = if host_op == "fsqrt" {
+                                        res
+                                    } else {
+                                        this.apply_random_float_error_ulp(res, host_op)
+                                    };
+                                    let res = this.adjust_nan(res, &[f]);
+                                    Scalar::from(res)
+                                }
                                 FloatTy::F32 => {
                                     let f = op.to_scalar().to_f32()?;
                                     let f_host = f.to_host();
@@ -122,6 +146,12 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                                         _ => bug!(),
                                     };
                                     let res = res.to_soft();
+                                    // `sqrt` is correctly rounded, the other operations are not.
+                                    let res = if host_op == "fsqrt" {
+                                        res
+                                    } else {
+                                        this.apply_random_float_error_ulp(res, host_op)
+                                    };
                                     let res = this.adjust_nan(res, &[f]);
                                     Scalar::from(res)
                                 }
@@ -140,10 +170,17 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                                         _ => bug!(),
                                     };
                                     let res = res.to_soft();
+                                    // `sqrt` is correctly rounded, the other operations are not.
+                                    let res = if host_op == "fsqrt" {
+                                        res
+                                    } else {
+                                        this.apply_random_float_error_ulp(res, host_op)
+                                    };
                                     let res = this.adjust_nan(res, &[f]);
                                     Scalar::from(res)
                                 }
-                                FloatTy::F128 => unimplemented!("f16_f128"),
+                                FloatTy::F128 =>
+                                    throw_unsup_format!("`simd_{host_op}` is not supported for `f128`"),
                             }
                         }
                         Op::Round(rounding) => {
@@ -151,7 +188,12 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                                 span_bug!(this.cur_span(), "{} operand is not a float", intrinsic_name)
                             };
                             match float_ty {
-                                FloatTy::F16 => unimplemented!("f16_f128"),
+                                FloatTy::F16 => {
+                                    let f = op.to_scalar().to_f16()?;
+                                    let res = f.round_to_integral(rounding).value;
+                                    let res = this.adjust_nan(res, &[f]);
+                                    Scalar::from(res)
+                                }
                                 FloatTy::F32 => {
                                     let f = op.to_scalar().to_f32()?;
                                     let res = f.round_to_integral(rounding).value;
@@ -164,7 +206,12 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                                     let res = this.adjust_nan(res, &[f]);
                                     Scalar::from_f64(res)
                                 }
-                                FloatTy::F128 => unimplemented!("f16_f128"),
+                                FloatTy::F128 => {
+                                    let f = op.to_scalar().to_f128()?;
+                                    let res = f.round_to_integral(rounding).value;
+                                    let res = this.adjust_nan(res, &[f]);
+                                    Scalar::from(res)
+                                }
                             }
                         }
                         Op::Numeric(name) => {
@@ -306,13 +353,20 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     let c = this.read_scalar(&this.project_index(&c, i)?)?;
                     let dest = this.project_index(&dest, i)?;
 
-                    // Works for f32 and f64.
-                    // FIXME: using host floats to work around https://github.com/rust-lang/miri/issues/2468.
+                    // FIXME: for f32 and f64, using host floats to work around https://github.com/rust-lang/miri/issues/2468.
                     let ty::Float(float_ty) = dest.layout.ty.kind() else {
                         span_bug!(this.cur_span(), "{} operand is not a float", intrinsic_name)
                     };
                     let val = match float_ty {
-                        FloatTy::F16 => unimplemented!("f16_f128"),
+                        FloatTy::F16 => {
+                            let a = a.to_f16()?;
+                            let b = b.to_f16()?;
+                            let c = c.to_f16()?;
+                            // There are no host floats for this type, so we have to use soft-floats.
+                            let res = a.mul_add(b, c).value;
+                            let res = this.adjust_nan(res, &[a, b, c]);
+                            Scalar::from(res)
+                        }
                         FloatTy::F32 => {
                             let a = a.to_f32()?;
                             let b = b.to_f32()?;
@@ -329,7 +383,15 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                             let res = this.adjust_nan(res, &[a, b, c]);
                             Scalar::from(res)
                         }
-                        FloatTy::F128 => unimplemented!("f16_f128"),
+                        FloatTy::F128 => {
+                            let a = a.to_f128()?;
+                            let b = b.to_f128()?;
+                            let c = c.to_f128()?;
+                            // There are no host floats for this type, so we have to use soft-floats.
+                            let res = a.mul_add(b, c).value;
+                            let res = this.adjust_nan(res, &[a, b, c]);
+                            Scalar::from(res)
+                        }
                     };
                     this.write_scalar(val, &dest)?;
                 }
@@ -814,7 +876,16 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let left = left.to_scalar();
         let right = right.to_scalar();
         Ok(match float_ty {
-            FloatTy::F16 => unimplemented!("f16_f128"),
+            FloatTy::F16 => {
+                let left = left.to_f16()?;
+                let right = right.to_f16()?;
+                let res = match op {
+                    MinMax::Min => left.min(right),
+                    MinMax::Max => left.max(right),
+                };
+                let res = this.adjust_nan(res, &[left, right]);
+                Scalar::from(res)
+            }
             FloatTy::F32 => {
                 let left = left.to_f32()?;
                 let right = right.to_f32()?;
@@ -835,7 +906,16 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let res = this.adjust_nan(res, &[left, right]);
                 Scalar::from_f64(res)
             }
-            FloatTy::F128 => unimplemented!("f16_f128"),
+            FloatTy::F128 => {
+                let left = left.to_f128()?;
+                let right = right.to_f128()?;
+                let res = match op {
+                    MinMax::Min => left.min(right),
+                    MinMax::Max => left.max(right),
+                };
+                let res = this.adjust_nan(res, &[left, right]);
+                Scalar::from(res)
+            }
         })
     }
 }
@@ -64,6 +64,8 @@ extern crate rustc_data_structures;
 extern crate rustc_errors;
 extern crate rustc_hir;
 extern crate rustc_index;
+extern crate rustc_interface;
+extern crate rustc_metadata;
 extern crate rustc_middle;
 extern crate rustc_session;
 extern crate rustc_span;
@@ -76,18 +78,28 @@ extern crate rustc_driver;
 mod alloc_addresses;
 mod alloc_bytes;
 mod borrow_tracker;
+mod call_trace;
+mod cheri;
 mod clock;
 mod concurrency;
+mod coverage;
+mod debugger;
 mod diagnostics;
+mod embed;
+mod error_codes;
 mod eval;
+mod heap_profile;
 mod helpers;
 mod intrinsics;
 mod machine;
 mod mono_hash_map;
 mod operator;
+mod parallel_tests;
 mod provenance_gc;
 mod range_map;
+mod server;
 mod shims;
+mod test_budget;
 
 // Establish a "crate-wide prelude": we often import `crate::*`.
 use rustc_middle::{bug, span_bug};
@@ -110,15 +122,21 @@ pub type PlaceTy<'tcx> = interpret::PlaceTy<'tcx, machine::Provenance>;
 pub type MPlaceTy<'tcx> = interpret::MPlaceTy<'tcx, machine::Provenance>;
 
 pub use crate::intrinsics::EvalContextExt as _;
+pub use crate::shims::atexit::{AtExitHandlers, EvalContextExt as _};
+pub use crate::shims::bench::EvalContextExt as _;
 pub use crate::shims::env::{EnvVars, EvalContextExt as _};
 pub use crate::shims::foreign_items::{DynSym, EvalContextExt as _};
+pub use crate::shims::hooks::{EvalContextExt as _, ShimHook};
 pub use crate::shims::os_str::EvalContextExt as _;
-pub use crate::shims::panic::{CatchUnwindData, EvalContextExt as _};
+pub use crate::shims::panic::{CatchUnwindData, EvalContextExt as _, UnwindPayload};
+pub use crate::shims::stubs::{parse_shim_stubs, ShimStub};
 pub use crate::shims::time::EvalContextExt as _;
 pub use crate::shims::tls::TlsData;
 pub use crate::shims::EmulateItemResult;
 
-pub use crate::alloc_addresses::{EvalContextExt as _, ProvenanceMode};
+pub use crate::alloc_addresses::{
+    alloc_number, renumber_allocs, EvalContextExt as _, ProvenanceMode,
+};
 pub use crate::alloc_bytes::MiriAllocBytes;
 pub use crate::borrow_tracker::stacked_borrows::{
     EvalContextExt as _, Item, Permission, Stack, Stacks,
@@ -127,6 +145,8 @@ pub use crate::borrow_tracker::tree_borrows::{EvalContextExt as _, Tree};
 pub use crate::borrow_tracker::{
     BorTag, BorrowTrackerMethod, CallId, EvalContextExt as _, RetagFields,
 };
+pub use crate::call_trace::{CallTrace, EvalContextExt as _};
+pub use crate::cheri::EvalContextExt as _;
 pub use crate::clock::{Clock, Instant};
 pub use crate::concurrency::{
     data_race::{AtomicFenceOrd, AtomicReadOrd, AtomicRwOrd, AtomicWriteOrd, EvalContextExt as _},
@@ -137,13 +157,20 @@ pub use crate::concurrency::{
         TimeoutAnchor, TimeoutClock, UnblockCallback,
     },
 };
+pub use crate::coverage::{Coverage, EvalContextExt as _};
+pub use crate::debugger::EvalContextExt as _;
 pub use crate::diagnostics::{
-    report_error, EvalContextExt as _, NonHaltingDiagnostic, TerminationInfo,
+    report_error, CapturedOutput, EvalContextExt as _, MiriError, MiriErrorKind,
+    NonHaltingDiagnostic, TerminationInfo,
 };
+pub use crate::embed::{entry_fn, override_queries, run_program, MiriCallbacks, MiriOutcome};
+pub use crate::error_codes::{explain_error_code, explain_hint, ERROR_CODES};
 pub use crate::eval::{
-    create_ecx, eval_entry, AlignmentCheck, BacktraceStyle, IsolatedOp, MiriConfig, RejectOpWith,
+    create_ecx, eval_entry, AlignmentCheck, BacktraceStyle, HeapProfileFormat, IsolatedOp,
+    MiriConfig, RaceReportFormat, RejectOpWith, SchedulingPolicy, StoreBufferEviction, TraceMode,
 };
-pub use crate::helpers::{AccessKind, EvalContextExt as _};
+pub use crate::heap_profile::HeapProfile;
+pub use crate::helpers::{AccessKind, EvalContextExt as _, VarArgs};
 pub use crate::machine::{
     AllocExtra, FrameExtra, MemoryKind, MiriInterpCx, MiriInterpCxExt, MiriMachine, MiriMemoryKind,
     PrimitiveLayouts, Provenance, ProvenanceExtra,
@@ -152,6 +179,7 @@ pub use crate::mono_hash_map::MonoHashMap;
 pub use crate::operator::EvalContextExt as _;
 pub use crate::provenance_gc::{EvalContextExt as _, LiveAllocs, VisitProvenance, VisitWith};
 pub use crate::range_map::RangeMap;
+pub use crate::test_budget::{EvalContextExt as _, RunningTest};
 
 /// Insert rustc arguments at the beginning of the argument list that Miri wants to be
 /// set per default, for maximal validation power.
@@ -1,11 +1,15 @@
 //! Global machine state as well as implementation of the interpreter engine
 //! `Machine` trait.
 
-use std::cell::RefCell;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
 use std::collections::hash_map::Entry;
 use std::fmt;
-use std::path::Path;
+use std::fs::File;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::Arc;
+use std::time::Duration;
 
 use rand::rngs::StdRng;
 use rand::Rng;
@@ -14,6 +18,8 @@ use rand::SeedableRng;
 use rustc_data_structures::fx::{FxHashMap, FxHashSet};
 #[allow(unused)]
 use rustc_data_structures::static_assert_size;
+use rustc_errors::DiagCtxt;
+use rustc_hir::def::Namespace;
 use rustc_middle::{
     mir,
     query::TyCtxtAt,
@@ -27,13 +33,15 @@ use rustc_span::def_id::{CrateNum, DefId};
 use rustc_span::{Span, SpanData, Symbol};
 use rustc_target::abi::{Align, Size};
 use rustc_target::spec::abi::Abi;
+use rustc_target::spec::TargetTriple;
 
 use crate::{
     concurrency::{
         data_race::{self, NaReadType, NaWriteType},
         weak_memory,
     },
-    *,
+    debugger::{DapServer, Frontend, GdbStub},
+    eval, *,
 };
 
 /// First real-time signal.
@@ -46,11 +54,6 @@ pub const SIGRTMIN: i32 = 34;
 /// `SIGRTMAX` - `SIGRTMIN` >= 8 (which is the value of `_POSIX_RTSIG_MAX`)
 pub const SIGRTMAX: i32 = 42;
 
-/// Each const has multiple addresses, but only this many. Since const allocations are never
-/// deallocated, choosing a new [`AllocId`] and thus base address for each evaluation would
-/// produce unbounded memory usage.
-const ADDRS_PER_CONST: usize = 16;
-
 /// Extra data stored with each stack frame
 pub struct FrameExtra<'tcx> {
     /// Extra data for the Borrow Tracker.
@@ -61,11 +64,19 @@ pub struct FrameExtra<'tcx> {
     /// we stop unwinding, use the `CatchUnwindData` to handle catching.
     pub catch_unwind: Option<CatchUnwindData<'tcx>>,
 
+    /// If this is Some(), then this is the frame of an `atexit` handler that was called by `exit`
+    /// with this exit code. When this frame returns, we run the next handler or stop the program.
+    pub exit_code: Option<i64>,
+
     /// If `measureme` profiling is enabled, holds timing information
     /// for the start of this frame. When we finish executing this frame,
     /// we use this to register a completed event with `measureme`.
     pub timing: Option<measureme::DetachedTiming>,
 
+    /// If `measureme` profiling is enabled, the name of this frame's function in the profile. The
+    /// allocation and borrow tracking events of the frame are recorded under this name.
+    profile_name: Option<measureme::StringId>,
+
     /// Indicates whether a `Frame` is part of a workspace-local crate and is also not
     /// `#[track_caller]`. We compute this once on creation and store the result, as an
     /// optimization.
@@ -77,30 +88,60 @@ pub struct FrameExtra<'tcx> {
     /// an additional bit of "salt" into the cache key. This salt is fixed per-frame
     /// so that within a call, a const will have a stable address.
     salt: usize,
+
+    /// How many bytes of stack this frame and all frames below it on the same thread take up,
+    /// counting every local. This is how we detect stack overflows.
+    stack_bytes: u64,
 }
 
 impl<'tcx> std::fmt::Debug for FrameExtra<'tcx> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         // Omitting `timing`, it does not support `Debug`.
-        let FrameExtra { borrow_tracker, catch_unwind, timing: _, is_user_relevant: _, salt: _ } =
-            self;
+        let FrameExtra {
+            borrow_tracker,
+            catch_unwind,
+            exit_code,
+            timing: _,
+            profile_name: _,
+            is_user_relevant: _,
+            salt: _,
+            stack_bytes: _,
+        } = self;
         f.debug_struct("FrameData")
             .field("borrow_tracker", borrow_tracker)
             .field("catch_unwind", catch_unwind)
+            .field("exit_code", exit_code)
             .finish()
     }
 }
 
 impl VisitProvenance for FrameExtra<'_> {
     fn visit_provenance(&self, visit: &mut VisitWith<'_>) {
-        let FrameExtra { catch_unwind, borrow_tracker, timing: _, is_user_relevant: _, salt: _ } =
-            self;
+        let FrameExtra {
+            catch_unwind,
+            borrow_tracker,
+            exit_code: _,
+            timing: _,
+            profile_name: _,
+            is_user_relevant: _,
+            salt: _,
+            stack_bytes: _,
+        } = self;
 
         catch_unwind.visit_provenance(visit);
         borrow_tracker.visit_provenance(visit);
     }
 }
 
+/// What a function did, for `-Zmiri-profile-summary`. This only counts what the function did
+/// itself, not what its callees did.
+#[derive(Default, Debug, Clone, Copy)]
+struct FunctionStats {
+    blocks: u64,
+    heap_bytes: u64,
+    retags: u64,
+}
+
 /// Extra memory kinds
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum MiriMemoryKind {
@@ -152,6 +193,16 @@ impl MayLeak for MiriMemoryKind {
 }
 
 impl MiriMemoryKind {
+    /// Whether this is memory that the program allocated on the heap, which counts towards
+    /// `-Zmiri-memory-limit`.
+    pub fn is_heap(self) -> bool {
+        use self::MiriMemoryKind::*;
+        match self {
+            Rust | Miri | C | WinHeap | WinLocal => true,
+            Machine | Runtime | Global | ExternStatic | Tls | Mmap => false,
+        }
+    }
+
     /// Whether we have a useful allocation span for an allocation of this kind.
     fn should_save_allocation_span(self) -> bool {
         use self::MiriMemoryKind::*;
@@ -251,8 +302,11 @@ impl fmt::Debug for Provenance {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Provenance::Concrete { alloc_id, tag } => {
-                // Forward `alternate` flag to `alloc_id` printing.
-                if f.alternate() {
+                // With `-Zmiri-strict-determinism`, print the allocation number instead.
+                if let Some(number) = alloc_number(*alloc_id) {
+                    write!(f, "[alloc#{number}]")?;
+                } else if f.alternate() {
+                    // Forward `alternate` flag to `alloc_id` printing.
                     write!(f, "[{alloc_id:#?}]")?;
                 } else {
                     write!(f, "[{alloc_id:?}]")?;
@@ -443,6 +497,12 @@ pub struct MiriMachine<'tcx> {
     /// Return place of the main function.
     pub(crate) main_fn_ret_place: Option<MPlaceTy<'tcx>>,
 
+    /// With `-Zmiri-entry-fn`, the function to run instead of `main`, and the `Default::default`
+    /// instances that construct its arguments.
+    pub(crate) entry_fn: Option<(ty::Instance<'tcx>, Vec<ty::Instance<'tcx>>)>,
+    /// With `-Zmiri-test`, the `#[should_panic]` attribute of the test, if it has one.
+    pub(crate) entry_fn_should_panic: Option<eval::ShouldPanic>,
+
     /// Program arguments (`Option` because we can only initialize them after creating the ecx).
     /// These are *pointers* to argc/argv because macOS.
     /// We also need the full command line as one string because of Windows.
@@ -453,11 +513,21 @@ pub struct MiriMachine<'tcx> {
     /// TLS state.
     pub(crate) tls: TlsData<'tcx>,
 
+    /// The functions registered with `atexit` and friends.
+    pub(crate) atexit_handlers: AtExitHandlers<'tcx>,
+
+    /// The targets of `longjmp`.
+    pub(crate) jmp_bufs: shims::JmpBufTable<'tcx>,
+
     /// What should Miri do when an op requires communicating with the host,
     /// such as accessing host env vars, random number generation, and
     /// file system access.
     pub(crate) isolated_op: IsolatedOp,
 
+    /// The process ID reported to the program. With isolation, this is made up from the seed, so
+    /// that it neither leaks information about the host nor makes executions nondeterministic.
+    pub(crate) process_id: u32,
+
     /// Whether to enforce the validity invariant.
     pub(crate) validate: bool,
 
@@ -465,6 +535,12 @@ pub struct MiriMachine<'tcx> {
     pub(crate) fds: shims::FdTable,
     /// The table of directory descriptors.
     pub(crate) dirs: shims::DirTable,
+    /// The host directories mapped with `-Zmiri-map-dir`, see `MiriConfig::mapped_dirs`.
+    pub(crate) mapped_dirs: Vec<(PathBuf, PathBuf, bool)>,
+    /// The in-memory file system used under isolation, if `-Zmiri-virtual-fs` is set.
+    pub(crate) vfs: Option<shims::VirtualFs>,
+    /// The state of the locale and `iconv` shims.
+    pub(crate) locale: shims::LocaleState,
 
     /// This machine's monotone clock.
     pub(crate) clock: Clock,
@@ -472,7 +548,7 @@ pub struct MiriMachine<'tcx> {
     /// The set of threads.
     pub(crate) threads: ThreadManager<'tcx>,
     /// The state of the primitive synchronization objects.
-    pub(crate) sync: SynchronizationObjects,
+    pub(crate) sync: SynchronizationObjects<'tcx>,
 
     /// Precomputed `TyLayout`s for primitive data types that are commonly used inside Miri.
     pub(crate) layouts: PrimitiveLayouts<'tcx>,
@@ -485,11 +561,29 @@ pub struct MiriMachine<'tcx> {
     profiler: Option<measureme::Profiler>,
     /// Used with `profiler` to cache the `StringId`s for event names
     /// used with `measureme`.
-    string_cache: FxHashMap<String, measureme::StringId>,
+    string_cache: RefCell<FxHashMap<String, measureme::StringId>>,
+    /// With `-Zmiri-coverage`, how often each basic block of the local crates was executed.
+    pub(crate) coverage: Option<Coverage>,
+    /// With `-Zmiri-heap-profile`, where the live heap allocations were made.
+    pub(crate) heap_profile: Option<RefCell<HeapProfile<'tcx>>>,
+    /// With `-Zmiri-trace`, where to log calls and thread switches.
+    pub(crate) call_trace: Option<CallTrace>,
+    /// With `-Zmiri-profile-summary`, what each function did.
+    function_stats: Option<RefCell<FxHashMap<Instance<'tcx>, FunctionStats>>>,
 
     /// Cache of `Instance` exported under the given `Symbol` name.
     /// `None` means no `Instance` exported under the given name is found.
     pub(crate) exported_symbols_cache: FxHashMap<Symbol, Option<Instance<'tcx>>>,
+    /// Cache of the names of the functions that backtrace frames were resolved to, see
+    /// `resolve_frame_pointer`. Printing an `Instance` is slow, and a program that prints many
+    /// backtraces resolves the same frames over and over.
+    pub(crate) frame_name_cache: FxHashMap<Instance<'tcx>, String>,
+    /// Cache of the MIR bodies of the functions we called. Programs with deep generic call trees
+    /// call the same instances over and over, and this saves going through the query system.
+    mir_cache: RefCell<FxHashMap<ty::InstanceKind<'tcx>, &'tcx mir::Body<'tcx>>>,
+    /// Cache of the items we looked up by their path (with the segments joined by `::`), see
+    /// `try_resolve_path`.
+    pub(crate) path_cache: RefCell<FxHashMap<(String, Namespace), Option<Instance<'tcx>>>>,
 
     /// Whether to raise a panic in the context of the evaluated process when unsupported
     /// functionality is encountered. If `false`, an error is propagated in the Miri application context
@@ -498,16 +592,31 @@ pub struct MiriMachine<'tcx> {
 
     /// Equivalent setting as RUST_BACKTRACE on encountering an error.
     pub(crate) backtrace_style: BacktraceStyle,
+    /// Corresponds to -Zmiri-backtrace-depth.
+    pub(crate) backtrace_max_depth: Option<usize>,
+    /// Corresponds to -Zmiri-backtrace-include.
+    backtrace_include: Vec<String>,
+    /// Which functions match `backtrace_include`.
+    backtrace_include_cache: RefCell<FxHashMap<DefId, bool>>,
+    /// Corresponds to -Zmiri-backtrace-exclude.
+    pub(crate) backtrace_exclude: Vec<String>,
 
     /// Crates which are considered local for the purposes of error reporting.
     pub(crate) local_crates: Vec<CrateNum>,
 
     /// Mapping extern static names to their pointer.
     extern_statics: FxHashMap<Symbol, StrictPointer>,
+    /// The extern statics declared with `-Zmiri-extern-static`.
+    pub(crate) user_extern_statics: FxHashSet<Symbol>,
 
     /// The random number generator used for resolving non-determinism.
     /// Needs to be queried by ptr_to_int, hence needs interior mutability.
     pub(crate) rng: RefCell<StdRng>,
+    /// With `-Zmiri-log-nondeterminism`, how many nondeterministic decisions were logged so far.
+    nondeterminism_log: Option<Cell<u64>>,
+    /// The maximal error, in ULPs, that is applied to the results of float operations whose
+    /// precision is not guaranteed.
+    pub(crate) float_max_ulp_error: u32,
 
     /// The allocation IDs to report when they are being allocated
     /// (helps for debugging memory leaks and use after free bugs).
@@ -521,9 +630,37 @@ pub struct MiriMachine<'tcx> {
     /// Failure rate of compare_exchange_weak, between 0.0 and 1.0
     pub(crate) cmpxchg_weak_failure_rate: f64,
 
+    /// The probability of a condvar wait returning without having been signaled.
+    pub(crate) spurious_wakeup_rate: f64,
+    /// The probability of a futex wait returning spuriously.
+    pub(crate) futex_spurious_wakeup_rate: f64,
+
+    /// Whether to wake up a random waiter of a futex or condvar, instead of the one that waited
+    /// longest.
+    pub(crate) random_wakeup_order: bool,
+
+    /// The format in which data races are reported.
+    pub(crate) race_report: RaceReportFormat,
+
+    /// The values passed to `miri_observe` so far, if we are running a litmus test.
+    pub(crate) observations: Option<Vec<(String, u64)>>,
+
+    /// The restart points reached with `miri_restart_point`, or `None` if restarting is not
+    /// supported in this mode (e.g., with `-Zmiri-dpor`).
+    pub(crate) restart_points: Option<crate::eval::RestartPoints>,
+
     /// Corresponds to -Zmiri-mute-stdout-stderr and doesn't write the output but acts as if it succeeded.
     pub(crate) mute_stdout_stderr: bool,
 
+    /// Corresponds to `-Zmiri-panic-handler=abort`.
+    pub(crate) abort_in_panic_handler: bool,
+
+    /// If set, the program's stdout and stderr and Miri's diagnostics go here instead of to the
+    /// host's stdout and stderr.
+    pub(crate) captured_output: Option<CapturedOutput>,
+    /// The `DiagCtxt` that emits diagnostics into `captured_output`.
+    captured_dcx: Option<DiagCtxt>,
+
     /// Whether weak memory emulation is enabled
     pub(crate) weak_memory: bool,
 
@@ -532,14 +669,51 @@ pub struct MiriMachine<'tcx> {
 
     /// If `Some`, we will report the current stack every N basic blocks.
     pub(crate) report_progress: Option<u32>,
+    /// If `Some`, progress reports are written to this file as JSON lines instead.
+    pub(crate) progress_json: Option<File>,
     // The total number of blocks that have been executed.
     pub(crate) basic_block_count: u64,
+    /// Corresponds to -Zmiri-max-blocks.
+    pub(crate) max_blocks: Option<u64>,
+    /// Corresponds to -Zmiri-timeout: the time limit, and when it is reached.
+    pub(crate) timeout: Option<(Duration, std::time::Instant)>,
+    /// Corresponds to -Zmiri-test-max-blocks.
+    pub(crate) test_max_blocks: Option<u64>,
+    /// Corresponds to -Zmiri-test-timeout.
+    pub(crate) test_timeout: Option<Duration>,
+    /// The libtest tests that are currently running. Only tracked if one of the limits above is
+    /// set.
+    pub(crate) running_tests: Vec<RunningTest>,
+    /// Corresponds to -Zmiri-memory-limit.
+    pub(crate) memory_limit: Option<u64>,
+    /// The total size of the live heap allocations, which `memory_limit` applies to.
+    pub(crate) heap_bytes: Cell<u64>,
+    /// The probability of a heap allocation failing.
+    pub(crate) alloc_failure_rate: f64,
+    /// Whether the next heap allocation fails, as requested with `miri_fail_next_alloc`.
+    pub(crate) fail_next_alloc: bool,
+    /// The bounds of the capabilities, with `-Zmiri-cheri`.
+    pub(crate) capabilities: Option<RefCell<crate::cheri::Capabilities>>,
+
+    /// If set, stop the program after this many basic blocks, since it is being run again to
+    /// show where it was `stop_before_error` blocks before an error.
+    pub(crate) stop_at_block: Option<u64>,
+    pub(crate) stop_before_error: u64,
+
+    /// The debugger attached with `-Zmiri-gdb` or `-Zmiri-dap`, if any.
+    pub(crate) debugger: Option<Box<dyn Frontend<'tcx>>>,
+
+    /// The user-provided handlers for foreign functions.
+    pub(crate) shim_hooks: FxHashMap<Symbol, Arc<dyn ShimHook>>,
 
     /// Handle of the optional shared object file for native functions.
     #[cfg(target_os = "linux")]
     pub native_lib: Option<(libloading::Library, std::path::PathBuf)>,
     #[cfg(not(target_os = "linux"))]
     pub native_lib: Option<!>,
+    /// Whether to check the bytes that native code changed in the memory it got pointers to, like
+    /// writes of the calling thread.
+    pub(crate) native_lib_diff_writes: bool,
 
     /// Run a garbage collector for BorTags every N basic blocks.
     pub(crate) gc_interval: u32,
@@ -548,14 +722,29 @@ pub struct MiriMachine<'tcx> {
 
     /// The number of CPUs to be reported by miri.
     pub(crate) num_cpus: u32,
+    /// The host name reported to the program.
+    pub(crate) hostname: String,
+    /// The OS release reported by `uname`, if it was overridden.
+    pub(crate) os_release: Option<String>,
 
     /// Determines Miri's page size and associated values
     pub(crate) page_size: u64,
+    /// The largest size of an atomic access, in bits, if it is enforced. Built-in targets leave
+    /// out some atomic widths the hardware supports (like the 128-bit `cmpxchg16b` on x86_64), so
+    /// we only enforce this for custom target specs and with `-Zmiri-max-atomic-width`.
+    pub(crate) max_atomic_width: Option<u64>,
     pub(crate) stack_addr: u64,
     pub(crate) stack_size: u64,
+    /// How many bytes of stack each thread may use before we report a stack overflow.
+    pub(crate) stack_limit: u64,
+    /// For each function, how many bytes of stack one of its frames takes up.
+    frame_sizes: RefCell<FxHashMap<Instance<'tcx>, u64>>,
 
     /// Whether to collect a backtrace when each allocation is created, just in case it leaks.
     pub(crate) collect_leak_backtraces: bool,
+    /// Whether to report threads that were never joined or detached (and hence collect a
+    /// backtrace whenever a thread is spawned).
+    pub(crate) check_unjoined_threads: bool,
 
     /// The spans we will use to report where an allocation was created and deallocated in
     /// diagnostics.
@@ -565,6 +754,15 @@ pub struct MiriMachine<'tcx> {
     /// that is fixed per stack frame; this lets us have sometimes different results for the
     /// same const while ensuring consistent results within a single call.
     const_cache: RefCell<FxHashMap<(mir::Const<'tcx>, usize), OpTy<'tcx>>>,
+    /// The number of different salts, and hence the number of different addresses each const
+    /// can have.
+    addrs_per_const: usize,
+    /// The adjusted version of each constant allocation. A constant can be given many `AllocId`s
+    /// (see `addrs_per_const`), but its contents are the same for all of them, so we only need to
+    /// adjust it once. Thanks to `MiriAllocBytes` being copy-on-write, the copies for the
+    /// individual `AllocId`s then share their bytes.
+    adjusted_consts:
+        RefCell<FxHashMap<ConstAllocation<'tcx>, Allocation<Provenance, (), MiriAllocBytes>>>,
 
     /// For each allocation, an offset inside that allocation that was deemed aligned even for
     /// symbolic alignment checks. This cannot be stored in `AllocExtra` since it needs to be
@@ -581,15 +779,15 @@ impl<'tcx> MiriMachine<'tcx> {
         let local_crates = helpers::get_local_crates(tcx);
         let layouts =
             PrimitiveLayouts::new(layout_cx).expect("Couldn't get layouts of primitive types");
+        let crate_name = layout_cx
+            .tcx
+            .sess
+            .opts
+            .crate_name
+            .clone()
+            .unwrap_or_else(|| "unknown-crate".to_string());
+        let pid = process::id();
         let profiler = config.measureme_out.as_ref().map(|out| {
-            let crate_name = layout_cx
-                .tcx
-                .sess
-                .opts
-                .crate_name
-                .clone()
-                .unwrap_or_else(|| "unknown-crate".to_string());
-            let pid = process::id();
             // We adopt the same naming scheme for the profiler output that rustc uses. In rustc,
             // the PID is padded so that the nondeterministic value of the PID does not spread
             // nondeterminism to the allocator. In Miri we are not aiming for such performance
@@ -598,7 +796,30 @@ impl<'tcx> MiriMachine<'tcx> {
             let path = Path::new(out).join(filename);
             measureme::Profiler::new(path).expect("Couldn't create `measureme` profiler")
         });
-        let rng = StdRng::seed_from_u64(config.seed.unwrap_or(0));
+        let coverage = config
+            .coverage_out
+            .as_ref()
+            .map(|out| Coverage::new(Path::new(out).join(format!("{crate_name}-{pid:07}.lcov"))));
+        let heap_profile = config.heap_profile_out.as_ref().map(|out| {
+            let extension = match config.heap_profile_format {
+                HeapProfileFormat::Massif => "massif",
+                HeapProfileFormat::Json => "json",
+            };
+            let path = Path::new(out).join(format!("{crate_name}-{pid:07}.{extension}"));
+            RefCell::new(HeapProfile::new(path, config.heap_profile_format))
+        });
+        let progress_json = config.report_progress_json.as_ref().map(|out| {
+            File::create(out).unwrap_or_else(|err| {
+                tcx.dcx().fatal(format!("failed to create the progress report file `{out}`: {err}"))
+            })
+        });
+        let call_trace = config.trace.map(|mode| {
+            let out = config.trace_out.clone().unwrap_or_else(|| format!("{crate_name}.trace"));
+            CallTrace::new(mode, Path::new(&out)).unwrap_or_else(|err| {
+                tcx.dcx().fatal(format!("failed to create the trace file `{out}`: {err}"))
+            })
+        });
+        let mut rng = StdRng::seed_from_u64(config.seed.unwrap_or(0));
         let borrow_tracker = config.borrow_tracker.map(|bt| bt.instantiate_global_state(config));
         let data_race = config.data_race_detector.then(|| data_race::GlobalState::new(config));
         // Determine page size, stack address, and stack size.
@@ -620,51 +841,155 @@ impl<'tcx> MiriMachine<'tcx> {
                         4 * 1024
                     }
                 }
-                _ => 4 * 1024,
+                "loongarch64" => 16 * 1024,
+                "sparc64" => 8 * 1024,
+                "x86" | "x86_64" | "arm" | "riscv32" | "riscv64" | "mips" | "mips64" | "powerpc"
+                | "powerpc64" | "s390x" | "csky" | "bpf" | "avr" | "msp430" => 4 * 1024,
+                arch => {
+                    // Custom target specs can name architectures we know nothing about. Do not
+                    // silently pretend they are just like x86.
+                    if matches!(tcx.sess.opts.target_triple, TargetTriple::TargetJson { .. }) {
+                        tcx.dcx().warn(format!(
+                            "Miri does not know the page size of the `{arch}` architecture; \
+                            assuming 4 KiB pages, use `-Zmiri-force-page-size` to override this"
+                        ));
+                    }
+                    4 * 1024
+                }
             }
         };
+        let max_atomic_width = config.max_atomic_width.or_else(|| {
+            matches!(tcx.sess.opts.target_triple, TargetTriple::TargetJson { .. })
+                .then(|| tcx.sess.target.max_atomic_width())
+        });
         // On 16bit targets, 32 pages is more than the entire address space!
         let stack_addr = if tcx.pointer_size().bits() < 32 { page_size } else { page_size * 32 };
-        let stack_size =
-            if tcx.pointer_size().bits() < 32 { page_size * 4 } else { page_size * 16 };
+        let stack_size = config.stack_size.unwrap_or(if tcx.pointer_size().bits() < 32 {
+            page_size * 4
+        } else {
+            page_size * 16
+        });
+        // Unless configured otherwise, we allow as much stack as a typical main thread has. Miri's
+        // frames are larger than they would be in a compiled program, since every local counts.
+        let stack_limit = config.stack_size.unwrap_or(if tcx.pointer_size().bits() < 32 {
+            stack_size
+        } else {
+            8 * 1024 * 1024
+        });
+        // Unless configured otherwise, the other allocations start right at the stack address.
+        let (mut heap_start, heap_end) = config.address_range.unwrap_or((stack_addr, u64::MAX));
+        if config.aslr {
+            let heap_end = heap_end.min(tcx.target_usize_max());
+            let range = heap_end.saturating_sub(heap_start);
+            heap_start += if tcx.pointer_size().bits() < 32 {
+                // On 16bit targets, there are only a handful of pages, and giving up half of them
+                // would leave little room for the program. So we move the start by at most an
+                // eighth of the range, in steps of 16 bytes.
+                16 * rng.gen_range(0..=range / 8 / 16)
+            } else {
+                // Move the start to a random page in the first half of the range, but by at most
+                // 2^20 pages, so that addresses stay small enough to be readable.
+                page_size * rng.gen_range(0..=(range / 2 / page_size).min(1 << 20))
+            };
+        }
         MiriMachine {
             tcx,
             borrow_tracker,
             data_race,
-            alloc_addresses: RefCell::new(alloc_addresses::GlobalStateInner::new(config, stack_addr)),
+            alloc_addresses: RefCell::new(alloc_addresses::GlobalStateInner::new(
+                config, heap_start, heap_end,
+            )),
             // `env_vars` depends on a full interpreter so we cannot properly initialize it yet.
             env_vars: EnvVars::default(),
             main_fn_ret_place: None,
+            entry_fn: None,
+            entry_fn_should_panic: None,
             argc: None,
             argv: None,
             cmd_line: None,
             tls: TlsData::default(),
+            atexit_handlers: AtExitHandlers::default(),
+            jmp_bufs: Default::default(),
             isolated_op: config.isolated_op,
+            process_id: if config.isolated_op == IsolatedOp::Allow {
+                pid
+            } else {
+                1000 + u32::try_from(config.seed.unwrap_or(0) % 1000).unwrap()
+            },
             validate: config.validate,
-            fds: shims::FdTable::new(config.mute_stdout_stderr),
+            fds: shims::FdTable::new(config.mute_stdout_stderr, config.captured_output.is_some()),
             dirs: Default::default(),
+            mapped_dirs: config.mapped_dirs.clone(),
+            vfs: config.virtual_fs.then(shims::VirtualFs::default),
+            locale: Default::default(),
             layouts,
-            threads: ThreadManager::default(),
-            sync: SynchronizationObjects::default(),
+            threads: ThreadManager::new(config),
+            sync: SynchronizationObjects::new(config.track_lock_order),
             static_roots: Vec::new(),
             profiler,
-            string_cache: Default::default(),
+            string_cache: RefCell::new(FxHashMap::default()),
+            coverage,
+            heap_profile,
+            call_trace,
+            function_stats: config.profile_summary.then(|| RefCell::new(FxHashMap::default())),
             exported_symbols_cache: FxHashMap::default(),
+            frame_name_cache: FxHashMap::default(),
+            mir_cache: RefCell::new(FxHashMap::default()),
+            path_cache: RefCell::new(FxHashMap::default()),
             panic_on_unsupported: config.panic_on_unsupported,
             backtrace_style: config.backtrace_style,
+            backtrace_max_depth: config.backtrace_max_depth,
+            backtrace_include: config.backtrace_include.clone(),
+            backtrace_include_cache: RefCell::new(FxHashMap::default()),
+            backtrace_exclude: config.backtrace_exclude.clone(),
             local_crates,
             extern_statics: FxHashMap::default(),
+            user_extern_statics: FxHashSet::default(),
             rng: RefCell::new(rng),
+            nondeterminism_log: config.log_nondeterminism.then(|| Cell::new(0)),
+            float_max_ulp_error: config.float_max_ulp_error,
             tracked_alloc_ids: config.tracked_alloc_ids.clone(),
             track_alloc_accesses: config.track_alloc_accesses,
             check_alignment: config.check_alignment,
             cmpxchg_weak_failure_rate: config.cmpxchg_weak_failure_rate,
+            spurious_wakeup_rate: config.spurious_wakeup_rate,
+            futex_spurious_wakeup_rate: config.futex_spurious_wakeup_rate,
+            random_wakeup_order: config.random_wakeup_order,
+            race_report: config.race_report,
+            observations: config.litmus_runs.is_some().then(Vec::new),
+            restart_points: None,
             mute_stdout_stderr: config.mute_stdout_stderr,
+            abort_in_panic_handler: config.abort_in_panic_handler,
+            captured_output: config.captured_output.clone(),
+            captured_dcx: config.captured_output.as_ref().map(|captured| captured.dcx(tcx)),
             weak_memory: config.weak_memory_emulation,
             preemption_rate: config.preemption_rate,
             report_progress: config.report_progress,
+            progress_json,
             basic_block_count: 0,
-            clock: Clock::new(config.isolated_op == IsolatedOp::Allow),
+            max_blocks: config.max_blocks,
+            timeout: config.timeout,
+            test_max_blocks: config.test_max_blocks,
+            test_timeout: config.test_timeout,
+            running_tests: Vec::new(),
+            memory_limit: config.memory_limit,
+            heap_bytes: Cell::new(0),
+            alloc_failure_rate: config.alloc_failure_rate,
+            fail_next_alloc: false,
+            capabilities: config.cheri.then(|| RefCell::new(crate::cheri::Capabilities::new())),
+            stop_at_block: config.stop_at_block,
+            stop_before_error: config.stop_before_error.unwrap_or(0),
+            debugger: Self::attach_debugger(config, tcx),
+            shim_hooks: config
+                .shim_hooks
+                .iter()
+                .map(|(link_name, hook)| (Symbol::intern(link_name), hook.clone()))
+                .collect(),
+            clock: Clock::new(
+                config.isolated_op == IsolatedOp::Allow,
+                config.clock_nanoseconds_per_block,
+                config.clock_epoch,
+            ),
             #[cfg(target_os = "linux")]
             native_lib: config.native_lib.as_ref().map(|lib_file_path| {
                 let target_triple = layout_cx.tcx.sess.opts.target_triple.triple();
@@ -691,15 +1016,24 @@ impl<'tcx> MiriMachine<'tcx> {
             native_lib: config.native_lib.as_ref().map(|_| {
                 panic!("loading external .so files is only supported on Linux")
             }),
+            native_lib_diff_writes: config.native_lib_diff_writes,
             gc_interval: config.gc_interval,
             since_gc: 0,
             num_cpus: config.num_cpus,
+            hostname: config.hostname.clone(),
+            os_release: config.os_release.clone(),
             page_size,
+            max_atomic_width,
             stack_addr,
             stack_size,
+            stack_limit,
+            frame_sizes: RefCell::new(FxHashMap::default()),
             collect_leak_backtraces: config.collect_leak_backtraces,
+            check_unjoined_threads: config.check_unjoined_threads,
             allocation_spans: RefCell::new(FxHashMap::default()),
             const_cache: RefCell::new(FxHashMap::default()),
+            addrs_per_const: config.addrs_per_const,
+            adjusted_consts: RefCell::new(FxHashMap::default()),
             symbolic_alignment: RefCell::new(FxHashMap::default()),
         }
     }
@@ -710,11 +1044,25 @@ impl<'tcx> MiriMachine<'tcx> {
         on_main_stack_empty: StackEmptyCallback<'tcx>,
     ) -> InterpResult<'tcx> {
         EnvVars::init(this, config)?;
-        MiriMachine::init_extern_statics(this)?;
+        MiriMachine::init_extern_statics(this, config)?;
         ThreadManager::init(this, on_main_stack_empty);
         Ok(())
     }
 
+    /// Wait for the debugger requested by `-Zmiri-gdb` or `-Zmiri-dap` to connect.
+    fn attach_debugger(config: &MiriConfig, tcx: TyCtxt<'tcx>) -> Option<Box<dyn Frontend<'tcx>>> {
+        let (addr, debugger) = if let Some(addr) = &config.gdb_addr {
+            (addr, GdbStub::listen(addr).map(|gdb| Box::new(gdb) as Box<dyn Frontend<'tcx>>))
+        } else if let Some(addr) = &config.dap_addr {
+            (addr, DapServer::listen(addr).map(|dap| Box::new(dap) as Box<dyn Frontend<'tcx>>))
+        } else {
+            return None;
+        };
+        Some(debugger.unwrap_or_else(|err| {
+            tcx.dcx().fatal(format!("failed to wait for a debugger at {addr}: {err}"))
+        }))
+    }
+
     pub(crate) fn add_extern_static(this: &mut MiriInterpCx<'tcx>, name: &str, ptr: Pointer) {
         // This got just allocated, so there definitely is a pointer here.
         let ptr = ptr.into_pointer_or_addr().unwrap();
@@ -725,10 +1073,67 @@ impl<'tcx> MiriMachine<'tcx> {
         self.isolated_op == IsolatedOp::Allow
     }
 
-    /// Check whether the stack frame that this `FrameInfo` refers to is part of a local crate.
+    /// Stop the program if the time limit set with `-Zmiri-timeout` was reached.
+    pub(crate) fn check_timeout(&self) -> InterpResult<'tcx> {
+        if let Some((timeout, deadline)) = self.timeout
+            && std::time::Instant::now() >= deadline
+        {
+            throw_machine_stop!(TerminationInfo::BudgetExceeded {
+                budget: format!("{} seconds", timeout.as_secs()),
+                flag: "-Zmiri-timeout",
+                test: None,
+            });
+        }
+        Ok(())
+    }
+
+    /// Write to the stdout of the program: the host's stdout, unless it is captured.
+    pub(crate) fn write_to_stdout(&self, bytes: &[u8]) -> std::io::Result<()> {
+        use std::io::Write;
+        if let Some(captured) = &self.captured_output {
+            captured.stdout.lock().unwrap().extend_from_slice(bytes);
+            Ok(())
+        } else {
+            std::io::stdout().write_all(bytes)
+        }
+    }
+
+    /// Write to the stderr of the program: the host's stderr, unless it is captured.
+    pub(crate) fn write_to_stderr(&self, bytes: &[u8]) -> std::io::Result<()> {
+        use std::io::Write;
+        if let Some(captured) = &self.captured_output {
+            captured.stderr.lock().unwrap().extend_from_slice(bytes);
+            Ok(())
+        } else {
+            std::io::stderr().write_all(bytes)
+        }
+    }
+
+    /// Where diagnostics about the program go: the compiler's `DiagCtxt`, unless the output of
+    /// the program is captured.
+    pub(crate) fn dcx(&self) -> &DiagCtxt {
+        self.captured_dcx.as_ref().unwrap_or_else(|| self.tcx.dcx())
+    }
+
+    /// Check whether the stack frame that this `FrameInfo` refers to is part of a local crate, or
+    /// should be treated like it because of `-Zmiri-backtrace-include`.
     pub(crate) fn is_local(&self, frame: &FrameInfo<'_>) -> bool {
         let def_id = frame.instance.def_id();
-        def_id.is_local() || self.local_crates.contains(&def_id.krate)
+        def_id.is_local()
+            || self.local_crates.contains(&def_id.krate)
+            || self.is_backtrace_included(def_id)
+    }
+
+    /// Whether the user asked for frames of this function to be treated like those of the local
+    /// crates, with `-Zmiri-backtrace-include`.
+    pub(crate) fn is_backtrace_included(&self, def_id: DefId) -> bool {
+        if self.backtrace_include.is_empty() {
+            return false;
+        }
+        *self.backtrace_include_cache.borrow_mut().entry(def_id).or_insert_with(|| {
+            let path = self.tcx.def_path_str(def_id);
+            self.backtrace_include.iter().any(|pattern| helpers::glob_matches(pattern, &path))
+        })
     }
 
     /// Called when the interpreter is going to shut down abnormally, such as due to a Ctrl-C.
@@ -740,6 +1145,92 @@ impl<'tcx> MiriMachine<'tcx> {
         drop(self.profiler.take());
     }
 
+    /// With `-Zmiri-log-nondeterminism`, print a nondeterministic decision that `subsystem` just
+    /// made. The decisions are numbered, so that the logs of two runs can be compared to find the
+    /// first decision in which they differ.
+    pub(crate) fn log_nondeterminism(&self, subsystem: &str, decision: impl FnOnce() -> String) {
+        let Some(count) = &self.nondeterminism_log else { return };
+        count.set(count.get() + 1);
+        let span = self.current_span();
+        eprintln!(
+            "nondeterminism #{}: [{subsystem}] {} at {}",
+            count.get(),
+            decision(),
+            self.tcx.sess.source_map().span_to_embeddable_string(span)
+        );
+    }
+
+    /// The `StringId` of `s` in the `measureme` profile.
+    fn profile_string(&self, profiler: &measureme::Profiler, s: &str) -> measureme::StringId {
+        let mut cache = self.string_cache.borrow_mut();
+        if let Some(&id) = cache.get(s) {
+            return id;
+        }
+        let id = profiler.alloc_string(s);
+        cache.insert(s.to_owned(), id);
+        id
+    }
+
+    /// If `measureme` profiling is enabled, record an event of the given kind, with the given
+    /// value (e.g. a number of bytes), for the function that is currently running.
+    pub(crate) fn record_profile_event(&self, kind: &str, value: u64) {
+        let Some(profiler) = &self.profiler else { return };
+        let Some(name) =
+            self.threads.active_thread_stack().last().and_then(|frame| frame.extra.profile_name)
+        else {
+            return;
+        };
+        let kind = self.profile_string(profiler, kind);
+        profiler.record_integer_event(
+            kind,
+            measureme::EventId::from_label(name),
+            self.threads.active_thread().to_u32(),
+            value,
+        );
+    }
+
+    /// With `-Zmiri-profile-summary`, update the statistics of the function that is currently
+    /// running.
+    fn record_function_stats(&self, f: impl FnOnce(&mut FunctionStats)) {
+        let Some(function_stats) = &self.function_stats else { return };
+        let Some(frame) = self.threads.active_thread_stack().last() else { return };
+        f(function_stats.borrow_mut().entry(frame.instance).or_default());
+    }
+
+    /// The table printed by `-Zmiri-profile-summary`: the functions that are among the top ones
+    /// by any of the statistics, ordered by the number of basic blocks they executed.
+    pub(crate) fn profile_summary(&self) -> Option<String> {
+        const TOP: usize = 10;
+
+        let function_stats = self.function_stats.as_ref()?.borrow();
+        let mut top = FxHashSet::default();
+        let metrics: [fn(&FunctionStats) -> u64; 3] =
+            [|s| s.blocks, |s| s.heap_bytes, |s| s.retags];
+        for metric in metrics {
+            let mut entries: Vec<_> =
+                function_stats.iter().filter(|(_, stats)| metric(stats) > 0).collect();
+            entries.sort_by_key(|(_, stats)| std::cmp::Reverse(metric(stats)));
+            top.extend(entries.into_iter().take(TOP).map(|(instance, _)| *instance));
+        }
+        let mut rows: Vec<_> = top
+            .into_iter()
+            .map(|instance| (instance.to_string(), function_stats[&instance]))
+            .collect();
+        // Sort by name as well, so that the output is deterministic.
+        rows.sort_by(|(name_a, a), (name_b, b)| b.blocks.cmp(&a.blocks).then(name_a.cmp(name_b)));
+        let mut msg = format!(
+            "profile summary (not counting what callees did):\n{:>12}  {:>12}  {:>10}  function",
+            "blocks", "heap bytes", "retags"
+        );
+        for (name, stats) in rows {
+            msg.push_str(&format!(
+                "\n{:>12}  {:>12}  {:>10}  {name}",
+                stats.blocks, stats.heap_bytes, stats.retags
+            ));
+        }
+        Some(msg)
+    }
+
     pub(crate) fn page_align(&self) -> Align {
         Align::from_bytes(self.page_size).unwrap()
     }
@@ -767,56 +1258,114 @@ impl VisitProvenance for MiriMachine<'_> {
             threads,
             sync: _,
             tls,
+            atexit_handlers,
+            jmp_bufs,
             env_vars,
             main_fn_ret_place,
+            entry_fn: _,
+            entry_fn_should_panic: _,
             argc,
             argv,
             cmd_line,
             extern_statics,
+            user_extern_statics: _,
             dirs,
+            mapped_dirs: _,
+            vfs: _,
+            locale,
             borrow_tracker,
             data_race,
             alloc_addresses,
             fds,
             tcx: _,
             isolated_op: _,
+            process_id: _,
             validate: _,
             clock: _,
             layouts: _,
             static_roots: _,
             profiler: _,
             string_cache: _,
+            coverage: _,
+            heap_profile: _,
+            call_trace: _,
+            function_stats: _,
             exported_symbols_cache: _,
+            frame_name_cache: _,
+            mir_cache: _,
+            path_cache: _,
             panic_on_unsupported: _,
             backtrace_style: _,
+            backtrace_max_depth: _,
+            backtrace_include: _,
+            backtrace_include_cache: _,
+            backtrace_exclude: _,
             local_crates: _,
             rng: _,
+            nondeterminism_log: _,
+            float_max_ulp_error: _,
             tracked_alloc_ids: _,
             track_alloc_accesses: _,
             check_alignment: _,
             cmpxchg_weak_failure_rate: _,
+            spurious_wakeup_rate: _,
+            futex_spurious_wakeup_rate: _,
+            random_wakeup_order: _,
+            race_report: _,
+            observations: _,
+            restart_points: _,
             mute_stdout_stderr: _,
+            abort_in_panic_handler: _,
+            captured_output: _,
+            captured_dcx: _,
             weak_memory: _,
             preemption_rate: _,
             report_progress: _,
+            progress_json: _,
             basic_block_count: _,
+            max_blocks: _,
+            timeout: _,
+            test_max_blocks: _,
+            test_timeout: _,
+            running_tests: _,
+            memory_limit: _,
+            heap_bytes: _,
+            alloc_failure_rate: _,
+            fail_next_alloc: _,
+            capabilities: _,
+            stop_at_block: _,
+            stop_before_error: _,
+            debugger: _,
+            shim_hooks: _,
             native_lib: _,
+            native_lib_diff_writes: _,
             gc_interval: _,
             since_gc: _,
             num_cpus: _,
+            hostname: _,
+            os_release: _,
             page_size: _,
+            max_atomic_width: _,
             stack_addr: _,
             stack_size: _,
+            stack_limit: _,
+            frame_sizes: _,
             collect_leak_backtraces: _,
+            check_unjoined_threads: _,
             allocation_spans: _,
             const_cache: _,
+            addrs_per_const: _,
+            adjusted_consts,
             symbolic_alignment: _,
         } = self;
 
         threads.visit_provenance(visit);
         tls.visit_provenance(visit);
+        atexit_handlers.visit_provenance(visit);
+        jmp_bufs.visit_provenance(visit);
         env_vars.visit_provenance(visit);
         dirs.visit_provenance(visit);
+        locale.visit_provenance(visit);
         fds.visit_provenance(visit);
         data_race.visit_provenance(visit);
         borrow_tracker.visit_provenance(visit);
@@ -828,6 +1377,11 @@ impl VisitProvenance for MiriMachine<'_> {
         for ptr in extern_statics.values() {
             ptr.visit_provenance(visit);
         }
+        for alloc in adjusted_consts.borrow().values() {
+            for prov in alloc.provenance().provenances() {
+                prov.visit_provenance(visit);
+            }
+        }
     }
 }
 
@@ -935,6 +1489,15 @@ impl<'tcx> Machine<'tcx> for MiriMachine<'tcx> {
         !ecx.tcx.sess.overflow_checks()
     }
 
+    #[inline(always)]
+    fn load_mir(
+        ecx: &MiriInterpCx<'tcx>,
+        instance: ty::InstanceKind<'tcx>,
+    ) -> InterpResult<'tcx, &'tcx mir::Body<'tcx>> {
+        let mut cache = ecx.machine.mir_cache.borrow_mut();
+        Ok(*cache.entry(instance).or_insert_with(|| ecx.tcx.instance_mir(instance)))
+    }
+
     #[inline(always)]
     fn find_mir_or_eval_fn(
         ecx: &mut MiriInterpCx<'tcx>,
@@ -958,6 +1521,9 @@ impl<'tcx> Machine<'tcx> for MiriMachine<'tcx> {
             return ecx.emulate_foreign_item(link_name, abi, &args, dest, ret, unwind);
         }
 
+        // Benchmarks are not measured, they are just run once.
+        ecx.bench_run_once(instance, args)?;
+
         // Otherwise, load the MIR.
         Ok(Some((ecx.load_mir(instance.def, None)?, instance)))
     }
@@ -972,6 +1538,9 @@ impl<'tcx> Machine<'tcx> for MiriMachine<'tcx> {
         ret: Option<mir::BasicBlock>,
         unwind: mir::UnwindAction,
     ) -> InterpResult<'tcx> {
+        if fn_val == DynSym::from_str(eval::ENTRY_FN_TRAMPOLINE) {
+            return eval::call_entry_fn(ecx, abi, dest, ret, unwind);
+        }
         let args = ecx.copy_fn_args(args); // FIXME: Should `InPlace` arguments be reset to uninit?
         ecx.emulate_dyn_sym(fn_val, abi, &args, dest, ret, unwind)
     }
@@ -1061,7 +1630,14 @@ impl<'tcx> Machine<'tcx> for MiriMachine<'tcx> {
             let (shim_size, shim_align, _kind) = ecx.get_alloc_info(alloc_id);
             let def_ty = ecx.tcx.type_of(def_id).instantiate_identity();
             let extern_decl_layout = ecx.tcx.layout_of(ty::ParamEnv::empty().and(def_ty)).unwrap();
-            if extern_decl_layout.size != shim_size || extern_decl_layout.align.abi != shim_align {
+            // We do not know the types of the statics declared with `-Zmiri-extern-static`, so
+            // those may be more aligned than their declaration. Our own shims must match exactly.
+            let align_ok = if ecx.machine.user_extern_statics.contains(&link_name) {
+                extern_decl_layout.align.abi <= shim_align
+            } else {
+                extern_decl_layout.align.abi == shim_align
+            };
+            if extern_decl_layout.size != shim_size || !align_ok {
                 throw_unsup_format!(
                     "extern static `{link_name}` has been declared as `{krate}::{name}` \
                     with a size of {decl_size} bytes and alignment of {decl_align} bytes, \
@@ -1118,6 +1694,29 @@ impl<'tcx> Machine<'tcx> for MiriMachine<'tcx> {
             Some(ecx.generate_stacktrace())
         };
 
+        ecx.machine.record_profile_event("MiriAllocation", size.bytes());
+        if matches!(kind, MemoryKind::Machine(kind) if kind.is_heap()) {
+            ecx.machine.record_function_stats(|stats| stats.heap_bytes += size.bytes());
+            let heap_bytes = &ecx.machine.heap_bytes;
+            heap_bytes.set(heap_bytes.get().strict_add(size.bytes()));
+            if let Some(heap_profile) = &ecx.machine.heap_profile {
+                let stack = ecx.active_thread_stack();
+                let instance = ecx
+                    .machine
+                    .threads
+                    .active_thread_ref()
+                    .top_user_relevant_frame()
+                    .map(|idx| stack[idx].instance);
+                heap_profile.borrow_mut().record_alloc(
+                    id,
+                    size.bytes(),
+                    ecx.machine.current_span(),
+                    instance,
+                    ecx.machine.basic_block_count,
+                );
+            }
+        }
+
         if matches!(kind, MemoryKind::Machine(kind) if kind.should_save_allocation_span()) {
             ecx.machine
                 .allocation_spans
@@ -1128,6 +1727,33 @@ impl<'tcx> Machine<'tcx> for MiriMachine<'tcx> {
         Ok(AllocExtra { borrow_tracker, data_race, weak_memory, backtrace })
     }
 
+    fn adjust_global_allocation<'b>(
+        ecx: &MiriInterpCx<'tcx>,
+        id: AllocId,
+        alloc: &'b Allocation,
+    ) -> InterpResult<'tcx, Cow<'b, Allocation<Provenance, Self::AllocExtra, Self::Bytes>>> {
+        let adjust = || alloc.adjust_from_tcx(&ecx.tcx, |ptr| ecx.global_root_pointer(ptr));
+        let alloc = match ecx.tcx.try_get_global_alloc(id) {
+            // With a native library, each allocation needs its own bytes so that their host
+            // addresses differ.
+            Some(GlobalAlloc::Memory(const_alloc)) if ecx.machine.native_lib.is_none() => {
+                let cached = ecx.machine.adjusted_consts.borrow().get(&const_alloc).cloned();
+                match cached {
+                    Some(alloc) => alloc,
+                    None => {
+                        let alloc = adjust()?;
+                        ecx.machine.adjusted_consts.borrow_mut().insert(const_alloc, alloc.clone());
+                        alloc
+                    }
+                }
+            }
+            _ => adjust()?,
+        };
+        let kind = MemoryKind::Machine(MiriMemoryKind::Global);
+        let extra = Self::init_alloc_extra(ecx, id, kind, alloc.size(), alloc.align)?;
+        Ok(Cow::Owned(alloc.with_extra(extra)))
+    }
+
     fn adjust_alloc_root_pointer(
         ecx: &MiriInterpCx<'tcx>,
         ptr: interpret::Pointer<CtfeProvenance>,
@@ -1212,6 +1838,9 @@ impl<'tcx> Machine<'tcx> for MiriMachine<'tcx> {
             machine
                 .emit_diagnostic(NonHaltingDiagnostic::AccessedAlloc(alloc_id, AccessKind::Read));
         }
+        if let Some(capabilities) = &machine.capabilities {
+            capabilities.borrow().check(alloc_id, prov_extra, range, "reading from")?;
+        }
         if let Some(data_race) = &alloc_extra.data_race {
             data_race.read(alloc_id, range, NaReadType::Read, None, machine)?;
         }
@@ -1236,6 +1865,10 @@ impl<'tcx> Machine<'tcx> for MiriMachine<'tcx> {
             machine
                 .emit_diagnostic(NonHaltingDiagnostic::AccessedAlloc(alloc_id, AccessKind::Write));
         }
+        if let Some(capabilities) = &machine.capabilities {
+            capabilities.borrow().check(alloc_id, prov_extra, range, "writing to")?;
+        }
+        machine.threads.note_progress();
         if let Some(data_race) = &mut alloc_extra.data_race {
             data_race.write(alloc_id, range, NaWriteType::Write, None, machine)?;
         }
@@ -1261,6 +1894,11 @@ impl<'tcx> Machine<'tcx> for MiriMachine<'tcx> {
         if machine.tracked_alloc_ids.contains(&alloc_id) {
             machine.emit_diagnostic(NonHaltingDiagnostic::FreedAlloc(alloc_id));
         }
+        machine.record_profile_event("MiriDeallocation", size.bytes());
+        if let Some(capabilities) = &machine.capabilities {
+            let range = alloc_range(Size::ZERO, size);
+            capabilities.borrow().check(alloc_id, prove_extra, range, "deallocating")?;
+        }
         if let Some(data_race) = &mut alloc_extra.data_race {
             data_race.write(
                 alloc_id,
@@ -1277,6 +1915,12 @@ impl<'tcx> Machine<'tcx> for MiriMachine<'tcx> {
         {
             *deallocated_at = Some(machine.current_span());
         }
+        if matches!(kind, MemoryKind::Machine(kind) if kind.is_heap()) {
+            machine.heap_bytes.set(machine.heap_bytes.get().strict_sub(size.bytes()));
+            if let Some(heap_profile) = &machine.heap_profile {
+                heap_profile.borrow_mut().record_dealloc(alloc_id, machine.basic_block_count);
+            }
+        }
         machine.free_alloc_id(alloc_id, size, align, kind);
         Ok(())
     }
@@ -1288,6 +1932,8 @@ impl<'tcx> Machine<'tcx> for MiriMachine<'tcx> {
         val: &ImmTy<'tcx>,
     ) -> InterpResult<'tcx, ImmTy<'tcx>> {
         if ecx.machine.borrow_tracker.is_some() {
+            ecx.machine.record_profile_event("MiriRetag", 1);
+            ecx.machine.record_function_stats(|stats| stats.retags += 1);
             ecx.retag_ptr_value(kind, val)
         } else {
             Ok(val.clone())
@@ -1301,6 +1947,8 @@ impl<'tcx> Machine<'tcx> for MiriMachine<'tcx> {
         place: &PlaceTy<'tcx>,
     ) -> InterpResult<'tcx> {
         if ecx.machine.borrow_tracker.is_some() {
+            ecx.machine.record_profile_event("MiriRetag", 1);
+            ecx.machine.record_function_stats(|stats| stats.retags += 1);
             ecx.retag_place_contents(kind, place)?;
         }
         Ok(())
@@ -1332,29 +1980,70 @@ impl<'tcx> Machine<'tcx> for MiriMachine<'tcx> {
         ecx: &mut InterpCx<'tcx, Self>,
         frame: Frame<'tcx, Provenance>,
     ) -> InterpResult<'tcx, Frame<'tcx, Provenance, FrameExtra<'tcx>>> {
+        let cached_frame_size = ecx.machine.frame_sizes.borrow().get(&frame.instance).copied();
+        let first_call = cached_frame_size.is_none();
+        let frame_size = cached_frame_size.unwrap_or_else(|| {
+            let size = frame
+                .body
+                .local_decls
+                .iter()
+                .map(|decl| {
+                    let ty = frame.instance.instantiate_mir_and_normalize_erasing_regions(
+                        *ecx.tcx,
+                        ty::ParamEnv::reveal_all(),
+                        ty::EarlyBinder::bind(decl.ty),
+                    );
+                    // Errors will be reported when the local is used.
+                    ecx.layout_of(ty).map_or(0, |layout| layout.size.bytes())
+                })
+                .sum();
+            ecx.machine.frame_sizes.borrow_mut().insert(frame.instance, size);
+            size
+        });
+        let stack_bytes = ecx
+            .active_thread_stack()
+            .last()
+            .map_or(0, |caller| caller.extra.stack_bytes)
+            .saturating_add(frame_size);
+        if stack_bytes > ecx.machine.stack_limit {
+            throw_machine_stop!(TerminationInfo::StackOverflow { limit: ecx.machine.stack_limit });
+        }
+
         // Start recording our event before doing anything else
-        let timing = if let Some(profiler) = ecx.machine.profiler.as_ref() {
-            let fn_name = frame.instance.to_string();
-            let entry = ecx.machine.string_cache.entry(fn_name.clone());
-            let name = entry.or_insert_with(|| profiler.alloc_string(&*fn_name));
-
-            Some(profiler.start_recording_interval_event_detached(
-                *name,
-                measureme::EventId::from_label(*name),
+        let (timing, profile_name) = if let Some(profiler) = ecx.machine.profiler.as_ref() {
+            let name = ecx.machine.profile_string(profiler, &frame.instance.to_string());
+            let timing = profiler.start_recording_interval_event_detached(
+                name,
+                measureme::EventId::from_label(name),
                 ecx.active_thread().to_u32(),
-            ))
+            );
+            (Some(timing), Some(name))
         } else {
-            None
+            (None, None)
         };
 
+        let salt = ecx.machine.rng.borrow_mut().gen::<usize>() % ecx.machine.addrs_per_const;
+        // Every call picks a new salt; logging all of them would drown out everything else.
+        if first_call {
+            ecx.machine.log_nondeterminism("consts", || {
+                format!(
+                    "picked const address salt {salt} for the first call to `{}`",
+                    frame.instance
+                )
+            });
+        }
+
         let borrow_tracker = ecx.machine.borrow_tracker.as_ref();
 
         let extra = FrameExtra {
             borrow_tracker: borrow_tracker.map(|bt| bt.borrow_mut().new_frame(&ecx.machine)),
             catch_unwind: None,
+            exit_code: None,
             timing,
+            profile_name,
             is_user_relevant: ecx.machine.is_user_relevant(&frame),
-            salt: ecx.machine.rng.borrow_mut().gen::<usize>() % ADDRS_PER_CONST,
+            salt,
+            stack_bytes,
         };
 
         Ok(frame.with_extra(extra))
@@ -1375,14 +2064,41 @@ impl<'tcx> Machine<'tcx> for MiriMachine<'tcx> {
     fn before_terminator(ecx: &mut InterpCx<'tcx, Self>) -> InterpResult<'tcx> {
         ecx.machine.basic_block_count += 1u64; // a u64 that is only incremented by 1 will "never" overflow
         ecx.machine.since_gc += 1;
+        ecx.machine.record_function_stats(|stats| stats.blocks += 1);
+        ecx.record_coverage();
         // Possibly report our progress.
         if let Some(report_progress) = ecx.machine.report_progress {
             if ecx.machine.basic_block_count % u64::from(report_progress) == 0 {
-                ecx.emit_diagnostic(NonHaltingDiagnostic::ProgressReport {
-                    block_count: ecx.machine.basic_block_count,
-                });
+                ecx.report_progress();
             }
         }
+        // Abort the program if it exceeded its execution budget.
+        if let Some(max_blocks) = ecx.machine.max_blocks
+            && ecx.machine.basic_block_count > max_blocks
+        {
+            throw_machine_stop!(TerminationInfo::BudgetExceeded {
+                budget: format!("{max_blocks} basic blocks"),
+                flag: "-Zmiri-max-blocks",
+                test: None,
+            });
+        }
+        // Checking the time is not free, so we only do it every now and then.
+        if ecx.machine.basic_block_count % 1024 == 0 {
+            ecx.machine.check_timeout()?;
+        }
+        // Fail a test that exceeded its execution budget. Checking the time is not free, so we
+        // only do it every now and then.
+        if !ecx.machine.running_tests.is_empty() {
+            ecx.check_test_budgets(ecx.machine.basic_block_count % 1024 == 0)?;
+        }
+        // Possibly stop here, to show where the program was shortly before an error.
+        if ecx.machine.stop_at_block == Some(ecx.machine.basic_block_count) {
+            ecx.emit_diagnostic(NonHaltingDiagnostic::StoppedBeforeError {
+                blocks: ecx.machine.stop_before_error,
+            });
+            throw_machine_stop!(TerminationInfo::Exit { code: 0, leak_check: false });
+        }
+        ecx.debugger_check_stop(/* entry */ false)?;
 
         // Search for BorTags to find all live pointers, then remove all other tags from borrow
         // stacks.
@@ -1404,12 +2120,17 @@ impl<'tcx> Machine<'tcx> for MiriMachine<'tcx> {
 
     #[inline(always)]
     fn after_stack_push(ecx: &mut InterpCx<'tcx, Self>) -> InterpResult<'tcx> {
+        ecx.trace_call();
+        if ecx.machine.test_max_blocks.is_some() || ecx.machine.test_timeout.is_some() {
+            ecx.record_test_start();
+        }
         if ecx.frame().extra.is_user_relevant {
             // We just pushed a local frame, so we know that the topmost local frame is the topmost
             // frame. If we push a non-local frame, there's no need to do anything.
             let stack_len = ecx.active_thread_stack().len();
             ecx.active_thread_mut().set_top_user_relevant_frame(stack_len - 1);
         }
+        ecx.debugger_check_stop(/* entry */ true)?;
         Ok(())
     }
 
@@ -1422,6 +2143,9 @@ impl<'tcx> Machine<'tcx> for MiriMachine<'tcx> {
         if ecx.machine.borrow_tracker.is_some() {
             ecx.on_stack_pop(frame)?;
         }
+        if ecx.machine.entry_fn_should_panic.is_some() {
+            eval::check_should_panic(ecx, frame)?;
+        }
         // tracing-tree can autoamtically annotate scope changes, but it gets very confused by our
         // concurrency and what it prints is just plain wrong. So we print our own information
         // instead. (Cc https://github.com/rust-lang/miri/issues/2266)
@@ -1442,11 +2166,21 @@ impl<'tcx> Machine<'tcx> for MiriMachine<'tcx> {
             // user-relevant frame and restore that here.)
             ecx.active_thread_mut().recompute_top_user_relevant_frame();
         }
+        if !ecx.machine.running_tests.is_empty() {
+            ecx.record_test_end();
+        }
+        let thread = ecx.active_thread();
+        let stack_len = ecx.active_thread_stack().len();
+        ecx.machine.jmp_bufs.on_stack_pop(thread, stack_len);
         let res = {
             // Move `frame`` into a sub-scope so we control when it will be dropped.
             let mut frame = frame;
             let timing = frame.extra.timing.take();
-            let res = ecx.handle_stack_pop_unwind(frame.extra, unwinding);
+            let exit_code = frame.extra.exit_code;
+            let res = match exit_code {
+                Some(code) if !unwinding => ecx.return_from_atexit_handler(code),
+                _ => ecx.handle_stack_pop_unwind(frame.extra, unwinding),
+            };
             if let Some(profiler) = ecx.machine.profiler.as_ref() {
                 profiler.finish_recording_interval_event(timing.unwrap());
             }
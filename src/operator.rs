@@ -107,15 +107,44 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         // Pick one of the NaNs.
         let nan = nans.choose(&mut *rand).unwrap();
         // Non-deterministically flip the sign.
-        if rand.gen() {
-            // This will properly flip even for NaN.
-            -nan
-        } else {
-            nan
-        }
+        // This will properly flip even for NaN.
+        let nan = if rand.gen() { -nan } else { nan };
+        this.machine.log_nondeterminism("nan", || format!("picked NaN {:#x}", nan.to_bits()));
+        nan
     }
 
     fn adjust_nan<F1: Float + FloatConvert<F2>, F2: Float>(&self, f: F2, inputs: &[F1]) -> F2 {
         if f.is_nan() { self.generate_nan(inputs) } else { f }
     }
+
+    /// Moves the result `f` of the operation `op`, whose precision is not guaranteed (like `sin`
+    /// or `exp`), by a random number of ULPs (units in the last place), up to
+    /// `-Zmiri-float-max-ulp-error`. This keeps programs from relying on the exact results of the
+    /// host's math library. Results that are zero, infinite or NaN are left alone.
+    fn apply_random_float_error_ulp<F: Float>(&self, f: F, op: &str) -> F {
+        let this = self.eval_context_ref();
+        let max_error = i64::from(this.machine.float_max_ulp_error);
+        if max_error == 0 || !f.is_finite() || f.is_zero() {
+            return f;
+        }
+        let ulps = this.machine.rng.borrow_mut().gen_range(-max_error..=max_error);
+        // For floats of the same sign, the bits of the magnitude are ordered like the floats, and
+        // consecutive values are one ULP apart. So we can just add to the magnitude, as long as we
+        // do not turn the finite, non-zero result into an infinity or a zero.
+        let bits = f.to_bits();
+        let sign = bits & (1 << (F::BITS - 1));
+        let magnitude = bits ^ sign;
+        let steps = u128::from(ulps.unsigned_abs());
+        let new_magnitude = if (ulps > 0) != f.is_negative() {
+            magnitude.saturating_add(steps).min(F::largest().to_bits())
+        } else {
+            magnitude.saturating_sub(steps).max(1)
+        };
+        let moved = i128::try_from(new_magnitude).unwrap() - i128::try_from(magnitude).unwrap();
+        let moved = if f.is_negative() { -moved } else { moved };
+        this.machine.log_nondeterminism("float-error", || {
+            format!("moved the result of `{op}` by {moved} ULP")
+        });
+        F::from_bits(sign | new_magnitude)
+    }
 }
@@ -0,0 +1,256 @@
+//! Running the tests of a libtest test harness in parallel, for `-Zmiri-parallel-tests`, and
+//! writing JUnit reports for `-Zmiri-junit`.
+//!
+//! We first run the harness with `--list` to find out which tests there are, and then run every
+//! test in its own instance of the interpreter, on the threads of rustc's thread pool. All these
+//! instances share the compiled program (including the MIR of the standard library), so the
+//! program is only compiled once. The output of each test (its stdout and stderr, and the
+//! diagnostics that Miri emits for it) is captured and printed once the test is done, so that the
+//! output of different tests does not get mixed up. At the end, we print a summary of all tests.
+//!
+//! `-Zmiri-junit` also runs every test in its own instance of the interpreter (but only in parallel
+//! with `-Zmiri-parallel-tests`), since that way, we know how every test ended, even when Miri
+//! stops one of them with an error.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use rustc_data_structures::sync;
+use rustc_hir::def_id::{DefId, LOCAL_CRATE};
+use rustc_middle::ty::TyCtxt;
+use rustc_session::config::EntryFnType;
+
+use crate::*;
+
+/// The options of libtest that take a value. We need to know them to tell the filters apart from
+/// the values of options.
+const LIBTEST_OPTIONS_WITH_VALUE: &[&str] =
+    &["--logfile", "--test-threads", "--skip", "--color", "--format", "-Z", "--shuffle-seed"];
+
+/// Run the tests of the harness at `entry_id`. `config.args` are the arguments for the harness,
+/// which may include filters. The result is like that of a single run of the harness: the first
+/// error that Miri stopped a test with, or otherwise the exit code of a test that failed.
+pub fn eval_tests_in_parallel<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    entry_id: DefId,
+    entry_type: EntryFnType,
+    config: MiriConfig,
+) -> Result<i64, MiriError> {
+    // The runs of the individual tests must not end up here again.
+    let mut config = MiriConfig { parallel_tests: false, ..config };
+    let junit_out = config.junit_out.take();
+
+    // Split the arguments of the harness into options (each with its value, if it has one) and
+    // filters. The first argument is the name of the program.
+    let mut options: Vec<&[String]> = Vec::new();
+    let mut filters: Vec<&String> = Vec::new();
+    let mut i = 1;
+    while let Some(arg) = config.args.get(i) {
+        let len = if LIBTEST_OPTIONS_WITH_VALUE.contains(&arg.as_str()) { 2 } else { 1 };
+        if arg.starts_with('-') {
+            options.push(&config.args[i..(i + len).min(config.args.len())]);
+        } else {
+            filters.push(arg);
+        }
+        i += len;
+    }
+    let is_option = |option: &[String], name: &str| {
+        option[0] == name || option[0].strip_prefix(name).is_some_and(|rest| rest.starts_with('='))
+    };
+
+    // Ask libtest which tests there are. This also applies the filters.
+    let listing = CapturedOutput::default();
+    let mut list_config = MiriConfig { captured_output: Some(listing.clone()), ..config.clone() };
+    list_config.args = config.args[..1].to_vec();
+    for option in options.iter().filter(|option| !is_option(option, "--format")) {
+        list_config.args.extend_from_slice(option);
+    }
+    list_config.args.extend(filters.into_iter().cloned());
+    list_config.args.extend(["--list", "--format", "terse"].map(String::from));
+    let result = eval_entry(tcx, entry_id, entry_type, list_config);
+    if !matches!(result, Ok(0)) {
+        listing.print(tcx.dcx());
+        return result;
+    }
+    let listing = String::from_utf8_lossy(&listing.stdout.lock().unwrap()).into_owned();
+    let tests: Vec<String> = listing
+        .lines()
+        .filter_map(|line| line.strip_suffix(": test").or_else(|| line.strip_suffix(": bench")))
+        .map(String::from)
+        .collect();
+
+    // Each test runs with the options, but instead of the filters, it gets its own name.
+    let mut args = config.args[..1].to_vec();
+    for option in options.iter().filter(|option| !is_option(option, "--exact")) {
+        args.extend_from_slice(option);
+    }
+    args.push("--exact".to_owned());
+
+    let start = Instant::now();
+    let outcomes = sync::IntoDynSyncSend(Mutex::new(Vec::new()));
+    sync::par_for_each_in(tests, |test| {
+        let output = CapturedOutput::default();
+        let mut config = MiriConfig { captured_output: Some(output.clone()), ..config.clone() };
+        config.args = args.clone();
+        config.args.push(test.clone());
+        let start = Instant::now();
+        let result = eval_entry(tcx, entry_id, entry_type, config);
+        let time = start.elapsed();
+        // Print the output of the test in one piece. Holding the lock on `outcomes` makes sure
+        // that no other test prints at the same time.
+        let mut outcomes = outcomes.0.lock().unwrap();
+        output.print(tcx.dcx());
+        let stdout = output.stdout.lock().unwrap().clone();
+        outcomes.push(TestOutcome { name: test, result, stdout, time });
+    });
+    let mut outcomes = outcomes.0.into_inner().unwrap();
+    outcomes.sort_by(|a, b| a.name.cmp(&b.name));
+
+    if let Some(dir) = &junit_out {
+        let crate_name = tcx.crate_name(LOCAL_CRATE);
+        let path = Path::new(dir).join(format!("{crate_name}-{:07}.xml", std::process::id()));
+        if let Err(err) = write_junit_report(&path, crate_name.as_str(), &outcomes) {
+            tcx.dcx().warn(format!("failed to write the JUnit report: {err}"));
+        }
+    }
+
+    // Print a summary of all tests, like the one libtest prints at the end of a run.
+    let failed: Vec<&TestOutcome> =
+        outcomes.iter().filter(|outcome| !matches!(outcome.result, Ok(0))).collect();
+    let mut summary = String::new();
+    if !failed.is_empty() {
+        writeln!(summary, "\nfailures:").unwrap();
+        for outcome in &failed {
+            let reason = match &outcome.result {
+                Ok(code) => format!("exited with code {code}"),
+                Err(err) => format!("stopped by Miri: {}", err.kind.name()),
+            };
+            writeln!(summary, "    {} ({reason})", outcome.name).unwrap();
+        }
+    }
+    writeln!(
+        summary,
+        "\ntest result: {}. {} passed; {} failed; finished in {:.2}s\n",
+        if failed.is_empty() { "ok" } else { "FAILED" },
+        outcomes.len() - failed.len(),
+        failed.len(),
+        start.elapsed().as_secs_f64(),
+    )
+    .unwrap();
+    print!("{summary}");
+
+    match failed.iter().find_map(|outcome| outcome.result.clone().err()) {
+        Some(err) => Err(err),
+        None => Ok(failed.last().map_or(0, |outcome| *outcome.result.as_ref().unwrap())),
+    }
+}
+
+/// How a test ended, when it was run in its own instance of the interpreter.
+struct TestOutcome {
+    name: String,
+    result: Result<i64, MiriError>,
+    /// What the test printed to stdout.
+    stdout: Vec<u8>,
+    time: Duration,
+}
+
+/// Whether the outcome of a test is an error rather than a failure in JUnit terms: the test did not
+/// fail an assertion, but Miri found that the program is not valid or could not run it.
+fn is_junit_error(result: &Result<i64, MiriError>) -> bool {
+    matches!(
+        result,
+        Err(MiriError { kind: MiriErrorKind::UndefinedBehavior | MiriErrorKind::Unsupported, .. })
+    )
+}
+
+/// Writes a report in the JUnit XML format that CI systems understand. If Miri stopped a test, the
+/// kind of error (e.g. `ub` or `leak`) is the type of its error or failure.
+fn write_junit_report(path: &Path, suite: &str, outcomes: &[TestOutcome]) -> io::Result<()> {
+    let errors = outcomes.iter().filter(|outcome| is_junit_error(&outcome.result)).count();
+    let failures =
+        outcomes.iter().filter(|outcome| !matches!(outcome.result, Ok(0))).count() - errors;
+    let time: Duration = outcomes.iter().map(|outcome| outcome.time).sum();
+    let mut report = String::new();
+    writeln!(report, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(report, "<testsuites>").unwrap();
+    writeln!(
+        report,
+        r#"  <testsuite name="{}" tests="{}" failures="{failures}" errors="{errors}" time="{:.3}">"#,
+        xml_escape(suite),
+        outcomes.len(),
+        time.as_secs_f64(),
+    )
+    .unwrap();
+    for outcome in outcomes {
+        // libtest names tests by their path within the crate; split off the last segment like
+        // libtest's own JUnit output does.
+        let (classname, name) = match outcome.name.rsplit_once("::") {
+            Some((module, name)) => (format!("{suite}::{module}"), name),
+            None => (suite.to_owned(), outcome.name.as_str()),
+        };
+        write!(
+            report,
+            r#"    <testcase classname="{}" name="{}" time="{:.3}""#,
+            xml_escape(&classname),
+            xml_escape(name),
+            outcome.time.as_secs_f64(),
+        )
+        .unwrap();
+        let failure = match &outcome.result {
+            Ok(0) => None,
+            Ok(code) => Some(("failed".to_owned(), format!("the test exited with code {code}"))),
+            Err(err) => Some((err.kind.name().to_owned(), err.message.clone())),
+        };
+        if failure.is_none() && outcome.stdout.is_empty() {
+            writeln!(report, "/>").unwrap();
+            continue;
+        }
+        writeln!(report, ">").unwrap();
+        if let Some((kind, message)) = failure {
+            let element = if is_junit_error(&outcome.result) { "error" } else { "failure" };
+            writeln!(
+                report,
+                r#"      <{element} type="{}" message="{}"/>"#,
+                xml_escape(&kind),
+                xml_escape(&message),
+            )
+            .unwrap();
+        }
+        if !outcome.stdout.is_empty() {
+            writeln!(
+                report,
+                "      <system-out>{}</system-out>",
+                xml_escape(&String::from_utf8_lossy(&outcome.stdout)),
+            )
+            .unwrap();
+        }
+        writeln!(report, "    </testcase>").unwrap();
+    }
+    writeln!(report, "  </testsuite>").unwrap();
+    writeln!(report, "</testsuites>").unwrap();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, report)
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters are not allowed in XML 1.0, except for whitespace.
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => escaped.push('\u{FFFD}'),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,228 @@
+//! A JSON-RPC server on stdin and stdout, for `-Zmiri-server`, so that IDEs can run functions and
+//! tests under Miri without recompiling the program (and the standard library) for every run.
+//!
+//! Messages are framed like in the Language Server Protocol: a `Content-Length` header, an empty
+//! line, and the JSON body. The server understands these requests:
+//! - `run`, with the parameters `function` (the path of a function to run instead of `main`, like
+//!   with `-Zmiri-entry-fn`) or `test` (the name of a test to run with `--exact`), and optionally
+//!   `args` (the arguments of the program, replacing the ones the server was started with) and
+//!   `seed`. The result has the `exitCode`, `stdout` and `stderr` of the program, the
+//!   `diagnostics` Miri emitted (each with a `level`, a `message`, the `spans` it points to, and
+//!   its `children`), and, if Miri stopped the program, an `error` with the `kind` and `message`
+//!   of the error.
+//! - `shutdown`, which makes the server exit after responding.
+//!
+//! The program itself must not read from stdin, since that is where the requests come from.
+
+use std::io::{self, BufRead, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+
+use rustc_errors::{DiagMessage, Level, MultiSpan, Style, Subdiag};
+use rustc_hir::def_id::DefId;
+use rustc_middle::ty::TyCtxt;
+use rustc_session::config::EntryFnType;
+use rustc_span::fatal_error::FatalErrorMarker;
+use serde_json::{json, Value};
+
+use crate::*;
+
+/// The JSON-RPC error codes we use.
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Serve requests until the client sends `shutdown` or closes stdin.
+pub fn serve<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    entry_id: DefId,
+    entry_type: EntryFnType,
+    config: MiriConfig,
+) -> Result<i64, MiriError> {
+    let config = MiriConfig { server: false, ..config };
+    let mut stdin = io::stdin().lock();
+    loop {
+        let msg = match recv(&mut stdin) {
+            Ok(Some(msg)) => msg,
+            Ok(None) => return Ok(0),
+            Err(err) => {
+                send(&json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": { "code": PARSE_ERROR, "message": err.to_string() },
+                }));
+                continue;
+            }
+        };
+        let id = msg["id"].clone();
+        let result = match msg["method"].as_str() {
+            Some("run") => run(tcx, entry_id, entry_type, &config, &msg["params"]),
+            Some("shutdown") => {
+                send(&json!({ "jsonrpc": "2.0", "id": id, "result": null }));
+                return Ok(0);
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method {}", msg["method"]))),
+        };
+        // Notifications (requests without an `id`) do not get a response.
+        if id.is_null() {
+            continue;
+        }
+        send(&match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) =>
+                json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }),
+        });
+    }
+}
+
+/// Handle a `run` request.
+fn run<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    entry_id: DefId,
+    entry_type: EntryFnType,
+    config: &MiriConfig,
+    params: &Value,
+) -> Result<Value, (i64, String)> {
+    let invalid = |what: &str| (INVALID_PARAMS, format!("invalid `{what}` parameter"));
+    let output = CapturedOutput::default();
+    let mut config = MiriConfig { captured_output: Some(output.clone()), ..config.clone() };
+    if let Some(args) = params.get("args") {
+        let args = args.as_array().ok_or_else(|| invalid("args"))?;
+        config.args.truncate(1);
+        for arg in args {
+            config.args.push(arg.as_str().ok_or_else(|| invalid("args"))?.to_owned());
+        }
+    }
+    if let Some(seed) = params.get("seed") {
+        config.seed = Some(seed.as_u64().ok_or_else(|| invalid("seed"))?);
+    }
+    match (params.get("function"), params.get("test")) {
+        (Some(function), None) => {
+            config.entry_fn =
+                Some(function.as_str().ok_or_else(|| invalid("function"))?.to_owned());
+        }
+        (None, Some(test)) => {
+            let test = test.as_str().ok_or_else(|| invalid("test"))?;
+            config.args.extend(["--exact".to_owned(), test.to_owned()]);
+        }
+        (None, None) => {}
+        (Some(_), Some(_)) =>
+            return Err((
+                INVALID_PARAMS,
+                "only one of `function` and `test` can be given".to_owned(),
+            )),
+    }
+
+    // A bad `function` is a fatal error; that should not end the whole server.
+    let result =
+        panic::catch_unwind(AssertUnwindSafe(|| eval_entry(tcx, entry_id, entry_type, config)))
+            .unwrap_or_else(|payload| {
+                if !payload.is::<FatalErrorMarker>() {
+                    panic::resume_unwind(payload);
+                }
+                Err(MiriError {
+                    kind: MiriErrorKind::Other,
+                    message: "the program could not be started".to_owned(),
+                })
+            });
+    // Errors that were emitted outside of the program's `DiagCtxt` (e.g. while setting up the
+    // interpreter) must not count against the next request, nor make the server fail at the end.
+    tcx.dcx().reset_err_count();
+    let diagnostics: Vec<Value> = output
+        .diagnostics
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|diag| {
+            diagnostic_to_json(tcx, diag.level(), &diag.messages, &diag.span, &diag.children)
+        })
+        .collect();
+    let stdout = String::from_utf8_lossy(&output.stdout.lock().unwrap()).into_owned();
+    let stderr = String::from_utf8_lossy(&output.stderr.lock().unwrap()).into_owned();
+    Ok(match result {
+        Ok(exit_code) =>
+            json!({
+                "exitCode": exit_code,
+                "stdout": stdout,
+                "stderr": stderr,
+                "diagnostics": diagnostics,
+            }),
+        Err(error) =>
+            json!({
+                "exitCode": null,
+                "stdout": stdout,
+                "stderr": stderr,
+                "diagnostics": diagnostics,
+                "error": { "kind": error.kind.name(), "message": error.message },
+            }),
+    })
+}
+
+/// Convert a diagnostic (or, without `children`, a sub-diagnostic) to JSON.
+fn diagnostic_to_json(
+    tcx: TyCtxt<'_>,
+    level: Level,
+    messages: &[(DiagMessage, Style)],
+    span: &MultiSpan,
+    children: &[Subdiag],
+) -> Value {
+    // Miri only emits diagnostics with plain string messages, which need no translation.
+    let message: String = messages.iter().filter_map(|(msg, _)| msg.as_str()).collect();
+    let sm = tcx.sess.source_map();
+    let spans: Vec<Value> = span
+        .span_labels()
+        .into_iter()
+        .filter(|label| !label.span.is_dummy())
+        .map(|label| {
+            let loc = sm.lookup_char_pos(label.span.lo());
+            json!({
+                "file": sm.filename_for_diagnostics(&loc.file.name).to_string(),
+                "line": loc.line,
+                "column": loc.col.0.strict_add(1),
+                "isPrimary": label.is_primary,
+                "label": label.label.as_ref().and_then(|label| label.as_str()),
+            })
+        })
+        .collect();
+    let children: Vec<Value> = children
+        .iter()
+        .map(|child| diagnostic_to_json(tcx, child.level, &child.messages, &child.span, &[]))
+        .collect();
+    json!({
+        "level": level.to_str(),
+        "message": message,
+        "spans": spans,
+        "children": children,
+    })
+}
+
+/// Receive the next message, or `None` if the client closed stdin.
+fn recv(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(n) = line.strip_prefix("Content-Length:") {
+            len = n.trim().parse::<usize>().ok();
+        }
+    }
+    let Some(len) = len else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "missing `Content-Length`"));
+    };
+    let mut buf = vec![0; len];
+    reader.read_exact(&mut buf)?;
+    Ok(Some(serde_json::from_slice(&buf)?))
+}
+
+fn send(msg: &Value) {
+    let msg = msg.to_string();
+    let mut stdout = io::stdout().lock();
+    // If the client went away, there is nobody to tell about it.
+    let _ignore = write!(stdout, "Content-Length: {}\r\n\r\n{msg}", msg.len());
+    let _ignore = stdout.flush();
+}
@@ -1,5 +1,3 @@
-use std::iter;
-
 use rustc_ast::expand::allocator::AllocatorKind;
 use rustc_target::abi::{Align, Size};
 
@@ -18,6 +16,9 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         // `library/std/src/sys/pal/common/alloc.rs` (where this is called `MIN_ALIGN`) and should
         // be kept in sync.
         let max_fundamental_align = match this.tcx.sess.target.arch.as_ref() {
+            // These targets have no `std`; we use the largest alignment of their primitive types.
+            "avr" => 1,
+            "msp430" => 2,
             "x86" | "arm" | "mips" | "mips32r6" | "powerpc" | "powerpc64" | "wasm32" => 8,
             "x86_64" | "aarch64" | "mips64" | "mips64r6" | "s390x" | "sparc64" | "loongarch64" =>
                 16,
@@ -80,17 +81,106 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         }
     }
 
+    /// Whether a heap allocation that needs `size` more bytes should fail: because it would exceed
+    /// `-Zmiri-memory-limit`, because the program asked for it with `miri_fail_next_alloc`, or at
+    /// random, with `-Zmiri-alloc-failure-rate`.
+    fn should_fail_alloc(&mut self, size: u64) -> bool {
+        use rand::Rng as _;
+
+        let this = self.eval_context_mut();
+        if this
+            .machine
+            .memory_limit
+            .is_some_and(|limit| this.machine.heap_bytes.get().saturating_add(size) > limit)
+        {
+            return true;
+        }
+        if std::mem::take(&mut this.machine.fail_next_alloc) {
+            return true;
+        }
+        let rate = this.machine.alloc_failure_rate;
+        rate > 0.0 && this.machine.rng.get_mut().gen_bool(rate)
+    }
+
+    /// Mark the `size` bytes at `ptr`, which must be part of an allocation that was just created,
+    /// as initialized to zero. The bytes of a fresh allocation are already zero, so unlike writing
+    /// zeroes, this does not touch them; for huge allocations, that means the host does not have
+    /// to provide memory for the parts the program does not use.
+    fn mark_fresh_alloc_zeroed(&mut self, ptr: Pointer, size: Size) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        if size == Size::ZERO {
+            return Ok(());
+        }
+        let (alloc_id, offset, _tag) = this.ptr_get_alloc_id(ptr)?;
+        let tcx = *this.tcx;
+        let (alloc, _machine) = this.get_alloc_raw_mut(alloc_id)?;
+        // This marks the range as initialized and without provenance, but leaves the bytes alone.
+        // The range is in-bounds and there is no provenance yet, so this cannot fail.
+        alloc.get_bytes_unchecked_for_overwrite_ptr(&tcx, alloc_range(offset, size)).unwrap();
+        Ok(())
+    }
+
+    /// Like `mem_copy`, but when `size` bytes cover both the entire source and the entire
+    /// destination allocation, the destination shares the bytes (and takes a copy of the
+    /// provenance and initialization state) of the source, instead of copying them byte by byte.
+    /// The bytes are only really copied once one of the two allocations gets written to.
+    ///
+    /// Only the `memcpy` shim uses this. Copies made by the interpreter core (typed copies,
+    /// `ptr::copy` and `ptr::copy_nonoverlapping`) always copy the bytes.
+    fn mem_copy_sharing(
+        &mut self,
+        src: Pointer,
+        dest: Pointer,
+        size: Size,
+        nonoverlapping: bool,
+    ) -> InterpResult<'tcx> {
+        /// Smaller copies are not worth the bookkeeping.
+        const SHARE_THRESHOLD: u64 = 1 << 16;
+
+        let this = self.eval_context_mut();
+        // With a native library, the host addresses of the bytes must stay put.
+        let whole_allocs = if size.bytes() >= SHARE_THRESHOLD && this.machine.native_lib.is_none() {
+            match (this.ptr_try_get_alloc_id(src), this.ptr_try_get_alloc_id(dest)) {
+                (Ok((src_id, src_offset, _)), Ok((dest_id, dest_offset, _)))
+                    if src_id != dest_id
+                        && src_offset == Size::ZERO
+                        && dest_offset == Size::ZERO
+                        && this.get_alloc_info(src_id).0 == size
+                        && this.get_alloc_info(dest_id).0 == size =>
+                    Some((src_id, dest_id)),
+                _ => None,
+            }
+        } else {
+            None
+        };
+        let Some((src_id, dest_id)) = whole_allocs else {
+            return this.mem_copy(src, dest, size, nonoverlapping);
+        };
+        // This performs all the checks (and borrow tracking and data race detection) of the
+        // accesses that a regular copy would do.
+        this.get_ptr_alloc(src, size)?;
+        this.get_ptr_alloc_mut(dest, size)?;
+
+        let src_alloc = this.get_alloc_raw(src_id)?.clone();
+        let (dest_alloc, _machine) = this.get_alloc_raw_mut(dest_id)?;
+        let mut new_alloc = src_alloc;
+        // Everything about the destination except for its contents stays the same.
+        std::mem::swap(&mut new_alloc.extra, &mut dest_alloc.extra);
+        new_alloc.align = dest_alloc.align;
+        new_alloc.mutability = dest_alloc.mutability;
+        *dest_alloc = new_alloc;
+        Ok(())
+    }
+
     fn malloc(&mut self, size: u64, zero_init: bool) -> InterpResult<'tcx, Pointer> {
         let this = self.eval_context_mut();
+        if this.should_fail_alloc(size) {
+            return Ok(Pointer::null());
+        }
         let align = this.malloc_align(size);
         let ptr = this.allocate_ptr(Size::from_bytes(size), align, MiriMemoryKind::C.into())?;
         if zero_init {
-            // We just allocated this, the access is definitely in-bounds and fits into our address space.
-            this.write_bytes_ptr(
-                ptr.into(),
-                iter::repeat(0u8).take(usize::try_from(size).unwrap()),
-            )
-            .unwrap();
+            this.mark_fresh_alloc_zeroed(ptr.into(), Size::from_bytes(size))?;
         }
         Ok(ptr.into())
     }
@@ -110,6 +200,8 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         // But failure to adhere to this is not UB, it's an error condition.
         if !align.is_power_of_two() || align < this.pointer_size().bytes() {
             Ok(this.eval_libc("EINVAL"))
+        } else if this.should_fail_alloc(size) {
+            Ok(this.eval_libc("ENOMEM"))
         } else {
             let ptr = this.allocate_ptr(
                 Size::from_bytes(size),
@@ -141,6 +233,18 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 // <https://www.open-std.org/jtc1/sc22/wg14/www/docs/n2464.pdf>
                 throw_ub_format!("`realloc` with a size of zero");
             } else {
+                // The number of additional bytes only matters for the memory limit.
+                let growth = if this.machine.memory_limit.is_some() {
+                    let (alloc_id, _, _) = this.ptr_get_alloc_id(old_ptr)?;
+                    let (old_size, _, _) = this.get_alloc_info(alloc_id);
+                    new_size.saturating_sub(old_size.bytes())
+                } else {
+                    new_size
+                };
+                if this.should_fail_alloc(growth) {
+                    // The old allocation stays untouched.
+                    return Ok(Pointer::null());
+                }
                 let new_ptr = this.reallocate_ptr(
                     old_ptr,
                     None,
@@ -182,6 +286,9 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         // FreeBSD: https://man.freebsd.org/cgi/man.cgi?query=aligned_alloc&apropos=0&sektion=3&manpath=FreeBSD+9-current&format=html
         match size.checked_rem(align) {
             Some(0) if align.is_power_of_two() => {
+                if this.should_fail_alloc(size) {
+                    return Ok(Pointer::null());
+                }
                 let align = align.max(this.malloc_align(size).bytes());
                 let ptr = this.allocate_ptr(
                     Size::from_bytes(size),
@@ -0,0 +1,119 @@
+//! Implement `atexit` and friends, which register functions to run when the program exits.
+
+use rustc_middle::{mir, ty};
+use rustc_target::spec::abi::Abi;
+
+use crate::*;
+
+#[derive(Clone, Debug)]
+enum AtExitHandler<'tcx> {
+    /// A `void f(void)`, registered with `atexit`.
+    NoArg(ty::Instance<'tcx>),
+    /// A `void f(void *)` and its argument, registered with `__cxa_atexit`.
+    WithArg(ty::Instance<'tcx>, Scalar),
+    /// An `int f(void)`, registered with `_onexit` on Windows.
+    ReturnsInt(ty::Instance<'tcx>),
+}
+
+/// The handlers that are still to be called, in the order they were registered.
+#[derive(Default, Debug)]
+pub struct AtExitHandlers<'tcx> {
+    handlers: Vec<AtExitHandler<'tcx>>,
+}
+
+impl VisitProvenance for AtExitHandlers<'_> {
+    fn visit_provenance(&self, visit: &mut VisitWith<'_>) {
+        let AtExitHandlers { handlers } = self;
+
+        for handler in handlers {
+            if let AtExitHandler::WithArg(_, arg) = handler {
+                arg.visit_provenance(visit);
+            }
+        }
+    }
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    fn atexit(&mut self, func_op: &OpTy<'tcx>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let func = this.get_ptr_fn(this.read_pointer(func_op)?)?.as_instance()?;
+        this.machine.atexit_handlers.handlers.push(AtExitHandler::NoArg(func));
+        Ok(0)
+    }
+
+    fn cxa_atexit(
+        &mut self,
+        func_op: &OpTy<'tcx>,
+        arg_op: &OpTy<'tcx>,
+        dso_handle_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let func = this.get_ptr_fn(this.read_pointer(func_op)?)?.as_instance()?;
+        let arg = this.read_scalar(arg_op)?;
+        // The DSO handle is only relevant for unloading shared libraries, which we do not support.
+        let _dso_handle = this.read_pointer(dso_handle_op)?;
+        this.machine.atexit_handlers.handlers.push(AtExitHandler::WithArg(func, arg));
+        Ok(0)
+    }
+
+    fn onexit(&mut self, func_op: &OpTy<'tcx>) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        let func_ptr = this.read_pointer(func_op)?;
+        let func = this.get_ptr_fn(func_ptr)?.as_instance()?;
+        this.machine.atexit_handlers.handlers.push(AtExitHandler::ReturnsInt(func));
+        // On success, the function pointer is returned.
+        Ok(Scalar::from_pointer(func_ptr, this))
+    }
+
+    /// Calls the handler that was registered last, and removes it from the list. Returns `false`
+    /// if there are no handlers left.
+    fn call_next_atexit_handler(&mut self, stack_pop: StackPopCleanup) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+
+        let Some(handler) = this.machine.atexit_handlers.handlers.pop() else {
+            return Ok(false);
+        };
+        trace!("Running atexit handler {:?}", handler);
+        match handler {
+            AtExitHandler::NoArg(func) =>
+                this.call_function(func, Abi::C { unwind: false }, &[], None, stack_pop)?,
+            AtExitHandler::WithArg(func, arg) =>
+                this.call_function(func, Abi::C { unwind: false }, &[arg.into()], None, stack_pop)?,
+            AtExitHandler::ReturnsInt(func) => {
+                // The return value is ignored, but we need somewhere to put it.
+                let dest =
+                    this.allocate(this.machine.layouts.i32, MiriMemoryKind::Machine.into())?;
+                this.call_function(func, Abi::C { unwind: false }, &[], Some(&dest), stack_pop)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Exit the program with the given exit code, after running the `atexit` handlers. This does
+    /// not check for leaks, since the stack frames that are still around may own some memory.
+    fn exit(&mut self, code: i64) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        // The handlers run on top of the current stack. The frame is marked so that when it is
+        // popped, `return_from_atexit_handler` continues with the next handler.
+        let stack_pop = StackPopCleanup::Goto { ret: None, unwind: mir::UnwindAction::Unreachable };
+        if this.call_next_atexit_handler(stack_pop)? {
+            this.frame_mut().extra.exit_code = Some(code);
+            Ok(())
+        } else {
+            throw_machine_stop!(TerminationInfo::Exit { code, leak_check: false });
+        }
+    }
+
+    /// Called when the frame of an `atexit` handler that was called by `exit` returns.
+    fn return_from_atexit_handler(&mut self, code: i64) -> InterpResult<'tcx, StackPopJump> {
+        let this = self.eval_context_mut();
+        this.exit(code)?;
+        // We pushed the frame of the next handler, the engine should not do any jumping now!
+        Ok(StackPopJump::NoJump)
+    }
+}
@@ -130,7 +130,12 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let lo =
             this.tcx.sess.source_map().lookup_char_pos(BytePos(offset.bytes().try_into().unwrap()));
 
-        let name = fn_instance.to_string();
+        let name = this
+            .machine
+            .frame_name_cache
+            .entry(fn_instance)
+            .or_insert_with(|| fn_instance.to_string())
+            .clone();
         let filename = lo.file.name.prefer_remapped_unconditionaly().to_string();
 
         Ok((fn_instance, lo, name, filename))
@@ -0,0 +1,53 @@
+//! Measuring benchmarks is meaningless in Miri: time is virtual, and everything is orders of
+//! magnitude slower than in a real execution. So when the program asks libtest to measure a
+//! benchmark, we make it run the benchmarked code just once, like `cargo test` does.
+
+use rustc_middle::ty;
+use rustc_span::sym;
+
+use crate::*;
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// If `instance` is libtest's `Bencher::iter`, switch the `Bencher` to the mode libtest uses
+    /// for benchmarks that are run as tests, in which `iter` calls the closure once instead of
+    /// hundreds of times.
+    fn bench_run_once(
+        &mut self,
+        instance: ty::Instance<'tcx>,
+        args: &[FnArg<'tcx, Provenance>],
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let tcx = this.tcx.tcx;
+        let def_id = instance.def_id();
+        // Check the crate first, this is called for every function call.
+        if tcx.crate_name(def_id.krate) != sym::test
+            || tcx.opt_item_name(def_id).is_none_or(|name| name.as_str() != "iter")
+        {
+            return Ok(());
+        }
+        // `test::bench` also has a free function called `iter`, which does the measuring.
+        let Some(impl_def_id) = tcx.impl_of_method(def_id) else { return Ok(()) };
+        let ty::Adt(bencher, _) = tcx.type_of(impl_def_id).skip_binder().kind() else {
+            return Ok(());
+        };
+        if tcx.item_name(bencher.did()).as_str() != "Bencher" {
+            return Ok(());
+        }
+
+        // `Bencher::iter` takes `&mut self`.
+        let bencher = this.copy_fn_arg(&args[0]);
+        let bencher = this.deref_pointer(&bencher)?;
+        let mode = this.project_field_named(&bencher, "mode")?;
+        let ty::Adt(mode_adt, _) = mode.layout.ty.kind() else {
+            bug!("`Bencher::mode` is not an enum")
+        };
+        let (single, _) = mode_adt
+            .variants()
+            .iter_enumerated()
+            .find(|(_, variant)| variant.name.as_str() == "Single")
+            .expect("`BenchMode` has no `Single` variant");
+        this.write_discriminant(single, &mode)?;
+        Ok(())
+    }
+}
@@ -33,8 +33,14 @@ impl<'tcx> EnvVars<'tcx> {
         let mut env_vars = FxHashMap::default();
         if ecx.machine.communicate() || !config.forwarded_env_vars.is_empty() {
             for (name, value) in &config.env {
-                let forward = ecx.machine.communicate()
-                    || config.forwarded_env_vars.iter().any(|v| **v == *name);
+                // Variables whose names are not valid UTF-8 can only be forwarded wholesale.
+                let matches = |patterns: &[String]| {
+                    name.to_str().is_some_and(|name| {
+                        patterns.iter().any(|pattern| helpers::glob_matches(pattern, name))
+                    })
+                };
+                let forward = (ecx.machine.communicate() || matches(&config.forwarded_env_vars))
+                    && !matches(&config.excluded_env_vars);
                 if forward {
                     env_vars.insert(OsString::from(name), OsString::from(value));
                 }
@@ -1,5 +1,8 @@
 //! Provides the `extern static` that this platform expects.
 
+use rustc_span::Symbol;
+use rustc_target::abi::{Align, Size};
+
 use crate::*;
 
 impl<'tcx> MiriMachine<'tcx> {
@@ -44,8 +47,57 @@ impl<'tcx> MiriMachine<'tcx> {
         Ok(())
     }
 
+    /// The C standard streams `stdin`, `stdout` and `stderr`, under the names the target uses for
+    /// them. We do not implement `FILE`: these pointers are opaque handles that are only good for
+    /// passing to `fprintf`. Behind them is just the number of their file descriptor, so the
+    /// program must not look inside (e.g. at glibc's `_fileno`), and passing them to any other
+    /// `FILE *` function makes Miri report it as unsupported, since we have no such shims.
+    fn std_stream_extern_statics(
+        this: &mut MiriInterpCx<'tcx>,
+        names: [&str; 3],
+    ) -> InterpResult<'tcx> {
+        for (fd, name) in (0..).zip(names) {
+            let file = this.allocate(this.machine.layouts.i32, MiriMemoryKind::Machine.into())?;
+            this.write_scalar(Scalar::from_i32(fd), &file)?;
+            this.mark_immutable(&file);
+            let val = ImmTy::from_scalar(
+                Scalar::from_maybe_pointer(file.ptr(), this),
+                this.machine.layouts.mut_raw_ptr,
+            );
+            Self::alloc_extern_static(this, name, val)?;
+        }
+        Ok(())
+    }
+
+    /// Extern statics declared with `-Zmiri-extern-static`. We do not know the type they are
+    /// declared with, so we give them an alignment that is enough for any primitive type.
+    fn user_extern_statics(
+        this: &mut MiriInterpCx<'tcx>,
+        statics: &[(String, Vec<u8>)],
+    ) -> InterpResult<'tcx> {
+        for (name, bytes) in statics {
+            if this.machine.extern_statics.contains_key(&Symbol::intern(name)) {
+                throw_unsup_format!(
+                    "-Zmiri-extern-static: extern static `{name}` is already provided by Miri"
+                );
+            }
+            let ptr = this.allocate_ptr(
+                Size::from_bytes(bytes.len()),
+                Align::from_bytes(16).unwrap(),
+                MiriMemoryKind::ExternStatic.into(),
+            )?;
+            this.write_bytes_ptr(ptr.into(), bytes.iter().copied())?;
+            Self::add_extern_static(this, name, ptr.into());
+            this.machine.user_extern_statics.insert(Symbol::intern(name));
+        }
+        Ok(())
+    }
+
     /// Sets up the "extern statics" for this machine.
-    pub fn init_extern_statics(this: &mut MiriInterpCx<'tcx>) -> InterpResult<'tcx> {
+    pub fn init_extern_statics(
+        this: &mut MiriInterpCx<'tcx>,
+        config: &MiriConfig,
+    ) -> InterpResult<'tcx> {
         // "__rust_no_alloc_shim_is_unstable"
         let val = ImmTy::from_int(0, this.machine.layouts.u8); // always 0, value does not matter
         Self::alloc_extern_static(this, "__rust_no_alloc_shim_is_unstable", val)?;
@@ -68,13 +120,19 @@ impl<'tcx> MiriMachine<'tcx> {
                     &["__cxa_thread_atexit_impl", "__clock_gettime64"],
                 )?;
                 Self::weak_symbol_extern_statics(this, &["getrandom", "statx"])?;
+                Self::std_stream_extern_statics(this, ["stdin", "stdout", "stderr"])?;
             }
             "freebsd" => {
                 Self::null_ptr_extern_statics(this, &["__cxa_thread_atexit_impl"])?;
+                Self::std_stream_extern_statics(this, ["__stdinp", "__stdoutp", "__stderrp"])?;
             }
             "android" => {
                 Self::null_ptr_extern_statics(this, &["bsd_signal"])?;
                 Self::weak_symbol_extern_statics(this, &["signal", "getrandom"])?;
+                Self::std_stream_extern_statics(this, ["stdin", "stdout", "stderr"])?;
+            }
+            "macos" => {
+                Self::std_stream_extern_statics(this, ["__stdinp", "__stdoutp", "__stderrp"])?;
             }
             "windows" => {
                 // "_tls_used"
@@ -87,6 +145,8 @@ impl<'tcx> MiriMachine<'tcx> {
             }
             _ => {} // No "extern statics" supported on this target
         }
+
+        Self::user_extern_statics(this, &config.extern_statics)?;
         Ok(())
     }
 }
@@ -1,12 +1,12 @@
-use std::{collections::hash_map::Entry, io::Write, iter, path::Path};
+use std::{collections::hash_map::Entry, path::Path};
 
-use rustc_apfloat::Float;
+use rustc_apfloat::{ieee::Quad, Float};
 use rustc_ast::expand::allocator::alloc_error_handler_name;
 use rustc_hir::{def::DefKind, def_id::CrateNum};
 use rustc_middle::middle::codegen_fn_attrs::CodegenFnAttrFlags;
 use rustc_middle::mir;
 use rustc_middle::ty;
-use rustc_span::Symbol;
+use rustc_span::{sym, Symbol};
 use rustc_target::{
     abi::{Align, Size},
     spec::abi::Abi,
@@ -14,11 +14,12 @@ use rustc_target::{
 
 use super::alloc::EvalContextExt as _;
 use super::backtrace::EvalContextExt as _;
+use super::x86::x87::EvalContextExt as _;
 use crate::*;
 use helpers::{ToHost, ToSoft};
 
 /// Type of dynamic symbols (for `dlsym` et al)
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct DynSym(Symbol);
 
 #[allow(clippy::should_implement_trait)]
@@ -47,6 +48,8 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     ) -> InterpResult<'tcx, Option<(&'tcx mir::Body<'tcx>, ty::Instance<'tcx>)>> {
         let this = self.eval_context_mut();
         let tcx = this.tcx.tcx;
+        // Calling into the environment (e.g. to check the time) counts as making progress.
+        this.machine.threads.note_progress();
 
         // Some shims forward to other MIR bodies.
         match link_name.as_str() {
@@ -58,6 +61,13 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 // called.
                 this.check_abi_and_shim_symbol_clash(abi, Abi::Rust, link_name)?;
                 let panic_impl_id = tcx.lang_items().panic_impl().unwrap();
+                if this.machine.abort_in_panic_handler
+                    && tcx.crate_name(panic_impl_id.krate) != sym::std
+                {
+                    // This is a `no_std` program, and we are asked not to run its panic handler
+                    // (which often just loops forever).
+                    throw_machine_stop!(TerminationInfo::Abort("the program panicked".to_owned()));
+                }
                 let panic_impl_instance = ty::Instance::mono(tcx, panic_impl_id);
                 return Ok(Some((
                     this.load_mir(panic_impl_instance.def, None)?,
@@ -82,7 +92,19 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         }
 
         // The rest either implements the logic, or falls back to `lookup_exported_symbol`.
-        match this.emulate_foreign_item_inner(link_name, abi, args, dest)? {
+        let trace_args = this.trace_shim_args(args);
+        let res = match this.call_shim_hook(link_name, abi, args, dest) {
+            Ok(EmulateItemResult::NotSupported) =>
+                this.emulate_foreign_item_inner(link_name, abi, args, dest),
+            res => res,
+        };
+        if let Some(trace_args) = trace_args
+            && !matches!(res, Ok(EmulateItemResult::NotSupported))
+        {
+            let ret = matches!(res, Ok(EmulateItemResult::NeedsReturn)).then_some(dest);
+            this.trace_shim_return(link_name, trace_args, ret);
+        }
+        match res? {
             EmulateItemResult::NeedsReturn => {
                 trace!("{:?}", this.dump_place(&dest.clone().into()));
                 this.return_to_block(ret)?;
@@ -286,6 +308,16 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 this.handle_miri_start_unwind(payload)?;
                 return Ok(EmulateItemResult::NeedsUnwind);
             }
+            "miri_throw_foreign_exception" => {
+                let [] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                this.handle_miri_throw_foreign_exception()?;
+                return Ok(EmulateItemResult::NeedsUnwind);
+            }
+            "miri_exit" => {
+                let [code] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                let code = this.read_scalar(code)?.to_i32()?;
+                throw_machine_stop!(TerminationInfo::Exit { code: code.into(), leak_check: false });
+            }
             "miri_run_provenance_gc" => {
                 let [] = this.check_shim(abi, Abi::Rust, link_name, args)?;
                 this.run_provenance_gc();
@@ -308,6 +340,11 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     this.print_borrow_state(AllocId(id), show_unnamed)?;
                 }
             }
+            "miri_get_tag_history" => {
+                let [ptr] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                let ptr = this.read_pointer(ptr)?;
+                this.print_tag_history(ptr)?;
+            }
             "miri_pointer_name" => {
                 // This associates a name to a tag. Very useful for debugging, and also makes
                 // tests more strict.
@@ -376,11 +413,77 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let msg = this.read_byte_slice(&msg)?;
                 // Note: we're ignoring errors writing to host stdout/stderr.
                 let _ignore = match link_name.as_str() {
-                    "miri_write_to_stdout" => std::io::stdout().write_all(msg),
-                    "miri_write_to_stderr" => std::io::stderr().write_all(msg),
+                    "miri_write_to_stdout" => this.machine.write_to_stdout(msg),
+                    "miri_write_to_stderr" => this.machine.write_to_stderr(msg),
                     _ => unreachable!(),
                 };
             }
+            // Records a value for the litmus test summary.
+            "miri_observe" => {
+                let [name, value] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                let name = this.read_immediate(name)?;
+                let name = String::from_utf8_lossy(this.read_byte_slice(&name)?).into_owned();
+                let value = this.read_scalar(value)?.to_u64()?;
+                if let Some(observations) = &mut this.machine.observations {
+                    observations.push((name, value));
+                }
+            }
+            // Marks a point the program can be restarted at, and restarts it there.
+            "miri_restart_point" => {
+                let [] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                // Without restart support, the program can never be restarted at this point.
+                let restarts = this.machine.restart_points.as_mut().map_or(0, |r| r.reach());
+                this.write_scalar(Scalar::from_target_usize(restarts, this), dest)?;
+            }
+            "miri_restart" => {
+                let [id] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                let id = this.read_target_usize(id)?;
+                if this.machine.communicate() {
+                    throw_unsup_format!(
+                        "`miri_restart` requires isolation, since the program is run again from the start"
+                    );
+                }
+                let Some(restart_points) = &mut this.machine.restart_points else {
+                    throw_unsup_format!(
+                        "`miri_restart` cannot be combined with -Zmiri-dpor, -Zmiri-model-check, or -Zmiri-litmus"
+                    );
+                };
+                if !restart_points.restart(id) {
+                    throw_ub_format!("`miri_restart`: there is no restart point with ID {id}");
+                }
+                // End this execution; the program will be run again from the start.
+                throw_machine_stop!(TerminationInfo::Exit { code: 0, leak_check: false });
+            }
+            // Overrides the preemption rate of the current thread.
+            "miri_set_preemption_rate" => {
+                let [rate] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                let rate = this.read_scalar(rate)?.to_f64()?.to_host();
+                if !(0.0..=1.0).contains(&rate) {
+                    throw_unsup_format!(
+                        "`miri_set_preemption_rate`: rate must be between 0.0 and 1.0, got {rate}"
+                    );
+                }
+                let thread = this.active_thread();
+                this.machine.threads.set_thread_preemption_rate(thread, rate);
+            }
+            // Narrows the bounds of a capability, with `-Zmiri-cheri`.
+            "miri_cheri_set_bounds" => {
+                let [ptr, len] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                let ptr = this.read_pointer(ptr)?;
+                let len = this.read_target_usize(len)?;
+                let ptr = this.cheri_set_bounds(ptr, len)?;
+                this.write_pointer(ptr, dest)?;
+            }
+            // Makes the next heap allocation fail.
+            "miri_fail_next_alloc" => {
+                let [] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                this.machine.fail_next_alloc = true;
+            }
+            // Stops the program in the attached debugger, or in a REPL if there is none.
+            "miri_breakpoint" => {
+                let [] = this.check_shim(abi, Abi::Rust, link_name, args)?;
+                this.debugger_breakpoint()?;
+            }
             // Promises that a pointer has a given symbolic alignment.
             "miri_promise_symbolic_alignment" => {
                 use rustc_target::abi::AlignFromBytesError;
@@ -427,8 +530,16 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
             // Aborting the process.
             "exit" => {
                 let [code] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
-                let code = this.read_scalar(code)?.to_i32()?;
-                throw_machine_stop!(TerminationInfo::Exit { code: code.into(), leak_check: false });
+                // `c_int` is only 16 bits wide on some targets.
+                let code = this.read_scalar(code)?.to_int(code.layout.size)?;
+                let code = i64::try_from(code).unwrap();
+                this.exit(code)?;
+                return Ok(EmulateItemResult::AlreadyJumped);
+            }
+            "atexit" => {
+                let [func] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.atexit(func)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
             }
             "abort" => {
                 let [] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
@@ -480,6 +591,10 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
                     this.check_rustc_alloc_request(size, align)?;
 
+                    if this.should_fail_alloc(size) {
+                        return this.write_pointer(Pointer::null(), dest);
+                    }
+
                     let memory_kind = match link_name.as_str() {
                         "__rust_alloc" => MiriMemoryKind::Rust,
                         "miri_alloc" => MiriMemoryKind::Miri,
@@ -514,18 +629,17 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
                     this.check_rustc_alloc_request(size, align)?;
 
+                    if this.should_fail_alloc(size) {
+                        return this.write_pointer(Pointer::null(), dest);
+                    }
+
                     let ptr = this.allocate_ptr(
                         Size::from_bytes(size),
                         Align::from_bytes(align).unwrap(),
                         MiriMemoryKind::Rust.into(),
                     )?;
 
-                    // We just allocated this, the access is definitely in-bounds.
-                    this.write_bytes_ptr(
-                        ptr.into(),
-                        iter::repeat(0u8).take(usize::try_from(size).unwrap()),
-                    )
-                    .unwrap();
+                    this.mark_fresh_alloc_zeroed(ptr.into(), Size::from_bytes(size))?;
                     this.write_pointer(ptr, dest)
                 });
             }
@@ -578,6 +692,11 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
                     this.check_rustc_alloc_request(new_size, align)?;
 
+                    if this.should_fail_alloc(new_size.saturating_sub(old_size)) {
+                        // The old allocation stays untouched.
+                        return this.write_pointer(Pointer::null(), dest);
+                    }
+
                     let align = Align::from_bytes(align).unwrap();
                     let new_ptr = this.reallocate_ptr(
                         ptr,
@@ -614,13 +733,13 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     }
                 };
 
-                this.write_scalar(Scalar::from_i32(result), dest)?;
+                this.write_int(result, dest)?;
             }
             "memrchr" => {
                 let [ptr, val, num] =
                     this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let ptr = this.read_pointer(ptr)?;
-                let val = this.read_scalar(val)?.to_i32()?;
+                let val = this.read_scalar(val)?.to_int(val.layout.size)?;
                 let num = this.read_target_usize(num)?;
                 // The docs say val is "interpreted as unsigned char".
                 #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
@@ -647,7 +766,7 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let [ptr, val, num] =
                     this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let ptr = this.read_pointer(ptr)?;
-                let val = this.read_scalar(val)?.to_i32()?;
+                let val = this.read_scalar(val)?.to_int(val.layout.size)?;
                 let num = this.read_target_usize(num)?;
                 // The docs say val is "interpreted as unsigned char".
                 #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
@@ -667,6 +786,35 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     this.write_null(dest)?;
                 }
             }
+            "memmem" => {
+                let [haystack, haystack_len, needle, needle_len] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let haystack = this.read_pointer(haystack)?;
+                let haystack_len = this.read_target_usize(haystack_len)?;
+                let needle = this.read_pointer(needle)?;
+                let needle_len = this.read_target_usize(needle_len)?;
+
+                // C requires that this must always be a valid pointer (C18 §7.1.4).
+                this.ptr_get_alloc_id(haystack)?;
+                this.ptr_get_alloc_id(needle)?;
+
+                let haystack_bytes =
+                    this.read_bytes_ptr_strip_provenance(haystack, Size::from_bytes(haystack_len))?;
+                let needle_bytes =
+                    this.read_bytes_ptr_strip_provenance(needle, Size::from_bytes(needle_len))?;
+                // An empty needle is found at the start of the haystack.
+                let idx = if needle_bytes.is_empty() {
+                    Some(0)
+                } else {
+                    haystack_bytes.windows(needle_bytes.len()).position(|w| w == needle_bytes)
+                };
+                if let Some(idx) = idx {
+                    let new_ptr = haystack.offset(Size::from_bytes(idx as u64), this)?;
+                    this.write_pointer(new_ptr, dest)?;
+                } else {
+                    this.write_null(dest)?;
+                }
+            }
             "strlen" => {
                 let [ptr] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let ptr = this.read_pointer(ptr)?;
@@ -677,6 +825,22 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     dest,
                 )?;
             }
+            "strnlen" => {
+                let [ptr, maxlen] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let ptr = this.read_pointer(ptr)?;
+                let maxlen = this.read_target_usize(maxlen)?;
+
+                // C requires that this must always be a valid pointer (C18 §7.1.4).
+                this.ptr_get_alloc_id(ptr)?;
+
+                // Only the first `maxlen` bytes are accessed, they need not be null-terminated.
+                let n = this.read_c_str_bounded(ptr, maxlen)?.len();
+                this.write_scalar(
+                    Scalar::from_target_usize(u64::try_from(n).unwrap(), this),
+                    dest,
+                )?;
+            }
             "wcslen" => {
                 let [ptr] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let ptr = this.read_pointer(ptr)?;
@@ -699,7 +863,7 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 this.ptr_get_alloc_id(ptr_dest)?;
                 this.ptr_get_alloc_id(ptr_src)?;
 
-                this.mem_copy(ptr_src, ptr_dest, Size::from_bytes(n), true)?;
+                this.mem_copy_sharing(ptr_src, ptr_dest, Size::from_bytes(n), true)?;
                 this.write_pointer(ptr_dest, dest)?;
             }
             "strcpy" => {
@@ -718,6 +882,90 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 this.mem_copy(ptr_src, ptr_dest, Size::from_bytes(n), true)?;
                 this.write_pointer(ptr_dest, dest)?;
             }
+            "stpcpy" => {
+                let [ptr_dest, ptr_src] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let ptr_dest = this.read_pointer(ptr_dest)?;
+                let ptr_src = this.read_pointer(ptr_src)?;
+
+                // Like `strcpy`, but returns a pointer to the copied null terminator.
+                let len = u64::try_from(this.read_c_str(ptr_src)?.len()).unwrap();
+                this.mem_copy(ptr_src, ptr_dest, Size::from_bytes(len.strict_add(1)), true)?;
+                let end = ptr_dest.offset(Size::from_bytes(len), this)?;
+                this.write_pointer(end, dest)?;
+            }
+            "strlcpy" | "strlcat" => {
+                let [ptr_dest, ptr_src, size] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let ptr_dest = this.read_pointer(ptr_dest)?;
+                let ptr_src = this.read_pointer(ptr_src)?;
+                let size = this.read_target_usize(size)?;
+
+                // `strlcat` appends to the string already in the buffer. If there is no null
+                // terminator within the first `size` bytes, it does not write anything.
+                let dest_len = if link_name.as_str() == "strlcat" {
+                    u64::try_from(this.read_c_str_bounded(ptr_dest, size)?.len()).unwrap()
+                } else {
+                    0
+                };
+                // This reads at least 1 byte, so we are already enforcing that this is a valid pointer.
+                let src_len = u64::try_from(this.read_c_str(ptr_src)?.len()).unwrap();
+                if dest_len < size {
+                    // Copy as much as fits, and always add a null terminator.
+                    let n = src_len.min(size.strict_sub(dest_len).strict_sub(1));
+                    let ptr_end = ptr_dest.offset(Size::from_bytes(dest_len), this)?;
+                    this.mem_copy(ptr_src, ptr_end, Size::from_bytes(n), true)?;
+                    let ptr_nul = ptr_end.offset(Size::from_bytes(n), this)?;
+                    this.write_bytes_ptr(ptr_nul, [0u8])?;
+                }
+                // The result is the length of the string that we tried to create.
+                this.write_scalar(
+                    Scalar::from_target_usize(dest_len.strict_add(src_len), this),
+                    dest,
+                )?;
+            }
+            "strcasecmp" => {
+                let [left, right] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let left = this.read_pointer(left)?;
+                let right = this.read_pointer(right)?;
+
+                // This only ignores the case of ASCII letters, like the "C" locale does.
+                let left = this.read_c_str(left)?.to_ascii_lowercase();
+                let right = this.read_c_str(right)?.to_ascii_lowercase();
+                use std::cmp::Ordering::*;
+                let result = match left.cmp(&right) {
+                    Less => -1i32,
+                    Equal => 0,
+                    Greater => 1,
+                };
+                this.write_int(result, dest)?;
+            }
+            "strsep" => {
+                let [stringp, delim] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let stringp = this.deref_pointer_as(stringp, this.machine.layouts.mut_raw_ptr)?;
+                let delim = this.read_pointer(delim)?;
+                let string = this.read_pointer(&stringp)?;
+
+                if this.ptr_is_null(string)? {
+                    this.write_null(dest)?;
+                } else {
+                    let delim = this.read_c_str(delim)?.to_owned();
+                    let idx = this.read_c_str(string)?.iter().position(|c| delim.contains(c));
+                    match idx {
+                        Some(idx) => {
+                            // Terminate the token, and continue after the delimiter next time.
+                            let ptr_delim = string.offset(Size::from_bytes(idx as u64), this)?;
+                            this.write_bytes_ptr(ptr_delim, [0u8])?;
+                            let next = ptr_delim.offset(Size::from_bytes(1), this)?;
+                            this.write_pointer(next, &stringp)?;
+                        }
+                        None => this.write_null(&stringp)?,
+                    }
+                    this.write_pointer(string, dest)?;
+                }
+            }
 
             // math functions (note that there are also intrinsics for some other functions)
             #[rustfmt::skip]
@@ -752,6 +1000,7 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     _ => bug!(),
                 };
                 let res = res.to_soft();
+                let res = this.apply_random_float_error_ulp(res, link_name.as_str());
                 let res = this.adjust_nan(res, &[f]);
                 this.write_scalar(res, dest)?;
             }
@@ -774,6 +1023,12 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     "fdimf" => f1.to_host().abs_sub(f2.to_host()).to_soft(),
                     _ => bug!(),
                 };
+                // `fdimf` is correctly rounded, the other operations are not.
+                let res = if link_name.as_str() == "fdimf" {
+                    res
+                } else {
+                    this.apply_random_float_error_ulp(res, link_name.as_str())
+                };
                 let res = this.adjust_nan(res, &[f1, f2]);
                 this.write_scalar(res, dest)?;
             }
@@ -809,6 +1064,7 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     _ => bug!(),
                 };
                 let res = res.to_soft();
+                let res = this.apply_random_float_error_ulp(res, link_name.as_str());
                 let res = this.adjust_nan(res, &[f]);
                 this.write_scalar(res, dest)?;
             }
@@ -831,6 +1087,31 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     "fdim" => f1.to_host().abs_sub(f2.to_host()).to_soft(),
                     _ => bug!(),
                 };
+                // `fdim` is correctly rounded, the other operations are not.
+                let res = if link_name.as_str() == "fdim" {
+                    res
+                } else {
+                    this.apply_random_float_error_ulp(res, link_name.as_str())
+                };
+                let res = this.adjust_nan(res, &[f1, f2]);
+                this.write_scalar(res, dest)?;
+            }
+            "fmodf128" | "fdimf128" => {
+                let [f1, f2] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let f1 = this.read_scalar(f1)?.to_f128()?;
+                let f2 = this.read_scalar(f2)?.to_f128()?;
+                // There are no host floats for `f128`, but these operations are correctly rounded
+                // anyway, so soft-floats compute exactly the right result.
+                let res = match link_name.as_str() {
+                    "fmodf128" => (f1 % f2).value,
+                    "fdimf128" =>
+                        if f1.is_nan() || f2.is_nan() || f1 > f2 {
+                            (f1 - f2).value
+                        } else {
+                            Quad::ZERO
+                        },
+                    _ => bug!(),
+                };
                 let res = this.adjust_nan(res, &[f1, f2]);
                 this.write_scalar(res, dest)?;
             }
@@ -856,7 +1137,8 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 // Using host floats (but it's fine, these operations do not have guaranteed precision).
                 let (res, sign) = x.to_host().ln_gamma();
                 this.write_int(sign, &signp)?;
-                let res = this.adjust_nan(res.to_soft(), &[x]);
+                let res = this.apply_random_float_error_ulp(res.to_soft(), link_name.as_str());
+                let res = this.adjust_nan(res, &[x]);
                 this.write_scalar(res, dest)?;
             }
             "lgamma_r" => {
@@ -867,7 +1149,8 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 // Using host floats (but it's fine, these operations do not have guaranteed precision).
                 let (res, sign) = x.to_host().ln_gamma();
                 this.write_int(sign, &signp)?;
-                let res = this.adjust_nan(res.to_soft(), &[x]);
+                let res = this.apply_random_float_error_ulp(res.to_soft(), link_name.as_str());
+                let res = this.adjust_nan(res, &[x]);
                 this.write_scalar(res, dest)?;
             }
 
@@ -961,7 +1244,17 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
             }
 
             // Platform-specific shims
-            _ =>
+            _ => {
+                // The `long double` math functions (`sinl` etc.) are implemented by libm on all
+                // platforms, but which format `long double` has depends on the target.
+                if this.long_double_is_x87() {
+                    let res = shims::x86::x87::EvalContextExt::emulate_x87_long_double_math(
+                        this, link_name, abi, args, dest,
+                    )?;
+                    if !matches!(res, EmulateItemResult::NotSupported) {
+                        return Ok(res);
+                    }
+                }
                 return match this.tcx.sess.target.os.as_ref() {
                     _ if this.target_os_is_unix() =>
                         shims::unix::foreign_items::EvalContextExt::emulate_foreign_item_inner(
@@ -976,7 +1269,8 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
                             this, link_name, abi, args, dest,
                         ),
                     _ => Ok(EmulateItemResult::NotSupported),
-                },
+                };
+            }
         };
         // We only fall through to here if we did *not* hit the `_` arm above,
         // i.e., if we actually emulated the function with one of the shims.
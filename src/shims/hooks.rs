@@ -0,0 +1,42 @@
+//! Foreign functions that are implemented by the user of the Miri library rather than by Miri
+//! itself, see `MiriConfig::shim_hooks`. This lets projects model their in-house C libraries
+//! without patching Miri.
+
+use rustc_span::Symbol;
+use rustc_target::spec::abi::Abi;
+
+use crate::*;
+
+/// A handler for calls to a foreign function.
+pub trait ShimHook: Send + Sync {
+    /// Emulate a call to `link_name`, just like Miri's own shims do: check the ABI and the
+    /// arguments (e.g. with `check_shim`), read them, do whatever the function does, and write
+    /// the return value to `dest`. Return `EmulateItemResult::NeedsReturn` once the call is done,
+    /// or `EmulateItemResult::NotSupported` to let Miri's own shims handle the call after all.
+    fn call<'tcx>(
+        &self,
+        ecx: &mut MiriInterpCx<'tcx>,
+        link_name: Symbol,
+        abi: Abi,
+        args: &[OpTy<'tcx>],
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx, EmulateItemResult>;
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Call the user-provided hook for `link_name`, if there is one.
+    fn call_shim_hook(
+        &mut self,
+        link_name: Symbol,
+        abi: Abi,
+        args: &[OpTy<'tcx>],
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx, EmulateItemResult> {
+        let this = self.eval_context_mut();
+        let Some(hook) = this.machine.shim_hooks.get(&link_name).cloned() else {
+            return Ok(EmulateItemResult::NotSupported);
+        };
+        hook.call(this, link_name, abi, args, dest)
+    }
+}
@@ -4,20 +4,25 @@ mod alloc;
 mod backtrace;
 #[cfg(target_os = "linux")]
 mod native_lib;
+mod tz;
 mod unix;
 mod wasi;
 mod windows;
 mod x86;
 
+pub mod atexit;
+pub mod bench;
 pub mod env;
 pub mod extern_static;
 pub mod foreign_items;
+pub mod hooks;
 pub mod os_str;
 pub mod panic;
+pub mod stubs;
 pub mod time;
 pub mod tls;
 
-pub use unix::{DirTable, FdTable};
+pub use unix::{DirTable, FdTable, JmpBufTable, LocaleState, VirtualFs};
 
 /// What needs to be done after emulating an item (a shim or an intrinsic) is done.
 pub enum EmulateItemResult {
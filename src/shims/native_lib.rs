@@ -1,10 +1,14 @@
 //! Implements calling functions from a native library.
 use libffi::{high::call as ffi, low::CodePtr};
-use std::ops::Deref;
+use std::collections::BTreeSet;
+use std::ffi::c_void;
+use std::iter;
+use std::ops::{Deref, Range};
 
+use rustc_middle::mir::interpret::{read_target_uint, write_target_uint};
 use rustc_middle::ty::{self as ty, IntTy, UintTy};
 use rustc_span::Symbol;
-use rustc_target::abi::{Abi, HasDataLayout};
+use rustc_target::abi::{Abi, HasDataLayout, Size};
 
 use crate::*;
 
@@ -79,6 +83,215 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
         Ok(ImmTy::from_scalar(scalar, dest.layout))
     }
 
+    /// Turn a pointer of the interpreted program into the host pointer to the same byte, so that
+    /// native code can access the allocation directly.
+    fn ptr_to_carg(
+        &mut self,
+        ptr: Pointer,
+        native_allocs: &mut Vec<NativeAlloc>,
+    ) -> InterpResult<'tcx, CArg> {
+        let this = self.eval_context_mut();
+        let (alloc_id, offset, _extra) = match this.ptr_try_get_alloc_id(ptr) {
+            Ok(parts) => parts,
+            // A pointer without provenance (e.g. null) is passed on unchanged.
+            Err(addr) => return Ok(CArg::RawPtr(usize::try_from(addr).unwrap() as *mut c_void)),
+        };
+        let (size, _align, kind) = this.get_alloc_info(alloc_id);
+        if !matches!(kind, AllocKind::LiveData) {
+            throw_unsup_format!(
+                "only pointers to live memory can be passed to native calls, but got {ptr:?}"
+            );
+        }
+        if offset > size {
+            throw_unsup_format!(
+                "out-of-bounds pointers cannot be passed to native calls, but got {ptr:?}"
+            );
+        }
+        let host_ptr = this.ptr_to_host(ptr, native_allocs)?.unwrap();
+        Ok(CArg::RawPtr(host_ptr.cast()))
+    }
+
+    /// The host pointer to the byte `ptr` points to, or `None` if `ptr` does not point into (or
+    /// just past) a live allocation. The native code may do anything with the pointer, including
+    /// casting it to an integer and storing it somewhere, so the allocation is exposed. It is also
+    /// prepared for the call with `prepare_native_alloc`.
+    fn ptr_to_host(
+        &mut self,
+        ptr: Pointer,
+        native_allocs: &mut Vec<NativeAlloc>,
+    ) -> InterpResult<'tcx, Option<*mut u8>> {
+        let this = self.eval_context_mut();
+        let Ok((alloc_id, offset, extra)) = this.ptr_try_get_alloc_id(ptr) else {
+            return Ok(None);
+        };
+        let (size, _align, kind) = this.get_alloc_info(alloc_id);
+        if !matches!(kind, AllocKind::LiveData) || offset > size {
+            return Ok(None);
+        }
+        if let ProvenanceExtra::Concrete(tag) = extra {
+            this.expose_ptr(alloc_id, tag)?;
+        }
+        let host_base = match native_allocs.iter().find(|native| native.alloc_id == alloc_id) {
+            Some(native) => native.host_base,
+            None => this.prepare_native_alloc(alloc_id, ptr, offset, native_allocs)?,
+        };
+        Ok(Some(host_base.wrapping_add(offset.bytes_usize())))
+    }
+
+    /// Prepare an allocation for a native call, and return the host pointer to its bytes.
+    ///
+    /// In memory, the interpreted program's pointers hold the addresses Miri made up for their
+    /// allocations, which the native code cannot follow. So for the duration of the call, the
+    /// pointers stored in a mutable allocation are replaced by the host addresses of the bytes
+    /// they point to (which prepares those allocations as well). Pointers stored in read-only
+    /// memory cannot be replaced.
+    fn prepare_native_alloc(
+        &mut self,
+        alloc_id: AllocId,
+        ptr: Pointer,
+        offset: Size,
+        native_allocs: &mut Vec<NativeAlloc>,
+    ) -> InterpResult<'tcx, *mut u8> {
+        let this = self.eval_context_mut();
+        let mutable = this.get_alloc_mutability(alloc_id)?.is_mut();
+        let host_base = if mutable {
+            this.get_alloc_raw_mut(alloc_id)?.0.get_bytes_unchecked_raw_mut()
+        } else {
+            this.get_alloc_raw(alloc_id)?.get_bytes_unchecked_raw().cast_mut()
+        };
+        let size = this.get_alloc_info(alloc_id).0;
+        // Register the allocation before following the pointers in it, which might lead back here.
+        let idx = native_allocs.len();
+        native_allocs.push(NativeAlloc {
+            alloc_id,
+            ptr,
+            offset,
+            host_base,
+            size,
+            before: None,
+            stored_ptrs: Vec::new(),
+        });
+        if !mutable {
+            if this.machine.native_lib_diff_writes {
+                let before = this
+                    .get_alloc_raw(alloc_id)?
+                    .get_bytes_unchecked(alloc_range(Size::ZERO, size));
+                native_allocs[idx].before = Some(before.to_vec());
+            }
+            return Ok(host_base);
+        }
+
+        let ptr_size = this.pointer_size();
+        let endian = this.data_layout().endian;
+        let stored: Vec<(Size, Provenance)> =
+            this.get_alloc_raw(alloc_id)?.provenance().ptrs().iter().copied().collect();
+        for (ptr_offset, prov) in stored {
+            let range = alloc_range(ptr_offset, ptr_size);
+            let miri_bytes = this.get_alloc_raw(alloc_id)?.get_bytes_unchecked(range).to_vec();
+            let addr = read_target_uint(endian, &miri_bytes).unwrap();
+            let stored_ptr = Pointer::new(Some(prov), Size::from_bytes(addr));
+            // Dangling pointers are left alone; the native code must not follow them anyway.
+            let Some(host_ptr) = this.ptr_to_host(stored_ptr, native_allocs)? else { continue };
+            let mut host_bytes = vec![0; ptr_size.bytes_usize()];
+            write_target_uint(endian, &mut host_bytes, (host_ptr as usize).try_into().unwrap())
+                .unwrap();
+            // SAFETY: the pointer is stored in bounds of the allocation, so all of its bytes are.
+            unsafe {
+                host_base
+                    .add(ptr_offset.bytes_usize())
+                    .copy_from(host_bytes.as_ptr(), host_bytes.len())
+            };
+            native_allocs[idx].stored_ptrs.push((ptr_offset, miri_bytes));
+        }
+        // Remember what the bytes were (as the native code sees them), to find out which ones the
+        // native code wrote.
+        let before =
+            this.get_alloc_raw(alloc_id)?.get_bytes_unchecked(alloc_range(Size::ZERO, size));
+        native_allocs[idx].before = Some(before.to_vec());
+        Ok(host_base)
+    }
+
+    /// After a native call, find out which bytes of `native` the native code wrote, and turn the
+    /// pointers stored in it back into pointers of the interpreted program.
+    fn finish_native_alloc(
+        &mut self,
+        native: &NativeAlloc,
+        native_allocs: &[NativeAlloc],
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let Some(before) = &native.before else { return Ok(()) };
+        let tcx = *this.tcx;
+        let ptr_size = this.pointer_size();
+        let endian = this.data_layout().endian;
+        let after = this
+            .get_alloc_raw(native.alloc_id)?
+            .get_bytes_unchecked(alloc_range(Size::ZERO, native.size))
+            .to_vec();
+        // Only the bytes that changed were written. A write that stores the value that was
+        // already there goes unnoticed, so such bytes might remain uninitialized.
+        let mut written = changed_ranges(before, &after);
+        let overlaps = |range: &Range<u64>, start: u64| {
+            range.start < start.strict_add(ptr_size.bytes()) && start < range.end
+        };
+
+        // The pointers the native code did not touch get their bytes back, and keep their
+        // provenance. Any other pointer counts as overwritten entirely.
+        for (offset, miri_bytes) in &native.stored_ptrs {
+            if written.iter().any(|range| overlaps(range, offset.bytes())) {
+                written.push(offset.bytes()..offset.bytes().strict_add(ptr_size.bytes()));
+                continue;
+            }
+            // SAFETY: the pointer is stored in bounds of the allocation, so all of its bytes are.
+            unsafe {
+                native
+                    .host_base
+                    .add(offset.bytes_usize())
+                    .copy_from(miri_bytes.as_ptr(), miri_bytes.len())
+            };
+        }
+
+        for range in &written {
+            let start = Size::from_bytes(range.start);
+            let len = Size::from_bytes(range.end.strict_sub(range.start));
+            if this.machine.native_lib_diff_writes {
+                // Treat the write like a write of the current thread through the pointer the
+                // native code got, so that the borrow tracker and the data race detector check
+                // it.
+                let delta = i64::try_from(range.start).unwrap()
+                    - i64::try_from(native.offset.bytes()).unwrap();
+                let ptr = native.ptr.wrapping_signed_offset(delta, this);
+                this.get_ptr_alloc_mut(ptr, len)?;
+            }
+            let (alloc, _machine) = this.get_alloc_raw_mut(native.alloc_id)?;
+            alloc.get_bytes_unchecked_for_overwrite_ptr(&tcx, alloc_range(start, len))?;
+        }
+
+        // The native code may have stored the host address of memory it got. Turn those into
+        // addresses of the interpreted program, with wildcard provenance like a pointer cast from
+        // an integer (the allocations were exposed).
+        let words: BTreeSet<u64> = written
+            .iter()
+            .flat_map(|range| {
+                let first = range.start / ptr_size.bytes() * ptr_size.bytes();
+                (first..range.end).step_by(ptr_size.bytes_usize())
+            })
+            .filter(|word| word.strict_add(ptr_size.bytes()) <= native.size.bytes())
+            .collect();
+        for word in words {
+            let range = alloc_range(Size::from_bytes(word), ptr_size);
+            let bytes = this.get_alloc_raw(native.alloc_id)?.get_bytes_unchecked(range);
+            let host_addr = read_target_uint(endian, bytes).unwrap();
+            if let Some(addr) = host_to_miri_addr(native_allocs, host_addr) {
+                let ptr = Pointer::new(Provenance::Wildcard, Size::from_bytes(addr));
+                let (alloc, _machine) = this.get_alloc_raw_mut(native.alloc_id)?;
+                alloc
+                    .write_scalar(&tcx, range, Scalar::from_pointer(ptr, &tcx))
+                    .map_err(|err| err.to_interp_error(native.alloc_id))?;
+            }
+        }
+        Ok(())
+    }
+
     /// Get the pointer to the function of the specified name in the shared object file,
     /// if it exists. The function must be in the shared object file specified: we do *not*
     /// return pointers to functions in dependencies of the library.  
@@ -147,11 +360,19 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
         // Get the function arguments, and convert them to `libffi`-compatible form.
         let mut libffi_args = Vec::<CArg>::with_capacity(args.len());
+        // The allocations that the native code can access.
+        let mut native_allocs = Vec::<NativeAlloc>::new();
         for arg in args.iter() {
             if !matches!(arg.layout.abi, Abi::Scalar(_)) {
                 throw_unsup_format!("only scalar argument types are support for native calls")
             }
-            libffi_args.push(imm_to_carg(this.read_immediate(arg)?, this)?);
+            let carg = if matches!(arg.layout.ty.kind(), ty::RawPtr(..) | ty::Ref(..)) {
+                let ptr = this.read_pointer(arg)?;
+                this.ptr_to_carg(ptr, &mut native_allocs)?
+            } else {
+                imm_to_carg(this.read_immediate(arg)?, this)?
+            };
+            libffi_args.push(carg);
         }
 
         // Convert them to `libffi::high::Arg` type.
@@ -161,12 +382,71 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             .collect::<Vec<libffi::high::Arg<'_>>>();
 
         // Call the function and store output, depending on return type in the function signature.
-        let ret = this.call_native_with_args(link_name, dest, code_ptr, libffi_args)?;
+        let mut ret = this.call_native_with_args(link_name, dest, code_ptr, libffi_args)?;
+
+        // The native code wrote directly to the bytes of the allocations it could access.
+        for native in &native_allocs {
+            this.finish_native_alloc(native, &native_allocs)?;
+        }
+        // A returned `uintptr_t` might be the host address of memory the native code got.
+        if let ty::Uint(UintTy::Usize) = dest.layout.ty.kind() {
+            let host_addr = ret.to_scalar().to_target_usize(this)?;
+            if let Some(addr) = host_to_miri_addr(&native_allocs, host_addr) {
+                ret = ImmTy::from_scalar(Scalar::from_target_usize(addr, this), dest.layout);
+            }
+        }
+
         this.write_immediate(*ret, dest)?;
         Ok(true)
     }
 }
 
+/// An allocation that native code can access during a call, and may have written to.
+struct NativeAlloc {
+    alloc_id: AllocId,
+    /// A pointer the native code got to the allocation, and its offset in the allocation.
+    ptr: Pointer,
+    offset: Size,
+    /// The host pointer to the bytes of the allocation, and their number.
+    host_base: *mut u8,
+    size: Size,
+    /// The bytes of the allocation before the call, as the native code saw them, for mutable
+    /// allocations (and with `-Zmiri-native-lib-diff-writes`, for all of them).
+    before: Option<Vec<u8>>,
+    /// The pointers stored in the allocation that were replaced by host addresses for the call:
+    /// their offset, and the bytes holding the interpreted program's address.
+    stored_ptrs: Vec<(Size, Vec<u8>)>,
+}
+
+/// The address in the interpreted program of the byte at host address `host_addr`, if it belongs
+/// to (or is just past) one of the allocations the native code could access.
+fn host_to_miri_addr(native_allocs: &[NativeAlloc], host_addr: u64) -> Option<u64> {
+    native_allocs.iter().find_map(|native| {
+        let host_base = u64::try_from(native.host_base as usize).unwrap();
+        let offset = host_addr.checked_sub(host_base)?;
+        if offset > native.size.bytes() {
+            return None;
+        }
+        let miri_base = native.ptr.addr().bytes().strict_sub(native.offset.bytes());
+        Some(miri_base.strict_add(offset))
+    })
+}
+
+/// The ranges of bytes that differ between `before` and `after`.
+fn changed_ranges(before: &[u8], after: &[u8]) -> Vec<Range<u64>> {
+    let mut ranges: Vec<Range<u64>> = Vec::new();
+    for (offset, (a, b)) in (0u64..).zip(iter::zip(before, after)) {
+        if a == b {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(range) if range.end == offset => range.end = offset.strict_add(1),
+            _ => ranges.push(offset..offset.strict_add(1)),
+        }
+    }
+    ranges
+}
+
 #[derive(Debug, Clone)]
 /// Enum of supported arguments to external C functions.
 // We introduce this enum instead of just calling `ffi::arg` and storing a list
@@ -194,6 +474,8 @@ enum CArg {
     UInt64(u64),
     /// usize.
     USize(usize),
+    /// A host pointer into the bytes of an allocation (or a pointer without provenance).
+    RawPtr(*mut c_void),
 }
 
 impl<'a> CArg {
@@ -210,6 +492,7 @@ impl<'a> CArg {
             CArg::UInt32(i) => ffi::arg(i),
             CArg::UInt64(i) => ffi::arg(i),
             CArg::USize(i) => ffi::arg(i),
+            CArg::RawPtr(i) => ffi::arg(i),
         }
     }
 }
@@ -10,15 +10,39 @@
 //! - A hook executed each time a frame is popped, such that if the frame pushed by `__rust_maybe_catch_panic`
 //!   gets popped *during unwinding*, we take the panic payload and store it according to the extra
 //!   metadata we remembered when pushing said frame.
+//!
+//! Additionally, `miri_throw_foreign_exception` starts unwinding with a *foreign* exception, as if
+//! it had been thrown by C++ code. Such an exception runs drop glue like a Rust panic does, but
+//! Rust code is not allowed to catch it: unwinding into a `try` frame aborts the program.
 
 use rustc_ast::Mutability;
 use rustc_middle::{mir, ty};
+use rustc_span::sym;
+use rustc_target::abi::Size;
 use rustc_target::spec::abi::Abi;
 use rustc_target::spec::PanicStrategy;
 
 use crate::*;
 use helpers::check_arg_count;
 
+/// What is being unwound.
+#[derive(Clone, Copy, Debug)]
+pub enum UnwindPayload {
+    /// A Rust panic, with the payload that was passed to `miri_start_unwind`.
+    Rust(Scalar),
+    /// A foreign exception, started by `miri_throw_foreign_exception`.
+    Foreign,
+}
+
+impl VisitProvenance for UnwindPayload {
+    fn visit_provenance(&self, visit: &mut VisitWith<'_>) {
+        match self {
+            UnwindPayload::Rust(payload) => payload.visit_provenance(visit),
+            UnwindPayload::Foreign => {}
+        }
+    }
+}
+
 /// Holds all of the relevant data for when unwinding hits a `try` frame.
 #[derive(Debug)]
 pub struct CatchUnwindData<'tcx> {
@@ -52,7 +76,20 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
         let payload = this.read_scalar(payload)?;
         let thread = this.active_thread_mut();
-        thread.panic_payloads.push(payload);
+        thread.panic_payloads.push(UnwindPayload::Rust(payload));
+
+        Ok(())
+    }
+
+    /// Handles `miri_throw_foreign_exception`, which starts unwinding with an exception that does
+    /// not originate from Rust.
+    fn handle_miri_throw_foreign_exception(&mut self) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        trace!("miri_throw_foreign_exception: {:?}", this.frame().instance);
+
+        let thread = this.active_thread_mut();
+        thread.panic_payloads.push(UnwindPayload::Foreign);
 
         Ok(())
     }
@@ -127,12 +164,19 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 this.frame().instance
             );
 
-            // We set the return value of `try` to 1, since there was a panic.
-            this.write_scalar(Scalar::from_i32(1), &catch_unwind.dest)?;
-
             // The Thread's `panic_payload` holds what was passed to `miri_start_unwind`.
             // This is exactly the second argument we need to pass to `catch_fn`.
-            let payload = this.active_thread_mut().panic_payloads.pop().unwrap();
+            let payload = match this.active_thread_mut().panic_payloads.pop().unwrap() {
+                UnwindPayload::Rust(payload) => payload,
+                // This is what `__rust_foreign_exception` does on real targets.
+                UnwindPayload::Foreign =>
+                    throw_machine_stop!(TerminationInfo::Abort(
+                        "Rust cannot catch foreign exceptions".to_owned()
+                    )),
+            };
+
+            // We set the return value of `try` to 1, since there was a panic.
+            this.write_scalar(Scalar::from_i32(1), &catch_unwind.dest)?;
 
             // Push the `catch_fn` stackframe.
             let f_instance = this.get_ptr_fn(catch_unwind.catch_fn)?.as_instance()?;
@@ -156,6 +200,44 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         }
     }
 
+    /// The message of the Rust panic that the active thread is unwinding with, if its payload is a
+    /// string, like the payloads of `panic!`.
+    fn panic_payload_message(&self) -> InterpResult<'tcx, Option<String>> {
+        let this = self.eval_context_ref();
+        let Some(UnwindPayload::Rust(payload)) = this.active_thread_ref().panic_payloads.last()
+        else {
+            return Ok(None);
+        };
+        // `panic_unwind` passes `miri_start_unwind` a pointer to a `Box<dyn Any + Send>`, i.e., to
+        // a data pointer followed by a vtable pointer.
+        let ptr_layout = this.machine.layouts.mut_raw_ptr;
+        let payload = this.ptr_to_mplace(payload.to_pointer(this)?, ptr_layout);
+        let data = this.read_pointer(&payload)?;
+        let vtable = this.read_pointer(&payload.offset(ptr_layout.size, ptr_layout, this)?)?;
+        let (ty, _) = this.get_ptr_vtable(vtable)?;
+        let data = this.ptr_to_mplace(data, this.layout_of(ty)?);
+        let bytes = match ty.kind() {
+            ty::Ref(_, pointee, _) if pointee.is_str() => {
+                let str = this.read_immediate(&data)?;
+                this.read_byte_slice(&str)?.to_owned()
+            }
+            ty::Adt(adt, _) if this.tcx.is_diagnostic_item(sym::String, adt.did()) => {
+                // The buffer pointer of the `Vec<u8>` in the `String` is the first field of the
+                // first field of ... its `buf`.
+                let vec = this.project_field_named(&data, "vec")?;
+                let len = this.read_target_usize(&this.project_field_named(&vec, "len")?)?;
+                let mut buf = this.project_field_named(&vec, "buf")?;
+                while !buf.layout.ty.is_unsafe_ptr() {
+                    buf = this.project_field(&buf, 0)?;
+                }
+                let buf = this.read_pointer(&buf)?;
+                this.read_bytes_ptr_strip_provenance(buf, Size::from_bytes(len))?.to_owned()
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+    }
+
     /// Start a panic in the interpreter with the given message as payload.
     fn start_panic(&mut self, msg: &str, unwind: mir::UnwindAction) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
@@ -0,0 +1,152 @@
+//! Simple foreign functions described in a JSON file, for `-Zmiri-shim-stubs`. Many programs call
+//! a long tail of functions that Miri does not support, but that can be emulated well enough by
+//! doing (almost) nothing; this lets projects do that without writing a shim.
+//!
+//! The file maps link names to stubs, e.g.
+//! ```json
+//! {
+//!     "mylib_init": {},
+//!     "mylib_get_config": { "write_zeros": [{ "arg": 1, "bytes": 16 }], "return": 16 },
+//!     "mylib_open_device": { "errno": "ENOSYS", "return": -1 }
+//! }
+//! ```
+//! A stub first writes the zeros, then sets `errno`, and then returns its return value (which
+//! defaults to 0).
+
+use std::iter;
+
+use rustc_hir::def::Namespace;
+use rustc_middle::ty;
+use rustc_span::Symbol;
+use rustc_target::spec::abi::Abi;
+use serde_json::Value;
+
+use crate::*;
+
+#[derive(Debug)]
+pub struct ShimStub {
+    /// The value to return.
+    ret: i128,
+    /// For each pointer argument to write zeros to: its index, and how many bytes to write.
+    write_zeros: Vec<(usize, usize)>,
+    /// The name of the `libc` constant to set `errno` to.
+    errno: Option<String>,
+}
+
+impl ShimStub {
+    fn from_json(stub: &Value) -> Result<Self, String> {
+        let Some(stub) = stub.as_object() else { return Err(format!("expected an object")) };
+        let mut ret = 0;
+        let mut write_zeros = vec![];
+        let mut errno = None;
+        for (key, value) in stub {
+            match key.as_str() {
+                "return" =>
+                    ret = value
+                        .as_i64()
+                        .map(i128::from)
+                        .or_else(|| value.as_u64().map(i128::from))
+                        .ok_or_else(|| format!("`return` must be an integer"))?,
+                "write_zeros" => {
+                    let Some(writes) = value.as_array() else {
+                        return Err(format!("`write_zeros` must be an array"));
+                    };
+                    for write in writes {
+                        let field = |name| {
+                            write
+                                .get(name)
+                                .and_then(Value::as_u64)
+                                .and_then(|value| usize::try_from(value).ok())
+                                .ok_or_else(|| {
+                                    format!("each entry of `write_zeros` needs an integer `{name}`")
+                                })
+                        };
+                        write_zeros.push((field("arg")?, field("bytes")?));
+                    }
+                }
+                "errno" => {
+                    let Some(name) = value.as_str() else {
+                        return Err(format!("`errno` must be the name of a `libc` constant"));
+                    };
+                    errno = Some(name.to_owned());
+                }
+                _ => return Err(format!("unknown key `{key}`")),
+            }
+        }
+        Ok(ShimStub { ret, write_zeros, errno })
+    }
+}
+
+/// Parse the contents of a `-Zmiri-shim-stubs` file.
+pub fn parse_shim_stubs(json: &str) -> Result<Vec<(String, ShimStub)>, String> {
+    let stubs: Value = serde_json::from_str(json).map_err(|err| err.to_string())?;
+    let Some(stubs) = stubs.as_object() else {
+        return Err(format!("expected an object mapping link names to stubs"));
+    };
+    stubs
+        .iter()
+        .map(|(link_name, stub)| {
+            let stub = ShimStub::from_json(stub)
+                .map_err(|err| format!("stub for `{link_name}`: {err}"))?;
+            Ok((link_name.clone(), stub))
+        })
+        .collect()
+}
+
+impl ShimHook for ShimStub {
+    fn call<'tcx>(
+        &self,
+        ecx: &mut MiriInterpCx<'tcx>,
+        link_name: Symbol,
+        abi: Abi,
+        args: &[OpTy<'tcx>],
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx, EmulateItemResult> {
+        ecx.check_abi_and_shim_symbol_clash(abi, Abi::C { unwind: false }, link_name)?;
+        for &(arg, bytes) in &self.write_zeros {
+            let Some(arg) = args.get(arg) else {
+                throw_ub_format!(
+                    "the stub for `{link_name}` writes to argument {arg}, but it was called with {} arguments",
+                    args.len()
+                );
+            };
+            let ptr = ecx.read_pointer(arg)?;
+            ecx.write_bytes_ptr(ptr, iter::repeat(0u8).take(bytes))?;
+        }
+        if let Some(errno) = &self.errno {
+            if !ecx.target_os_is_unix()
+                || ecx.try_resolve_path(&["libc", errno], Namespace::ValueNS).is_none()
+            {
+                throw_unsup_format!(
+                    "the stub for `{link_name}` sets `errno` to `{errno}`, which is not a `libc` constant on this target"
+                );
+            }
+            let errno = ecx.eval_libc(errno);
+            ecx.set_last_error(errno)?;
+        }
+        if !dest.layout.is_zst() {
+            // The return value was given without a type, so make sure it is representable as the
+            // return type the program declared.
+            let size = dest.layout.size;
+            let fits = match dest.layout.ty.kind() {
+                ty::Int(_) => size.signed_int_min() <= self.ret && self.ret <= size.signed_int_max(),
+                ty::Uint(_) =>
+                    u128::try_from(self.ret).is_ok_and(|ret| ret <= size.unsigned_int_max()),
+                _ =>
+                    throw_unsup_format!(
+                        "the stub for `{link_name}` can only return integers, but the return type is `{}`",
+                        dest.layout.ty
+                    ),
+            };
+            if !fits {
+                throw_unsup_format!(
+                    "the stub for `{link_name}` returns {}, which does not fit into the return type `{}`",
+                    self.ret,
+                    dest.layout.ty
+                );
+            }
+            ecx.write_int(self.ret, dest)?;
+        }
+        Ok(EmulateItemResult::NeedsReturn)
+    }
+}
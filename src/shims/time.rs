@@ -1,11 +1,9 @@
 use std::ffi::{OsStr, OsString};
-use std::fmt::Write;
-use std::str::FromStr;
 use std::time::{Duration, SystemTime};
 
-use chrono::{DateTime, Datelike, Offset, Timelike, Utc};
-use chrono_tz::Tz;
+use chrono::{DateTime, Datelike, FixedOffset, Timelike, Utc};
 
+use crate::shims::tz::TimeZone;
 use crate::*;
 
 /// Returns the time elapsed between the provided time and the unix epoch as a `Duration`.
@@ -16,6 +14,21 @@ pub fn system_time_to_duration<'tcx>(time: &SystemTime) -> InterpResult<'tcx, Du
 
 impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
 pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// The current wall-clock time, as the duration since the Unix epoch. With isolation enabled,
+    /// this is derived from the virtual clock if `-Zmiri-clock-epoch` is set, and rejected
+    /// otherwise.
+    fn wall_clock_time(&mut self, name: &str) -> InterpResult<'tcx, Duration> {
+        let this = self.eval_context_mut();
+        if !this.machine.communicate()
+            && let Some(epoch) = this.machine.clock.wall_clock_epoch()
+        {
+            let elapsed = this.machine.clock.now().duration_since(this.machine.clock.epoch());
+            return Ok(epoch.saturating_add(elapsed));
+        }
+        this.check_no_isolation(name)?;
+        system_time_to_duration(&SystemTime::now())
+    }
+
     fn clock_gettime(
         &mut self,
         clk_id_op: &OpTy<'tcx>,
@@ -75,8 +88,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         }
 
         let duration = if absolute_clocks.contains(&clk_id) {
-            this.check_no_isolation("`clock_gettime` with `REALTIME` clocks")?;
-            system_time_to_duration(&SystemTime::now())?
+            this.wall_clock_time("`clock_gettime` with `REALTIME` clocks")?
         } else if relative_clocks.contains(&clk_id) {
             this.machine.clock.now().duration_since(this.machine.clock.epoch())
         } else {
@@ -97,7 +109,6 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let this = self.eval_context_mut();
 
         this.assert_target_os_is_unix("gettimeofday");
-        this.check_no_isolation("`gettimeofday`")?;
 
         let tv = this.deref_pointer_as(tv_op, this.libc_ty_layout("timeval"))?;
 
@@ -109,7 +120,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             return Ok(-1);
         }
 
-        let duration = system_time_to_duration(&SystemTime::now())?;
+        let duration = this.wall_clock_time("`gettimeofday`")?;
         let tv_sec = duration.as_secs();
         let tv_usec = duration.subsec_micros();
 
@@ -129,7 +140,6 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let this = self.eval_context_mut();
 
         this.assert_target_os_is_unix("localtime_r");
-        this.check_no_isolation("`localtime_r`")?;
 
         let timep = this.deref_pointer(timep)?;
         let result = this.deref_pointer_as(result_op, this.libc_ty_layout("tm"))?;
@@ -144,20 +154,22 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let dt_utc: DateTime<Utc> =
             DateTime::from_timestamp(sec_since_epoch, 0).expect("Invalid timestamp");
 
-        // Figure out what time zone is in use
-        let tz = this.get_env_var(OsStr::new("TZ"))?.unwrap_or_else(|| OsString::from("UTC"));
-        let tz = match tz.into_string() {
-            Ok(tz) => Tz::from_str(&tz).unwrap_or(Tz::UTC),
-            _ => Tz::UTC,
+        // Figure out what time zone is in use. This only depends on the environment of the
+        // interpreted program, so it works with isolation, too.
+        let tz = this.get_env_var(OsStr::new("TZ"))?;
+        let tz = TimeZone::from_env(tz.as_deref().and_then(OsStr::to_str));
+        let Some(local) = tz.offset_at(sec_since_epoch) else {
+            throw_unsup_format!("`localtime_r` is not supported for timestamp {sec_since_epoch}");
         };
 
         // Convert that to local time, then return the broken-down time value.
-        let dt: DateTime<Tz> = dt_utc.with_timezone(&tz);
-
-        // This value is always set to -1, because there is no way to know if dst is in effect with
-        // chrono crate yet.
-        // This may not be consistent with libc::localtime_r's result.
-        let tm_isdst = -1;
+        let Some(offset) = FixedOffset::east_opt(local.utc_offset) else {
+            throw_unsup_format!(
+                "`localtime_r` is not supported for a UTC offset of {} seconds",
+                local.utc_offset
+            );
+        };
+        let dt = dt_utc.with_timezone(&offset);
         this.write_int_fields_named(
             &[
                 ("tm_sec", dt.second().into()),
@@ -168,7 +180,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 ("tm_year", dt.year().strict_sub(1900).into()),
                 ("tm_wday", dt.weekday().num_days_from_sunday().into()),
                 ("tm_yday", dt.ordinal0().into()),
-                ("tm_isdst", tm_isdst),
+                ("tm_isdst", local.is_dst.into()),
             ],
             &result,
         )?;
@@ -177,22 +189,9 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         // the additional tm_zone/tm_gmtoff fields.
         // https://docs.oracle.com/cd/E36784_01/html/E36874/localtime-r-3c.html
         if !matches!(&*this.tcx.sess.target.os, "solaris" | "illumos") {
-            // tm_zone represents the timezone value in the form of: +0730, +08, -0730 or -08.
-            // This may not be consistent with libc::localtime_r's result.
-            let offset_in_seconds = dt.offset().fix().local_minus_utc();
-            let tm_gmtoff = offset_in_seconds;
-            let mut tm_zone = String::new();
-            if offset_in_seconds < 0 {
-                tm_zone.push('-');
-            } else {
-                tm_zone.push('+');
-            }
-            let offset_hour = offset_in_seconds.abs() / 3600;
-            write!(tm_zone, "{:02}", offset_hour).unwrap();
-            let offset_min = (offset_in_seconds.abs() % 3600) / 60;
-            if offset_min != 0 {
-                write!(tm_zone, "{:02}", offset_min).unwrap();
-            }
+            // tm_zone is the abbreviation of the time zone, e.g. `CEST`.
+            let tm_zone = local.abbreviation;
+            let tm_gmtoff = local.utc_offset;
 
             // FIXME: String de-duplication is needed so that we only allocate this string only once
             // even when there are multiple calls to this function.
@@ -213,7 +212,6 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let this = self.eval_context_mut();
 
         this.assert_target_os("windows", shim_name);
-        this.check_no_isolation(shim_name)?;
 
         let filetime = this.deref_pointer_as(LPFILETIME_op, this.windows_ty_layout("FILETIME"))?;
 
@@ -223,8 +221,8 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let NANOS_PER_INTERVAL = NANOS_PER_SEC / INTERVALS_PER_SEC;
         let SECONDS_TO_UNIX_EPOCH = INTERVALS_TO_UNIX_EPOCH / INTERVALS_PER_SEC;
 
-        let duration = system_time_to_duration(&SystemTime::now())?
-            + Duration::from_secs(SECONDS_TO_UNIX_EPOCH);
+        let duration =
+            this.wall_clock_time(shim_name)? + Duration::from_secs(SECONDS_TO_UNIX_EPOCH);
         let duration_ticks = u64::try_from(duration.as_nanos() / u128::from(NANOS_PER_INTERVAL))
             .map_err(|_| err_unsup_format!("programs running more than 2^64 Windows ticks after the Windows epoch are not supported"))?;
 
@@ -0,0 +1,326 @@
+//! Time zones for `localtime_r`. The `TZ` environment variable of the interpreted program is either
+//! the name of a time zone in the IANA database (e.g. `Europe/Berlin`), or a POSIX time zone rule
+//! (e.g. `CET-1CEST,M3.5.0,M10.5.0/3`). Without `TZ`, or if it cannot be parsed, we use UTC.
+
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, NaiveDate, Offset, TimeZone as _};
+use chrono_tz::{OffsetComponents, OffsetName, Tz};
+
+/// The local time offset that applies at some point in time.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LocalOffset {
+    /// Seconds east of UTC.
+    pub utc_offset: i32,
+    pub is_dst: bool,
+    /// E.g. `CEST`.
+    pub abbreviation: String,
+}
+
+#[derive(Debug)]
+pub enum TimeZone {
+    Iana(Tz),
+    Posix(PosixTz),
+}
+
+impl TimeZone {
+    /// Interpret the value of the `TZ` environment variable.
+    pub fn from_env(tz: Option<&str>) -> Self {
+        let tz = tz.map(|tz| tz.strip_prefix(':').unwrap_or(tz)).unwrap_or_default();
+        if let Ok(tz) = Tz::from_str(tz) {
+            TimeZone::Iana(tz)
+        } else if let Some(tz) = PosixTz::parse(tz) {
+            TimeZone::Posix(tz)
+        } else {
+            TimeZone::Iana(Tz::UTC)
+        }
+    }
+
+    /// The local time offset at `timestamp` seconds after the Unix epoch.
+    pub fn offset_at(&self, timestamp: i64) -> Option<LocalOffset> {
+        match self {
+            TimeZone::Iana(tz) => {
+                let offset = tz
+                    .offset_from_utc_datetime(&DateTime::from_timestamp(timestamp, 0)?.naive_utc());
+                Some(LocalOffset {
+                    utc_offset: offset.fix().local_minus_utc(),
+                    is_dst: !offset.dst_offset().is_zero(),
+                    abbreviation: offset.abbreviation().to_owned(),
+                })
+            }
+            TimeZone::Posix(tz) => tz.offset_at(timestamp),
+        }
+    }
+}
+
+/// A time zone described by a POSIX `TZ` string:
+/// `std offset [dst [offset] [,start[/time],end[/time]]]`.
+#[derive(Debug)]
+pub struct PosixTz {
+    std_name: String,
+    /// Seconds east of UTC. (Note that POSIX uses seconds *west* of UTC.)
+    std_offset: i32,
+    dst: Option<Dst>,
+}
+
+#[derive(Debug)]
+struct Dst {
+    name: String,
+    /// Seconds east of UTC.
+    offset: i32,
+    /// When daylight saving time starts, in standard time.
+    start: Transition,
+    /// When daylight saving time ends, in daylight saving time.
+    end: Transition,
+}
+
+#[derive(Debug)]
+struct Transition {
+    date: TransitionDate,
+    /// Seconds since midnight of `date` (may be negative or more than a day).
+    time: i32,
+}
+
+#[derive(Debug)]
+enum TransitionDate {
+    /// `Jn`: the n-th day of the year (1 to 365), not counting February 29.
+    Julian(u32),
+    /// `n`: the n-th day of the year (0 to 365), counting February 29.
+    Ordinal(u32),
+    /// `Mm.w.d`: weekday `d` (0 is Sunday) of week `w` (1 to 5, 5 means the last) of month `m`.
+    Month { month: u32, week: u32, weekday: u32 },
+}
+
+impl PosixTz {
+    fn parse(tz: &str) -> Option<Self> {
+        let mut parser = Parser { rest: tz };
+        let std_name = parser.name()?;
+        let std_offset = parser.time(24)?.checked_neg()?;
+        let dst = if parser.rest.is_empty() {
+            None
+        } else {
+            let name = parser.name()?;
+            let offset = if parser.rest.is_empty() || parser.rest.starts_with(',') {
+                // Daylight saving time is one hour ahead of standard time by default.
+                std_offset.checked_add(3600)?
+            } else {
+                parser.time(24)?.checked_neg()?
+            };
+            let (start, end) = if parser.eat(',') {
+                let start = parser.transition()?;
+                if !parser.eat(',') {
+                    return None;
+                }
+                (start, parser.transition()?)
+            } else {
+                // Like glibc, default to the rules of the United States.
+                (
+                    Transition {
+                        date: TransitionDate::Month { month: 3, week: 2, weekday: 0 },
+                        time: 7200,
+                    },
+                    Transition {
+                        date: TransitionDate::Month { month: 11, week: 1, weekday: 0 },
+                        time: 7200,
+                    },
+                )
+            };
+            Some(Dst { name, offset, start, end })
+        };
+        parser.rest.is_empty().then_some(PosixTz { std_name, std_offset, dst })
+    }
+
+    fn offset_at(&self, timestamp: i64) -> Option<LocalOffset> {
+        let std = LocalOffset {
+            utc_offset: self.std_offset,
+            is_dst: false,
+            abbreviation: self.std_name.clone(),
+        };
+        let Some(dst) = &self.dst else { return Some(std) };
+        let year =
+            DateTime::from_timestamp(timestamp.checked_add(self.std_offset.into())?, 0)?.year();
+        let start = dst.start.timestamp(year, self.std_offset)?;
+        let end = dst.end.timestamp(year, dst.offset)?;
+        let is_dst = if start <= end {
+            start <= timestamp && timestamp < end
+        } else {
+            // On the southern hemisphere, daylight saving time spans the new year.
+            !(end <= timestamp && timestamp < start)
+        };
+        Some(if is_dst {
+            LocalOffset { utc_offset: dst.offset, is_dst: true, abbreviation: dst.name.clone() }
+        } else {
+            std
+        })
+    }
+}
+
+impl Transition {
+    /// The time of this transition in `year` as a Unix timestamp, given the UTC offset in effect
+    /// before the transition.
+    fn timestamp(&self, year: i32, utc_offset: i32) -> Option<i64> {
+        let date = match self.date {
+            TransitionDate::Julian(day) => {
+                let leap_day = NaiveDate::from_ymd_opt(year, 2, 29).is_some() && day >= 60;
+                NaiveDate::from_yo_opt(year, if leap_day { day.checked_add(1)? } else { day })?
+            }
+            TransitionDate::Ordinal(day) =>
+                NaiveDate::from_yo_opt(year, day.checked_add(1)?)
+                    .or_else(|| NaiveDate::from_ymd_opt(year, 12, 31))?,
+            TransitionDate::Month { month, week, weekday } => {
+                let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+                let first_weekday = first.weekday().num_days_from_sunday();
+                let day = weekday
+                    .checked_add(7)?
+                    .checked_sub(first_weekday)?
+                    .checked_rem(7)?
+                    .checked_add(week.checked_sub(1)?.checked_mul(7)?)?
+                    .checked_add(1)?;
+                // Week 5 means the last such weekday, which may be in week 4.
+                NaiveDate::from_ymd_opt(year, month, day)
+                    .or_else(|| NaiveDate::from_ymd_opt(year, month, day.checked_sub(7)?))?
+            }
+        };
+        let midnight = date.and_hms_opt(0, 0, 0)?.and_utc().timestamp();
+        midnight.checked_add(self.time.into())?.checked_sub(utc_offset.into())
+    }
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl Parser<'_> {
+    fn eat(&mut self, c: char) -> bool {
+        if let Some(rest) = self.rest.strip_prefix(c) {
+            self.rest = rest;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// A time zone name: at least three letters, or anything in angle brackets (e.g. `<+08>`).
+    fn name(&mut self) -> Option<String> {
+        let (name, rest) = if let Some(quoted) = self.rest.strip_prefix('<') {
+            let (name, rest) = quoted.split_once('>')?;
+            (name, rest)
+        } else {
+            let len = self.rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(self.rest.len());
+            self.rest.split_at(len)
+        };
+        if name.len() < 3 {
+            return None;
+        }
+        self.rest = rest;
+        Some(name.to_owned())
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        let len = self.rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(self.rest.len());
+        let (number, rest) = self.rest.split_at(len);
+        let number = number.parse().ok()?;
+        self.rest = rest;
+        Some(number)
+    }
+
+    /// `[+|-]hh[:mm[:ss]]` with at most `max_hours` hours, in seconds.
+    /// POSIX allows up to 24 hours for offsets, and up to 167 hours for transition times.
+    fn time(&mut self, max_hours: u32) -> Option<i32> {
+        let negative = self.eat('-');
+        if !negative {
+            self.eat('+');
+        }
+        let hours = self.number()?;
+        if hours > max_hours {
+            return None;
+        }
+        let mut seconds = hours.checked_mul(3600)?;
+        if self.eat(':') {
+            seconds = seconds.checked_add(self.number()?.checked_mul(60)?)?;
+            if self.eat(':') {
+                seconds = seconds.checked_add(self.number()?)?;
+            }
+        }
+        let seconds = i32::try_from(seconds).ok()?;
+        if negative {
+            seconds.checked_neg()
+        } else {
+            Some(seconds)
+        }
+    }
+
+    /// `Jn`, `n` or `Mm.w.d`, optionally followed by `/time`.
+    fn transition(&mut self) -> Option<Transition> {
+        let date = if self.eat('J') {
+            let day = self.number()?;
+            (1..=365).contains(&day).then_some(TransitionDate::Julian(day))?
+        } else if self.eat('M') {
+            let month = self.number()?;
+            let week = if self.eat('.') { self.number()? } else { return None };
+            let weekday = if self.eat('.') { self.number()? } else { return None };
+            ((1..=12).contains(&month) && (1..=5).contains(&week) && weekday <= 6)
+                .then_some(TransitionDate::Month { month, week, weekday })?
+        } else {
+            let day = self.number()?;
+            (day <= 365).then_some(TransitionDate::Ordinal(day))?
+        };
+        let time = if self.eat('/') { self.time(167)? } else { 7200 };
+        Some(Transition { date, time })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offset(tz: &str, timestamp: i64) -> (i32, bool, String) {
+        let offset = TimeZone::from_env(Some(tz)).offset_at(timestamp).unwrap();
+        (offset.utc_offset, offset.is_dst, offset.abbreviation)
+    }
+
+    #[test]
+    fn posix() {
+        // 2024-01-15 12:00:00 UTC and 2024-07-15 12:00:00 UTC.
+        let (winter, summer) = (1705320000, 1721044800);
+        let cet = "CET-1CEST,M3.5.0,M10.5.0/3";
+        assert_eq!(offset(cet, winter), (3600, false, "CET".to_owned()));
+        assert_eq!(offset(cet, summer), (7200, true, "CEST".to_owned()));
+        // DST starts on 2024-03-31 at 01:00:00 UTC.
+        assert!(!offset(cet, 1711846799).1);
+        assert!(offset(cet, 1711846800).1);
+
+        // Without rules, the US rules apply.
+        assert_eq!(offset("EST5EDT", winter), (-18000, false, "EST".to_owned()));
+        assert_eq!(offset("EST5EDT", summer), (-14400, true, "EDT".to_owned()));
+
+        // Southern hemisphere.
+        let aest = "AEST-10AEDT,M10.1.0,M4.1.0/3";
+        assert_eq!(offset(aest, winter), (39600, true, "AEDT".to_owned()));
+        assert_eq!(offset(aest, summer), (36000, false, "AEST".to_owned()));
+
+        assert_eq!(offset("<+0530>-5:30", winter), (19800, false, "+0530".to_owned()));
+        assert_eq!(offset("UTC0", summer), (0, false, "UTC".to_owned()));
+    }
+
+    #[test]
+    fn iana() {
+        assert_eq!(offset("Europe/Berlin", 1721044800), (7200, true, "CEST".to_owned()));
+        assert_eq!(offset(":America/New_York", 1705320000), (-18000, false, "EST".to_owned()));
+    }
+
+    #[test]
+    fn invalid() {
+        for tz in [
+            "",
+            "X",
+            "ABC99",
+            "CET-1CEST,M3.5.0",
+            "CET-1CEST,M13.5.0,M10.5.0",
+            "CET-1CEST,M3.5.0/168,M10.5.0",
+            "Not/A_Zone",
+        ] {
+            assert_eq!(offset(tz, 0), (0, false, "UTC".to_owned()));
+        }
+    }
+}
@@ -212,7 +212,11 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let size = this.read_target_usize(size_op)?;
 
         if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
-            this.reject_in_isolation("`getcwd`", reject_with)?;
+            // Printing a backtrace only uses the current directory to shorten paths, and works
+            // fine without it. No need to bother the user about isolation then.
+            if !this.in_std_backtrace() {
+                this.reject_in_isolation("`getcwd`", reject_with)?;
+            }
             this.set_last_error_from_io_error(ErrorKind::PermissionDenied.into())?;
             return Ok(Pointer::null());
         }
@@ -254,7 +258,8 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         }
     }
 
-    /// Updates the `environ` static.
+    /// Updates the `environ` static. This is called after every change to `map`, so that programs
+    /// walking `environ` always see the current environment variables.
     fn update_environ(&mut self) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
         // Deallocate the old environ list.
@@ -274,12 +279,83 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let this = self.eval_context_mut();
         this.assert_target_os_is_unix("getpid");
 
-        this.check_no_isolation("`getpid`")?;
-
         // The reason we need to do this wacky of a conversion is because
         // `libc::getpid` returns an i32, however, `std::process::id()` return an u32.
         // So we un-do the conversion that stdlib does and turn it back into an i32.
         #[allow(clippy::cast_possible_wrap)]
-        Ok(std::process::id() as i32)
+        Ok(this.machine.process_id as i32)
+    }
+
+    fn uname(&mut self, buf_op: &OpTy<'tcx>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+        this.assert_target_os_is_unix("uname");
+
+        // None of this comes from the host, so that the result is deterministic.
+        let sess = this.tcx.sess;
+        let target = &sess.target;
+        let (sysname, default_release) = match target.os.as_ref() {
+            "linux" | "android" => ("Linux", "6.1.0"),
+            "macos" => ("Darwin", "23.0.0"),
+            "freebsd" => ("FreeBSD", "14.0-RELEASE"),
+            "illumos" | "solaris" => ("SunOS", "5.11"),
+            os => throw_unsup_format!("`uname` is not supported on {os}"),
+        };
+        let release = this.machine.os_release.clone().unwrap_or_else(|| default_release.to_owned());
+        let version = format!("#1 {sysname} {release} (Miri)");
+        let machine = match (target.arch.as_ref(), target.vendor.as_ref()) {
+            ("aarch64", "apple") => "arm64",
+            ("x86", _) => "i686",
+            (arch, _) => arch,
+        };
+        let hostname = this.machine.hostname.clone();
+
+        let buf = this.deref_pointer_as(buf_op, this.libc_ty_layout("utsname"))?;
+        let mut fields = vec![
+            ("sysname", sysname),
+            ("nodename", &hostname),
+            ("release", &release),
+            ("version", &version),
+            ("machine", machine),
+        ];
+        // Only Linux has this field.
+        if this.projectable_has_field(&buf, "domainname") {
+            fields.push(("domainname", "(none)"));
+        }
+        for (name, value) in fields {
+            let field = this.project_field_named(&buf, name)?;
+            let size = field.layout.size.bytes();
+            let mut value = value.as_bytes();
+            // The config is validated to fit into the smallest of these fields, but the version
+            // string embeds the release plus some more text, so it may not fit; truncate it.
+            if name == "version" {
+                value = &value[..value.len().min(usize::try_from(size - 1).unwrap())];
+            }
+            let (written, _) = this.write_c_str(value, field.ptr(), size)?;
+            assert!(written, "`uname` field `{name}` is too small for {value:?}");
+        }
+        Ok(0)
+    }
+
+    fn gethostname(
+        &mut self,
+        name_op: &OpTy<'tcx>,
+        len_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+        this.assert_target_os_is_unix("gethostname");
+
+        let name = this.read_pointer(name_op)?;
+        let len = this.read_target_usize(len_op)?;
+
+        let hostname = this.machine.hostname.clone();
+        let (written, _) = this.write_c_str(hostname.as_bytes(), name, len)?;
+        if written {
+            Ok(0)
+        } else {
+            // What glibc does; POSIX leaves it unspecified whether the name is truncated instead.
+            let enametoolong = this.eval_libc("ENAMETOOLONG");
+            this.set_last_error(enametoolong)?;
+            Ok(-1)
+        }
     }
 }
@@ -9,6 +9,7 @@ use std::rc::Rc;
 
 use rustc_target::abi::Size;
 
+use crate::shims::unix::vfs::VirtualFile;
 use crate::shims::unix::*;
 use crate::*;
 
@@ -164,6 +165,33 @@ impl FileDescription for NullOutput {
     }
 }
 
+/// The stdout or stderr of a program whose output is captured (see
+/// `MiriConfig::captured_output`).
+#[derive(Debug)]
+pub struct CapturedStream {
+    stderr: bool,
+}
+
+impl FileDescription for CapturedStream {
+    fn name(&self) -> &'static str {
+        if self.stderr { "stderr" } else { "stdout" }
+    }
+
+    fn write<'tcx>(
+        &mut self,
+        _communicate_allowed: bool,
+        bytes: &[u8],
+        ecx: &mut MiriInterpCx<'tcx>,
+    ) -> InterpResult<'tcx, io::Result<usize>> {
+        let result = if self.stderr {
+            ecx.machine.write_to_stderr(bytes)
+        } else {
+            ecx.machine.write_to_stdout(bytes)
+        };
+        Ok(result.map(|()| bytes.len()))
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct FileDescriptor(Rc<RefCell<Box<dyn FileDescription>>>);
 
@@ -203,12 +231,15 @@ impl VisitProvenance for FdTable {
 }
 
 impl FdTable {
-    pub(crate) fn new(mute_stdout_stderr: bool) -> FdTable {
+    pub(crate) fn new(mute_stdout_stderr: bool, captured_output: bool) -> FdTable {
         let mut fds: BTreeMap<_, FileDescriptor> = BTreeMap::new();
         fds.insert(0i32, FileDescriptor::new(io::stdin()));
         if mute_stdout_stderr {
             fds.insert(1i32, FileDescriptor::new(NullOutput));
             fds.insert(2i32, FileDescriptor::new(NullOutput));
+        } else if captured_output {
+            fds.insert(1i32, FileDescriptor::new(CapturedStream { stderr: false }));
+            fds.insert(2i32, FileDescriptor::new(CapturedStream { stderr: true }));
         } else {
             fds.insert(1i32, FileDescriptor::new(io::stdout()));
             fds.insert(2i32, FileDescriptor::new(io::stderr()));
@@ -273,17 +304,16 @@ impl FdTable {
 
 impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
 pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
-    fn fcntl(&mut self, args: &[OpTy<'tcx>]) -> InterpResult<'tcx, i32> {
+    fn fcntl(
+        &mut self,
+        fd_op: &OpTy<'tcx>,
+        cmd_op: &OpTy<'tcx>,
+        mut varargs: VarArgs<'_, 'tcx>,
+    ) -> InterpResult<'tcx, i32> {
         let this = self.eval_context_mut();
 
-        if args.len() < 2 {
-            throw_ub_format!(
-                "incorrect number of arguments for fcntl: got {}, expected at least 2",
-                args.len()
-            );
-        }
-        let fd = this.read_scalar(&args[0])?.to_i32()?;
-        let cmd = this.read_scalar(&args[1])?.to_i32()?;
+        let fd = this.read_scalar(fd_op)?.to_i32()?;
+        let cmd = this.read_scalar(cmd_op)?.to_i32()?;
 
         // We only support getting the flags for a descriptor.
         if cmd == this.eval_libc_i32("F_GETFD") {
@@ -303,13 +333,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             // because exec() isn't supported. The F_DUPFD and F_DUPFD_CLOEXEC commands only
             // differ in whether the FD_CLOEXEC flag is pre-set on the new file descriptor,
             // thus they can share the same implementation here.
-            if args.len() < 3 {
-                throw_ub_format!(
-                    "incorrect number of arguments for fcntl with cmd=`F_DUPFD`/`F_DUPFD_CLOEXEC`: got {}, expected at least 3",
-                    args.len()
-                );
-            }
-            let start = this.read_scalar(&args[2])?.to_i32()?;
+            let start = varargs.next(this, this.machine.layouts.i32)?.to_i32()?;
 
             match this.machine.fds.dup(fd) {
                 Some(dup_fd) => Ok(this.machine.fds.insert_fd_with_min_fd(dup_fd, start)),
@@ -329,6 +353,27 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         }
     }
 
+    fn ioctl(
+        &mut self,
+        fd_op: &OpTy<'tcx>,
+        request_op: &OpTy<'tcx>,
+        _varargs: VarArgs<'_, 'tcx>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let fd = this.read_scalar(fd_op)?.to_i32()?;
+        let request = this.read_scalar(request_op)?;
+
+        // We only support setting the close-on-exec flag, which some targets' `std` uses instead
+        // of `fcntl`. Like for `fcntl`, we assume that this flag is always set, so there is
+        // nothing to do besides checking that the file is open.
+        if request == this.eval_libc("FIOCLEX") {
+            if this.machine.fds.is_fd(fd) { Ok(0) } else { this.fd_not_found() }
+        } else {
+            throw_unsup_format!("the {request:#x} request is not supported for `ioctl`");
+        }
+    }
+
     fn close(&mut self, fd_op: &OpTy<'tcx>) -> InterpResult<'tcx, Scalar> {
         let this = self.eval_context_mut();
 
@@ -417,9 +462,26 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let count = count
             .min(u64::try_from(this.target_isize_max()).unwrap())
             .min(u64::try_from(isize::MAX).unwrap());
-        let communicate = this.machine.communicate();
 
         let bytes = this.read_bytes_ptr_strip_provenance(buf, Size::from_bytes(count))?.to_owned();
+        this.write_bytes_to_fd(fd, &bytes)
+    }
+
+    /// Writes bytes from the host to a file descriptor. Returns the number of bytes written, or -1
+    /// (and sets the last error) if writing failed.
+    fn write_bytes_to_fd(&mut self, fd: i32, bytes: &[u8]) -> InterpResult<'tcx, i64> {
+        let this = self.eval_context_mut();
+        let communicate = this.machine.communicate();
+
+        // Like on a real file system, writing to a virtual file that was opened read-only fails
+        // with EBADF. There is no `io::ErrorKind` for that, so the file cannot report it itself.
+        let read_only = this.machine.fds.get(fd).is_some_and(|fd| {
+            fd.downcast_ref::<VirtualFile>().is_some_and(|file| !file.writable)
+        });
+        if read_only {
+            return this.fd_not_found();
+        }
+
         // We temporarily dup the FD to be able to retain mutable access to `this`.
         let Some(file_descriptor) = this.machine.fds.dup(fd) else {
             return this.fd_not_found();
@@ -427,7 +489,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
         let result = file_descriptor
             .borrow_mut()
-            .write(communicate, &bytes, this)?
+            .write(communicate, bytes, this)?
             .map(|c| i64::try_from(c).unwrap());
         drop(file_descriptor);
 
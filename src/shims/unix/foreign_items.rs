@@ -83,6 +83,16 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let result = this.getpid()?;
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
+            "uname" => {
+                let [buf] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.uname(buf)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "gethostname" => {
+                let [name, len] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.gethostname(name, len)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
 
             // File descriptors
             "read" => {
@@ -109,18 +119,123 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 this.write_scalar(result, dest)?;
             }
             "fcntl" => {
-                // `fcntl` is variadic. The argument count is checked based on the first argument
-                // in `this.fcntl()`, so we do not use `check_shim` here.
-                this.check_abi_and_shim_symbol_clash(abi, Abi::C { unwind: false }, link_name)?;
-                let result = this.fcntl(args)?;
+                // `fcntl` is variadic, which arguments it takes depends on the command.
+                let ([fd, cmd], varargs) =
+                    this.check_shim_variadic(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.fcntl(fd, cmd, varargs)?;
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
+            "ioctl" => {
+                // `ioctl` is variadic, which arguments it takes depends on the request.
+                let ([fd, request], varargs) =
+                    this.check_shim_variadic(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.ioctl(fd, request, varargs)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+
+            // Formatted output
+            "printf" => {
+                let ([format], varargs) =
+                    this.check_shim_variadic(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.printf_to_fd(1, format, varargs)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "dprintf" => {
+                let ([fd, format], varargs) =
+                    this.check_shim_variadic(abi, Abi::C { unwind: false }, link_name, args)?;
+                let fd = this.read_scalar(fd)?.to_i32()?;
+                let result = this.printf_to_fd(fd, format, varargs)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "fprintf" => {
+                let ([stream, format], varargs) =
+                    this.check_shim_variadic(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.fprintf(stream, format, varargs)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "snprintf" => {
+                let ([buf, size, format], varargs) =
+                    this.check_shim_variadic(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.snprintf(buf, size, format, varargs)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+
+            // Exit handlers
+            "__cxa_atexit" => {
+                let [func, arg, dso_handle] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.cxa_atexit(func, arg, dso_handle)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+
+            // Non-local jumps
+            "setjmp" | "_setjmp" => {
+                let [env] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.setjmp(env, dest)?;
+            }
+            "sigsetjmp" | "__sigsetjmp" => {
+                let [env, _savemask] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                // We do not support signals, so there is no signal mask to save.
+                this.setjmp(env, dest)?;
+            }
+            "longjmp" | "_longjmp" | "siglongjmp" => {
+                let [env, val] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.longjmp(env, val)?;
+                return Ok(EmulateItemResult::AlreadyJumped);
+            }
+
+            // Locales and character set conversion
+            "setlocale" => {
+                let [category, locale] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.setlocale(category, locale)?;
+                this.write_scalar(result, dest)?;
+            }
+            "newlocale" => {
+                let [category_mask, locale, base] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.newlocale(category_mask, locale, base)?;
+                this.write_scalar(result, dest)?;
+            }
+            "freelocale" => {
+                let [locale] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.freelocale(locale)?;
+            }
+            "uselocale" => {
+                let [locale] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.uselocale(locale)?;
+                this.write_scalar(result, dest)?;
+            }
+            "nl_langinfo" => {
+                let [item] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.nl_langinfo(item)?;
+                this.write_scalar(result, dest)?;
+            }
+            "iconv_open" => {
+                let [tocode, fromcode] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.iconv_open(tocode, fromcode)?;
+                this.write_scalar(result, dest)?;
+            }
+            "iconv" => {
+                let [cd, inbuf, inbytesleft, outbuf, outbytesleft] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.iconv(cd, inbuf, inbytesleft, outbuf, outbytesleft)?;
+                this.write_scalar(result, dest)?;
+            }
+            "iconv_close" => {
+                let [cd] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.iconv_close(cd)?;
+                this.write_scalar(result, dest)?;
+            }
 
             // File and file system access
             "open" | "open64" => {
                 // `open` is variadic, the third argument is only present when the second argument has O_CREAT (or on linux O_TMPFILE, but miri doesn't support that) set
-                this.check_abi_and_shim_symbol_clash(abi, Abi::C { unwind: false }, link_name)?;
-                let result = this.open(args)?;
+                let ([path, flag], varargs) =
+                    this.check_shim_variadic(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.open(path, flag, varargs)?;
                 this.write_scalar(Scalar::from_i32(result), dest)?;
             }
             "unlink" => {
@@ -235,6 +350,22 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 this.write_scalar(result, dest)?;
             }
 
+            // Network interfaces
+            "getifaddrs" => {
+                let [ifap] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.getifaddrs(ifap)?;
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "freeifaddrs" => {
+                let [ifa] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.freeifaddrs(ifa)?;
+            }
+            "if_nametoindex" => {
+                let [ifname] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.if_nametoindex(ifname)?;
+                this.write_scalar(Scalar::from_u32(result), dest)?;
+            }
+
             // Time
             "gettimeofday" => {
                 let [tv, tz] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
@@ -246,6 +377,11 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let result = this.localtime_r(timep, result_op)?;
                 this.write_pointer(result, dest)?;
             }
+            "tzset" => {
+                let [] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                // `localtime_r` reads `TZ` every time it is called, so there is nothing to do
+                // here. (We do not support the `tzname`, `timezone` and `daylight` globals.)
+            }
             "clock_gettime" => {
                 let [clk_id, tp] =
                     this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
@@ -548,6 +684,18 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let res = this.pthread_self()?;
                 this.write_scalar(res, dest)?;
             }
+            "pthread_setschedparam" => {
+                let [thread, policy, param] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.pthread_setschedparam(thread, policy, param)?;
+                this.write_scalar(result, dest)?;
+            }
+            "pthread_getschedparam" => {
+                let [thread, policy, param] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.pthread_getschedparam(thread, policy, param)?;
+                this.write_scalar(result, dest)?;
+            }
             "sched_yield" => {
                 let [] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
                 let result = this.sched_yield()?;
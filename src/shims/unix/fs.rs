@@ -1,17 +1,21 @@
 //! File and file system access
 
 use std::borrow::Cow;
+use std::cell::RefCell;
+use std::ffi::OsString;
 use std::fs::{
     read_dir, remove_dir, remove_file, rename, DirBuilder, File, FileType, OpenOptions, ReadDir,
 };
 use std::io::{self, ErrorKind, IsTerminal, Read, Seek, SeekFrom, Write};
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
 use std::time::SystemTime;
 
 use rustc_data_structures::fx::FxHashMap;
 use rustc_target::abi::Size;
 
 use crate::shims::os_str::bytes_to_os_str;
+use crate::shims::unix::vfs::{self, VirtualFile, VirtualFs};
 use crate::shims::unix::*;
 use crate::*;
 use shims::time::system_time_to_duration;
@@ -31,38 +35,34 @@ impl FileDescription for FileHandle {
 
     fn read<'tcx>(
         &mut self,
-        communicate_allowed: bool,
+        _communicate_allowed: bool,
         bytes: &mut [u8],
         _ecx: &mut MiriInterpCx<'tcx>,
     ) -> InterpResult<'tcx, io::Result<usize>> {
-        assert!(communicate_allowed, "isolation should have prevented even opening a file");
         Ok(self.file.read(bytes))
     }
 
     fn write<'tcx>(
         &mut self,
-        communicate_allowed: bool,
+        _communicate_allowed: bool,
         bytes: &[u8],
         _ecx: &mut MiriInterpCx<'tcx>,
     ) -> InterpResult<'tcx, io::Result<usize>> {
-        assert!(communicate_allowed, "isolation should have prevented even opening a file");
         Ok(self.file.write(bytes))
     }
 
     fn seek<'tcx>(
         &mut self,
-        communicate_allowed: bool,
+        _communicate_allowed: bool,
         offset: SeekFrom,
     ) -> InterpResult<'tcx, io::Result<u64>> {
-        assert!(communicate_allowed, "isolation should have prevented even opening a file");
         Ok(self.file.seek(offset))
     }
 
     fn close<'tcx>(
         self: Box<Self>,
-        communicate_allowed: bool,
+        _communicate_allowed: bool,
     ) -> InterpResult<'tcx, io::Result<()>> {
-        assert!(communicate_allowed, "isolation should have prevented even opening a file");
         // We sync the file if it was opened in a mode different than read-only.
         if self.writable {
             // `File::sync_all` does the checks that are done when closing a file. We do this to
@@ -88,6 +88,120 @@ impl FileDescription for FileHandle {
 
 impl<'tcx> EvalContextExtPrivate<'tcx> for crate::MiriInterpCx<'tcx> {}
 trait EvalContextExtPrivate<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Determine where `op_name` should look up the given path, applying the directories mapped
+    /// with `-Zmiri-map-dir`. With isolation enabled, only paths inside a mapped directory can be
+    /// accessed on the host; everything else is either rejected, or looked up in the virtual file
+    /// system if `-Zmiri-virtual-fs` is set. If the access is not allowed, this sets the last error
+    /// and returns `None`.
+    fn resolve_path(
+        &mut self,
+        op_name: &str,
+        path: &Path,
+        write: bool,
+    ) -> InterpResult<'tcx, Option<FsPath>> {
+        let this = self.eval_context_mut();
+
+        match this.map_path(path) {
+            Some((_, true)) if write => {
+                let erofs = this.eval_libc("EROFS");
+                this.set_last_error(erofs)?;
+                Ok(None)
+            }
+            Some((host_path, _)) => Ok(Some(FsPath::Host(host_path))),
+            None => {
+                if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+                    if this.machine.vfs.is_some() {
+                        return Ok(Some(FsPath::Virtual(VirtualFs::normalize(path))));
+                    }
+                    // Reject if isolation is enabled.
+                    this.reject_in_isolation(op_name, reject_with)?;
+                    let eacces = this.eval_libc("EACCES");
+                    this.set_last_error(eacces)?;
+                    return Ok(None);
+                }
+                Ok(Some(FsPath::Host(path.to_owned())))
+            }
+        }
+    }
+
+    /// If `path` is inside a directory mapped with `-Zmiri-map-dir`, return the corresponding
+    /// host path, and whether the mapping is read-only.
+    fn map_path(&self, path: &Path) -> Option<(PathBuf, bool)> {
+        let this = self.eval_context_ref();
+        this.machine.mapped_dirs.iter().find_map(|(host, guest, read_only)| {
+            let rest = path.strip_prefix(guest).ok()?;
+            // Do not let `..` escape the mapped directory.
+            if rest.components().any(|c| c == Component::ParentDir) {
+                return None;
+            }
+            Some((host.join(rest), *read_only))
+        })
+    }
+
+    /// The inverse of `resolve_path` for host paths: turn a host path into the path the program
+    /// would use for it.
+    fn guest_path(&self, path: PathBuf) -> PathBuf {
+        let this = self.eval_context_ref();
+        this.machine
+            .mapped_dirs
+            .iter()
+            .find_map(|(host, guest, _)| Some(guest.join(path.strip_prefix(host).ok()?)))
+            .unwrap_or(path)
+    }
+
+    fn vfs(&mut self) -> &mut VirtualFs {
+        self.eval_context_mut().machine.vfs.as_mut().unwrap()
+    }
+
+    /// Whether `fd` refers to a file. With isolation enabled, such files can only have been opened
+    /// in a directory mapped with `-Zmiri-map-dir` or in the virtual file system, so operating on
+    /// them is fine.
+    fn is_file(&self, fd: i32) -> bool {
+        let this = self.eval_context_ref();
+        this.machine.fds.get(fd).is_some_and(|fd| {
+            fd.downcast_ref::<FileHandle>().is_some() || fd.downcast_ref::<VirtualFile>().is_some()
+        })
+    }
+
+    /// Get the next entry of the directory stream `dirp`: its name (which is not a `Path` as there
+    /// are no separators), its inode number, and its `d_type`.
+    fn next_dir_entry(
+        &mut self,
+        dirp: u64,
+        op_name: &str,
+    ) -> InterpResult<'tcx, Option<io::Result<(OsString, u64, i32)>>> {
+        let this = self.eval_context_mut();
+
+        let open_dir = this.machine.dirs.streams.get_mut(&dirp).ok_or_else(|| {
+            err_unsup_format!("the DIR pointer passed to {op_name} did not come from opendir")
+        })?;
+        match &mut open_dir.entries {
+            DirEntries::Host(read_dir) => {
+                let dir_entry = match read_dir.next() {
+                    None => return Ok(None),
+                    Some(Err(e)) => return Ok(Some(Err(e))),
+                    Some(Ok(dir_entry)) => dir_entry,
+                };
+
+                // If the host is a Unix system, fill in the inode number with its real value.
+                // If not, use 0 as a fallback value.
+                #[cfg(unix)]
+                let ino = std::os::unix::fs::DirEntryExt::ino(&dir_entry);
+                #[cfg(not(unix))]
+                let ino = 0u64;
+
+                let file_type = this.file_type_to_d_type(dir_entry.file_type())?;
+                Ok(Some(Ok((dir_entry.file_name(), ino, file_type))))
+            }
+            DirEntries::Virtual(entries) => {
+                let Some((name, is_dir)) = entries.next() else { return Ok(None) };
+                let file_type = if is_dir { "DT_DIR" } else { "DT_REG" };
+                let file_type = this.eval_libc(file_type).to_u8()?.into();
+                Ok(Some(Ok((name, 0, file_type))))
+            }
+        }
+    }
+
     fn macos_stat_write_buf(
         &mut self,
         metadata: FileMetadata,
@@ -173,19 +287,36 @@ trait EvalContextExtPrivate<'tcx>: crate::MiriInterpCxExt<'tcx> {
     }
 }
 
+/// Where a path that the program accesses is looked up, see `resolve_path`.
+enum FsPath {
+    /// On the host.
+    Host(PathBuf),
+    /// In the virtual file system of `-Zmiri-virtual-fs`. The path is normalized.
+    Virtual(PathBuf),
+}
+
+/// The entries of an open directory.
+#[derive(Debug)]
+enum DirEntries {
+    /// The directory reader on the host.
+    Host(ReadDir),
+    /// The names of the entries of a directory in the virtual file system, and whether they are
+    /// directories.
+    Virtual(std::vec::IntoIter<(OsString, bool)>),
+}
+
 /// An open directory, tracked by DirHandler.
 #[derive(Debug)]
 struct OpenDir {
-    /// The directory reader on the host.
-    read_dir: ReadDir,
+    entries: DirEntries,
     /// The most recent entry returned by readdir().
     /// Will be freed by the next call.
     entry: Option<Pointer>,
 }
 
 impl OpenDir {
-    fn new(read_dir: ReadDir) -> Self {
-        Self { read_dir, entry: None }
+    fn new(entries: DirEntries) -> Self {
+        Self { entries, entry: None }
     }
 }
 
@@ -210,10 +341,10 @@ pub struct DirTable {
 
 impl DirTable {
     #[allow(clippy::arithmetic_side_effects)]
-    fn insert_new(&mut self, read_dir: ReadDir) -> u64 {
+    fn insert_new(&mut self, entries: DirEntries) -> u64 {
         let id = self.next_id;
         self.next_id += 1;
-        self.streams.try_insert(id, OpenDir::new(read_dir)).unwrap();
+        self.streams.try_insert(id, OpenDir::new(entries)).unwrap();
         id
     }
 }
@@ -256,18 +387,16 @@ fn maybe_sync_file(
 
 impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
 pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
-    fn open(&mut self, args: &[OpTy<'tcx>]) -> InterpResult<'tcx, i32> {
-        if args.len() < 2 {
-            throw_ub_format!(
-                "incorrect number of arguments for `open`: got {}, expected at least 2",
-                args.len()
-            );
-        }
-
+    fn open(
+        &mut self,
+        path_op: &OpTy<'tcx>,
+        flag_op: &OpTy<'tcx>,
+        mut varargs: VarArgs<'_, 'tcx>,
+    ) -> InterpResult<'tcx, i32> {
         let this = self.eval_context_mut();
 
-        let path = this.read_pointer(&args[0])?;
-        let flag = this.read_scalar(&args[1])?.to_i32()?;
+        let path = this.read_pointer(path_op)?;
+        let flag = this.read_scalar(flag_op)?.to_i32()?;
 
         let mut options = OpenOptions::new();
 
@@ -312,17 +441,17 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         }
         let o_creat = this.eval_libc_i32("O_CREAT");
         if flag & o_creat == o_creat {
-            // Get the mode.  On macOS, the argument type `mode_t` is actually `u16`, but
-            // C integer promotion rules mean that on the ABI level, it gets passed as `u32`
-            // (see https://github.com/rust-lang/rust/issues/71915).
-            let mode = if let Some(arg) = args.get(2) {
-                this.read_scalar(arg)?.to_u32()?
-            } else {
+            // Get the mode. On macOS, `mode_t` is actually `u16`, but C integer promotion rules
+            // mean that on the ABI level, it gets passed as `u32`
+            // (see https://github.com/rust-lang/rust/issues/71915). `VarArgs` takes care of that.
+            if varargs.remaining() == 0 {
                 throw_ub_format!(
-                    "incorrect number of arguments for `open` with `O_CREAT`: got {}, expected at least 3",
-                    args.len()
+                    "incorrect number of arguments for `open` with `O_CREAT`: got 2, expected at least 3"
                 );
-            };
+            }
+            let mode_t = this.libc_ty_layout("mode_t");
+            let mode = varargs.next(this, mode_t)?.to_uint(mode_t.size)?;
+            let mode = u32::try_from(mode).unwrap();
 
             #[cfg(unix)]
             {
@@ -372,19 +501,42 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             throw_unsup_format!("unsupported flags {:#x}", flag & !mirror);
         }
 
-        let path = this.read_path_from_c_str(path)?;
-
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
-            this.reject_in_isolation("`open`", reject_with)?;
-            this.set_last_error_from_io_error(ErrorKind::PermissionDenied.into())?;
-            return Ok(-1);
+        let path = this.read_path_from_c_str(path)?.into_owned();
+        let write = writable || flag & (o_trunc | o_creat) != 0;
+        // Files in `/proc` that describe the program are synthesized, with or without isolation.
+        if path.is_absolute() {
+            if let Some(contents) = this.procfs_file(&VirtualFs::normalize(&path))? {
+                if write {
+                    let eacces = this.eval_libc("EACCES");
+                    this.set_last_error(eacces)?;
+                    return Ok(-1);
+                }
+                let file = VirtualFile::new(Rc::new(RefCell::new(contents)), false, false);
+                return Ok(this.machine.fds.insert_fd(FileDescriptor::new(file)));
+            }
         }
+        let Some(path) = this.resolve_path("`open`", &path, write)? else {
+            return Ok(-1);
+        };
 
-        let fd = options.open(path).map(|file| {
-            let fh = &mut this.machine.fds;
-            fh.insert_fd(FileDescriptor::new(FileHandle { file, writable }))
-        });
+        let fd = match path {
+            FsPath::Host(path) =>
+                options.open(path).map(|file| {
+                    let fh = &mut this.machine.fds;
+                    fh.insert_fd(FileDescriptor::new(FileHandle { file, writable }))
+                }),
+            FsPath::Virtual(path) => {
+                let create = flag & o_creat == o_creat;
+                let o_excl = this.eval_libc_i32("O_EXCL");
+                let create_new = create && flag & o_excl == o_excl;
+                let truncate = flag & o_trunc == o_trunc;
+                let append = flag & o_append == o_append;
+                this.vfs().open(&path, create, create_new, truncate).map(|contents| {
+                    let fh = &mut this.machine.fds;
+                    fh.insert_fd(FileDescriptor::new(VirtualFile::new(contents, writable, append)))
+                })
+            }
+        };
 
         this.try_unwrap_io_result(fd)
     }
@@ -430,16 +582,16 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     fn unlink(&mut self, path_op: &OpTy<'tcx>) -> InterpResult<'tcx, i32> {
         let this = self.eval_context_mut();
 
-        let path = this.read_path_from_c_str(this.read_pointer(path_op)?)?;
-
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
-            this.reject_in_isolation("`unlink`", reject_with)?;
-            this.set_last_error_from_io_error(ErrorKind::PermissionDenied.into())?;
+        let path = this.read_path_from_c_str(this.read_pointer(path_op)?)?.into_owned();
+        let Some(path) = this.resolve_path("`unlink`", &path, /* write */ true)? else {
             return Ok(-1);
-        }
+        };
 
-        let result = remove_file(path).map(|_| 0);
+        let result = match path {
+            FsPath::Host(path) => remove_file(path),
+            FsPath::Virtual(path) => this.vfs().unlink(&path),
+        }
+        .map(|_| 0);
         this.try_unwrap_io_result(result)
     }
 
@@ -460,17 +612,46 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         }
 
         let this = self.eval_context_mut();
-        let target = this.read_path_from_c_str(this.read_pointer(target_op)?)?;
-        let linkpath = this.read_path_from_c_str(this.read_pointer(linkpath_op)?)?;
-
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
-            this.reject_in_isolation("`symlink`", reject_with)?;
-            this.set_last_error_from_io_error(ErrorKind::PermissionDenied.into())?;
+        let target = this.read_path_from_c_str(this.read_pointer(target_op)?)?.into_owned();
+        let linkpath = this.read_path_from_c_str(this.read_pointer(linkpath_op)?)?.into_owned();
+        let Some(linkpath) = this.resolve_path("`symlink`", &linkpath, /* write */ true)? else {
             return Ok(-1);
-        }
+        };
+
+        // The target is only looked up when the link is followed, and then on the host. So
+        // absolute targets have to be translated like any other path, and with isolation, no
+        // target may point outside of the mapped directories.
+        let host_target = if target.is_absolute() {
+            this.map_path(&target).map(|(host_target, _)| host_target)
+        } else if let FsPath::Host(linkpath) = &linkpath {
+            // Relative targets are resolved relative to the directory containing the link. Keep
+            // them relative as long as they stay in a mapped directory.
+            let dir = linkpath.parent().unwrap_or(linkpath);
+            let resolved = VirtualFs::normalize(&dir.join(&target));
+            let mut mapped_dirs = this.machine.mapped_dirs.iter();
+            mapped_dirs.any(|(host, ..)| resolved.starts_with(host)).then(|| target.clone())
+        } else {
+            None
+        };
+        let target = match host_target {
+            Some(host_target) => host_target,
+            None => {
+                if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+                    this.reject_in_isolation("`symlink`", reject_with)?;
+                    let eacces = this.eval_libc("EACCES");
+                    this.set_last_error(eacces)?;
+                    return Ok(-1);
+                }
+                target
+            }
+        };
 
-        let result = create_link(&target, &linkpath).map(|_| 0);
+        let result = match linkpath {
+            FsPath::Host(linkpath) => create_link(&target, &linkpath),
+            // The virtual file system does not support symbolic links.
+            FsPath::Virtual(_) => Err(ErrorKind::PermissionDenied.into()),
+        }
+        .map(|_| 0);
         this.try_unwrap_io_result(result)
     }
 
@@ -487,14 +668,9 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
         let path_scalar = this.read_pointer(path_op)?;
         let path = this.read_path_from_c_str(path_scalar)?.into_owned();
-
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
-            this.reject_in_isolation("`stat`", reject_with)?;
-            let eacc = this.eval_libc("EACCES");
-            this.set_last_error(eacc)?;
+        let Some(path) = this.resolve_path("`stat`", &path, /* write */ false)? else {
             return Ok(Scalar::from_i32(-1));
-        }
+        };
 
         // `stat` always follows symlinks.
         let metadata = match FileMetadata::from_path(this, &path, true)? {
@@ -519,14 +695,9 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
         let path_scalar = this.read_pointer(path_op)?;
         let path = this.read_path_from_c_str(path_scalar)?.into_owned();
-
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
-            this.reject_in_isolation("`lstat`", reject_with)?;
-            let eacc = this.eval_libc("EACCES");
-            this.set_last_error(eacc)?;
+        let Some(path) = this.resolve_path("`lstat`", &path, /* write */ false)? else {
             return Ok(Scalar::from_i32(-1));
-        }
+        };
 
         let metadata = match FileMetadata::from_path(this, &path, false)? {
             Some(metadata) => metadata,
@@ -549,8 +720,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
         let fd = this.read_scalar(fd_op)?.to_i32()?;
 
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+        // Reject if isolation is enabled, unless this is a file we opened.
+        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op
+            && !this.is_file(fd)
+        {
             this.reject_in_isolation("`fstat`", reject_with)?;
             // Set error code as "EBADF" (bad fd)
             return Ok(Scalar::from_i32(this.fd_not_found()?));
@@ -612,24 +785,6 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             )
         }
 
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
-            this.reject_in_isolation("`statx`", reject_with)?;
-            let ecode = if path.is_absolute() || dirfd == this.eval_libc_i32("AT_FDCWD") {
-                // since `path` is provided, either absolute or
-                // relative to CWD, `EACCES` is the most relevant.
-                this.eval_libc("EACCES")
-            } else {
-                // `dirfd` is set to target file, and `path` is empty
-                // (or we would have hit the `throw_unsup_format`
-                // above). `EACCES` would violate the spec.
-                assert!(empty_path_flag);
-                this.eval_libc("EBADF")
-            };
-            this.set_last_error(ecode)?;
-            return Ok(-1);
-        }
-
         // the `_mask_op` parameter specifies the file information that the caller requested.
         // However `statx` is allowed to return information that was not requested or to not
         // return information that was requested. This `mask` represents the information we can
@@ -643,8 +798,21 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         // If the path is empty, and the AT_EMPTY_PATH flag is set, we query the open file
         // represented by dirfd, whether it's a directory or otherwise.
         let metadata = if path.as_os_str().is_empty() && empty_path_flag {
+            // Reject if isolation is enabled, unless this is a file we opened.
+            if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op
+                && !this.is_file(dirfd)
+            {
+                this.reject_in_isolation("`statx`", reject_with)?;
+                // `EACCES` would violate the spec for a `dirfd` with an empty `path`.
+                let ebadf = this.eval_libc("EBADF");
+                this.set_last_error(ebadf)?;
+                return Ok(-1);
+            }
             FileMetadata::from_fd(this, dirfd)?
         } else {
+            let Some(path) = this.resolve_path("`statx`", &path, /* write */ false)? else {
+                return Ok(-1);
+            };
             FileMetadata::from_path(this, &path, follow_symlink)?
         };
         let metadata = match metadata {
@@ -761,17 +929,22 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             return Ok(-1);
         }
 
-        let oldpath = this.read_path_from_c_str(oldpath_ptr)?;
-        let newpath = this.read_path_from_c_str(newpath_ptr)?;
-
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
-            this.reject_in_isolation("`rename`", reject_with)?;
-            this.set_last_error_from_io_error(ErrorKind::PermissionDenied.into())?;
+        let oldpath = this.read_path_from_c_str(oldpath_ptr)?.into_owned();
+        let newpath = this.read_path_from_c_str(newpath_ptr)?.into_owned();
+        let Some(oldpath) = this.resolve_path("`rename`", &oldpath, /* write */ true)? else {
             return Ok(-1);
-        }
+        };
+        let Some(newpath) = this.resolve_path("`rename`", &newpath, /* write */ true)? else {
+            return Ok(-1);
+        };
 
-        let result = rename(oldpath, newpath).map(|_| 0);
+        let result = match (oldpath, newpath) {
+            (FsPath::Host(oldpath), FsPath::Host(newpath)) => rename(oldpath, newpath),
+            (FsPath::Virtual(oldpath), FsPath::Virtual(newpath)) =>
+                this.vfs().rename(&oldpath, &newpath),
+            _ => Err(ErrorKind::CrossesDevices.into()),
+        }
+        .map(|_| 0);
 
         this.try_unwrap_io_result(result)
     }
@@ -786,14 +959,17 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             this.read_scalar(mode_op)?.to_u32()?
         };
 
-        let path = this.read_path_from_c_str(this.read_pointer(path_op)?)?;
-
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
-            this.reject_in_isolation("`mkdir`", reject_with)?;
-            this.set_last_error_from_io_error(ErrorKind::PermissionDenied.into())?;
+        let path = this.read_path_from_c_str(this.read_pointer(path_op)?)?.into_owned();
+        let Some(path) = this.resolve_path("`mkdir`", &path, /* write */ true)? else {
             return Ok(-1);
-        }
+        };
+        let path = match path {
+            FsPath::Host(path) => path,
+            FsPath::Virtual(path) => {
+                let result = this.vfs().mkdir(&path).map(|_| 0i32);
+                return this.try_unwrap_io_result(result);
+            }
+        };
 
         #[cfg_attr(not(unix), allow(unused_mut))]
         let mut builder = DirBuilder::new();
@@ -814,16 +990,16 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     fn rmdir(&mut self, path_op: &OpTy<'tcx>) -> InterpResult<'tcx, i32> {
         let this = self.eval_context_mut();
 
-        let path = this.read_path_from_c_str(this.read_pointer(path_op)?)?;
-
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
-            this.reject_in_isolation("`rmdir`", reject_with)?;
-            this.set_last_error_from_io_error(ErrorKind::PermissionDenied.into())?;
+        let path = this.read_path_from_c_str(this.read_pointer(path_op)?)?.into_owned();
+        let Some(path) = this.resolve_path("`rmdir`", &path, /* write */ true)? else {
             return Ok(-1);
-        }
+        };
 
-        let result = remove_dir(path).map(|_| 0i32);
+        let result = match path {
+            FsPath::Host(path) => remove_dir(path),
+            FsPath::Virtual(path) => this.vfs().rmdir(&path),
+        }
+        .map(|_| 0i32);
 
         this.try_unwrap_io_result(result)
     }
@@ -831,21 +1007,20 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     fn opendir(&mut self, name_op: &OpTy<'tcx>) -> InterpResult<'tcx, Scalar> {
         let this = self.eval_context_mut();
 
-        let name = this.read_path_from_c_str(this.read_pointer(name_op)?)?;
-
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
-            this.reject_in_isolation("`opendir`", reject_with)?;
-            let eacc = this.eval_libc("EACCES");
-            this.set_last_error(eacc)?;
+        let name = this.read_path_from_c_str(this.read_pointer(name_op)?)?.into_owned();
+        let Some(name) = this.resolve_path("`opendir`", &name, /* write */ false)? else {
             return Ok(Scalar::null_ptr(this));
-        }
+        };
 
-        let result = read_dir(name);
+        let result = match name {
+            FsPath::Host(name) => read_dir(name).map(DirEntries::Host),
+            FsPath::Virtual(name) =>
+                this.vfs().read_dir(&name).map(|entries| DirEntries::Virtual(entries.into_iter())),
+        };
 
         match result {
-            Ok(dir_iter) => {
-                let id = this.machine.dirs.insert_new(dir_iter);
+            Ok(entries) => {
+                let id = this.machine.dirs.insert_new(entries);
 
                 // The libc API for opendir says that this method returns a pointer to an opaque
                 // structure, but we are returning an ID number. Thus, pass it as a scalar of
@@ -866,20 +1041,18 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
         let dirp = this.read_target_usize(dirp_op)?;
 
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+        // Reject if isolation is enabled, unless this is a directory we opened.
+        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op
+            && !this.machine.dirs.streams.contains_key(&dirp)
+        {
             this.reject_in_isolation("`readdir`", reject_with)?;
             let eacc = this.eval_libc("EBADF");
             this.set_last_error(eacc)?;
             return Ok(Scalar::null_ptr(this));
         }
 
-        let open_dir = this.machine.dirs.streams.get_mut(&dirp).ok_or_else(|| {
-            err_unsup_format!("the DIR pointer passed to readdir64 did not come from opendir")
-        })?;
-
-        let entry = match open_dir.read_dir.next() {
-            Some(Ok(dir_entry)) => {
+        let entry = match this.next_dir_entry(dirp, "readdir64")? {
+            Some(Ok((mut name, ino, file_type))) => {
                 // Write the directory entry into a newly allocated buffer.
                 // The name is written with write_bytes, while the rest of the
                 // dirent64 struct is written using write_int_fields.
@@ -893,7 +1066,6 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 //     pub d_name: [c_char; 256],
                 // }
 
-                let mut name = dir_entry.file_name(); // not a Path as there are no separators!
                 name.push("\0"); // Add a NUL terminator
                 let name_bytes = name.as_encoded_bytes();
                 let name_len = u64::try_from(name_bytes.len()).unwrap();
@@ -909,15 +1081,6 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 )?;
                 let entry: Pointer = entry.into();
 
-                // If the host is a Unix system, fill in the inode number with its real value.
-                // If not, use 0 as a fallback value.
-                #[cfg(unix)]
-                let ino = std::os::unix::fs::DirEntryExt::ino(&dir_entry);
-                #[cfg(not(unix))]
-                let ino = 0u64;
-
-                let file_type = this.file_type_to_d_type(dir_entry.file_type())?;
-
                 this.write_int_fields_named(
                     &[
                         ("d_ino", ino.into()),
@@ -966,18 +1129,17 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
         let dirp = this.read_target_usize(dirp_op)?;
 
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+        // Reject if isolation is enabled, unless this is a directory we opened.
+        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op
+            && !this.machine.dirs.streams.contains_key(&dirp)
+        {
             this.reject_in_isolation("`readdir_r`", reject_with)?;
             // Set error code as "EBADF" (bad fd)
             return Ok(Scalar::from_i32(this.fd_not_found()?));
         }
 
-        let open_dir = this.machine.dirs.streams.get_mut(&dirp).ok_or_else(|| {
-            err_unsup_format!("the DIR pointer passed to readdir_r did not come from opendir")
-        })?;
-        Ok(Scalar::from_i32(match open_dir.read_dir.next() {
-            Some(Ok(dir_entry)) => {
+        Ok(Scalar::from_i32(match this.next_dir_entry(dirp, "readdir_r")? {
+            Some(Ok((file_name, ino, file_type))) => {
                 // Write into entry, write pointer to result, return 0 on success.
                 // The name is written with write_os_str_to_c_str, while the rest of the
                 // dirent struct is written using write_int_fields.
@@ -995,7 +1157,6 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let entry_place = this.deref_pointer_as(entry_op, this.libc_ty_layout("dirent"))?;
                 let name_place = this.project_field_named(&entry_place, "d_name")?;
 
-                let file_name = dir_entry.file_name(); // not a Path as there are no separators!
                 let (name_fits, file_name_buf_len) = this.write_os_str_to_c_str(
                     &file_name,
                     name_place.ptr(),
@@ -1008,15 +1169,6 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     );
                 }
 
-                // If the host is a Unix system, fill in the inode number with its real value.
-                // If not, use 0 as a fallback value.
-                #[cfg(unix)]
-                let ino = std::os::unix::fs::DirEntryExt::ino(&dir_entry);
-                #[cfg(not(unix))]
-                let ino = 0u64;
-
-                let file_type = this.file_type_to_d_type(dir_entry.file_type())?;
-
                 // Common fields.
                 this.write_int_fields_named(
                     &[
@@ -1080,8 +1232,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
         let dirp = this.read_target_usize(dirp_op)?;
 
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+        // Reject if isolation is enabled, unless this is a directory we opened.
+        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op
+            && !this.machine.dirs.streams.contains_key(&dirp)
+        {
             this.reject_in_isolation("`closedir`", reject_with)?;
             // Set error code as "EBADF" (bad fd)
             return this.fd_not_found();
@@ -1101,8 +1255,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     fn ftruncate64(&mut self, fd: i32, length: i128) -> InterpResult<'tcx, Scalar> {
         let this = self.eval_context_mut();
 
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+        // Reject if isolation is enabled, unless this is a file we opened.
+        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op
+            && !this.is_file(fd)
+        {
             this.reject_in_isolation("`ftruncate64`", reject_with)?;
             // Set error code as "EBADF" (bad fd)
             return Ok(Scalar::from_i32(this.fd_not_found()?));
@@ -1112,6 +1268,26 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             return Ok(Scalar::from_i32(this.fd_not_found()?));
         };
 
+        if let Some(VirtualFile { contents, writable, .. }) =
+            file_descriptor.downcast_ref::<VirtualFile>()
+        {
+            let length = if *writable { usize::try_from(length).ok() } else { None };
+            if let Some(length) = length {
+                if length > vfs::MAX_FILE_SIZE {
+                    drop(file_descriptor);
+                    let efbig = this.eval_libc("EFBIG");
+                    this.set_last_error(efbig)?;
+                    return Ok(Scalar::from_i32(-1));
+                }
+                contents.borrow_mut().resize(length, 0);
+                return Ok(Scalar::from_i32(0));
+            }
+            drop(file_descriptor);
+            let einval = this.eval_libc("EINVAL");
+            this.set_last_error(einval)?;
+            return Ok(Scalar::from_i32(-1));
+        }
+
         // FIXME: Support ftruncate64 for all FDs
         let FileHandle { file, writable } =
             file_descriptor.downcast_ref::<FileHandle>().ok_or_else(|| {
@@ -1149,8 +1325,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
         let fd = this.read_scalar(fd_op)?.to_i32()?;
 
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+        // Reject if isolation is enabled, unless this is a file we opened.
+        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op
+            && !this.is_file(fd)
+        {
             this.reject_in_isolation("`fsync`", reject_with)?;
             // Set error code as "EBADF" (bad fd)
             return this.fd_not_found();
@@ -1164,6 +1342,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let Some(file_descriptor) = this.machine.fds.get(fd) else {
             return Ok(this.fd_not_found()?);
         };
+        // Files in the virtual file system are always in sync.
+        if file_descriptor.downcast_ref::<VirtualFile>().is_some() {
+            return Ok(0);
+        }
         // Only regular files support synchronization.
         let FileHandle { file, writable } =
             file_descriptor.downcast_ref::<FileHandle>().ok_or_else(|| {
@@ -1179,8 +1361,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
         let fd = this.read_scalar(fd_op)?.to_i32()?;
 
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+        // Reject if isolation is enabled, unless this is a file we opened.
+        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op
+            && !this.is_file(fd)
+        {
             this.reject_in_isolation("`fdatasync`", reject_with)?;
             // Set error code as "EBADF" (bad fd)
             return this.fd_not_found();
@@ -1189,6 +1373,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let Some(file_descriptor) = this.machine.fds.get(fd) else {
             return Ok(this.fd_not_found()?);
         };
+        // Files in the virtual file system are always in sync.
+        if file_descriptor.downcast_ref::<VirtualFile>().is_some() {
+            return Ok(0);
+        }
         // Only regular files support synchronization.
         let FileHandle { file, writable } =
             file_descriptor.downcast_ref::<FileHandle>().ok_or_else(|| {
@@ -1227,8 +1415,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             return Ok(Scalar::from_i32(-1));
         }
 
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+        // Reject if isolation is enabled, unless this is a file we opened.
+        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op
+            && !this.is_file(fd)
+        {
             this.reject_in_isolation("`sync_file_range`", reject_with)?;
             // Set error code as "EBADF" (bad fd)
             return Ok(Scalar::from_i32(this.fd_not_found()?));
@@ -1237,6 +1427,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let Some(file_descriptor) = this.machine.fds.get(fd) else {
             return Ok(Scalar::from_i32(this.fd_not_found()?));
         };
+        // Files in the virtual file system are always in sync.
+        if file_descriptor.downcast_ref::<VirtualFile>().is_some() {
+            return Ok(Scalar::from_i32(0));
+        }
         // Only regular files support synchronization.
         let FileHandle { file, writable } =
             file_descriptor.downcast_ref::<FileHandle>().ok_or_else(|| {
@@ -1257,19 +1451,25 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     ) -> InterpResult<'tcx, i64> {
         let this = self.eval_context_mut();
 
-        let pathname = this.read_path_from_c_str(this.read_pointer(pathname_op)?)?;
+        let pathname = this.read_path_from_c_str(this.read_pointer(pathname_op)?)?.into_owned();
         let buf = this.read_pointer(buf_op)?;
         let bufsize = this.read_target_usize(bufsize_op)?;
 
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
-            this.reject_in_isolation("`readlink`", reject_with)?;
-            let eacc = this.eval_libc("EACCES");
-            this.set_last_error(eacc)?;
+        let Some(pathname) = this.resolve_path("`readlink`", &pathname, /* write */ false)? else {
             return Ok(-1);
-        }
+        };
 
-        let result = std::fs::read_link(pathname);
+        let result = match pathname {
+            FsPath::Host(pathname) =>
+                std::fs::read_link(pathname).map(|target| {
+                    // Turn absolute targets in mapped directories back into paths the program
+                    // understands.
+                    if target.is_absolute() { this.guest_path(target) } else { target }
+                }),
+            // The virtual file system does not support symbolic links, so this is never one.
+            FsPath::Virtual(pathname) =>
+                this.vfs().get(&pathname).and_then(|_| Err(ErrorKind::InvalidInput.into())),
+        };
         match result {
             Ok(resolved) => {
                 // 'readlink' truncates the resolved path if the provided buffer is not large
@@ -1321,18 +1521,18 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let this = self.eval_context_mut();
         this.assert_target_os_is_unix("realpath");
 
-        let pathname = this.read_path_from_c_str(this.read_pointer(path_op)?)?;
+        let pathname = this.read_path_from_c_str(this.read_pointer(path_op)?)?.into_owned();
         let processed_ptr = this.read_pointer(processed_path_op)?;
 
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
-            this.reject_in_isolation("`realpath`", reject_with)?;
-            let eacc = this.eval_libc("EACCES");
-            this.set_last_error(eacc)?;
+        let Some(pathname) = this.resolve_path("`realpath`", &pathname, /* write */ false)? else {
             return Ok(Scalar::from_target_usize(0, this));
-        }
+        };
 
-        let result = std::fs::canonicalize(pathname);
+        let result = match pathname {
+            FsPath::Host(pathname) =>
+                std::fs::canonicalize(pathname).map(|resolved| this.guest_path(resolved)),
+            FsPath::Virtual(pathname) => this.vfs().get(&pathname).map(|_| pathname),
+        };
         match result {
             Ok(resolved) => {
                 let path_max = this
@@ -1399,8 +1599,11 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let mut template = this.eval_context_ref().read_c_str(template_ptr)?.to_owned();
         let template_bytes = template.as_mut_slice();
 
-        // Reject if isolation is enabled.
-        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
+        // Reject if isolation is enabled, unless we can use the virtual file system.
+        let use_vfs = !this.machine.communicate() && this.machine.vfs.is_some();
+        if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op
+            && !use_vfs
+        {
             this.reject_in_isolation("`mkstemp`", reject_with)?;
             let eacc = this.eval_libc("EACCES");
             this.set_last_error(eacc)?;
@@ -1460,6 +1663,9 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
             // Generate a random unique suffix.
             let unique_suffix = SUBSTITUTIONS.choose_multiple(rng, 6).collect::<String>();
+            this.machine.log_nondeterminism("mkstemp", || {
+                format!("picked file name suffix `{unique_suffix}`")
+            });
 
             // Replace the template string with the random string.
             template_bytes[start_pos..end_pos].copy_from_slice(unique_suffix.as_bytes());
@@ -1470,15 +1676,21 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             // To actually open the file, turn this into a host OsString.
             let p = bytes_to_os_str(template_bytes)?.to_os_string();
 
-            let possibly_unique = std::env::temp_dir().join::<PathBuf>(p.into());
-
-            let file = fopts.open(possibly_unique);
+            let file = if use_vfs {
+                let possibly_unique = VirtualFs::normalize(Path::new(&p));
+                this.vfs()
+                    .open(&possibly_unique, true, true, false)
+                    .map(|contents| FileDescriptor::new(VirtualFile::new(contents, true, false)))
+            } else {
+                let possibly_unique = std::env::temp_dir().join::<PathBuf>(p.into());
+                fopts
+                    .open(possibly_unique)
+                    .map(|f| FileDescriptor::new(FileHandle { file: f, writable: true }))
+            };
 
             match file {
-                Ok(f) => {
-                    let fh = &mut this.machine.fds;
-                    let fd =
-                        fh.insert_fd(FileDescriptor::new(FileHandle { file: f, writable: true }));
+                Ok(file_descriptor) => {
+                    let fd = this.machine.fds.insert_fd(file_descriptor);
                     return Ok(fd);
                 }
                 Err(e) =>
@@ -1530,15 +1742,47 @@ struct FileMetadata {
 impl FileMetadata {
     fn from_path<'tcx>(
         ecx: &mut MiriInterpCx<'tcx>,
-        path: &Path,
+        path: &FsPath,
         follow_symlink: bool,
     ) -> InterpResult<'tcx, Option<FileMetadata>> {
+        let path = match path {
+            FsPath::Host(path) => path,
+            FsPath::Virtual(path) => {
+                let node = match ecx.vfs().get(path) {
+                    Ok(node) => node.clone(),
+                    Err(e) => {
+                        ecx.set_last_error_from_io_error(e)?;
+                        return Ok(None);
+                    }
+                };
+                return FileMetadata::from_vfs(ecx, &node).map(Some);
+            }
+        };
         let metadata =
             if follow_symlink { std::fs::metadata(path) } else { std::fs::symlink_metadata(path) };
 
         FileMetadata::from_meta(ecx, metadata)
     }
 
+    /// The metadata of a file or directory in the virtual file system. To keep things
+    /// deterministic, all timestamps are the Unix epoch.
+    fn from_vfs<'tcx>(
+        ecx: &mut MiriInterpCx<'tcx>,
+        node: &vfs::Node,
+    ) -> InterpResult<'tcx, FileMetadata> {
+        let (mode_name, size) = match node {
+            vfs::Node::File(contents) => ("S_IFREG", contents.borrow().len()),
+            vfs::Node::Dir => ("S_IFDIR", 0),
+        };
+        Ok(FileMetadata {
+            mode: ecx.eval_libc(mode_name),
+            size: size.try_into().unwrap(),
+            created: Some((0, 0)),
+            accessed: Some((0, 0)),
+            modified: Some((0, 0)),
+        })
+    }
+
     fn from_fd<'tcx>(
         ecx: &mut MiriInterpCx<'tcx>,
         fd: i32,
@@ -1547,6 +1791,12 @@ impl FileMetadata {
             return ecx.fd_not_found().map(|_: i32| None);
         };
 
+        if let Some(file) = file_descriptor.downcast_ref::<VirtualFile>() {
+            let node = vfs::Node::File(file.contents.clone());
+            drop(file_descriptor);
+            return FileMetadata::from_vfs(ecx, &node).map(Some);
+        }
+
         let file = &file_descriptor
             .downcast_ref::<FileHandle>()
             .ok_or_else(|| {
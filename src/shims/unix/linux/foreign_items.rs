@@ -97,48 +97,35 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
             // Dynamically invoked syscalls
             "syscall" => {
-                // We do not use `check_shim` here because `syscall` is variadic. The argument
-                // count is checked bellow.
-                this.check_abi_and_shim_symbol_clash(abi, Abi::C { unwind: false }, link_name)?;
                 // The syscall variadic function is legal to call with more arguments than needed,
-                // extra arguments are simply ignored. The important check is that when we use an
-                // argument, we have to also check all arguments *before* it to ensure that they
-                // have the right type.
+                // extra arguments are simply ignored. Which arguments we read depends on the
+                // syscall.
+                let ([id], mut varargs) =
+                    this.check_shim_variadic(abi, Abi::C { unwind: false }, link_name, args)?;
 
                 let sys_getrandom = this.eval_libc("SYS_getrandom").to_target_usize(this)?;
                 let sys_futex = this.eval_libc("SYS_futex").to_target_usize(this)?;
 
-                if args.is_empty() {
-                    throw_ub_format!(
-                        "incorrect number of arguments for syscall: got 0, expected at least 1"
-                    );
-                }
-                match this.read_target_usize(&args[0])? {
+                match this.read_target_usize(id)? {
                     // `libc::syscall(NR_GETRANDOM, buf.as_mut_ptr(), buf.len(), GRND_NONBLOCK)`
                     // is called if a `HashMap` is created the regular way (e.g. HashMap<K, V>).
                     id if id == sys_getrandom => {
                         // Used by getrandom 0.1
-                        // The first argument is the syscall id, so skip over it.
-                        if args.len() < 4 {
-                            throw_ub_format!(
-                                "incorrect number of arguments for `getrandom` syscall: got {}, expected at least 4",
-                                args.len()
-                            );
-                        }
-
-                        let ptr = this.read_pointer(&args[1])?;
-                        let len = this.read_target_usize(&args[2])?;
+                        let ptr = varargs.next(this, this.machine.layouts.mut_raw_ptr)?;
+                        let ptr = ptr.to_pointer(this)?;
+                        let len = varargs.next(this, this.machine.layouts.usize)?;
+                        let len = len.to_target_usize(this)?;
                         // The only supported flags are GRND_RANDOM and GRND_NONBLOCK,
                         // neither of which have any effect on our current PRNG.
                         // See <https://github.com/rust-lang/rust/pull/79196> for a discussion of argument sizes.
-                        let _flags = this.read_scalar(&args[3])?.to_i32();
+                        let _flags = varargs.next(this, this.machine.layouts.i32)?;
 
                         this.gen_random(ptr, len)?;
                         this.write_scalar(Scalar::from_target_usize(len, this), dest)?;
                     }
                     // `futex` is used by some synchronization primitives.
                     id if id == sys_futex => {
-                        futex(this, &args[1..], dest)?;
+                        futex(this, varargs, dest)?;
                     }
                     id => {
                         this.handle_unsupported_foreign_item(format!(
@@ -1,6 +1,7 @@
 //! This follows the pattern in src/shims/unix/mem.rs: We only support uses of mremap that would
 //! correspond to valid uses of realloc.
 
+use crate::shims::alloc::EvalContextExt as _;
 use crate::*;
 use rustc_target::abi::Size;
 
@@ -50,13 +51,12 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             MiriMemoryKind::Mmap.into(),
         )?;
         if let Some(increase) = new_size.checked_sub(old_size) {
-            // We just allocated this, the access is definitely in-bounds and fits into our address space.
-            // mmap guarantees new mappings are zero-init.
-            this.write_bytes_ptr(
+            // mmap guarantees new mappings are zero-init. The part beyond the old size was not
+            // copied to, so it is still fresh.
+            this.mark_fresh_alloc_zeroed(
                 ptr.offset(Size::from_bytes(old_size), this).unwrap().into(),
-                std::iter::repeat(0u8).take(usize::try_from(increase).unwrap()),
-            )
-            .unwrap();
+                Size::from_bytes(increase),
+            )?;
         }
 
         Ok(Scalar::from_pointer(ptr, this))
@@ -1,10 +1,10 @@
 use crate::*;
 
 /// Implementation of the SYS_futex syscall.
-/// `args` is the arguments *after* the syscall number.
+/// `varargs` are the arguments *after* the syscall number.
 pub fn futex<'tcx>(
     this: &mut MiriInterpCx<'tcx>,
-    args: &[OpTy<'tcx>],
+    mut varargs: VarArgs<'_, 'tcx>,
     dest: &MPlaceTy<'tcx>,
 ) -> InterpResult<'tcx> {
     // The amount of arguments used depends on the type of futex operation.
@@ -12,22 +12,14 @@ pub fn futex<'tcx>(
     // number), which is also the maximum amount of arguments a linux syscall
     // can take on most architectures.
     // However, not all futex operations use all six arguments. The unused ones
-    // may or may not be left out from the `syscall()` call.
-    // Therefore we don't use `check_arg_count` here, but only check for the
-    // number of arguments to fall within a range.
-    if args.len() < 3 {
-        throw_ub_format!(
-            "incorrect number of arguments for `futex` syscall: got {}, expected at least 3",
-            args.len()
-        );
-    }
+    // may or may not be left out from the `syscall()` call, so we only read
+    // the arguments that the operation needs.
 
     // The first three arguments (after the syscall number itself) are the same to all futex operations:
     //     (int *addr, int op, int val).
-    // We checked above that these definitely exist.
-    let addr = this.read_pointer(&args[0])?;
-    let op = this.read_scalar(&args[1])?.to_i32()?;
-    let val = this.read_scalar(&args[2])?.to_i32()?;
+    let addr = varargs.next(this, this.machine.layouts.mut_raw_ptr)?.to_pointer(this)?;
+    let op = varargs.next(this, this.machine.layouts.i32)?.to_i32()?;
+    let val = varargs.next(this, this.machine.layouts.i32)?.to_i32()?;
 
     // This is a vararg function so we have to bring our own type for this pointer.
     let addr = this.ptr_to_mplace(addr, this.machine.layouts.i32);
@@ -54,23 +46,11 @@ pub fn futex<'tcx>(
         op if op & !futex_realtime == futex_wait || op & !futex_realtime == futex_wait_bitset => {
             let wait_bitset = op & !futex_realtime == futex_wait_bitset;
 
+            let timeout = varargs.next_op(this.machine.layouts.mut_raw_ptr)?;
             let bitset = if wait_bitset {
-                if args.len() < 6 {
-                    throw_ub_format!(
-                        "incorrect number of arguments for `futex` syscall with `op=FUTEX_WAIT_BITSET`: got {}, expected at least 6",
-                        args.len()
-                    );
-                }
-                let _timeout = this.read_pointer(&args[3])?;
-                let _uaddr2 = this.read_pointer(&args[4])?;
-                this.read_scalar(&args[5])?.to_u32()?
+                let _uaddr2 = varargs.next(this, this.machine.layouts.mut_raw_ptr)?;
+                varargs.next(this, this.machine.layouts.u32)?.to_u32()?
             } else {
-                if args.len() < 4 {
-                    throw_ub_format!(
-                        "incorrect number of arguments for `futex` syscall with `op=FUTEX_WAIT`: got {}, expected at least 4",
-                        args.len()
-                    );
-                }
                 u32::MAX
             };
 
@@ -81,7 +61,7 @@ pub fn futex<'tcx>(
                 return Ok(());
             }
 
-            let timeout = this.deref_pointer_as(&args[3], this.libc_ty_layout("timespec"))?;
+            let timeout = this.deref_pointer_as(timeout, this.libc_ty_layout("timespec"))?;
             let timeout = if this.ptr_is_null(timeout.ptr())? {
                 None
             } else {
@@ -168,10 +148,11 @@ pub fn futex<'tcx>(
                     Scalar::from_target_isize(-1, this), // retval_timeout
                     dest.clone(),
                     this.eval_libc("ETIMEDOUT"),
-                );
+                )?;
             } else {
                 // The futex value doesn't match the expected value, so we return failure
                 // right away without sleeping: -1 and errno set to EAGAIN.
+                this.futex_acquired_without_waiting(addr_usize);
                 let eagain = this.eval_libc("EAGAIN");
                 this.set_last_error(eagain)?;
                 this.write_scalar(Scalar::from_target_isize(-1, this), dest)?;
@@ -185,15 +166,9 @@ pub fn futex<'tcx>(
         // Same as FUTEX_WAKE, but allows you to specify a bitset to select which threads to wake up.
         op if op == futex_wake || op == futex_wake_bitset => {
             let bitset = if op == futex_wake_bitset {
-                if args.len() < 6 {
-                    throw_ub_format!(
-                        "incorrect number of arguments for `futex` syscall with `op=FUTEX_WAKE_BITSET`: got {}, expected at least 6",
-                        args.len()
-                    );
-                }
-                let _timeout = this.read_pointer(&args[3])?;
-                let _uaddr2 = this.read_pointer(&args[4])?;
-                this.read_scalar(&args[5])?.to_u32()?
+                let _timeout = varargs.next(this, this.machine.layouts.mut_raw_ptr)?;
+                let _uaddr2 = varargs.next(this, this.machine.layouts.mut_raw_ptr)?;
+                varargs.next(this, this.machine.layouts.u32)?.to_u32()?
             } else {
                 u32::MAX
             };
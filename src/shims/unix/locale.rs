@@ -0,0 +1,327 @@
+//! Minimal locale and `iconv` support. Miri only knows the "C" locale with UTF-8 as its character
+//! encoding: the locale functions just record which locale the program asked for, and `iconv` can
+//! only convert from UTF-8 to UTF-8.
+
+use std::ffi::OsString;
+
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
+use rustc_target::abi::Size;
+
+use crate::*;
+
+#[derive(Debug)]
+pub struct LocaleState {
+    /// The name of the global locale, as set by `setlocale`.
+    global: String,
+    /// Locale objects created by `newlocale`, with the name of the locale they represent.
+    objects: FxHashMap<u64, String>,
+    /// The locale object installed by `uselocale` for each thread. Threads that are not in this
+    /// map use the global locale.
+    thread_locales: FxHashMap<ThreadId, u64>,
+    /// The open `iconv` conversion descriptors.
+    iconv_descriptors: FxHashSet<u64>,
+    /// ID number to be used by the next locale object or conversion descriptor.
+    next_id: u64,
+    /// The strings returned by `setlocale` and `nl_langinfo`. The program must not modify them,
+    /// so each one is allocated only once.
+    strings: FxHashMap<String, Pointer>,
+}
+
+impl Default for LocaleState {
+    fn default() -> Self {
+        LocaleState {
+            global: "C".to_owned(),
+            objects: FxHashMap::default(),
+            thread_locales: FxHashMap::default(),
+            iconv_descriptors: FxHashSet::default(),
+            // Skip 0 as an ID, because it looks like a null pointer to libc.
+            next_id: 1,
+            strings: FxHashMap::default(),
+        }
+    }
+}
+
+impl LocaleState {
+    #[allow(clippy::arithmetic_side_effects)]
+    fn new_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+}
+
+impl VisitProvenance for LocaleState {
+    fn visit_provenance(&self, visit: &mut VisitWith<'_>) {
+        let LocaleState {
+            global: _,
+            objects: _,
+            thread_locales: _,
+            iconv_descriptors: _,
+            next_id: _,
+            strings,
+        } = self;
+
+        for ptr in strings.values() {
+            ptr.visit_provenance(visit);
+        }
+    }
+}
+
+/// Resolves the name of a locale that the program asked for. Returns `None` if we do not support
+/// that locale.
+fn resolve_locale_name(name: &str) -> Option<&str> {
+    match name {
+        // The empty name means "use the environment", which we do not look at.
+        "" => Some("C"),
+        "C" | "POSIX" => Some(name),
+        // Any locale using UTF-8 behaves like "C" for our purposes.
+        _ if is_utf8_codeset(name.split_once('.')?.1.split('@').next().unwrap()) => Some(name),
+        _ => None,
+    }
+}
+
+/// Whether this is a name of the UTF-8 character encoding, as used in locale names and `iconv`.
+fn is_utf8_codeset(name: &str) -> bool {
+    name.eq_ignore_ascii_case("UTF-8") || name.eq_ignore_ascii_case("UTF8")
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Returns a pointer to a null-terminated copy of `s` that lives until the end of the program.
+    fn locale_str(&mut self, s: &str) -> InterpResult<'tcx, Pointer> {
+        let this = self.eval_context_mut();
+        if let Some(&ptr) = this.machine.locale.strings.get(s) {
+            return Ok(ptr);
+        }
+        let ptr = this.alloc_os_str_as_c_str(&OsString::from(s), MiriMemoryKind::Machine.into())?;
+        this.machine.locale.strings.insert(s.to_owned(), ptr);
+        Ok(ptr)
+    }
+
+    fn setlocale(
+        &mut self,
+        category_op: &OpTy<'tcx>,
+        locale_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        // We do not distinguish categories: all of them always use the same locale.
+        let _category = this.read_scalar(category_op)?.to_i32()?;
+        let locale = this.read_pointer(locale_op)?;
+
+        if !this.ptr_is_null(locale)? {
+            let name = this.read_c_str(locale)?;
+            let Some(name) = std::str::from_utf8(name).ok().and_then(resolve_locale_name) else {
+                // The locale is not available. This does not set `errno`.
+                return Ok(Scalar::null_ptr(this));
+            };
+            this.machine.locale.global = name.to_owned();
+        }
+
+        let name = this.machine.locale.global.clone();
+        Ok(Scalar::from_pointer(this.locale_str(&name)?, this))
+    }
+
+    fn newlocale(
+        &mut self,
+        category_mask_op: &OpTy<'tcx>,
+        locale_op: &OpTy<'tcx>,
+        base_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        let _category_mask = this.read_scalar(category_mask_op)?.to_i32()?;
+        let name = this.read_c_str(this.read_pointer(locale_op)?)?;
+        let base = this.read_target_usize(base_op)?;
+
+        let Some(name) = std::str::from_utf8(name).ok().and_then(resolve_locale_name) else {
+            let enoent = this.eval_libc("ENOENT");
+            this.set_last_error(enoent)?;
+            return Ok(Scalar::null_ptr(this));
+        };
+        let name = name.to_owned();
+
+        // A non-null `base` is consumed: it is modified and returned, or freed if this fails.
+        // We just put the new locale into the same object.
+        let id = if base == 0 {
+            this.machine.locale.new_id()
+        } else if this.machine.locale.objects.contains_key(&base) {
+            base
+        } else {
+            throw_ub_format!("`newlocale` called with an invalid `base` locale object");
+        };
+        this.machine.locale.objects.insert(id, name);
+
+        // The locale object is an opaque pointer, but we use an ID number instead.
+        Ok(Scalar::from_target_usize(id, this))
+    }
+
+    fn freelocale(&mut self, locale_op: &OpTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let locale = this.read_target_usize(locale_op)?;
+        if this.machine.locale.thread_locales.values().any(|&l| l == locale) {
+            throw_ub_format!("`freelocale` called on a locale object that is still in use");
+        }
+        if this.machine.locale.objects.remove(&locale).is_none() {
+            throw_ub_format!("`freelocale` called on an invalid locale object");
+        }
+        Ok(())
+    }
+
+    fn uselocale(&mut self, locale_op: &OpTy<'tcx>) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        let locale = this.read_target_usize(locale_op)?;
+        let thread = this.machine.threads.active_thread();
+        // `(locale_t) -1` stands for the global locale.
+        let global = this.target_usize_max();
+
+        let old = this.machine.locale.thread_locales.get(&thread).copied().unwrap_or(global);
+        if locale == global {
+            this.machine.locale.thread_locales.remove(&thread);
+        } else if locale != 0 {
+            if !this.machine.locale.objects.contains_key(&locale) {
+                throw_ub_format!("`uselocale` called with an invalid locale object");
+            }
+            this.machine.locale.thread_locales.insert(thread, locale);
+        }
+
+        Ok(Scalar::from_target_usize(old, this))
+    }
+
+    fn nl_langinfo(&mut self, item_op: &OpTy<'tcx>) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        let item = this.read_scalar(item_op)?.to_i32()?;
+        // These are the values for the "C" locale, except that we always use UTF-8.
+        let value = if item == this.eval_libc_i32("CODESET") {
+            "UTF-8"
+        } else if item == this.eval_libc_i32("RADIXCHAR") {
+            "."
+        } else {
+            // This is also what is returned for unknown items.
+            ""
+        };
+        Ok(Scalar::from_pointer(this.locale_str(value)?, this))
+    }
+
+    fn iconv_open(
+        &mut self,
+        tocode_op: &OpTy<'tcx>,
+        fromcode_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        let tocode = this.read_c_str(this.read_pointer(tocode_op)?)?.to_owned();
+        let fromcode = this.read_c_str(this.read_pointer(fromcode_op)?)?.to_owned();
+
+        // The target encoding may have suffixes like `//TRANSLIT`, which do not matter when
+        // converting UTF-8 to UTF-8.
+        let supported = |code: &[u8]| {
+            std::str::from_utf8(code).is_ok_and(|code| {
+                let code = code.split("//").next().unwrap();
+                code.is_empty() || is_utf8_codeset(code)
+            })
+        };
+        if !supported(&tocode) || !supported(&fromcode) {
+            let einval = this.eval_libc("EINVAL");
+            this.set_last_error(einval)?;
+            return Ok(Scalar::from_target_usize(this.target_usize_max(), this));
+        }
+
+        let id = this.machine.locale.new_id();
+        this.machine.locale.iconv_descriptors.insert(id);
+        // The conversion descriptor is an opaque pointer, but we use an ID number instead.
+        Ok(Scalar::from_target_usize(id, this))
+    }
+
+    fn iconv(
+        &mut self,
+        cd_op: &OpTy<'tcx>,
+        inbuf_op: &OpTy<'tcx>,
+        inbytesleft_op: &OpTy<'tcx>,
+        outbuf_op: &OpTy<'tcx>,
+        outbytesleft_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        let cd = this.read_target_usize(cd_op)?;
+        let inbuf = this.read_pointer(inbuf_op)?;
+        let inbytesleft = this.read_pointer(inbytesleft_op)?;
+        let outbuf = this.read_pointer(outbuf_op)?;
+        let outbytesleft = this.read_pointer(outbytesleft_op)?;
+
+        if !this.machine.locale.iconv_descriptors.contains(&cd) {
+            let ebadf = this.eval_libc("EBADF");
+            this.set_last_error(ebadf)?;
+            return Ok(Scalar::from_target_usize(this.target_usize_max(), this));
+        }
+
+        // A null input buffer resets the conversion state. UTF-8 has no state, so there is
+        // nothing to do.
+        if this.ptr_is_null(inbuf)? {
+            return Ok(Scalar::from_target_usize(0, this));
+        }
+        let inbuf = this.ptr_to_mplace(inbuf, this.machine.layouts.mut_raw_ptr);
+        let in_ptr = this.read_pointer(&inbuf)?;
+        if this.ptr_is_null(in_ptr)? {
+            return Ok(Scalar::from_target_usize(0, this));
+        }
+        let inbytesleft = this.ptr_to_mplace(inbytesleft, this.machine.layouts.usize);
+        let in_len = this.read_target_usize(&inbytesleft)?;
+        let outbuf = this.ptr_to_mplace(outbuf, this.machine.layouts.mut_raw_ptr);
+        let out_ptr = this.read_pointer(&outbuf)?;
+        let outbytesleft = this.ptr_to_mplace(outbytesleft, this.machine.layouts.usize);
+        let out_len = this.read_target_usize(&outbytesleft)?;
+
+        // Only complete and valid characters are converted. Figure out how many bytes that is,
+        // and what stops us from converting more.
+        let input = this.read_bytes_ptr_strip_provenance(in_ptr, Size::from_bytes(in_len))?;
+        let (valid, error) = match std::str::from_utf8(input) {
+            Ok(s) => (s, None),
+            Err(e) => {
+                let valid = std::str::from_utf8(&input[..e.valid_up_to()]).unwrap();
+                // An incomplete character at the end of the input is not an error yet, the next
+                // call may complete it.
+                (valid, Some(if e.error_len().is_some() { "EILSEQ" } else { "EINVAL" }))
+            }
+        };
+        let (len, error) = if u64::try_from(valid.len()).unwrap() > out_len {
+            let out_len = usize::try_from(out_len).unwrap();
+            let len = (0..=out_len).rev().find(|&i| valid.is_char_boundary(i)).unwrap();
+            (len, Some("E2BIG"))
+        } else {
+            (valid.len(), error)
+        };
+        let len = u64::try_from(len).unwrap();
+
+        this.mem_copy(in_ptr, out_ptr, Size::from_bytes(len), true)?;
+        this.write_pointer(in_ptr.offset(Size::from_bytes(len), this)?, &inbuf)?;
+        this.write_scalar(Scalar::from_target_usize(in_len.strict_sub(len), this), &inbytesleft)?;
+        this.write_pointer(out_ptr.offset(Size::from_bytes(len), this)?, &outbuf)?;
+        this.write_scalar(Scalar::from_target_usize(out_len.strict_sub(len), this), &outbytesleft)?;
+
+        if let Some(error) = error {
+            let error = this.eval_libc(error);
+            this.set_last_error(error)?;
+            Ok(Scalar::from_target_usize(this.target_usize_max(), this))
+        } else {
+            // There were no irreversible conversions.
+            Ok(Scalar::from_target_usize(0, this))
+        }
+    }
+
+    fn iconv_close(&mut self, cd_op: &OpTy<'tcx>) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        let cd = this.read_target_usize(cd_op)?;
+        if this.machine.locale.iconv_descriptors.remove(&cd) {
+            Ok(Scalar::from_i32(0))
+        } else {
+            let ebadf = this.eval_libc("EBADF");
+            this.set_last_error(ebadf)?;
+            Ok(Scalar::from_i32(-1))
+        }
+    }
+}
@@ -93,11 +93,17 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             // Access to command-line arguments
             "_NSGetArgc" => {
                 let [] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
-                this.write_pointer(this.machine.argc.expect("machine must be initialized"), dest)?;
+                let Some(argc) = this.machine.argc else {
+                    throw_unsup_format!("`_NSGetArgc` is not supported in `no_std` programs");
+                };
+                this.write_pointer(argc, dest)?;
             }
             "_NSGetArgv" => {
                 let [] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
-                this.write_pointer(this.machine.argv.expect("machine must be initialized"), dest)?;
+                let Some(argv) = this.machine.argv else {
+                    throw_unsup_format!("`_NSGetArgv` is not supported in `no_std` programs");
+                };
+                this.write_pointer(argv, dest)?;
             }
             "_NSGetExecutablePath" => {
                 let [buf, bufsize] =
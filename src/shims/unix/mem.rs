@@ -14,6 +14,7 @@
 //! munmap shim which would partially unmap a region of address space previously mapped by mmap will
 //! report UB.
 
+use crate::shims::alloc::EvalContextExt as _;
 use crate::*;
 use rustc_target::abi::Size;
 
@@ -33,9 +34,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         // We do not support MAP_FIXED, so the addr argument is always ignored (except for the MacOS hack)
         let addr = this.read_target_usize(addr)?;
         let length = this.read_target_usize(length)?;
-        let prot = this.read_scalar(prot)?.to_i32()?;
-        let flags = this.read_scalar(flags)?.to_i32()?;
-        let fd = this.read_scalar(fd)?.to_i32()?;
+        // `c_int` is only 16 bits wide on some targets.
+        let prot = i32::try_from(this.read_scalar(prot)?.to_int(prot.layout.size)?).unwrap();
+        let flags = i32::try_from(this.read_scalar(flags)?.to_int(flags.layout.size)?).unwrap();
+        let fd = i32::try_from(this.read_scalar(fd)?.to_int(fd.layout.size)?).unwrap();
 
         let map_private = this.eval_libc_i32("MAP_PRIVATE");
         let map_anonymous = this.eval_libc_i32("MAP_ANONYMOUS");
@@ -112,13 +114,8 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
         let ptr =
             this.allocate_ptr(Size::from_bytes(map_length), align, MiriMemoryKind::Mmap.into())?;
-        // We just allocated this, the access is definitely in-bounds and fits into our address space.
         // mmap guarantees new mappings are zero-init.
-        this.write_bytes_ptr(
-            ptr.into(),
-            std::iter::repeat(0u8).take(usize::try_from(map_length).unwrap()),
-        )
-        .unwrap();
+        this.mark_fresh_alloc_zeroed(ptr.into(), Size::from_bytes(map_length))?;
 
         Ok(Scalar::from_pointer(ptr, this))
     }
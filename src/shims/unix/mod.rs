@@ -3,10 +3,16 @@ pub mod foreign_items;
 mod env;
 mod fd;
 mod fs;
+mod locale;
 mod mem;
+mod net;
+mod printf;
+mod procfs;
+mod setjmp;
 mod socket;
 mod sync;
 mod thread;
+mod vfs;
 
 mod android;
 mod freebsd;
@@ -17,11 +23,19 @@ mod solarish;
 pub use env::UnixEnvVars;
 pub use fd::{FdTable, FileDescription};
 pub use fs::DirTable;
+pub use locale::LocaleState;
+pub use setjmp::JmpBufTable;
+pub use vfs::VirtualFs;
 // All the Unix-specific extension traits
 pub use env::EvalContextExt as _;
 pub use fd::EvalContextExt as _;
 pub use fs::EvalContextExt as _;
+pub use locale::EvalContextExt as _;
 pub use mem::EvalContextExt as _;
+pub use net::EvalContextExt as _;
+pub use printf::EvalContextExt as _;
+pub use procfs::EvalContextExt as _;
+pub use setjmp::EvalContextExt as _;
 pub use socket::EvalContextExt as _;
 pub use sync::EvalContextExt as _;
 pub use thread::EvalContextExt as _;
@@ -0,0 +1,102 @@
+//! Network interface enumeration. The program always sees a single IPv4 loopback interface, no
+//! matter what the host has, so that the result is deterministic and does not depend on isolation.
+
+use rustc_target::abi::Size;
+
+use crate::*;
+
+/// The address of the loopback interface, in network byte order.
+const LOOPBACK_ADDR: [u8; 4] = [127, 0, 0, 1];
+/// The netmask of the loopback interface, in network byte order.
+const LOOPBACK_NETMASK: [u8; 4] = [255, 0, 0, 0];
+/// The interface index of the loopback interface.
+const LOOPBACK_INDEX: u32 = 1;
+
+/// The name of the loopback interface on the given target OS.
+fn loopback_name(os: &str) -> &'static str {
+    match os {
+        "linux" | "android" => "lo",
+        _ => "lo0",
+    }
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    fn getifaddrs(&mut self, ifap_op: &OpTy<'tcx>) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        if !matches!(&*this.tcx.sess.target.os, "linux" | "android" | "macos" | "freebsd") {
+            throw_unsup_format!("`getifaddrs` is not supported on {}", this.tcx.sess.target.os);
+        }
+
+        let ifap = this.deref_pointer(ifap_op)?;
+        let ifaddrs_layout = this.libc_ty_layout("ifaddrs");
+        let sockaddr_layout = this.libc_ty_layout("sockaddr_in");
+        let name = loopback_name(&this.tcx.sess.target.os);
+
+        // Like the real implementations, we put everything into a single allocation, so that
+        // `freeifaddrs` only has to free one pointer: the `ifaddrs` struct, then the address and
+        // the netmask, then the name of the interface.
+        let addr_offset = ifaddrs_layout.size.align_to(sockaddr_layout.align.abi).bytes();
+        let netmask_offset = addr_offset.strict_add(sockaddr_layout.size.bytes());
+        let name_offset = netmask_offset.strict_add(sockaddr_layout.size.bytes());
+        let size = name_offset.strict_add(u64::try_from(name.len()).unwrap()).strict_add(1);
+        let ptr = this.malloc(size, /*zero_init:*/ true)?;
+        if this.ptr_is_null(ptr)? {
+            let enomem = this.eval_libc("ENOMEM");
+            this.set_last_error(enomem)?;
+            return Ok(-1);
+        }
+
+        let addr_ptr = ptr.offset(Size::from_bytes(addr_offset), this)?;
+        let netmask_ptr = ptr.offset(Size::from_bytes(netmask_offset), this)?;
+        let name_ptr = ptr.offset(Size::from_bytes(name_offset), this)?;
+        for (sockaddr_ptr, bytes) in [(addr_ptr, LOOPBACK_ADDR), (netmask_ptr, LOOPBACK_NETMASK)] {
+            let sockaddr = this.ptr_to_mplace(sockaddr_ptr, sockaddr_layout);
+            let family = this.project_field_named(&sockaddr, "sin_family")?;
+            this.write_int(this.eval_libc_i32("AF_INET"), &family)?;
+            // BSDs store the size of the address in the address itself.
+            if this.projectable_has_field(&sockaddr, "sin_len") {
+                let len = this.project_field_named(&sockaddr, "sin_len")?;
+                this.write_int(sockaddr_layout.size.bytes(), &len)?;
+            }
+            let sin_addr = this.project_field_named(&sockaddr, "sin_addr")?;
+            this.write_bytes_ptr(sin_addr.ptr(), bytes)?;
+        }
+        this.write_bytes_ptr(name_ptr, name.bytes())?;
+
+        // The remaining fields (`ifa_next`, the broadcast or destination address, and `ifa_data`)
+        // are null.
+        let ifaddrs = this.ptr_to_mplace(ptr, ifaddrs_layout);
+        let flags = this.eval_libc_i32("IFF_UP")
+            | this.eval_libc_i32("IFF_LOOPBACK")
+            | this.eval_libc_i32("IFF_RUNNING");
+        this.write_int_fields_named(&[("ifa_flags", flags.into())], &ifaddrs)?;
+        this.write_pointer(name_ptr, &this.project_field_named(&ifaddrs, "ifa_name")?)?;
+        this.write_pointer(addr_ptr, &this.project_field_named(&ifaddrs, "ifa_addr")?)?;
+        this.write_pointer(netmask_ptr, &this.project_field_named(&ifaddrs, "ifa_netmask")?)?;
+
+        this.write_pointer(ptr, &ifap)?;
+        Ok(0)
+    }
+
+    fn freeifaddrs(&mut self, ifa_op: &OpTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let ifa = this.read_pointer(ifa_op)?;
+        this.free(ifa)
+    }
+
+    fn if_nametoindex(&mut self, ifname_op: &OpTy<'tcx>) -> InterpResult<'tcx, u32> {
+        let this = self.eval_context_mut();
+
+        let ifname = this.read_c_str(this.read_pointer(ifname_op)?)?;
+        if ifname == loopback_name(&this.tcx.sess.target.os).as_bytes() {
+            Ok(LOOPBACK_INDEX)
+        } else {
+            let enodev = this.eval_libc("ENODEV");
+            this.set_last_error(enodev)?;
+            Ok(0)
+        }
+    }
+}
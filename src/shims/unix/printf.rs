@@ -0,0 +1,432 @@
+//! The `printf` family of functions.
+//!
+//! We interpret the format string ourselves, and support the conversions that are commonly used:
+//! integers, characters, strings, pointers and floats, with all the flags, the width and the
+//! precision. The output is written through our file descriptors, like with `write`.
+
+use std::iter;
+
+use rustc_apfloat::ieee::Double;
+use rustc_apfloat::FloatConvert;
+use rustc_middle::ty::layout::{LayoutOf, TyAndLayout};
+
+use crate::shims::x86::x87::{self, EvalContextExt as _};
+use crate::*;
+use helpers::ToHost;
+
+/// The largest number of bytes the `printf` functions can output, since they return that number as
+/// an `int`.
+const INT_MAX: usize = i32::MAX as usize;
+
+/// The flags, width and precision of a conversion specification.
+#[derive(Default)]
+struct Spec {
+    /// `-`: pad on the right instead of the left.
+    left_align: bool,
+    /// `+`: always print the sign of signed conversions.
+    plus: bool,
+    /// ` `: print a space instead of a `+` sign.
+    space: bool,
+    /// `#`: the "alternate form" (`0x` prefixes, always a decimal point, ...).
+    alternate: bool,
+    /// `0`: pad with zeros instead of spaces.
+    zero_pad: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    fn printf_to_fd(
+        &mut self,
+        fd: i32,
+        format_op: &OpTy<'tcx>,
+        varargs: VarArgs<'_, 'tcx>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let format = this.read_pointer(format_op)?;
+        let Some(output) = this.format_printf(format, varargs)? else {
+            return this.printf_overflow();
+        };
+        let written = this.write_bytes_to_fd(fd, &output)?;
+        this.printf_result(written)
+    }
+
+    fn fprintf(
+        &mut self,
+        stream_op: &OpTy<'tcx>,
+        format_op: &OpTy<'tcx>,
+        varargs: VarArgs<'_, 'tcx>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        // We do not implement `FILE`, the only streams are the standard streams, which are opaque
+        // handles pointing to their file descriptor (see `std_stream_extern_statics`).
+        let stream = this.deref_pointer_as(stream_op, this.machine.layouts.i32)?;
+        let fd = this.read_scalar(&stream)?.to_i32()?;
+        this.printf_to_fd(fd, format_op, varargs)
+    }
+
+    fn snprintf(
+        &mut self,
+        buf_op: &OpTy<'tcx>,
+        size_op: &OpTy<'tcx>,
+        format_op: &OpTy<'tcx>,
+        varargs: VarArgs<'_, 'tcx>,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+
+        let buf = this.read_pointer(buf_op)?;
+        let size = this.read_target_usize(size_op)?;
+        let format = this.read_pointer(format_op)?;
+        let Some(output) = this.format_printf(format, varargs)? else {
+            return this.printf_overflow();
+        };
+        if size > 0 {
+            // The output is truncated to fit into the buffer, together with the null terminator.
+            let len = output.len().min(usize::try_from(size.strict_sub(1)).unwrap_or(usize::MAX));
+            this.write_bytes_ptr(buf, output[..len].iter().copied().chain(iter::once(0)))?;
+        }
+        this.printf_result(output.len().try_into().unwrap())
+    }
+
+    /// The return value of the `printf` functions for the given number of bytes, or -1 if that
+    /// does not fit into an `int`.
+    fn printf_result(&mut self, len: i64) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+        match i32::try_from(len) {
+            Ok(len) => Ok(len),
+            Err(_) => this.printf_overflow(),
+        }
+    }
+
+    /// Fail because the output is longer than an `int` can count.
+    fn printf_overflow(&mut self) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_mut();
+        let eoverflow = this.eval_libc("EOVERFLOW");
+        this.set_last_error(eoverflow)?;
+        Ok(-1)
+    }
+
+    /// Formats the arguments like `printf` does with the format string at `format`. Returns
+    /// `None` if the output would be longer than `INT_MAX` bytes, since the `printf` functions
+    /// cannot return its length then. We check this before formatting a conversion with a huge
+    /// width or precision, so that the host does not have to allocate the padding.
+    fn format_printf(
+        &self,
+        format: Pointer,
+        mut varargs: VarArgs<'_, 'tcx>,
+    ) -> InterpResult<'tcx, Option<Vec<u8>>> {
+        let this = self.eval_context_ref();
+
+        let format = this.read_c_str(format)?.to_owned();
+        let mut format = format.iter().copied().peekable();
+        let mut output = Vec::new();
+        while let Some(c) = format.next() {
+            if c != b'%' {
+                output.push(c);
+                continue;
+            }
+            let mut spec = Spec::default();
+            while let Some(flag) = format.next_if(|c| b"-+ #0".contains(c)) {
+                match flag {
+                    b'-' => spec.left_align = true,
+                    b'+' => spec.plus = true,
+                    b' ' => spec.space = true,
+                    b'#' => spec.alternate = true,
+                    b'0' => spec.zero_pad = true,
+                    _ => unreachable!(),
+                }
+            }
+            if format.next_if_eq(&b'*').is_some() {
+                let width = varargs.next(this, this.machine.layouts.i32)?.to_i32()?;
+                // A negative width is taken as a `-` flag followed by a positive width.
+                spec.left_align |= width < 0;
+                spec.width = width.unsigned_abs().try_into().unwrap();
+            } else {
+                spec.width = parse_number(&mut format);
+            }
+            if format.next_if_eq(&b'.').is_some() {
+                spec.precision = if format.next_if_eq(&b'*').is_some() {
+                    // A negative precision is taken as if the precision were omitted.
+                    let precision = varargs.next(this, this.machine.layouts.i32)?.to_i32()?;
+                    precision.try_into().ok()
+                } else {
+                    Some(parse_number(&mut format))
+                };
+            }
+            let mut length = Vec::new();
+            while let Some(c) = format.next_if(|c| b"hljztLq".contains(c)) {
+                length.push(c);
+            }
+
+            let Some(conversion) = format.next() else {
+                throw_ub_format!("`printf` format string ends in the middle of a conversion");
+            };
+            // The output of the conversion is at least as long as the width, and for conversions
+            // where the precision counts digits that are always printed, it is at least as long
+            // as those digits (and the digit and decimal point in front of them, for floats).
+            let precision = spec.precision.unwrap_or(0);
+            let min_digits = match conversion {
+                b'd' | b'i' | b'u' | b'o' | b'x' | b'X' => precision,
+                b'f' | b'F' | b'e' | b'E' if precision > 0 => precision.saturating_add(2),
+                b'g' | b'G' if spec.alternate => precision.saturating_add(1),
+                _ => 0,
+            };
+            let min_len = spec.width.max(min_digits);
+            if output.len().saturating_add(min_len) > INT_MAX {
+                return Ok(None);
+            }
+            let formatted = match conversion {
+                b'%' => vec![b'%'],
+                b'd' | b'i' => {
+                    let layout = this.printf_int_layout(&length)?;
+                    let n = varargs.next(this, layout)?.to_int(layout.size)?;
+                    // `hh` and `h` arguments are promoted to `int`, and converted back here.
+                    let n = match length.as_slice() {
+                        b"hh" => i128::from(n as i8),
+                        b"h" => i128::from(n as i16),
+                        _ => n,
+                    };
+                    format_int(&spec, n < 0, n.unsigned_abs(), conversion)
+                }
+                b'u' | b'o' | b'x' | b'X' => {
+                    let layout = this.printf_int_layout(&length)?;
+                    let n = varargs.next(this, layout)?.to_uint(layout.size)?;
+                    let n = match length.as_slice() {
+                        b"hh" => u128::from(n as u8),
+                        b"h" => u128::from(n as u16),
+                        _ => n,
+                    };
+                    format_int(&spec, false, n, conversion)
+                }
+                b'f' | b'F' | b'e' | b'E' | b'g' | b'G' => {
+                    let f = if length == b"L" {
+                        if !this.long_double_is_x87() || this.long_double_size().bytes() != 16 {
+                            throw_unsup_format!(
+                                "`printf` only supports `long double` on targets where it is a 16-byte x87 float"
+                            );
+                        }
+                        let bits = varargs.next(this, this.machine.layouts.u128)?;
+                        let bits = bits.to_uint(Size::from_bytes(16))?;
+                        let f = x87::long_double_from_bytes(bits.to_le_bytes());
+                        // There are no host floats with this much precision, so we lose the
+                        // digits beyond what `f64` can represent.
+                        f.convert::<Double>(&mut false).value
+                    } else {
+                        let layout = this.layout_of(this.tcx.types.f64)?;
+                        varargs.next(this, layout)?.to_f64()?
+                    };
+                    format_float(&spec, f.to_host(), conversion)
+                }
+                b'c' => {
+                    if !length.is_empty() {
+                        throw_unsup_format!("`printf` does not support wide characters");
+                    }
+                    // The argument is converted to `unsigned char`.
+                    let c = varargs.next(this, this.machine.layouts.i32)?.to_i32()?;
+                    pad(&spec, b"", &[c as u8], false)
+                }
+                b's' => {
+                    if !length.is_empty() {
+                        throw_unsup_format!("`printf` does not support wide strings");
+                    }
+                    let ptr = varargs.next(this, this.machine.layouts.const_raw_ptr)?;
+                    let ptr = ptr.to_pointer(this)?;
+                    let s = match spec.precision {
+                        // With a precision, the string does not need to be null-terminated, and we
+                        // must not read beyond that many bytes.
+                        Some(precision) => {
+                            let mut s = Vec::new();
+                            for i in 0..precision {
+                                let ptr = ptr.wrapping_offset(Size::from_bytes(i), this);
+                                let byte =
+                                    this.read_bytes_ptr_strip_provenance(ptr, Size::from_bytes(1))?;
+                                if byte[0] == 0 {
+                                    break;
+                                }
+                                s.push(byte[0]);
+                            }
+                            s
+                        }
+                        None => this.read_c_str(ptr)?.to_owned(),
+                    };
+                    pad(&spec, b"", &s, false)
+                }
+                b'p' => {
+                    let ptr = varargs.next(this, this.machine.layouts.const_raw_ptr)?;
+                    let addr = ptr.to_pointer(this)?.addr().bytes();
+                    let s = if addr == 0 && this.tcx.sess.target.env == "gnu" {
+                        "(nil)".to_owned()
+                    } else {
+                        format!("{addr:#x}")
+                    };
+                    pad(&spec, b"", s.as_bytes(), false)
+                }
+                b'n' => throw_unsup_format!("`printf` does not support `%n`"),
+                b'a' | b'A' =>
+                    throw_unsup_format!("`printf` does not support `%{}`", conversion as char),
+                _ =>
+                    throw_ub_format!(
+                        "invalid conversion `%{}` in `printf` format string",
+                        conversion as char
+                    ),
+            };
+            output.extend(formatted);
+            if output.len() > INT_MAX {
+                return Ok(None);
+            }
+        }
+        Ok((output.len() <= INT_MAX).then_some(output))
+    }
+
+    /// The type of the argument of an integer conversion with the given length modifier.
+    fn printf_int_layout(&self, length: &[u8]) -> InterpResult<'tcx, TyAndLayout<'tcx>> {
+        let this = self.eval_context_ref();
+        Ok(match length {
+            b"" | b"hh" | b"h" => this.machine.layouts.i32,
+            b"l" => this.libc_ty_layout("c_long"),
+            b"ll" | b"q" | b"j" => this.machine.layouts.i64,
+            b"z" | b"t" => this.machine.layouts.isize,
+            _ =>
+                throw_ub_format!(
+                    "invalid length modifier `{}` for an integer in `printf` format string",
+                    String::from_utf8_lossy(length)
+                ),
+        })
+    }
+}
+
+fn parse_number(format: &mut iter::Peekable<impl Iterator<Item = u8>>) -> usize {
+    let mut n = 0usize;
+    while let Some(digit) = format.next_if(u8::is_ascii_digit) {
+        n = n.saturating_mul(10).saturating_add(usize::from(digit.strict_sub(b'0')));
+    }
+    n
+}
+
+/// Pads `prefix` (a sign or `0x`) and `body` to the width of the conversion. Zeros are padded
+/// between the two, if the `0` flag is given and `zero_pad` allows it.
+fn pad(spec: &Spec, prefix: &[u8], body: &[u8], zero_pad: bool) -> Vec<u8> {
+    let padding = spec.width.saturating_sub(prefix.len().saturating_add(body.len()));
+    let mut out = Vec::new();
+    if spec.left_align {
+        out.extend(prefix.iter().chain(body));
+        out.extend(iter::repeat(b' ').take(padding));
+    } else if spec.zero_pad && zero_pad {
+        out.extend(prefix);
+        out.extend(iter::repeat(b'0').take(padding));
+        out.extend(body);
+    } else {
+        out.extend(iter::repeat(b' ').take(padding));
+        out.extend(prefix.iter().chain(body));
+    }
+    out
+}
+
+fn format_int(spec: &Spec, negative: bool, magnitude: u128, conversion: u8) -> Vec<u8> {
+    let mut digits = match conversion {
+        b'o' => format!("{magnitude:o}"),
+        b'x' => format!("{magnitude:x}"),
+        b'X' => format!("{magnitude:X}"),
+        _ => magnitude.to_string(),
+    };
+    // The precision is the minimal number of digits. Zero with a precision of 0 has no digits.
+    match spec.precision {
+        Some(0) if magnitude == 0 => digits.clear(),
+        Some(precision) if digits.len() < precision =>
+            digits.insert_str(0, &"0".repeat(precision.strict_sub(digits.len()))),
+        _ => {}
+    }
+    if spec.alternate && conversion == b'o' && !digits.starts_with('0') {
+        digits.insert(0, '0');
+    }
+    let signed = matches!(conversion, b'd' | b'i');
+    let prefix: &[u8] = match conversion {
+        _ if negative => b"-",
+        _ if signed && spec.plus => b"+",
+        _ if signed && spec.space => b" ",
+        b'x' if spec.alternate && magnitude != 0 => b"0x",
+        b'X' if spec.alternate && magnitude != 0 => b"0X",
+        _ => b"",
+    };
+    // The `0` flag is ignored when there is a precision.
+    pad(spec, prefix, digits.as_bytes(), spec.precision.is_none())
+}
+
+fn format_float(spec: &Spec, f: f64, conversion: u8) -> Vec<u8> {
+    let prefix: &[u8] = if f.is_sign_negative() {
+        b"-"
+    } else if spec.plus {
+        b"+"
+    } else if spec.space {
+        b" "
+    } else {
+        b""
+    };
+    let upper = conversion.is_ascii_uppercase();
+    if !f.is_finite() {
+        let body = if f.is_nan() { "nan" } else { "inf" };
+        let body = if upper { body.to_ascii_uppercase() } else { body.to_owned() };
+        return pad(spec, prefix, body.as_bytes(), false);
+    }
+
+    let f = f.abs();
+    let precision = spec.precision.unwrap_or(6);
+    let mut body = match conversion.to_ascii_lowercase() {
+        b'f' => format!("{f:.precision$}"),
+        b'e' => format_exp(f, precision),
+        b'g' => {
+            // `precision` is the number of significant digits. Use the exponential notation if
+            // the exponent is too small or too large for that, and remove trailing zeros. An
+            // `f64` has at most 767 significant decimal digits, so without the `#` flag, a larger
+            // precision only adds zeros that are removed again.
+            let precision = if spec.alternate { precision } else { precision.min(800) };
+            let precision = precision.max(1);
+            let exp = format!("{f:.*e}", precision.strict_sub(1));
+            let exp: i64 = exp.split_once('e').unwrap().1.parse().unwrap();
+            let body = if exp < -4 || exp >= i64::try_from(precision).unwrap() {
+                format_exp(f, precision.strict_sub(1))
+            } else {
+                let decimals = i64::try_from(precision).unwrap().strict_sub(1).strict_sub(exp);
+                format!("{f:.*}", usize::try_from(decimals).unwrap())
+            };
+            if spec.alternate {
+                body
+            } else {
+                strip_trailing_zeros(&body)
+            }
+        }
+        _ => unreachable!(),
+    };
+    // The alternate form always has a decimal point.
+    if spec.alternate && !body.contains('.') {
+        body.insert(body.find('e').unwrap_or(body.len()), '.');
+    }
+    if upper {
+        body.make_ascii_uppercase();
+    }
+    pad(spec, prefix, body.as_bytes(), true)
+}
+
+/// Formats `f` in the exponential notation of C, e.g. `1.500000e+03` (instead of Rust's
+/// `1.500000e3`).
+fn format_exp(f: f64, precision: usize) -> String {
+    let s = format!("{f:.precision$e}");
+    let (mantissa, exp) = s.split_once('e').unwrap();
+    let exp: i64 = exp.parse().unwrap();
+    format!("{mantissa}e{}{:02}", if exp < 0 { '-' } else { '+' }, exp.unsigned_abs())
+}
+
+/// Removes the trailing zeros of the fractional part, and the decimal point if nothing is left
+/// after it.
+fn strip_trailing_zeros(s: &str) -> String {
+    let (number, exp) = s.split_at(s.find('e').unwrap_or(s.len()));
+    let number = if number.contains('.') {
+        number.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        number
+    };
+    format!("{number}{exp}")
+}
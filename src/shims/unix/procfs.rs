@@ -0,0 +1,113 @@
+//! Some files in `/proc` describe the process that reads them. On the host, that process is Miri,
+//! so we synthesize these files from the state of the interpreted program instead.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::*;
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// If `path` (which must already be normalized) is one of the `/proc` files that we emulate on
+    /// the current target, return its contents.
+    fn procfs_file(&self, path: &Path) -> InterpResult<'tcx, Option<Vec<u8>>> {
+        let this = self.eval_context_ref();
+
+        if !matches!(&*this.tcx.sess.target.os, "linux" | "android") {
+            return Ok(None);
+        }
+        let contents = match path.to_str() {
+            Some("/proc/self/cmdline") => this.procfs_cmdline()?,
+            Some("/proc/self/maps") => this.procfs_maps().into_bytes(),
+            Some("/proc/self/status") => this.procfs_status()?.into_bytes(),
+            Some("/proc/cpuinfo") => this.procfs_cpuinfo().into_bytes(),
+            _ => return Ok(None),
+        };
+        Ok(Some(contents))
+    }
+
+    /// The program arguments, each followed by a null byte.
+    fn procfs_cmdline(&self) -> InterpResult<'tcx, Vec<u8>> {
+        let this = self.eval_context_ref();
+
+        let mut cmdline = Vec::new();
+        // These are only set up if the program uses `std`.
+        let (Some(argc), Some(argv)) = (this.machine.argc, this.machine.argv) else {
+            return Ok(cmdline);
+        };
+        let argc = this.read_target_isize(&this.ptr_to_mplace(argc, this.machine.layouts.isize))?;
+        let argv = this.ptr_to_mplace(argv, this.machine.layouts.const_raw_ptr);
+        let mut arg = this.ptr_to_mplace(this.read_pointer(&argv)?, argv.layout);
+        for _ in 0..argc {
+            cmdline.extend_from_slice(this.read_c_str(this.read_pointer(&arg)?)?);
+            cmdline.push(0);
+            arg = arg.offset(arg.layout.size, arg.layout, this)?;
+        }
+        Ok(cmdline)
+    }
+
+    /// The pages that live allocations with an address are on. All of them are readable and
+    /// writable; we do not report functions and vtables, as they are not really in memory.
+    fn procfs_maps(&self) -> String {
+        let this = self.eval_context_ref();
+
+        let page_size = this.machine.page_size;
+        let mut ranges: Vec<(u64, u64)> = Vec::new();
+        let allocs: Vec<_> = this.machine.alloc_addresses.borrow().live_base_addrs().collect();
+        for (addr, alloc_id) in allocs {
+            let (size, _align, kind) = this.get_alloc_info(alloc_id);
+            if !matches!(kind, AllocKind::LiveData) {
+                continue;
+            }
+            let start = addr.strict_sub(addr.strict_rem(page_size));
+            let end = addr.strict_add(size.bytes()).max(start.strict_add(1));
+            let end = end.next_multiple_of(page_size);
+            // Allocations are sorted by address, so we only need to look at the last range.
+            match ranges.last_mut() {
+                Some((_, last_end)) if *last_end >= start => *last_end = end.max(*last_end),
+                _ => ranges.push((start, end)),
+            }
+        }
+
+        let mut maps = String::new();
+        for (start, end) in ranges {
+            writeln!(maps, "{start:08x}-{end:08x} rw-p 00000000 00:00 0").unwrap();
+        }
+        maps
+    }
+
+    fn procfs_status(&self) -> InterpResult<'tcx, String> {
+        let this = self.eval_context_ref();
+
+        // Like the kernel, we use the file name of the program, truncated to 15 bytes.
+        let cmdline = this.procfs_cmdline()?;
+        let argv0 = cmdline.split(|&b| b == 0).next().unwrap();
+        let name = argv0.rsplit(|&b| b == b'/').next().unwrap();
+        let name = String::from_utf8_lossy(&name[..name.len().min(15)]);
+        let threads = this.machine.threads.get_live_thread_count();
+        let cpus = match this.machine.num_cpus {
+            0 | 1 => "0".to_owned(),
+            n => format!("0-{}", n.strict_sub(1)),
+        };
+
+        let mut status = String::new();
+        writeln!(status, "Name:\t{name}").unwrap();
+        writeln!(status, "State:\tR (running)").unwrap();
+        writeln!(status, "Threads:\t{threads}").unwrap();
+        writeln!(status, "Cpus_allowed_list:\t{cpus}").unwrap();
+        Ok(status)
+    }
+
+    /// One entry for each of the CPUs that `-Zmiri-num-cpus` configured.
+    fn procfs_cpuinfo(&self) -> String {
+        let this = self.eval_context_ref();
+
+        let mut cpuinfo = String::new();
+        for cpu in 0..this.machine.num_cpus {
+            writeln!(cpuinfo, "processor\t: {cpu}").unwrap();
+            writeln!(cpuinfo, "model name\t: Miri virtual CPU").unwrap();
+            writeln!(cpuinfo).unwrap();
+        }
+        cpuinfo
+    }
+}
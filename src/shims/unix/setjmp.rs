@@ -0,0 +1,154 @@
+//! Implement `setjmp` and `longjmp`, within the stack of a single thread.
+//!
+//! `setjmp` remembers which frame called it, and where that call returns to. `longjmp` then pops
+//! all frames above that one, without running any destructors (just like in C), and makes the
+//! `setjmp` call return a second time.
+
+use either::Either;
+use rustc_data_structures::fx::FxHashMap;
+use rustc_middle::mir;
+
+use crate::*;
+
+#[derive(Debug)]
+struct JmpTarget<'tcx> {
+    /// The thread that called `setjmp`.
+    thread: ThreadId,
+    /// The index of the frame that called `setjmp` in the stack of `thread`.
+    frame_idx: usize,
+    /// The block that the `setjmp` call returns to.
+    ret: Option<mir::BasicBlock>,
+    /// The place that the result of the `setjmp` call is written to.
+    dest: MPlaceTy<'tcx>,
+}
+
+/// The `jmp_buf`s that can currently be jumped to. Each `jmp_buf` that was passed to `setjmp`
+/// contains an ID number that indexes this table.
+#[derive(Debug)]
+pub struct JmpBufTable<'tcx> {
+    targets: FxHashMap<u64, JmpTarget<'tcx>>,
+    /// ID number to be used by the next call to `setjmp`.
+    next_id: u64,
+}
+
+impl Default for JmpBufTable<'_> {
+    fn default() -> Self {
+        // Skip 0 as an ID, so that zero-initialized `jmp_buf`s are never valid.
+        JmpBufTable { targets: FxHashMap::default(), next_id: 1 }
+    }
+}
+
+impl JmpBufTable<'_> {
+    /// Must be called whenever a frame is popped: the `jmp_buf`s set up by that frame can no
+    /// longer be jumped to.
+    pub fn on_stack_pop(&mut self, thread: ThreadId, stack_len: usize) {
+        if !self.targets.is_empty() {
+            self.targets
+                .retain(|_, target| target.thread != thread || target.frame_idx < stack_len);
+        }
+    }
+}
+
+impl VisitProvenance for JmpBufTable<'_> {
+    fn visit_provenance(&self, visit: &mut VisitWith<'_>) {
+        let JmpBufTable { targets, next_id: _ } = self;
+
+        for target in targets.values() {
+            target.dest.visit_provenance(visit);
+        }
+    }
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    fn setjmp(&mut self, env_op: &OpTy<'tcx>, dest: &MPlaceTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let env = this.deref_pointer_as(env_op, this.machine.layouts.usize)?;
+
+        // Find out where this call returns to, so that `longjmp` can return there again.
+        let frame = this.frame();
+        let Either::Left(loc) = frame.loc else {
+            throw_unsup_format!("`setjmp` must be called from a function with MIR");
+        };
+        let mir::TerminatorKind::Call { target: ret, .. } =
+            frame.body.basic_blocks[loc.block].terminator().kind
+        else {
+            throw_unsup_format!("`setjmp` must be called from a function with MIR");
+        };
+        let target = JmpTarget {
+            thread: this.active_thread(),
+            frame_idx: this.frame_idx(),
+            ret,
+            dest: dest.clone(),
+        };
+
+        let table = &mut this.machine.jmp_bufs;
+        let id = table.next_id;
+        table.next_id = id.strict_add(1);
+        table.targets.insert(id, target);
+        this.write_scalar(Scalar::from_target_usize(id, this), &env)?;
+
+        // The first time, `setjmp` returns 0.
+        this.write_int(0, dest)
+    }
+
+    fn longjmp(&mut self, env_op: &OpTy<'tcx>, val_op: &OpTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let env = this.deref_pointer_as(env_op, this.machine.layouts.usize)?;
+        let id = this.read_target_usize(&env)?;
+        let val = this.read_scalar(val_op)?.to_i32()?;
+
+        let Some(target) = this.machine.jmp_bufs.targets.get(&id) else {
+            throw_ub_format!(
+                "`longjmp` to a `jmp_buf` that was not set up by `setjmp`, or whose function has already returned"
+            );
+        };
+        if target.thread != this.active_thread() {
+            throw_ub_format!("`longjmp` to a `jmp_buf` that was set up by a different thread");
+        }
+        let frame_idx = target.frame_idx;
+        let ret = target.ret;
+        let dest = target.dest.clone();
+
+        // C says jumping out of an `atexit` handler is UB; it would also leave `exit` half-done.
+        if this.active_thread_stack()[frame_idx + 1..]
+            .iter()
+            .any(|frame| frame.extra.exit_code.is_some())
+        {
+            throw_ub_format!("`longjmp` out of a function registered with `atexit`");
+        }
+
+        // Pop all frames above the one that called `setjmp`.
+        while this.frame_idx() > frame_idx {
+            this.discard_top_frame()?;
+        }
+
+        // `setjmp` returns `val` this time, but it never returns 0 a second time.
+        this.write_int(if val == 0 { 1 } else { val }, &dest)?;
+        this.return_to_block(ret)
+    }
+
+    /// Removes the topmost frame of the current thread, without returning from it: no destructors
+    /// are run, and nothing is written to the return place. Its locals are deallocated. Apart from
+    /// that, this goes through the same hooks as any other frame that is popped, so the borrow
+    /// tracker, test bookkeeping and so on see the frame end. If the frame was pushed by
+    /// `catch_unwind`, that call is simply abandoned, just like the calls of all other frames.
+    fn discard_top_frame(&mut self) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        MiriMachine::before_stack_pop(this, this.frame())?;
+        let frame = this.active_thread_stack_mut().pop().unwrap();
+        for local in frame.locals.iter() {
+            if let Some(Either::Left((ptr, _meta))) = local.as_mplace_or_imm() {
+                this.deallocate_ptr(ptr, None, MemoryKind::Stack)?;
+            }
+        }
+        // We are not unwinding, so this never pushes a frame (like a `catch_unwind` handler), and
+        // `longjmp` makes sure we never pop an `atexit` handler.
+        let jump = MiriMachine::after_stack_pop(this, frame, /* unwinding */ false)?;
+        assert!(matches!(jump, StackPopJump::Normal));
+        Ok(())
+    }
+}
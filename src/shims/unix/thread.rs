@@ -113,6 +113,61 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         Ok(if success { Scalar::from_u32(0) } else { this.eval_libc("ERANGE") })
     }
 
+    fn pthread_setschedparam(
+        &mut self,
+        thread: &OpTy<'tcx>,
+        policy: &OpTy<'tcx>,
+        param: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        let thread = this.read_scalar(thread)?.to_int(this.libc_ty_layout("pthread_t").size)?;
+        let Some(thread) = this.machine.threads.thread_id_try_from(thread) else {
+            return Ok(this.eval_libc("ESRCH"));
+        };
+        let policy = this.read_scalar(policy)?.to_i32()?;
+        let param = this.deref_pointer_as(param, this.libc_ty_layout("sched_param"))?;
+        let priority = this.project_field_named(&param, "sched_priority")?;
+        let priority = this.read_scalar(&priority)?.to_i32()?;
+
+        if ![
+            this.eval_libc_i32("SCHED_OTHER"),
+            this.eval_libc_i32("SCHED_FIFO"),
+            this.eval_libc_i32("SCHED_RR"),
+        ]
+        .contains(&policy)
+        {
+            return Ok(this.eval_libc("EINVAL"));
+        }
+
+        this.set_thread_priority(thread, priority);
+
+        Ok(Scalar::from_u32(0))
+    }
+
+    fn pthread_getschedparam(
+        &mut self,
+        thread: &OpTy<'tcx>,
+        policy_out: &OpTy<'tcx>,
+        param_out: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        let thread = this.read_scalar(thread)?.to_int(this.libc_ty_layout("pthread_t").size)?;
+        let Some(thread) = this.machine.threads.thread_id_try_from(thread) else {
+            return Ok(this.eval_libc("ESRCH"));
+        };
+        let policy_out = this.deref_pointer_as(policy_out, this.machine.layouts.i32)?;
+        let param_out = this.deref_pointer_as(param_out, this.libc_ty_layout("sched_param"))?;
+
+        // FIXME: we do not keep track of the policy, only of the priority.
+        this.write_scalar(Scalar::from_i32(this.eval_libc_i32("SCHED_OTHER")), &policy_out)?;
+        let priority = this.get_thread_priority(thread);
+        this.write_int_fields_named(&[("sched_priority", priority.into())], &param_out)?;
+
+        Ok(Scalar::from_u32(0))
+    }
+
     fn sched_yield(&mut self) -> InterpResult<'tcx, i32> {
         let this = self.eval_context_mut();
 
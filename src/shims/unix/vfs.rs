@@ -0,0 +1,281 @@
+//! An in-memory file system for `-Zmiri-virtual-fs`. With isolation enabled, the file system shims
+//! use this instead of the host file system, so that programs that e.g. write a temporary file and
+//! read it back can run without disabling isolation. Nothing ever reaches the host, and all
+//! metadata is deterministic: timestamps are always the Unix epoch, and inode numbers are 0.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::io::{self, ErrorKind, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+
+use crate::shims::unix::*;
+use crate::*;
+
+/// The contents of a file, shared by all open file descriptions of that file.
+pub type FileContents = Rc<RefCell<Vec<u8>>>;
+
+/// Files cannot grow beyond this size (1 GiB), so that e.g. a write after seeking to a huge offset
+/// fails with EFBIG instead of making Miri allocate that much memory.
+pub const MAX_FILE_SIZE: usize = 1 << 30;
+
+#[derive(Debug, Clone)]
+pub enum Node {
+    File(FileContents),
+    Dir,
+}
+
+#[derive(Debug)]
+pub struct VirtualFs {
+    /// All files and directories, keyed by their normalized absolute path.
+    nodes: BTreeMap<PathBuf, Node>,
+}
+
+impl Default for VirtualFs {
+    fn default() -> Self {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(PathBuf::from("/"), Node::Dir);
+        // `std::env::temp_dir()` returns this when `TMPDIR` is not set.
+        nodes.insert(PathBuf::from("/tmp"), Node::Dir);
+        VirtualFs { nodes }
+    }
+}
+
+impl VirtualFs {
+    /// Turn `path` into an absolute path without `.` and `..` components. There is no current
+    /// directory, so relative paths are relative to the root directory.
+    pub fn normalize(path: &Path) -> PathBuf {
+        let mut normalized = PathBuf::from("/");
+        for component in path.components() {
+            match component {
+                Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
+                Component::ParentDir => {
+                    normalized.pop();
+                }
+                Component::Normal(name) => normalized.push(name),
+            }
+        }
+        normalized
+    }
+
+    pub fn get(&self, path: &Path) -> io::Result<&Node> {
+        self.nodes.get(path).ok_or_else(|| ErrorKind::NotFound.into())
+    }
+
+    /// Check that the parent directory of `path` exists.
+    fn check_parent(&self, path: &Path) -> io::Result<()> {
+        let Some(parent) = path.parent() else { return Ok(()) };
+        match self.get(parent)? {
+            Node::Dir => Ok(()),
+            Node::File(_) => Err(ErrorKind::NotADirectory.into()),
+        }
+    }
+
+    /// Open the file at `path`, creating it first if `create` is set and it does not exist yet.
+    pub fn open(
+        &mut self,
+        path: &Path,
+        create: bool,
+        create_new: bool,
+        truncate: bool,
+    ) -> io::Result<FileContents> {
+        match self.nodes.get(path) {
+            Some(_) if create_new => Err(ErrorKind::AlreadyExists.into()),
+            Some(Node::Dir) => Err(ErrorKind::IsADirectory.into()),
+            Some(Node::File(contents)) => {
+                if truncate {
+                    contents.borrow_mut().clear();
+                }
+                Ok(contents.clone())
+            }
+            None if create || create_new => {
+                self.check_parent(path)?;
+                let contents = FileContents::default();
+                self.nodes.insert(path.to_owned(), Node::File(contents.clone()));
+                Ok(contents)
+            }
+            None => Err(ErrorKind::NotFound.into()),
+        }
+    }
+
+    pub fn unlink(&mut self, path: &Path) -> io::Result<()> {
+        match self.get(path)? {
+            Node::Dir => Err(ErrorKind::IsADirectory.into()),
+            Node::File(_) => {
+                self.nodes.remove(path);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn mkdir(&mut self, path: &Path) -> io::Result<()> {
+        if self.nodes.contains_key(path) {
+            return Err(ErrorKind::AlreadyExists.into());
+        }
+        self.check_parent(path)?;
+        self.nodes.insert(path.to_owned(), Node::Dir);
+        Ok(())
+    }
+
+    pub fn rmdir(&mut self, path: &Path) -> io::Result<()> {
+        match self.get(path)? {
+            Node::File(_) => Err(ErrorKind::NotADirectory.into()),
+            Node::Dir if path.parent().is_none() => Err(ErrorKind::ResourceBusy.into()),
+            Node::Dir if self.children(path).next().is_some() =>
+                Err(ErrorKind::DirectoryNotEmpty.into()),
+            Node::Dir => {
+                self.nodes.remove(path);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        let node = self.get(from)?.clone();
+        if from == to {
+            return Ok(());
+        }
+        self.check_parent(to)?;
+        match (&node, self.nodes.get(to)) {
+            (_, None) => {}
+            (Node::File(_), Some(Node::File(_))) => {}
+            (Node::File(_), Some(Node::Dir)) => return Err(ErrorKind::IsADirectory.into()),
+            (Node::Dir, Some(Node::File(_))) => return Err(ErrorKind::NotADirectory.into()),
+            (Node::Dir, Some(Node::Dir)) =>
+                if self.children(to).next().is_some() {
+                    return Err(ErrorKind::DirectoryNotEmpty.into());
+                },
+        }
+        if to.starts_with(from) {
+            // Cannot move a directory into itself.
+            return Err(ErrorKind::InvalidInput.into());
+        }
+        // Move the node and, for directories, everything inside of it.
+        let moved: Vec<PathBuf> = self
+            .nodes
+            .range(from.to_owned()..)
+            .map(|(path, _)| path)
+            .take_while(|path| path.starts_with(from))
+            .cloned()
+            .collect();
+        for path in moved {
+            let node = self.nodes.remove(&path).unwrap();
+            self.nodes.insert(to.join(path.strip_prefix(from).unwrap()), node);
+        }
+        Ok(())
+    }
+
+    /// The entries of the directory at `path`: their names, and whether they are directories.
+    pub fn read_dir(&self, path: &Path) -> io::Result<Vec<(OsString, bool)>> {
+        match self.get(path)? {
+            Node::File(_) => Err(ErrorKind::NotADirectory.into()),
+            Node::Dir =>
+                Ok(self
+                    .children(path)
+                    .map(|(child, node)| {
+                        (child.file_name().unwrap().to_owned(), matches!(node, Node::Dir))
+                    })
+                    .collect()),
+        }
+    }
+
+    fn children<'a>(&'a self, path: &'a Path) -> impl Iterator<Item = (&'a PathBuf, &'a Node)> {
+        self.nodes
+            .range(path.to_owned()..)
+            .skip(1)
+            .take_while(move |(child, _)| child.starts_with(path))
+            .filter(move |(child, _)| child.parent() == Some(path))
+    }
+}
+
+/// An open file of the virtual file system.
+#[derive(Debug)]
+pub struct VirtualFile {
+    pub contents: FileContents,
+    pub writable: bool,
+    append: bool,
+    pos: u64,
+}
+
+impl VirtualFile {
+    pub fn new(contents: FileContents, writable: bool, append: bool) -> Self {
+        VirtualFile { contents, writable, append, pos: 0 }
+    }
+}
+
+impl FileDescription for VirtualFile {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn read<'tcx>(
+        &mut self,
+        _communicate_allowed: bool,
+        bytes: &mut [u8],
+        _ecx: &mut MiriInterpCx<'tcx>,
+    ) -> InterpResult<'tcx, io::Result<usize>> {
+        let contents = self.contents.borrow();
+        let start = usize::try_from(self.pos).unwrap_or(usize::MAX).min(contents.len());
+        let len = bytes.len().min(contents.len() - start);
+        bytes[..len].copy_from_slice(&contents[start..][..len]);
+        self.pos += u64::try_from(len).unwrap();
+        Ok(Ok(len))
+    }
+
+    fn write<'tcx>(
+        &mut self,
+        _communicate_allowed: bool,
+        bytes: &[u8],
+        _ecx: &mut MiriInterpCx<'tcx>,
+    ) -> InterpResult<'tcx, io::Result<usize>> {
+        // `write_bytes_to_fd` already rejects writes to read-only files.
+        assert!(self.writable, "write to a read-only virtual file");
+        let mut contents = self.contents.borrow_mut();
+        if self.append {
+            self.pos = u64::try_from(contents.len()).unwrap();
+        }
+        let Some(end) = usize::try_from(self.pos)
+            .ok()
+            .and_then(|start| start.checked_add(bytes.len()))
+            .filter(|&end| end <= MAX_FILE_SIZE)
+        else {
+            return Ok(Err(ErrorKind::FileTooLarge.into()));
+        };
+        let start = end - bytes.len();
+        if contents.len() < end {
+            // Writing past the end fills the gap with zeros.
+            contents.resize(end, 0);
+        }
+        contents[start..end].copy_from_slice(bytes);
+        self.pos = u64::try_from(end).unwrap();
+        Ok(Ok(bytes.len()))
+    }
+
+    fn seek<'tcx>(
+        &mut self,
+        _communicate_allowed: bool,
+        offset: SeekFrom,
+    ) -> InterpResult<'tcx, io::Result<u64>> {
+        let (base, offset) = match offset {
+            SeekFrom::Start(offset) => {
+                self.pos = offset;
+                return Ok(Ok(offset));
+            }
+            SeekFrom::End(offset) => (u64::try_from(self.contents.borrow().len()).unwrap(), offset),
+            SeekFrom::Current(offset) => (self.pos, offset),
+        };
+        let Some(pos) = base.checked_add_signed(offset) else {
+            return Ok(Err(ErrorKind::InvalidInput.into()));
+        };
+        self.pos = pos;
+        Ok(Ok(pos))
+    }
+
+    fn close<'tcx>(
+        self: Box<Self>,
+        _communicate_allowed: bool,
+    ) -> InterpResult<'tcx, io::Result<()>> {
+        Ok(Ok(()))
+    }
+}
@@ -149,7 +149,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let buf = this.read_pointer(buf_op)?;
 
         if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
-            this.reject_in_isolation("`GetCurrentDirectoryW`", reject_with)?;
+            // See the comment in `getcwd`.
+            if !this.in_std_backtrace() {
+                this.reject_in_isolation("`GetCurrentDirectoryW`", reject_with)?;
+            }
             this.set_last_error_from_io_error(ErrorKind::PermissionDenied.into())?;
             return Ok(Scalar::from_u32(0));
         }
@@ -200,9 +203,8 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     fn GetCurrentProcessId(&mut self) -> InterpResult<'tcx, u32> {
         let this = self.eval_context_mut();
         this.assert_target_os("windows", "GetCurrentProcessId");
-        this.check_no_isolation("`GetCurrentProcessId`")?;
 
-        Ok(std::process::id())
+        Ok(this.machine.process_id)
     }
 
     #[allow(non_snake_case)]
@@ -256,4 +258,33 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             }
         })
     }
+
+    #[allow(non_snake_case)]
+    fn GetComputerNameW(
+        &mut self,
+        buf_op: &OpTy<'tcx>,  // LPWSTR
+        size_op: &OpTy<'tcx>, // LPDWORD
+    ) -> InterpResult<'tcx, Scalar> // returns BOOL
+    {
+        let this = self.eval_context_mut();
+        this.assert_target_os("windows", "GetComputerNameW");
+
+        let buf = this.read_pointer(buf_op)?;
+        let size = this.deref_pointer_as(size_op, this.machine.layouts.u32)?;
+        let size_avail = this.read_scalar(&size)?.to_u32()?;
+
+        // See <https://learn.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-getcomputernamew> for docs.
+        // Windows reports the NetBIOS name, which is upper case.
+        let name = OsString::from(this.machine.hostname.to_uppercase());
+        let (success, len) = this.write_os_str_to_wide_str(&name, buf, size_avail.into())?;
+        // On success, `size` is set to the length without the null terminator, otherwise to the
+        // length of the buffer that is required, including it.
+        this.write_scalar(Scalar::from_u32(windows_check_buffer_size((success, len))), &size)?;
+        if success {
+            Ok(Scalar::from_i32(1)) // return TRUE
+        } else {
+            this.set_last_error(this.eval_windows("c", "ERROR_BUFFER_OVERFLOW"))?;
+            Ok(Scalar::from_i32(0)) // return FALSE
+        }
+    }
 }
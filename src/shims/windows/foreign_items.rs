@@ -8,6 +8,7 @@ use rustc_span::Symbol;
 use rustc_target::abi::{Align, Size};
 use rustc_target::spec::abi::Abi;
 
+use crate::shims::alloc::EvalContextExt as _;
 use crate::shims::os_str::bytes_to_os_str;
 use crate::shims::windows::*;
 use crate::*;
@@ -138,6 +139,12 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let result = this.GetUserProfileDirectoryW(token, buf, size)?;
                 this.write_scalar(result, dest)?;
             }
+            "GetComputerNameW" => {
+                let [buf, size] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+                let result = this.GetComputerNameW(buf, size)?;
+                this.write_scalar(result, dest)?;
+            }
 
             // File related shims
             "NtWriteFile" => {
@@ -173,16 +180,14 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
                 let written = if handle == -11 || handle == -12 {
                     // stdout/stderr
-                    use io::Write;
-
                     let buf_cont =
                         this.read_bytes_ptr_strip_provenance(buf, Size::from_bytes(u64::from(n)))?;
                     let res = if this.machine.mute_stdout_stderr {
                         Ok(buf_cont.len())
                     } else if handle == -11 {
-                        io::stdout().write(buf_cont)
+                        this.machine.write_to_stdout(buf_cont).map(|()| buf_cont.len())
                     } else {
-                        io::stderr().write(buf_cont)
+                        this.machine.write_to_stderr(buf_cont).map(|()| buf_cont.len())
                     };
                     // We write at most `n` bytes, which is a `u32`, so we cannot have written more than that.
                     res.ok().map(|n| u32::try_from(n).unwrap())
@@ -256,10 +261,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     MiriMemoryKind::WinHeap.into(),
                 )?;
                 if zero_init {
-                    this.write_bytes_ptr(
-                        ptr.into(),
-                        iter::repeat(0u8).take(usize::try_from(size).unwrap()),
-                    )?;
+                    this.mark_fresh_alloc_zeroed(ptr.into(), Size::from_bytes(size))?;
                 }
                 this.write_pointer(ptr, dest)?;
             }
@@ -373,10 +375,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             // Access to command-line arguments
             "GetCommandLineW" => {
                 let [] = this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
-                this.write_pointer(
-                    this.machine.cmd_line.expect("machine must be initialized"),
-                    dest,
-                )?;
+                let Some(cmd_line) = this.machine.cmd_line else {
+                    throw_unsup_format!("`GetCommandLineW` is not supported in `no_std` programs");
+                };
+                this.write_pointer(cmd_line, dest)?;
             }
 
             // Time related shims
@@ -533,14 +535,51 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
                 this.write_null(dest)?;
             }
+            "SetThreadPriority" => {
+                let [handle, priority] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+
+                let handle = this.read_scalar(handle)?;
+                let priority = this.read_scalar(priority)?.to_i32()?;
+
+                let thread = match Handle::from_scalar(handle, this)? {
+                    Some(Handle::Thread(thread)) => thread,
+                    Some(Handle::Pseudo(PseudoHandle::CurrentThread)) => this.active_thread(),
+                    _ => this.invalid_handle("SetThreadPriority")?,
+                };
+                this.set_thread_priority(thread, priority);
+
+                this.write_int(1, dest)?;
+            }
+            "GetThreadPriority" => {
+                let [handle] =
+                    this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
+
+                let handle = this.read_scalar(handle)?;
+
+                let thread = match Handle::from_scalar(handle, this)? {
+                    Some(Handle::Thread(thread)) => thread,
+                    Some(Handle::Pseudo(PseudoHandle::CurrentThread)) => this.active_thread(),
+                    _ => this.invalid_handle("GetThreadPriority")?,
+                };
+
+                this.write_int(this.get_thread_priority(thread), dest)?;
+            }
 
             // Miscellaneous
             "ExitProcess" => {
                 let [code] =
                     this.check_shim(abi, Abi::System { unwind: false }, link_name, args)?;
                 let code = this.read_scalar(code)?.to_u32()?;
+                // Unlike the C runtime's `exit`, this does not run the handlers registered with
+                // `atexit` or `_onexit`.
                 throw_machine_stop!(TerminationInfo::Exit { code: code.into(), leak_check: false });
             }
+            "_onexit" => {
+                let [func] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let result = this.onexit(func)?;
+                this.write_scalar(result, dest)?;
+            }
             "SystemFunction036" => {
                 // used by getrandom 0.1
                 // This is really 'RtlGenRandom'.
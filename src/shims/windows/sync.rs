@@ -182,7 +182,9 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 Scalar::from_i32(0), // retval_timeout
                 dest.clone(),
                 this.eval_windows("c", "ERROR_TIMEOUT"),
-            );
+            )?;
+        } else {
+            this.futex_acquired_without_waiting(addr);
         }
 
         this.write_scalar(Scalar::from_i32(1), dest)?;
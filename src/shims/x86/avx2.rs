@@ -5,8 +5,8 @@ use rustc_span::Symbol;
 use rustc_target::spec::abi::Abi;
 
 use super::{
-    horizontal_bin_op, int_abs, mask_load, mask_store, mpsadbw, packssdw, packsswb, packusdw,
-    packuswb, pmulhrsw, psign, shift_simd_by_scalar, shift_simd_by_simd, ShiftOp,
+    gather, horizontal_bin_op, int_abs, mask_load, mask_store, mpsadbw, packssdw, packsswb,
+    packusdw, packuswb, pmulhrsw, pshufb, psign, shift_simd_by_scalar, shift_simd_by_simd, ShiftOp,
 };
 use crate::*;
 
@@ -60,55 +60,18 @@ pub(super) trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let [src, slice, offsets, mask, scale] =
                     this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
 
-                assert_eq!(dest.layout, src.layout);
-
-                let (src, _) = this.operand_to_simd(src)?;
-                let (offsets, offsets_len) = this.operand_to_simd(offsets)?;
                 let (mask, mask_len) = this.operand_to_simd(mask)?;
-                let (dest, dest_len) = this.mplace_to_simd(dest)?;
-
-                // There are cases like dest: i32x4, offsets: i64x2
-                // If dest has more elements than offset, extra dest elements are filled with zero.
-                // If offsets has more elements than dest, extra offsets are ignored.
-                let actual_len = dest_len.min(offsets_len);
-
-                assert_eq!(dest_len, mask_len);
-
                 let mask_item_size = mask.layout.field(this, 0).size;
                 let high_bit_offset = mask_item_size.bits().strict_sub(1);
-
-                let scale = this.read_scalar(scale)?.to_i8()?;
-                if !matches!(scale, 1 | 2 | 4 | 8) {
-                    panic!("invalid gather scale {scale}");
+                let mut active = Vec::with_capacity(mask_len.try_into().unwrap());
+                for i in 0..mask_len {
+                    let mask = this.read_scalar(&this.project_index(&mask, i)?)?;
+                    active.push(mask.to_uint(mask_item_size)? >> high_bit_offset != 0);
                 }
-                let scale = i64::from(scale);
 
-                let slice = this.read_pointer(slice)?;
-                for i in 0..actual_len {
-                    let mask = this.project_index(&mask, i)?;
-                    let dest = this.project_index(&dest, i)?;
+                let scale = this.read_scalar(scale)?.to_i8()?;
 
-                    if this.read_scalar(&mask)?.to_uint(mask_item_size)? >> high_bit_offset != 0 {
-                        let offset = this.project_index(&offsets, i)?;
-                        let offset =
-                            i64::try_from(this.read_scalar(&offset)?.to_int(offset.layout.size)?)
-                                .unwrap();
-                        let ptr = slice.wrapping_signed_offset(offset.strict_mul(scale), &this.tcx);
-                        // Unaligned copy, which is what we want.
-                        this.mem_copy(
-                            ptr,
-                            dest.ptr(),
-                            dest.layout.size,
-                            /*nonoverlapping*/ true,
-                        )?;
-                    } else {
-                        this.copy_op(&this.project_index(&src, i)?, &dest)?;
-                    }
-                }
-                for i in actual_len..dest_len {
-                    let dest = this.project_index(&dest, i)?;
-                    this.write_scalar(Scalar::from_int(0, dest.layout.size), &dest)?;
-                }
+                gather(this, src, slice, offsets, &active, scale.into(), dest)?;
             }
             // Used to implement the _mm256_madd_epi16 function.
             // Multiplies packed signed 16-bit integers in `left` and `right`, producing
@@ -363,28 +326,7 @@ pub(super) trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let [left, right] =
                     this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
 
-                let (left, left_len) = this.operand_to_simd(left)?;
-                let (right, right_len) = this.operand_to_simd(right)?;
-                let (dest, dest_len) = this.mplace_to_simd(dest)?;
-
-                assert_eq!(dest_len, left_len);
-                assert_eq!(dest_len, right_len);
-
-                for i in 0..dest_len {
-                    let right = this.read_scalar(&this.project_index(&right, i)?)?.to_u8()?;
-                    let dest = this.project_index(&dest, i)?;
-
-                    let res = if right & 0x80 == 0 {
-                        // Shuffle each 128-bit (16-byte) block independently.
-                        let j = u64::from(right % 16).strict_add(i & !15);
-                        this.read_scalar(&this.project_index(&left, j)?)?
-                    } else {
-                        // If the highest bit in `right` is 1, write zero.
-                        Scalar::from_u8(0)
-                    };
-
-                    this.write_scalar(res, &dest)?;
-                }
+                pshufb(this, left, right, dest)?;
             }
             // Used to implement the _mm256_sign_epi{8,16,32} functions.
             // Negates elements from `left` when the corresponding element in
@@ -0,0 +1,403 @@
+use rustc_span::Symbol;
+use rustc_target::spec::abi::Abi;
+
+use super::{
+    gather, packssdw, packsswb, packusdw, packuswb, pmulhrsw, pshufb, shift_simd_by_scalar,
+    shift_simd_by_simd, ShiftOp,
+};
+use crate::*;
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub(super) trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    fn emulate_x86_avx512_intrinsic(
+        &mut self,
+        link_name: Symbol,
+        abi: Abi,
+        args: &[OpTy<'tcx>],
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx, EmulateItemResult> {
+        let this = self.eval_context_mut();
+        this.expect_target_feature_for_intrinsic(link_name, "avx512f")?;
+        // Prefix should have already been checked.
+        let unprefixed_name = link_name.as_str().strip_prefix("llvm.x86.avx512.").unwrap();
+        // The 128-bit and 256-bit variants also need AVX512VL.
+        if unprefixed_name.ends_with(".128") || unprefixed_name.ends_with(".256") {
+            this.expect_target_feature_for_intrinsic(link_name, "avx512vl")?;
+        }
+
+        match unprefixed_name {
+            // Used to implement the `_mm512{,_mask}_{i32,i64}gather_{epi32,epi64,ps,pd}`
+            // functions.
+            // Gathers elements from `slice` using `offsets * scale` as indices.
+            // When the bit of `mask` that corresponds to an element is 0, the
+            // value is copied from `src` instead.
+            "gather.dpi.512" | "gather.dpq.512" | "gather.qpi.512" | "gather.qpq.512"
+            | "gather.dps.512" | "gather.dpd.512" | "gather.qps.512" | "gather.qpd.512" => {
+                let [src, slice, offsets, mask, scale] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+
+                let (_, dest_len) = this.mplace_to_simd(dest)?;
+                let active = read_bitmask(this, mask, dest_len)?;
+                let scale = this.read_scalar(scale)?.to_i32()?;
+
+                gather(this, src, slice, offsets, &active, scale.into(), dest)?;
+            }
+            // Used to implement the `_mm512{,_mask}_{i32,i64}scatter_{epi32,epi64,ps,pd}`
+            // functions.
+            // Stores the elements of `value` to `slice` using `offsets * scale`
+            // as indices, for the elements whose bit in `mask` is 1. Elements
+            // are stored in order, so when indices overlap, the last one wins.
+            "scatter.dpi.512" | "scatter.dpq.512" | "scatter.qpi.512" | "scatter.qpq.512"
+            | "scatter.dps.512" | "scatter.dpd.512" | "scatter.qps.512" | "scatter.qpd.512" => {
+                let [slice, mask, offsets, value, scale] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+
+                let (offsets, offsets_len) = this.operand_to_simd(offsets)?;
+                let (value, value_len) = this.operand_to_simd(value)?;
+                let active = read_bitmask(this, mask, value_len)?;
+
+                let scale = i64::from(this.read_scalar(scale)?.to_i32()?);
+                if !matches!(scale, 1 | 2 | 4 | 8) {
+                    panic!("invalid scatter scale {scale}");
+                }
+
+                let slice = this.read_pointer(slice)?;
+                for i in 0..value_len.min(offsets_len) {
+                    if !active[usize::try_from(i).unwrap()] {
+                        continue;
+                    }
+                    let value = this.project_index(&value, i)?;
+                    let offset = this.project_index(&offsets, i)?;
+                    let offset =
+                        i64::try_from(this.read_scalar(&offset)?.to_int(offset.layout.size)?)
+                            .unwrap();
+                    let ptr = slice.wrapping_signed_offset(offset.strict_mul(scale), &this.tcx);
+                    // Unaligned copy, which is what we want.
+                    this.mem_copy(
+                        value.ptr(),
+                        ptr,
+                        value.layout.size,
+                        /*nonoverlapping*/ true,
+                    )?;
+                }
+            }
+            // Used to implement the `_mm{,256,512}_{mask,maskz}_loadu_*` functions.
+            // Loads the elements whose bit in `mask` is 1 from `ptr`; the other
+            // elements are copied from `src` and their memory is not accessed.
+            // `ptr` does not need to be aligned.
+            name if name.starts_with("mask.loadu.") => {
+                let [ptr, src, mask] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let feature = element_feature(name, 2, "avx512bw");
+                this.expect_target_feature_for_intrinsic(link_name, feature)?;
+
+                let (src, src_len) = this.operand_to_simd(src)?;
+                let (dest, dest_len) = this.mplace_to_simd(dest)?;
+                assert_eq!(dest_len, src_len);
+                let active = read_bitmask(this, mask, dest_len)?;
+
+                let ptr = this.read_pointer(ptr)?;
+                for i in 0..dest_len {
+                    let dest = this.project_index(&dest, i)?;
+                    if active[usize::try_from(i).unwrap()] {
+                        let ptr = ptr.wrapping_offset(dest.layout.size * i, &this.tcx);
+                        // Unaligned copy, which is what we want.
+                        this.mem_copy(
+                            ptr,
+                            dest.ptr(),
+                            dest.layout.size,
+                            /*nonoverlapping*/ true,
+                        )?;
+                    } else {
+                        this.copy_op(&this.project_index(&src, i)?, &dest)?;
+                    }
+                }
+            }
+            // Used to implement the `_mm{,256,512}_mask_storeu_*` functions.
+            // Stores the elements whose bit in `mask` is 1 into `ptr`; the memory
+            // of the other elements is not accessed. `ptr` does not need to be
+            // aligned.
+            name if name.starts_with("mask.storeu.") => {
+                let [ptr, value, mask] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let feature = element_feature(name, 2, "avx512bw");
+                this.expect_target_feature_for_intrinsic(link_name, feature)?;
+
+                let (value, value_len) = this.operand_to_simd(value)?;
+                let active = read_bitmask(this, mask, value_len)?;
+
+                let ptr = this.read_pointer(ptr)?;
+                for i in 0..value_len {
+                    if active[usize::try_from(i).unwrap()] {
+                        let value = this.project_index(&value, i)?;
+                        let ptr = ptr.wrapping_offset(value.layout.size * i, &this.tcx);
+                        // Unaligned copy, which is what we want.
+                        this.mem_copy(
+                            value.ptr(),
+                            ptr,
+                            value.layout.size,
+                            /*nonoverlapping*/ true,
+                        )?;
+                    }
+                }
+            }
+            // Used to implement the `_mm{,256,512}_mask_compressstoreu_*` functions.
+            // Stores the elements whose bit in `mask` is 1 contiguously into `ptr`.
+            // `ptr` does not need to be aligned.
+            name if name.starts_with("mask.compress.store.") => {
+                let [ptr, value, mask] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let feature = element_feature(name, 3, "avx512vbmi2");
+                this.expect_target_feature_for_intrinsic(link_name, feature)?;
+
+                let (value, value_len) = this.operand_to_simd(value)?;
+                let active = read_bitmask(this, mask, value_len)?;
+
+                let mut ptr = this.read_pointer(ptr)?;
+                for i in 0..value_len {
+                    if active[usize::try_from(i).unwrap()] {
+                        let value = this.project_index(&value, i)?;
+                        // Unaligned copy, which is what we want.
+                        this.mem_copy(
+                            value.ptr(),
+                            ptr,
+                            value.layout.size,
+                            /*nonoverlapping*/ true,
+                        )?;
+                        ptr = ptr.wrapping_offset(value.layout.size, &this.tcx);
+                    }
+                }
+            }
+            // Used to implement the `_mm{,256,512}_{mask,maskz}_compress_*` functions.
+            // Packs the elements of `op` whose bit in `mask` is 1 into the low
+            // elements of `dest`; the remaining elements are copied from `src`.
+            name if name.starts_with("mask.compress.") => {
+                let [op, src, mask] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let feature = element_feature(name, 2, "avx512vbmi2");
+                this.expect_target_feature_for_intrinsic(link_name, feature)?;
+
+                let (op, op_len) = this.operand_to_simd(op)?;
+                let (src, src_len) = this.operand_to_simd(src)?;
+                let (dest, dest_len) = this.mplace_to_simd(dest)?;
+                assert_eq!(dest_len, op_len);
+                assert_eq!(dest_len, src_len);
+                let active = read_bitmask(this, mask, dest_len)?;
+
+                let mut j = 0;
+                for i in 0..dest_len {
+                    if active[usize::try_from(i).unwrap()] {
+                        let op = this.project_index(&op, i)?;
+                        this.copy_op(&op, &this.project_index(&dest, j)?)?;
+                        j = j.strict_add(1);
+                    }
+                }
+                for i in j..dest_len {
+                    let src = this.project_index(&src, i)?;
+                    this.copy_op(&src, &this.project_index(&dest, i)?)?;
+                }
+            }
+            // Used to implement the `_mm{,256,512}_{mask,maskz}_expand_*` functions.
+            // Places consecutive low elements of `op` into the elements of `dest`
+            // whose bit in `mask` is 1; the remaining elements are copied from
+            // `src`.
+            name if name.starts_with("mask.expand.") => {
+                let [op, src, mask] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let feature = element_feature(name, 2, "avx512vbmi2");
+                this.expect_target_feature_for_intrinsic(link_name, feature)?;
+
+                let (op, op_len) = this.operand_to_simd(op)?;
+                let (src, src_len) = this.operand_to_simd(src)?;
+                let (dest, dest_len) = this.mplace_to_simd(dest)?;
+                assert_eq!(dest_len, op_len);
+                assert_eq!(dest_len, src_len);
+                let active = read_bitmask(this, mask, dest_len)?;
+
+                let mut j = 0;
+                for i in 0..dest_len {
+                    let dest = this.project_index(&dest, i)?;
+                    if active[usize::try_from(i).unwrap()] {
+                        this.copy_op(&this.project_index(&op, j)?, &dest)?;
+                        j = j.strict_add(1);
+                    } else {
+                        this.copy_op(&this.project_index(&src, i)?, &dest)?;
+                    }
+                }
+            }
+            // Used to implement the `_mm{,256,512}_permutexvar_*` functions.
+            // Shuffles `left` across lanes using the low bits of the elements of
+            // `right` as indices.
+            "permvar.si.512" | "permvar.sf.512" | "permvar.di.512" | "permvar.df.512"
+            | "permvar.di.256" | "permvar.df.256" | "permvar.hi.128" | "permvar.hi.256"
+            | "permvar.hi.512" | "permvar.qi.128" | "permvar.qi.256" | "permvar.qi.512" => {
+                let [left, right] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let feature = match unprefixed_name.split('.').nth(1).unwrap() {
+                    "hi" => "avx512bw",
+                    "qi" => "avx512vbmi",
+                    _ => "avx512f",
+                };
+                this.expect_target_feature_for_intrinsic(link_name, feature)?;
+
+                let (left, left_len) = this.operand_to_simd(left)?;
+                let (right, right_len) = this.operand_to_simd(right)?;
+                let (dest, dest_len) = this.mplace_to_simd(dest)?;
+                assert_eq!(dest_len, left_len);
+                assert_eq!(dest_len, right_len);
+
+                for i in 0..dest_len {
+                    let index = this.project_index(&right, i)?;
+                    let index = this.read_scalar(&index)?.to_uint(index.layout.size)?;
+                    // The length is a power of two, so this only keeps the low bits.
+                    let index = u64::try_from(index % u128::from(dest_len)).unwrap();
+                    let src = this.project_index(&left, index)?;
+                    this.copy_op(&src, &this.project_index(&dest, i)?)?;
+                }
+            }
+            // Used to implement the `_mm{,256,512}_permutex2var_*` functions.
+            // Shuffles the concatenation of `left` and `right` using the low bits
+            // of the elements of `indices` as indices.
+            "vpermi2var.d.128" | "vpermi2var.d.256" | "vpermi2var.d.512" | "vpermi2var.q.128"
+            | "vpermi2var.q.256" | "vpermi2var.q.512" | "vpermi2var.ps.128"
+            | "vpermi2var.ps.256" | "vpermi2var.ps.512" | "vpermi2var.pd.128"
+            | "vpermi2var.pd.256" | "vpermi2var.pd.512" | "vpermi2var.hi.128"
+            | "vpermi2var.hi.256" | "vpermi2var.hi.512" | "vpermi2var.qi.128"
+            | "vpermi2var.qi.256" | "vpermi2var.qi.512" => {
+                let [left, indices, right] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let feature = match unprefixed_name.split('.').nth(1).unwrap() {
+                    "hi" => "avx512bw",
+                    "qi" => "avx512vbmi",
+                    _ => "avx512f",
+                };
+                this.expect_target_feature_for_intrinsic(link_name, feature)?;
+
+                let (left, left_len) = this.operand_to_simd(left)?;
+                let (indices, indices_len) = this.operand_to_simd(indices)?;
+                let (right, right_len) = this.operand_to_simd(right)?;
+                let (dest, dest_len) = this.mplace_to_simd(dest)?;
+                assert_eq!(dest_len, left_len);
+                assert_eq!(dest_len, indices_len);
+                assert_eq!(dest_len, right_len);
+
+                for i in 0..dest_len {
+                    let index = this.project_index(&indices, i)?;
+                    let index = this.read_scalar(&index)?.to_uint(index.layout.size)?;
+                    let index = u64::try_from(index % u128::from(dest_len.strict_mul(2))).unwrap();
+                    let src = if index < dest_len {
+                        this.project_index(&left, index)?
+                    } else {
+                        this.project_index(&right, index.strict_sub(dest_len))?
+                    };
+                    this.copy_op(&src, &this.project_index(&dest, i)?)?;
+                }
+            }
+            // Used to implement the _mm512_shuffle_epi8 intrinsic.
+            // Shuffles bytes from `left` using `right` as pattern.
+            // Each 128-bit block is shuffled independently.
+            "pshuf.b.512" => {
+                let [left, right] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.expect_target_feature_for_intrinsic(link_name, "avx512bw")?;
+
+                pshufb(this, left, right, dest)?;
+            }
+            // Used to implement the _mm512_{sll,srl,sra}_epi{16,32,64} and
+            // _mm{,256}_sra_epi64 functions.
+            // Shifts N-bit packed integers in left by the amount in right.
+            // `right` is as 128-bit vector. but it is interpreted as a single
+            // 64-bit integer (remaining bits are ignored).
+            "psll.w.512" | "psrl.w.512" | "psra.w.512" | "psll.d.512" | "psrl.d.512"
+            | "psra.d.512" | "psll.q.512" | "psrl.q.512" | "psra.q.512" | "psra.q.128"
+            | "psra.q.256" => {
+                let [left, right] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let feature = element_feature(unprefixed_name, 1, "avx512bw");
+                this.expect_target_feature_for_intrinsic(link_name, feature)?;
+
+                let which = match unprefixed_name.split('.').next().unwrap() {
+                    "psll" => ShiftOp::Left,
+                    "psrl" => ShiftOp::RightLogic,
+                    "psra" => ShiftOp::RightArith,
+                    _ => unreachable!(),
+                };
+
+                shift_simd_by_scalar(this, left, right, which, dest)?;
+            }
+            // Used to implement the _mm512_{sllv,srlv,srav}_epi{16,32,64},
+            // _mm{,256}_{sllv,srlv,srav}_epi16 and _mm{,256}_srav_epi64 functions.
+            "psllv.d.512" | "psrlv.d.512" | "psrav.d.512" | "psllv.q.512" | "psrlv.q.512"
+            | "psrav.q.512" | "psrav.q.128" | "psrav.q.256" | "psllv.w.128" | "psllv.w.256"
+            | "psllv.w.512" | "psrlv.w.128" | "psrlv.w.256" | "psrlv.w.512" | "psrav.w.128"
+            | "psrav.w.256" | "psrav.w.512" => {
+                let [left, right] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let feature = element_feature(unprefixed_name, 1, "avx512bw");
+                this.expect_target_feature_for_intrinsic(link_name, feature)?;
+
+                let which = match unprefixed_name.split('.').next().unwrap() {
+                    "psllv" => ShiftOp::Left,
+                    "psrlv" => ShiftOp::RightLogic,
+                    "psrav" => ShiftOp::RightArith,
+                    _ => unreachable!(),
+                };
+
+                shift_simd_by_simd(this, left, right, which, dest)?;
+            }
+            // Used to implement the _mm512_packs_epi{16,32} and _mm512_packus_epi{16,32}
+            // functions.
+            // Converts two N-bit integer vectors to a single N/2-bit integer
+            // vector with saturation.
+            "packsswb.512" | "packuswb.512" | "packssdw.512" | "packusdw.512" => {
+                let [left, right] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.expect_target_feature_for_intrinsic(link_name, "avx512bw")?;
+
+                match unprefixed_name {
+                    "packsswb.512" => packsswb(this, left, right, dest)?,
+                    "packuswb.512" => packuswb(this, left, right, dest)?,
+                    "packssdw.512" => packssdw(this, left, right, dest)?,
+                    "packusdw.512" => packusdw(this, left, right, dest)?,
+                    _ => unreachable!(),
+                }
+            }
+            // Used to implement the _mm512_mulhrs_epi16 function.
+            // Multiplies packed 16-bit signed integer values, truncates the 32-bit
+            // product to the 18 most significant bits by right-shifting, and then
+            // divides the 18-bit value by 2 (rounding to nearest) by first adding
+            // 1 and then taking the bits `1..=16`.
+            "pmul.hr.sw.512" => {
+                let [left, right] =
+                    this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                this.expect_target_feature_for_intrinsic(link_name, "avx512bw")?;
+
+                pmulhrsw(this, left, right, dest)?;
+            }
+            _ => return Ok(EmulateItemResult::NotSupported),
+        }
+        Ok(EmulateItemResult::NeedsReturn)
+    }
+}
+
+/// Reads the `len` low bits of the integer `mask` (an AVX-512 mask register):
+/// element `i` is active if bit `i` is set.
+fn read_bitmask<'tcx>(
+    this: &crate::MiriInterpCx<'tcx>,
+    mask: &OpTy<'tcx>,
+    len: u64,
+) -> InterpResult<'tcx, Vec<bool>> {
+    let bits = this.read_scalar(mask)?.to_uint(mask.layout.size)?;
+    assert!(len <= mask.layout.size.bits());
+    Ok((0..len).map(|i| (bits >> i) & 1 != 0).collect())
+}
+
+/// The target feature (besides AVX512F) that an intrinsic needs, based on
+/// the element type that is the `segment`-th `.`-separated part of `name`:
+/// byte and word elements need `byte_word_feature`, the others nothing extra.
+fn element_feature(name: &str, segment: usize, byte_word_feature: &'static str) -> &'static str {
+    match name.split('.').nth(segment).unwrap() {
+        "b" | "w" => byte_word_feature,
+        _ => "avx512f",
+    }
+}
@@ -14,6 +14,7 @@ use helpers::bool_to_simd_element;
 mod aesni;
 mod avx;
 mod avx2;
+mod avx512;
 mod bmi;
 mod sse;
 mod sse2;
@@ -22,6 +23,8 @@ mod sse41;
 mod sse42;
 mod ssse3;
 
+pub mod x87;
+
 impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
 pub(super) trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     fn emulate_x86_intrinsic(
@@ -150,6 +153,11 @@ pub(super) trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     this, link_name, abi, args, dest,
                 );
             }
+            name if name.starts_with("avx512.") => {
+                return avx512::EvalContextExt::emulate_x86_avx512_intrinsic(
+                    this, link_name, abi, args, dest,
+                );
+            }
 
             _ => return Ok(EmulateItemResult::NotSupported),
         }
@@ -430,6 +438,7 @@ fn apply_random_float_error<F: rustc_apfloat::Float>(
     let err = F::from_u128(rng.gen::<u64>().into()).value.scalbn(err_scale.strict_sub(64));
     // give it a random sign
     let err = if rng.gen::<bool>() { -err } else { err };
+    this.machine.log_nondeterminism("float-error", || format!("picked relative error {err}"));
     // multiple the value with (1+err)
     (val * (F::from_u128(1).value + err).value).value
 }
@@ -1035,6 +1044,94 @@ fn mask_store<'tcx>(
     Ok(())
 }
 
+/// Gathers elements from `slice` using `offsets * scale` as byte offsets.
+/// Elements for which `active` is false are copied from `src` instead.
+/// `slice` does not need to be aligned.
+///
+/// There are cases like dest: i32x4, offsets: i64x2. If `dest` has more
+/// elements than `offsets`, the extra elements are filled with zero. If
+/// `offsets` has more elements than `dest`, the extra offsets are ignored.
+fn gather<'tcx>(
+    this: &mut crate::MiriInterpCx<'tcx>,
+    src: &OpTy<'tcx>,
+    slice: &OpTy<'tcx>,
+    offsets: &OpTy<'tcx>,
+    active: &[bool],
+    scale: i64,
+    dest: &MPlaceTy<'tcx>,
+) -> InterpResult<'tcx, ()> {
+    assert_eq!(dest.layout, src.layout);
+
+    let (src, _) = this.operand_to_simd(src)?;
+    let (offsets, offsets_len) = this.operand_to_simd(offsets)?;
+    let (dest, dest_len) = this.mplace_to_simd(dest)?;
+
+    let actual_len = dest_len.min(offsets_len);
+
+    assert_eq!(dest_len, u64::try_from(active.len()).unwrap());
+
+    if !matches!(scale, 1 | 2 | 4 | 8) {
+        panic!("invalid gather scale {scale}");
+    }
+
+    let slice = this.read_pointer(slice)?;
+    for i in 0..actual_len {
+        let dest = this.project_index(&dest, i)?;
+
+        if active[usize::try_from(i).unwrap()] {
+            let offset = this.project_index(&offsets, i)?;
+            let offset =
+                i64::try_from(this.read_scalar(&offset)?.to_int(offset.layout.size)?).unwrap();
+            let ptr = slice.wrapping_signed_offset(offset.strict_mul(scale), &this.tcx);
+            // Unaligned copy, which is what we want.
+            this.mem_copy(ptr, dest.ptr(), dest.layout.size, /*nonoverlapping*/ true)?;
+        } else {
+            this.copy_op(&this.project_index(&src, i)?, &dest)?;
+        }
+    }
+    for i in actual_len..dest_len {
+        let dest = this.project_index(&dest, i)?;
+        this.write_scalar(Scalar::from_int(0, dest.layout.size), &dest)?;
+    }
+
+    Ok(())
+}
+
+/// Shuffles bytes from `left` using `right` as pattern. Each 128-bit
+/// block is shuffled independently. If the highest bit of an element of
+/// `right` is 1, zero is written instead.
+fn pshufb<'tcx>(
+    this: &mut crate::MiriInterpCx<'tcx>,
+    left: &OpTy<'tcx>,
+    right: &OpTy<'tcx>,
+    dest: &MPlaceTy<'tcx>,
+) -> InterpResult<'tcx, ()> {
+    let (left, left_len) = this.operand_to_simd(left)?;
+    let (right, right_len) = this.operand_to_simd(right)?;
+    let (dest, dest_len) = this.mplace_to_simd(dest)?;
+
+    assert_eq!(dest_len, left_len);
+    assert_eq!(dest_len, right_len);
+
+    for i in 0..dest_len {
+        let right = this.read_scalar(&this.project_index(&right, i)?)?.to_u8()?;
+        let dest = this.project_index(&dest, i)?;
+
+        let res = if right & 0x80 == 0 {
+            // Shuffle each 128-bit (16-byte) block independently.
+            let j = u64::from(right % 16).strict_add(i & !15);
+            this.read_scalar(&this.project_index(&left, j)?)?
+        } else {
+            // If the highest bit in `right` is 1, write zero.
+            Scalar::from_u8(0)
+        };
+
+        this.write_scalar(res, &dest)?;
+    }
+
+    Ok(())
+}
+
 /// Compute the sum of absolute differences of quadruplets of unsigned
 /// 8-bit integers in `left` and `right`, and store the 16-bit results
 /// in `right`. Quadruplets are selected from `left` and `right` with
@@ -0,0 +1,266 @@
+//! Support for C's `long double`, on the targets where it is the x87 80-bit extended precision
+//! format (all x86 targets, except for the MSVC ones, where `long double` is just `double`).
+//!
+//! Rust has no type for this format, so bindings to C functions that take or return a
+//! `long double` (like the ones bindgen generates) use some other type of the same size instead,
+//! usually `u128`. We accept any type of the right size; the first 10 bytes of the value are the
+//! 80-bit float, the rest is padding.
+
+use either::Either;
+use rustc_apfloat::ieee::{Double, X87DoubleExtended};
+use rustc_apfloat::{Float, FloatConvert, Round};
+use rustc_span::Symbol;
+use rustc_target::abi::Size;
+use rustc_target::spec::abi::Abi;
+
+use crate::*;
+use helpers::{ToHost, ToSoft};
+
+/// The number of bytes of a `long double` that are not padding.
+const X87_BYTES: usize = 10;
+
+/// Interprets the bytes of a `long double` (extended to 16 bytes), ignoring the padding.
+pub fn long_double_from_bytes(mut bytes: [u8; 16]) -> X87DoubleExtended {
+    bytes[X87_BYTES..].fill(0);
+    X87DoubleExtended::from_bits(u128::from_le_bytes(bytes))
+}
+
+/// Computes the correctly rounded (to nearest, ties to even) square root of `f`.
+fn sqrt_x87(f: X87DoubleExtended) -> X87DoubleExtended {
+    if f.is_nan() || f.is_zero() || (f.is_infinite() && !f.is_negative()) {
+        // This includes `sqrt(-0) = -0`.
+        return f;
+    }
+    if f.is_negative() {
+        return X87DoubleExtended::NAN;
+    }
+    // Split `f` into `m * 2^exp` with `m` in `[1, 4)` and `exp` even. Scaling by powers of two is
+    // exact, also for subnormals, and the final exponent is always in the normal range.
+    let mut exp = f.ilogb();
+    let mut m = f.scalbn(-exp);
+    if exp % 2 != 0 {
+        m = m.scalbn(1);
+        exp -= 1;
+    }
+    // `m` has 64 significant bits, so `m * 2^63` is an integer `n` below `2^65`, and
+    // `sqrt(m) * 2^63 = sqrt(n * 2^63)` is in `[2^63, 2^64)`.
+    let n = m.scalbn(63).to_u128(128).value << 63;
+    let mut root = n.isqrt();
+    // `root` is the truncated square root. Round it to nearest: the exact root is above
+    // `root + 1/2` iff `n > root^2 + root + 1/4`, i.e. iff `n - root^2 > root` since `n` is an
+    // integer. Ties are impossible since `(root + 1/2)^2` is never an integer.
+    if n - root * root > root {
+        root += 1;
+    }
+    // `root` is at most `2^64`, so it is representable exactly.
+    X87DoubleExtended::from_u128(root).value.scalbn(exp / 2 - 63)
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Whether `long double` is the x87 80-bit format on the current target.
+    fn long_double_is_x87(&self) -> bool {
+        let target = &self.eval_context_ref().tcx.sess.target;
+        (target.arch == "x86" || target.arch == "x86_64") && target.env != "msvc"
+    }
+
+    /// The size of a `long double` on the current target, including the padding.
+    fn long_double_size(&self) -> Size {
+        let target = &self.eval_context_ref().tcx.sess.target;
+        // On 32-bit x86, `long double` is only 4-aligned (except on Apple targets), so there are
+        // just 2 bytes of padding.
+        if target.arch == "x86" && !target.is_like_osx {
+            Size::from_bytes(12)
+        } else {
+            Size::from_bytes(16)
+        }
+    }
+
+    fn read_long_double(&self, op: &OpTy<'tcx>) -> InterpResult<'tcx, X87DoubleExtended> {
+        let this = self.eval_context_ref();
+        let size = this.long_double_size();
+        if op.layout.size != size {
+            throw_unsup_format!(
+                "a `long double` has {} bytes on this target, but it was passed as a `{}`",
+                size.bytes(),
+                op.layout.ty
+            );
+        }
+        let bytes = match op.as_mplace_or_imm() {
+            Either::Left(mplace) => {
+                // Only read the bytes that are not padding, the padding may be uninitialized.
+                let bytes = this
+                    .read_bytes_ptr_strip_provenance(mplace.ptr(), Size::from_bytes(X87_BYTES))?;
+                let mut buf = [0u8; 16];
+                buf[..bytes.len()].copy_from_slice(bytes);
+                buf
+            }
+            Either::Right(_) => this.read_scalar(op)?.to_uint(size)?.to_le_bytes(),
+        };
+        Ok(long_double_from_bytes(bytes))
+    }
+
+    fn write_long_double(
+        &mut self,
+        f: X87DoubleExtended,
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let size = this.long_double_size();
+        if dest.layout.size != size {
+            throw_unsup_format!(
+                "a `long double` has {} bytes on this target, but it was returned as a `{}`",
+                size.bytes(),
+                dest.layout.ty
+            );
+        }
+        // The padding is zeroed.
+        let bytes = f.to_bits().to_le_bytes();
+        this.write_bytes_ptr(dest.ptr(), bytes[..size.bytes_usize()].iter().copied())
+    }
+
+    fn emulate_x87_long_double_math(
+        &mut self,
+        link_name: Symbol,
+        abi: Abi,
+        args: &[OpTy<'tcx>],
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx, EmulateItemResult> {
+        let this = self.eval_context_mut();
+        match link_name.as_str() {
+            // These are correctly rounded, so we compute them exactly with soft-floats.
+            #[rustfmt::skip]
+            | "sqrtl"
+            | "fabsl"
+            | "floorl"
+            | "ceill"
+            | "truncl"
+            | "roundl"
+            | "rintl"
+            | "nearbyintl"
+            => {
+                let [f] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let f = this.read_long_double(f)?;
+                let res = match link_name.as_str() {
+                    "sqrtl" => sqrt_x87(f),
+                    "fabsl" => f.abs(),
+                    "floorl" => f.round_to_integral(Round::TowardNegative).value,
+                    "ceill" => f.round_to_integral(Round::TowardPositive).value,
+                    "truncl" => f.round_to_integral(Round::TowardZero).value,
+                    "roundl" => f.round_to_integral(Round::NearestTiesToAway).value,
+                    "rintl" | "nearbyintl" =>
+                        f.round_to_integral(Round::NearestTiesToEven).value,
+                    _ => bug!(),
+                };
+                // `fabs` is a bitwise operation, it does not touch the NaN payload.
+                let res =
+                    if link_name.as_str() == "fabsl" { res } else { this.adjust_nan(res, &[f]) };
+                this.write_long_double(res, dest)?;
+            }
+            "fmodl" | "fminl" | "fmaxl" | "copysignl" | "fdiml" => {
+                let [f1, f2] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let f1 = this.read_long_double(f1)?;
+                let f2 = this.read_long_double(f2)?;
+                let res = match link_name.as_str() {
+                    "fmodl" => (f1 % f2).value,
+                    "fminl" => f1.min(f2),
+                    "fmaxl" => f1.max(f2),
+                    "copysignl" => f1.copy_sign(f2),
+                    "fdiml" =>
+                        if f1.is_nan() || f2.is_nan() || f1 > f2 {
+                            (f1 - f2).value
+                        } else {
+                            X87DoubleExtended::ZERO
+                        },
+                    _ => bug!(),
+                };
+                // `copysign` does not touch the NaN payload.
+                let res = if link_name.as_str() == "copysignl" {
+                    res
+                } else {
+                    this.adjust_nan(res, &[f1, f2])
+                };
+                this.write_long_double(res, dest)?;
+            }
+            "ldexpl" | "scalbnl" => {
+                let [x, exp] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let x = this.read_long_double(x)?;
+                let exp = this.read_scalar(exp)?.to_i32()?;
+                let res = x.scalbn(exp);
+                let res = this.adjust_nan(res, &[x]);
+                this.write_long_double(res, dest)?;
+            }
+
+            // There are no host floats with this much precision, so these are computed with
+            // `f64`. That is less precise than the real thing, but these operations do not have
+            // guaranteed precision anyway.
+            #[rustfmt::skip]
+            | "cbrtl"
+            | "sinl"
+            | "cosl"
+            | "tanl"
+            | "asinl"
+            | "acosl"
+            | "atanl"
+            | "sinhl"
+            | "coshl"
+            | "tanhl"
+            | "expl"
+            | "exp2l"
+            | "expm1l"
+            | "logl"
+            | "log2l"
+            | "log10l"
+            | "log1pl"
+            => {
+                let [f] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let f = this.read_long_double(f)?;
+                let f_host = f.convert::<Double>(&mut false).value.to_host();
+                let res = match link_name.as_str() {
+                    "cbrtl" => f_host.cbrt(),
+                    "sinl" => f_host.sin(),
+                    "cosl" => f_host.cos(),
+                    "tanl" => f_host.tan(),
+                    "asinl" => f_host.asin(),
+                    "acosl" => f_host.acos(),
+                    "atanl" => f_host.atan(),
+                    "sinhl" => f_host.sinh(),
+                    "coshl" => f_host.cosh(),
+                    "tanhl" => f_host.tanh(),
+                    "expl" => f_host.exp(),
+                    "exp2l" => f_host.exp2(),
+                    "expm1l" => f_host.exp_m1(),
+                    "logl" => f_host.ln(),
+                    "log2l" => f_host.log2(),
+                    "log10l" => f_host.log10(),
+                    "log1pl" => f_host.ln_1p(),
+                    _ => bug!(),
+                };
+                let res: X87DoubleExtended = res.to_soft().convert(&mut false).value;
+                let res = this.apply_random_float_error_ulp(res, link_name.as_str());
+                let res = this.adjust_nan(res, &[f]);
+                this.write_long_double(res, dest)?;
+            }
+            "powl" | "atan2l" | "hypotl" => {
+                let [f1, f2] = this.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+                let f1 = this.read_long_double(f1)?;
+                let f2 = this.read_long_double(f2)?;
+                let f1_host = f1.convert::<Double>(&mut false).value.to_host();
+                let f2_host = f2.convert::<Double>(&mut false).value.to_host();
+                let res = match link_name.as_str() {
+                    "powl" => f1_host.powf(f2_host),
+                    "atan2l" => f1_host.atan2(f2_host),
+                    "hypotl" => f1_host.hypot(f2_host),
+                    _ => bug!(),
+                };
+                let res: X87DoubleExtended = res.to_soft().convert(&mut false).value;
+                let res = this.apply_random_float_error_ulp(res, link_name.as_str());
+                let res = this.adjust_nan(res, &[f1, f2]);
+                this.write_long_double(res, dest)?;
+            }
+
+            _ => return Ok(EmulateItemResult::NotSupported),
+        }
+        Ok(EmulateItemResult::NeedsReturn)
+    }
+}
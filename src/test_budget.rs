@@ -0,0 +1,136 @@
+//! Per-test execution budgets, for `-Zmiri-test-max-blocks` and `-Zmiri-test-timeout`.
+//!
+//! libtest runs the body of every test (and benchmark) through
+//! `test::__rust_begin_short_backtrace`, on a thread that is named after the test. So a test starts
+//! when a frame of that function is pushed, and ends when that frame is popped again. While a test
+//! runs, all basic blocks that are executed count towards its budget, no matter on which thread
+//! they are executed, since a test that hangs may well do so on a thread it spawned.
+//!
+//! A test that exceeds its budget is made to panic on its own thread the next time that thread
+//! gets to run, so libtest reports it as failed and goes on with the other tests. If that thread
+//! is blocked, it cannot panic, so then we stop the entire program instead.
+
+use std::time::Instant;
+
+use rustc_middle::mir;
+use rustc_span::sym;
+
+use crate::*;
+
+/// A test that is currently running.
+#[derive(Debug)]
+pub struct RunningTest {
+    /// The thread the test runs on.
+    thread: ThreadId,
+    /// The length of the stack of `thread` with the test's frame on top; the test is over once the
+    /// stack is shorter than this.
+    stack_len: usize,
+    /// The value of `basic_block_count` when the test started.
+    start_block: u64,
+    /// The wall-clock time when the test started.
+    start_time: Instant,
+    /// The budget and the flag that set it, once the test exceeded its budget.
+    exceeded: Option<(String, &'static str)>,
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Called after a frame was pushed: if this is the start of a test, start tracking it.
+    fn record_test_start(&mut self) {
+        let this = self.eval_context_mut();
+        let def_id = this.frame().instance.def_id();
+        if this.tcx.crate_name(def_id.krate) != sym::test
+            || this
+                .tcx
+                .opt_item_name(def_id)
+                .is_none_or(|name| name.as_str() != "__rust_begin_short_backtrace")
+        {
+            return;
+        }
+        let test = RunningTest {
+            thread: this.active_thread(),
+            stack_len: this.active_thread_stack().len(),
+            start_block: this.machine.basic_block_count,
+            start_time: Instant::now(),
+            exceeded: None,
+        };
+        this.machine.running_tests.push(test);
+    }
+
+    /// Called after a frame was popped: stop tracking the tests that are over.
+    fn record_test_end(&mut self) {
+        let this = self.eval_context_mut();
+        let thread = this.active_thread();
+        let stack_len = this.active_thread_stack().len();
+        this.machine
+            .running_tests
+            .retain(|test| test.thread != thread || test.stack_len <= stack_len);
+    }
+
+    /// Mark the running tests that exceeded their budget, so that `fail_tests_over_budget` makes
+    /// them panic. If the thread of such a test is blocked, stop the program instead.
+    /// `check_time` says whether to check the timeout, which is not free.
+    fn check_test_budgets(&mut self, check_time: bool) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let blocks = this.machine.basic_block_count;
+        let max_blocks = this.machine.test_max_blocks;
+        let timeout = this.machine.test_timeout;
+        for test in &mut this.machine.running_tests {
+            if test.exceeded.is_some() {
+                continue;
+            }
+            if let Some(max_blocks) = max_blocks
+                && blocks - test.start_block > max_blocks
+            {
+                test.exceeded =
+                    Some((format!("{max_blocks} basic blocks"), "-Zmiri-test-max-blocks"));
+            } else if let Some(timeout) = timeout
+                && check_time
+                && test.start_time.elapsed() >= timeout
+            {
+                test.exceeded =
+                    Some((format!("{} seconds", timeout.as_secs()), "-Zmiri-test-timeout"));
+            }
+        }
+        let threads = &this.machine.threads;
+        if let Some(test) = this.machine.running_tests.iter().find(|test| {
+            test.exceeded.is_some() && threads.blocked_threads().any(|(id, _)| id == test.thread)
+        }) {
+            let (budget, flag) = test.exceeded.clone().unwrap();
+            throw_machine_stop!(TerminationInfo::BudgetExceeded {
+                budget,
+                flag,
+                test: Some(threads.get_thread_display_name(test.thread)),
+            });
+        }
+        Ok(())
+    }
+
+    /// Called before the active thread executes a step: if it runs a test that exceeded its
+    /// budget, make the test panic.
+    fn fail_tests_over_budget(&mut self) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let thread = this.active_thread();
+        let Some(idx) = this
+            .machine
+            .running_tests
+            .iter()
+            .position(|test| test.thread == thread && test.exceeded.is_some())
+        else {
+            return Ok(());
+        };
+        // Only start the panic at a regular program point, not while the thread is unwinding.
+        if this.frame().current_loc().is_right() {
+            return Ok(());
+        }
+        let test = this.machine.running_tests.remove(idx);
+        let (budget, flag) = test.exceeded.unwrap();
+        let name = this.machine.threads.get_thread_display_name(thread);
+        // The frame that we interrupt does not get to run its cleanup, so its locals leak; the
+        // frames above it unwind as usual.
+        this.start_panic(
+            &format!("test `{name}` exceeded its budget of {budget} (set with `{flag}`)"),
+            mir::UnwindAction::Continue,
+        )
+    }
+}
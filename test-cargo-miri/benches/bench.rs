@@ -0,0 +1,23 @@
+#![feature(test)]
+
+extern crate test;
+
+use test::Bencher;
+
+#[bench]
+fn bench_sum(b: &mut Bencher) {
+    // `cargo miri bench` keeps debug assertions enabled.
+    assert!(cfg!(debug_assertions));
+    let mut calls = 0;
+    b.iter(|| {
+        calls += 1;
+        (0..10u32).sum::<u32>()
+    });
+    // Miri does not measure benchmarks, it runs them just once.
+    assert_eq!(calls, 1);
+}
+
+#[test]
+fn check_sum() {
+    panic!("`cargo miri bench` must only run benchmarks");
+}
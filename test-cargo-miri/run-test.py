@@ -6,11 +6,18 @@ and the working directory to contain the cargo-miri-test project.
 '''
 
 import difflib
+import json
 import os
+import platform
 import re
+import shutil
+import signal
 import subprocess
 import sys
 import argparse
+import tempfile
+import threading
+import time
 
 CGREEN  = '\33[32m'
 CBOLD   = '\33[1m'
@@ -32,7 +39,9 @@ def cargo_miri(cmd, quiet = True):
 
 def normalize_stdout(str):
     str = str.replace("src\\", "src/") # normalize paths across platforms
+    str = str.replace("\r\n", "\n") # the `-Zmiri-server` framing uses CRLF
     str = re.sub("finished in \\d+\\.\\d\\ds", "finished in $TIME", str) # the time keeps changing, obviously
+    str = re.sub("\"exec_time\": \\d+(\\.\\d+)?(e-?\\d+)?", "\"exec_time\": $TIME", str) # same for libtest's JSON output
     return str
 
 def check_output(actual, path, name):
@@ -76,6 +85,25 @@ def test(name, cmd, stdout_ref, stderr_ref, stdin=b'', env=None):
         return
     fail("exit code was {}".format(p.returncode))
 
+def test_junit(name, cmd, xml_ref, env=None):
+    if env is None:
+        env = {}
+    print("Testing {}...".format(name))
+    with tempfile.TemporaryDirectory() as junit_dir:
+        p_env = os.environ.copy()
+        p_env.update(env)
+        p_env['MIRIFLAGS'] = p_env.get('MIRIFLAGS', '') + " -Zmiri-junit=" + junit_dir
+        # The tests may fail, that is what the report is about.
+        subprocess.run(cmd, stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL, env=p_env)
+        reports = os.listdir(junit_dir)
+        if len(reports) != 1:
+            fail("expected one JUnit report, found {}".format(reports))
+        report = open(os.path.join(junit_dir, reports[0])).read()
+    report = normalize_stdout(report)
+    report = re.sub("time=\"\\d+\\.\\d+\"", "time=\"$TIME\"", report)
+    if not check_output(report, xml_ref, "JUnit report"):
+        fail("JUnit report did not match")
+
 def test_no_rebuild(name, cmd, env=None):
     if env is None:
         env = {}
@@ -100,6 +128,113 @@ def test_no_rebuild(name, cmd, env=None):
         print("--- END stderr ---")
         fail("Something was being rebuilt when it should not be (or we got no output)")
 
+def test_deterministic(name, cmd, env=None):
+    '''Runs `cmd` twice, the second time with ASLR disabled, and checks that the output and the
+    `-Zmiri-trace` log of both runs are the same.'''
+    if env is None:
+        env = {}
+    print("Testing {}...".format(name))
+    no_aslr = ["setarch", platform.machine(), "-R"] if shutil.which("setarch") else []
+    runs = []
+    with tempfile.TemporaryDirectory() as trace_dir:
+        for (i, prefix) in enumerate([[], no_aslr]):
+            trace = os.path.join(trace_dir, "{}.trace".format(i))
+            p_env = os.environ.copy()
+            p_env.update(env)
+            p_env['MIRIFLAGS'] = p_env.get('MIRIFLAGS', '') + " -Zmiri-trace=all -Zmiri-trace-out=" + trace
+            p = subprocess.run(prefix + cmd, stdout=subprocess.PIPE, stderr=subprocess.PIPE, env=p_env)
+            if p.returncode != 0:
+                fail("exit code was {}".format(p.returncode))
+            runs.append((p.stdout.decode("UTF-8"), p.stderr.decode("UTF-8"), open(trace).read()))
+    if "[alloc#" not in runs[0][2]:
+        fail("the trace does not show allocation numbers")
+    for (what, first, second) in zip(["stdout", "stderr", "trace"], runs[0], runs[1]):
+        if first != second:
+            print(f"--- BEGIN diff {what} ---")
+            for text in difflib.unified_diff(first.split("\n"), second.split("\n")):
+                print(text)
+            print(f"--- END diff {what} ---")
+            fail("the {} of the two runs differs".format(what))
+
+def test_exit_code(name, cmd, expected, env=None):
+    '''Runs `cmd` and checks that it exits with the `expected` code. The output is not checked.'''
+    if env is None:
+        env = {}
+    print("Testing {}...".format(name))
+    p_env = os.environ.copy()
+    p_env.update(env)
+    p = subprocess.run(cmd, stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL, env=p_env)
+    if p.returncode != expected:
+        fail("exit code was {}, expected {}".format(p.returncode, expected))
+
+def test_watch(name, cmd, touch, env=None):
+    '''Runs `cmd`, which must be a `cargo miri watch` command, and checks that it runs again after
+    `touch` is modified, but not before.'''
+    if env is None:
+        env = {}
+    print("Testing {}...".format(name))
+    p_env = os.environ.copy()
+    p_env.update(env)
+    # The watcher never exits by itself, and we have to stop the `cargo-miri` process that `cargo`
+    # spawns as well, so give them their own process group.
+    p = subprocess.Popen(cmd, stdout=subprocess.DEVNULL, stderr=subprocess.PIPE, env=p_env,
+        start_new_session=True)
+    runs = []
+    def read_stderr():
+        for line in p.stderr:
+            if line.startswith(b"[cargo-miri watch] "):
+                runs.append(line.decode("UTF-8").strip())
+    reader = threading.Thread(target=read_stderr, daemon=True)
+    reader.start()
+    def wait_for_runs(count, timeout):
+        deadline = time.monotonic() + timeout
+        while len(runs) < count and time.monotonic() < deadline and p.poll() is None:
+            time.sleep(0.1)
+    try:
+        wait_for_runs(1, 300)
+        # Give the watcher a few polls to (wrongly) notice the files written by the run.
+        time.sleep(3)
+        if len(runs) != 1:
+            fail("expected one run before any change, got {}".format(runs))
+        os.utime(touch)
+        wait_for_runs(2, 300)
+        if len(runs) != 2:
+            fail("expected a second run after touching {}, got {}".format(touch, runs))
+        if any("finished" not in run for run in runs):
+            fail("a run failed: {}".format(runs))
+    finally:
+        if os.name == "posix":
+            os.killpg(p.pid, signal.SIGKILL)
+        else:
+            p.kill()
+        p.wait()
+
+def server_requests(*requests):
+    msgs = b''
+    for (id, method, params) in enumerate(requests, start=1):
+        body = json.dumps({"jsonrpc": "2.0", "id": id, "method": method, "params": params}).encode()
+        msgs += b"Content-Length: %d\r\n\r\n%s" % (len(body), body)
+    return msgs
+
+def dap_requests(*requests):
+    msgs = b''
+    for (seq, (command, arguments)) in enumerate(requests, start=1):
+        body = json.dumps({"seq": seq, "type": "request", "command": command, "arguments": arguments}).encode()
+        msgs += b"Content-Length: %d\r\n\r\n%s" % (len(body), body)
+    return msgs
+
+def gdb_packets(*packets):
+    '''Frames GDB remote protocol packets. Each packet comes with the number of packets Miri sends
+    in response before it reads the next one, since each of those needs an acknowledgment.'''
+    msgs = b''
+    for (packet, replies) in packets:
+        packet = packet.encode()
+        msgs += b"$%s#%02x" % (packet, sum(packet) % 256) + b"+" * replies
+    return msgs
+
+def gdb_monitor(cmd):
+    return "qRcmd," + cmd.encode().hex()
+
 def test_cargo_miri_run():
     test("`cargo miri run` (no isolation)",
         cargo_miri("run"),
@@ -135,33 +270,106 @@ def test_cargo_miri_run():
          cargo_miri("run") + ["--package=test-local-crate-detection"],
          "run.local_crate.stdout.ref", "run.local_crate.stderr.ref",
     )
+    # The first request fails to start; that must not affect the second one.
+    test("`cargo miri run` (server)",
+        cargo_miri("run") + ["--bin", "cargo-miri-test"],
+        "run.server.stdout.ref", "test.stderr-empty.ref",
+        stdin=server_requests(
+            ("run", {"function": "nope"}),
+            ("run", {"args": ["hello"]}),
+            ("shutdown", None),
+        ),
+        env={'MIRIFLAGS': "-Zmiri-server"},
+    )
+    test("`cargo miri run` (GDB)",
+        cargo_miri("run") + ["--bin", "cargo-miri-test"],
+        "run.gdb.stdout.ref", "test.stderr-empty.ref",
+        stdin=gdb_packets(
+            (gdb_monitor("break debugger_check"), 2),
+            ("c", 1), # stops at the breakpoint
+            (gdb_monitor("locals"), 3),
+            ("c", 1), # the program exits
+        ),
+        env={'MIRIFLAGS': "-Zmiri-entry-fn=debugger_break -Zmiri-gdb=stdio"},
+    )
+    test("`cargo miri run` (DAP)",
+        cargo_miri("run") + ["--bin", "cargo-miri-test"],
+        "run.dap.stdout.ref", "test.stderr-empty.ref",
+        stdin=dap_requests(
+            ("initialize", {"adapterID": "miri"}),
+            ("setFunctionBreakpoints", {"breakpoints": [{"name": "debugger_check"}]}),
+            ("configurationDone", {}),
+            # The program stops at the breakpoint; inspect the locals of the innermost frame.
+            ("scopes", {"frameId": 0}),
+            ("variables", {"variablesReference": 1}),
+            ("continue", {"threadId": 0}),
+        ),
+        env={'MIRIFLAGS': "-Zmiri-entry-fn=debugger_break -Zmiri-dap=stdio"},
+    )
+    test("`cargo miri run` (`miri_breakpoint` REPL)",
+        cargo_miri("run") + ["--bin", "cargo-miri-test"],
+        "test.stdout-empty.ref", "run.repl.stderr.ref",
+        stdin=b'locals\ncontinue\n',
+        env={'MIRIFLAGS': "-Zmiri-entry-fn=debugger_repl"},
+    )
+    test_deterministic("`cargo miri run` (strict determinism)",
+        cargo_miri("run") + ["--bin", "cargo-miri-test"],
+        env={'MIRIFLAGS': "-Zmiri-entry-fn=strict_determinism -Zmiri-strict-determinism"},
+    )
+    for (flags, entry_fn, expected) in [
+        ("", "exit_code_leak", 1),
+        ("-Zmiri-error-exit-codes", "exit_code_leak", 87),
+        ("-Zmiri-error-exit-codes", "exit_code_ub", 80),
+        ("-Zmiri-error-exit-codes=leak=3", "exit_code_leak", 3),
+        ("-Zmiri-error-exit-codes=leak=3", "exit_code_ub", 80),
+    ]:
+        test_exit_code("`cargo miri run` ({} with `{}`)".format(entry_fn, flags or "no flags"),
+            cargo_miri("run") + ["--bin", "cargo-miri-test"],
+            expected,
+            env={'MIRIFLAGS': "-Zmiri-entry-fn={} {}".format(entry_fn, flags)},
+        )
+    test_watch("`cargo miri watch run`",
+        # `watch` has to come right before the subcommand it watches.
+        ["cargo", "miri", "watch"] + cargo_miri("run")[2:] + ["--bin", "cargo-miri-test"],
+        "src/main.rs",
+        env={'MIRIFLAGS': "-Zmiri-entry-fn=watch_output -Zmiri-disable-isolation"},
+    )
 
 def test_cargo_miri_test():
-    # rustdoc is not run on foreign targets
-    is_foreign = ARGS.target is not None
-    default_ref = "test.cross-target.stdout.ref" if is_foreign else "test.default.stdout.ref"
-    filter_ref = "test.filter.cross-target.stdout.ref" if is_foreign else "test.filter.stdout.ref"
-
     # macOS needs permissive provenance inside getrandom_1.
     test("`cargo miri test`",
         cargo_miri("test"),
-        default_ref, "test.stderr-empty.ref",
+        "test.default.stdout.ref", "test.stderr-empty.ref",
         env={'MIRIFLAGS': "-Zmiri-permissive-provenance -Zmiri-seed=4242"},
     )
     test("`cargo miri test` (no isolation, no doctests)",
         cargo_miri("test") + ["--bins", "--tests"], # no `--lib`, we disabled that in `Cargo.toml`
-        "test.cross-target.stdout.ref", "test.stderr-empty.ref",
+        "test.no-doctests.stdout.ref", "test.stderr-empty.ref",
         env={'MIRIFLAGS': "-Zmiri-permissive-provenance -Zmiri-disable-isolation"},
     )
     test("`cargo miri test` (with filter)",
         cargo_miri("test") + ["--", "--format=pretty", "pl"],
-        filter_ref, "test.stderr-empty.ref",
+        "test.filter.stdout.ref", "test.stderr-empty.ref",
     )
     test("`cargo miri test` (test target)",
         cargo_miri("test") + ["--test", "test", "--", "--format=pretty"],
         "test.test-target.stdout.ref", "test.stderr-empty.ref",
         env={'MIRIFLAGS': "-Zmiri-permissive-provenance"},
     )
+    test("`cargo miri test` (JSON output)",
+        cargo_miri("test") + ["--test", "test", "--", "--format=json", "simple"],
+        "test.json.stdout.ref", "test.stderr-empty.ref",
+    )
+    test("`cargo miri test` (JSON output, with `-Zunstable-options`)",
+        cargo_miri("test") + ["--test", "test", "--", "-Zunstable-options", "--format=json", "simple"],
+        "test.json.stdout.ref", "test.stderr-empty.ref",
+    )
+    # The test that uses inline assembly is unsupported on x86, which makes it a JUnit error.
+    if (ARGS.target or platform.machine()).split("-")[0] in ["x86_64", "AMD64", "i686"]:
+        test_junit("`cargo miri test` (JUnit report)",
+            cargo_miri("test") + ["--test", "test", "--", "--include-ignored", "simple", "does_not_work_on_miri"],
+            "test.junit.xml.ref",
+        )
     test("`cargo miri test` (bin target)",
         cargo_miri("test") + ["--bin", "cargo-miri-test", "--", "--format=pretty"],
         "test.bin-target.stdout.ref", "test.stderr-empty.ref",
@@ -177,16 +385,91 @@ def test_cargo_miri_test():
     )
     test("`cargo miri test` (custom target dir)",
         cargo_miri("test") + ["--target-dir=custom-test"],
-        default_ref, "test.stderr-empty.ref",
+        "test.default.stdout.ref", "test.stderr-empty.ref",
         env={'MIRIFLAGS': "-Zmiri-permissive-provenance"},
     )
     del os.environ["CARGO_TARGET_DIR"] # this overrides `build.target-dir` passed by `--config`, so unset it
     test("`cargo miri test` (config-cli)",
         cargo_miri("test") + ["--config=build.target-dir=\"config-cli\""],
-        default_ref, "test.stderr-empty.ref",
+        "test.default.stdout.ref", "test.stderr-empty.ref",
         env={'MIRIFLAGS': "-Zmiri-permissive-provenance"},
     )
 
+def test_cargo_miri_bench():
+    test("`cargo miri bench`",
+        cargo_miri("bench") + ["--bench", "bench"],
+        "bench.stdout.ref", "test.stderr-empty.ref",
+    )
+
+def test_sysroot_config():
+    '''Builds a sysroot with `MIRI_SYSROOT_STD_FEATURES` and `MIRI_SYSROOT_RUSTFLAGS`, checks that
+    it is rebuilt exactly when they change, and runs a program with it.'''
+    with tempfile.TemporaryDirectory() as sysroot:
+        def setup(name, env, rebuild):
+            print("Testing {}...".format(name))
+            p_env = os.environ.copy()
+            p_env.update(env)
+            p_env['MIRI_SYSROOT'] = sysroot
+            p = subprocess.run(cargo_miri("setup", quiet=False), stdout=subprocess.DEVNULL,
+                stderr=subprocess.PIPE, env=p_env)
+            if p.returncode != 0:
+                fail("exit code was {}".format(p.returncode))
+            if ("Preparing a sysroot" in p.stderr.decode("UTF-8")) != rebuild:
+                fail("the sysroot was {}rebuilt".format("not " if rebuild else ""))
+        features = {'MIRI_SYSROOT_STD_FEATURES': "panic_unwind"}
+        features_and_flags = dict(features, MIRI_SYSROOT_RUSTFLAGS="-Cdebug-assertions=on")
+        setup("`cargo miri setup` (std features)", features, rebuild=True)
+        setup("`cargo miri setup` (same std features)", features, rebuild=False)
+        setup("`cargo miri setup` (std features and rustflags)", features_and_flags, rebuild=True)
+        test("`cargo miri run` (configured sysroot)",
+            cargo_miri("run") + ["--bin", "cargo-miri-test", "hello world", '"hello world"', r'he\\llo\"world'],
+            "run.args.stdout.ref", "run.args.stderr.ref",
+            env={'MIRI_SYSROOT': sysroot},
+        )
+
+def test_multiple_targets():
+    '''Runs a test binary for the host and another target in one go, and checks that a sysroot is
+    prepared for both targets and that both test binaries run.'''
+    # The location of the sysroot cache is only easy to redirect on Linux.
+    if ARGS.target or platform.system() != "Linux":
+        return
+    print("Testing `cargo miri test` (several targets)...")
+    host = re.search("host: (.*)", subprocess.check_output(["rustc", "-vV"], text=True)).group(1)
+    other = "s390x-unknown-linux-gnu" if host.startswith("i686-") else "i686-unknown-linux-gnu"
+    targets = [host, other]
+    cmd = ["cargo", "miri", "test"] + CARGO_EXTRA_FLAGS
+    for target in targets:
+        cmd += ["--target", target]
+    cmd += ["--test", "test", "--", "simple"]
+    with tempfile.TemporaryDirectory() as cache:
+        # Make cargo-miri prepare fresh sysroots in the default location.
+        p_env = os.environ.copy()
+        p_env.pop('MIRI_SYSROOT', None)
+        p_env['XDG_CACHE_HOME'] = cache
+        p = subprocess.run(cmd, stdout=subprocess.PIPE, stderr=subprocess.PIPE, env=p_env)
+    stdout = p.stdout.decode("UTF-8")
+    stderr = p.stderr.decode("UTF-8")
+    def show_output():
+        print("--- BEGIN stdout ---")
+        print(stdout, end="")
+        print("--- END stdout ---")
+        print("--- BEGIN stderr ---")
+        print(stderr, end="")
+        print("--- END stderr ---")
+    if p.returncode != 0:
+        show_output()
+        fail("exit code was {}".format(p.returncode))
+    for target in targets:
+        if "Preparing a sysroot for Miri (target: {})".format(target) not in stderr:
+            show_output()
+            fail("no sysroot was prepared for {}".format(target))
+        if not re.search(" Running tests/test.rs \\(target/miri/{}/".format(re.escape(target)), stderr):
+            show_output()
+            fail("the test binary for {} did not run".format(target))
+    if stdout.count("test result: ok.") != len(targets):
+        show_output()
+        fail("expected a successful test run for each target")
+
 args_parser = argparse.ArgumentParser(description='`cargo miri` testing')
 args_parser.add_argument('--target', help='the target to test')
 args_parser.add_argument('--bless', help='bless the reference files', action='store_true')
@@ -202,6 +485,9 @@ print(CGREEN + CBOLD + "## Running `cargo miri` tests{}".format(target_str) + CE
 
 test_cargo_miri_run()
 test_cargo_miri_test()
+test_cargo_miri_bench()
+test_sysroot_config()
+test_multiple_targets()
 
 # Ensure we did not create anything outside the expected target dir.
 for target_dir in ["target", "custom-run", "custom-test", "config-cli"]:
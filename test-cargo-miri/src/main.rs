@@ -62,6 +62,62 @@ fn main() {
     }
 }
 
+/// Run with `-Zmiri-entry-fn` by the debugger tests, which set a breakpoint on `debugger_check`.
+#[allow(dead_code)]
+fn debugger_break() {
+    let answer = 42u32;
+    debugger_check(answer);
+}
+
+#[allow(dead_code)]
+fn debugger_check(answer: u32) {
+    let doubled = answer * 2;
+    assert_eq!(doubled, 84);
+}
+
+/// Run with `-Zmiri-entry-fn` by the test of the REPL that `miri_breakpoint` drops into.
+#[allow(dead_code)]
+fn debugger_repl() {
+    extern "Rust" {
+        fn miri_breakpoint();
+    }
+    let answer = 42u32;
+    unsafe { miri_breakpoint() };
+    assert_eq!(answer, 42);
+}
+
+/// Run with `-Zmiri-entry-fn` by the test of `-Zmiri-strict-determinism`, which runs it twice and
+/// compares the output.
+#[allow(dead_code)]
+fn strict_determinism() {
+    let boxed = Box::new(42u32);
+    let vec = vec![1u8, 2, 3];
+    println!("{:p} {:p}", boxed, vec.as_ptr());
+    println!("{:#x}", vec.as_ptr() as usize);
+}
+
+/// Run with `-Zmiri-entry-fn` by the tests of `-Zmiri-error-exit-codes`.
+#[allow(dead_code)]
+fn exit_code_leak() {
+    std::mem::forget(Box::new(42u32));
+}
+
+/// Run with `-Zmiri-entry-fn` by the tests of `-Zmiri-error-exit-codes`.
+#[allow(dead_code)]
+fn exit_code_ub() {
+    let boxed = Box::new(42u32);
+    let ptr = &*boxed as *const u32;
+    drop(boxed);
+    let _val = unsafe { *ptr };
+}
+
+/// Run with `-Zmiri-entry-fn` by the test of `cargo miri watch`: writing into the workspace must
+/// not make it run the program again.
+#[allow(dead_code)]
+fn watch_output() {
+    std::fs::write("watch.real", "written by the program\n").unwrap();
+}
+
 #[cfg(test)]
 mod test {
     use byteorder_2::{BigEndian, ByteOrder};
@@ -0,0 +1,344 @@
+//! Tests for running programs with Miri as a library, see `src/embed.rs`.
+#![feature(rustc_private)]
+
+extern crate rustc_driver;
+extern crate rustc_middle;
+extern crate rustc_span;
+extern crate rustc_target;
+
+use std::env;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Symbol;
+use rustc_target::spec::abi::Abi;
+
+use miri::{
+    run_program, EmulateItemResult, EvalContextExt as _, HeapProfileFormat, InterpResult,
+    MPlaceTy, MiriCallbacks, MiriConfig, MiriErrorKind, MiriInterpCx, MiriOutcome, OpTy, Scalar,
+    ShimHook, TraceMode,
+};
+
+/// Run `source` as the main file of a binary crate.
+fn run(source: &str, config: MiriConfig, callbacks: impl MiriCallbacks) -> MiriOutcome {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("main.rs");
+    fs::write(&file, source).unwrap();
+    let sysroot =
+        env::var("MIRI_SYSROOT").expect("MIRI_SYSROOT must be set to run the embed tests");
+    let args = vec![
+        "miri".to_owned(),
+        file.display().to_string(),
+        format!("--sysroot={sysroot}"),
+        "--edition=2021".to_owned(),
+    ];
+    run_program(args, config, callbacks)
+}
+
+fn exit_code() {
+    let outcome = run("fn main() { std::process::exit(42) }", MiriConfig::default(), ());
+    assert!(matches!(outcome, MiriOutcome::Exit(42)), "{outcome:?}");
+}
+
+fn undefined_behavior() {
+    let source = r#"
+        fn main() {
+            let b = Box::new(0u8);
+            let p = &*b as *const u8;
+            drop(b);
+            let _x = unsafe { *p };
+        }
+    "#;
+    let outcome = run(source, MiriConfig::default(), ());
+    let MiriOutcome::Error(error) = outcome else { panic!("{outcome:?}") };
+    assert_eq!(error.kind, MiriErrorKind::UndefinedBehavior);
+    assert!(error.message.contains("has been freed"), "{}", error.message);
+}
+
+fn compilation_failed() {
+    let outcome = run("fn main() { let x: u8 = \"no\"; }", MiriConfig::default(), ());
+    assert!(matches!(outcome, MiriOutcome::CompilationFailed), "{outcome:?}");
+}
+
+/// Passes an argument to the program, and records the outcome.
+#[derive(Default)]
+struct ArgCallbacks {
+    outcome: Option<MiriOutcome>,
+}
+
+impl MiriCallbacks for &mut ArgCallbacks {
+    fn before_run<'tcx>(&mut self, _tcx: TyCtxt<'tcx>, config: &mut MiriConfig) {
+        config.args.push("7".to_owned());
+    }
+
+    fn after_run<'tcx>(&mut self, _tcx: TyCtxt<'tcx>, outcome: &MiriOutcome) {
+        self.outcome = Some(outcome.clone());
+    }
+}
+
+fn callbacks() {
+    let source = r#"
+        fn main() {
+            let code: i32 = std::env::args().nth(1).unwrap().parse().unwrap();
+            std::process::exit(code)
+        }
+    "#;
+    let mut callbacks = ArgCallbacks::default();
+    let outcome = run(source, MiriConfig::default(), &mut callbacks);
+    assert!(matches!(outcome, MiriOutcome::Exit(7)), "{outcome:?}");
+    assert!(matches!(callbacks.outcome, Some(MiriOutcome::Exit(7))), "{:?}", callbacks.outcome);
+}
+
+fn measureme_events() {
+    let source = r#"
+        fn main() {
+            let b = Box::new([0u8; 3]);
+            let r = &*b;
+            assert_eq!(r[0], 0);
+        }
+    "#;
+    let out = tempfile::tempdir().unwrap();
+    let config = MiriConfig {
+        measureme_out: Some(out.path().display().to_string()),
+        ..MiriConfig::default()
+    };
+    let outcome = run(source, config, ());
+    assert!(matches!(outcome, MiriOutcome::Exit(0)), "{outcome:?}");
+    // The event kinds end up in the string table of the profile, which is stored as plain UTF-8.
+    let mut profile = Vec::new();
+    for entry in fs::read_dir(out.path()).unwrap() {
+        profile.extend(fs::read(entry.unwrap().path()).unwrap());
+    }
+    for kind in ["MiriAllocation", "MiriDeallocation", "MiriRetag"] {
+        assert!(
+            profile.windows(kind.len()).any(|window| window == kind.as_bytes()),
+            "no `{kind}` event in the profile"
+        );
+    }
+}
+
+fn coverage() {
+    let source = "\
+fn used() -> u32 {
+    1
+}
+fn unused() -> u32 {
+    2
+}
+fn main() {
+    assert_eq!(used(), 1);
+}
+";
+    let out = tempfile::tempdir().unwrap();
+    let config = MiriConfig {
+        coverage_out: Some(out.path().display().to_string()),
+        ..MiriConfig::default()
+    };
+    let outcome = run(source, config, ());
+    assert!(matches!(outcome, MiriOutcome::Exit(0)), "{outcome:?}");
+    let entry = fs::read_dir(out.path()).unwrap().next().unwrap().unwrap();
+    let report = fs::read_to_string(entry.path()).unwrap();
+    let lines: Vec<&str> = report.lines().collect();
+    for line in ["FNDA:1,used", "FNDA:0,unused", "FNDA:1,main", "DA:2,1", "DA:5,0", "FNH:2"] {
+        assert!(lines.contains(&line), "no `{line}` in the coverage report:\n{report}");
+    }
+}
+
+fn trace() {
+    let source = r#"
+        fn answer() -> u32 {
+            42
+        }
+
+        fn main() {
+            let t = std::thread::spawn(answer);
+            assert_eq!(t.join().unwrap(), 42);
+        }
+    "#;
+    let out = tempfile::tempdir().unwrap();
+    let trace_out = out.path().join("main.trace");
+    let config = MiriConfig {
+        trace: Some(TraceMode::All),
+        trace_out: Some(trace_out.display().to_string()),
+        ..MiriConfig::default()
+    };
+    let outcome = run(source, config, ());
+    assert!(matches!(outcome, MiriOutcome::Exit(0)), "{outcome:?}");
+    let trace = fs::read_to_string(trace_out).unwrap();
+    let has_line = |thread: &str, what: &str| {
+        trace.lines().any(|line| {
+            line.strip_prefix(thread).is_some_and(|rest| rest.trim_start().starts_with(what))
+        })
+    };
+    assert!(has_line("[main]", "call main"), "no call of `main` in the trace:\n{trace}");
+    assert!(has_line("[main]", "shim "), "no shim call in the trace:\n{trace}");
+    assert!(has_line("[unnamed-1]", "switched to thread `unnamed-1`"), "{trace}");
+    assert!(has_line("[unnamed-1]", "call answer"), "no call of `answer` in the trace:\n{trace}");
+}
+
+fn heap_profile() {
+    let source = r#"
+        fn make_buffer() -> Vec<u8> {
+            vec![0; 1000]
+        }
+
+        fn main() {
+            let buffer = make_buffer();
+            assert_eq!(buffer.len(), 1000);
+        }
+    "#;
+    for format in [HeapProfileFormat::Massif, HeapProfileFormat::Json] {
+        let out = tempfile::tempdir().unwrap();
+        let config = MiriConfig {
+            heap_profile_out: Some(out.path().display().to_string()),
+            heap_profile_format: format,
+            ..MiriConfig::default()
+        };
+        let outcome = run(source, config, ());
+        assert!(matches!(outcome, MiriOutcome::Exit(0)), "{outcome:?}");
+        let entry = fs::read_dir(out.path()).unwrap().next().unwrap().unwrap();
+        let profile = fs::read_to_string(entry.path()).unwrap();
+        match format {
+            HeapProfileFormat::Massif => {
+                assert!(profile.starts_with("desc: -Zmiri-heap-profile\n"), "{profile}");
+                assert!(profile.lines().any(|line| line == "heap_tree=peak"), "{profile}");
+                assert!(
+                    profile.lines().any(|line| line.starts_with(" n0: 1000 0x0: make_buffer (")),
+                    "{profile}"
+                );
+            }
+            HeapProfileFormat::Json => {
+                let profile: serde_json::Value = serde_json::from_str(&profile).unwrap();
+                assert!(profile["peak"]["bytes"].as_u64().unwrap() >= 1000, "{profile:#}");
+                let site = profile["sites"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .find(|site| site["function"] == "make_buffer")
+                    .unwrap_or_else(|| panic!("no allocation site in `make_buffer`: {profile:#}"));
+                assert_eq!(site["count"], 1);
+                assert_eq!(site["allocated_bytes"], 1000);
+                assert_eq!(site["peak_bytes"], 1000);
+                assert_eq!(site["live_bytes_at_exit"], 0);
+            }
+        }
+    }
+}
+
+fn report_progress_json() {
+    let source = r#"
+        #[inline(never)]
+        fn spin(n: u64) -> u64 {
+            let mut i = 0;
+            let mut sum = 0;
+            while i < n {
+                sum += i;
+                i += 1;
+            }
+            sum
+        }
+
+        fn main() {
+            assert_eq!(spin(1000), 499500);
+        }
+    "#;
+    let out = tempfile::tempdir().unwrap();
+    let progress_out = out.path().join("progress.json");
+    let config = MiriConfig {
+        report_progress: Some(100),
+        report_progress_json: Some(progress_out.display().to_string()),
+        ..MiriConfig::default()
+    };
+    let outcome = run(source, config, ());
+    assert!(matches!(outcome, MiriOutcome::Exit(0)), "{outcome:?}");
+    let progress = fs::read_to_string(progress_out).unwrap();
+    let reports: Vec<serde_json::Value> =
+        progress.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    // `spin` alone executes thousands of blocks.
+    assert!(reports.len() > 10, "{progress}");
+    for (idx, report) in reports.iter().enumerate() {
+        assert_eq!(report["block_count"].as_u64().unwrap(), 100 * (idx as u64 + 1), "{report}");
+        assert_eq!(report["thread"], "main", "{report}");
+    }
+    let in_spin = reports.iter().find(|report| report["function"] == "spin").unwrap();
+    assert!(in_spin["span"].as_str().unwrap().contains("main.rs:"), "{in_spin}");
+    assert_eq!(in_spin["span"], in_spin["user_span"], "{in_spin}");
+}
+
+/// Implements `triple`, which Miri does not know about.
+struct Triple;
+
+impl ShimHook for Triple {
+    fn call<'tcx>(
+        &self,
+        ecx: &mut MiriInterpCx<'tcx>,
+        link_name: Symbol,
+        abi: Abi,
+        args: &[OpTy<'tcx>],
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx, EmulateItemResult> {
+        let [x] = ecx.check_shim(abi, Abi::C { unwind: false }, link_name, args)?;
+        let x = ecx.read_scalar(x)?.to_i32()?;
+        ecx.write_scalar(Scalar::from_i32(x * 3), dest)?;
+        Ok(EmulateItemResult::NeedsReturn)
+    }
+}
+
+/// Leaves `strlen` to Miri's own shim, and records that it was asked.
+struct Decline(Arc<AtomicBool>);
+
+impl ShimHook for Decline {
+    fn call<'tcx>(
+        &self,
+        _ecx: &mut MiriInterpCx<'tcx>,
+        _link_name: Symbol,
+        _abi: Abi,
+        _args: &[OpTy<'tcx>],
+        _dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx, EmulateItemResult> {
+        self.0.store(true, Ordering::Relaxed);
+        Ok(EmulateItemResult::NotSupported)
+    }
+}
+
+fn shim_hooks() {
+    let source = r#"
+        extern "C" {
+            fn triple(x: i32) -> i32;
+            fn strlen(s: *const std::ffi::c_char) -> usize;
+        }
+
+        fn main() {
+            assert_eq!(unsafe { triple(14) }, 42);
+            assert_eq!(unsafe { strlen(c"abc".as_ptr()) }, 3);
+        }
+    "#;
+    let declined = Arc::new(AtomicBool::new(false));
+    let mut config = MiriConfig::default();
+    config.shim_hooks.insert("triple".to_owned(), Arc::new(Triple));
+    config.shim_hooks.insert("strlen".to_owned(), Arc::new(Decline(declined.clone())));
+    let outcome = run(source, config, ());
+    assert!(matches!(outcome, MiriOutcome::Exit(0)), "{outcome:?}");
+    assert!(declined.load(Ordering::Relaxed), "the `strlen` hook was not called");
+}
+
+fn main() {
+    let tests: &[(&str, fn())] = &[
+        ("exit_code", exit_code),
+        ("undefined_behavior", undefined_behavior),
+        ("compilation_failed", compilation_failed),
+        ("callbacks", callbacks),
+        ("measureme_events", measureme_events),
+        ("coverage", coverage),
+        ("trace", trace),
+        ("heap_profile", heap_profile),
+        ("report_progress_json", report_progress_json),
+        ("shim_hooks", shim_hooks),
+    ];
+    for (name, test) in tests {
+        eprintln!("embed test {name}");
+        test();
+    }
+}
@@ -0,0 +1,29 @@
+//@ignore-target-windows: No pthreads on Windows
+//@error-in-other-file: the main thread terminated without waiting for all remaining threads
+
+// Check that we report what the remaining threads are blocked on when the main thread terminates.
+
+use std::cell::UnsafeCell;
+use std::{mem, ptr, thread};
+
+struct Mutex(UnsafeCell<libc::pthread_mutex_t>);
+
+unsafe impl Sync for Mutex {}
+
+static LOCK: Mutex = Mutex(UnsafeCell::new(libc::PTHREAD_MUTEX_INITIALIZER));
+
+extern "C" fn thread_start(_null: *mut libc::c_void) -> *mut libc::c_void {
+    unsafe { libc::pthread_mutex_lock(LOCK.0.get()) };
+    ptr::null_mut()
+}
+
+fn main() {
+    unsafe {
+        assert_eq!(libc::pthread_mutex_lock(LOCK.0.get()), 0);
+        let mut native: libc::pthread_t = mem::zeroed();
+        let attr: libc::pthread_attr_t = mem::zeroed();
+        assert_eq!(libc::pthread_create(&mut native, &attr, thread_start, ptr::null_mut()), 0);
+    }
+    // Let the other thread block on the mutex before the main thread terminates.
+    thread::yield_now();
+}
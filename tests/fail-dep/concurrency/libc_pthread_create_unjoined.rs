@@ -0,0 +1,20 @@
+//@ignore-target-windows: No pthreads on Windows
+//@compile-flags: -Zmiri-check-unjoined-threads -Zmiri-preemption-rate=0
+
+// A thread that is neither joined nor detached is leaked.
+
+use std::{mem, ptr};
+
+extern "C" fn thread_start(_null: *mut libc::c_void) -> *mut libc::c_void {
+    ptr::null_mut()
+}
+
+fn main() {
+    unsafe {
+        let mut native: libc::pthread_t = mem::zeroed();
+        let attr: libc::pthread_attr_t = mem::zeroed();
+        assert_eq!(libc::pthread_create(&mut native, &attr, thread_start, ptr::null_mut()), 0); //~ ERROR: was never joined or detached
+        // Let the thread run to completion.
+        libc::sched_yield();
+    }
+}
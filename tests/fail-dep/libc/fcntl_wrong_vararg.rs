@@ -0,0 +1,8 @@
+//@ignore-target-windows: No libc IO on Windows
+
+// `F_DUPFD` takes an `int`, passing a `long` instead is UB.
+fn main() {
+    unsafe {
+        libc::fcntl(1, libc::F_DUPFD, 0i64); //~ERROR: expected a variadic argument of type `i32`
+    }
+}
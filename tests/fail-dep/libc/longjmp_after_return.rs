@@ -0,0 +1,22 @@
+//@ignore-target-windows: no `setjmp` on Windows
+use std::ffi::c_int;
+
+type JmpBuf = [u64; 64];
+
+extern "C" {
+    fn setjmp(env: *mut JmpBuf) -> c_int;
+    fn longjmp(env: *mut JmpBuf, val: c_int) -> !;
+}
+
+fn set_up(env: &mut JmpBuf) {
+    unsafe {
+        setjmp(env);
+    }
+}
+
+fn main() {
+    let mut env = [0; 64];
+    set_up(&mut env);
+    // The function that called `setjmp` has returned, so there is nowhere to jump to.
+    unsafe { longjmp(&mut env, 1) }; //~ERROR: whose function has already returned
+}
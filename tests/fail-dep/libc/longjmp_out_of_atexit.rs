@@ -0,0 +1,26 @@
+//@ignore-target-windows: no `setjmp` on Windows
+use std::ffi::c_int;
+use std::ptr::addr_of_mut;
+
+type JmpBuf = [u64; 64];
+
+extern "C" {
+    fn setjmp(env: *mut JmpBuf) -> c_int;
+    fn longjmp(env: *mut JmpBuf, val: c_int) -> !;
+}
+
+static mut ENV: JmpBuf = [0; 64];
+
+extern "C" fn handler() {
+    // C leaves this undefined: `exit` would never finish running the handlers.
+    unsafe { longjmp(addr_of_mut!(ENV), 1) }; //~ERROR: `longjmp` out of a function registered with `atexit`
+}
+
+fn main() {
+    unsafe {
+        if setjmp(addr_of_mut!(ENV)) == 0 {
+            libc::atexit(handler);
+            libc::exit(0);
+        }
+    }
+}
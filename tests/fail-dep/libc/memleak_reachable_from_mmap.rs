@@ -0,0 +1,19 @@
+//@ignore-target-windows: No mmap on Windows
+//@error-in-other-file: memory leaked
+//@normalize-stderr-test: ".*│.*" -> "$$stripped$$"
+// The memory mapped by the program does not count as leaked, but what it points to does.
+
+fn main() {
+    unsafe {
+        let page = libc::mmap(
+            std::ptr::null_mut(),
+            4096,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        );
+        assert_ne!(page, libc::MAP_FAILED);
+        page.cast::<*mut i32>().write(Box::into_raw(Box::new(42)));
+    }
+}
@@ -0,0 +1,9 @@
+//@ignore-target-windows: No libc on Windows
+//@compile-flags: -Zmiri-disable-isolation -Zmiri-timeout=10
+
+// While all threads sleep, no basic blocks are executed, but the time limit is still enforced.
+fn main() {
+    let req = libc::timespec { tv_sec: 600, tv_nsec: 0 };
+    unsafe { libc::nanosleep(&req, std::ptr::null_mut()) };
+    //~^ ERROR: did not finish within 10 seconds
+}
@@ -0,0 +1,18 @@
+//@compile-flags: -Zmiri-backtrace-exclude=mid*
+// `middle` is left out of the backtrace.
+
+fn inner() {
+    let _b = unsafe { std::mem::transmute::<u8, bool>(2) }; //~ ERROR: expected a boolean
+}
+
+fn middle() {
+    inner()
+}
+
+fn outer() {
+    middle()
+}
+
+fn main() {
+    outer()
+}
@@ -0,0 +1,8 @@
+//@compile-flags: -Zmiri-backtrace-include=std::boxed::*
+//@error-in-other-file: memory leaked
+//@normalize-stderr-test: ".*│.*" -> "$$stripped$$"
+// `Box::new` is shown with its source code, like the functions of this crate.
+
+fn main() {
+    std::mem::forget(Box::new(42));
+}
@@ -0,0 +1,15 @@
+//@compile-flags: -Zmiri-both-borrow-trackers
+// Check that errors report which of the two models rejected the access.
+
+#![allow(invalid_reference_casting)]
+
+fn main() {
+    let target = Box::new(42); // has an implicit raw
+    let xref = &*target;
+    {
+        let x: *mut u32 = xref as *const _ as *mut _;
+        unsafe { *x = 42 };
+        //~^ ERROR: /write access .* tag only grants SharedReadOnly permission/
+    }
+    let _x = *xref;
+}
@@ -0,0 +1,19 @@
+//@compile-flags: -Zmiri-both-borrow-trackers
+// A function-entry retag of `&mut x` invalidates `z` under Stacked Borrows, but under Tree Borrows
+// it is only a read of `x`, which leaves the sibling `z` usable for reads.
+fn main() {
+    let mut x = 0i32;
+    let z = &mut x as *mut i32;
+    x.do_bad();
+    unsafe {
+        let _oof = *z; //~ ERROR: /read access .* tag does not exist in the borrow stack/
+    }
+}
+
+trait Bad {
+    fn do_bad(&mut self) {
+        // who knows
+    }
+}
+
+impl Bad for i32 {}
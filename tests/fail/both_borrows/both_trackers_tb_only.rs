@@ -0,0 +1,13 @@
+//@compile-flags: -Zmiri-both-borrow-trackers
+// Under Tree Borrows, a read from the local kills the raw pointer derived from `mref`, whereas
+// Stacked Borrows keeps the `SharedReadWrite` item of the raw pointer.
+fn main() {
+    unsafe {
+        let mut root = 6u8;
+        let mref = &mut root;
+        let ptr = mref as *mut u8;
+        *ptr = 0; // Write
+        assert_eq!(root, 0); // Parent Read
+        *ptr = 0; //~ ERROR: /write access through .* is forbidden/
+    }
+}
@@ -0,0 +1,12 @@
+//@compile-flags: -Zmiri-cheri
+#[path = "../utils/mod.rs"]
+mod utils;
+
+fn main() {
+    let array = [0u8; 16];
+    let ptr = unsafe { utils::miri_cheri_set_bounds(array.as_ptr().cast(), 8) }.cast::<u8>();
+    // In bounds of the capability.
+    assert_eq!(unsafe { *ptr.add(7) }, 0);
+    // In bounds of the allocation, but not of the capability.
+    let _val = unsafe { *ptr.add(8) }; //~ ERROR: with a capability whose bounds are only
+}
@@ -0,0 +1,30 @@
+//@compile-flags: -Zmiri-dpor
+//@normalize-stderr-test: "execution \d+ of" -> "execution N of"
+
+// The bug below is only reachable when `main` reads the first store of the other thread. The
+// state right after that read agrees with the state of an earlier execution (in which `main` read
+// before the store) in the position of every thread and the value of `X`; only `seen` differs.
+// So the exploration must not skip states that look the same on the surface.
+
+use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+use std::thread;
+
+static X: AtomicUsize = AtomicUsize::new(0);
+
+fn main() {
+    let t = thread::spawn(|| {
+        X.store(1, SeqCst);
+        X.store(0, SeqCst);
+    });
+    let mut seen = 0;
+    seen += X.load(SeqCst);
+    seen += X.load(SeqCst);
+    t.join().unwrap();
+    if seen == 2 {
+        let p = {
+            let b = Box::new(42);
+            &*b as *const i32
+        };
+        let _x = unsafe { *p }; //~ERROR: has been freed
+    }
+}
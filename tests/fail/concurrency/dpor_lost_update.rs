@@ -0,0 +1,29 @@
+//@compile-flags: -Zmiri-dpor
+//@normalize-stderr-test: "execution \d+ of" -> "execution N of"
+
+// Both threads increment `X` with a separate load and store, so an update is lost if one thread
+// runs between the load and the store of the other. The scheduler that runs the active thread as
+// long as possible never does that, so DPOR has to find the interleaving by reversing a race.
+
+use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+use std::thread;
+
+static X: AtomicUsize = AtomicUsize::new(0);
+
+fn increment() {
+    let x = X.load(SeqCst);
+    X.store(x + 1, SeqCst);
+}
+
+fn main() {
+    let t = thread::spawn(increment);
+    increment();
+    t.join().unwrap();
+    if X.load(SeqCst) == 1 {
+        let p = {
+            let b = Box::new(42);
+            &*b as *const i32
+        };
+        let _x = unsafe { *p }; //~ERROR: has been freed
+    }
+}
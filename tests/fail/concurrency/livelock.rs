@@ -0,0 +1,10 @@
+//@compile-flags: -Zmiri-livelock-threshold=1000
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static READY: AtomicBool = AtomicBool::new(false);
+
+fn main() {
+    // Nobody is ever going to set `READY`.
+    while !READY.load(Ordering::Relaxed) {} //~ERROR: livelocked
+}
@@ -0,0 +1,29 @@
+//@compile-flags: -Zmiri-model-check
+//@normalize-stderr-test: "execution \d+ of" -> "execution N of"
+
+// Store buffering: with relaxed accesses, both loads may miss the store of the other thread. No
+// interleaving produces that outcome, only the weak memory emulation does, so the model checker
+// has to try the older store for both loads.
+
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+use std::thread;
+
+static X: AtomicUsize = AtomicUsize::new(0);
+static Y: AtomicUsize = AtomicUsize::new(0);
+
+fn main() {
+    let t = thread::spawn(|| {
+        X.store(1, Relaxed);
+        Y.load(Relaxed)
+    });
+    Y.store(1, Relaxed);
+    let b = X.load(Relaxed);
+    let a = t.join().unwrap();
+    if a == 0 && b == 0 {
+        let p = {
+            let b = Box::new(42);
+            &*b as *const i32
+        };
+        let _x = unsafe { *p }; //~ERROR: has been freed
+    }
+}
@@ -0,0 +1,37 @@
+// We want to control preemption here. Stacked borrows interferes by having its own accesses.
+//@compile-flags: -Zmiri-preemption-rate=0 -Zmiri-disable-stacked-borrows
+// Avoid accidental synchronization via address reuse inside `thread::spawn`.
+//@compile-flags: -Zmiri-address-reuse-cross-thread-rate=0
+//@compile-flags: -Zmiri-explain-data-races
+// The exact timestamps depend on what the standard library does while spawning threads.
+//@normalize-stderr-test: ": \d+" -> ": N"
+//@normalize-stderr-test: "at time \d+" -> "at time N"
+
+use std::thread::spawn;
+
+#[derive(Copy, Clone)]
+struct EvilSend<T>(pub T);
+
+unsafe impl<T> Send for EvilSend<T> {}
+unsafe impl<T> Sync for EvilSend<T> {}
+
+pub fn main() {
+    let mut a = 0u32;
+    let b = &mut a as *mut u32;
+    let c = EvilSend(b);
+    // Spawn the closures by name, so that the spawn sites fit on one line.
+    let reader = move || {
+        let c = c; // avoid field capturing
+        let _val = unsafe { *c.0 };
+    };
+    let writer = move || {
+        let c = c; // avoid field capturing
+        unsafe { *c.0 = 64 }; //~ ERROR: Data race detected between (1) non-atomic read on thread `unnamed-1` and (2) non-atomic write on thread `unnamed-2`
+    };
+
+    let j1 = spawn(reader);
+    let j2 = spawn(writer);
+
+    j1.join().unwrap();
+    j2.join().unwrap();
+}
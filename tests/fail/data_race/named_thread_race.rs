@@ -0,0 +1,39 @@
+// We want to control preemption here. Stacked borrows interferes by having its own accesses.
+//@compile-flags: -Zmiri-preemption-rate=0 -Zmiri-disable-stacked-borrows
+// Avoid accidental synchronization via address reuse inside `thread::spawn`.
+//@compile-flags: -Zmiri-address-reuse-cross-thread-rate=0
+
+use std::thread::Builder;
+
+#[derive(Copy, Clone)]
+struct EvilSend<T>(pub T);
+
+unsafe impl<T> Send for EvilSend<T> {}
+unsafe impl<T> Sync for EvilSend<T> {}
+
+pub fn main() {
+    let mut a = 0u32;
+    let b = &mut a as *mut u32;
+    let c = EvilSend(b);
+    unsafe {
+        let j1 = Builder::new()
+            .name("worker".into())
+            .spawn(move || {
+                let c = c; // avoid field capturing
+                let _val = *c.0;
+            })
+            .unwrap();
+
+        // Same name as the first thread, only the ID tells them apart.
+        let j2 = Builder::new()
+            .name("worker".into())
+            .spawn(move || {
+                let c = c; // avoid field capturing
+                *c.0 = 64; //~ ERROR: Data race detected between (1) non-atomic read on thread `worker` (id 1) and (2) non-atomic write on thread `worker` (id 2)
+            })
+            .unwrap();
+
+        j1.join().unwrap();
+        j2.join().unwrap();
+    }
+}
@@ -0,0 +1,7 @@
+//@compile-flags: -Zmiri-max-atomic-width=32
+#![feature(core_intrinsics)]
+
+fn main() {
+    let x = 0u64;
+    unsafe { std::intrinsics::atomic_load_seqcst(&x) }; //~ERROR: 64-bit atomic accesses are not supported
+}
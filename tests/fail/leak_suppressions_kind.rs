@@ -0,0 +1,12 @@
+//@compile-flags: -Zmiri-leak-suppressions=tests/leak-suppressions
+//@error-in-other-file: memory leaked
+//@normalize-stderr-test: ".*│.*" -> "$$stripped$$"
+
+// The suppression for `leak_c_memory` only applies to C heap memory, not to this box.
+fn leak_c_memory() {
+    std::mem::forget(Box::new(42));
+}
+
+fn main() {
+    leak_c_memory();
+}
@@ -0,0 +1,5 @@
+//@compile-flags: -Zmiri-max-blocks=100000
+
+fn main() {
+    loop {} //~ERROR: did not finish within 100000 basic blocks
+}
@@ -0,0 +1,15 @@
+//@error-in-other-file: memory leaked
+//@normalize-stderr-test: ".*│.*" -> "$$stripped$$"
+//@normalize-stderr-test: "Rust heap, size: [0-9]+, align: [0-9]+" -> "Rust heap, SIZE, ALIGN"
+// Of two `Rc`s that point to each other, the first one is reported as the root of the cycle.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+struct Node(RefCell<Option<Rc<Node>>>);
+
+fn main() {
+    let a = Rc::new(Node(RefCell::new(None)));
+    let b = Rc::new(Node(RefCell::new(Some(a.clone()))));
+    *a.0.borrow_mut() = Some(b);
+}
@@ -1,2 +1,2 @@
-//@error-in-other-file: miri can only run programs that have a main function
+//@error-in-other-file: miri can only run programs that have a main function, or that export a `miri_start` function
 #![no_main]
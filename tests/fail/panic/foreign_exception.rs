@@ -0,0 +1,28 @@
+//! Foreign exceptions run drop glue while they unwind through Rust frames, but Rust cannot
+//! catch them.
+//@error-in-other-file: Rust cannot catch foreign exceptions
+//@normalize-stderr-test: "\| +\^+" -> "| ^"
+
+#[path = "../../utils/mod.rs"]
+mod utils;
+
+struct Guard;
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        eprintln!("dropping guard");
+    }
+}
+
+extern "C-unwind" fn throw() {
+    unsafe { utils::miri_throw_foreign_exception() }
+}
+
+fn throw_with_guard() {
+    let _guard = Guard;
+    throw();
+}
+
+fn main() {
+    let _ = std::panic::catch_unwind(throw_with_guard);
+}
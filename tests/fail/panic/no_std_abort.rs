@@ -0,0 +1,15 @@
+//@compile-flags: -Cpanic=abort -Zmiri-panic-handler=abort
+//@error-in-other-file: the program panicked
+#![feature(start)]
+#![no_std]
+
+#[start]
+fn start(_: isize, _: *const *const u8) -> isize {
+    panic!("blarg I am dead")
+}
+
+#[panic_handler]
+fn panic_handler(_: &core::panic::PanicInfo) -> ! {
+    // Miri stops the program before it gets here.
+    loop {}
+}
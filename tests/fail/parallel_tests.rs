@@ -0,0 +1,29 @@
+//@compile-flags: --test -Zmiri-parallel-tests -Zthreads=1 -Zmiri-backtrace=0
+//@normalize-stdout-test: "finished in \d+\.\d+s" -> "finished in $$TIME"
+//@normalize-stdout-test: "\.rs:\d+:\d+" -> ".rs:LL:CC"
+
+// Every test runs in its own instance of the interpreter, so a test that panics or that Miri stops
+// does not keep the other tests from running. The output of each test is printed in one piece,
+// followed by a summary of all tests.
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    #[test]
+    fn panics() {
+        std::io::stderr().write_all(b"about to panic\n").unwrap();
+        panic!("oh no");
+    }
+
+    #[test]
+    fn passes() {}
+
+    #[test]
+    fn use_after_free() {
+        let b = Box::new(42);
+        let ptr = &*b as *const i32;
+        drop(b);
+        let _x = unsafe { *ptr }; //~ERROR: has been freed
+    }
+}
@@ -0,0 +1,9 @@
+//@ignore-target-windows: the error names a different function on Windows
+//@error-in-other-file: `getcwd` not available when isolation is enabled
+//@normalize-stderr-test: "\| +\^+" -> "| ^"
+
+// Backtrace printing may read the current directory under isolation, but that must not leak into
+// the rest of the program.
+fn main() {
+    let _cwd = std::env::current_dir();
+}
@@ -0,0 +1,9 @@
+//@compile-flags: -Zmiri-shim-stubs=tests/shim-stubs.json
+
+extern "C" {
+    fn stub_float() -> f64;
+}
+
+fn main() {
+    unsafe { stub_float() }; //~ERROR: can only return integers
+}
@@ -0,0 +1,9 @@
+//@compile-flags: -Zmiri-shim-stubs=tests/shim-stubs.json
+
+extern "C" {
+    fn stub_negative() -> u32;
+}
+
+fn main() {
+    unsafe { stub_negative() }; //~ERROR: does not fit into the return type
+}
@@ -0,0 +1,9 @@
+//@compile-flags: -Zmiri-shim-stubs=tests/shim-stubs.json
+
+extern "C" {
+    fn stub_too_big() -> u8;
+}
+
+fn main() {
+    unsafe { stub_too_big() }; //~ERROR: does not fit into the return type
+}
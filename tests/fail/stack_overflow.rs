@@ -0,0 +1,13 @@
+//@compile-flags: -Zmiri-stack-size=350000
+// Each frame of `recurse` takes up a bit more than 100000 bytes, so the fourth call overflows.
+
+#[allow(unconditional_recursion)]
+fn recurse() {
+    let buf = [0u8; 100_000];
+    std::hint::black_box(&buf);
+    recurse(); //~ ERROR: stack overflow in interpreted program
+}
+
+fn main() {
+    recurse();
+}
@@ -0,0 +1,24 @@
+//@compile-flags: -Zmiri-retag-fields=none:per_type_field_retagging::Newtype
+//@error-in-other-file: which is strongly protected
+
+struct Newtype<'a>(#[allow(dead_code)] &'a mut i32);
+
+struct Other<'a>(#[allow(dead_code)] &'a mut i32);
+
+fn dealloc_while_running(_n: Other<'_>, dealloc: impl FnOnce()) {
+    dealloc();
+}
+
+// Overriding the policy for `Newtype` must not affect the fields of other types.
+fn main() {
+    let mut x = 0;
+    let _n = Newtype(&mut x);
+    let ptr = Box::into_raw(Box::new(0i32));
+    #[rustfmt::skip] // I like my newlines
+    unsafe {
+        dealloc_while_running(
+            Other(&mut *ptr),
+            || drop(Box::from_raw(ptr)),
+        )
+    };
+}
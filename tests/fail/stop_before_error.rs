@@ -0,0 +1,17 @@
+//@compile-flags: -Zmiri-stop-before-error=3
+
+#[inline(never)]
+fn first_step() {}
+
+#[inline(never)]
+fn last_step() {}
+
+#[allow(deref_nullptr)]
+fn main() {
+    let ptr = 0usize as *const i32;
+    // Three basic blocks before the error, the program is about to call `first_step`: then come
+    // the call of `first_step`, its return, the call of `last_step`, and its return.
+    first_step();
+    last_step();
+    let _val = unsafe { *ptr }; //~ ERROR: null pointer is a dangling pointer
+}
@@ -0,0 +1,12 @@
+//@compile-flags: --test -Zmiri-test=tests::does_not_panic
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    #[should_panic]
+    //~^ HELP: the test is marked `#[should_panic]` here
+    fn does_not_panic() {
+        let v = vec![1u8];
+        let _ = v[0];
+    } //~ ERROR: the test returned without panicking
+}
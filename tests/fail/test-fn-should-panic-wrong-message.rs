@@ -0,0 +1,13 @@
+//@compile-flags: --test -Zmiri-test=tests::wrong_message
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    #[should_panic(expected = "index out of bounds")]
+    //~^ HELP: the test is marked `#[should_panic]` here
+    fn wrong_message() {
+        //~^ ERROR: the test panicked with `the value is 42`, which does not contain the expected string `index out of bounds`
+        let value = 42;
+        panic!("the value is {value}");
+    }
+}
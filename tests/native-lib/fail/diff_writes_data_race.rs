@@ -0,0 +1,31 @@
+//@only-target-linux
+//@only-on-host
+// We want to control preemption here. Stacked borrows interferes by having its own accesses.
+//@compile-flags: -Zmiri-native-lib-diff-writes -Zmiri-preemption-rate=0 -Zmiri-disable-stacked-borrows
+// Avoid accidental synchronization via address reuse inside `thread::spawn`.
+//@compile-flags: -Zmiri-address-reuse-cross-thread-rate=0
+
+use std::thread;
+
+#[derive(Copy, Clone)]
+struct EvilSend<T>(pub T);
+
+unsafe impl<T> Send for EvilSend<T> {}
+
+extern "C" {
+    fn increment_int(p: *mut i32);
+}
+
+fn main() {
+    let mut x = 41;
+    let ptr = EvilSend(&mut x as *mut i32);
+    let j = thread::spawn(move || {
+        let ptr = ptr; // avoid field capturing
+        let _val = unsafe { *ptr.0 };
+    });
+    // Let the other thread read `x`.
+    thread::yield_now();
+    // The native code writes to `x` without synchronizing with that read.
+    unsafe { increment_int(ptr.0) }; //~ ERROR: Data race detected between (1) non-atomic read on thread `unnamed-1` and (2) non-atomic write on thread `main`
+    j.join().unwrap();
+}
@@ -0,0 +1,14 @@
+//@only-target-linux
+//@only-on-host
+//@compile-flags: -Zmiri-native-lib-diff-writes
+
+extern "C" {
+    fn increment_int(p: *mut i32);
+}
+
+fn main() {
+    let x = 41;
+    let r = &x;
+    // The native code writes through a pointer derived from a shared reference.
+    unsafe { increment_int(r as *const i32 as *mut i32) }; //~ ERROR: /write access .* only grants SharedReadOnly permission/
+}
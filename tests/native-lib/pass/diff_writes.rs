@@ -0,0 +1,19 @@
+//@only-target-linux
+//@only-on-host
+//@compile-flags: -Zmiri-native-lib-diff-writes
+
+extern "C" {
+    fn increment_int(p: *mut i32);
+}
+
+fn main() {
+    let mut x = 41;
+    // Writing through a pointer derived from a mutable reference is fine.
+    unsafe { increment_int(&mut x) };
+    assert_eq!(x, 42);
+    // The reference that pointer was derived from stays usable.
+    let r = &mut x;
+    unsafe { increment_int(&mut *r) };
+    *r += 1;
+    assert_eq!(x, 44);
+}
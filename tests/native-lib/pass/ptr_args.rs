@@ -0,0 +1,32 @@
+//@only-target-linux
+//@only-on-host
+
+use std::mem::MaybeUninit;
+
+extern "C" {
+    fn sum_array(arr: *const i32, len: usize) -> i32;
+    fn fill_bytes(buf: *mut u8, len: usize, value: u8);
+    fn increment_int(p: *mut i32);
+}
+
+fn main() {
+    unsafe {
+        // Native code reads the memory of the program.
+        let values = [1, 2, 3, 4];
+        assert_eq!(sum_array(values.as_ptr(), values.len()), 10);
+        // ...also at an offset into an allocation.
+        assert_eq!(sum_array(values.as_ptr().add(2), 2), 7);
+
+        // Native writes are visible to the program, even to memory that was uninitialized.
+        let mut buf = [MaybeUninit::<u8>::uninit(); 8];
+        fill_bytes(buf.as_mut_ptr().cast(), buf.len(), 0xab);
+        assert!(buf.iter().all(|b| b.assume_init() == 0xab));
+
+        let mut x = Box::new(41);
+        increment_int(&mut *x);
+        assert_eq!(*x, 42);
+
+        // Null pointers are passed on unchanged.
+        assert_eq!(sum_array(std::ptr::null(), 0), 0);
+    }
+}
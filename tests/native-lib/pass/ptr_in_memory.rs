@@ -0,0 +1,31 @@
+//@only-target-linux
+//@only-on-host
+
+extern "C" {
+    fn sum_indirect(ptrs: *const *const i32, len: usize) -> i32;
+    fn store_ptr(slot: *mut *mut i32, p: *mut i32);
+    fn ptr_to_int(p: *mut i32) -> usize;
+}
+
+fn main() {
+    unsafe {
+        // Native code can follow the pointers stored in the memory it gets, and only reads them,
+        // so they keep their provenance.
+        let (a, b, c) = (1, 2, 3);
+        let ptrs = [&a as *const i32, &b, &c];
+        assert_eq!(sum_indirect(ptrs.as_ptr(), ptrs.len()), 6);
+        assert_eq!(*ptrs[0] + *ptrs[1] + *ptrs[2], 6);
+
+        // Host addresses that native code stores are turned into addresses of the program.
+        let mut x = 41;
+        let p: *mut i32 = &mut x;
+        let mut slot: *mut i32 = std::ptr::null_mut();
+        store_ptr(&mut slot, p);
+        assert_eq!(slot, p);
+        *slot += 1;
+        assert_eq!(x, 42);
+
+        // ...and so are the ones it returns as an integer.
+        assert_eq!(ptr_to_int(p), p as usize);
+    }
+}
@@ -25,3 +25,43 @@ short add_int16(short x) {
 long add_short_to_long(short x, long y) {
   return x + y;
 }
+
+/* Functions that take pointers into the memory of the interpreted program. */
+
+#include <stddef.h>
+
+int sum_array(const int *arr, size_t len) {
+  int sum = 0;
+  for (size_t i = 0; i < len; i++) {
+    sum += arr[i];
+  }
+  return sum;
+}
+
+void fill_bytes(unsigned char *buf, size_t len, unsigned char value) {
+  for (size_t i = 0; i < len; i++) {
+    buf[i] = value;
+  }
+}
+
+void increment_int(int *p) {
+  *p += 1;
+}
+
+int sum_indirect(const int *const *ptrs, size_t len) {
+  int sum = 0;
+  for (size_t i = 0; i < len; i++) {
+    sum += *ptrs[i];
+  }
+  return sum;
+}
+
+void store_ptr(int **slot, int *p) {
+  *slot = p;
+}
+
+#include <stdint.h>
+
+uintptr_t ptr_to_int(int *p) {
+  return (uintptr_t)p;
+}
@@ -0,0 +1,19 @@
+//@compile-flags: -Zmiri-entry-fn=run
+// `Default::default` runs after the runtime was set up, so its panic is reported like any other.
+
+fn main() {
+    unreachable!("`main` does not run with -Zmiri-entry-fn");
+}
+
+struct Config;
+
+impl Default for Config {
+    fn default() -> Self {
+        panic!("cannot construct the config");
+    }
+}
+
+#[allow(dead_code)]
+fn run(_config: Config) {
+    unreachable!("the entry function does not run if its arguments cannot be constructed");
+}
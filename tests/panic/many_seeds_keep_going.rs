@@ -0,0 +1,6 @@
+//@compile-flags: -Zmiri-many-seeds=0..3 -Zmiri-many-seeds-keep-going -Zthreads=1
+// With one thread, the seeds are tried one after the other, so the output is deterministic.
+
+fn main() {
+    panic!("this fails with every seed");
+}
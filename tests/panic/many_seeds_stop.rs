@@ -0,0 +1,7 @@
+//@compile-flags: -Zmiri-many-seeds=0..3 -Zthreads=1
+// Without `-Zmiri-many-seeds-keep-going`, Miri stops at the first seed that fails.
+// With one thread, the seeds are tried one after the other, so the output is deterministic.
+
+fn main() {
+    panic!("this fails with every seed");
+}
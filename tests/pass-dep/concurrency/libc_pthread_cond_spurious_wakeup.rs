@@ -0,0 +1,23 @@
+//@ignore-target-windows: No pthreads on Windows
+//@compile-flags: -Zmiri-spurious-wakeup-rate=1.0
+
+/// With a spurious wakeup rate of 1, waiting on a condvar returns right away, even though nobody
+/// ever signals it.
+use std::mem::MaybeUninit;
+
+fn main() {
+    unsafe {
+        let mut cond: MaybeUninit<libc::pthread_cond_t> = MaybeUninit::uninit();
+        assert_eq!(libc::pthread_cond_init(cond.as_mut_ptr(), std::ptr::null()), 0);
+        let mut mutex: libc::pthread_mutex_t = libc::PTHREAD_MUTEX_INITIALIZER;
+
+        assert_eq!(libc::pthread_mutex_lock(&mut mutex as *mut _), 0);
+        assert_eq!(libc::pthread_cond_wait(cond.as_mut_ptr(), &mut mutex as *mut _), 0);
+        // We hold the mutex again.
+        assert_eq!(libc::pthread_mutex_trylock(&mut mutex as *mut _), libc::EBUSY);
+        assert_eq!(libc::pthread_mutex_unlock(&mut mutex as *mut _), 0);
+
+        assert_eq!(libc::pthread_cond_destroy(cond.as_mut_ptr()), 0);
+        assert_eq!(libc::pthread_mutex_destroy(&mut mutex as *mut _), 0);
+    }
+}
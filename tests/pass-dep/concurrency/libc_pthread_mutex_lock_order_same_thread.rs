@@ -0,0 +1,39 @@
+//@only-target-linux: std does not use pthread mutexes here, so the only ones are those we create
+//@compile-flags: -Zmiri-track-lock-order
+
+use std::cell::UnsafeCell;
+use std::thread;
+
+struct Mutex(UnsafeCell<libc::pthread_mutex_t>);
+
+unsafe impl Sync for Mutex {}
+
+static A: Mutex = Mutex(UnsafeCell::new(libc::PTHREAD_MUTEX_INITIALIZER));
+static B: Mutex = Mutex(UnsafeCell::new(libc::PTHREAD_MUTEX_INITIALIZER));
+
+fn lock(m: &Mutex) {
+    unsafe { libc::pthread_mutex_lock(m.0.get()) };
+}
+
+fn unlock(m: &Mutex) {
+    unsafe { libc::pthread_mutex_unlock(m.0.get()) };
+}
+
+fn lock_a_then_b() {
+    lock(&A);
+    lock(&B);
+    unlock(&B);
+    unlock(&A);
+}
+
+fn main() {
+    // The main thread acquires A, then B, and later B, then A. On its own, that cannot deadlock...
+    lock_a_then_b();
+    lock(&B);
+    lock(&A);
+    unlock(&A);
+    unlock(&B);
+
+    // ...but together with another thread that acquires A, then B, it can.
+    thread::spawn(lock_a_then_b).join().unwrap();
+}
@@ -0,0 +1,40 @@
+//@only-target-linux
+//@compile-flags: -Zmiri-report-contention -Zmiri-preemption-rate=0
+//@normalize-stderr-test: "0x[0-9a-f]+" -> "$$HEX"
+//@normalize-stderr-test: "blocked for [^ ]+ in total" -> "blocked for $$TIME in total"
+
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::thread;
+
+static FUTEX: AtomicI32 = AtomicI32::new(0);
+
+fn futex_wait(val: i32) -> i64 {
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            FUTEX.as_ptr(),
+            libc::FUTEX_WAIT,
+            val,
+            std::ptr::null::<libc::timespec>(),
+        )
+    }
+}
+
+fn main() {
+    let t = thread::spawn(|| {
+        // Blocks until the main thread wakes us up: the first acquisition, which is contended.
+        assert_eq!(futex_wait(0), 0);
+    });
+    // Without preemption, this lets the other thread run until it blocks.
+    thread::yield_now();
+    FUTEX.store(1, Ordering::Relaxed);
+    unsafe {
+        assert_eq!(libc::syscall(libc::SYS_futex, FUTEX.as_ptr(), libc::FUTEX_WAKE, 1), 1);
+    }
+    t.join().unwrap();
+
+    // The value already changed, so this returns right away: the second acquisition, which is not
+    // contended.
+    assert_eq!(futex_wait(0), -1);
+    assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EAGAIN);
+}
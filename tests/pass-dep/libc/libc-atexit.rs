@@ -0,0 +1,19 @@
+//@ignore-target-windows: no `__cxa_atexit` on Windows
+use std::ffi::c_void;
+
+extern "C" {
+    fn __cxa_atexit(f: extern "C" fn(*mut c_void), arg: *mut c_void, dso: *mut c_void) -> i32;
+}
+
+extern "C" fn free_box(arg: *mut c_void) {
+    let b = unsafe { Box::from_raw(arg.cast::<u64>()) };
+    assert_eq!(*b, 42);
+}
+
+fn main() {
+    // The handlers run before the leak check, so this does not leak.
+    let b = Box::into_raw(Box::new(42u64));
+    unsafe {
+        assert_eq!(__cxa_atexit(free_box, b.cast(), std::ptr::null_mut()), 0);
+    }
+}
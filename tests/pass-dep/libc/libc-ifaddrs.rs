@@ -0,0 +1,36 @@
+//@only-target-linux
+use std::ffi::CStr;
+use std::ptr;
+
+fn test_getifaddrs() {
+    unsafe {
+        let mut ifap = ptr::null_mut();
+        assert_eq!(libc::getifaddrs(&mut ifap), 0);
+        let ifa = &*ifap;
+        assert!(ifa.ifa_next.is_null());
+        assert_eq!(CStr::from_ptr(ifa.ifa_name), c"lo");
+        assert_eq!(ifa.ifa_flags & libc::IFF_LOOPBACK as u32, libc::IFF_LOOPBACK as u32);
+        assert_eq!(ifa.ifa_flags & libc::IFF_UP as u32, libc::IFF_UP as u32);
+
+        let addr = &*ifa.ifa_addr.cast::<libc::sockaddr_in>();
+        assert_eq!(i32::from(addr.sin_family), libc::AF_INET);
+        assert_eq!(addr.sin_addr.s_addr.to_ne_bytes(), [127, 0, 0, 1]);
+        let netmask = &*ifa.ifa_netmask.cast::<libc::sockaddr_in>();
+        assert_eq!(netmask.sin_addr.s_addr.to_ne_bytes(), [255, 0, 0, 0]);
+
+        libc::freeifaddrs(ifap);
+    }
+}
+
+fn test_if_nametoindex() {
+    unsafe {
+        assert_eq!(libc::if_nametoindex(c"lo".as_ptr()), 1);
+        assert_eq!(libc::if_nametoindex(c"eth0".as_ptr()), 0);
+        assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::ENODEV));
+    }
+}
+
+fn main() {
+    test_getifaddrs();
+    test_if_nametoindex();
+}
@@ -0,0 +1,108 @@
+//@only-target-linux
+use std::ffi::{c_char, c_void, CStr};
+use std::ptr;
+
+fn test_setlocale() {
+    unsafe {
+        let query = || CStr::from_ptr(libc::setlocale(libc::LC_ALL, ptr::null()));
+        assert_eq!(query(), c"C");
+
+        assert!(!libc::setlocale(libc::LC_ALL, c"".as_ptr()).is_null());
+        assert_eq!(query(), c"C");
+        let res = libc::setlocale(libc::LC_CTYPE, c"en_US.UTF-8".as_ptr());
+        assert_eq!(CStr::from_ptr(res), c"en_US.UTF-8");
+        assert_eq!(query(), c"en_US.UTF-8");
+
+        // Unsupported locales are rejected and do not change anything.
+        assert!(libc::setlocale(libc::LC_ALL, c"de_DE.ISO-8859-1".as_ptr()).is_null());
+        assert_eq!(query(), c"en_US.UTF-8");
+
+        assert_eq!(CStr::from_ptr(libc::nl_langinfo(libc::CODESET)), c"UTF-8");
+        assert_eq!(CStr::from_ptr(libc::nl_langinfo(libc::RADIXCHAR)), c".");
+    }
+}
+
+fn test_newlocale() {
+    unsafe {
+        let loc = libc::newlocale(libc::LC_ALL_MASK, c"C.UTF-8".as_ptr(), ptr::null_mut());
+        assert!(!loc.is_null());
+        assert_eq!(libc::uselocale(loc), libc::LC_GLOBAL_LOCALE);
+        // Querying does not change the thread's locale.
+        assert_eq!(libc::uselocale(ptr::null_mut()), loc);
+        // Other threads are not affected.
+        std::thread::spawn(|| assert_eq!(libc::uselocale(ptr::null_mut()), libc::LC_GLOBAL_LOCALE))
+            .join()
+            .unwrap();
+        assert_eq!(libc::uselocale(libc::LC_GLOBAL_LOCALE), loc);
+        libc::freelocale(loc);
+
+        let loc = libc::newlocale(libc::LC_ALL_MASK, c"tlh_KX".as_ptr(), ptr::null_mut());
+        assert!(loc.is_null());
+        assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::ENOENT));
+    }
+}
+
+fn test_iconv() {
+    type IconvT = *mut c_void;
+    extern "C" {
+        fn iconv_open(tocode: *const c_char, fromcode: *const c_char) -> IconvT;
+        fn iconv(
+            cd: IconvT,
+            inbuf: *mut *mut c_char,
+            inbytesleft: *mut usize,
+            outbuf: *mut *mut c_char,
+            outbytesleft: *mut usize,
+        ) -> usize;
+        fn iconv_close(cd: IconvT) -> i32;
+    }
+    let last_error = || std::io::Error::last_os_error().raw_os_error().unwrap();
+
+    unsafe {
+        let cd = iconv_open(c"ISO-8859-1".as_ptr(), c"UTF-8".as_ptr());
+        assert_eq!(cd as usize, usize::MAX);
+        assert_eq!(last_error(), libc::EINVAL);
+
+        let cd = iconv_open(c"UTF-8//TRANSLIT".as_ptr(), c"utf8".as_ptr());
+        assert_ne!(cd as usize, usize::MAX);
+
+        // "ä" is two bytes, so it does not fit into the remaining output space.
+        let mut input = *b"abc\xc3\xa4";
+        let mut output = [0u8; 4];
+        let mut in_ptr = input.as_mut_ptr().cast::<c_char>();
+        let mut in_left = input.len();
+        let mut out_ptr = output.as_mut_ptr().cast::<c_char>();
+        let mut out_left = output.len();
+        let res = iconv(cd, &mut in_ptr, &mut in_left, &mut out_ptr, &mut out_left);
+        assert_eq!(res, usize::MAX);
+        assert_eq!(last_error(), libc::E2BIG);
+        assert_eq!((in_left, out_left), (2, 1));
+        assert_eq!(&output[..3], b"abc");
+
+        // Incomplete and invalid input.
+        let mut input = *b"a\xc3";
+        let mut in_ptr = input.as_mut_ptr().cast::<c_char>();
+        let mut in_left = input.len();
+        let mut out_ptr = output.as_mut_ptr().cast::<c_char>();
+        let mut out_left = output.len();
+        assert_eq!(iconv(cd, &mut in_ptr, &mut in_left, &mut out_ptr, &mut out_left), usize::MAX);
+        assert_eq!(last_error(), libc::EINVAL);
+        assert_eq!(in_left, 1);
+        let mut input = *b"\xff";
+        let mut in_ptr = input.as_mut_ptr().cast::<c_char>();
+        let mut in_left = input.len();
+        assert_eq!(iconv(cd, &mut in_ptr, &mut in_left, &mut out_ptr, &mut out_left), usize::MAX);
+        assert_eq!(last_error(), libc::EILSEQ);
+
+        // Resetting the state.
+        let null = ptr::null_mut();
+        assert_eq!(iconv(cd, null, null.cast(), null, null.cast()), 0);
+
+        assert_eq!(iconv_close(cd), 0);
+    }
+}
+
+fn main() {
+    test_setlocale();
+    test_newlocale();
+    test_iconv();
+}
@@ -52,6 +52,22 @@ fn test_memcpy() {
         );
         assert_eq!(*dest, 123);
     }
+
+    // Copying an entire large allocation shares its bytes; writing to either side must not
+    // affect the other.
+    unsafe {
+        let size = 1 << 20;
+        let src = libc::malloc(size) as *mut u8;
+        ptr::write_bytes(src, 7, size);
+        let dest = libc::malloc(size) as *mut u8;
+        libc::memcpy(dest.cast(), src.cast(), size);
+        *dest = 1;
+        *src.add(size - 1) = 2;
+        assert_eq!((*src, *src.add(size - 1)), (7, 2));
+        assert_eq!((*dest, *dest.add(size - 1)), (1, 7));
+        libc::free(src.cast());
+        libc::free(dest.cast());
+    }
 }
 
 fn test_strcpy() {
@@ -78,6 +94,67 @@ fn test_strcpy() {
     }
 }
 
+#[cfg(not(target_os = "windows"))]
+fn test_string_functions() {
+    use std::ffi::{c_char, c_void, CStr};
+
+    // Not all of these are exposed by the `libc` crate on every target.
+    extern "C" {
+        fn memmem(h: *const c_void, hlen: usize, n: *const c_void, nlen: usize) -> *mut c_void;
+        fn strnlen(s: *const c_char, maxlen: usize) -> usize;
+        fn stpcpy(dest: *mut c_char, src: *const c_char) -> *mut c_char;
+        fn strlcpy(dest: *mut c_char, src: *const c_char, size: usize) -> usize;
+        fn strlcat(dest: *mut c_char, src: *const c_char, size: usize) -> usize;
+        fn strcasecmp(s1: *const c_char, s2: *const c_char) -> i32;
+        fn strsep(stringp: *mut *mut c_char, delim: *const c_char) -> *mut c_char;
+    }
+
+    unsafe {
+        let haystack = b"hello world";
+        let found = memmem(haystack.as_ptr().cast(), haystack.len(), b"wor".as_ptr().cast(), 3);
+        assert_eq!(found, haystack.as_ptr().add(6) as *mut c_void);
+        let found = memmem(haystack.as_ptr().cast(), haystack.len(), b"xyz".as_ptr().cast(), 3);
+        assert!(found.is_null());
+
+        // `strnlen` does not look beyond `maxlen`, so this needs no null terminator.
+        let unterminated = [b'a' as c_char; 4];
+        assert_eq!(strnlen(unterminated.as_ptr(), 4), 4);
+        assert_eq!(strnlen(c"ab".as_ptr(), 4), 2);
+
+        let mut buf = [0 as c_char; 8];
+        let end = stpcpy(buf.as_mut_ptr(), c"abc".as_ptr());
+        assert_eq!(end, buf.as_mut_ptr().add(3));
+        let end = stpcpy(end, c"de".as_ptr());
+        assert_eq!(end, buf.as_mut_ptr().add(5));
+        assert_eq!(CStr::from_ptr(buf.as_ptr()), c"abcde");
+
+        let mut buf = [0x7f as c_char; 4];
+        assert_eq!(strlcpy(buf.as_mut_ptr(), c"rustacean".as_ptr(), buf.len()), 9);
+        assert_eq!(CStr::from_ptr(buf.as_ptr()), c"rus");
+        let mut buf = [0 as c_char; 6];
+        assert_eq!(strlcpy(buf.as_mut_ptr(), c"ab".as_ptr(), buf.len()), 2);
+        assert_eq!(strlcat(buf.as_mut_ptr(), c"cdefg".as_ptr(), buf.len()), 7);
+        assert_eq!(CStr::from_ptr(buf.as_ptr()), c"abcde");
+
+        assert_eq!(strcasecmp(c"Hello".as_ptr(), c"hELLO".as_ptr()), 0);
+        assert!(strcasecmp(c"abc".as_ptr(), c"ABD".as_ptr()) < 0);
+        assert!(strcasecmp(c"abcd".as_ptr(), c"ABC".as_ptr()) > 0);
+
+        let mut buf = *b"a,b;;c\0";
+        let mut stringp = buf.as_mut_ptr().cast::<c_char>();
+        let mut tokens = Vec::new();
+        loop {
+            let token = strsep(&mut stringp, c",;".as_ptr());
+            if token.is_null() {
+                break;
+            }
+            tokens.push(CStr::from_ptr(token).to_str().unwrap());
+        }
+        assert_eq!(tokens, ["a", "b", "", "c"]);
+        assert!(stringp.is_null());
+    }
+}
+
 fn test_malloc() {
     // Test that small allocations sometimes *are* not very aligned.
     let saw_unaligned = (0..64).any(|_| unsafe {
@@ -299,4 +376,6 @@ fn main() {
 
     test_memcpy();
     test_strcpy();
+    #[cfg(not(target_os = "windows"))]
+    test_string_functions();
 }
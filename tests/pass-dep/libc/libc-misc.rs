@@ -31,18 +31,40 @@ fn test_thread_local_errno() {
 }
 
 fn test_environ() {
-    // Just a smoke test for now, checking that the extern static exists.
+    use std::ffi::CStr;
+
     extern "C" {
         static mut environ: *const *const libc::c_char;
     }
 
-    unsafe {
-        let mut e = environ;
-        // Iterate to the end.
-        while !(*e).is_null() {
-            e = e.add(1);
+    /// Walks `environ` and collects all its entries.
+    fn environ_entries() -> Vec<String> {
+        let mut entries = Vec::new();
+        unsafe {
+            let mut e = environ;
+            while !(*e).is_null() {
+                entries.push(CStr::from_ptr(*e).to_str().unwrap().to_owned());
+                e = e.add(1);
+            }
         }
+        entries
+    }
+
+    // `environ` reflects changes made with `setenv` and `unsetenv`.
+    unsafe {
+        assert_eq!(libc::setenv(c"MIRI_ENVIRON".as_ptr(), c"1".as_ptr(), 1), 0);
+    }
+    assert!(environ_entries().iter().any(|e| e == "MIRI_ENVIRON=1"));
+    unsafe {
+        assert_eq!(libc::setenv(c"MIRI_ENVIRON".as_ptr(), c"2".as_ptr(), 1), 0);
+    }
+    let entries = environ_entries();
+    assert!(entries.iter().any(|e| e == "MIRI_ENVIRON=2"));
+    assert!(!entries.iter().any(|e| e == "MIRI_ENVIRON=1"));
+    unsafe {
+        assert_eq!(libc::unsetenv(c"MIRI_ENVIRON".as_ptr()), 0);
     }
+    assert!(!environ_entries().iter().any(|e| e.starts_with("MIRI_ENVIRON=")));
 }
 
 #[cfg(target_os = "linux")]
@@ -0,0 +1,142 @@
+//@ignore-target-windows: only very limited libc on Windows
+use std::ffi::{c_char, c_int, CStr};
+
+/// Formats with `snprintf` and compares the result.
+macro_rules! assert_snprintf {
+    ($expected:expr, $format:expr $(, $arg:expr)* $(,)?) => {{
+        let mut buf = [0u8; 128];
+        let format: &CStr = $format;
+        let len = unsafe {
+            libc::snprintf(buf.as_mut_ptr().cast::<c_char>(), buf.len(), format.as_ptr() $(, $arg)*)
+        };
+        let len = usize::try_from(len).unwrap();
+        assert_eq!(std::str::from_utf8(&buf[..len]).unwrap(), $expected);
+        assert_eq!(buf[len], 0);
+    }};
+}
+
+fn test_integers() {
+    assert_snprintf!(
+        "42|   42|42   |00042|+42| 42",
+        c"%d|%5d|%-5d|%05d|%+d|% d",
+        42,
+        42,
+        42,
+        42,
+        42,
+        42
+    );
+    assert_snprintf!("-7 -2147483648", c"%i %d", -7, i32::MIN);
+    assert_snprintf!(
+        "3000000000 ff FF 10 0xff 010",
+        c"%u %x %X %o %#x %#o",
+        3000000000u32,
+        255,
+        255,
+        8,
+        255,
+        8,
+    );
+    assert_snprintf!(
+        "-1 9223372036854775807 12",
+        c"%ld %lld %zu",
+        -1 as libc::c_long,
+        i64::MAX as libc::c_longlong,
+        12usize,
+    );
+    assert_snprintf!("44 4464", c"%hhd %hu", 300, 70000);
+    assert_snprintf!("005||     00a", c"%.3d|%.0d|%8.3x", 5, 0, 10);
+    assert_snprintf!("   1|2   |003", c"%*d|%-*d|%.*d", 4, 1, 4, 2, 3, 3);
+}
+
+fn test_floats() {
+    assert_snprintf!(
+        "3.141590 2.50     -1.000 1.3     |",
+        c"%f %.2f %10.3f %-8.1f|",
+        3.14159,
+        2.5,
+        -1.0005,
+        1.26
+    );
+    assert_snprintf!("1.234568e+04 1.200000E-04 0.00e+00", c"%e %E %.2e", 12345.678, 0.00012, 0.0);
+    assert_snprintf!(
+        "100000 1e+06 0.0001 1.234e-05 1E-10",
+        c"%g %g %g %g %G",
+        100000.0,
+        1000000.0,
+        0.0001,
+        0.00001234,
+        1e-10,
+    );
+    assert_snprintf!("3. 1.50000 +2.0", c"%#.0f %#g %+.1f", 3.0, 1.5, 2.0);
+    assert_snprintf!("inf -INF nan", c"%f %F %f", f64::INFINITY, f64::NEG_INFINITY, f64::NAN);
+}
+
+fn test_chars_and_strings() {
+    assert_snprintf!(
+        "ab|str|abc|ab    |    ab",
+        c"%c%c|%s|%.3s|%-6s|%6s",
+        'a' as c_int,
+        'b' as c_int,
+        c"str".as_ptr(),
+        c"abcdef".as_ptr(),
+        c"ab".as_ptr(),
+        c"ab".as_ptr(),
+    );
+    // With a precision, the string does not need to be null-terminated.
+    let unterminated = *b"xyz";
+    assert_snprintf!("xy", c"%.2s", unterminated.as_ptr());
+    assert_snprintf!("100%", c"100%%");
+    let x = 0u8;
+    assert_snprintf!(format!("{:p}", &x).as_str(), c"%p", &x as *const u8);
+}
+
+fn test_snprintf_truncation() {
+    let mut buf = [b'x'; 8];
+    let len = unsafe { libc::snprintf(buf.as_mut_ptr().cast(), 4, c"hello".as_ptr()) };
+    assert_eq!(len, 5);
+    assert_eq!(&buf, b"hel\0xxxx");
+    // With a size of 0, nothing is written, but the length is still computed.
+    let len = unsafe { libc::snprintf(std::ptr::null_mut(), 0, c"%d".as_ptr(), 12345) };
+    assert_eq!(len, 5);
+}
+
+fn test_overflow() {
+    // Output that is longer than `INT_MAX` bytes cannot be counted, so it is an error, even if it
+    // is not written anywhere. This must not make Miri allocate the padding either.
+    let overflows = |len: c_int| {
+        assert_eq!(len, -1);
+        assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::EOVERFLOW));
+    };
+    unsafe {
+        let null = std::ptr::null_mut();
+        overflows(libc::snprintf(null, 0, c"%2147483648d".as_ptr(), 1));
+        overflows(libc::snprintf(null, 0, c"x%2147483647d".as_ptr(), 1));
+        overflows(libc::snprintf(null, 0, c"%*d".as_ptr(), i32::MIN, 1));
+        overflows(libc::snprintf(null, 0, c"%.*f".as_ptr(), i32::MAX, 1.0));
+        overflows(libc::snprintf(null, 0, c"%.2147483648x".as_ptr(), 1));
+        // A large precision that only limits the output is fine.
+        assert_eq!(libc::snprintf(null, 0, c"%.*s".as_ptr(), i32::MAX, c"abc".as_ptr()), 3);
+        assert_eq!(libc::snprintf(null, 0, c"%.*g".as_ptr(), i32::MAX, 1.5), 3);
+    }
+}
+
+fn test_output() {
+    unsafe {
+        assert_eq!(libc::printf(c"printf %s %d\n".as_ptr(), c"works".as_ptr(), 1), 15);
+        assert_eq!(libc::dprintf(1, c"dprintf %05.1f\n".as_ptr(), 3.14159), 14);
+        #[cfg(target_os = "linux")]
+        libc::fprintf(libc::stdout, c"fprintf %x\n".as_ptr(), 255);
+        #[cfg(not(target_os = "linux"))]
+        libc::printf(c"fprintf %x\n".as_ptr(), 255);
+    }
+}
+
+fn main() {
+    test_integers();
+    test_floats();
+    test_chars_and_strings();
+    test_snprintf_truncation();
+    test_overflow();
+    test_output();
+}
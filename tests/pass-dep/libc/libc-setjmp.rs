@@ -0,0 +1,59 @@
+//@ignore-target-windows: no `setjmp` on Windows
+use std::ffi::c_int;
+use std::panic;
+use std::ptr::addr_of_mut;
+
+/// Large enough for the `jmp_buf` of all supported targets.
+type JmpBuf = [u64; 64];
+
+extern "C" {
+    fn setjmp(env: *mut JmpBuf) -> c_int;
+    fn longjmp(env: *mut JmpBuf, val: c_int) -> !;
+}
+
+static mut ENV: JmpBuf = [0; 64];
+
+fn jump_from_depth(depth: u32, val: c_int) -> ! {
+    // This local is deallocated by the jump, without running any destructors.
+    let _local = [depth; 4];
+    if depth == 0 {
+        unsafe { longjmp(addr_of_mut!(ENV), val) }
+    } else {
+        jump_from_depth(depth - 1, val)
+    }
+}
+
+static mut CATCH_ENV: JmpBuf = [0; 64];
+
+fn jump_out_of_catch_unwind() {
+    if unsafe { setjmp(addr_of_mut!(CATCH_ENV)) } == 0 {
+        // The `catch_unwind` call is abandoned, like all other calls that are jumped over.
+        let _ = panic::catch_unwind(|| unsafe { longjmp(addr_of_mut!(CATCH_ENV), 1) });
+        unreachable!();
+    }
+    // Unwinding still works afterwards.
+    let res = panic::catch_unwind(|| panic::resume_unwind(Box::new(42)));
+    assert_eq!(*res.unwrap_err().downcast::<i32>().unwrap(), 42);
+}
+
+fn main() {
+    jump_out_of_catch_unwind();
+
+    let mut jumps = 0;
+    let res = unsafe { setjmp(addr_of_mut!(ENV)) };
+    match res {
+        0 => {
+            assert_eq!(jumps, 0);
+            jumps += 1;
+            jump_from_depth(5, 42);
+        }
+        42 => {
+            assert_eq!(jumps, 1);
+            jumps += 1;
+            // Jumping with 0 makes `setjmp` return 1.
+            jump_from_depth(0, 0);
+        }
+        1 => assert_eq!(jumps, 2),
+        _ => unreachable!(),
+    }
+}
@@ -0,0 +1,29 @@
+//@ignore-target-windows: no `errno` on Windows
+//@compile-flags: -Zmiri-shim-stubs=tests/shim-stubs.json
+
+use std::io;
+
+extern "C" {
+    fn stub_init();
+    fn stub_get(len: usize, buf: *mut u8) -> isize;
+    fn stub_open() -> i32;
+    fn stub_max() -> u8;
+    fn stub_negative() -> i64;
+}
+
+fn main() {
+    unsafe {
+        stub_init();
+
+        let mut buf = [1u8; 6];
+        assert_eq!(stub_get(buf.len(), buf.as_mut_ptr()), 4);
+        assert_eq!(buf, [0, 0, 0, 0, 1, 1]);
+
+        assert_eq!(stub_open(), -1);
+        assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::ENOSYS));
+
+        assert_eq!(stub_max(), u8::MAX);
+        assert_eq!(stub_negative(), -1);
+    }
+}
+
@@ -0,0 +1,52 @@
+//@ignore-target-windows: no libc time APIs on Windows
+// Time zone conversions work with isolation, since they only depend on `TZ`.
+use std::{env, ffi::CStr, mem};
+
+fn localtime(time: libc::time_t) -> libc::tm {
+    let mut tm = mem::MaybeUninit::<libc::tm>::uninit();
+    unsafe {
+        libc::tzset();
+        assert!(!libc::localtime_r(&time, tm.as_mut_ptr()).is_null());
+        tm.assume_init()
+    }
+}
+
+#[track_caller]
+fn check(time: libc::time_t, hour: i32, isdst: i32, gmtoff: i64, zone: &str) {
+    let tm = localtime(time);
+    assert_eq!(tm.tm_hour, hour);
+    assert_eq!(tm.tm_isdst, isdst);
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
+    {
+        assert_eq!(tm.tm_gmtoff as i64, gmtoff);
+        assert_eq!(unsafe { CStr::from_ptr(tm.tm_zone) }.to_str().unwrap(), zone);
+    }
+    let _ = (gmtoff, zone);
+}
+
+fn main() {
+    // 2024-01-15 12:00:00 UTC and 2024-07-15 12:00:00 UTC.
+    const WINTER: libc::time_t = 1705320000;
+    const SUMMER: libc::time_t = 1721044800;
+
+    // Without `TZ`, local time is UTC.
+    check(WINTER, 12, 0, 0, "UTC");
+
+    // IANA time zone names.
+    env::set_var("TZ", "Europe/Berlin");
+    check(WINTER, 13, 0, 3600, "CET");
+    check(SUMMER, 14, 1, 7200, "CEST");
+    env::set_var("TZ", ":America/New_York");
+    check(SUMMER, 8, 1, -14400, "EDT");
+
+    // POSIX time zone rules.
+    env::set_var("TZ", "AEST-10AEDT,M10.1.0,M4.1.0/3");
+    check(WINTER, 23, 1, 39600, "AEDT");
+    check(SUMMER, 22, 0, 36000, "AEST");
+    env::set_var("TZ", "<+0530>-5:30");
+    check(WINTER, 17, 0, 19800, "+0530");
+
+    // Offsets of more than 24 hours are invalid, so local time falls back to UTC.
+    env::set_var("TZ", "ABC99");
+    check(WINTER, 12, 0, 0, "UTC");
+}
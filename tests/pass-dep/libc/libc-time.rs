@@ -78,12 +78,12 @@ fn test_localtime_r() {
     assert_eq!(tm.tm_year, 124);
     assert_eq!(tm.tm_wday, 0);
     assert_eq!(tm.tm_yday, 97);
-    assert_eq!(tm.tm_isdst, -1);
+    assert_eq!(tm.tm_isdst, 0);
     #[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
     assert_eq!(tm.tm_gmtoff, 0);
     #[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
     unsafe {
-        assert_eq!(std::ffi::CStr::from_ptr(tm.tm_zone).to_str().unwrap(), "+00")
+        assert_eq!(std::ffi::CStr::from_ptr(tm.tm_zone).to_str().unwrap(), "GMT")
     };
 
     // The returned value is the pointer passed in.
@@ -0,0 +1,19 @@
+//@ignore-target-windows: no `uname` on Windows
+//@compile-flags: -Zmiri-os-release=0123456789012345678901234567890123456789012345678901234567890123
+// The longest accepted release fits, even though the version string that embeds it does not.
+use std::ffi::CStr;
+use std::mem::MaybeUninit;
+
+fn main() {
+    unsafe {
+        let mut buf = MaybeUninit::<libc::utsname>::uninit();
+        assert_eq!(libc::uname(buf.as_mut_ptr()), 0);
+        let buf = buf.assume_init();
+        let release = CStr::from_ptr(buf.release.as_ptr());
+        assert_eq!(release.to_bytes().len(), 64);
+        // The version is truncated to fit its field.
+        let version = CStr::from_ptr(buf.version.as_ptr());
+        assert!(version.to_bytes().starts_with(b"#1 "));
+        assert!(version.to_bytes().len() < buf.version.len());
+    }
+}
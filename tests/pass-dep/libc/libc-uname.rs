@@ -0,0 +1,38 @@
+//@ignore-target-windows: no `uname` on Windows
+//@compile-flags: -Zmiri-hostname=testhost -Zmiri-os-release=1.2.3
+use std::ffi::CStr;
+use std::mem::MaybeUninit;
+
+fn test_uname() {
+    unsafe {
+        let mut buf = MaybeUninit::<libc::utsname>::uninit();
+        assert_eq!(libc::uname(buf.as_mut_ptr()), 0);
+        let buf = buf.assume_init();
+        assert_eq!(CStr::from_ptr(buf.nodename.as_ptr()), c"testhost");
+        assert_eq!(CStr::from_ptr(buf.release.as_ptr()), c"1.2.3");
+        let sysname = CStr::from_ptr(buf.sysname.as_ptr());
+        if cfg!(target_os = "linux") {
+            assert_eq!(sysname, c"Linux");
+        } else if cfg!(target_os = "macos") {
+            assert_eq!(sysname, c"Darwin");
+        }
+    }
+}
+
+fn test_gethostname() {
+    unsafe {
+        let mut buf = [0u8; 16];
+        assert_eq!(libc::gethostname(buf.as_mut_ptr().cast(), buf.len()), 0);
+        assert_eq!(CStr::from_bytes_until_nul(&buf).unwrap(), c"testhost");
+
+        // The name and its null terminator do not fit.
+        let mut buf = [0u8; 8];
+        assert_eq!(libc::gethostname(buf.as_mut_ptr().cast(), buf.len()), -1);
+        assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::ENAMETOOLONG));
+    }
+}
+
+fn main() {
+    test_uname();
+    test_gethostname();
+}
@@ -0,0 +1,38 @@
+//@ignore-target-windows: No pthreads on Windows
+// With the bias at 1, a ready higher-priority thread always takes over from a lower-priority one.
+//@compile-flags: -Zmiri-preemption-rate=0 -Zmiri-thread-priority-bias=1
+use std::sync::{Arc, Barrier, Mutex};
+use std::thread;
+
+fn set_own_priority(priority: i32) {
+    let mut param: libc::sched_param = unsafe { std::mem::zeroed() };
+    param.sched_priority = priority;
+    let res =
+        unsafe { libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) };
+    assert_eq!(res, 0);
+}
+
+fn main() {
+    let barrier = Arc::new(Barrier::new(2));
+    let finished = Arc::new(Mutex::new(Vec::new()));
+    let spawn = |name: &'static str, priority: i32| {
+        let barrier = Arc::clone(&barrier);
+        let finished = Arc::clone(&finished);
+        thread::spawn(move || {
+            set_own_priority(priority);
+            // Both threads are ready to run from here on.
+            barrier.wait();
+            let mut sum = 0u64;
+            for i in 0..1000 {
+                sum = std::hint::black_box(sum + i);
+            }
+            assert_eq!(sum, 499500);
+            finished.lock().unwrap().push(name);
+        })
+    };
+    let low = spawn("low", 1);
+    let high = spawn("high", 50);
+    low.join().unwrap();
+    high.join().unwrap();
+    assert_eq!(*finished.lock().unwrap(), ["high", "low"]);
+}
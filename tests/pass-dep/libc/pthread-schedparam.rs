@@ -0,0 +1,56 @@
+//@ignore-target-windows: No pthreads on Windows
+use std::mem::MaybeUninit;
+use std::thread;
+
+fn sched_param(priority: i32) -> libc::sched_param {
+    // Some targets have additional private fields.
+    let mut param: libc::sched_param = unsafe { std::mem::zeroed() };
+    param.sched_priority = priority;
+    param
+}
+
+fn set_priority(thread: libc::pthread_t, priority: i32) {
+    let param = sched_param(priority);
+    let res = unsafe { libc::pthread_setschedparam(thread, libc::SCHED_FIFO, &param) };
+    assert_eq!(res, 0);
+}
+
+fn get_priority(thread: libc::pthread_t) -> i32 {
+    let mut policy = MaybeUninit::<i32>::uninit();
+    let mut param = sched_param(-1);
+    let res = unsafe { libc::pthread_getschedparam(thread, policy.as_mut_ptr(), &mut param) };
+    assert_eq!(res, 0);
+    param.sched_priority
+}
+
+fn main() {
+    let main = unsafe { libc::pthread_self() };
+    assert_eq!(get_priority(main), 0);
+
+    // Invalid policies are rejected.
+    let param = sched_param(0);
+    let res = unsafe { libc::pthread_setschedparam(main, -1, &param) };
+    assert_eq!(res, libc::EINVAL);
+
+    // Threads that do not exist are rejected.
+    let bogus = 1000usize as libc::pthread_t;
+    let res = unsafe { libc::pthread_setschedparam(bogus, libc::SCHED_FIFO, &param) };
+    assert_eq!(res, libc::ESRCH);
+    let mut policy = MaybeUninit::<i32>::uninit();
+    let mut param = sched_param(-1);
+    let res = unsafe { libc::pthread_getschedparam(bogus, policy.as_mut_ptr(), &mut param) };
+    assert_eq!(res, libc::ESRCH);
+
+    // A low-priority thread and a high-priority thread racing each other.
+    let low = thread::spawn(|| {
+        set_priority(unsafe { libc::pthread_self() }, 1);
+        assert_eq!(get_priority(unsafe { libc::pthread_self() }), 1);
+    });
+    let high = thread::spawn(|| {
+        set_priority(unsafe { libc::pthread_self() }, 50);
+        assert_eq!(get_priority(unsafe { libc::pthread_self() }), 50);
+    });
+    low.join().unwrap();
+    high.join().unwrap();
+    assert_eq!(get_priority(main), 0);
+}
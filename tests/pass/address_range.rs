@@ -0,0 +1,9 @@
+//@compile-flags: -Zmiri-address-range=0x100000..0x200000 -Zmiri-address-gap=1 -Zmiri-aslr
+
+fn main() {
+    let boxes = (0..16).map(Box::new).collect::<Vec<_>>();
+    for b in &boxes {
+        let addr = &**b as *const i32 as usize;
+        assert!((0x100000..0x200000).contains(&addr));
+    }
+}
@@ -0,0 +1,18 @@
+//@compile-flags: -Zmiri-aliasing-suppressions=tests/aliasing-suppressions
+
+mod third_party {
+    pub fn alias(x: &mut u8) -> u8 {
+        let p = x as *mut u8;
+        let r = unsafe { &mut *p };
+        *x = 1;
+        // `r` was invalidated by the write through `x`.
+        *r
+    }
+}
+
+fn main() {
+    let mut x = 0;
+    assert_eq!(third_party::alias(&mut x), 1);
+    // The warning is only shown once per location.
+    assert_eq!(third_party::alias(&mut x), 1);
+}
@@ -0,0 +1,15 @@
+//@compile-flags: -Zmiri-alloc-failure-rate=1
+// Skip the runtime setup of std: its allocations would fail, too, and abort the program.
+#![no_main]
+
+#[no_mangle]
+fn miri_start(_argc: isize, _argv: *const *const u8) -> isize {
+    for size in [1, 16, 4096] {
+        let mut v: Vec<u8> = Vec::new();
+        assert!(v.try_reserve(size).is_err());
+        assert_eq!(v.capacity(), 0);
+    }
+    let mut s = String::new();
+    assert!(s.try_reserve_exact(8).is_err());
+    0
+}
@@ -0,0 +1,33 @@
+//@compile-flags: -Zmiri-env-set=RUST_BACKTRACE=1
+// Capturing and printing a backtrace also works with isolation, where the current directory is not
+// available to shorten the paths.
+
+use std::backtrace::Backtrace;
+
+#[inline(never)]
+fn func_a() -> Backtrace {
+    func_b::<u8>()
+}
+#[inline(never)]
+fn func_b<T>() -> Backtrace {
+    func_c()
+}
+
+macro_rules! invoke_func_d {
+    () => {
+        func_d()
+    };
+}
+
+#[inline(never)]
+fn func_c() -> Backtrace {
+    invoke_func_d!()
+}
+#[inline(never)]
+fn func_d() -> Backtrace {
+    Backtrace::capture()
+}
+
+fn main() {
+    eprint!("{}", func_a());
+}
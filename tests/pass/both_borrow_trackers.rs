@@ -0,0 +1,55 @@
+//@compile-flags: -Zmiri-both-borrow-trackers
+// Code that both Stacked Borrows and Tree Borrows accept also runs with both of them enabled.
+use std::cell::Cell;
+
+fn main() {
+    references();
+    raw_pointers();
+    boxes();
+    interior_mutability();
+    two_phase_borrow();
+}
+
+fn references() {
+    let mut x = 0u32;
+    let r = &mut x;
+    *r += 1;
+    let s = &*r;
+    assert_eq!(*s, 1);
+    assert_eq!(x, 1);
+}
+
+fn raw_pointers() {
+    let mut arr = [0u8; 4];
+    let ptr = arr.as_mut_ptr();
+    unsafe {
+        *ptr = 1;
+        *ptr.add(3) = 4;
+        assert_eq!(*ptr.add(3), 4);
+    }
+    assert_eq!(arr, [1, 0, 0, 4]);
+}
+
+fn boxes() {
+    let b = Box::new(5i32);
+    let raw = Box::into_raw(b);
+    unsafe {
+        *raw += 1;
+        let b = Box::from_raw(raw);
+        assert_eq!(*b, 6);
+    }
+}
+
+fn interior_mutability() {
+    let c = Cell::new(1);
+    let r1 = &c;
+    let r2 = &c;
+    r1.set(2);
+    assert_eq!(r2.get(), 2);
+}
+
+fn two_phase_borrow() {
+    let mut v = vec![1, 2];
+    v.push(v.len());
+    assert_eq!(v, [1, 2, 2]);
+}
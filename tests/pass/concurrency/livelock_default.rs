@@ -0,0 +1,17 @@
+// Livelock detection is opt-in: without `-Zmiri-livelock-threshold`, a loop that keeps loading an
+// unchanged value is not reported, no matter how often it does that.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static STOP: AtomicBool = AtomicBool::new(false);
+
+fn main() {
+    let mut polls = 0;
+    while !STOP.load(Ordering::Relaxed) {
+        polls += 1;
+        if polls == 100_001 {
+            break;
+        }
+    }
+    assert_eq!(polls, 100_001);
+}
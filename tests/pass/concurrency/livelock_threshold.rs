@@ -0,0 +1,19 @@
+// A spin loop that another thread can end is not a livelock, even if it spins past the threshold.
+//@compile-flags: -Zmiri-livelock-threshold=10
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+static READY: AtomicBool = AtomicBool::new(false);
+
+fn main() {
+    let setter = thread::spawn(|| {
+        // Make sure the main thread spins for a while before the flag is set.
+        for _ in 0..100 {
+            thread::yield_now();
+        }
+        READY.store(true, Ordering::Relaxed);
+    });
+    while !READY.load(Ordering::Relaxed) {}
+    setter.join().unwrap();
+}
@@ -9,8 +9,8 @@ fn convert(a: &AtomicU16) -> &[AtomicU8; 2] {
     unsafe { std::mem::transmute(a) }
 }
 
-// We can't allow mixed-size accesses; they are not possible in C++ and even
-// Intel says you shouldn't do it.
+// Racing mixed-size reads are allowed by Rust's memory model (unlike racing mixed-size
+// accesses involving a write, see `tests/fail/data_race/mixed_size_write.rs`).
 fn main() {
     let a = AtomicU16::new(0);
     let a16 = &a;
@@ -22,7 +22,6 @@ fn main() {
         });
         s.spawn(|| {
             a8[0].load(Ordering::SeqCst);
-            //~^ ERROR: Race condition detected between (1) 2-byte atomic load on thread `unnamed-1` and (2) 1-byte atomic load on thread `unnamed-2`
         });
     });
 }
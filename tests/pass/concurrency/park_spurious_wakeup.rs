@@ -0,0 +1,56 @@
+//@compile-flags: -Zmiri-futex-spurious-wakeup-rate=0.5
+
+/// `thread::park` may return spuriously, so correct code has to park in a loop.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn park_loop() {
+    let flag = Arc::new(AtomicBool::new(false));
+    let t = {
+        let flag = flag.clone();
+        thread::spawn(move || {
+            while !flag.load(Ordering::Acquire) {
+                thread::park();
+            }
+        })
+    };
+    thread::yield_now();
+    flag.store(true, Ordering::Release);
+    t.thread().unpark();
+    t.join().unwrap();
+}
+
+fn park_timeout_loop() {
+    let start = Instant::now();
+    let deadline = start + Duration::from_millis(100);
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        thread::park_timeout(deadline - now);
+    }
+}
+
+fn condvar_loop() {
+    let pair = Arc::new((Mutex::new(false), Condvar::new()));
+    let t = {
+        let pair = pair.clone();
+        thread::spawn(move || {
+            let (lock, cvar) = &*pair;
+            let _guard = cvar.wait_while(lock.lock().unwrap(), |ready| !*ready).unwrap();
+        })
+    };
+    let (lock, cvar) = &*pair;
+    *lock.lock().unwrap() = true;
+    cvar.notify_one();
+    t.join().unwrap();
+}
+
+fn main() {
+    park_loop();
+    park_timeout_loop();
+    condvar_loop();
+}
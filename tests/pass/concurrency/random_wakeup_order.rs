@@ -0,0 +1,78 @@
+//@compile-flags: -Zmiri-random-wakeup-order
+
+/// With `-Zmiri-random-wakeup-order`, `notify_one` does not always wake up the thread that has been
+/// waiting the longest.
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+#[derive(Default)]
+struct State {
+    /// How many workers are waiting for `go`.
+    waiting: usize,
+    /// How many workers may stop waiting.
+    go: usize,
+    /// The workers that stopped waiting, in order.
+    woken: Vec<usize>,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    /// Signaled by `main` to let a worker go.
+    workers: Condvar,
+    /// Signaled by the workers when `waiting` or `woken` changes.
+    main: Condvar,
+}
+
+const WORKERS: usize = 3;
+
+/// Lets `WORKERS` threads wait on the same condvar, in a known order, then wakes them up one by
+/// one and returns the order in which they woke up.
+fn wakeup_order() -> Vec<usize> {
+    let shared = Arc::new(Shared::default());
+    let mut handles = Vec::new();
+    for i in 0..WORKERS {
+        let worker = shared.clone();
+        handles.push(thread::spawn(move || {
+            let mut state = worker.state.lock().unwrap();
+            state.waiting += 1;
+            worker.main.notify_all();
+            while state.go == 0 {
+                state = worker.workers.wait(state).unwrap();
+            }
+            state.go -= 1;
+            state.woken.push(i);
+            worker.main.notify_all();
+        }));
+        // Once `waiting` is increased, the worker is blocked on the condvar: `wait` releases the
+        // mutex only after enqueuing the thread.
+        let mut state = shared.state.lock().unwrap();
+        while state.waiting <= i {
+            state = shared.main.wait(state).unwrap();
+        }
+    }
+    for n in 1..=WORKERS {
+        let mut state = shared.state.lock().unwrap();
+        state.go += 1;
+        shared.workers.notify_one();
+        while state.woken.len() < n {
+            state = shared.main.wait(state).unwrap();
+        }
+    }
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    Arc::try_unwrap(shared).ok().unwrap().state.into_inner().unwrap().woken
+}
+
+fn main() {
+    let fifo: Vec<usize> = (0..WORKERS).collect();
+    let orders: Vec<Vec<usize>> = (0..10).map(|_| wakeup_order()).collect();
+    for order in &orders {
+        let mut sorted = order.clone();
+        sorted.sort();
+        assert_eq!(sorted, fifo);
+    }
+    // Each round wakes up the workers in order with probability 1/6.
+    assert!(orders.iter().any(|order| *order != fifo));
+}
@@ -0,0 +1,23 @@
+// The FIFO scheduler switches to the thread that has been ready to run for the longest time, not
+// to the next thread by ID.
+//@compile-flags: -Zmiri-scheduler=fifo -Zmiri-preemption-rate=0
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+fn main() {
+    let first = thread::spawn(|| {
+        thread::park();
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    });
+    // Let `first` run until it parks.
+    thread::yield_now();
+    let second = thread::spawn(|| NEXT.fetch_add(1, Ordering::Relaxed));
+    // `first` becomes ready again only after `second` was spawned, so `second` runs first, even
+    // though `first` has the lower ID.
+    first.thread().unpark();
+    assert_eq!(second.join().unwrap(), 0);
+    assert_eq!(first.join().unwrap(), 1);
+}
@@ -0,0 +1,22 @@
+// Round-robin scheduling preempts the active thread after every basic block, even though the
+// preemption rate is 0.
+//@compile-flags: -Zmiri-scheduler=round-robin -Zmiri-preemption-rate=0
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+fn main() {
+    static FLAG: AtomicBool = AtomicBool::new(false);
+    let setter = thread::spawn(|| FLAG.store(true, Ordering::Relaxed));
+    // Spin without ever yielding (`std::hint::spin_loop` would yield). The other thread gets to
+    // run long before the loop ends.
+    let mut set = false;
+    for _ in 0..100 {
+        if FLAG.load(Ordering::Relaxed) {
+            set = true;
+            break;
+        }
+    }
+    assert!(set, "the spinning thread was not preempted");
+    setter.join().unwrap();
+}
@@ -0,0 +1,62 @@
+// Only the spinning threads below get preempted. If they were not, the threads that set their
+// flags would never get to run.
+//@compile-flags: -Zmiri-preemption-rate=0 -Zmiri-thread-preemption-rate=spinner=1.0
+
+#[path = "../../utils/mod.rs"]
+mod utils;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+/// Spin until `flag` is set, without ever yielding (`std::hint::spin_loop` would yield).
+fn spin_until_set(flag: &AtomicBool) {
+    for _ in 0..1000 {
+        if flag.load(Ordering::Relaxed) {
+            return;
+        }
+    }
+    panic!("the spinning thread was not preempted");
+}
+
+/// The preemption rate can be set for a thread name on the command line.
+fn named_thread() {
+    static FLAG: AtomicBool = AtomicBool::new(false);
+    let spinner = thread::Builder::new()
+        .name("spinner".into())
+        .spawn(|| spin_until_set(&FLAG))
+        .unwrap();
+    let setter = thread::spawn(|| FLAG.store(true, Ordering::Relaxed));
+    spinner.join().unwrap();
+    setter.join().unwrap();
+}
+
+/// A thread can set its own preemption rate.
+fn extern_call() {
+    static FLAG: AtomicBool = AtomicBool::new(false);
+    let spinner = thread::spawn(|| {
+        unsafe { utils::miri_set_preemption_rate(1.0) };
+        spin_until_set(&FLAG)
+    });
+    let setter = thread::spawn(|| FLAG.store(true, Ordering::Relaxed));
+    spinner.join().unwrap();
+    setter.join().unwrap();
+}
+
+/// Threads without an override keep the global rate, i.e., they are not preempted here.
+fn not_preempted() {
+    static FLAG: AtomicBool = AtomicBool::new(false);
+    let spinner = thread::spawn(|| {
+        for _ in 0..1000 {
+            assert!(!FLAG.load(Ordering::Relaxed), "the thread was preempted");
+        }
+    });
+    let setter = thread::spawn(|| FLAG.store(true, Ordering::Relaxed));
+    spinner.join().unwrap();
+    setter.join().unwrap();
+}
+
+fn main() {
+    named_thread();
+    extern_call();
+    not_preempted();
+}
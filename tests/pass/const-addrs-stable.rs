@@ -0,0 +1,14 @@
+// With a single address per const, every evaluation of a const produces the same address.
+//@compile-flags: -Zinline-mir=no -Zmiri-addresses-per-const=1
+#![feature(strict_provenance)]
+
+fn main() {
+    let addr = const_addr();
+    for _ in 0..64 {
+        assert_eq!(const_addr(), addr);
+    }
+}
+
+fn const_addr() -> usize {
+    "test".as_bytes().as_ptr().addr()
+}
@@ -0,0 +1,22 @@
+//@compile-flags: -Zmiri-entry-fn=run::with_args
+
+fn main() {
+    unreachable!("`main` does not run with -Zmiri-entry-fn");
+}
+
+mod run {
+    #[derive(Default)]
+    struct Config {
+        name: String,
+        retries: u32,
+    }
+
+    #[allow(dead_code)]
+    fn with_args(config: Config, values: Vec<i32>, (): ()) -> Result<(), String> {
+        assert_eq!(config.name, "");
+        assert_eq!(config.retries, 0);
+        assert!(values.is_empty());
+        println!("running the entry function");
+        Ok(())
+    }
+}
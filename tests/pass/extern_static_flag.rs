@@ -0,0 +1,17 @@
+//@compile-flags: -Zmiri-extern-static=MY_FLAGS:4:2a2a2a2a -Zmiri-extern-static=MY_COUNTER:8
+
+extern "C" {
+    static MY_FLAGS: u32;
+    static mut MY_COUNTER: u64;
+}
+
+fn main() {
+    unsafe {
+        assert_eq!(MY_FLAGS, 0x2a2a2a2a);
+        let counter = MY_COUNTER;
+        assert_eq!(counter, 0);
+        MY_COUNTER += 1;
+        let counter = MY_COUNTER;
+        assert_eq!(counter, 1);
+    }
+}
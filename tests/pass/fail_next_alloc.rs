@@ -0,0 +1,14 @@
+#[path = "../utils/mod.rs"]
+mod utils;
+
+fn main() {
+    let mut v: Vec<u8> = Vec::new();
+    unsafe { utils::miri_fail_next_alloc() };
+    assert!(v.try_reserve(16).is_err());
+    // Only the next allocation fails.
+    v.try_reserve(16).unwrap();
+    unsafe { utils::miri_fail_next_alloc() };
+    assert!(v.try_reserve(64).is_err());
+    // The old allocation is untouched.
+    assert!(v.capacity() >= 16);
+}
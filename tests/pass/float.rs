@@ -24,6 +24,7 @@ fn main() {
     nan_casts();
     rounding();
     mul_add();
+    f16_f128_intrinsics();
     libm();
     test_fast();
     test_algebraic();
@@ -592,6 +593,34 @@ fn mul_add() {
     assert_eq!(f.to_bits(), f32::to_bits(-0.0));
 }
 
+// FIXME(f16_f128): use the methods once they are available
+fn f16_f128_intrinsics() {
+    use std::intrinsics::*;
+
+    unsafe {
+        assert_eq(fabsf16(-1.5), 1.5);
+        assert_eq(fabsf128(-1.5), 1.5);
+
+        assert_eq(floorf16(2.7), 2.0);
+        assert_eq(ceilf16(-2.7), -2.0);
+        assert_eq(truncf128(-2.7), -2.0);
+        assert_eq(roundf128(2.5), 3.0);
+        assert_eq(rintf16(2.5), 2.0);
+        assert_eq(rintf128(3.5), 4.0);
+
+        assert_eq(minnumf16(1.0, 2.0), 1.0);
+        assert_eq(maxnumf128(1.0, 2.0), 2.0);
+        assert_eq(copysignf16(1.0, -0.0), -1.0);
+        assert_eq(copysignf128(-1.0, 0.0), 1.0);
+
+        assert_eq(fmaf16(3.0, 2.0, 5.0), 11.0);
+        assert_eq(fmaf128(3.0, 2.0, 5.0), 11.0);
+
+        assert_eq(sqrtf16(16.0), 4.0);
+        assert_eq(powif16(2.0, 3), 8.0);
+    }
+}
+
 pub fn libm() {
     fn ldexp(a: f64, b: i32) -> f64 {
         extern "C" {
@@ -0,0 +1,32 @@
+//@compile-flags: -Zmiri-float-max-ulp-error=4
+use std::collections::HashSet;
+use std::hint::black_box;
+
+/// The distance between `a` and `b` in ULPs, for two positive finite floats.
+fn ulp_distance(a: f64, b: f64) -> u64 {
+    a.to_bits().abs_diff(b.to_bits())
+}
+
+fn main() {
+    let x = black_box(1.0f64);
+    let exact = 0.8414709848078965; // sin(1.0), correctly rounded
+    let mut results = HashSet::new();
+    for _ in 0..64 {
+        let res = black_box(x).sin();
+        // The host may be off by one ULP itself.
+        assert!(ulp_distance(res, exact) <= 5);
+        results.insert(res.to_bits());
+    }
+    // The results are not always the same.
+    assert!(results.len() > 1);
+
+    // Correctly rounded operations are not affected.
+    for _ in 0..64 {
+        assert_eq!(black_box(2.0f64).sqrt(), std::f64::consts::SQRT_2);
+        assert_eq!(black_box(2.0f64).mul_add(3.0, 1.0), 7.0);
+    }
+    // Zero results stay exact.
+    for _ in 0..64 {
+        assert_eq!(black_box(0.0f64).sin(), 0.0);
+    }
+}
@@ -0,0 +1,6 @@
+//@compile-flags: -Zmiri-seed=42
+
+fn main() {
+    // Under isolation, the process ID is made up from the seed.
+    assert_eq!(std::process::id(), 1042);
+}
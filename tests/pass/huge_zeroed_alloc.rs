@@ -0,0 +1,12 @@
+// Zeroed allocations are not materialized in host memory until they are used, so reserving a huge
+// arena and only touching a few bytes of it must not make Miri use as much memory.
+
+fn main() {
+    const SIZE: usize = 1 << 30;
+    let mut arena = vec![0u8; SIZE];
+    assert_eq!(arena[0], 0);
+    assert_eq!(arena[SIZE - 1], 0);
+    arena[SIZE / 2 + 1] = 42;
+    assert_eq!(arena.iter().step_by(1 << 20).map(|&b| b as u64).sum::<u64>(), 0);
+    assert_eq!(arena[SIZE / 2 + 1], 42);
+}
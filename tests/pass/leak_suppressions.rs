@@ -0,0 +1,21 @@
+//@ignore-target-windows: `System` does not allocate C heap memory on Windows
+//@compile-flags: -Zmiri-leak-suppressions=tests/leak-suppressions
+
+use std::alloc::{GlobalAlloc, Layout, System};
+
+mod global_cache {
+    pub fn fill() {
+        // Both the box and the buffer of the vector leak.
+        let cache: &'static mut Vec<u32> = Box::leak(Box::new(vec![1, 2, 3]));
+        cache.push(4);
+    }
+}
+
+fn leak_c_memory() {
+    let _ = unsafe { System.alloc(Layout::new::<u64>()) };
+}
+
+fn main() {
+    global_cache::fill();
+    leak_c_memory();
+}
@@ -0,0 +1,9 @@
+//@compile-flags: -Zmiri-litmus=3
+
+#[path = "../utils/mod.rs"]
+mod utils;
+
+fn main() {
+    let x = 1;
+    unsafe { utils::miri_observe("x", x) };
+}
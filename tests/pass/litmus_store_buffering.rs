@@ -0,0 +1,31 @@
+//@compile-flags: -Zmiri-model-check -Zmiri-litmus
+//@normalize-stderr-test: "\d+ execution" -> "N execution"
+//@normalize-stderr-test: " +\d+  a=" -> "             N  a="
+
+// The classic store buffering litmus test: each thread stores to one location and then loads from
+// the other one. With relaxed accesses, both loads may miss the other thread's store, so all four
+// outcomes must be reachable, including `a=0, b=0`.
+
+#[path = "../utils/mod.rs"]
+mod utils;
+
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+use std::thread;
+
+static X: AtomicU64 = AtomicU64::new(0);
+static Y: AtomicU64 = AtomicU64::new(0);
+
+fn main() {
+    let t1 = thread::spawn(|| {
+        X.store(1, Relaxed);
+        let a = Y.load(Relaxed);
+        unsafe { utils::miri_observe("a", a) };
+    });
+    let t2 = thread::spawn(|| {
+        Y.store(1, Relaxed);
+        let b = X.load(Relaxed);
+        unsafe { utils::miri_observe("b", b) };
+    });
+    t1.join().unwrap();
+    t2.join().unwrap();
+}
@@ -0,0 +1,17 @@
+//@compile-flags: -Zmiri-log-nondeterminism
+// Only keep the decision made when `callee` is entered, which must be logged once, not per call.
+//@normalize-stderr-test: "nondeterminism #\d+: \[consts\] picked const address salt \d+ for the first call to `[^`]*callee`" -> "CALLEE ENTERED"
+//@normalize-stderr-test: "(?m)^nondeterminism #[^\n]*\n" -> ""
+
+#[inline(never)]
+fn callee(x: u32) -> u32 {
+    x + 1
+}
+
+fn main() {
+    let mut x = 0;
+    for _ in 0..3 {
+        x = callee(x);
+    }
+    assert_eq!(x, 3);
+}
@@ -0,0 +1,24 @@
+//@compile-flags: -Zmiri-memory-limit=4096
+
+use std::alloc::{alloc, dealloc, Layout};
+
+fn main() {
+    // Allocations that exceed the limit fail...
+    let mut v: Vec<u8> = Vec::new();
+    assert!(v.try_reserve(8192).is_err());
+    // ...but smaller ones still succeed.
+    v.try_reserve(1024).unwrap();
+    // Growing the allocation counts only the additional bytes.
+    v.try_reserve_exact(2048).unwrap();
+    assert!(v.try_reserve_exact(8192).is_err());
+    drop(v);
+
+    // Freed memory is available again.
+    let layout = Layout::from_size_align(3000, 8).unwrap();
+    unsafe {
+        let ptr = alloc(layout);
+        assert!(!ptr.is_null());
+        assert!(alloc(layout).is_null());
+        dealloc(ptr, layout);
+    }
+}
@@ -0,0 +1,43 @@
+// This runs on a 16-bit target in CI (see `ci/ci.sh`), where the heap and `c_int` are tiny.
+//@compile-flags: -Cpanic=abort -Zmiri-aslr
+#![no_std]
+#![no_main]
+
+use core::ffi::{c_int, c_void};
+
+#[path = "../utils/mod.no_std.rs"]
+mod utils;
+
+extern "C" {
+    fn memchr(s: *const c_void, c: c_int, n: usize) -> *mut c_void;
+    fn memrchr(s: *const c_void, c: c_int, n: usize) -> *mut c_void;
+}
+
+#[no_mangle]
+fn miri_start(_argc: isize, _argv: *const *const u8) -> isize {
+    // On 16-bit targets, this is half of the address space, which must fit even with ASLR.
+    const COUNT: usize = 32;
+    const SIZE: usize = 1024;
+    let mut blocks = [core::ptr::null_mut(); COUNT];
+    for block in &mut blocks {
+        *block = unsafe { utils::miri_alloc(SIZE, 8) };
+        assert!(block.addr().checked_add(SIZE).is_some());
+    }
+    for block in blocks {
+        unsafe { utils::miri_dealloc(block, SIZE, 8) };
+    }
+
+    let bytes = *b"a_b_c";
+    unsafe {
+        let first = memchr(bytes.as_ptr().cast(), c_int::from(b'_'), bytes.len());
+        assert_eq!(first.cast::<u8>(), bytes.as_ptr().add(1).cast_mut());
+        let last = memrchr(bytes.as_ptr().cast(), c_int::from(b'_'), bytes.len());
+        assert_eq!(last.cast::<u8>(), bytes.as_ptr().add(3).cast_mut());
+    }
+    0
+}
+
+#[panic_handler]
+fn panic_handler(_: &core::panic::PanicInfo) -> ! {
+    unsafe { utils::miri_exit(101) }
+}
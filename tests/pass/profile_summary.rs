@@ -0,0 +1,30 @@
+//@compile-flags: -Cpanic=abort -Zmiri-profile-summary
+// The exact number of basic blocks depends on how the compiler lowers the loop.
+//@normalize-stderr-test: "(?m)^ +\d+ +0 +0  (.*)" -> "      N  0  0  $1"
+// Without std, the summary only lists the functions of this file.
+#![no_std]
+#![no_main]
+
+#[inline(never)]
+fn busy(n: u32) -> u32 {
+    let mut i = 0;
+    let mut sum = 0;
+    while i < n {
+        sum += i;
+        i += 1;
+    }
+    sum
+}
+
+#[no_mangle]
+fn miri_start(_argc: isize, _argv: *const *const u8) -> isize {
+    if busy(100) != 4950 {
+        return 1;
+    }
+    0
+}
+
+#[panic_handler]
+fn panic_handler(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,22 @@
+#[path = "../utils/mod.rs"]
+mod utils;
+
+fn main() {
+    // Restarting runs the program again from the start, so this is printed every time.
+    println!("start");
+    let mut v = vec![1, 2, 3];
+    let restarts = unsafe { utils::miri_restart_point() };
+    // Each continuation starts from the same state.
+    assert_eq!(v, [1, 2, 3]);
+    v.push(restarts + 4);
+    println!("continuation {restarts}: {v:?}");
+    // A second restart point, reached once per continuation.
+    let inner = unsafe { utils::miri_restart_point() };
+    println!("inner {inner}");
+    if restarts == 0 && inner == 0 {
+        unsafe { utils::miri_restart(1) };
+    }
+    if restarts < 2 {
+        unsafe { utils::miri_restart(0) };
+    }
+}
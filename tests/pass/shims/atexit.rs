@@ -0,0 +1,31 @@
+// `atexit` handlers run in reverse order of registration, also when exiting via `exit`.
+extern "C" {
+    fn atexit(f: extern "C" fn()) -> i32;
+    // Not `std::process::exit`, which does not run the handlers on Windows.
+    fn exit(code: i32) -> !;
+}
+
+extern "C" fn first() {
+    println!("first handler");
+}
+
+extern "C" fn second() {
+    println!("second handler");
+    // Handlers can register more handlers, which run next.
+    unsafe {
+        assert_eq!(atexit(third), 0);
+    }
+}
+
+extern "C" fn third() {
+    println!("third handler");
+}
+
+fn main() {
+    unsafe {
+        assert_eq!(atexit(first), 0);
+        assert_eq!(atexit(second), 0);
+    }
+    println!("main");
+    unsafe { exit(0) }
+}
@@ -0,0 +1,10 @@
+//@compile-flags: -Zmiri-env-file=tests/env-file
+
+fn main() {
+    // Set by the file.
+    assert_eq!(std::env::var("MIRI_ENV_FILE_VAR"), Ok("hello".to_owned()));
+    // Forwarded because it matches `MIRI_*`.
+    assert_eq!(std::env::var("MIRI_ENV_VAR_TEST"), Ok("0".to_owned()));
+    // Matches `MIRI_*`, but is excluded by `!MIRI_TEMP`.
+    assert!(std::env::var("MIRI_TEMP").is_err());
+}
@@ -0,0 +1,8 @@
+//@compile-flags: -Zmiri-env-forward=MIRI_*_TEST
+
+fn main() {
+    // Forwarded because it matches the pattern.
+    assert_eq!(std::env::var("MIRI_ENV_VAR_TEST"), Ok("0".to_owned()));
+    // Set by the test runner, but does not match the pattern.
+    assert!(std::env::var("MIRI_TEMP").is_err());
+}
@@ -0,0 +1,50 @@
+//@ignore-target-windows: File handling is not implemented yet
+//@ignore-host-windows: maps the host's `/tmp`
+//@compile-flags: -Zmiri-isolation-error=hide -Zmiri-map-dir=/tmp:/scratch:rw
+
+#![feature(io_error_more)]
+
+use std::fs;
+use std::io::ErrorKind;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+
+fn main() {
+    let dir = Path::new("/scratch/miri_test_fs_map_dir_rw");
+    let _ = fs::remove_dir_all(dir);
+    fs::create_dir(dir).unwrap();
+
+    // Files can be created, written, renamed and removed.
+    let file = dir.join("file.txt");
+    fs::write(&file, "hello").unwrap();
+    assert_eq!(fs::read_to_string(&file).unwrap(), "hello");
+    fs::rename(&file, dir.join("renamed.txt")).unwrap();
+    fs::rename(dir.join("renamed.txt"), &file).unwrap();
+
+    // Relative link targets stay relative.
+    let relative = dir.join("relative");
+    symlink("file.txt", &relative).unwrap();
+    assert_eq!(fs::read_link(&relative).unwrap(), Path::new("file.txt"));
+    assert_eq!(fs::read_to_string(&relative).unwrap(), "hello");
+
+    // Absolute link targets are paths of the program, not of the host.
+    let absolute = dir.join("absolute");
+    symlink(&file, &absolute).unwrap();
+    assert_eq!(fs::read_link(&absolute).unwrap(), file);
+    assert_eq!(fs::read_to_string(&absolute).unwrap(), "hello");
+
+    // Links out of the mapped directory cannot be created, so they cannot be used to get around
+    // isolation.
+    assert_eq!(
+        symlink("/etc/passwd", dir.join("escape")).unwrap_err().kind(),
+        ErrorKind::PermissionDenied
+    );
+    assert_eq!(
+        symlink("../../../../../../../etc/passwd", dir.join("escape")).unwrap_err().kind(),
+        ErrorKind::PermissionDenied
+    );
+    assert!(fs::symlink_metadata(dir.join("escape")).is_err());
+
+    fs::remove_dir_all(dir).unwrap();
+    assert!(!dir.exists());
+}
@@ -0,0 +1,43 @@
+//@ignore-target-windows: File handling is not implemented yet
+//@compile-flags: -Zmiri-isolation-error=hide -Zmiri-map-dir=tests/pass/shims:/fixtures
+
+#![feature(io_error_more)]
+
+use std::fs::{self, File};
+use std::io::ErrorKind;
+use std::path::Path;
+
+fn main() {
+    // Reading from the mapped directory works despite isolation.
+    let contents = fs::read_to_string("/fixtures/fs-map-dir.rs").unwrap();
+    assert!(contents.contains("-Zmiri-map-dir"));
+    assert!(fs::metadata("/fixtures").unwrap().is_dir());
+    assert!(fs::read_dir("/fixtures")
+        .unwrap()
+        .any(|entry| entry.unwrap().file_name() == "fs-map-dir.rs"));
+    assert_eq!(
+        fs::canonicalize("/fixtures/fs-map-dir.rs").unwrap(),
+        Path::new("/fixtures/fs-map-dir.rs")
+    );
+
+    // The mapping is read-only.
+    assert_eq!(
+        File::create("/fixtures/foo.txt").unwrap_err().kind(),
+        ErrorKind::ReadOnlyFilesystem
+    );
+    assert_eq!(
+        fs::remove_file("/fixtures/fs-map-dir.rs").unwrap_err().kind(),
+        ErrorKind::ReadOnlyFilesystem
+    );
+    assert_eq!(
+        std::os::unix::fs::symlink("fs-map-dir.rs", "/fixtures/link").unwrap_err().kind(),
+        ErrorKind::ReadOnlyFilesystem
+    );
+
+    // Everything else is still isolated, including paths that leave the mapped directory.
+    assert_eq!(
+        File::open("/fixtures/../../ui.rs").unwrap_err().kind(),
+        ErrorKind::PermissionDenied
+    );
+    assert_eq!(File::open("Cargo.toml").unwrap_err().kind(), ErrorKind::PermissionDenied);
+}
@@ -0,0 +1,69 @@
+//@ignore-target-windows: File handling is not implemented yet
+//@compile-flags: -Zmiri-virtual-fs
+
+#![feature(io_error_more)]
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+fn main() {
+    let dir = std::env::temp_dir().join("miri_test_fs_virtual");
+    fs::create_dir(&dir).unwrap();
+    let path = dir.join("file.txt");
+
+    // Write a file and read it back.
+    let mut file = File::create(&path).unwrap();
+    file.write_all(b"Hello, World!").unwrap();
+    drop(file);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "Hello, World!");
+
+    // Appending, seeking and truncating.
+    let mut file = OpenOptions::new().append(true).read(true).open(&path).unwrap();
+    file.write_all(b"\n").unwrap();
+    file.seek(SeekFrom::Start(7)).unwrap();
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "World!\n");
+    file.set_len(5).unwrap();
+    assert_eq!(file.metadata().unwrap().len(), 5);
+    drop(file);
+
+    // Metadata is deterministic.
+    let metadata = fs::metadata(&path).unwrap();
+    assert!(metadata.is_file());
+    assert_eq!(metadata.modified().unwrap(), std::time::UNIX_EPOCH);
+    assert!(fs::metadata(&dir).unwrap().is_dir());
+
+    // Renaming, and listing the directory.
+    let new_path = dir.join("renamed.txt");
+    fs::rename(&path, &new_path).unwrap();
+    assert_eq!(fs::read(&path).unwrap_err().kind(), ErrorKind::NotFound);
+    let entries: Vec<_> =
+        fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    assert_eq!(entries, ["renamed.txt"]);
+    assert_eq!(
+        fs::canonicalize(dir.join("../miri_test_fs_virtual/renamed.txt")).unwrap(),
+        new_path
+    );
+
+    // Errors.
+    assert_eq!(File::create_new(&new_path).unwrap_err().kind(), ErrorKind::AlreadyExists);
+    assert_eq!(fs::remove_dir(&dir).unwrap_err().kind(), ErrorKind::DirectoryNotEmpty);
+    assert_eq!(File::open("/does/not/exist").unwrap_err().kind(), ErrorKind::NotFound);
+    // Writing to a file that was opened read-only fails with EBADF.
+    let mut file = File::open(&new_path).unwrap();
+    assert_eq!(file.write(b"x").unwrap_err().raw_os_error(), Some(9));
+    drop(file);
+    // Files cannot grow beyond 1 GiB.
+    let mut file = OpenOptions::new().write(true).open(&new_path).unwrap();
+    file.seek(SeekFrom::Start(1 << 31)).unwrap();
+    assert_eq!(file.write(b"x").unwrap_err().kind(), ErrorKind::FileTooLarge);
+    assert_eq!(file.set_len(1 << 31).unwrap_err().kind(), ErrorKind::FileTooLarge);
+    drop(file);
+
+    // Deleting.
+    fs::remove_file(&new_path).unwrap();
+    fs::remove_dir(&dir).unwrap();
+    assert!(!Path::new(&dir).exists());
+}
@@ -0,0 +1,39 @@
+//@only-target-linux: `/proc` is Linux-specific
+//@compile-flags: -Zmiri-num-cpus=4
+
+use std::fs::{self, File};
+use std::io::ErrorKind;
+
+fn main() {
+    // These are synthesized even with isolation enabled.
+    let cmdline = fs::read("/proc/self/cmdline").unwrap();
+    let args: Vec<_> = std::env::args().collect();
+    let mut expected = Vec::new();
+    for arg in &args {
+        expected.extend_from_slice(arg.as_bytes());
+        expected.push(0);
+    }
+    assert_eq!(cmdline, expected);
+
+    let status = fs::read_to_string("/proc/self/status").unwrap();
+    assert!(status.contains("\nThreads:\t1\n"));
+    assert!(status.contains("\nCpus_allowed_list:\t0-3\n"));
+
+    let cpuinfo = fs::read_to_string("/proc/cpuinfo").unwrap();
+    assert_eq!(cpuinfo.lines().filter(|line| line.starts_with("processor")).count(), 4);
+
+    // The heap allocation we just made is on one of the mapped pages.
+    let addr = cpuinfo.as_ptr() as u64;
+    let maps = fs::read_to_string("/proc/self/maps").unwrap();
+    assert!(maps.lines().any(|line| {
+        let (range, _) = line.split_once(' ').unwrap();
+        let (start, end) = range.split_once('-').unwrap();
+        let start = u64::from_str_radix(start, 16).unwrap();
+        let end = u64::from_str_radix(end, 16).unwrap();
+        start <= addr && addr < end
+    }));
+
+    // They cannot be written to.
+    let err = File::options().write(true).open("/proc/self/status").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+}
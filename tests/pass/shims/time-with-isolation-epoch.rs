@@ -0,0 +1,21 @@
+//@compile-flags: -Zmiri-clock-epoch=1000000000 -Zmiri-clock-rate=0
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+fn main() {
+    // The wall clock starts at the configured epoch.
+    let now = SystemTime::now();
+    assert_eq!(now, UNIX_EPOCH + Duration::from_secs(1_000_000_000));
+
+    // Time is frozen, so it only advances when we sleep.
+    let start = Instant::now();
+    for _ in 0..100 {
+        std::hint::black_box(());
+    }
+    assert_eq!(start.elapsed(), Duration::ZERO);
+    assert_eq!(SystemTime::now(), now);
+
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(start.elapsed(), Duration::from_millis(100));
+    assert_eq!(SystemTime::now().duration_since(now).unwrap(), Duration::from_millis(100));
+}
@@ -0,0 +1,24 @@
+//@only-target-windows: this directly tests windows-only functions
+// `ExitProcess` ends the process right away, without running the C runtime's `_onexit` handlers.
+type OnExit = extern "C" fn() -> i32;
+
+extern "C" {
+    fn _onexit(f: OnExit) -> Option<OnExit>;
+}
+
+extern "system" {
+    fn ExitProcess(code: u32) -> !;
+}
+
+extern "C" fn handler() -> i32 {
+    println!("handler");
+    0
+}
+
+fn main() {
+    unsafe {
+        assert!(_onexit(handler).is_some());
+    }
+    println!("main");
+    unsafe { ExitProcess(0) }
+}
@@ -0,0 +1,25 @@
+//@only-target-windows: this directly tests windows-only functions
+//@compile-flags: -Zmiri-hostname=testhost
+
+type BOOL = i32;
+type PWSTR = *mut u16;
+extern "system" {
+    fn GetComputerNameW(lpbuffer: PWSTR, nsize: *mut u32) -> BOOL;
+}
+
+fn main() {
+    let expected: Vec<u16> = "TESTHOST".encode_utf16().collect();
+    unsafe {
+        // The buffer is too small: the required size, including the null terminator, is returned.
+        let mut buf = [0u16; 4];
+        let mut size = buf.len() as u32;
+        assert_eq!(GetComputerNameW(buf.as_mut_ptr(), &mut size), 0);
+        assert_eq!(size, 9);
+
+        let mut buf = [0u16; 16];
+        let mut size = buf.len() as u32;
+        assert_eq!(GetComputerNameW(buf.as_mut_ptr(), &mut size), 1);
+        assert_eq!(size, 8);
+        assert_eq!(&buf[..9], [&expected[..], &[0]].concat());
+    }
+}
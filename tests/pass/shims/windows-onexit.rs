@@ -0,0 +1,27 @@
+//@only-target-windows: this directly tests windows-only functions
+// `_onexit` handlers run in reverse order of registration when the C runtime's `exit` is called.
+type OnExit = extern "C" fn() -> i32;
+
+extern "C" {
+    fn _onexit(f: OnExit) -> Option<OnExit>;
+    fn exit(code: i32) -> !;
+}
+
+extern "C" fn first() -> i32 {
+    println!("first handler");
+    0
+}
+
+extern "C" fn second() -> i32 {
+    println!("second handler");
+    0
+}
+
+fn main() {
+    unsafe {
+        assert!(_onexit(first).is_some());
+        assert!(_onexit(second).is_some());
+    }
+    println!("main");
+    unsafe { exit(0) }
+}
@@ -7,7 +7,7 @@
 //@ignore-target-s390x
 //@ignore-target-thumbv7em
 //@ignore-target-wasm32
-//@compile-flags: -C target-feature=+avx512f,+avx512vl,+avx512bitalg,+avx512vpopcntdq
+//@compile-flags: -C target-feature=+avx512f,+avx512vl,+avx512bw,+avx512vbmi,+avx512vbmi2,+avx512bitalg,+avx512vpopcntdq
 
 #![feature(avx512_target_feature)]
 #![feature(stdarch_x86_avx512)]
@@ -16,20 +16,125 @@
 use std::arch::x86::*;
 #[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::*;
+use std::array;
 use std::mem::transmute;
 
 fn main() {
     assert!(is_x86_feature_detected!("avx512f"));
     assert!(is_x86_feature_detected!("avx512vl"));
+    assert!(is_x86_feature_detected!("avx512bw"));
+    assert!(is_x86_feature_detected!("avx512vbmi"));
+    assert!(is_x86_feature_detected!("avx512vbmi2"));
     assert!(is_x86_feature_detected!("avx512bitalg"));
     assert!(is_x86_feature_detected!("avx512vpopcntdq"));
 
     unsafe {
+        test_avx512f();
+        test_avx512bw();
+        test_avx512vbmi();
         test_avx512bitalg();
         test_avx512vpopcntdq();
     }
 }
 
+#[target_feature(enable = "avx512f,avx512vl")]
+unsafe fn test_avx512f() {
+    let to_array = |v: __m512i| transmute::<_, [i32; 16]>(v);
+    // 1, 2, ..., 16
+    let a: __m512i = transmute(array::from_fn::<i32, 16, _>(|i| i as i32 + 1));
+
+    // Gather and scatter
+    let data: [i32; 32] = array::from_fn(|i| i as i32 * 10);
+    let offsets: __m512i = transmute(array::from_fn::<i32, 16, _>(|i| i as i32 * 2));
+    let r = _mm512_i32gather_epi32::<4>(offsets, data.as_ptr().cast());
+    assert_eq!(to_array(r), array::from_fn(|i| i as i32 * 20));
+    let r = _mm512_mask_i32gather_epi32::<4>(
+        _mm512_set1_epi32(-1),
+        0b0101_0101_0101_0101,
+        offsets,
+        data.as_ptr().cast(),
+    );
+    assert_eq!(to_array(r), array::from_fn(|i| if i % 2 == 0 { i as i32 * 20 } else { -1 }));
+
+    let mut out = [0i32; 32];
+    _mm512_i32scatter_epi32::<4>(out.as_mut_ptr().cast(), offsets, _mm512_set1_epi32(7));
+    assert_eq!(out, array::from_fn(|i| if i % 2 == 0 { 7 } else { 0 }));
+
+    // Masked loads and stores do not access the memory of masked-off elements.
+    let small = [1i32, 2, 3, 4];
+    let r = _mm512_maskz_loadu_epi32(0b1111, small.as_ptr());
+    assert_eq!(to_array(r), [1, 2, 3, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    let r = _mm512_mask_loadu_epi32(_mm512_set1_epi32(9), 0b0011, small.as_ptr());
+    assert_eq!(to_array(r), [1, 2, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9]);
+    let mut small = [0i32; 4];
+    _mm512_mask_storeu_epi32(small.as_mut_ptr(), 0b1010, _mm512_set1_epi32(5));
+    assert_eq!(small, [0, 5, 0, 5]);
+
+    // Compress and expand
+    let r = _mm512_maskz_compress_epi32(0b1000_0000_0000_0101, a);
+    assert_eq!(to_array(r), [1, 3, 16, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    let r = _mm512_mask_compress_epi32(_mm512_set1_epi32(-1), 0b0101, a);
+    assert_eq!(to_array(r), [1, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1]);
+    let r = _mm512_maskz_expand_epi32(0b1010, a);
+    assert_eq!(to_array(r), [0, 1, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    let mut out = [0i32; 3];
+    _mm512_mask_compressstoreu_epi32(out.as_mut_ptr().cast(), 0b1000_0000_0000_0101, a);
+    assert_eq!(out, [1, 3, 16]);
+
+    // Permutes only use the low bits of the indices.
+    let idx: __m512i = transmute(array::from_fn::<i32, 16, _>(|i| 31 - i as i32));
+    let r = _mm512_permutexvar_epi32(idx, a);
+    assert_eq!(to_array(r), array::from_fn(|i| 16 - i as i32));
+    let b: __m512i = transmute(array::from_fn::<i32, 16, _>(|i| i as i32 + 101));
+    let idx: __m512i = transmute(array::from_fn::<i32, 16, _>(|i| i as i32 * 2 + 32));
+    let r = _mm512_permutex2var_epi32(a, idx, b);
+    assert_eq!(
+        to_array(r),
+        array::from_fn(|i| if i < 8 { i as i32 * 2 + 1 } else { i as i32 * 2 - 16 + 101 }),
+    );
+
+    // Arithmetic shifts of 64-bit elements
+    let r =
+        _mm512_srav_epi64(_mm512_set1_epi64(-256), _mm512_setr_epi64(0, 1, 2, 3, 4, 8, 64, 100));
+    assert_eq!(transmute::<_, [i64; 8]>(r), [-256, -128, -64, -32, -16, -1, -1, -1]);
+    let r = _mm256_srav_epi64(_mm256_set1_epi64x(-256), _mm256_setr_epi64x(0, 1, 8, 64));
+    assert_eq!(transmute::<_, [i64; 4]>(r), [-256, -128, -1, -1]);
+    let r = _mm512_sra_epi64(_mm512_set1_epi64(-256), _mm_set_epi64x(0, 4));
+    assert_eq!(transmute::<_, [i64; 8]>(r), [-16; 8]);
+}
+
+#[target_feature(enable = "avx512bw")]
+unsafe fn test_avx512bw() {
+    // Each 128-bit block is shuffled independently.
+    let a: __m512i = transmute(array::from_fn::<i8, 64, _>(|i| i as i8));
+    let b: __m512i = transmute(array::from_fn::<i8, 64, _>(|i| (15 - i % 16) as i8));
+    let r = _mm512_shuffle_epi8(a, b);
+    let expected: [i8; 64] = array::from_fn(|i| ((i & !15) + 15 - i % 16) as i8);
+    assert_eq!(transmute::<_, [i8; 64]>(r), expected);
+
+    let r = _mm512_packs_epi16(_mm512_set1_epi16(300), _mm512_set1_epi16(-300));
+    let expected: [i8; 64] = array::from_fn(|i| if i % 16 < 8 { 127 } else { -128 });
+    assert_eq!(transmute::<_, [i8; 64]>(r), expected);
+
+    let count: __m512i = transmute(array::from_fn::<i16, 32, _>(|i| i as i16));
+    let r = _mm512_srlv_epi16(_mm512_set1_epi16(-1), count);
+    let expected: [u16; 32] = array::from_fn(|i| 0xffffu16.checked_shr(i as u32).unwrap_or(0));
+    assert_eq!(transmute::<_, [u16; 32]>(r), expected);
+}
+
+#[target_feature(enable = "avx512vbmi,avx512vbmi2")]
+unsafe fn test_avx512vbmi() {
+    let a: __m512i = transmute(array::from_fn::<i8, 64, _>(|i| i as i8));
+    let idx: __m512i = transmute(array::from_fn::<i8, 64, _>(|i| (63 - i) as i8));
+    let r = _mm512_permutexvar_epi8(idx, a);
+    let expected: [i8; 64] = array::from_fn(|i| (63 - i) as i8);
+    assert_eq!(transmute::<_, [i8; 64]>(r), expected);
+
+    let r = _mm512_maskz_compress_epi8(0xaaaa_aaaa_aaaa_aaaa, a);
+    let expected: [i8; 64] = array::from_fn(|i| if i < 32 { (i * 2 + 1) as i8 } else { 0 });
+    assert_eq!(transmute::<_, [i8; 64]>(r), expected);
+}
+
 // Some of the constants in the tests below are just bit patterns. They should not
 // be interpreted as integers; signedness does not make sense for them, but
 // __mXXXi happens to be defined in terms of signed integers.
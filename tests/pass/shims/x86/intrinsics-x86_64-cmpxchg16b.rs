@@ -0,0 +1,24 @@
+// The x86_64 target spec only has a `max-atomic-width` of 64, but `cmpxchg16b` still does 128-bit
+// atomic accesses.
+//@only-target-x86_64
+//@compile-flags: -C target-feature=+cmpxchg16b
+#![feature(cmpxchg16b_intrinsic)]
+
+use std::arch::x86_64::cmpxchg16b;
+use std::sync::atomic::Ordering;
+
+fn main() {
+    assert!(is_x86_feature_detected!("cmpxchg16b"));
+
+    let mut x: u128 = 1 << 64 | 2;
+    unsafe {
+        // A failing exchange returns the current value and leaves it unchanged.
+        let old = cmpxchg16b(&mut x, 0, 3, Ordering::SeqCst, Ordering::SeqCst);
+        assert_eq!(old, 1 << 64 | 2);
+        assert_eq!(x, 1 << 64 | 2);
+        // A successful one replaces it.
+        let old = cmpxchg16b(&mut x, 1 << 64 | 2, 3 << 64, Ordering::AcqRel, Ordering::Acquire);
+        assert_eq!(old, 1 << 64 | 2);
+        assert_eq!(x, 3 << 64);
+    }
+}
@@ -0,0 +1,92 @@
+// `long double` is the x87 80-bit format, passed like bindgen does it: as a `u128` on x86_64, and
+// as a 12-byte blob on 32-bit x86 (where it is only 4-aligned). `u128` has no stable C ABI, which
+// is why we need to allow `improper_ctypes`.
+#![allow(improper_ctypes)]
+
+#[cfg(all(any(target_arch = "x86", target_arch = "x86_64"), not(target_env = "msvc")))]
+fn main() {
+    #[cfg(any(target_arch = "x86_64", target_vendor = "apple"))]
+    #[allow(non_camel_case_types)]
+    type long_double = u128;
+
+    #[cfg(not(any(target_arch = "x86_64", target_vendor = "apple")))]
+    #[allow(non_camel_case_types)]
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    struct long_double([u32; 3]);
+
+    #[cfg(any(target_arch = "x86_64", target_vendor = "apple"))]
+    fn from_bits(bits: u128) -> long_double {
+        bits
+    }
+    #[cfg(any(target_arch = "x86_64", target_vendor = "apple"))]
+    fn to_bits(x: long_double) -> u128 {
+        x
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_vendor = "apple")))]
+    fn from_bits(bits: u128) -> long_double {
+        assert!(bits >> 96 == 0);
+        long_double([bits as u32, (bits >> 32) as u32, (bits >> 64) as u32])
+    }
+    #[cfg(not(any(target_arch = "x86_64", target_vendor = "apple")))]
+    fn to_bits(x: long_double) -> u128 {
+        let [a, b, c] = x.0;
+        u128::from(a) | (u128::from(b) << 32) | (u128::from(c) << 64)
+    }
+
+    extern "C" {
+        fn fabsl(x: long_double) -> long_double;
+        fn floorl(x: long_double) -> long_double;
+        fn fmodl(x: long_double, y: long_double) -> long_double;
+        fn ldexpl(x: long_double, exp: i32) -> long_double;
+        fn sqrtl(x: long_double) -> long_double;
+        fn sinl(x: long_double) -> long_double;
+    }
+
+    /// Builds an 80-bit float from its sign, biased exponent and mantissa (with the explicit
+    /// integer bit).
+    fn x87(sign: bool, exp: u16, mantissa: u64) -> u128 {
+        (u128::from(sign) << 79) | (u128::from(exp) << 64) | u128::from(mantissa)
+    }
+    let one = x87(false, 0x3fff, 1 << 63);
+    let minus_one_and_half = x87(true, 0x3fff, 0b11 << 62);
+    let two = x87(false, 0x4000, 1 << 63);
+    let four = x87(false, 0x4001, 1 << 63);
+
+    let fabsl = |x| to_bits(unsafe { fabsl(from_bits(x)) });
+    let floorl = |x| to_bits(unsafe { floorl(from_bits(x)) });
+    let fmodl = |x, y| to_bits(unsafe { fmodl(from_bits(x), from_bits(y)) });
+    let ldexpl = |x, exp| to_bits(unsafe { ldexpl(from_bits(x), exp) });
+    let sqrtl = |x| to_bits(unsafe { sqrtl(from_bits(x)) });
+    let sinl = |x| to_bits(unsafe { sinl(from_bits(x)) });
+
+    assert_eq!(fabsl(minus_one_and_half), x87(false, 0x3fff, 0b11 << 62));
+    assert_eq!(floorl(minus_one_and_half), x87(true, 0x4000, 1 << 63));
+    assert_eq!(fmodl(four, x87(false, 0x4000, 0b11 << 62)), one);
+    assert_eq!(ldexpl(one, 2), four);
+    assert_eq!(sinl(0), 0);
+    // `fabsl` only clears the sign bit, even of NaNs.
+    let neg_nan = x87(true, 0x7fff, 0xc000_0000_0000_1234);
+    assert_eq!(fabsl(neg_nan), x87(false, 0x7fff, 0xc000_0000_0000_1234));
+    // Padding bits are ignored.
+    #[cfg(any(target_arch = "x86_64", target_vendor = "apple"))]
+    assert_eq!(fabsl(one | (0xffff << 80)), one);
+
+    // `sqrtl` is correctly rounded to the full 64-bit mantissa.
+    assert_eq!(sqrtl(four), two);
+    assert_eq!(sqrtl(two), x87(false, 0x3fff, 0xb504_f333_f9de_6484));
+    let three = x87(false, 0x4000, 0b11 << 62);
+    assert_eq!(sqrtl(three), x87(false, 0x3fff, 0xddb3_d742_c265_539e));
+    // ... also outside of the range of `f64`.
+    assert_eq!(sqrtl(x87(false, 0x7ffe, 1 << 63)), x87(false, 0x5ffe, 0xb504_f333_f9de_6484));
+    assert_eq!(sqrtl(x87(false, 0, 1)), x87(false, 0x1fe0, 0xb504_f333_f9de_6484));
+    assert_eq!(sqrtl(x87(true, 0, 0)), x87(true, 0, 0));
+    assert_eq!(sqrtl(x87(false, 0x7fff, 1 << 63)), x87(false, 0x7fff, 1 << 63));
+    let nan = sqrtl(minus_one_and_half);
+    assert_eq!((nan >> 64) & 0x7fff, 0x7fff);
+    assert_ne!(nan & ((1 << 63) - 1), 0);
+}
+
+#[cfg(not(all(any(target_arch = "x86", target_arch = "x86_64"), not(target_env = "msvc"))))]
+fn main() {}
@@ -0,0 +1,55 @@
+//@compile-flags: -Zmiri-retag-fields=none:per_type_field_retagging::Newtype
+//@compile-flags: -Zmiri-retag-fields=scalar:per_type_field_retagging::Triple
+
+struct Newtype<'a>(#[allow(dead_code)] &'a mut i32);
+
+/// Too big to be passed as a scalar pair, so its fields are not retagged with `scalar`.
+struct Triple<'a>(
+    #[allow(dead_code)] &'a mut i32,
+    #[allow(dead_code)] &'a mut i32,
+    #[allow(dead_code)] &'a mut i32,
+);
+
+struct Other<'a>(#[allow(dead_code)] &'a mut i32);
+
+fn dealloc_while_running(_n: Newtype<'_>, dealloc: impl FnOnce()) {
+    dealloc();
+}
+
+fn dealloc_while_running_triple(_t: Triple<'_>, dealloc: impl FnOnce()) {
+    dealloc();
+}
+
+fn write_while_running(n: Other<'_>, write: impl FnOnce()) {
+    write();
+    drop(n);
+}
+
+// Make sure that we do *not* retag the fields of `Newtype` and `Triple` (which would be UB, see
+// `tests/fail/stacked_borrows/per_type_field_retagging.rs`), but still retag the fields of other
+// types. (The latter does not cause UB here, it just checks that nothing goes wrong.)
+fn main() {
+    let ptr = Box::into_raw(Box::new(0i32));
+    #[rustfmt::skip] // I like my newlines
+    unsafe {
+        dealloc_while_running(
+            Newtype(&mut *ptr),
+            || drop(Box::from_raw(ptr)),
+        )
+    };
+
+    let ptr = Box::into_raw(Box::new(0i32));
+    let (mut a, mut b) = (0, 0);
+    #[rustfmt::skip]
+    unsafe {
+        dealloc_while_running_triple(
+            Triple(&mut *ptr, &mut a, &mut b),
+            || drop(Box::from_raw(ptr)),
+        )
+    };
+
+    let mut x = 0;
+    write_while_running(Other(&mut x), || ());
+    x += 1;
+    assert_eq!(x, 1);
+}
@@ -0,0 +1,12 @@
+//@compile-flags: --test -Zmiri-test=tests::formatted_message
+
+#[cfg(test)]
+mod tests {
+    // The payload of a formatted panic message is a `String`.
+    #[test]
+    #[should_panic(expected = "value is 42")]
+    fn formatted_message() {
+        let value = 42;
+        panic!("the value is {value}");
+    }
+}
@@ -0,0 +1,16 @@
+//@compile-flags: --test -Zmiri-test=tests::overflows
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    #[should_panic]
+    fn overflows() {
+        let v: Vec<u8> = Vec::new();
+        let _ = v[0];
+    }
+
+    #[test]
+    fn not_run() {
+        unreachable!("only the test given to -Zmiri-test runs");
+    }
+}
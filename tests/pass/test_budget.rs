@@ -0,0 +1,21 @@
+//@compile-flags: --test -Zmiri-test-max-blocks=100000
+//@normalize-stdout-test: "finished in \d+\.\d+s" -> "finished in $$TIME"
+
+// A test that exceeds its budget panics, and the other tests still run.
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    #[should_panic(expected = "exceeded its budget of 100000 basic blocks")]
+    fn hangs() {
+        loop {
+            std::hint::black_box(());
+        }
+    }
+
+    #[test]
+    fn passes() {
+        let v: Vec<u32> = (0..100).collect();
+        assert_eq!(v.iter().sum::<u32>(), 4950);
+    }
+}
@@ -0,0 +1,9 @@
+//@compile-flags: -Zmiri-track-exposed-provenance -Zmiri-permissive-provenance
+use std::ptr;
+
+fn main() {
+    let x = 42u32;
+    let addr = ptr::from_ref(&x).expose_provenance();
+    let p = ptr::with_exposed_provenance::<u32>(addr);
+    assert_eq!(unsafe { *p }, 42);
+}
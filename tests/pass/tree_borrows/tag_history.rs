@@ -0,0 +1,13 @@
+//@compile-flags: -Zmiri-tree-borrows
+extern "Rust" {
+    // Takes the reference itself: casting it to a raw pointer could give that pointer a new tag.
+    fn miri_get_tag_history(ptr: &u8);
+}
+
+fn main() {
+    let mut x = 0u8;
+    let p = &mut x as *mut u8;
+    let s = unsafe { &*p };
+    unsafe { *p = 1 };
+    unsafe { miri_get_tag_history(s) };
+}
@@ -0,0 +1,58 @@
+//@compile-flags: -Zmiri-ignore-leaks -Zmiri-preemption-rate=0 -Zmiri-weak-memory-eviction=unreadable
+
+// Dropping unreadable stores from the store buffers must not drop stores that some thread can
+// still read, and must not let a thread read a store older than one that happens-before it.
+
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::*;
+use std::thread::spawn;
+
+fn static_atomic(val: usize) -> &'static AtomicUsize {
+    Box::leak(Box::new(AtomicUsize::new(val)))
+}
+
+/// Returns whether the other thread read an outdated value, which it is allowed to.
+fn outdated_still_readable() -> bool {
+    let x = static_atomic(0);
+    let reader = spawn(move || {
+        // Preemption is disabled, so this only runs once the main thread is blocked on the join
+        // below, after all stores to `x`.
+        let first = x.load(Relaxed);
+        let second = x.load(Relaxed);
+        // Coherence: reads of the same thread never go back in modification order.
+        assert!(second >= first);
+        first < 100
+    });
+    for i in 1..=100 {
+        x.store(i, Relaxed);
+    }
+    let outdated = reader.join().unwrap();
+    // The stores happen-before this load, so it must see the last one.
+    assert_eq!(x.load(Relaxed), 100);
+    outdated
+}
+
+/// After synchronizing with the writer, a thread can only read the latest value.
+fn synchronized_reads_latest() {
+    let x = static_atomic(0);
+    let flag = static_atomic(0);
+    let writer = spawn(move || {
+        for i in 1..=100 {
+            x.store(i, Relaxed);
+        }
+        flag.store(1, Release);
+    });
+    while flag.load(Acquire) == 0 {
+        std::hint::spin_loop();
+    }
+    assert_eq!(x.load(Relaxed), 100);
+    writer.join().unwrap();
+}
+
+fn main() {
+    // The reader may pick any store that is still in the buffer, so try until it picks an old one.
+    assert!((0..100).any(|_| outdated_still_readable()));
+    for _ in 0..10 {
+        synchronized_reads_latest();
+    }
+}
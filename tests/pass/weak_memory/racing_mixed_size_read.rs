@@ -15,11 +15,10 @@ fn split_u32_ptr(dword: *const u32) -> *const [u16; 2] {
     unsafe { std::mem::transmute::<*const u32, *const [u16; 2]>(dword) }
 }
 
-// Racing mixed size reads may cause two loads to read-from
-// the same store but observe different values, which doesn't make
-// sense under the formal model so we forbid this.
+// Racing mixed size reads are allowed. The weak memory emulation then makes them read the latest
+// value, so that two loads never read from the same store but observe different values.
 pub fn main() {
-    let x = static_atomic(0);
+    let x = static_atomic(0x0001_0002);
 
     let j1 = spawn(move || {
         x.load(Relaxed);
@@ -30,10 +29,11 @@ pub fn main() {
         let x_split = split_u32_ptr(x_ptr);
         unsafe {
             let hi = x_split as *const u16 as *const AtomicU16;
-            (*hi).load(Relaxed); //~ ERROR: (1) 4-byte atomic load on thread `unnamed-1` and (2) 2-byte atomic load
+            assert_eq!((*hi).load(Relaxed), if cfg!(target_endian = "little") { 2 } else { 1 });
         }
     });
 
     j1.join().unwrap();
     j2.join().unwrap();
+    assert_eq!(x.load(Relaxed), 0x0001_0002);
 }
@@ -99,6 +99,25 @@ fn initialization_write(add_fence: bool) -> bool {
     r2 == 11
 }
 
+// A failed compare-exchange is just a load, so it can read an outdated value.
+fn cmpxchg_fail_reads_outdated() -> bool {
+    let x = static_atomic(0);
+    let j1 = spawn(move || {
+        x.store(1, Relaxed);
+        // Preemption is disabled, so the store above will never be the
+        // latest store visible to another thread.
+        x.store(2, Relaxed);
+    });
+
+    // Reading 0 would make the exchange succeed, so that is not an option for a failed one.
+    let j2 = spawn(move || x.compare_exchange(0, 3, Relaxed, Relaxed));
+
+    j1.join().unwrap();
+    let r2 = j2.join().unwrap();
+
+    r2 == Err(1)
+}
+
 fn faa_replaced_by_load() -> bool {
     // Example from https://github.com/llvm/llvm-project/issues/56450#issuecomment-1183695905
     #[no_mangle]
@@ -150,5 +169,6 @@ pub fn main() {
     assert_once(seq_cst);
     assert_once(|| initialization_write(false));
     assert_once(|| initialization_write(true));
+    assert_once(cmpxchg_fail_reads_outdated);
     assert_once(faa_replaced_by_load);
 }
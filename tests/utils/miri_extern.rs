@@ -58,6 +58,11 @@ extern "Rust" {
     /// just to be complete.
     pub fn miri_start_unwind(payload: *mut u8) -> !;
 
+    /// Miri-provided extern function to begin unwinding with a foreign exception, as if it had
+    /// been thrown by C++ code. Drop glue runs while this unwinds, but `catch_unwind` does not
+    /// catch it: the program aborts instead.
+    pub fn miri_throw_foreign_exception() -> !;
+
     /// Miri-provided extern function to get the internal unique identifier for the allocation that a pointer
     /// points to. If this pointer is invalid (not pointing to an allocation), interpretation will abort.
     ///
@@ -90,6 +95,16 @@ extern "Rust" {
     /// change, or it may be removed entirely.
     pub fn miri_print_borrow_state(alloc_id: u64, show_unnamed: bool);
 
+    /// Miri-provided extern function to print (from the interpreter, not the program) everything
+    /// the borrow tracker knows about the tag of `ptr`: where it was created and from which parent,
+    /// whether it is currently protected, which events invalidated it, and its current permissions.
+    ///
+    /// This is useful to debug aliasing issues without rerunning with `-Zmiri-track-pointer-tag`.
+    /// Note that the provenance GC may remove history of tags that are no longer reachable.
+    ///
+    /// The format of what this emits is unstable and may change at any time.
+    pub fn miri_get_tag_history(ptr: *const ());
+
     /// Miri-provided extern function to associate a name to the nth parent of a tag.
     /// Typically the name given would be the name of the program variable that holds the pointer.
     /// Unreachable tags can still be named by using nonzero `nth_parent` and a child tag.
@@ -100,6 +115,47 @@ extern "Rust" {
     /// Under Tree Borrows, the names also appear in error messages.
     pub fn miri_pointer_name(ptr: *const (), nth_parent: u8, name: &[u8]);
 
+    /// Miri-provided extern function to record that `value` was observed under the given name.
+    /// With `-Zmiri-litmus`, Miri prints how often each combination of observed values occurred
+    /// over all executions. Otherwise, this does nothing.
+    pub fn miri_observe(name: &str, value: u64);
+
+    /// Miri-provided extern function to set the probability that the current thread gets
+    /// preempted at the end of each basic block, overriding `-Zmiri-preemption-rate` for this
+    /// thread. `rate` must be between 0.0 and 1.0.
+    pub fn miri_set_preemption_rate(rate: f64);
+
+    /// Miri-provided extern function to derive a capability from `ptr` that can only access the
+    /// `len` bytes starting at `ptr`, like `cheri_bounds_set` on CHERI hardware. Accessing memory
+    /// outside those bounds through the result (or any pointer derived from it) is a capability
+    /// fault. The bounds can only shrink: they must be within the bounds of `ptr`.
+    ///
+    /// This is only supported with `-Zmiri-cheri`.
+    pub fn miri_cheri_set_bounds(ptr: *const (), len: usize) -> *const ();
+
+    /// Miri-provided extern function to make the next heap allocation fail, like it would when
+    /// the system runs out of memory.
+    pub fn miri_fail_next_alloc();
+
+    /// Miri-provided extern function to stop the program here. If a debugger is attached with
+    /// `-Zmiri-gdb` or `-Zmiri-dap`, the program stops there; otherwise, Miri reads commands from
+    /// its standard input to inspect the program (type `help` for a list), until it gets
+    /// `continue` or the input ends.
+    pub fn miri_breakpoint();
+
+    /// Miri-provided extern function to mark a point the program can be restarted at. Restart
+    /// points are identified by the order in which they are reached, starting at 0. Returns how
+    /// often the program was restarted at this point, so the first return is always 0.
+    pub fn miri_restart_point() -> usize;
+
+    /// Miri-provided extern function to restart the program at the restart point with the given
+    /// ID. `miri_restart_point` then returns again, with a larger return value than before.
+    ///
+    /// This does not restore a snapshot: the program is run again from the start, so everything
+    /// it did up to the restart point (like printing) happens again. It requires isolation, and
+    /// cannot be combined with `-Zmiri-dpor`, `-Zmiri-model-check`, or `-Zmiri-litmus`.
+    pub fn miri_restart(id: usize) -> !;
+
     /// Miri-provided extern function to print (from the interpreter, not the
     /// program) the contents of a section of program memory, as bytes. Bytes
     /// written using this function will emerge from the interpreter's stdout.
@@ -110,6 +166,13 @@ extern "Rust" {
     /// written using this function will emerge from the interpreter's stderr.
     pub fn miri_write_to_stderr(bytes: &[u8]);
 
+    /// Miri-provided extern function to end the program with the given exit code, without running
+    /// any destructors or checking for leaks, like `exit` does in C.
+    ///
+    /// This is useful in `no_std` programs, which have no other way to exit with an error, e.g.
+    /// from their panic handler.
+    pub fn miri_exit(code: i32) -> !;
+
     /// Miri-provided extern function to allocate memory from the interpreter.
     ///
     /// This is useful when no fundamental way of allocating memory is